@@ -0,0 +1,79 @@
+//! Cost of writing pixels, to check that copy-on-write buffers add nothing
+//! to writes into an image no clone shares
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use hide_rs::img::StegoImage;
+use hide_rs::testing::gradient_image;
+use hide_rs::Encoder;
+use image::{DynamicImage, Rgb};
+
+const SIZE: u32 = 256;
+
+fn pixel_writes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("pixel_writes");
+
+    group.bench_function("image_buffer_put_pixel", |b| {
+        let mut buffer = gradient_image(SIZE, SIZE).inner().to_rgb8();
+        b.iter(|| {
+            for y in 0..SIZE {
+                for x in 0..SIZE {
+                    buffer.put_pixel(x, y, black_box(Rgb([x as u8, y as u8, 7])));
+                }
+            }
+        })
+    });
+
+    group.bench_function("set_pixel_rgb_unshared", |b| {
+        let mut image = gradient_image(SIZE, SIZE);
+        b.iter(|| {
+            for y in 0..SIZE {
+                for x in 0..SIZE {
+                    image
+                        .set_pixel_rgb(x, y, black_box(Rgb([x as u8, y as u8, 7])))
+                        .unwrap();
+                }
+            }
+        })
+    });
+
+    group.bench_function("set_lsb_unshared", |b| {
+        let mut image = gradient_image(SIZE, SIZE);
+        b.iter(|| {
+            for y in 0..SIZE {
+                for x in 0..SIZE {
+                    image.set_lsb(x, y, 1, black_box(x % 2 == 0)).unwrap();
+                }
+            }
+        })
+    });
+
+    // The first write to a clone pays for one copy of the buffer
+    group.bench_function("set_pixel_rgb_shared_first_write", |b| {
+        let original = gradient_image(SIZE, SIZE);
+        b.iter_batched(
+            || original.clone(),
+            |mut image| {
+                image.set_pixel_rgb(0, 0, Rgb([1, 2, 3])).unwrap();
+                image
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    group.finish();
+}
+
+fn encode(c: &mut Criterion) {
+    let cover = gradient_image(SIZE, SIZE);
+    let message = vec![b'm'; Encoder::new().max_message_size(&cover)];
+    c.bench_function("encode_full_cover", |b| {
+        b.iter_batched(
+            || StegoImage::from_dynamic_image(DynamicImage::ImageRgb8(cover.inner().to_rgb8())),
+            |cover| Encoder::new().encode(cover, black_box(&message)).unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+criterion_group!(benches, pixel_writes, encode);
+criterion_main!(benches);
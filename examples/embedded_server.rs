@@ -0,0 +1,37 @@
+//! Serve the hide-rs API from inside another actix-web application
+//!
+//! Run with `cargo run --example embedded_server`, then try
+//! `curl http://127.0.0.1:8080/stego/api/v1/health`.
+
+use actix_web::{web, App, HttpResponse, HttpServer};
+use hide_rs::api::{self, handlers::ServerConfig, ApiConfig};
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    let upload_dir = std::env::temp_dir().join("hide-embedded-uploads");
+    std::fs::create_dir_all(&upload_dir)?;
+
+    // Created once, so every worker shares the same state
+    let hide_api = ApiConfig::new(
+        "/stego",
+        ServerConfig {
+            upload_dir: upload_dir.to_string_lossy().into_owned(),
+            enable_legacy_routes: false,
+            ..Default::default()
+        },
+    );
+    hide_api.spawn_background_tasks();
+
+    println!("Serving at http://127.0.0.1:8080, hide-rs under /stego");
+    HttpServer::new(move || {
+        App::new()
+            .route(
+                "/",
+                web::get().to(|| async { HttpResponse::Ok().body("The host application") }),
+            )
+            .service(api::scope(hide_api.clone()))
+    })
+    .bind(("127.0.0.1", 8080))?
+    .run()
+    .await
+}
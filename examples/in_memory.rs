@@ -0,0 +1,61 @@
+//! Hide and recover a message without touching the filesystem
+//!
+//! Run with `cargo run --example in_memory`, optionally passing the path of a
+//! cover image and a message: `cargo run --example in_memory -- cover.png hello`.
+//! The file is only read to get some bytes; everything else happens in memory,
+//! as it would for images received over the network or from a database.
+
+use hide_rs::{Decoder, Encoder, Result, StegoImage};
+use image::{ImageFormat, Rgb, RgbImage};
+
+/// Hide a message in an encoded cover image and return the encoded stego image
+fn hide_in_bytes(cover: &[u8], message: &[u8]) -> Result<Vec<u8>> {
+    let stego = Encoder::new().encode(StegoImage::from_bytes(cover)?, message)?;
+    // PNG is lossless, so the payload survives; lossy formats are rejected
+    stego.to_bytes(ImageFormat::Png)
+}
+
+/// Recover the message from an encoded stego image
+fn reveal_from_bytes(stego: &[u8]) -> Result<Vec<u8>> {
+    Decoder::new().decode(&StegoImage::from_bytes(stego)?)
+}
+
+/// A generated cover, encoded as PNG
+fn sample_cover() -> Result<Vec<u8>> {
+    let pixels = RgbImage::from_fn(64, 64, |x, y| Rgb([(x * 4) as u8, (y * 4) as u8, 128]));
+    StegoImage::from_dynamic_image(pixels.into()).to_bytes(ImageFormat::Png)
+}
+
+fn main() -> Result<()> {
+    let mut args = std::env::args().skip(1);
+    let cover = match args.next() {
+        Some(path) => std::fs::read(path)?,
+        None => sample_cover()?,
+    };
+    let message = args
+        .next()
+        .unwrap_or_else(|| "hidden in memory".to_string());
+
+    let stego = hide_in_bytes(&cover, message.as_bytes())?;
+    let revealed = reveal_from_bytes(&stego)?;
+
+    println!(
+        "Hid {} bytes in a {} byte cover, giving a {} byte PNG",
+        message.len(),
+        cover.len(),
+        stego.len()
+    );
+    println!("Recovered: {}", String::from_utf8_lossy(&revealed));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trips_through_bytes() {
+        let stego = hide_in_bytes(&sample_cover().unwrap(), b"bytes in, bytes out").unwrap();
+        assert_eq!(reveal_from_bytes(&stego).unwrap(), b"bytes in, bytes out");
+    }
+}
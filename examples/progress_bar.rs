@@ -0,0 +1,64 @@
+//! Show a progress bar while hiding a large message
+//!
+//! Run with `cargo run --release --example progress_bar`. The encoder reports
+//! how many of the payload's pixels are done through the callback set with
+//! [`EncoderConfig::on_progress`]; this draws a plain text bar on stderr, but
+//! the callback can just as well drive a progress bar crate or a GUI.
+
+use hide_rs::decoder::Decoder;
+use hide_rs::encoder::{Encoder, EncoderConfig};
+use hide_rs::img::StegoImage;
+use hide_rs::Result;
+use image::{Rgb, RgbImage};
+use std::io::Write;
+
+/// Width of the bar in characters
+const BAR_WIDTH: u64 = 40;
+
+/// Render the bar for a number of pixels done out of a total
+fn render_bar(done: u64, total: u64) -> String {
+    let filled = (done * BAR_WIDTH).checked_div(total).unwrap_or(BAR_WIDTH);
+    format!(
+        "[{}{}] {}/{} pixels",
+        "#".repeat(filled as usize),
+        "-".repeat((BAR_WIDTH - filled) as usize),
+        done,
+        total
+    )
+}
+
+fn main() -> Result<()> {
+    let cover = StegoImage::from_dynamic_image(
+        RgbImage::from_fn(1024, 1024, |x, y| Rgb([x as u8, y as u8, (x + y) as u8])).into(),
+    );
+    let message = vec![0x42; 300 * 1024];
+
+    let encoder = Encoder::with_config(EncoderConfig::default().on_progress(|done, total| {
+        eprint!("\r{}", render_bar(done, total));
+        let _ = std::io::stderr().flush();
+    }));
+    let stego = encoder.encode(cover, &message)?;
+    eprintln!();
+
+    let decoded = Decoder::new().decode(&stego)?;
+    println!("Hid and recovered {} bytes", decoded.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bar_fills_with_progress() {
+        assert_eq!(
+            render_bar(0, 10),
+            format!("[{}] 0/10 pixels", "-".repeat(40))
+        );
+        assert_eq!(
+            render_bar(5, 10),
+            format!("[{}{}] 5/10 pixels", "#".repeat(20), "-".repeat(20))
+        );
+        assert_eq!(render_bar(0, 0), format!("[{}] 0/0 pixels", "#".repeat(40)));
+    }
+}
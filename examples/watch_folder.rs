@@ -0,0 +1,95 @@
+//! Hide a message in every image dropped into a directory
+//!
+//! Run with `cargo run --example watch_folder -- <input dir> <output dir> <message>`
+//! and copy images into the input directory. Each one is written to the
+//! output directory as a PNG once it has finished copying. Stop with Ctrl-C.
+
+use hide_rs::batch::DirWatcher;
+use hide_rs::encoder::Encoder;
+use hide_rs::Result;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// How long to wait between looks at the input directory
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Encode the covers that settled since the last poll
+///
+/// A cover that fails to encode is reported and skipped; it is tried again
+/// if it is rewritten.
+///
+/// # Returns
+/// * Paths of the stego images written
+fn encode_new_covers(
+    watcher: &mut DirWatcher,
+    encoder: &Encoder,
+    message: &[u8],
+    output_dir: &Path,
+) -> Result<Vec<PathBuf>> {
+    let mut written = Vec::new();
+    for cover in watcher.poll()? {
+        let Some(stem) = cover.file_stem() else {
+            continue;
+        };
+        let output = output_dir.join(stem).with_extension("png");
+        match encoder.encode_file(&cover, message, &output) {
+            Ok(_) => written.push(output),
+            Err(e) => eprintln!("Skipping {}: {}", cover.display(), e),
+        }
+    }
+    Ok(written)
+}
+
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let [input_dir, output_dir, message] = args.as_slice() else {
+        eprintln!("Usage: watch_folder <input dir> <output dir> <message>");
+        std::process::exit(2);
+    };
+    std::fs::create_dir_all(output_dir)?;
+
+    let encoder = Encoder::new();
+    let mut watcher = DirWatcher::new(input_dir);
+    println!("Watching {} for images", input_dir);
+    loop {
+        for output in encode_new_covers(
+            &mut watcher,
+            &encoder,
+            message.as_bytes(),
+            Path::new(output_dir),
+        )? {
+            println!("Wrote {}", output.display());
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hide_rs::decoder::Decoder;
+    use hide_rs::img::StegoImage;
+    use hide_rs::testing::gradient_image;
+
+    #[test]
+    fn test_encodes_covers_once_they_settle() {
+        let input_dir = tempfile::tempdir().unwrap();
+        let output_dir = tempfile::tempdir().unwrap();
+        gradient_image(40, 40)
+            .save(input_dir.path().join("dropped.png"))
+            .unwrap();
+
+        let encoder = Encoder::new();
+        let mut watcher = DirWatcher::new(input_dir.path());
+        let mut poll =
+            || encode_new_covers(&mut watcher, &encoder, b"watched", output_dir.path()).unwrap();
+        assert!(poll().is_empty());
+        let written = poll();
+        assert_eq!(written, vec![output_dir.path().join("dropped.png")]);
+        assert!(poll().is_empty());
+
+        let stego = StegoImage::from_file(&written[0]).unwrap();
+        assert_eq!(Decoder::new().decode(&stego).unwrap(), b"watched");
+    }
+}
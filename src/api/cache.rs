@@ -0,0 +1,297 @@
+//! In-memory LRU cache of recently decoded results
+
+use crate::warning::Warning;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Cache key: SHA-256 of the uploaded image plus the decoder options
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    /// SHA-256 digest of the uploaded bytes
+    digest: [u8; 32],
+
+    /// Whether partial decoding was allowed
+    allow_partial: bool,
+
+    /// Cover dimensions the decoder was told to expect
+    expected_dimensions: Option<(u32, u32)>,
+}
+
+impl CacheKey {
+    /// Create a key for the given upload and decode mode
+    pub fn new(data: &[u8], allow_partial: bool) -> Self {
+        Self {
+            digest: Sha256::digest(data).into(),
+            allow_partial,
+            expected_dimensions: None,
+        }
+    }
+
+    /// Key the decode to the cover dimensions it was given, if any
+    pub fn with_expected_dimensions(mut self, expected_dimensions: Option<(u32, u32)>) -> Self {
+        self.expected_dimensions = expected_dimensions;
+        self
+    }
+}
+
+/// The payload fields of a successful decode
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedDecode {
+    /// The decoded message (if it's valid UTF-8 text)
+    pub message: Option<String>,
+
+    /// The decoded binary message (base64 encoded)
+    pub binary_message: String,
+
+    /// Length of the decoded message in bytes
+    pub message_length: usize,
+
+    /// Message length declared in the header (only for partial decodes)
+    pub declared_length: Option<usize>,
+
+    /// Number of message bytes recovered (only for partial decodes)
+    pub recovered_length: Option<usize>,
+
+    /// Whether the full declared message was recovered (only for partial decodes)
+    pub complete: Option<bool>,
+
+    /// Things the decode noticed that didn't stop it
+    pub warnings: Vec<Warning>,
+}
+
+impl CachedDecode {
+    /// Approximate memory used by this entry
+    fn size_bytes(&self) -> usize {
+        self.message.as_ref().map_or(0, String::len) + self.binary_message.len()
+    }
+}
+
+/// Snapshot of the cache counters
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CacheMetrics {
+    /// Number of lookups answered from the cache
+    pub hits: u64,
+
+    /// Number of lookups that missed the cache
+    pub misses: u64,
+
+    /// Number of entries currently cached
+    pub entries: usize,
+
+    /// Approximate number of bytes currently cached
+    pub bytes: usize,
+}
+
+/// Entries and recency order, guarded together
+#[derive(Debug, Default)]
+struct CacheInner {
+    /// Cached entries by key
+    entries: HashMap<CacheKey, CachedDecode>,
+
+    /// Keys from least to most recently used
+    order: VecDeque<CacheKey>,
+
+    /// Total size of all entries
+    total_bytes: usize,
+}
+
+impl CacheInner {
+    /// Move a key to the most recently used position
+    fn touch(&mut self, key: &CacheKey) {
+        if let Some(pos) = self.order.iter().position(|k| k == key) {
+            self.order.remove(pos);
+        }
+        self.order.push_back(*key);
+    }
+
+    /// Remove the least recently used entry
+    fn evict_oldest(&mut self) {
+        if let Some(key) = self.order.pop_front() {
+            if let Some(entry) = self.entries.remove(&key) {
+                self.total_bytes -= entry.size_bytes();
+            }
+        }
+    }
+}
+
+/// Size-bounded LRU cache of decode results
+///
+/// The cache is bounded both by entry count and by total size; a cache with
+/// either limit set to zero is disabled.
+#[derive(Debug)]
+pub struct DecodeCache {
+    /// Maximum number of entries
+    max_entries: usize,
+
+    /// Maximum total size of all entries
+    max_bytes: usize,
+
+    /// Cached entries
+    inner: Mutex<CacheInner>,
+
+    /// Number of lookups answered from the cache
+    hits: AtomicU64,
+
+    /// Number of lookups that missed the cache
+    misses: AtomicU64,
+}
+
+impl DecodeCache {
+    /// Create a cache with the given limits
+    pub fn new(max_entries: usize, max_bytes: usize) -> Self {
+        Self {
+            max_entries,
+            max_bytes,
+            inner: Mutex::new(CacheInner::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Whether the cache stores anything at all
+    pub fn is_enabled(&self) -> bool {
+        self.max_entries > 0 && self.max_bytes > 0
+    }
+
+    /// Look up a decode result, counting the hit or miss
+    pub fn get(&self, key: &CacheKey) -> Option<CachedDecode> {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+
+        let entry = inner.entries.get(key).cloned();
+        if entry.is_some() {
+            inner.touch(key);
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+
+        entry
+    }
+
+    /// Store a decode result, evicting the least recently used entries as needed
+    pub fn insert(&self, key: CacheKey, value: CachedDecode) {
+        let size = value.size_bytes();
+        if !self.is_enabled() || size > self.max_bytes {
+            return;
+        }
+
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+
+        // Replace any existing entry for the same key
+        if let Some(old) = inner.entries.remove(&key) {
+            inner.total_bytes -= old.size_bytes();
+        }
+
+        while inner.entries.len() >= self.max_entries || inner.total_bytes + size > self.max_bytes {
+            inner.evict_oldest();
+        }
+
+        inner.total_bytes += size;
+        inner.entries.insert(key, value);
+        inner.touch(&key);
+    }
+
+    /// Get the current cache counters
+    pub fn metrics(&self) -> CacheMetrics {
+        let inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+
+        CacheMetrics {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: inner.entries.len(),
+            bytes: inner.total_bytes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(text: &str) -> CachedDecode {
+        CachedDecode {
+            message: Some(text.to_string()),
+            binary_message: String::new(),
+            message_length: text.len(),
+            declared_length: None,
+            recovered_length: None,
+            complete: None,
+            warnings: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used_entry() {
+        let cache = DecodeCache::new(2, 1024);
+        let (a, b, c) = (
+            CacheKey::new(b"a", false),
+            CacheKey::new(b"b", false),
+            CacheKey::new(b"c", false),
+        );
+
+        cache.insert(a, entry("a"));
+        cache.insert(b, entry("b"));
+
+        // Touch `a` so that `b` becomes the oldest
+        assert_eq!(cache.get(&a), Some(entry("a")));
+        cache.insert(c, entry("c"));
+
+        assert!(cache.get(&b).is_none());
+        assert!(cache.get(&a).is_some());
+        assert!(cache.get(&c).is_some());
+
+        let metrics = cache.metrics();
+        assert_eq!(metrics.hits, 3);
+        assert_eq!(metrics.misses, 1);
+        assert_eq!(metrics.entries, 2);
+    }
+
+    #[test]
+    fn test_evicts_by_total_bytes() {
+        let cache = DecodeCache::new(10, 10);
+        let (a, b) = (CacheKey::new(b"a", false), CacheKey::new(b"b", false));
+
+        cache.insert(a, entry("123456"));
+        cache.insert(b, entry("7890ab"));
+
+        // Only one six-byte entry fits in ten bytes
+        assert!(cache.get(&a).is_none());
+        assert!(cache.get(&b).is_some());
+        assert_eq!(cache.metrics().bytes, 6);
+
+        // Entries larger than the whole budget are never stored
+        cache.insert(a, entry("this is far too long"));
+        assert!(cache.get(&a).is_none());
+    }
+
+    #[test]
+    fn test_decoder_options_are_part_of_the_key() {
+        let cache = DecodeCache::new(4, 1024);
+        cache.insert(CacheKey::new(b"image", false), entry("strict"));
+
+        assert!(cache.get(&CacheKey::new(b"image", true)).is_none());
+        assert!(cache.get(&CacheKey::new(b"image", false)).is_some());
+
+        let sized = CacheKey::new(b"image", false).with_expected_dimensions(Some((40, 30)));
+        assert!(cache.get(&sized).is_none());
+        cache.insert(sized, entry("sized"));
+        assert_eq!(cache.get(&sized), Some(entry("sized")));
+        assert_eq!(
+            cache.get(&CacheKey::new(b"image", false).with_expected_dimensions(Some((30, 40)))),
+            None
+        );
+    }
+
+    #[test]
+    fn test_disabled_cache_stores_nothing() {
+        let cache = DecodeCache::new(0, 1024);
+        let key = CacheKey::new(b"a", false);
+
+        assert!(!cache.is_enabled());
+        cache.insert(key, entry("a"));
+        assert!(cache.get(&key).is_none());
+    }
+}
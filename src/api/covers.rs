@@ -0,0 +1,216 @@
+//! Server-side pool of cover images for clients that don't upload one
+
+use crate::api::models::{CoverInfo, ImageMetadata};
+use crate::encoder::Encoder;
+use log::{info, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::Duration;
+
+/// `cover_id` value asking the server to pick a cover
+pub const AUTO_COVER_ID: &str = "auto";
+
+/// A cover image available in the pool
+#[derive(Debug, Clone)]
+pub struct PoolCover {
+    /// Path of the cover file
+    pub path: PathBuf,
+
+    /// How the cover is listed to clients
+    pub info: CoverInfo,
+}
+
+/// Cached listing of the images in the cover pool directory
+///
+/// The pool files are only ever read; encodes write their output to the
+/// upload directory.
+#[derive(Debug, Default)]
+pub struct CoverPool {
+    /// Directory holding the covers, if the pool is enabled
+    dir: Option<PathBuf>,
+
+    /// Covers found by the last scan, smallest capacity first
+    covers: RwLock<Vec<PoolCover>>,
+}
+
+impl CoverPool {
+    /// Create a pool and scan its directory
+    ///
+    /// # Arguments
+    /// * `dir` - Directory holding the covers; `None` disables the pool
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        let pool = Self {
+            dir,
+            covers: RwLock::new(Vec::new()),
+        };
+        pool.refresh();
+        pool
+    }
+
+    /// Whether a pool directory is configured
+    pub fn is_enabled(&self) -> bool {
+        self.dir.is_some()
+    }
+
+    /// Rescan the pool directory
+    pub fn refresh(&self) {
+        let Some(dir) = &self.dir else {
+            return;
+        };
+
+        let covers = scan_covers(dir);
+        info!("Cover pool refreshed: {} covers", covers.len());
+        // The list is only ever replaced whole, so a poisoned lock still holds a valid one
+        *self.covers.write().unwrap_or_else(PoisonError::into_inner) = covers;
+    }
+
+    /// Rescan the pool directory periodically on the current runtime
+    pub fn spawn_refresh(self: Arc<Self>, every: Duration) {
+        if !self.is_enabled() || every.is_zero() {
+            return;
+        }
+
+        actix_web::rt::spawn(async move {
+            let mut interval = actix_web::rt::time::interval(every);
+            // The first tick completes immediately and the pool was just scanned
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                self.refresh();
+            }
+        });
+    }
+
+    /// List the covers, smallest capacity first
+    pub fn list(&self) -> Vec<CoverInfo> {
+        self.covers
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|cover| cover.info.clone())
+            .collect()
+    }
+
+    /// Find a cover by ID
+    pub fn get(&self, id: &str) -> Option<PoolCover> {
+        self.covers
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .find(|cover| cover.info.id == id)
+            .cloned()
+    }
+
+    /// Find the smallest cover that can hold a message
+    ///
+    /// # Arguments
+    /// * `message_length` - Length of the message in bytes
+    pub fn smallest_fitting(&self, message_length: usize) -> Option<PoolCover> {
+        self.covers
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .find(|cover| cover.info.metadata.max_message_bytes >= message_length)
+            .cloned()
+    }
+}
+
+/// Read the dimensions of every image in a directory
+fn scan_covers(dir: &Path) -> Vec<PoolCover> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Failed to read cover pool {}: {}", dir.display(), e);
+            return Vec::new();
+        }
+    };
+
+    let mut covers: Vec<PoolCover> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_file()))
+        .filter_map(|entry| {
+            let path = entry.path();
+            let id = path.file_name()?.to_str()?.to_string();
+            let (width, height) = image::image_dimensions(&path).ok()?;
+            let format = image::ImageFormat::from_path(&path)
+                .ok()?
+                .extensions_str()
+                .first()?
+                .to_string();
+
+            Some(PoolCover {
+                info: CoverInfo {
+                    id,
+                    metadata: ImageMetadata {
+                        width,
+                        height,
+                        format,
+                        size_bytes: entry.metadata().ok()?.len() as usize,
+                        max_message_bytes: Encoder::max_message_size_for_dimensions(width, height),
+                        embedded_message_bytes: None,
+                        stored_message_bytes: None,
+                        message_sha256: None,
+                        content_sha256: None,
+                    },
+                },
+                path,
+            })
+        })
+        .collect();
+
+    covers.sort_by(|a, b| {
+        a.info
+            .metadata
+            .max_message_bytes
+            .cmp(&b.info.metadata.max_message_bytes)
+            .then_with(|| a.info.id.cmp(&b.info.id))
+    });
+    covers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::img::create_rgb_image;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_scan_and_select() {
+        let dir = tempdir().unwrap();
+        create_rgb_image(40, 40)
+            .unwrap()
+            .save(dir.path().join("large.png"))
+            .unwrap();
+        create_rgb_image(10, 10)
+            .unwrap()
+            .save(dir.path().join("small.png"))
+            .unwrap();
+        fs::write(dir.path().join("notes.txt"), "not an image").unwrap();
+
+        let pool = CoverPool::new(Some(dir.path().to_path_buf()));
+        let ids: Vec<_> = pool.list().into_iter().map(|cover| cover.id).collect();
+        assert_eq!(ids, ["small.png", "large.png"]);
+
+        assert_eq!(pool.smallest_fitting(10).unwrap().info.id, "small.png");
+        assert_eq!(pool.smallest_fitting(100).unwrap().info.id, "large.png");
+        assert!(pool.smallest_fitting(1000).is_none());
+        assert!(pool.get("../large.png").is_none());
+
+        // New files show up after a refresh
+        create_rgb_image(20, 20)
+            .unwrap()
+            .save(dir.path().join("medium.png"))
+            .unwrap();
+        assert!(pool.get("medium.png").is_none());
+        pool.refresh();
+        assert!(pool.get("medium.png").is_some());
+    }
+
+    #[test]
+    fn test_disabled_pool() {
+        let pool = CoverPool::new(None);
+        assert!(!pool.is_enabled());
+        assert!(pool.list().is_empty());
+    }
+}
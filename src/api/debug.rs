@@ -0,0 +1,134 @@
+//! Traces of what a request received, for debugging uploads
+//!
+//! A [`RequestTrace`] is filled in while a request is handled and turned into
+//! the [`DebugInfo`] of the response when the server allows debug responses
+//! (`ServerConfig::allow_debug_responses`) and the request asks for one with
+//! `debug=true`. It records sizes, sniffed formats, temporary file names and
+//! stage timings only; field contents are never kept.
+
+use crate::api::models::{DebugInfo, FieldTrace, StageTiming};
+use std::path::Path;
+use std::time::Instant;
+
+/// Collects the fields and stage timings of one request
+#[derive(Debug)]
+pub struct RequestTrace {
+    /// What has been recorded so far
+    info: DebugInfo,
+
+    /// When the current stage started
+    stage_start: Instant,
+}
+
+impl Default for RequestTrace {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RequestTrace {
+    /// Start a trace; the first stage starts now
+    pub fn new() -> Self {
+        Self {
+            info: DebugInfo::default(),
+            stage_start: Instant::now(),
+        }
+    }
+
+    /// Record a form field read into memory
+    ///
+    /// # Arguments
+    /// * `name` - Name of the field
+    /// * `bytes` - Size of its content
+    pub fn field(&mut self, name: &str, bytes: usize) {
+        self.info.fields.push(FieldTrace {
+            name: name.to_string(),
+            bytes: bytes as u64,
+            format: None,
+            temp_file: None,
+        });
+    }
+
+    /// Record a form field saved to a temporary file
+    ///
+    /// The size and format are read back from the file, so they describe what
+    /// was actually written.
+    ///
+    /// # Arguments
+    /// * `name` - Name of the field
+    /// * `path` - The temporary file
+    pub fn file(&mut self, name: &str, path: &Path) {
+        let format = image::ImageReader::open(path)
+            .and_then(|reader| reader.with_guessed_format())
+            .ok()
+            .and_then(|reader| reader.format())
+            .and_then(|format| format.extensions_str().first())
+            .map(|extension| extension.to_string());
+
+        self.info.fields.push(FieldTrace {
+            name: name.to_string(),
+            bytes: path.metadata().map(|metadata| metadata.len()).unwrap_or(0),
+            format,
+            temp_file: path
+                .file_name()
+                .map(|file_name| file_name.to_string_lossy().into_owned()),
+        });
+    }
+
+    /// End the current stage and start the next
+    ///
+    /// # Arguments
+    /// * `stage` - Name of the stage that just ended
+    pub fn stage(&mut self, stage: &str) {
+        let now = Instant::now();
+        self.info.stages.push(StageTiming {
+            stage: stage.to_string(),
+            ms: now.duration_since(self.stage_start).as_secs_f64() * 1000.0,
+        });
+        self.stage_start = now;
+    }
+
+    /// The trace for the response, if it should be included
+    ///
+    /// # Arguments
+    /// * `enabled` - Whether the server allows debug responses and the request asked for one
+    pub fn into_debug(self, enabled: bool) -> Option<DebugInfo> {
+        enabled.then_some(self.info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_records_fields_and_stages_in_order() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("cover.png");
+        image::RgbImage::new(2, 2).save(&path).unwrap();
+
+        let mut trace = RequestTrace::new();
+        trace.field("message", 5);
+        trace.file("cover_image", &path);
+        trace.stage("upload");
+        trace.stage("encode");
+
+        let info = trace.into_debug(true).unwrap();
+        assert_eq!(info.fields[0].name, "message");
+        assert_eq!(info.fields[0].bytes, 5);
+        assert_eq!(info.fields[0].format, None);
+        assert_eq!(info.fields[1].format.as_deref(), Some("png"));
+        assert_eq!(info.fields[1].temp_file.as_deref(), Some("cover.png"));
+        assert_eq!(info.fields[1].bytes, path.metadata().unwrap().len());
+        let stages: Vec<_> = info.stages.iter().map(|s| s.stage.as_str()).collect();
+        assert_eq!(stages, ["upload", "encode"]);
+    }
+
+    #[test]
+    fn test_disabled_trace_is_dropped() {
+        let mut trace = RequestTrace::new();
+        trace.field("message", 5);
+        assert_eq!(trace.into_debug(false), None);
+    }
+}
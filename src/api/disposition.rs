@@ -0,0 +1,139 @@
+//! `Content-Disposition` headers for downloads
+//!
+//! Filenames are sent twice: as a quoted ASCII fallback, and when that lost
+//! anything, RFC 5987 percent-encoded UTF-8 in `filename*`. Control
+//! characters are dropped from both, so a filename can't end the header and
+//! start another.
+
+use serde::Deserialize;
+use std::fmt::Write;
+
+/// How a browser should present a download
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DispositionKind {
+    /// Show it in the browser window
+    Inline,
+
+    /// Save it as a file
+    Attachment,
+}
+
+impl DispositionKind {
+    /// Name of the disposition type in the header
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Inline => "inline",
+            Self::Attachment => "attachment",
+        }
+    }
+}
+
+/// The `disposition` query parameter of download endpoints
+#[derive(Debug, Default, Deserialize)]
+pub struct DispositionQuery {
+    /// How to present the download; each endpoint has its own default
+    pub disposition: Option<DispositionKind>,
+}
+
+/// Build a `Content-Disposition` header value
+///
+/// # Arguments
+/// * `filename` - Name to suggest for the file, in any script
+/// * `kind` - Whether the file is shown or saved
+///
+/// # Returns
+/// * The header value, safe to send whatever the filename holds
+pub fn content_disposition(filename: &str, kind: DispositionKind) -> String {
+    let filename: String = filename.chars().filter(|c| !c.is_control()).collect();
+    let fallback: String = filename
+        .chars()
+        .map(|c| match c {
+            '"' | '\\' => '_',
+            c if c.is_ascii() => c,
+            _ => '_',
+        })
+        .collect();
+
+    let mut value = format!("{}; filename=\"{}\"", kind.as_str(), fallback);
+    if fallback != filename {
+        value.push_str("; filename*=UTF-8''");
+        for byte in filename.bytes() {
+            if is_attr_char(byte) {
+                value.push(byte as char);
+            } else {
+                // Writing to a String can't fail
+                let _ = write!(value, "%{:02X}", byte);
+            }
+        }
+    }
+    value
+}
+
+/// Check whether RFC 5987 allows a byte unencoded in an extended value
+fn is_attr_char(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric()
+        || matches!(
+            byte,
+            b'!' | b'#' | b'$' | b'&' | b'+' | b'-' | b'.' | b'^' | b'_' | b'`' | b'|' | b'~'
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::{ContentDisposition, HeaderValue};
+
+    #[test]
+    fn test_plain_filenames_are_quoted() {
+        assert_eq!(
+            content_disposition("stego image.png", DispositionKind::Inline),
+            "inline; filename=\"stego image.png\""
+        );
+        assert_eq!(
+            parsed_filename(&content_disposition(
+                "stego image.png",
+                DispositionKind::Inline
+            )),
+            "stego image.png"
+        );
+    }
+
+    #[test]
+    fn test_unicode_filenames_get_an_encoded_copy() {
+        assert_eq!(
+            content_disposition("café ünïcode.png", DispositionKind::Attachment),
+            "attachment; filename=\"caf_ _n_code.png\"; \
+             filename*=UTF-8''caf%C3%A9%20%C3%BCn%C3%AFcode.png"
+        );
+        assert_eq!(
+            content_disposition("say \"hi\".png", DispositionKind::Attachment),
+            "attachment; filename=\"say _hi_.png\"; filename*=UTF-8''say%20%22hi%22.png"
+        );
+        assert_eq!(
+            parsed_filename(&content_disposition(
+                "café ünïcode.png",
+                DispositionKind::Attachment
+            )),
+            "café ünïcode.png"
+        );
+    }
+
+    #[test]
+    fn test_header_injection_is_stripped() {
+        let value = content_disposition("foo\r\nSet-Cookie: x", DispositionKind::Attachment);
+        assert_eq!(value, "attachment; filename=\"fooSet-Cookie: x\"");
+        assert!(!value.contains(['\r', '\n']));
+        assert_eq!(parsed_filename(&value), "fooSet-Cookie: x");
+    }
+
+    /// Parse a header value the way clients do and get the filename back
+    fn parsed_filename(value: &str) -> String {
+        let header = HeaderValue::from_str(value).unwrap();
+        let parsed = ContentDisposition::from_raw(&header).unwrap();
+        match parsed.get_filename_ext() {
+            Some(ext) => String::from_utf8(ext.value.clone()).unwrap(),
+            None => parsed.get_filename().unwrap().to_string(),
+        }
+    }
+}
@@ -0,0 +1,98 @@
+//! Mounting the API inside another actix-web application
+//!
+//! [`scope`] bundles the routes with their own [`AppState`], so a host
+//! application needs nothing but
+//! `App::new().service(hide_rs::api::scope(config.clone()))`. The request
+//! limits in the [`ServerConfig`] travel with that state, and the legacy
+//! routes keep their deprecation headers; access logging is left to the host.
+
+use crate::api::handlers::{AppState, ServerConfig};
+use crate::api::routes::register_routes;
+use actix_web::{web, Scope};
+use std::time::Duration;
+
+/// Configuration of an API mounted inside another application
+///
+/// Create it once and clone it into each worker's `App`, so the workers
+/// share caches, stores and limits.
+#[derive(Clone)]
+pub struct ApiConfig {
+    /// Path the API is mounted under, without a trailing slash
+    mount_path: String,
+
+    /// State shared by every worker
+    state: web::Data<AppState>,
+}
+
+impl ApiConfig {
+    /// Create the configuration for an API mounted under a path
+    ///
+    /// # Arguments
+    /// * `mount_path` - Path such as `/hide`; empty or `/` for the root
+    /// * `config` - Server configuration, including the request limits
+    pub fn new(mount_path: &str, config: ServerConfig) -> Self {
+        let trimmed = mount_path.trim_matches('/');
+        let mount_path = if trimmed.is_empty() {
+            String::new()
+        } else {
+            format!("/{}", trimmed)
+        };
+
+        let mut state = AppState::new(config);
+        state.url_builder = state.url_builder.with_mount_path(&mount_path);
+
+        Self {
+            mount_path,
+            state: web::Data::new(state),
+        }
+    }
+
+    /// Path the API is mounted under, empty for the root
+    pub fn mount_path(&self) -> &str {
+        &self.mount_path
+    }
+
+    /// State shared by the mounted routes
+    pub fn state(&self) -> &web::Data<AppState> {
+        &self.state
+    }
+
+    /// Start the background tasks of the API, such as refreshing the cover pool
+    ///
+    /// Call this once, from within the host's actix runtime.
+    pub fn spawn_background_tasks(&self) {
+        self.state
+            .cover_pool
+            .clone()
+            .spawn_refresh(Duration::from_secs(
+                self.state.config.cover_pool_refresh_secs,
+            ));
+    }
+}
+
+/// Build a scope serving the whole API under the configured mount path
+///
+/// # Arguments
+/// * `config` - The mount path and shared state
+///
+/// # Returns
+/// * A scope to register with `App::service`
+pub fn scope(config: ApiConfig) -> Scope {
+    let legacy_enabled = config.state.config.enable_legacy_routes;
+    web::scope(&config.mount_path)
+        .app_data(config.state)
+        .configure(move |cfg| register_routes(cfg, move |_| legacy_enabled))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mount_path_is_normalized() {
+        for (given, expected) in [("", ""), ("/", ""), ("hide", "/hide"), ("/a/b/", "/a/b")] {
+            let config = ApiConfig::new(given, ServerConfig::default());
+            assert_eq!(config.mount_path(), expected);
+        }
+    }
+}
@@ -1,21 +1,47 @@
 //! Request handlers for the REST API
 
+use crate::api::cache::{CacheKey, CachedDecode, DecodeCache};
+use crate::api::covers::{CoverPool, AUTO_COVER_ID};
+use crate::api::debug::RequestTrace;
+use crate::api::disposition::{content_disposition, DispositionKind};
+use crate::api::idempotency::{
+    Claim, IdempotencyKey, IdempotencyStore, RequestDigest, IDEMPOTENCY_KEY_HEADER, REPLAY_HEADER,
+};
 use crate::api::models::*;
-use crate::decoder::create_decoder;
-use crate::encoder::create_encoder;
+use crate::api::signing::{SignatureError, SignedQuery, UrlSigner};
+use crate::api::spool::{KeepError, SpoolFull, SpoolManager};
+use crate::api::store::{
+    self, Cursor, FsMetadataStore, MetadataStore, CONTENT_SHA256_HEADER, DEFAULT_PAGE_LIMIT,
+};
+use crate::api::urls::UrlBuilder;
+use crate::capabilities::{self, Algorithm, Capabilities, Limits};
+use crate::decoder::{Decoder, DecoderConfig, PartialDecode, UntrustedLimits};
+use crate::depth::EmbeddingDepth;
+use crate::encoder::{self, EncodeStats, Encoder, EncoderConfig};
 use crate::error::HideError;
-use crate::img::StegoImage;
-
-use actix_multipart::Multipart;
-use actix_web::{Error, HttpResponse};
+use crate::img::{self, FillStyle, StegoImage};
+#[cfg(feature = "jpeg")]
+use crate::jpeg::JpegStego;
+use crate::manifest;
+use crate::planner::{self, PlanConstraints};
+use crate::robustness::IntendedChannel;
+use crate::utils::{self, Timing};
+use crate::warning::Warning;
+
+use actix_multipart::{Field, Multipart};
+use actix_web::{http::header, Error, HttpRequest, HttpResponse};
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
 use futures::StreamExt;
 use log::{error, info, warn};
 use mime_guess::from_path;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::fs;
-use std::io::Write;
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 use uuid::Uuid;
 
 /// Stores temporary files related to a request
@@ -29,6 +55,9 @@ pub struct RequestFiles {
 
     /// Paths to any files created
     pub file_paths: Vec<PathBuf>,
+
+    /// Spool accounting for the files, if the request was admitted to one
+    spool: Option<Arc<SpoolManager>>,
 }
 
 impl RequestFiles {
@@ -38,6 +67,33 @@ impl RequestFiles {
             base_dir: base_dir.as_ref().to_path_buf(),
             request_id,
             file_paths: Vec::new(),
+            spool: None,
+        }
+    }
+
+    /// Create the files of a request admitted to a spool with a disk budget
+    ///
+    /// # Arguments
+    /// * `spool` - The spool holding the request directories
+    /// * `request_id` - ID of the request
+    ///
+    /// # Returns
+    /// * The request's files, or `SpoolFull` if the spool is over its budget
+    pub fn in_spool(spool: &Arc<SpoolManager>, request_id: Uuid) -> Result<Self, SpoolFull> {
+        spool.admit(request_id)?;
+        Ok(Self {
+            base_dir: spool.root().to_path_buf(),
+            request_id,
+            file_paths: Vec::new(),
+            spool: Some(Arc::clone(spool)),
+        })
+    }
+
+    /// Account for bytes about to be written to this request's files
+    pub fn reserve(&self, bytes: u64) -> Result<(), SpoolFull> {
+        match &self.spool {
+            Some(spool) => spool.reserve(self.request_id, bytes),
+            None => Ok(()),
         }
     }
 
@@ -48,7 +104,7 @@ impl RequestFiles {
         fs::create_dir_all(&dir_path)?;
 
         // Create file
-        let file_path = dir_path.join(sanitize_filename::sanitize(filename));
+        let file_path = dir_path.join(utils::safe_filename(Some(filename), "upload"));
         let file = fs::File::create(&file_path)?;
 
         // Remember the file path
@@ -67,6 +123,10 @@ impl RequestFiles {
                 self.request_id, e
             );
         }
+
+        if let Some(spool) = &self.spool {
+            spool.release(self.request_id);
+        }
     }
 }
 
@@ -84,6 +144,117 @@ pub fn hide_error_to_response(err: HideError, request_id: Uuid) -> ErrorResponse
             error_codes::MESSAGE_TOO_LARGE,
             "Message is too large for the given image",
         ),
+        HideError::ExistingPayload { declared_length } => ErrorResponse::new(
+            request_id,
+            error_codes::EXISTING_PAYLOAD,
+            &format!(
+                "Cover image already contains a hidden payload of {} bytes; set overwrite=true to replace it",
+                declared_length
+            ),
+        )
+        .with_details(ExistingPayloadDetails { declared_length }),
+        HideError::OutputLimitExceeded {
+            declared_length,
+            limit,
+        } => ErrorResponse::new(
+            request_id,
+            error_codes::OUTPUT_LIMIT_EXCEEDED,
+            &format!(
+                "Hidden message of {} bytes exceeds the decode limit of {} bytes",
+                declared_length, limit
+            ),
+        )
+        .with_details(LimitDetails {
+            required: declared_length as u64,
+            limit: limit as u64,
+        }),
+        HideError::PixelLimitExceeded { required, limit } => ErrorResponse::new(
+            request_id,
+            error_codes::PIXEL_LIMIT_EXCEEDED,
+            &format!(
+                "Decoding requires {} pixels, exceeding the limit of {}",
+                required, limit
+            ),
+        )
+        .with_details(LimitDetails { required, limit }),
+        HideError::UnsupportedFeature { version, bits } => ErrorResponse::new(
+            request_id,
+            error_codes::UNSUPPORTED_FEATURE,
+            &if bits == 0 {
+                format!(
+                    "Hidden payload uses format version {}, which this server does not support",
+                    version
+                )
+            } else {
+                format!(
+                    "Hidden payload uses features this server does not support (flags {:#06x})",
+                    bits
+                )
+            },
+        )
+        .with_details(UnsupportedFeatureDetails {
+            format_version: version,
+            flags: bits,
+        }),
+        HideError::ChangeBudgetExceeded { needed, allowed } => ErrorResponse::new(
+            request_id,
+            error_codes::CHANGE_BUDGET_EXCEEDED,
+            &format!(
+                "Encoding would change {} pixels, exceeding the budget of {}",
+                needed, allowed
+            ),
+        )
+        .with_details(LimitDetails {
+            required: needed as u64,
+            limit: allowed as u64,
+        }),
+        HideError::DeadlineExceeded => ErrorResponse::new(
+            request_id,
+            error_codes::DEADLINE_EXCEEDED,
+            "Decoding took too long and was aborted",
+        ),
+        HideError::TruncatedPayload {
+            declared,
+            available,
+        } => ErrorResponse::new(
+            request_id,
+            error_codes::TRUNCATED_PAYLOAD,
+            &format!(
+                "Hidden message declares {} bytes but the image only holds {}; it may have been cropped or only partly encoded",
+                declared, available
+            ),
+        )
+        .with_details(TruncatedPayloadDetails {
+            declared_length: declared,
+            available_length: available,
+        }),
+        HideError::ColorConverted(cause) => {
+            // Keep the details of the underlying failure
+            let mut response = hide_error_to_response(*cause, request_id);
+            response.error_code = error_codes::COLOR_CONVERTED.to_string();
+            response.message = format!(
+                "{}; this image appears to have been color-converted, use the original file",
+                response.message
+            );
+            response
+        }
+        HideError::DimensionsChanged {
+            encoded,
+            actual,
+            cause,
+        } => {
+            // The size change replaces the underlying failure's details
+            let mut response = hide_error_to_response(*cause, request_id);
+            response.error_code = error_codes::DIMENSIONS_CHANGED.to_string();
+            response.message = format!(
+                "{}; the image is {}x{} but was encoded at {}x{}, it was likely resized",
+                response.message, actual.0, actual.1, encoded.0, encoded.1
+            );
+            response.with_details(DimensionsChangedDetails {
+                encoded: encoded.into(),
+                actual: actual.into(),
+            })
+        }
         HideError::NoMessageFound => ErrorResponse::new(
             request_id,
             error_codes::NO_MESSAGE_FOUND,
@@ -94,9 +265,29 @@ pub fn hide_error_to_response(err: HideError, request_id: Uuid) -> ErrorResponse
             error_codes::INVALID_IMAGE,
             &format!("Invalid image: {}", e),
         ),
+        e @ HideError::InvalidJpeg(_) => {
+            ErrorResponse::new(request_id, error_codes::INVALID_IMAGE, &e.to_string())
+        }
         HideError::InvalidParameters(msg) => {
             ErrorResponse::new(request_id, error_codes::VALIDATION_ERROR, &msg)
         }
+        e @ HideError::EncryptedPayload => {
+            ErrorResponse::new(request_id, error_codes::ENCRYPTED_PAYLOAD, &e.to_string())
+        }
+        e @ HideError::ChecksumMismatch { .. } => {
+            ErrorResponse::new(request_id, error_codes::CHECKSUM_MISMATCH, &e.to_string())
+        }
+        e @ HideError::UncorrectableErrors { .. } => {
+            ErrorResponse::new(request_id, error_codes::UNCORRECTABLE_ERRORS, &e.to_string())
+        }
+        HideError::SelfCheckFailed { detail } => ErrorResponse::new(
+            request_id,
+            error_codes::SELF_CHECK_FAILED,
+            &format!(
+                "The encoded image failed to decode back to the message ({}); nothing was stored",
+                detail
+            ),
+        ),
         _ => ErrorResponse::new(
             request_id,
             error_codes::INTERNAL_ERROR,
@@ -105,9 +296,147 @@ pub fn hide_error_to_response(err: HideError, request_id: Uuid) -> ErrorResponse
     }
 }
 
+/// Build the HTTP response for a failed decode
+///
+/// Decode limit violations get their own status codes; other errors are the
+/// client's fault.
+pub fn decode_error_response(err: HideError, request_id: Uuid) -> HttpResponse {
+    let mut builder = match err {
+        HideError::OutputLimitExceeded { .. } | HideError::PixelLimitExceeded { .. } => {
+            HttpResponse::PayloadTooLarge()
+        }
+        HideError::DeadlineExceeded => HttpResponse::ServiceUnavailable(),
+        _ => HttpResponse::BadRequest(),
+    };
+    builder.json(hide_error_to_response(err, request_id))
+}
+
+/// Seconds clients should wait before retrying when storage is unavailable
+pub const STORAGE_RETRY_AFTER_SECS: u64 = 30;
+
+/// Name of the file a decoded message is stored in, in its spool directory
+pub const DECODED_MESSAGE_FILE: &str = "message.bin";
+
+/// Check whether an I/O error means the upload directory can't be written to
+pub fn is_storage_unavailable(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::PermissionDenied
+            | io::ErrorKind::ReadOnlyFilesystem
+            | io::ErrorKind::StorageFull
+    )
+}
+
+/// Record a successful write to the upload directory
+///
+/// Logs once when storage recovers from a degraded state.
+fn mark_storage_healthy(storage_degraded: &AtomicBool) {
+    if storage_degraded.swap(false, Ordering::SeqCst) {
+        info!("Upload directory is writable again");
+    }
+}
+
+/// Build the response for a failed write to the upload directory
+///
+/// Storage failures return `storage_unavailable` with a 503 and mark storage
+/// as degraded, logging only when the state changes. Other failures are
+/// reported as internal errors with the given message.
+fn storage_error_response(
+    err: &io::Error,
+    storage_degraded: &AtomicBool,
+    request_id: Uuid,
+    message: &str,
+) -> HttpResponse {
+    if !is_storage_unavailable(err) {
+        error!("{}: {}", message, err);
+        return HttpResponse::InternalServerError().json(ErrorResponse::new(
+            request_id,
+            error_codes::INTERNAL_ERROR,
+            message,
+        ));
+    }
+
+    storage_unavailable_response(err, storage_degraded, request_id)
+}
+
+/// Build the `storage_unavailable` response and mark storage as degraded
+///
+/// Logs the cause only when the state changes.
+fn storage_unavailable_response(
+    cause: &dyn std::fmt::Display,
+    storage_degraded: &AtomicBool,
+    request_id: Uuid,
+) -> HttpResponse {
+    if !storage_degraded.swap(true, Ordering::SeqCst) {
+        error!("Upload directory is unavailable: {}", cause);
+    }
+
+    HttpResponse::ServiceUnavailable()
+        .insert_header((header::RETRY_AFTER, STORAGE_RETRY_AFTER_SECS.to_string()))
+        .json(
+            ErrorResponse::new(
+                request_id,
+                error_codes::STORAGE_UNAVAILABLE,
+                "Upload storage is temporarily unavailable",
+            )
+            .with_details(RetryDetails {
+                retry_after_secs: STORAGE_RETRY_AFTER_SECS,
+            }),
+        )
+}
+
+/// Build the response for a request refused because the spool is over its disk budget
+fn spool_full_response(full: SpoolFull, request_id: Uuid) -> HttpResponse {
+    warn!(
+        "Upload spool is full ({} of {} bytes); refusing request {}",
+        full.used, full.budget, request_id
+    );
+
+    HttpResponse::ServiceUnavailable()
+        .insert_header((header::RETRY_AFTER, STORAGE_RETRY_AFTER_SECS.to_string()))
+        .json(
+            ErrorResponse::new(
+                request_id,
+                error_codes::STORAGE_UNAVAILABLE,
+                "Upload storage is temporarily full",
+            )
+            .with_details(RetryDetails {
+                retry_after_secs: STORAGE_RETRY_AFTER_SECS,
+            }),
+        )
+}
+
+/// Build the response for a failed image save
+fn save_error_response(
+    err: HideError,
+    storage_degraded: &AtomicBool,
+    request_id: Uuid,
+    message: &str,
+) -> HttpResponse {
+    match err {
+        HideError::Io(e) | HideError::Image(image::ImageError::IoError(e)) => {
+            storage_error_response(&e, storage_degraded, request_id, message)
+        }
+        e @ HideError::OutputPathInvalid { .. } => {
+            storage_unavailable_response(&e, storage_degraded, request_id)
+        }
+        e => {
+            error!("{}: {}", message, e);
+            HttpResponse::InternalServerError().json(ErrorResponse::new(
+                request_id,
+                error_codes::INTERNAL_ERROR,
+                message,
+            ))
+        }
+    }
+}
+
 /// Extract image metadata
-pub fn extract_image_metadata(image: &StegoImage) -> ImageMetadata {
-    let encoder = create_encoder();
+///
+/// # Arguments
+/// * `image` - The image to describe
+/// * `encoder` - The encoder whose options the capacity is reported for
+pub fn extract_image_metadata(image: &StegoImage, encoder: &Encoder) -> ImageMetadata {
     let max_message_bytes = encoder.max_message_size(image);
 
     ImageMetadata {
@@ -117,23 +446,45 @@ pub fn extract_image_metadata(image: &StegoImage) -> ImageMetadata {
         size_bytes: 0,             // Will be updated after saving
         max_message_bytes,
         embedded_message_bytes: None,
+        stored_message_bytes: None,
+        message_sha256: None,
+        content_sha256: None,
     }
 }
 
 /// Process a multipart form submission for image encoding
 pub async fn process_encode_form(
+    req: &HttpRequest,
     mut payload: Multipart,
-    upload_dir: &Path,
+    state: &AppState,
 ) -> Result<HttpResponse, Error> {
     info!("Processing encode form submission");
 
+    let AppState {
+        config,
+        spool,
+        storage_degraded,
+        url_builder: urls,
+        cover_pool: covers,
+        idempotency,
+        ..
+    } = state;
+    let mut trace = RequestTrace::new();
     let request_id = Uuid::new_v4();
-    let mut files = RequestFiles::new(upload_dir, request_id);
+    let upload_dir = spool.root();
+    let mut files = match RequestFiles::in_spool(spool, request_id) {
+        Ok(files) => files,
+        Err(full) => return Ok(spool_full_response(full, request_id)),
+    };
 
     let mut cover_image_path: Option<PathBuf> = None;
-    let mut message: Option<String> = None;
-    let mut message_file_content: Option<Vec<u8>> = None;
+    let mut cover_id: Option<String> = None;
+    let mut message: Option<MessageField> = None;
+    let mut message_file_content: Option<MessageField> = None;
     let mut options = EncodeOptions::default();
+    let mut intended_channel: Option<IntendedChannel> = None;
+    let mut variants: Option<Vec<EncodeVariant>> = None;
+    let mut debug_requested = false;
 
     // Process multipart form data
     while let Some(item) = payload.next().await {
@@ -158,202 +509,562 @@ pub async fn process_encode_form(
 
         match field_name.as_str() {
             "cover_image" => {
-                // Fix: Use content_disposition() as Option and then get the filename safely
-                let filename = content_disposition
-                    .and_then(|cd| cd.get_filename())
-                    .unwrap_or("cover_image.png")
-                    .to_string();
-
-                // Create a file to save the uploaded image
-                let (path, mut file) = match files.create_file(&filename) {
-                    Ok((p, f)) => (p, f),
-                    Err(e) => {
-                        error!("Failed to create file: {}", e);
-                        return Ok(HttpResponse::InternalServerError().json(ErrorResponse::new(
-                            request_id,
-                            error_codes::INTERNAL_ERROR,
-                            "Failed to process uploaded file",
-                        )));
-                    }
+                let filename = utils::safe_filename(
+                    content_disposition.and_then(|cd| cd.get_filename()),
+                    "cover_image.png",
+                );
+
+                let path = match save_image_field(
+                    &mut field,
+                    &mut files,
+                    &filename,
+                    request_id,
+                    storage_degraded,
+                )
+                .await
+                {
+                    Ok(p) => p,
+                    Err(response) => return Ok(response),
                 };
 
-                // Save the file
-                let mut size: usize = 0;
-                while let Some(chunk) = field.next().await {
-                    let data = match chunk {
-                        Ok(d) => d,
-                        Err(e) => {
-                            error!("Error reading multipart chunk: {}", e);
-                            return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(
-                                request_id,
-                                error_codes::VALIDATION_ERROR,
-                                &format!("Error reading upload: {}", e),
-                            )));
-                        }
-                    };
-
-                    size += data.len();
-                    if size > MAX_IMAGE_SIZE {
-                        return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(
+                trace.file(&field_name, &path);
+                cover_image_path = Some(path);
+            }
+            "message" | "message_file" => {
+                // Refuse a second message before reading it, rather than picking one
+                let other = match field_name.as_str() {
+                    "message" => &message_file_content,
+                    _ => &message,
+                };
+                if other.is_some() {
+                    return Ok(HttpResponse::BadRequest().json(
+                        ErrorResponse::new(
                             request_id,
-                            error_codes::IMAGE_TOO_LARGE,
-                            &format!("Image exceeds maximum size of {} bytes", MAX_IMAGE_SIZE),
-                        )));
-                    }
+                            error_codes::VALIDATION_ERROR,
+                            "Provide either message or message_file, not both",
+                        )
+                        .with_details(ValidationDetails::conflicting(&["message", "message_file"])),
+                    ));
+                }
 
-                    // Write chunk to file
-                    if let Err(e) = file.write_all(&data) {
-                        error!("Error writing to file: {}", e);
-                        return Ok(HttpResponse::InternalServerError().json(ErrorResponse::new(
-                            request_id,
-                            error_codes::INTERNAL_ERROR,
-                            "Failed to save uploaded file",
-                        )));
-                    }
+                // Keep the message as raw bytes; it need not be text
+                let content = match read_message_field(&mut field, &field_name, request_id).await {
+                    Ok(content) => content,
+                    Err(response) => return Ok(response),
+                };
+
+                trace.field(&field_name, content.content.len());
+                if field_name == "message" {
+                    message = Some(content);
+                } else {
+                    message_file_content = Some(content);
+                }
+            }
+            "output_format" => {
+                // Read the output format
+                let format = match read_text_field(
+                    &mut field,
+                    &field_name,
+                    MAX_TEXT_FIELD_LENGTH,
+                    request_id,
+                )
+                .await
+                {
+                    Ok(value) => value,
+                    Err(response) => return Ok(response),
+                };
+                trace.field(&field_name, format.len());
+
+                let format = format.trim().to_lowercase();
+                if format == "png" || format == "jpeg" || format == "jpg" {
+                    options.output_format = format;
                 }
+            }
+            "jpeg_quality" => {
+                // Read the JPEG quality
+                let quality_str = match read_text_field(
+                    &mut field,
+                    &field_name,
+                    MAX_TEXT_FIELD_LENGTH,
+                    request_id,
+                )
+                .await
+                {
+                    Ok(value) => value,
+                    Err(response) => return Ok(response),
+                };
+                trace.field(&field_name, quality_str.len());
 
-                cover_image_path = Some(path);
+                if let Ok(quality) = quality_str.trim().parse::<u8>() {
+                    options.jpeg_quality = quality.min(100);
+                }
             }
-            "message" => {
-                // Read the message content
-                let mut content = Vec::new();
-                while let Some(chunk) = field.next().await {
-                    let data = match chunk {
-                        Ok(d) => d,
-                        Err(e) => {
-                            error!("Error reading message: {}", e);
-                            return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(
-                                request_id,
-                                error_codes::VALIDATION_ERROR,
-                                &format!("Error reading message: {}", e),
-                            )));
-                        }
-                    };
+            "cover_id" => {
+                // Read the ID of a pool cover
+                let id = match read_text_field(
+                    &mut field,
+                    &field_name,
+                    MAX_TEXT_FIELD_LENGTH,
+                    request_id,
+                )
+                .await
+                {
+                    Ok(value) => value,
+                    Err(response) => return Ok(response),
+                };
+                trace.field(&field_name, id.len());
 
-                    if content.len() + data.len() > MAX_MESSAGE_LENGTH {
-                        return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(
-                            request_id,
-                            error_codes::MESSAGE_TOO_LARGE,
-                            &format!(
-                                "Message exceeds maximum size of {} bytes",
-                                MAX_MESSAGE_LENGTH
+                cover_id = Some(id.trim().to_string()).filter(|id| !id.is_empty());
+            }
+            "overwrite" => {
+                // Read the overwrite flag
+                let flag = match read_text_field(
+                    &mut field,
+                    &field_name,
+                    MAX_TEXT_FIELD_LENGTH,
+                    request_id,
+                )
+                .await
+                {
+                    Ok(value) => value,
+                    Err(response) => return Ok(response),
+                };
+                trace.field(&field_name, flag.len());
+
+                let flag = flag.trim().to_lowercase();
+                options.overwrite = matches!(flag.as_str(), "true" | "1" | "yes" | "on");
+            }
+            "max_change" => {
+                // Read the change budget, as a fraction or a percentage
+                let value = match read_text_field(
+                    &mut field,
+                    &field_name,
+                    MAX_TEXT_FIELD_LENGTH,
+                    request_id,
+                )
+                .await
+                {
+                    Ok(value) => value,
+                    Err(response) => return Ok(response),
+                };
+                trace.field(&field_name, value.len());
+
+                match utils::parse_ratio(&value) {
+                    Ok(ratio) => options.max_change = Some(ratio),
+                    Err(e) => {
+                        let reason = e.to_string();
+                        return Ok(HttpResponse::BadRequest().json(
+                            hide_error_to_response(e, request_id).with_details(
+                                ValidationDetails::field("max_change", &reason, Some(&value)),
                             ),
-                        )));
+                        ));
                     }
-
-                    content.extend_from_slice(&data);
                 }
+            }
+            "intended_channel" => {
+                // Read where the image goes after encoding
+                let value = match read_text_field(
+                    &mut field,
+                    &field_name,
+                    MAX_TEXT_FIELD_LENGTH,
+                    request_id,
+                )
+                .await
+                {
+                    Ok(value) => value,
+                    Err(response) => return Ok(response),
+                };
+                trace.field(&field_name, value.len());
 
-                message = match String::from_utf8(content.clone()) {
-                    Ok(s) => Some(s),
-                    Err(_) => {
-                        // Store as binary if not valid UTF-8
-                        message_file_content = Some(content);
-                        None
+                match value.parse::<IntendedChannel>() {
+                    Ok(channel) => {
+                        options.intended_channel = Some(channel.to_string());
+                        intended_channel = Some(channel);
+                    }
+                    Err(e) => {
+                        return Ok(HttpResponse::BadRequest().json(
+                            ErrorResponse::new(
+                                request_id,
+                                error_codes::VALIDATION_ERROR,
+                                &e.to_string(),
+                            )
+                            .with_details(ValidationDetails::field(
+                                "intended_channel",
+                                "expected print or an image format such as png or jpeg",
+                                Some(&value),
+                            )),
+                        ));
                     }
+                }
+            }
+            "strict" => {
+                // Read the strict flag
+                let flag = match read_text_field(
+                    &mut field,
+                    &field_name,
+                    MAX_TEXT_FIELD_LENGTH,
+                    request_id,
+                )
+                .await
+                {
+                    Ok(value) => value,
+                    Err(response) => return Ok(response),
                 };
+                trace.field(&field_name, flag.len());
+
+                let flag = flag.trim().to_lowercase();
+                options.strict = matches!(flag.as_str(), "true" | "1" | "yes" | "on");
             }
-            "message_file" => {
-                // Fix: Use content_disposition() as Option and then get the filename safely
-                let _filename = content_disposition
-                    .and_then(|cd| cd.get_filename())
-                    .unwrap_or("message.txt")
-                    .to_string();
+            "verify_roundtrip" => {
+                // Read the self-check flag; only an explicit opt-out turns it off
+                let flag = match read_text_field(
+                    &mut field,
+                    &field_name,
+                    MAX_TEXT_FIELD_LENGTH,
+                    request_id,
+                )
+                .await
+                {
+                    Ok(value) => value,
+                    Err(response) => return Ok(response),
+                };
+                trace.field(&field_name, flag.len());
 
-                // Read the file content
-                let mut content = Vec::new();
-                while let Some(chunk) = field.next().await {
-                    let data = match chunk {
-                        Ok(d) => d,
-                        Err(e) => {
-                            error!("Error reading message file: {}", e);
-                            return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(
-                                request_id,
-                                error_codes::VALIDATION_ERROR,
-                                &format!("Error reading message file: {}", e),
-                            )));
-                        }
-                    };
+                let flag = flag.trim().to_lowercase();
+                options.verify_roundtrip = !matches!(flag.as_str(), "false" | "0" | "no" | "off");
+            }
+            "record_dimensions" => {
+                // Read the flag for recording the cover's dimensions in the payload
+                let flag = match read_text_field(
+                    &mut field,
+                    &field_name,
+                    MAX_TEXT_FIELD_LENGTH,
+                    request_id,
+                )
+                .await
+                {
+                    Ok(value) => value,
+                    Err(response) => return Ok(response),
+                };
+                trace.field(&field_name, flag.len());
 
-                    if content.len() + data.len() > MAX_MESSAGE_LENGTH {
-                        return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(
-                            request_id,
-                            error_codes::MESSAGE_TOO_LARGE,
-                            &format!(
-                                "Message exceeds maximum size of {} bytes",
-                                MAX_MESSAGE_LENGTH
-                            ),
-                        )));
-                    }
+                let flag = flag.trim().to_lowercase();
+                options.record_dimensions = matches!(flag.as_str(), "true" | "1" | "yes" | "on");
+            }
+            "compress" => {
+                // Read the flag for deflating the message before embedding it
+                let flag = match read_text_field(
+                    &mut field,
+                    &field_name,
+                    MAX_TEXT_FIELD_LENGTH,
+                    request_id,
+                )
+                .await
+                {
+                    Ok(value) => value,
+                    Err(response) => return Ok(response),
+                };
+                trace.field(&field_name, flag.len());
 
-                    content.extend_from_slice(&data);
-                }
+                let flag = flag.trim().to_lowercase();
+                options.compress = matches!(flag.as_str(), "true" | "1" | "yes" | "on");
+            }
+            "checksum" => {
+                // Read the flag for ending the payload with a CRC-32
+                let flag = match read_text_field(
+                    &mut field,
+                    &field_name,
+                    MAX_TEXT_FIELD_LENGTH,
+                    request_id,
+                )
+                .await
+                {
+                    Ok(value) => value,
+                    Err(response) => return Ok(response),
+                };
+                trace.field(&field_name, flag.len());
 
-                message_file_content = Some(content);
+                let flag = flag.trim().to_lowercase();
+                options.checksum = matches!(flag.as_str(), "true" | "1" | "yes" | "on");
             }
-            "output_format" => {
-                // Read the output format
-                let mut content = Vec::new();
-                while let Some(chunk) = field.next().await {
-                    content.extend_from_slice(&match chunk {
-                        Ok(d) => d,
-                        Err(_) => continue,
-                    });
-                }
+            "embedding_depth" => {
+                // Read how many of each channel's lowest bits the payload takes
+                let value = match read_text_field(
+                    &mut field,
+                    &field_name,
+                    MAX_TEXT_FIELD_LENGTH,
+                    request_id,
+                )
+                .await
+                {
+                    Ok(value) => value,
+                    Err(response) => return Ok(response),
+                };
+                trace.field(&field_name, value.len());
 
-                if let Ok(format) = String::from_utf8(content) {
-                    let format = format.trim().to_lowercase();
-                    if format == "png" || format == "jpeg" || format == "jpg" {
-                        options.output_format = format;
+                match value.parse::<EmbeddingDepth>() {
+                    Ok(depth) => options.embedding_depth = depth,
+                    Err(e) => {
+                        let reason = e.to_string();
+                        return Ok(HttpResponse::BadRequest().json(
+                            hide_error_to_response(e, request_id).with_details(
+                                ValidationDetails::field("embedding_depth", &reason, Some(&value)),
+                            ),
+                        ));
                     }
                 }
             }
-            "jpeg_quality" => {
-                // Read the JPEG quality
-                let mut content = Vec::new();
-                while let Some(chunk) = field.next().await {
-                    content.extend_from_slice(&match chunk {
-                        Ok(d) => d,
-                        Err(_) => continue,
-                    });
+            "auto_extend" => {
+                // Read how to fill the area added to a cover that is too small
+                let value = match read_text_field(
+                    &mut field,
+                    &field_name,
+                    MAX_TEXT_FIELD_LENGTH,
+                    request_id,
+                )
+                .await
+                {
+                    Ok(value) => value,
+                    Err(response) => return Ok(response),
+                };
+                trace.field(&field_name, value.len());
+
+                match value.parse::<FillStyle>() {
+                    Ok(fill) => options.auto_extend = Some(fill),
+                    Err(e) => {
+                        let reason = e.to_string();
+                        return Ok(HttpResponse::BadRequest().json(
+                            hide_error_to_response(e, request_id).with_details(
+                                ValidationDetails::field("auto_extend", &reason, Some(&value)),
+                            ),
+                        ));
+                    }
                 }
+            }
+            "algorithm" => {
+                // Read the embedding algorithm
+                let value = match read_text_field(
+                    &mut field,
+                    &field_name,
+                    MAX_TEXT_FIELD_LENGTH,
+                    request_id,
+                )
+                .await
+                {
+                    Ok(value) => value,
+                    Err(response) => return Ok(response),
+                };
+                trace.field(&field_name, value.len());
 
-                if let Ok(quality_str) = String::from_utf8(content) {
-                    if let Ok(quality) = quality_str.trim().parse::<u8>() {
-                        options.jpeg_quality = quality.min(100);
+                match value.parse::<Algorithm>() {
+                    Ok(algorithm) => options.algorithm = algorithm,
+                    Err(e) => {
+                        let reason = e.to_string();
+                        return Ok(HttpResponse::BadRequest().json(
+                            hide_error_to_response(e, request_id).with_details(
+                                ValidationDetails::field("algorithm", &reason, Some(&value)),
+                            ),
+                        ));
                     }
                 }
             }
+            "variants" => {
+                // Read the JSON array of images to produce from the one cover
+                let value = match read_text_field(
+                    &mut field,
+                    &field_name,
+                    MAX_VARIANTS_FIELD_LENGTH,
+                    request_id,
+                )
+                .await
+                {
+                    Ok(value) => value,
+                    Err(response) => return Ok(response),
+                };
+                trace.field(&field_name, value.len());
+
+                let reason = match serde_json::from_str::<Vec<EncodeVariant>>(&value) {
+                    Ok(list) if list.is_empty() => "expected at least one variant".to_string(),
+                    Ok(list) if list.len() > MAX_ENCODE_VARIANTS => {
+                        format!("at most {} variants are allowed", MAX_ENCODE_VARIANTS)
+                    }
+                    Ok(list) => {
+                        variants = Some(list);
+                        continue;
+                    }
+                    Err(e) => format!(
+                        "expected a JSON array of preset names (fast, stealth, robust) \
+                         or option objects: {}",
+                        e
+                    ),
+                };
+                return Ok(HttpResponse::BadRequest().json(
+                    ErrorResponse::new(
+                        request_id,
+                        error_codes::VALIDATION_ERROR,
+                        "Invalid encode variants",
+                    )
+                    .with_details(ValidationDetails::field(
+                        "variants",
+                        &reason,
+                        Some(&value),
+                    )),
+                ));
+            }
+            "debug" => {
+                // Read whether to trace the request in the response
+                let flag = match read_text_field(
+                    &mut field,
+                    &field_name,
+                    MAX_TEXT_FIELD_LENGTH,
+                    request_id,
+                )
+                .await
+                {
+                    Ok(value) => value,
+                    Err(response) => return Ok(response),
+                };
+                trace.field(&field_name, flag.len());
+
+                debug_requested = flag.trim().eq_ignore_ascii_case("true");
+            }
             _ => {
-                // Skip unknown fields
-                while let Some(_) = field.next().await {}
+                // Skip unknown fields, noting only their size
+                let mut bytes = 0;
+                while let Some(chunk) = field.next().await {
+                    bytes += chunk.map_or(0, |data| data.len());
+                }
+                trace.field(&field_name, bytes);
             }
         }
     }
+    trace.stage("upload");
+    let debug = debug_requested && config.allow_debug_responses;
+    if debug_requested && !debug {
+        warn!("Ignoring debug=true; debug responses are not allowed on this server");
+    }
 
-    // Ensure we have a cover image
-    let cover_image_path = match cover_image_path {
-        Some(path) => path,
-        None => {
-            return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(
+    // Ensure we have a cover image, either uploaded or from the pool
+    if cover_image_path.is_none() && cover_id.is_none() {
+        return Ok(HttpResponse::BadRequest().json(
+            ErrorResponse::new(
                 request_id,
                 error_codes::VALIDATION_ERROR,
                 "Missing cover image",
-            )));
+            )
+            .with_details(ValidationDetails::field(
+                "cover_image",
+                "required unless cover_id is given",
+                None,
+            )),
+        ));
+    }
+    if cover_image_path.is_some() && cover_id.is_some() {
+        return Ok(HttpResponse::BadRequest().json(
+            ErrorResponse::new(
+                request_id,
+                error_codes::VALIDATION_ERROR,
+                "Provide either cover_image or cover_id, not both",
+            )
+            .with_details(ValidationDetails::field(
+                "cover_id",
+                "not allowed together with cover_image",
+                cover_id.as_deref(),
+            )),
+        ));
+    }
+
+    // Ensure we have a message (either from the form field or a file)
+    let MessageField {
+        content: message_content,
+        sha256: message_sha256,
+    } = match message.or(message_file_content) {
+        Some(field) => field,
+        None => {
+            return Ok(HttpResponse::BadRequest().json(
+                ErrorResponse::new(
+                    request_id,
+                    error_codes::VALIDATION_ERROR,
+                    "Missing message content",
+                )
+                .with_details(ValidationDetails::field(
+                    "message",
+                    "required unless message_file is given",
+                    None,
+                )),
+            ));
         }
     };
 
-    // Ensure we have a message (either text or file)
-    let message_content = match (&message, &message_file_content) {
-        (Some(text), _) => text.as_bytes().to_vec(),
-        (_, Some(binary)) => binary.clone(),
-        _ => {
-            return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(
+    if variants.is_some() && options.algorithm != Algorithm::Bltm {
+        return Ok(HttpResponse::BadRequest().json(
+            ErrorResponse::new(
                 request_id,
                 error_codes::VALIDATION_ERROR,
-                "Missing message content",
-            )));
-        }
+                "Encode variants are only available with the bltm3x3 algorithm",
+            )
+            .with_details(ValidationDetails::field(
+                "variants",
+                "not allowed with this algorithm",
+                Some(options.algorithm.name()),
+            )),
+        ));
+    }
+
+    // The bytes are embedded as-is; the text view is only used for logging
+    let message_text = std::str::from_utf8(&message_content).ok();
+    match message_text {
+        Some(text) => info!(
+            "Encoding a {} byte text message ({} characters)",
+            message_content.len(),
+            text.chars().count()
+        ),
+        None => info!("Encoding a {} byte binary message", message_content.len()),
+    }
+
+    // Retries of a request already seen get its response instead of a new
+    // image; responses listing variants are not recorded, so aren't replayed
+    let key = match variants {
+        Some(_) => None,
+        None => idempotency_key(
+            req,
+            urls,
+            cover_image_path.as_deref(),
+            cover_id.as_deref(),
+            &message_content,
+            &options,
+        ),
+    };
+    let pending = match key {
+        Some((key, digest)) => match idempotency.claim(key, digest).await {
+            Claim::Replay(response) => {
+                info!("Replaying encode response for image {}", response.image_id);
+                return Ok(HttpResponse::Ok()
+                    .insert_header((REPLAY_HEADER, "true"))
+                    .json(response));
+            }
+            Claim::Conflict => {
+                return Ok(HttpResponse::UnprocessableEntity().json(ErrorResponse::new(
+                    request_id,
+                    error_codes::IDEMPOTENCY_KEY_REUSED,
+                    "The Idempotency-Key was already used for a request with different contents",
+                )));
+            }
+            Claim::Proceed(pending) => Some(pending),
+        },
+        None => None,
+    };
+
+    let cover_image_path = match (cover_image_path, cover_id) {
+        (Some(path), _) => path,
+        (None, Some(id)) => match pool_cover_path(covers, &id, message_content.len(), request_id) {
+            Ok(path) => path,
+            Err(err) if err.error_code == error_codes::NOT_FOUND => {
+                return Ok(HttpResponse::NotFound().json(err));
+            }
+            Err(err) => return Ok(HttpResponse::BadRequest().json(err)),
+        },
+        (None, None) => unreachable!("checked above"),
     };
 
     // Load the cover image
@@ -369,67 +1080,576 @@ pub async fn process_encode_form(
         }
     };
 
+    // Fingerprint the cover as received, so clients can spot a cover resized on the way
+    let cover_sha256 = fs::read(&cover_image_path)
+        .map(|data| manifest::sha256_hex(&data))
+        .ok();
+    let cover_dimensions = ImageDimensions::from(cover_image.dimensions());
+    trace.stage("load");
+
+    // Don't spend time encoding if the result can't be stored
+    if let Err(e) = img::prepare_output_dir(upload_dir, false) {
+        return Ok(save_error_response(
+            e,
+            storage_degraded,
+            request_id,
+            "Upload directory is unavailable",
+        ));
+    }
+
+    let target = EncodeTarget {
+        req,
+        urls,
+        upload_dir,
+        storage_degraded,
+        request_id,
+    };
+    match options.algorithm {
+        Algorithm::Bltm => {}
+        #[cfg(feature = "jpeg")]
+        Algorithm::JpegDct => {
+            let (image_id, metadata, timing) = match encode_jpeg_dct(
+                &target,
+                &cover_image_path,
+                &message_content,
+                &message_sha256,
+            ) {
+                Ok(stored) => stored,
+                Err(response) => return Ok(*response),
+            };
+            // The JPEG path writes the file as it encodes, so this covers saving too
+            trace.stage("encode");
+            let mut response = EncodeResponse {
+                request_id,
+                status: "success".to_string(),
+                image_id,
+                download_url: urls.image_url(req, image_id),
+                metadata,
+                cover_sha256,
+                cover_dimensions: Some(cover_dimensions),
+                processing_ms: Some(timing.processing_ms()),
+                pixels_per_second: Some(timing.pixels_per_second()),
+                warnings: Vec::new(),
+                debug: None,
+            };
+            if let Some(pending) = pending {
+                pending.complete(&response);
+            }
+            response.debug = trace.into_debug(debug);
+            return Ok(with_timing(HttpResponse::Ok().json(response), timing));
+        }
+    }
+    if let Some(variants) = variants {
+        let results = match encode_variants(
+            &target,
+            &cover_image,
+            &message_content,
+            &message_sha256,
+            &options,
+            intended_channel,
+            &variants,
+        ) {
+            Ok(results) => results,
+            Err(response) => return Ok(*response),
+        };
+        trace.stage("encode");
+        return Ok(HttpResponse::Ok().json(EncodeVariantsResponse {
+            request_id,
+            status: "success".to_string(),
+            variants: results,
+            cover_sha256,
+            cover_dimensions: Some(cover_dimensions),
+            debug: trace.into_debug(debug),
+        }));
+    }
+
     // Create the encoder
-    let encoder = create_encoder();
+    let config = EncoderConfig {
+        overwrite_existing: options.overwrite,
+        max_changed_pixels: options.max_change,
+        verify_roundtrip: options.verify_roundtrip,
+        record_dimensions: options.record_dimensions,
+        auto_extend: options.auto_extend,
+        compress: options.compress,
+        checksum: options.checksum,
+        embedding_depth: options.embedding_depth,
+        ..Default::default()
+    };
+
+    let warnings: Vec<_> = intended_channel
+        .and_then(|channel| channel.warning(&config))
+        .into_iter()
+        .collect();
+    if options.strict && !warnings.is_empty() {
+        let message = warnings
+            .iter()
+            .map(|warning| warning.message.as_str())
+            .collect::<Vec<_>>()
+            .join("; ");
+        return Ok(HttpResponse::BadRequest().json(
+            ErrorResponse::new(request_id, error_codes::WARNINGS_IN_STRICT_MODE, &message)
+                .with_details(WarningDetails { warnings }),
+        ));
+    }
+
+    let encoder = Encoder::with_config(config);
 
-    // Check if the message will fit
+    // Check if the message will fit, unless the cover may grow to fit it or
+    // compression may shrink it enough
     let max_message_size = encoder.max_message_size(&cover_image);
-    if message_content.len() > max_message_size {
-        return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(
-            request_id,
-            error_codes::MESSAGE_TOO_LARGE,
-            &format!(
-                "Message is too large ({} bytes) for this image (max {} bytes)",
-                message_content.len(),
-                max_message_size
-            ),
-        )));
+    if options.auto_extend.is_none()
+        && !options.compress
+        && message_content.len() > max_message_size
+    {
+        return Ok(HttpResponse::BadRequest().json(
+            ErrorResponse::new(
+                request_id,
+                error_codes::MESSAGE_TOO_LARGE,
+                &format!(
+                    "Message is too large ({} bytes) for this image (max {} bytes)",
+                    message_content.len(),
+                    max_message_size
+                ),
+            )
+            .with_details(MessageTooLargeDetails {
+                message_bytes: message_content.len(),
+                capacity_bytes: max_message_size,
+            }),
+        ));
     }
 
     // Encode the message
-    let stego_image = match encoder.encode(cover_image, &message_content) {
-        Ok(img) => img,
+    let (stego_image, stats) = match encoder.encode_with_stats(cover_image, &message_content) {
+        Ok(result) => result,
         Err(e) => {
             error!("Failed to encode message: {:?}", e);
-            return Ok(HttpResponse::BadRequest().json(hide_error_to_response(e, request_id)));
+            let mut builder = match e {
+                HideError::SelfCheckFailed { .. } => HttpResponse::InternalServerError(),
+                _ => HttpResponse::BadRequest(),
+            };
+            return Ok(builder.json(hide_error_to_response(e, request_id)));
         }
     };
+    trace.stage("encode");
 
-    // Generate a unique ID for the stego image
-    let image_id = Uuid::new_v4();
+    let (image_id, metadata) =
+        match store_encoded_image(&target, &encoder, &stego_image, &stats, &message_sha256) {
+            Ok(stored) => stored,
+            Err(response) => return Ok(*response),
+        };
+    trace.stage("save");
 
-    // Save the stego image
-    let stego_image_path = upload_dir.join(image_id.to_string() + ".png");
-    if let Err(e) = stego_image.save(&stego_image_path) {
-        error!("Failed to save stego image: {}", e);
-        return Ok(HttpResponse::InternalServerError().json(ErrorResponse::new(
-            request_id,
-            error_codes::INTERNAL_ERROR,
-            "Failed to save encoded image",
-        )));
-    }
+    // Report what the encode noticed along with the channel advisories
+    let mut warnings = warnings;
+    warnings.extend(stats.warnings.iter().map(Warning::from));
 
-    // Get the file size
-    let size_bytes = match fs::metadata(&stego_image_path) {
+    // Create the response
+    let mut response = EncodeResponse {
+        request_id,
+        status: "success".to_string(),
+        image_id,
+        download_url: urls.image_url(req, image_id),
+        metadata,
+        cover_sha256,
+        cover_dimensions: Some(cover_dimensions),
+        processing_ms: Some(stats.timing.processing_ms()),
+        pixels_per_second: Some(stats.timing.pixels_per_second()),
+        warnings,
+        debug: None,
+    };
+    if let Some(pending) = pending {
+        pending.complete(&response);
+    }
+    // Replays of this response don't carry its trace
+    response.debug = trace.into_debug(debug);
+
+    Ok(with_timing(HttpResponse::Ok().json(response), stats.timing))
+}
+
+/// Where an encode request's images are stored and linked from
+struct EncodeTarget<'a> {
+    /// The request, for building download URLs
+    req: &'a HttpRequest,
+
+    /// Builder of download URLs
+    urls: &'a UrlBuilder,
+
+    /// Directory the images are saved to
+    upload_dir: &'a Path,
+
+    /// Flag raised while storage is failing
+    storage_degraded: &'a AtomicBool,
+
+    /// ID of the request, for error responses
+    request_id: Uuid,
+}
+
+/// Save an encoded image under a new ID and record its hash for checking downloads
+///
+/// # Arguments
+/// * `target` - Where to store the image
+/// * `encoder` - The encoder that made the image, for the capacity in the metadata
+/// * `stego_image` - The encoded image
+/// * `stats` - Statistics of the encode, for the message's stored size
+/// * `message_sha256` - Hex-encoded SHA-256 of the message, for the metadata
+///
+/// # Returns
+/// * The image's ID and metadata, or the error response to send
+fn store_encoded_image(
+    target: &EncodeTarget,
+    encoder: &Encoder,
+    stego_image: &StegoImage,
+    stats: &EncodeStats,
+    message_sha256: &str,
+) -> Result<(Uuid, ImageMetadata), Box<HttpResponse>> {
+    let image_id = Uuid::new_v4();
+    let stego_image_path = target.upload_dir.join(image_id.to_string() + ".png");
+    let content_sha256 = stego_image
+        .save(&stego_image_path)
+        .and_then(|()| store::record_content_hash(&stego_image_path))
+        .map_err(|e| {
+            Box::new(save_error_response(
+                e,
+                target.storage_degraded,
+                target.request_id,
+                "Failed to save encoded image",
+            ))
+        })?;
+    mark_storage_healthy(target.storage_degraded);
+
+    // Get the file size
+    let size_bytes = match fs::metadata(&stego_image_path) {
         Ok(metadata) => metadata.len() as usize,
         Err(_) => 0,
     };
 
     // Extract metadata
-    let mut metadata = extract_image_metadata(&stego_image);
+    let mut metadata = extract_image_metadata(stego_image, encoder);
     metadata.size_bytes = size_bytes;
-    metadata.embedded_message_bytes = Some(message_content.len());
+    metadata.embedded_message_bytes = Some(stats.message_bytes);
+    metadata.stored_message_bytes = Some(stats.stored_message_bytes);
+    metadata.message_sha256 = Some(message_sha256.to_string());
+    metadata.content_sha256 = Some(content_sha256);
+    Ok((image_id, metadata))
+}
 
-    // Create the response
-    let response = EncodeResponse {
-        request_id,
-        status: "success".to_string(),
-        image_id,
-        download_url: format!("/api/images/{}", image_id),
-        metadata,
+/// Hide a message in a JPEG cover's DCT coefficients and store the JPEG under a new ID
+///
+/// # Returns
+/// * The image's ID, metadata and encode timing, or the error response to send
+#[cfg(feature = "jpeg")]
+fn encode_jpeg_dct(
+    target: &EncodeTarget,
+    cover_image_path: &Path,
+    message: &[u8],
+    message_sha256: &str,
+) -> Result<(Uuid, ImageMetadata, Timing), Box<HttpResponse>> {
+    let request_id = target.request_id;
+    let bad_request = |e: HideError| {
+        Box::new(HttpResponse::BadRequest().json(hide_error_to_response(e, request_id)))
     };
 
-    Ok(HttpResponse::Ok().json(response))
+    let cover = fs::read(cover_image_path).map_err(|e| {
+        Box::new(storage_error_response(
+            &e,
+            target.storage_degraded,
+            request_id,
+            "Failed to read cover image",
+        ))
+    })?;
+    let start = std::time::Instant::now();
+    let stego = JpegStego::new();
+    let capacity = stego.capacity(&cover).map_err(bad_request)?;
+    if message.len() > capacity.max_message_bytes {
+        return Err(Box::new(
+            HttpResponse::BadRequest().json(
+                ErrorResponse::new(
+                    request_id,
+                    error_codes::MESSAGE_TOO_LARGE,
+                    &format!(
+                        "Message is too large ({} bytes) for this JPEG (max {} bytes)",
+                        message.len(),
+                        capacity.max_message_bytes
+                    ),
+                )
+                .with_details(MessageTooLargeDetails {
+                    message_bytes: message.len(),
+                    capacity_bytes: capacity.max_message_bytes,
+                }),
+            ),
+        ));
+    }
+    let encoded = stego.encode(&cover, message).map_err(bad_request)?;
+    let timing = Timing::since(
+        start,
+        u64::from(capacity.width) * u64::from(capacity.height),
+    );
+
+    let image_id = Uuid::new_v4();
+    let stego_image_path = target.upload_dir.join(format!("{}.jpg", image_id));
+    let content_sha256 = fs::write(&stego_image_path, &encoded)
+        .map_err(HideError::from)
+        .and_then(|()| store::record_content_hash(&stego_image_path))
+        .map_err(|e| {
+            Box::new(save_error_response(
+                e,
+                target.storage_degraded,
+                request_id,
+                "Failed to save encoded image",
+            ))
+        })?;
+    mark_storage_healthy(target.storage_degraded);
+
+    let metadata = ImageMetadata {
+        width: capacity.width,
+        height: capacity.height,
+        format: "jpeg".to_string(),
+        size_bytes: encoded.len(),
+        max_message_bytes: capacity.max_message_bytes,
+        embedded_message_bytes: Some(message.len()),
+        stored_message_bytes: Some(message.len()),
+        message_sha256: Some(message_sha256.to_string()),
+        content_sha256: Some(content_sha256),
+    };
+    Ok((image_id, metadata, timing))
+}
+
+/// Encode one cover into a stored image per requested variant
+///
+/// Every variant is checked before any is encoded. If an encode or save
+/// fails, the images already stored for the request are removed.
+///
+/// # Returns
+/// * One result per variant, in order, or the error response to send
+fn encode_variants(
+    target: &EncodeTarget,
+    cover_image: &StegoImage,
+    message: &[u8],
+    message_sha256: &str,
+    options: &EncodeOptions,
+    intended_channel: Option<IntendedChannel>,
+    variants: &[EncodeVariant],
+) -> Result<Vec<VariantResult>, Box<HttpResponse>> {
+    let request_id = target.request_id;
+    let (width, height) = cover_image.dimensions();
+    let work = width as u64 * height as u64 * variants.len() as u64;
+    if work > MAX_VARIANT_PIXELS {
+        return Err(Box::new(
+            HttpResponse::BadRequest().json(
+                ErrorResponse::new(
+                    request_id,
+                    error_codes::PIXEL_LIMIT_EXCEEDED,
+                    &format!(
+                        "Encoding {} variants of a {}x{} cover exceeds the limit of {} pixels",
+                        variants.len(),
+                        width,
+                        height,
+                        MAX_VARIANT_PIXELS
+                    ),
+                )
+                .with_details(LimitDetails {
+                    required: work,
+                    limit: MAX_VARIANT_PIXELS,
+                }),
+            ),
+        ));
+    }
+
+    let mut planned = Vec::with_capacity(variants.len());
+    for (index, variant) in variants.iter().enumerate() {
+        let config = variant.encoder_config(options);
+        let warnings: Vec<_> = intended_channel
+            .and_then(|channel| channel.warning(&config))
+            .into_iter()
+            .collect();
+        if options.strict && !warnings.is_empty() {
+            let message = warnings
+                .iter()
+                .map(|warning| warning.message.as_str())
+                .collect::<Vec<_>>()
+                .join("; ");
+            return Err(Box::new(
+                HttpResponse::BadRequest().json(
+                    ErrorResponse::new(
+                        request_id,
+                        error_codes::WARNINGS_IN_STRICT_MODE,
+                        &format!("Variant {}: {}", index, message),
+                    )
+                    .with_details(WarningDetails { warnings }),
+                ),
+            ));
+        }
+
+        let capacity = encoder::capacity_breakdown(cover_image, &config);
+        if config.auto_extend.is_none()
+            && !config.compress
+            && message.len() > capacity.max_message_bytes
+        {
+            return Err(Box::new(HttpResponse::BadRequest().json(
+                ErrorResponse::new(
+                    request_id,
+                    error_codes::MESSAGE_TOO_LARGE,
+                    &format!(
+                        "Message is too large ({} bytes) for variant {} of this image (max {} bytes)",
+                        message.len(),
+                        index,
+                        capacity.max_message_bytes
+                    ),
+                )
+                .with_details(MessageTooLargeDetails {
+                    message_bytes: message.len(),
+                    capacity_bytes: capacity.max_message_bytes,
+                }),
+            )));
+        }
+        planned.push((variant, config, capacity, warnings));
+    }
+
+    let mut results: Vec<VariantResult> = Vec::with_capacity(planned.len());
+    for (variant, config, capacity, warnings) in planned {
+        let preset = match variant {
+            EncodeVariant::Preset(preset) => Some(*preset),
+            EncodeVariant::Options(options) => options.preset,
+        };
+        let echo = VariantOptions::resolved(preset, &config);
+
+        let encoder = Encoder::with_config(config);
+        let stored = encoder
+            .encode_with_stats(cover_image.clone(), message)
+            .map_err(|e| {
+                error!("Failed to encode variant: {:?}", e);
+                let mut builder = match e {
+                    HideError::SelfCheckFailed { .. } => HttpResponse::InternalServerError(),
+                    _ => HttpResponse::BadRequest(),
+                };
+                Box::new(builder.json(hide_error_to_response(e, request_id)))
+            })
+            .and_then(|(stego_image, stats)| {
+                store_encoded_image(target, &encoder, &stego_image, &stats, message_sha256)
+                    .map(|(image_id, metadata)| (image_id, metadata, stats))
+            });
+        let (image_id, metadata, stats) = match stored {
+            Ok(stored) => stored,
+            Err(response) => {
+                for result in &results {
+                    let path = target.upload_dir.join(result.image_id.to_string() + ".png");
+                    let hash_path = path.with_extension(store::CONTENT_HASH_EXTENSION);
+                    if let Err(e) = fs::remove_file(&path).and_then(|()| fs::remove_file(hash_path))
+                    {
+                        warn!("Failed to remove image {}: {}", result.image_id, e);
+                    }
+                }
+                return Err(response);
+            }
+        };
+
+        results.push(VariantResult {
+            image_id,
+            download_url: target.urls.image_url(target.req, image_id),
+            options: echo,
+            metadata,
+            capacity,
+            processing_ms: Some(stats.timing.processing_ms()),
+            warnings: [warnings, stats.warnings.iter().map(Warning::from).collect()].concat(),
+        });
+    }
+
+    Ok(results)
+}
+
+/// Key identifying retries of an encode request, and a digest of its contents
+///
+/// The client's `Idempotency-Key` header is used if present, otherwise the
+/// digest of the cover, message and options. Either way the key is scoped
+/// to the client's IP address, as resolved through trusted proxies.
+///
+/// # Returns
+/// * The key and digest, or `None` if the uploaded cover can't be read back to hash it
+fn idempotency_key(
+    req: &HttpRequest,
+    urls: &UrlBuilder,
+    cover_image_path: Option<&Path>,
+    cover_id: Option<&str>,
+    message: &[u8],
+    options: &EncodeOptions,
+) -> Option<(IdempotencyKey, RequestDigest)> {
+    let cover = match (cover_image_path, cover_id) {
+        (Some(path), _) => fs::read(path).ok()?,
+        (None, Some(id)) => [b"cover_id:", id.as_bytes()].concat(),
+        (None, None) => return None,
+    };
+    let options = serde_json::to_vec(options).ok()?;
+    let digest = RequestDigest::new(&[&cover, message, &options]);
+
+    // Retries may come over a new connection, so only the address counts
+    let client = urls.client_ip(req);
+    let header_key = req
+        .headers()
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|value| !value.is_empty());
+    let key = match header_key {
+        Some(value) => IdempotencyKey::from_header(&client, value),
+        None => IdempotencyKey::from_digest(&client, &digest),
+    };
+    Some((key, digest))
+}
+
+/// Resolve a `cover_id` form value to a cover in the pool
+///
+/// # Returns
+/// * The path of the cover, or the error to report; unknown IDs use the `not_found` code
+fn pool_cover_path(
+    covers: &CoverPool,
+    cover_id: &str,
+    message_length: usize,
+    request_id: Uuid,
+) -> Result<PathBuf, ErrorResponse> {
+    if !covers.is_enabled() {
+        return Err(ErrorResponse::new(
+            request_id,
+            error_codes::VALIDATION_ERROR,
+            "This server has no cover pool; upload a cover_image instead",
+        )
+        .with_details(ValidationDetails::field(
+            "cover_id",
+            "this server has no cover pool",
+            Some(cover_id),
+        )));
+    }
+
+    if cover_id == AUTO_COVER_ID {
+        return covers
+            .smallest_fitting(message_length)
+            .map(|cover| cover.path)
+            .ok_or_else(|| {
+                let largest = covers
+                    .list()
+                    .last()
+                    .map_or(0, |cover| cover.metadata.max_message_bytes);
+                ErrorResponse::new(
+                    request_id,
+                    error_codes::MESSAGE_TOO_LARGE,
+                    &format!(
+                        "No cover in the pool can hold a message of {} bytes",
+                        message_length
+                    ),
+                )
+                .with_details(MessageTooLargeDetails {
+                    message_bytes: message_length,
+                    capacity_bytes: largest,
+                })
+            });
+    }
+
+    covers.get(cover_id).map(|cover| cover.path).ok_or_else(|| {
+        ErrorResponse::new(
+            request_id,
+            error_codes::NOT_FOUND,
+            &format!("Cover not found: {}", cover_id),
+        )
+    })
 }
 
 /// Application state shared across requests
@@ -437,6 +1657,63 @@ pub async fn process_encode_form(
 pub struct AppState {
     /// Server configuration
     pub config: ServerConfig,
+    /// Cache of recently decoded results
+    pub decode_cache: Arc<DecodeCache>,
+    /// Set while writes to the upload directory are failing
+    pub storage_degraded: Arc<AtomicBool>,
+    /// Builds the URLs handed out to clients
+    pub url_builder: UrlBuilder,
+    /// Covers offered to clients that don't upload one
+    pub cover_pool: Arc<CoverPool>,
+    /// Metadata of the encoded images, for listing them
+    pub image_store: Arc<dyn MetadataStore>,
+    /// Recent encode responses, replayed to retried requests
+    pub idempotency: Arc<IdempotencyStore>,
+    /// Disk usage of the per-request upload directories
+    pub spool: Arc<SpoolManager>,
+}
+
+impl AppState {
+    /// Create the application state for a configuration
+    pub fn new(config: ServerConfig) -> Self {
+        let decode_cache = Arc::new(DecodeCache::new(
+            config.decode_cache_entries,
+            config.decode_cache_bytes,
+        ));
+        let mut url_builder = UrlBuilder::new(config.public_base_url.clone(), config.trust_proxy);
+        if let Some(secret) = config
+            .url_signing_secret
+            .as_deref()
+            .filter(|s| !s.is_empty())
+        {
+            url_builder = url_builder.with_signer(UrlSigner::new(
+                secret,
+                Duration::from_secs(config.signed_url_ttl_secs),
+            ));
+        }
+        let cover_pool = Arc::new(CoverPool::new(
+            config.cover_pool_dir.as_ref().map(PathBuf::from),
+        ));
+        let image_store = Arc::new(FsMetadataStore::new(&config.upload_dir));
+        let idempotency = Arc::new(IdempotencyStore::new(Duration::from_secs(
+            config.idempotency_window_secs,
+        )));
+        let spool = Arc::new(
+            SpoolManager::new(&config.upload_dir, config.max_spool_bytes)
+                .with_ttl(Duration::from_secs(config.decoded_message_ttl_secs)),
+        );
+
+        Self {
+            config,
+            decode_cache,
+            storage_degraded: Arc::new(AtomicBool::new(false)),
+            url_builder,
+            cover_pool,
+            image_store,
+            idempotency,
+            spool,
+        }
+    }
 }
 
 /// Server configuration
@@ -448,6 +1725,66 @@ pub struct ServerConfig {
     pub port: u16,
     /// Temporary directory for file uploads
     pub upload_dir: String,
+    /// Whether the deprecated unversioned `/api/...` routes are served
+    pub enable_legacy_routes: bool,
+    /// Maximum number of cached decode results (0 disables the cache)
+    pub decode_cache_entries: usize,
+    /// Maximum total size of cached decode results in bytes
+    pub decode_cache_bytes: usize,
+    /// External base URL used for download links (relative links if unset)
+    pub public_base_url: Option<String>,
+    /// Whether `X-Forwarded-*` and `Host` headers from a reverse proxy are honored
+    pub trust_proxy: bool,
+    /// Maximum number of message bytes a decode request may produce
+    pub decode_max_output_bytes: usize,
+    /// Maximum number of pixels a decode request may read
+    pub decode_max_pixels: u64,
+    /// Maximum time a decode request may spend decoding, in milliseconds
+    pub decode_deadline_ms: u64,
+    /// Directory of covers offered to clients (pool disabled if unset)
+    pub cover_pool_dir: Option<String>,
+    /// How often the cover pool directory is rescanned, in seconds
+    pub cover_pool_refresh_secs: u64,
+    /// Largest decoded message returned inline; larger ones are stored for download
+    pub max_inline_decode_bytes: usize,
+    /// How long an encode response is replayed to retries, in seconds (0 disables)
+    pub idempotency_window_secs: u64,
+    /// Most bytes the per-request upload directories may hold at once (0 for no limit)
+    pub max_spool_bytes: u64,
+    /// How long decoded messages stored for download are kept, in seconds (0 until swept for space)
+    pub decoded_message_ttl_secs: u64,
+    /// Secret image download URLs are signed with (unsigned URLs if unset)
+    pub url_signing_secret: Option<String>,
+    /// How long signed download URLs stay valid, in seconds
+    pub signed_url_ttl_secs: u64,
+    /// Whether encode requests may ask for a trace of their fields and timings with `debug=true`
+    pub allow_debug_responses: bool,
+}
+
+impl ServerConfig {
+    /// Capabilities of this deployment, including its request limits
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            limits: Some(Limits {
+                max_message_bytes: MAX_MESSAGE_LENGTH,
+                max_image_bytes: MAX_IMAGE_SIZE,
+                decode_max_output_bytes: self.decode_max_output_bytes,
+                decode_max_pixels: self.decode_max_pixels,
+                decode_deadline_ms: self.decode_deadline_ms,
+                max_inline_decode_bytes: self.max_inline_decode_bytes,
+            }),
+            ..capabilities::capabilities()
+        }
+    }
+
+    /// Limits applied when decoding uploaded images
+    pub fn decode_limits(&self) -> UntrustedLimits {
+        UntrustedLimits {
+            max_output_bytes: self.decode_max_output_bytes,
+            max_pixels: self.decode_max_pixels,
+            deadline: Duration::from_millis(self.decode_deadline_ms),
+        }
+    }
 }
 
 impl Default for ServerConfig {
@@ -456,6 +1793,23 @@ impl Default for ServerConfig {
             host: "127.0.0.1".to_string(),
             port: 8080,
             upload_dir: "./tmp".to_string(),
+            enable_legacy_routes: true,
+            decode_cache_entries: 64,
+            decode_cache_bytes: 16 * 1024 * 1024,
+            public_base_url: None,
+            trust_proxy: false,
+            decode_max_output_bytes: MAX_MESSAGE_LENGTH,
+            decode_max_pixels: 25_000_000,
+            decode_deadline_ms: 10_000,
+            cover_pool_dir: None,
+            cover_pool_refresh_secs: 300,
+            max_inline_decode_bytes: 256 * 1024,
+            idempotency_window_secs: 60,
+            max_spool_bytes: 0,
+            decoded_message_ttl_secs: 3600,
+            url_signing_secret: None,
+            signed_url_ttl_secs: 3600,
+            allow_debug_responses: false,
         }
     }
 }
@@ -468,9 +1822,18 @@ pub struct HealthResponse {
 }
 
 /// Serve an encoded image file
+///
+/// # Arguments
+/// * `image_id` - ID of the stored image
+/// * `upload_dir` - Directory the images are stored in
+/// * `signer` - Checks the download's signature, if downloads must be signed
+/// * `query` - The signature parameters of the request
 pub async fn serve_encoded_image(
     image_id: String,
     upload_dir: &Path,
+    signer: Option<&UrlSigner>,
+    query: &SignedQuery,
+    disposition: DispositionKind,
 ) -> Result<HttpResponse, Error> {
     // Validate the image ID format (basic security check)
     if !image_id
@@ -480,13 +1843,20 @@ pub async fn serve_encoded_image(
         return Ok(HttpResponse::BadRequest().body("Invalid image ID format"));
     }
 
-    // Construct the image path
-    let image_path = upload_dir.join(format!("{}.png", image_id));
+    // Checked before the file is looked up, so unsigned requests can't probe for IDs
+    if let Some(Err(err)) = signer.map(|signer| signer.verify(&image_id, query)) {
+        return Ok(signature_error_response(err));
+    }
 
-    // Check if the file exists
-    if !image_path.exists() {
+    // Find the image; only the experimental jpeg-dct algorithm stores JPEGs
+    let file_name = ["png", "jpg"]
+        .iter()
+        .map(|ext| format!("{}.{}", image_id, ext))
+        .find(|name| upload_dir.join(name).exists());
+    let Some(file_name) = file_name else {
         return Ok(HttpResponse::NotFound().body("Image not found"));
-    }
+    };
+    let image_path = upload_dir.join(&file_name);
 
     // Read the file
     let file_data = match std::fs::read(&image_path) {
@@ -497,30 +1867,343 @@ pub async fn serve_encoded_image(
         }
     };
 
+    // Refuse to serve an image that no longer matches the hash recorded when
+    // it was stored; images stored before hashes were recorded have none
+    let content_sha256 = match store::recorded_content_hash(&image_path) {
+        Ok(hash) => hash,
+        Err(e) => {
+            error!("Failed to read content hash of image {}: {}", image_id, e);
+            return Ok(HttpResponse::InternalServerError().body("Failed to read image file"));
+        }
+    };
+    if let Some(expected) = &content_sha256 {
+        let actual = manifest::sha256_hex(&file_data);
+        if &actual != expected {
+            error!(
+                "Stored image {} is corrupt: SHA-256 is {}, expected {}",
+                image_id, actual, expected
+            );
+            return Ok(
+                HttpResponse::InternalServerError().body("Stored image failed its integrity check")
+            );
+        }
+    }
+
     // Determine content type based on file extension
     let content_type = from_path(&image_path).first_or_octet_stream().to_string();
 
     // Return the image with appropriate headers
-    Ok(HttpResponse::Ok()
-        .content_type(content_type)
+    let mut response = HttpResponse::Ok();
+    response.content_type(content_type).append_header((
+        header::CONTENT_DISPOSITION,
+        content_disposition(&file_name, disposition),
+    ));
+    if let Some(hash) = content_sha256 {
+        response
+            .append_header((header::ETAG, format!("\"{}\"", hash)))
+            .append_header((CONTENT_SHA256_HEADER, hash));
+    }
+    Ok(response.body(file_data))
+}
+
+/// List stored images one page at a time
+///
+/// # Arguments
+/// * `req` - The request, for building download URLs
+/// * `query` - The `cursor` and `limit` query parameters
+/// * `store` - Metadata of the stored images
+/// * `urls` - Builds the download URLs
+pub fn list_stored_images(
+    req: &HttpRequest,
+    query: &ListImagesQuery,
+    store: &dyn MetadataStore,
+    urls: &UrlBuilder,
+) -> HttpResponse {
+    let request_id = Uuid::new_v4();
+    let invalid = |field: &str, reason: &str, value: &str| {
+        HttpResponse::BadRequest().json(
+            ErrorResponse::new(
+                request_id,
+                error_codes::VALIDATION_ERROR,
+                &format!("Invalid {}", field),
+            )
+            .with_details(ValidationDetails::field(field, reason, Some(value))),
+        )
+    };
+
+    let limit = match query.limit.as_deref().map(str::parse::<usize>) {
+        None => DEFAULT_PAGE_LIMIT,
+        Some(Ok(limit)) if limit > 0 => limit,
+        Some(_) => {
+            let value = query.limit.as_deref().unwrap_or_default();
+            return invalid("limit", "must be a positive integer", value);
+        }
+    };
+
+    let cursor = match query.cursor.as_deref().map(Cursor::decode) {
+        None => None,
+        Some(Ok(cursor)) => Some(cursor),
+        Some(Err(_)) => {
+            let value = query.cursor.as_deref().unwrap_or_default();
+            return invalid("cursor", "not a cursor returned by this endpoint", value);
+        }
+    };
+
+    let page = match store.page(cursor.as_ref(), limit) {
+        Ok(page) => page,
+        Err(e) => {
+            error!("Failed to list stored images: {}", e);
+            return HttpResponse::InternalServerError().json(ErrorResponse::new(
+                request_id,
+                error_codes::INTERNAL_ERROR,
+                "Failed to list stored images",
+            ));
+        }
+    };
+
+    HttpResponse::Ok().json(ImagesResponse {
+        images: page
+            .records
+            .into_iter()
+            .map(|record| ImageListEntry {
+                image_id: record.image_id,
+                created_at: record.created_at,
+                download_url: urls.image_url(req, record.image_id),
+            })
+            .collect(),
+        next_cursor: page.next_cursor.map(|cursor| cursor.encode()),
+        has_more: page.has_more,
+        total_count: page.total_count,
+    })
+}
+
+/// Build the response for a download refused for its signature
+fn signature_error_response(err: SignatureError) -> HttpResponse {
+    let error_code = match err {
+        SignatureError::Missing | SignatureError::Invalid => error_codes::INVALID_SIGNATURE,
+        SignatureError::Expired => error_codes::LINK_EXPIRED,
+    };
+    HttpResponse::Forbidden().json(ErrorResponse::new(
+        Uuid::new_v4(),
+        error_code,
+        &err.to_string(),
+    ))
+}
+
+/// Serve a decoded message stored because it was too large to inline
+///
+/// Messages are kept in the spool, so expired or swept ones are not found.
+///
+/// # Arguments
+/// * `message_id` - ID of the stored message
+/// * `spool` - The spool the message is kept in
+/// * `signer` - Checks the download's signature, if downloads must be signed
+/// * `query` - The signature parameters of the request
+pub async fn serve_decoded_message(
+    message_id: String,
+    spool: &SpoolManager,
+    signer: Option<&UrlSigner>,
+    query: &SignedQuery,
+    disposition: DispositionKind,
+) -> Result<HttpResponse, Error> {
+    // Only IDs handed out by the decode endpoint are valid
+    let Ok(message_id) = Uuid::parse_str(&message_id) else {
+        return Ok(HttpResponse::BadRequest().body("Invalid message ID format"));
+    };
+
+    // Checked before the file is looked up, so unsigned requests can't probe for IDs
+    if let Some(Err(err)) = signer.map(|signer| signer.verify(&message_id.to_string(), query)) {
+        return Ok(signature_error_response(err));
+    }
+
+    let Some(message_path) = spool.kept_file(message_id, DECODED_MESSAGE_FILE) else {
+        return Ok(HttpResponse::NotFound().body("Message not found"));
+    };
+    match fs::read(&message_path) {
+        Ok(data) => Ok(HttpResponse::Ok()
+            .content_type("application/octet-stream")
+            .append_header((
+                header::CONTENT_DISPOSITION,
+                content_disposition(&format!("{}.bin", message_id), disposition),
+            ))
+            .body(data)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            Ok(HttpResponse::NotFound().body("Message not found"))
+        }
+        Err(e) => {
+            error!("Failed to read decoded message: {}", e);
+            Ok(HttpResponse::InternalServerError().body("Failed to read decoded message"))
+        }
+    }
+}
+
+/// Attach the timing of a library call to a response for the access log
+fn with_timing(mut response: HttpResponse, timing: Timing) -> HttpResponse {
+    response.extensions_mut().insert(timing);
+    response
+}
+
+/// Get the timing attached to a response, if any
+pub fn response_timing(response: &HttpResponse) -> Option<Timing> {
+    response.extensions().get::<Timing>().copied()
+}
+
+/// Build the decode response, tagging whether it came from the cache
+///
+/// Cached results carry no timing since no decoding took place.
+fn cached_decode_response(
+    request_id: Uuid,
+    decoded: CachedDecode,
+    matches: Option<bool>,
+    timing: Option<Timing>,
+) -> HttpResponse {
+    let response = DecodeResponse {
+        request_id,
+        status: "success".to_string(),
+        message: decoded.message,
+        binary_message: Some(decoded.binary_message),
+        message_length: decoded.message_length,
+        inline: true,
+        message_truncated: None,
+        download_url: None,
+        declared_length: decoded.declared_length,
+        recovered_length: decoded.recovered_length,
+        complete: decoded.complete,
+        matches,
+        processing_ms: timing.map(|t| t.processing_ms()),
+        pixels_per_second: timing.map(|t| t.pixels_per_second()),
+        warnings: decoded.warnings,
+    };
+
+    let response = HttpResponse::Ok()
+        .insert_header(("X-Cache", if timing.is_some() { "MISS" } else { "HIT" }))
+        .json(response);
+
+    match timing {
+        Some(timing) => with_timing(response, timing),
+        None => response,
+    }
+}
+
+/// Build the response for a decoded message too large to inline
+///
+/// The message itself is left out; clients download it from `download_url`.
+fn stored_decode_response(
+    request_id: Uuid,
+    decoded: &PartialDecode,
+    partial: bool,
+    matches: Option<bool>,
+    download_url: String,
+    timing: Timing,
+    warnings: Vec<Warning>,
+) -> HttpResponse {
+    let response = DecodeResponse {
+        request_id,
+        status: "success".to_string(),
+        message: None,
+        binary_message: None,
+        message_length: decoded.data.len(),
+        inline: false,
+        message_truncated: Some(false),
+        download_url: Some(download_url),
+        declared_length: partial.then_some(decoded.declared_length),
+        recovered_length: partial.then_some(decoded.recovered_length),
+        complete: partial.then_some(decoded.complete),
+        matches,
+        processing_ms: Some(timing.processing_ms()),
+        pixels_per_second: Some(timing.pixels_per_second()),
+        warnings,
+    };
+
+    with_timing(HttpResponse::Ok().json(response), timing)
+}
+
+/// Build the response to a `verify_only` decode, which leaves the message out
+///
+/// # Arguments
+/// * `message_length` - Length of the decoded message in bytes
+/// * `complete` - Whether the full message was recovered, for partial decodes
+/// * `matches` - Whether the message equals the expected one
+/// * `timing` - Time spent decoding, absent for cached results
+/// * `warnings` - Things the decode noticed that didn't stop it
+fn verify_only_decode_response(
+    request_id: Uuid,
+    message_length: usize,
+    complete: Option<bool>,
+    matches: bool,
+    timing: Option<Timing>,
+    warnings: Vec<Warning>,
+) -> HttpResponse {
+    let response = DecodeResponse {
+        request_id,
+        status: "success".to_string(),
+        message: None,
+        binary_message: None,
+        message_length,
+        inline: false,
+        message_truncated: None,
+        download_url: None,
+        declared_length: None,
+        recovered_length: None,
+        complete,
+        matches: Some(matches),
+        processing_ms: timing.map(|t| t.processing_ms()),
+        pixels_per_second: timing.map(|t| t.pixels_per_second()),
+        warnings,
+    };
+
+    match timing {
+        Some(timing) => with_timing(HttpResponse::Ok().json(response), timing),
+        None => HttpResponse::Ok().json(response),
+    }
+}
+
+/// Build a response carrying the decoded message bytes as the body
+fn raw_decode_response(request_id: Uuid, message: Vec<u8>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("application/octet-stream")
         .append_header((
-            "Content-Disposition",
-            format!("inline; filename=\"{}.png\"", image_id),
+            header::CONTENT_DISPOSITION,
+            content_disposition(&format!("{}.bin", request_id), DispositionKind::Attachment),
         ))
-        .body(file_data))
+        .body(message)
 }
 
 /// Process a multipart form submission for image decoding
+///
+/// Messages larger than `max_inline_decode_bytes` are kept in the spool until
+/// they expire and linked from the response instead of being inlined. Sending
+/// `response=raw` returns the message bytes as the response body.
+///
+/// An `expected_message` or `expected_message_file` field is compared with the
+/// decoded message and the result reported as `matches`; with
+/// `verify_only=true` the message itself is left out of the response.
 pub async fn process_decode_form(
+    req: &HttpRequest,
     mut payload: Multipart,
-    upload_dir: &Path,
+    spool: &Arc<SpoolManager>,
+    config: &ServerConfig,
+    urls: &UrlBuilder,
+    cache: &DecodeCache,
+    storage_degraded: &AtomicBool,
 ) -> Result<HttpResponse, Error> {
     info!("Processing decode form submission");
 
     let request_id = Uuid::new_v4();
-    let mut files = RequestFiles::new(upload_dir, request_id);
+    let mut files = match RequestFiles::in_spool(spool, request_id) {
+        Ok(files) => files,
+        Err(full) => return Ok(spool_full_response(full, request_id)),
+    };
 
     let mut stego_image_path: Option<PathBuf> = None;
+    let mut allow_partial = false;
+    let mut raw_response = false;
+    let mut expected_dimensions: Option<(u32, u32)> = None;
+    let mut expected_cover_sha256: Option<String> = None;
+    let mut expected_message: Option<Vec<u8>> = None;
+    let mut expected_message_file: Option<Vec<u8>> = None;
+    let mut verify_only = false;
+    let mut algorithm = Algorithm::default();
 
     // Process multipart form data
     while let Some(item) = payload.next().await {
@@ -544,64 +2227,145 @@ pub async fn process_decode_form(
             .to_string();
 
         if field_name == "stego_image" {
-            // Get filename
-            let filename = content_disposition
-                .and_then(|cd| cd.get_filename())
-                .unwrap_or("stego_image.png")
-                .to_string();
-
-            // Create a file to save the uploaded image
-            let (path, mut file) = match files.create_file(&filename) {
-                Ok((p, f)) => (p, f),
-                Err(e) => {
-                    error!("Failed to create file: {}", e);
-                    return Ok(HttpResponse::InternalServerError().json(ErrorResponse::new(
-                        request_id,
-                        error_codes::INTERNAL_ERROR,
-                        "Failed to process uploaded file",
-                    )));
-                }
+            let filename = utils::safe_filename(
+                content_disposition.and_then(|cd| cd.get_filename()),
+                "stego_image.png",
+            );
+
+            let path = match save_image_field(
+                &mut field,
+                &mut files,
+                &filename,
+                request_id,
+                storage_degraded,
+            )
+            .await
+            {
+                Ok(p) => p,
+                Err(response) => return Ok(response),
             };
 
-            // Save the file
-            let mut size: usize = 0;
-            while let Some(chunk) = field.next().await {
-                let data = match chunk {
-                    Ok(d) => d,
-                    Err(e) => {
-                        error!("Error reading multipart chunk: {}", e);
-                        return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(
+            stego_image_path = Some(path);
+        } else if field_name == "allow_partial" {
+            // Read the partial decoding flag
+            let value =
+                match read_text_field(&mut field, &field_name, MAX_TEXT_FIELD_LENGTH, request_id)
+                    .await
+                {
+                    Ok(value) => value,
+                    Err(response) => return Ok(response),
+                };
+
+            allow_partial = value.trim().eq_ignore_ascii_case("true");
+        } else if field_name == "response" {
+            // Read the requested response shape
+            let value =
+                match read_text_field(&mut field, &field_name, MAX_TEXT_FIELD_LENGTH, request_id)
+                    .await
+                {
+                    Ok(value) => value,
+                    Err(response) => return Ok(response),
+                };
+
+            let value = value.trim().to_lowercase();
+            raw_response = match value.as_str() {
+                "raw" => true,
+                "json" => false,
+                _ => {
+                    return Ok(HttpResponse::BadRequest().json(
+                        ErrorResponse::new(
                             request_id,
                             error_codes::VALIDATION_ERROR,
-                            &format!("Error reading upload: {}", e),
-                        )));
-                    }
+                            &format!("Unknown response type: {} (expected json or raw)", value),
+                        )
+                        .with_details(ValidationDetails::field(
+                            "response",
+                            "expected json or raw",
+                            Some(&value),
+                        )),
+                    ));
+                }
+            };
+        } else if field_name == "expected_dimensions" {
+            // Read the size the cover had when the message was encoded
+            let value =
+                match read_text_field(&mut field, &field_name, MAX_TEXT_FIELD_LENGTH, request_id)
+                    .await
+                {
+                    Ok(value) => value,
+                    Err(response) => return Ok(response),
                 };
 
-                size += data.len();
-                if size > MAX_IMAGE_SIZE {
-                    return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(
-                        request_id,
-                        error_codes::IMAGE_TOO_LARGE,
-                        &format!("Image exceeds maximum size of {} bytes", MAX_IMAGE_SIZE),
-                    )));
+            match utils::parse_dimensions(&value) {
+                Ok(dimensions) => expected_dimensions = Some(dimensions),
+                Err(e) => {
+                    let reason = e.to_string();
+                    return Ok(HttpResponse::BadRequest().json(
+                        hide_error_to_response(e, request_id).with_details(
+                            ValidationDetails::field("expected_dimensions", &reason, Some(&value)),
+                        ),
+                    ));
                 }
+            }
+        } else if field_name == "expected_cover_sha256" {
+            // Read the fingerprint of the cover returned by the encode
+            let value =
+                match read_text_field(&mut field, &field_name, MAX_TEXT_FIELD_LENGTH, request_id)
+                    .await
+                {
+                    Ok(value) => value,
+                    Err(response) => return Ok(response),
+                };
 
-                // Write chunk to file
-                if let Err(e) = file.write_all(&data) {
-                    error!("Error writing to file: {}", e);
-                    return Ok(HttpResponse::InternalServerError().json(ErrorResponse::new(
-                        request_id,
-                        error_codes::INTERNAL_ERROR,
-                        "Failed to save uploaded file",
-                    )));
-                }
+            let value = value.trim().to_lowercase();
+            expected_cover_sha256 = Some(value).filter(|value| !value.is_empty());
+        } else if field_name == "expected_message" || field_name == "expected_message_file" {
+            // Read the message the client expects the image to hold
+            let content = match read_message_field(&mut field, &field_name, request_id).await {
+                Ok(field) => field.content,
+                Err(response) => return Ok(response),
+            };
+
+            if field_name == "expected_message" {
+                expected_message = Some(content);
+            } else {
+                expected_message_file = Some(content);
             }
+        } else if field_name == "verify_only" {
+            // Read whether to leave the message out of the response
+            let value =
+                match read_text_field(&mut field, &field_name, MAX_TEXT_FIELD_LENGTH, request_id)
+                    .await
+                {
+                    Ok(value) => value,
+                    Err(response) => return Ok(response),
+                };
 
-            stego_image_path = Some(path);
+            verify_only = value.trim().eq_ignore_ascii_case("true");
+        } else if field_name == "algorithm" {
+            // Read the algorithm the message was hidden with
+            let value =
+                match read_text_field(&mut field, &field_name, MAX_TEXT_FIELD_LENGTH, request_id)
+                    .await
+                {
+                    Ok(value) => value,
+                    Err(response) => return Ok(response),
+                };
+
+            match value.parse() {
+                Ok(parsed) => algorithm = parsed,
+                Err(e) => {
+                    let reason = e.to_string();
+                    return Ok(HttpResponse::BadRequest().json(
+                        hide_error_to_response(e, request_id).with_details(
+                            ValidationDetails::field("algorithm", &reason, Some(&value)),
+                        ),
+                    ));
+                }
+            }
         } else {
             // Skip unknown fields
-            while let Some(_) = field.next().await {}
+            while field.next().await.is_some() {}
         }
     }
 
@@ -609,53 +2373,1028 @@ pub async fn process_decode_form(
     let stego_image_path = match stego_image_path {
         Some(path) => path,
         None => {
-            return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(
+            return Ok(HttpResponse::BadRequest().json(
+                ErrorResponse::new(
+                    request_id,
+                    error_codes::VALIDATION_ERROR,
+                    "Missing stego image",
+                )
+                .with_details(ValidationDetails::field(
+                    "stego_image",
+                    "required",
+                    None,
+                )),
+            ));
+        }
+    };
+
+    // The form field wins over the file, as for encodes
+    let expected = expected_message.or(expected_message_file);
+    if verify_only && expected.is_none() {
+        return Ok(HttpResponse::BadRequest().json(
+            ErrorResponse::new(
                 request_id,
                 error_codes::VALIDATION_ERROR,
-                "Missing stego image",
-            )));
+                "verify_only needs an expected message to compare with",
+            )
+            .with_details(ValidationDetails::field(
+                "verify_only",
+                "requires expected_message or expected_message_file",
+                None,
+            )),
+        ));
+    }
+    if raw_response && expected.is_some() {
+        return Ok(HttpResponse::BadRequest().json(
+            ErrorResponse::new(
+                request_id,
+                error_codes::VALIDATION_ERROR,
+                "Comparisons with an expected message are only reported in JSON responses",
+            )
+            .with_details(ValidationDetails::field(
+                "response",
+                "expected json when an expected message is given",
+                Some("raw"),
+            )),
+        ));
+    }
+
+    // Answer repeated uploads from the cache; the cache only holds BLTM decodes
+    let cache_key = if cache.is_enabled() && algorithm == Algorithm::Bltm {
+        match fs::read(&stego_image_path) {
+            Ok(data) => Some(
+                CacheKey::new(&data, allow_partial).with_expected_dimensions(expected_dimensions),
+            ),
+            Err(e) => {
+                warn!("Failed to read upload for cache lookup: {}", e);
+                None
+            }
         }
+    } else {
+        None
     };
 
-    // Load the stego image
-    let stego_image = match StegoImage::from_file(&stego_image_path) {
-        Ok(img) => img,
-        Err(e) => {
-            error!("Failed to load stego image: {}", e);
-            return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(
+    if let Some(cached) = cache_key.as_ref().and_then(|key| cache.get(key)) {
+        info!("Decode cache hit for request {}", request_id);
+        if raw_response {
+            if let Ok(message) = BASE64.decode(&cached.binary_message) {
+                return Ok(raw_decode_response(request_id, message));
+            }
+        }
+
+        let matches = expected.as_deref().map(|expected| {
+            BASE64
+                .decode(&cached.binary_message)
+                .is_ok_and(|message| utils::constant_time_eq(&message, expected))
+        });
+        if let (true, Some(matches)) = (verify_only, matches) {
+            return Ok(verify_only_decode_response(
                 request_id,
-                error_codes::INVALID_IMAGE,
-                &format!("Failed to load stego image: {}", e),
-            )));
+                cached.message_length,
+                cached.complete,
+                matches,
+                None,
+                cached.warnings.clone(),
+            ));
         }
-    };
+        return Ok(cached_decode_response(request_id, cached, matches, None));
+    }
 
-    // Create the decoder
-    let decoder = create_decoder();
+    // Decode the message within the configured limits, tolerating truncated
+    // images if requested
+    let limits = config.decode_limits();
+    let result = match algorithm {
+        Algorithm::Bltm => {
+            // Load the stego image
+            let stego_image = match StegoImage::from_file(&stego_image_path) {
+                Ok(img) => img,
+                Err(e) => {
+                    error!("Failed to load stego image: {}", e);
+                    return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(
+                        request_id,
+                        error_codes::INVALID_IMAGE,
+                        &format!("Failed to load stego image: {}", e),
+                    )));
+                }
+            };
 
-    // Decode the message
-    let message_bytes = match decoder.decode(&stego_image) {
-        Ok(msg) => msg,
+            let decoder = Decoder::with_config(DecoderConfig {
+                expected_dimensions,
+                ..Default::default()
+            });
+            decoder.decode_untrusted_detailed(&stego_image, limits, allow_partial)
+        }
+        #[cfg(feature = "jpeg")]
+        Algorithm::JpegDct => decode_jpeg_dct(&stego_image_path, limits),
+    };
+    let detailed = match result {
+        Ok(detailed) => detailed,
         Err(e) => {
             error!("Failed to decode message: {:?}", e);
-            return Ok(HttpResponse::BadRequest().json(hide_error_to_response(e, request_id)));
+            if is_original_cover(&stego_image_path, expected_cover_sha256.as_deref()) {
+                return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(
+                    request_id,
+                    error_codes::NO_MESSAGE_FOUND,
+                    "The uploaded image is the original cover, not the encoded image",
+                )));
+            }
+            return Ok(decode_error_response(e, request_id));
         }
     };
 
-    // Check if the message is valid UTF-8
-    let text_message = String::from_utf8(message_bytes.clone()).ok();
+    let warnings: Vec<_> = detailed.warnings.iter().map(Warning::from).collect();
+    let partial = allow_partial.then_some(&detailed.decoded);
+    if partial.is_some_and(|p| !p.complete) {
+        warn!(
+            "Partial decode: recovered {} of {} declared bytes",
+            detailed.decoded.recovered_length, detailed.decoded.declared_length
+        );
+    }
+    if raw_response {
+        return Ok(with_timing(
+            raw_decode_response(request_id, detailed.decoded.data),
+            detailed.timing,
+        ));
+    }
+
+    let matches = expected
+        .as_deref()
+        .map(|expected| utils::constant_time_eq(&detailed.decoded.data, expected));
+    if let (true, Some(matches)) = (verify_only, matches) {
+        return Ok(verify_only_decode_response(
+            request_id,
+            detailed.decoded.data.len(),
+            partial.map(|p| p.complete),
+            matches,
+            Some(detailed.timing),
+            warnings,
+        ));
+    }
 
-    // Base64 encode the binary message
-    let binary_message = BASE64.encode(&message_bytes);
+    // Store large messages rather than building a huge JSON string
+    if detailed.decoded.data.len() > config.max_inline_decode_bytes {
+        let message_id = Uuid::new_v4();
+        match spool.keep(message_id, DECODED_MESSAGE_FILE, &detailed.decoded.data) {
+            Ok(_) => {}
+            Err(KeepError::Full(full)) => return Ok(spool_full_response(full, request_id)),
+            Err(KeepError::Io(e)) => {
+                return Ok(storage_error_response(
+                    &e,
+                    storage_degraded,
+                    request_id,
+                    "Failed to store decoded message",
+                ))
+            }
+        }
+        mark_storage_healthy(storage_degraded);
 
-    // Create the response
-    let response = DecodeResponse {
-        request_id,
-        status: "success".to_string(),
-        message: text_message,
-        binary_message,
+        return Ok(stored_decode_response(
+            request_id,
+            &detailed.decoded,
+            allow_partial,
+            matches,
+            urls.message_url(req, message_id),
+            detailed.timing,
+            warnings,
+        ));
+    }
+
+    let message_bytes = &detailed.decoded.data;
+
+    let decoded = CachedDecode {
+        // Check if the message is valid UTF-8
+        message: String::from_utf8(message_bytes.clone()).ok(),
+        // Base64 encode the binary message
+        binary_message: BASE64.encode(message_bytes),
         message_length: message_bytes.len(),
+        declared_length: partial.map(|p| p.declared_length),
+        recovered_length: partial.map(|p| p.recovered_length),
+        complete: partial.map(|p| p.complete),
+        warnings,
     };
 
-    Ok(HttpResponse::Ok().json(response))
+    // Only successful decodes are cached
+    if let Some(key) = cache_key {
+        cache.insert(key, decoded.clone());
+    }
+
+    Ok(cached_decode_response(
+        request_id,
+        decoded,
+        matches,
+        Some(detailed.timing),
+    ))
+}
+
+/// Read a message hidden in a JPEG's DCT coefficients within the decode limits
+///
+/// The message is always complete; the JPEG payload has no partial decoding.
+#[cfg(feature = "jpeg")]
+fn decode_jpeg_dct(
+    path: &Path,
+    limits: UntrustedLimits,
+) -> crate::Result<crate::decoder::DetailedDecode> {
+    let start = std::time::Instant::now();
+    let (width, height) = image::ImageReader::open(path)?
+        .with_guessed_format()?
+        .into_dimensions()?;
+    let pixels = u64::from(width) * u64::from(height);
+    if pixels > limits.max_pixels {
+        return Err(HideError::PixelLimitExceeded {
+            required: pixels,
+            limit: limits.max_pixels,
+        });
+    }
+
+    let data = JpegStego::new().decode(&fs::read(path)?)?;
+    if data.len() > limits.max_output_bytes {
+        return Err(HideError::OutputLimitExceeded {
+            declared_length: data.len(),
+            limit: limits.max_output_bytes,
+        });
+    }
+    Ok(crate::decoder::DetailedDecode {
+        decoded: PartialDecode {
+            declared_length: data.len(),
+            recovered_length: data.len(),
+            complete: true,
+            data,
+        },
+        timing: Timing::since(start, pixels),
+        warnings: Vec::new(),
+    })
+}
+
+/// Check whether an upload is byte for byte the cover an encode reported
+///
+/// # Arguments
+/// * `path` - The uploaded file
+/// * `cover_sha256` - The `cover_sha256` of the encode response, if the client sent it
+fn is_original_cover(path: &Path, cover_sha256: Option<&str>) -> bool {
+    cover_sha256.is_some_and(|expected| {
+        fs::read(path).is_ok_and(|data| manifest::sha256_hex(&data) == expected)
+    })
+}
+
+/// A message read from a form field
+struct MessageField {
+    /// The message bytes
+    content: Vec<u8>,
+
+    /// Hex-encoded SHA-256 of the message, computed as it was read
+    sha256: String,
+}
+
+/// Read a message field, refusing messages over [`MAX_MESSAGE_LENGTH`]
+///
+/// Each chunk is checked against the limit before it is buffered, and hashed
+/// as it arrives. On failure, the error response to send back to the client
+/// is returned instead.
+async fn read_message_field(
+    field: &mut Field,
+    name: &str,
+    request_id: Uuid,
+) -> Result<MessageField, HttpResponse> {
+    let mut content = Vec::new();
+    let mut hasher = Sha256::new();
+    while let Some(chunk) = field.next().await {
+        let data = match chunk {
+            Ok(d) => d,
+            Err(e) => {
+                error!("Error reading {}: {}", name, e);
+                return Err(HttpResponse::BadRequest().json(ErrorResponse::new(
+                    request_id,
+                    error_codes::VALIDATION_ERROR,
+                    &format!("Error reading {}: {}", name, e),
+                )));
+            }
+        };
+
+        if content.len() + data.len() > MAX_MESSAGE_LENGTH {
+            return Err(HttpResponse::BadRequest().json(
+                ErrorResponse::new(
+                    request_id,
+                    error_codes::MESSAGE_TOO_LARGE,
+                    &format!(
+                        "Message exceeds maximum size of {} bytes",
+                        MAX_MESSAGE_LENGTH
+                    ),
+                )
+                .with_details(MessageTooLargeDetails {
+                    message_bytes: content.len() + data.len(),
+                    capacity_bytes: MAX_MESSAGE_LENGTH,
+                }),
+            ));
+        }
+
+        hasher.update(&data);
+        content.extend_from_slice(&data);
+    }
+
+    Ok(MessageField {
+        content,
+        sha256: manifest::to_hex(&hasher.finalize()),
+    })
+}
+
+/// Read a text option field, refusing values over `max_len` bytes
+///
+/// Each chunk is checked against the limit before it is buffered. Bytes that
+/// aren't UTF-8 are replaced, so they fail whatever parsing the value gets.
+/// On failure, the error response to send back to the client is returned
+/// instead.
+async fn read_text_field(
+    field: &mut Field,
+    name: &str,
+    max_len: usize,
+    request_id: Uuid,
+) -> Result<String, HttpResponse> {
+    let mut content = Vec::new();
+    while let Some(chunk) = field.next().await {
+        let data = match chunk {
+            Ok(d) => d,
+            Err(e) => {
+                error!("Error reading {}: {}", name, e);
+                return Err(HttpResponse::BadRequest().json(ErrorResponse::new(
+                    request_id,
+                    error_codes::VALIDATION_ERROR,
+                    &format!("Error reading {}: {}", name, e),
+                )));
+            }
+        };
+
+        if content.len() + data.len() > max_len {
+            let reason = format!("must be at most {} bytes", max_len);
+            return Err(HttpResponse::BadRequest().json(
+                ErrorResponse::new(
+                    request_id,
+                    error_codes::VALIDATION_ERROR,
+                    &format!("Field {} exceeds maximum size of {} bytes", name, max_len),
+                )
+                .with_details(ValidationDetails::field(name, &reason, None)),
+            ));
+        }
+
+        content.extend_from_slice(&data);
+    }
+
+    Ok(String::from_utf8_lossy(&content).into_owned())
+}
+
+/// Save an uploaded image field into the request's temporary directory
+///
+/// On failure, the error response to send back to the client is returned instead.
+async fn save_image_field(
+    field: &mut Field,
+    files: &mut RequestFiles,
+    filename: &str,
+    request_id: Uuid,
+    storage_degraded: &AtomicBool,
+) -> Result<PathBuf, HttpResponse> {
+    // Create a file to save the uploaded image
+    let (path, mut file) = match files.create_file(filename) {
+        Ok((p, f)) => (p, f),
+        Err(e) => {
+            return Err(storage_error_response(
+                &e,
+                storage_degraded,
+                request_id,
+                "Failed to process uploaded file",
+            ));
+        }
+    };
+
+    // Save the file
+    let mut size: usize = 0;
+    while let Some(chunk) = field.next().await {
+        let data = match chunk {
+            Ok(d) => d,
+            Err(e) => {
+                error!("Error reading multipart chunk: {}", e);
+                return Err(HttpResponse::BadRequest().json(ErrorResponse::new(
+                    request_id,
+                    error_codes::VALIDATION_ERROR,
+                    &format!("Error reading upload: {}", e),
+                )));
+            }
+        };
+
+        size += data.len();
+        if size > MAX_IMAGE_SIZE {
+            return Err(HttpResponse::BadRequest().json(ErrorResponse::new(
+                request_id,
+                error_codes::IMAGE_TOO_LARGE,
+                &format!("Image exceeds maximum size of {} bytes", MAX_IMAGE_SIZE),
+            )));
+        }
+
+        // Write chunk to file, within the spool's budget
+        if let Err(full) = files.reserve(data.len() as u64) {
+            return Err(spool_full_response(full, request_id));
+        }
+        if let Err(e) = file.write_all(&data) {
+            return Err(storage_error_response(
+                &e,
+                storage_degraded,
+                request_id,
+                "Failed to save uploaded file",
+            ));
+        }
+    }
+
+    mark_storage_healthy(storage_degraded);
+    Ok(path)
+}
+
+/// Process a multipart form submission comparing two images
+pub async fn process_diff_form(
+    req: &HttpRequest,
+    mut payload: Multipart,
+    spool: &Arc<SpoolManager>,
+    storage_degraded: &AtomicBool,
+    urls: &UrlBuilder,
+) -> Result<HttpResponse, Error> {
+    info!("Processing diff form submission");
+
+    let request_id = Uuid::new_v4();
+    let upload_dir = spool.root();
+    let mut files = match RequestFiles::in_spool(spool, request_id) {
+        Ok(files) => files,
+        Err(full) => return Ok(spool_full_response(full, request_id)),
+    };
+
+    let mut image_a_path: Option<PathBuf> = None;
+    let mut image_b_path: Option<PathBuf> = None;
+    let mut render = false;
+    let mut block_size = None;
+
+    // Process multipart form data
+    while let Some(item) = payload.next().await {
+        let mut field = match item {
+            Ok(f) => f,
+            Err(e) => {
+                error!("Error getting multipart field: {}", e);
+                return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(
+                    request_id,
+                    error_codes::VALIDATION_ERROR,
+                    &format!("Invalid form data: {}", e),
+                )));
+            }
+        };
+
+        // Get field information
+        let content_disposition = field.content_disposition();
+        let field_name = content_disposition
+            .and_then(|cd| cd.get_name())
+            .unwrap_or("")
+            .to_string();
+
+        match field_name.as_str() {
+            "image_a" | "image_b" => {
+                // Prefix with the field name so both uploads can share a filename
+                let filename = format!(
+                    "{}_{}",
+                    field_name,
+                    utils::safe_filename(
+                        content_disposition.and_then(|cd| cd.get_filename()),
+                        "image.png"
+                    )
+                );
+
+                let path = match save_image_field(
+                    &mut field,
+                    &mut files,
+                    &filename,
+                    request_id,
+                    storage_degraded,
+                )
+                .await
+                {
+                    Ok(p) => p,
+                    Err(response) => return Ok(response),
+                };
+
+                if field_name == "image_a" {
+                    image_a_path = Some(path);
+                } else {
+                    image_b_path = Some(path);
+                }
+            }
+            "render" => {
+                // Read the heatmap rendering flag
+                let value = match read_text_field(
+                    &mut field,
+                    &field_name,
+                    MAX_TEXT_FIELD_LENGTH,
+                    request_id,
+                )
+                .await
+                {
+                    Ok(value) => value,
+                    Err(response) => return Ok(response),
+                };
+
+                render = value.trim().eq_ignore_ascii_case("true");
+            }
+            "block_size" => {
+                // Read the size of the blocks to count changed bits in
+                let value = match read_text_field(
+                    &mut field,
+                    &field_name,
+                    MAX_TEXT_FIELD_LENGTH,
+                    request_id,
+                )
+                .await
+                {
+                    Ok(value) => value,
+                    Err(response) => return Ok(response),
+                };
+
+                match utils::parse_block_size(&value) {
+                    Ok(size) => block_size = Some(size),
+                    Err(e) => {
+                        let reason = e.to_string();
+                        return Ok(HttpResponse::BadRequest().json(
+                            hide_error_to_response(e, request_id).with_details(
+                                ValidationDetails::field("block_size", &reason, Some(&value)),
+                            ),
+                        ));
+                    }
+                }
+            }
+            _ => {
+                // Skip unknown fields
+                while field.next().await.is_some() {}
+            }
+        }
+    }
+
+    // Ensure we have both images
+    let (image_a_path, image_b_path) = match (image_a_path, image_b_path) {
+        (Some(a), Some(b)) => (a, b),
+        (a, b) => {
+            let field_errors = [("image_a", a.is_none()), ("image_b", b.is_none())]
+                .into_iter()
+                .filter(|(_, missing)| *missing)
+                .map(|(field, _)| FieldError {
+                    field: field.to_string(),
+                    reason: "required".to_string(),
+                    received: None,
+                })
+                .collect();
+            return Ok(HttpResponse::BadRequest().json(
+                ErrorResponse::new(
+                    request_id,
+                    error_codes::VALIDATION_ERROR,
+                    "Missing image_a or image_b",
+                )
+                .with_details(ValidationDetails { field_errors }),
+            ));
+        }
+    };
+
+    // Load both images
+    let (image_a, image_b) = match [&image_a_path, &image_b_path].map(StegoImage::from_file) {
+        [Ok(a), Ok(b)] => (a, b),
+        [Err(e), _] | [_, Err(e)] => {
+            error!("Failed to load image for diff: {}", e);
+            return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(
+                request_id,
+                error_codes::INVALID_IMAGE,
+                &format!("Failed to load image: {}", e),
+            )));
+        }
+    };
+
+    // Reject mismatched dimensions with both sizes in the details
+    if image_a.dimensions() != image_b.dimensions() {
+        return Ok(HttpResponse::BadRequest().json(
+            ErrorResponse::new(
+                request_id,
+                error_codes::VALIDATION_ERROR,
+                "Images must have the same dimensions",
+            )
+            .with_details(DimensionMismatchDetails {
+                image_a: ImageDimensions {
+                    width: image_a.width(),
+                    height: image_a.height(),
+                },
+                image_b: ImageDimensions {
+                    width: image_b.width(),
+                    height: image_b.height(),
+                },
+            }),
+        ));
+    }
+
+    // Compare the images
+    let stats = match img::compare(&image_a, &image_b, block_size) {
+        Ok(stats) => stats,
+        Err(e) => {
+            error!("Failed to compare images: {:?}", e);
+            return Ok(HttpResponse::BadRequest().json(hide_error_to_response(e, request_id)));
+        }
+    };
+
+    // Render and store the heatmap if requested
+    let mut heatmap_id = None;
+    if render {
+        let heatmap = match img::lsb_diff(&image_a, &image_b) {
+            Ok(h) => h,
+            Err(e) => {
+                error!("Failed to render heatmap: {:?}", e);
+                return Ok(HttpResponse::BadRequest().json(hide_error_to_response(e, request_id)));
+            }
+        };
+
+        // Store the heatmap alongside encoded images so it can be downloaded the same way
+        let image_id = Uuid::new_v4();
+        let heatmap_path = upload_dir.join(image_id.to_string() + ".png");
+        if let Err(e) = heatmap
+            .save(&heatmap_path)
+            .and_then(|()| store::record_content_hash(&heatmap_path))
+        {
+            return Ok(save_error_response(
+                e,
+                storage_degraded,
+                request_id,
+                "Failed to save heatmap image",
+            ));
+        }
+        mark_storage_healthy(storage_degraded);
+
+        heatmap_id = Some(image_id);
+    }
+
+    // Create the response
+    let response = DiffResponse {
+        request_id,
+        status: "success".to_string(),
+        stats,
+        heatmap_id,
+        download_url: heatmap_id.map(|id| urls.image_url(req, id)),
+    };
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Process a multipart form asking how much an image can hold
+///
+/// Accepts the same option fields as the encode form; only those that
+/// affect capacity are used.
+pub async fn process_capacity_form(
+    mut payload: Multipart,
+    spool: &Arc<SpoolManager>,
+    storage_degraded: &AtomicBool,
+) -> Result<HttpResponse, Error> {
+    info!("Processing capacity form submission");
+
+    let request_id = Uuid::new_v4();
+    let mut files = match RequestFiles::in_spool(spool, request_id) {
+        Ok(files) => files,
+        Err(full) => return Ok(spool_full_response(full, request_id)),
+    };
+
+    let mut image_path: Option<PathBuf> = None;
+    let mut options = EncodeOptions::default();
+
+    // Process multipart form data
+    while let Some(item) = payload.next().await {
+        let mut field = match item {
+            Ok(f) => f,
+            Err(e) => {
+                error!("Error getting multipart field: {}", e);
+                return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(
+                    request_id,
+                    error_codes::VALIDATION_ERROR,
+                    &format!("Invalid form data: {}", e),
+                )));
+            }
+        };
+
+        // Get field information
+        let content_disposition = field.content_disposition();
+        let field_name = content_disposition
+            .and_then(|cd| cd.get_name())
+            .unwrap_or("")
+            .to_string();
+
+        match field_name.as_str() {
+            "cover_image" => {
+                let filename = utils::safe_filename(
+                    content_disposition.and_then(|cd| cd.get_filename()),
+                    "cover_image.png",
+                );
+
+                let path = match save_image_field(
+                    &mut field,
+                    &mut files,
+                    &filename,
+                    request_id,
+                    storage_degraded,
+                )
+                .await
+                {
+                    Ok(p) => p,
+                    Err(response) => return Ok(response),
+                };
+
+                image_path = Some(path);
+            }
+            "max_change" => {
+                // Read the change budget, as a fraction or a percentage
+                let value = match read_text_field(
+                    &mut field,
+                    &field_name,
+                    MAX_TEXT_FIELD_LENGTH,
+                    request_id,
+                )
+                .await
+                {
+                    Ok(value) => value,
+                    Err(response) => return Ok(response),
+                };
+
+                match utils::parse_ratio(&value) {
+                    Ok(ratio) => options.max_change = Some(ratio),
+                    Err(e) => {
+                        let reason = e.to_string();
+                        return Ok(HttpResponse::BadRequest().json(
+                            hide_error_to_response(e, request_id).with_details(
+                                ValidationDetails::field("max_change", &reason, Some(&value)),
+                            ),
+                        ));
+                    }
+                }
+            }
+            "embedding_depth" => {
+                // Read how many of each channel's lowest bits the payload takes
+                let value = match read_text_field(
+                    &mut field,
+                    &field_name,
+                    MAX_TEXT_FIELD_LENGTH,
+                    request_id,
+                )
+                .await
+                {
+                    Ok(value) => value,
+                    Err(response) => return Ok(response),
+                };
+
+                match value.parse::<EmbeddingDepth>() {
+                    Ok(depth) => options.embedding_depth = depth,
+                    Err(e) => {
+                        let reason = e.to_string();
+                        return Ok(HttpResponse::BadRequest().json(
+                            hide_error_to_response(e, request_id).with_details(
+                                ValidationDetails::field("embedding_depth", &reason, Some(&value)),
+                            ),
+                        ));
+                    }
+                }
+            }
+            _ => {
+                // Skip unknown fields
+                while field.next().await.is_some() {}
+            }
+        }
+    }
+
+    // Ensure we have an image
+    let Some(image_path) = image_path else {
+        return Ok(HttpResponse::BadRequest().json(
+            ErrorResponse::new(
+                request_id,
+                error_codes::VALIDATION_ERROR,
+                "Missing cover image",
+            )
+            .with_details(ValidationDetails::field("cover_image", "required", None)),
+        ));
+    };
+
+    let image = match StegoImage::from_file(&image_path) {
+        Ok(img) => img,
+        Err(e) => {
+            error!("Failed to load image for capacity: {}", e);
+            return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(
+                request_id,
+                error_codes::INVALID_IMAGE,
+                &format!("Failed to load image: {}", e),
+            )));
+        }
+    };
+
+    let config = EncoderConfig {
+        max_changed_pixels: options.max_change,
+        embedding_depth: options.embedding_depth,
+        ..Default::default()
+    };
+
+    Ok(HttpResponse::Ok().json(CapacityResponse {
+        request_id,
+        status: "success".to_string(),
+        width: image.width(),
+        height: image.height(),
+        capacity: encoder::capacity_breakdown(&image, &config),
+    }))
+}
+
+/// Process a multipart form asking which options a message size fits a cover with
+///
+/// Takes the cover as `cover_image`, the message length as `message_size` and
+/// the constraints as `max_change`, `robustness` and `hide_length`.
+pub async fn process_plan_form(
+    mut payload: Multipart,
+    spool: &Arc<SpoolManager>,
+    storage_degraded: &AtomicBool,
+) -> Result<HttpResponse, Error> {
+    info!("Processing plan form submission");
+
+    let request_id = Uuid::new_v4();
+    let mut files = match RequestFiles::in_spool(spool, request_id) {
+        Ok(files) => files,
+        Err(full) => return Ok(spool_full_response(full, request_id)),
+    };
+
+    let mut image_path: Option<PathBuf> = None;
+    let mut message_size: Option<usize> = None;
+    let mut constraints = PlanConstraints::default();
+
+    // Process multipart form data
+    while let Some(item) = payload.next().await {
+        let mut field = match item {
+            Ok(f) => f,
+            Err(e) => {
+                error!("Error getting multipart field: {}", e);
+                return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(
+                    request_id,
+                    error_codes::VALIDATION_ERROR,
+                    &format!("Invalid form data: {}", e),
+                )));
+            }
+        };
+
+        // Get field information
+        let content_disposition = field.content_disposition();
+        let field_name = content_disposition
+            .and_then(|cd| cd.get_name())
+            .unwrap_or("")
+            .to_string();
+
+        if field_name == "cover_image" {
+            let filename = utils::safe_filename(
+                content_disposition.and_then(|cd| cd.get_filename()),
+                "cover_image.png",
+            );
+
+            let path = match save_image_field(
+                &mut field,
+                &mut files,
+                &filename,
+                request_id,
+                storage_degraded,
+            )
+            .await
+            {
+                Ok(p) => p,
+                Err(response) => return Ok(response),
+            };
+
+            image_path = Some(path);
+            continue;
+        }
+
+        // Every other field is a short text value
+        let value =
+            match read_text_field(&mut field, &field_name, MAX_TEXT_FIELD_LENGTH, request_id).await
+            {
+                Ok(value) => value,
+                Err(response) => return Ok(response),
+            };
+        let value = value.trim().to_string();
+
+        let parsed = match field_name.as_str() {
+            "message_size" => value
+                .parse()
+                .map(|size| message_size = Some(size))
+                .map_err(|_| {
+                    HideError::InvalidParameters(format!(
+                        "Invalid message size: {} (expected a number of bytes)",
+                        value
+                    ))
+                }),
+            "max_change" => {
+                utils::parse_ratio(&value).map(|ratio| constraints.max_change = Some(ratio))
+            }
+            "robustness" => value
+                .parse()
+                .map(|robustness| constraints.robustness = robustness),
+            "hide_length" => {
+                constraints.hide_length = value.eq_ignore_ascii_case("true");
+                Ok(())
+            }
+            // Skip unknown fields
+            _ => Ok(()),
+        };
+        if let Err(e) = parsed {
+            let reason = e.to_string();
+            return Ok(HttpResponse::BadRequest().json(
+                hide_error_to_response(e, request_id).with_details(ValidationDetails::field(
+                    &field_name,
+                    &reason,
+                    Some(&value),
+                )),
+            ));
+        }
+    }
+
+    // Ensure we have an image and a size
+    let Some(image_path) = image_path else {
+        return Ok(HttpResponse::BadRequest().json(
+            ErrorResponse::new(
+                request_id,
+                error_codes::VALIDATION_ERROR,
+                "Missing cover image",
+            )
+            .with_details(ValidationDetails::field("cover_image", "required", None)),
+        ));
+    };
+    let Some(message_size) = message_size else {
+        return Ok(HttpResponse::BadRequest().json(
+            ErrorResponse::new(
+                request_id,
+                error_codes::VALIDATION_ERROR,
+                "Missing message size",
+            )
+            .with_details(ValidationDetails::field("message_size", "required", None)),
+        ));
+    };
+
+    let image = match StegoImage::from_file(&image_path) {
+        Ok(img) => img,
+        Err(e) => {
+            error!("Failed to load image for plan: {}", e);
+            return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(
+                request_id,
+                error_codes::INVALID_IMAGE,
+                &format!("Failed to load image: {}", e),
+            )));
+        }
+    };
+
+    Ok(HttpResponse::Ok().json(PlanResponse {
+        request_id,
+        status: "success".to_string(),
+        width: image.width(),
+        height: image.height(),
+        plan: planner::plan(&image, message_size, constraints),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_created_files_stay_in_the_request_directory() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let request_id = Uuid::new_v4();
+        let request_dir = temp_dir.path().join(request_id.to_string());
+        let mut files = RequestFiles::new(temp_dir.path(), request_id);
+
+        for (filename, expected) in [
+            ("../../x.png", "x.png"),
+            ("...", "upload"),
+            ("CON", "upload"),
+            ("a\\b.png", "b.png"),
+        ] {
+            let (path, _) = files.create_file(filename).unwrap();
+            assert_eq!(path, request_dir.join(expected));
+        }
+    }
+
+    #[test]
+    fn test_storage_errors_degrade_until_next_write() {
+        let degraded = AtomicBool::new(false);
+        let request_id = Uuid::new_v4();
+
+        // A read-only upload directory is reported as unavailable
+        let err = io::Error::from(io::ErrorKind::ReadOnlyFilesystem);
+        let response = storage_error_response(&err, &degraded, request_id, "Failed");
+        assert_eq!(response.status(), 503);
+        assert_eq!(
+            response.headers().get(header::RETRY_AFTER).unwrap(),
+            &STORAGE_RETRY_AFTER_SECS.to_string()
+        );
+        assert!(degraded.load(Ordering::SeqCst));
+
+        // Other failures stay internal errors and don't touch the state
+        let err = io::Error::from(io::ErrorKind::InvalidData);
+        let response = storage_error_response(&err, &degraded, request_id, "Failed");
+        assert_eq!(response.status(), 500);
+        assert!(degraded.load(Ordering::SeqCst));
+
+        mark_storage_healthy(&degraded);
+        assert!(!degraded.load(Ordering::SeqCst));
+    }
 }
@@ -0,0 +1,480 @@
+//! Replaying encode responses to retried requests
+//!
+//! A retry carrying the same `Idempotency-Key` header, or without one the same
+//! cover, message and options, gets the response of the first attempt instead
+//! of encoding and storing the image again. A retry that arrives while the
+//! first attempt is still encoding waits for it and then replays its result.
+//!
+//! Keys are scoped to the client sending them, so two clients choosing the
+//! same `Idempotency-Key` never see each other's responses. Each recorded
+//! response keeps a digest of the request it answered, and a request reusing
+//! a key for a different cover, message or options is refused rather than
+//! answered with a response that doesn't belong to it.
+
+use crate::api::models::EncodeResponse;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+
+/// Request header clients set to mark retries of the same request
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// Response header set on replayed responses
+pub const REPLAY_HEADER: &str = "X-Idempotent-Replay";
+
+/// Identifies requests that should produce the same response
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IdempotencyKey([u8; 32]);
+
+impl IdempotencyKey {
+    /// Key for a client-supplied `Idempotency-Key` header value
+    ///
+    /// # Arguments
+    /// * `client` - Identifies the client sending the request
+    /// * `value` - The header value
+    pub fn from_header(client: &str, value: &str) -> Self {
+        Self::scoped(b"header\0", client, value.as_bytes())
+    }
+
+    /// Key for the contents of a request
+    ///
+    /// # Arguments
+    /// * `client` - Identifies the client sending the request
+    /// * `digest` - Digest of the request's contents
+    pub fn from_digest(client: &str, digest: &RequestDigest) -> Self {
+        Self::scoped(b"contents\0", client, &digest.0)
+    }
+
+    fn scoped(domain: &[u8], client: &str, value: &[u8]) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(domain);
+        // Length-prefix the client so it can't run into the value
+        hasher.update((client.len() as u64).to_be_bytes());
+        hasher.update(client.as_bytes());
+        hasher.update(value);
+        Self(hasher.finalize().into())
+    }
+}
+
+/// Digest of the contents of a request
+///
+/// Recorded with each response, so a request reusing a key for different
+/// contents can be told apart from a retry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RequestDigest([u8; 32]);
+
+impl RequestDigest {
+    /// Digest the fields of a request
+    ///
+    /// # Arguments
+    /// * `parts` - The request fields, in a fixed order
+    pub fn new(parts: &[&[u8]]) -> Self {
+        let mut hasher = Sha256::new();
+        for part in parts {
+            // Length-prefix each part so moving bytes between fields changes the digest
+            hasher.update((part.len() as u64).to_be_bytes());
+            hasher.update(part);
+        }
+        Self(hasher.finalize().into())
+    }
+}
+
+/// A finished request and when it finished
+#[derive(Debug)]
+struct Completed {
+    /// The response sent for it
+    response: EncodeResponse,
+
+    /// Digest of the request the response answers
+    digest: RequestDigest,
+
+    /// When the response was recorded
+    at: Instant,
+}
+
+/// State of one key; the async lock is held while a request is processing it
+type Slot = Arc<AsyncMutex<Option<Completed>>>;
+
+/// Short-lived record of completed encode responses by key
+///
+/// A store with a zero window is disabled and never replays anything.
+#[derive(Debug)]
+pub struct IdempotencyStore {
+    /// How long a response is replayed after it was recorded
+    window: Duration,
+
+    /// Slots by key, with the order to check them for expiry
+    slots: Mutex<Slots>,
+}
+
+/// The slots of a store
+#[derive(Debug, Default)]
+struct Slots {
+    /// Slots by key
+    by_key: HashMap<IdempotencyKey, Slot>,
+
+    /// Keys with when their slot was last found in use, oldest first
+    ///
+    /// Each claim only looks at the keys at the front that have been idle
+    /// for a whole window, so expiring slots costs little per claim however
+    /// many keys are tracked.
+    expiry: VecDeque<(Instant, IdempotencyKey)>,
+}
+
+/// Outcome of claiming a key
+#[derive(Debug)]
+pub enum Claim {
+    /// An earlier request with the same key succeeded; send its response again
+    Replay(Box<EncodeResponse>),
+
+    /// An earlier request used the key for different contents; refuse this one
+    Conflict,
+
+    /// No response to replay; process the request and record the result
+    Proceed(Pending),
+}
+
+/// Exclusive right to process a key
+///
+/// Other requests with the key wait until this is completed or dropped.
+/// Dropping it without completing, as on an error, lets the next waiter
+/// process the request itself.
+#[derive(Debug)]
+pub struct Pending {
+    /// The held slot, or `None` if the store is disabled
+    slot: Option<OwnedMutexGuard<Option<Completed>>>,
+
+    /// Digest of the request being processed
+    digest: RequestDigest,
+}
+
+impl Pending {
+    /// Record the response so retries within the window replay it
+    pub fn complete(mut self, response: &EncodeResponse) {
+        if let Some(slot) = self.slot.as_mut() {
+            **slot = Some(Completed {
+                response: response.clone(),
+                digest: self.digest,
+                at: Instant::now(),
+            });
+        }
+    }
+}
+
+impl IdempotencyStore {
+    /// Create a store replaying responses for the given window
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            slots: Mutex::new(Slots::default()),
+        }
+    }
+
+    /// Whether responses are recorded at all
+    pub fn is_enabled(&self) -> bool {
+        !self.window.is_zero()
+    }
+
+    /// Claim a key, waiting for any request already processing it
+    ///
+    /// # Arguments
+    /// * `key` - The key of the incoming request
+    /// * `digest` - Digest of the incoming request's contents
+    ///
+    /// # Returns
+    /// * The response to replay, a conflict if the key was used for other
+    ///   contents, or the right to process the request
+    pub async fn claim(&self, key: IdempotencyKey, digest: RequestDigest) -> Claim {
+        if !self.is_enabled() {
+            return Claim::Proceed(Pending { slot: None, digest });
+        }
+
+        let slot = {
+            let mut slots = self.slots.lock().unwrap_or_else(PoisonError::into_inner);
+            let now = Instant::now();
+            slots.purge_expired(now, self.window);
+            match slots.by_key.get(&key) {
+                Some(slot) => Arc::clone(slot),
+                None => {
+                    let slot = Slot::default();
+                    slots.by_key.insert(key, Arc::clone(&slot));
+                    slots.expiry.push_back((now, key));
+                    slot
+                }
+            }
+        };
+
+        let guard = slot.lock_owned().await;
+        match guard.as_ref() {
+            Some(done) if done.at.elapsed() < self.window => {
+                if done.digest == digest {
+                    Claim::Replay(Box::new(done.response.clone()))
+                } else {
+                    Claim::Conflict
+                }
+            }
+            _ => Claim::Proceed(Pending {
+                slot: Some(guard),
+                digest,
+            }),
+        }
+    }
+
+    /// Number of keys currently tracked, including those being processed
+    pub fn len(&self) -> usize {
+        self.slots
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .by_key
+            .len()
+    }
+
+    /// Whether no keys are tracked
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Slots {
+    /// Drop slots nobody is using whose response has expired or was never recorded
+    ///
+    /// Only keys idle for a whole window are looked at. Those still in use
+    /// or holding a live response go to the back of the queue.
+    fn purge_expired(&mut self, now: Instant, window: Duration) {
+        while let Some(&(since, key)) = self.expiry.front() {
+            if now.saturating_duration_since(since) < window {
+                break;
+            }
+            self.expiry.pop_front();
+            let Some(slot) = self.by_key.get(&key) else {
+                continue;
+            };
+
+            // Holders and waiters keep their own reference to the slot
+            let keep = Arc::strong_count(slot) > 1
+                || match slot.try_lock() {
+                    Ok(done) => done
+                        .as_ref()
+                        .is_some_and(|done| now.saturating_duration_since(done.at) < window),
+                    Err(_) => true,
+                };
+            if keep {
+                self.expiry.push_back((now, key));
+            } else {
+                self.by_key.remove(&key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::models::ImageMetadata;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use uuid::Uuid;
+
+    fn response() -> EncodeResponse {
+        EncodeResponse {
+            request_id: Uuid::new_v4(),
+            status: "success".to_string(),
+            image_id: Uuid::new_v4(),
+            download_url: "/api/v1/images/x".to_string(),
+            metadata: ImageMetadata {
+                width: 1,
+                height: 1,
+                format: "png".to_string(),
+                size_bytes: 0,
+                max_message_bytes: 0,
+                embedded_message_bytes: None,
+                stored_message_bytes: None,
+                message_sha256: None,
+                content_sha256: None,
+            },
+            cover_sha256: None,
+            cover_dimensions: None,
+            processing_ms: None,
+            pixels_per_second: None,
+            warnings: Vec::new(),
+            debug: None,
+        }
+    }
+
+    fn digest(contents: &str) -> RequestDigest {
+        RequestDigest::new(&[contents.as_bytes()])
+    }
+
+    #[test]
+    fn test_keys_separate_clients_headers_and_field_boundaries() {
+        assert_eq!(
+            RequestDigest::new(&[b"ab", b"c"]),
+            RequestDigest::new(&[b"ab", b"c"])
+        );
+        assert_ne!(
+            RequestDigest::new(&[b"ab", b"c"]),
+            RequestDigest::new(&[b"a", b"bc"])
+        );
+        assert_ne!(
+            IdempotencyKey::from_header("client", "abc"),
+            IdempotencyKey::from_digest("client", &digest("abc"))
+        );
+        assert_ne!(
+            IdempotencyKey::from_header("client", "abc"),
+            IdempotencyKey::from_header("other", "abc")
+        );
+        assert_ne!(
+            IdempotencyKey::from_header("ab", "c"),
+            IdempotencyKey::from_header("a", "bc")
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_in_flight_duplicate_waits_for_the_original() {
+        let store = Arc::new(IdempotencyStore::new(Duration::from_secs(60)));
+        let key = IdempotencyKey::from_header("client", "retry");
+        let processed = Arc::new(AtomicUsize::new(0));
+
+        let Claim::Proceed(first) = store.claim(key, digest("body")).await else {
+            panic!("first claim should proceed");
+        };
+
+        // The duplicate can't finish while the original holds the key
+        let waiter = {
+            let (store, processed) = (Arc::clone(&store), Arc::clone(&processed));
+            actix_web::rt::spawn(async move {
+                match store.claim(key, digest("body")).await {
+                    Claim::Replay(response) => *response,
+                    Claim::Conflict | Claim::Proceed(_) => {
+                        processed.fetch_add(1, Ordering::SeqCst);
+                        response()
+                    }
+                }
+            })
+        };
+        tokio::task::yield_now().await;
+        assert!(!waiter.is_finished());
+
+        let original = response();
+        first.complete(&original);
+
+        let replayed = waiter.await.unwrap();
+        assert_eq!(replayed.image_id, original.image_id);
+        assert_eq!(processed.load(Ordering::SeqCst), 0);
+    }
+
+    #[actix_web::test]
+    async fn test_reused_key_with_other_contents_conflicts() {
+        let store = IdempotencyStore::new(Duration::from_secs(60));
+        let key = IdempotencyKey::from_header("client", "retry");
+        let Claim::Proceed(first) = store.claim(key, digest("body")).await else {
+            panic!("first claim should proceed");
+        };
+        first.complete(&response());
+
+        assert!(matches!(
+            store.claim(key, digest("other body")).await,
+            Claim::Conflict
+        ));
+        assert!(matches!(
+            store.claim(key, digest("body")).await,
+            Claim::Replay(_)
+        ));
+    }
+
+    #[actix_web::test]
+    async fn test_failed_attempt_lets_the_next_one_proceed() {
+        let store = IdempotencyStore::new(Duration::from_secs(60));
+        let key = IdempotencyKey::from_header("client", "retry");
+
+        let Claim::Proceed(first) = store.claim(key, digest("body")).await else {
+            panic!("first claim should proceed");
+        };
+        drop(first);
+
+        // Nothing was recorded, so other contents may take the key over
+        assert!(matches!(
+            store.claim(key, digest("other body")).await,
+            Claim::Proceed(_)
+        ));
+    }
+
+    #[actix_web::test]
+    async fn test_expired_and_disabled_stores_do_not_replay() {
+        let window = Duration::from_millis(20);
+        let store = IdempotencyStore::new(window);
+        let key = IdempotencyKey::from_header("client", "retry");
+        let Claim::Proceed(first) = store.claim(key, digest("body")).await else {
+            panic!("first claim should proceed");
+        };
+        first.complete(&response());
+        assert!(matches!(
+            store.claim(key, digest("body")).await,
+            Claim::Replay(_)
+        ));
+
+        std::thread::sleep(window + Duration::from_millis(10));
+        let Claim::Proceed(second) = store.claim(key, digest("body")).await else {
+            panic!("expired response should not be replayed");
+        };
+        drop(second);
+
+        // Once the slot has been idle for a window, claiming another key purges it
+        std::thread::sleep(window + Duration::from_millis(10));
+        drop(
+            store
+                .claim(
+                    IdempotencyKey::from_header("client", "other"),
+                    digest("body"),
+                )
+                .await,
+        );
+        assert_eq!(store.len(), 1);
+
+        let disabled = IdempotencyStore::new(Duration::ZERO);
+        let Claim::Proceed(pending) = disabled.claim(key, digest("body")).await else {
+            panic!("disabled store should proceed");
+        };
+        pending.complete(&response());
+        assert!(matches!(
+            disabled.claim(key, digest("body")).await,
+            Claim::Proceed(_)
+        ));
+        assert!(disabled.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_purging_only_looks_at_idle_keys() {
+        let window = Duration::from_millis(30);
+        let store = IdempotencyStore::new(window);
+        for i in 0..100 {
+            let key = IdempotencyKey::from_header("client", &i.to_string());
+            let Claim::Proceed(pending) = store.claim(key, digest("body")).await else {
+                panic!("new key should proceed");
+            };
+            pending.complete(&response());
+        }
+        {
+            let slots = store.slots.lock().unwrap();
+            assert_eq!(slots.expiry.len(), 100);
+        }
+
+        // Live responses are requeued rather than dropped
+        let key = IdempotencyKey::from_header("client", "0");
+        assert!(matches!(
+            store.claim(key, digest("body")).await,
+            Claim::Replay(_)
+        ));
+        assert_eq!(store.len(), 100);
+
+        std::thread::sleep(window + Duration::from_millis(10));
+        drop(
+            store
+                .claim(IdempotencyKey::from_header("client", "new"), digest("body"))
+                .await,
+        );
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.slots.lock().unwrap().expiry.len(), 1);
+    }
+}
@@ -1,5 +1,17 @@
 //! API models and utilities for the REST API
 
+pub mod cache;
+pub mod covers;
+pub mod debug;
+pub mod disposition;
+pub mod embed;
 pub mod handlers;
+pub mod idempotency;
 pub mod models;
 pub mod routes;
+pub mod signing;
+pub mod spool;
+pub mod store;
+pub mod urls;
+
+pub use embed::{scope, ApiConfig};
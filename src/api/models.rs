@@ -1,5 +1,15 @@
 //! Data models for the REST API
 
+use crate::api::cache::CacheMetrics;
+use crate::capabilities::Algorithm;
+use crate::depth::EmbeddingDepth;
+use crate::encoder::{CapacityBreakdown, EncoderConfig};
+use crate::img::{DiffStats, FillStyle};
+use crate::padding::PaddingPolicy;
+use crate::planner::Plan;
+use crate::preset::Preset;
+use crate::schedule::ChannelSchedule;
+use crate::warning::Warning;
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use uuid::Uuid;
@@ -10,6 +20,18 @@ pub const MAX_MESSAGE_LENGTH: usize = 1024 * 1024; // 1MB
 /// Maximum allowed image size (in bytes) to prevent abuse
 pub const MAX_IMAGE_SIZE: usize = 10 * 1024 * 1024; // 10MB
 
+/// Maximum allowed size (in bytes) of a text option field, such as a flag or a number
+pub const MAX_TEXT_FIELD_LENGTH: usize = 1024;
+
+/// Maximum number of variants one encode request may ask for
+pub const MAX_ENCODE_VARIANTS: usize = 4;
+
+/// Maximum allowed size (in bytes) of the JSON list of encode variants
+pub const MAX_VARIANTS_FIELD_LENGTH: usize = 64 * 1024;
+
+/// Maximum cover pixels one encode request may encode, summed over its variants
+pub const MAX_VARIANT_PIXELS: u64 = 50_000_000;
+
 /// Function to generate a new UUID v4
 fn generate_uuid_v4() -> Uuid {
     Uuid::new_v4()
@@ -52,6 +74,52 @@ pub struct EncodeOptions {
     /// JPEG quality (0-100) if output format is JPEG
     #[serde(default = "default_jpeg_quality")]
     pub jpeg_quality: u8,
+
+    /// Replace a payload already present in the cover image
+    #[serde(default)]
+    pub overwrite: bool,
+
+    /// Largest fraction of the cover's pixels the payload may touch
+    #[serde(default)]
+    pub max_change: Option<f64>,
+
+    /// Where the image goes after encoding, e.g. `png`, `jpeg` or `print`
+    #[serde(default)]
+    pub intended_channel: Option<String>,
+
+    /// Refuse to encode instead of returning warnings
+    #[serde(default)]
+    pub strict: bool,
+
+    /// Decode the stego image to check it before storing it
+    #[serde(default = "default_verify_roundtrip")]
+    pub verify_roundtrip: bool,
+
+    /// Record the cover's dimensions in the payload, so
+    /// failed decodes of resized copies report the original size
+    #[serde(default)]
+    pub record_dimensions: bool,
+
+    /// Grow a cover too small for the message, filling the added area in this
+    /// style (`mirror`, `blur`, `solid` or `solid:#rrggbb`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_extend: Option<FillStyle>,
+
+    /// Embedding algorithm; `jpeg-dct` takes a JPEG cover and stores a JPEG
+    #[serde(default, skip_serializing_if = "is_default_algorithm")]
+    pub algorithm: Algorithm,
+
+    /// Deflate the message before embedding it when that makes it smaller
+    #[serde(default)]
+    pub compress: bool,
+
+    /// End the payload with a CRC-32 that decoders verify
+    #[serde(default)]
+    pub checksum: bool,
+
+    /// Bits of each channel the payload takes after the header, 1 or 2
+    #[serde(default)]
+    pub embedding_depth: EmbeddingDepth,
 }
 
 impl Default for EncodeOptions {
@@ -59,10 +127,29 @@ impl Default for EncodeOptions {
         Self {
             output_format: default_output_format(),
             jpeg_quality: default_jpeg_quality(),
+            overwrite: false,
+            max_change: None,
+            intended_channel: None,
+            strict: false,
+            verify_roundtrip: default_verify_roundtrip(),
+            record_dimensions: false,
+            auto_extend: None,
+            algorithm: Algorithm::default(),
+            compress: false,
+            checksum: false,
+            embedding_depth: EmbeddingDepth::One,
         }
     }
 }
 
+fn is_default_algorithm(algorithm: &Algorithm) -> bool {
+    *algorithm == Algorithm::default()
+}
+
+fn default_verify_roundtrip() -> bool {
+    true
+}
+
 fn default_output_format() -> String {
     "png".to_string()
 }
@@ -72,7 +159,7 @@ fn default_jpeg_quality() -> u8 {
 }
 
 /// Response for successful encoding
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EncodeResponse {
     /// Request ID from the original request
     pub request_id: Uuid,
@@ -88,10 +175,227 @@ pub struct EncodeResponse {
 
     /// Metadata about the encoded image
     pub metadata: ImageMetadata,
+
+    /// SHA-256 of the cover image file as the server received it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cover_sha256: Option<String>,
+
+    /// Size of the cover image as the server received it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cover_dimensions: Option<ImageDimensions>,
+
+    /// Time spent encoding, in milliseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub processing_ms: Option<f64>,
+
+    /// Encoding throughput in pixels per second
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pixels_per_second: Option<f64>,
+
+    /// Problems that did not stop the encode, such as a lossy intended channel
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<Warning>,
+
+    /// What the server received and where its time went, for trusted callers
+    /// that asked with `debug=true`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub debug: Option<DebugInfo>,
+}
+
+/// A request's trace, returned when debug responses are allowed and asked for
+///
+/// Only sizes, formats, names and timings are listed, never field contents.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DebugInfo {
+    /// Form fields in the order they were received
+    pub fields: Vec<FieldTrace>,
+
+    /// Time spent in each stage of the request, in order
+    pub stages: Vec<StageTiming>,
+}
+
+/// A form field as the server received it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldTrace {
+    /// Name of the field
+    pub name: String,
+
+    /// Size of the field's content in bytes
+    pub bytes: u64,
+
+    /// Image format sniffed from the content of uploaded files, if recognized
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+
+    /// Sanitized name of the temporary file an upload was saved to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temp_file: Option<String>,
+}
+
+/// Time spent in one stage of a request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StageTiming {
+    /// Name of the stage, such as `upload` or `encode`
+    pub stage: String,
+
+    /// Time spent, in milliseconds
+    pub ms: f64,
+}
+
+/// One stego image an encode request asks for in its `variants` field
+///
+/// Written either as a preset name (`"robust"`) or as an object of options.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum EncodeVariant {
+    /// A preset by name
+    Preset(Preset),
+
+    /// Options, starting from a preset if they name one
+    Options(VariantOptions),
+}
+
+impl EncodeVariant {
+    /// The encoder options this variant stands for
+    ///
+    /// Unset options come from the variant's preset; without a preset they
+    /// come from the request's own fields. The request's change budget applies
+    /// unless the variant sets its own, and whether an existing payload may be
+    /// overwritten always comes from the request.
+    ///
+    /// # Arguments
+    /// * `request` - The options of the encode request the variant belongs to
+    pub fn encoder_config(&self, request: &EncodeOptions) -> EncoderConfig {
+        let options = match self {
+            Self::Preset(preset) => VariantOptions {
+                preset: Some(*preset),
+                ..Default::default()
+            },
+            Self::Options(options) => options.clone(),
+        };
+
+        let base = match options.preset {
+            Some(preset) => EncoderConfig::preset(preset),
+            None => EncoderConfig {
+                verify_roundtrip: request.verify_roundtrip,
+                record_dimensions: request.record_dimensions,
+                ..Default::default()
+            },
+        };
+        EncoderConfig {
+            overwrite_existing: request.overwrite,
+            padding: options.padding.or(base.padding),
+            channel_schedule: options.channel_schedule.unwrap_or(base.channel_schedule),
+            record_dimensions: options.record_dimensions.unwrap_or(base.record_dimensions),
+            verify_roundtrip: options.verify_roundtrip.unwrap_or(base.verify_roundtrip),
+            max_changed_pixels: options.max_change.or(request.max_change),
+            auto_extend: request.auto_extend,
+            compress: request.compress,
+            checksum: request.checksum,
+            embedding_depth: request.embedding_depth,
+            ..base
+        }
+    }
+}
+
+/// Encoder options of an encode variant; unset options are inherited
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VariantOptions {
+    /// Preset to start from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preset: Option<Preset>,
+
+    /// How far to pad the payload, e.g. `"fill_capacity"` or `{"exact": 4096}`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub padding: Option<PaddingPolicy>,
+
+    /// How payload bits are assigned to color channels
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel_schedule: Option<ChannelSchedule>,
+
+    /// Record the cover's dimensions in the payload
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub record_dimensions: Option<bool>,
+
+    /// Decode the stego image to check it before storing it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verify_roundtrip: Option<bool>,
+
+    /// Largest fraction of the cover's pixels the payload may touch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_change: Option<f64>,
+}
+
+impl VariantOptions {
+    /// Every option of an encoder configuration, for echoing what a variant used
+    pub fn resolved(preset: Option<Preset>, config: &EncoderConfig) -> Self {
+        Self {
+            preset,
+            padding: config.padding,
+            channel_schedule: Some(config.channel_schedule),
+            record_dimensions: Some(config.record_dimensions),
+            verify_roundtrip: Some(config.verify_roundtrip),
+            max_change: config.max_changed_pixels,
+        }
+    }
+}
+
+/// Response for an encode request with `variants`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncodeVariantsResponse {
+    /// Request ID from the original request
+    pub request_id: Uuid,
+
+    /// Status of the operation
+    pub status: String,
+
+    /// One result per requested variant, in the order they were asked for
+    pub variants: Vec<VariantResult>,
+
+    /// SHA-256 of the cover image file as the server received it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cover_sha256: Option<String>,
+
+    /// Size of the cover image as the server received it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cover_dimensions: Option<ImageDimensions>,
+
+    /// What the server received and where its time went, for trusted callers
+    /// that asked with `debug=true`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub debug: Option<DebugInfo>,
+}
+
+/// The stored image of one encode variant
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VariantResult {
+    /// ID of the encoded image for retrieval
+    pub image_id: Uuid,
+
+    /// URL path to download the encoded image
+    pub download_url: String,
+
+    /// The options the variant was encoded with, inherited ones included
+    pub options: VariantOptions,
+
+    /// Metadata about the encoded image
+    pub metadata: ImageMetadata,
+
+    /// Where the cover's capacity went with these options
+    pub capacity: CapacityBreakdown,
+
+    /// Time spent encoding, in milliseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub processing_ms: Option<f64>,
+
+    /// Problems that did not stop the encode, such as a lossy intended channel
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<Warning>,
 }
 
 /// Response for successful decoding
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct DecodeResponse {
     /// Request ID from the original request
     pub request_id: Uuid,
@@ -103,15 +407,146 @@ pub struct DecodeResponse {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub message: Option<String>,
 
-    /// The decoded binary message (base64 encoded)
-    pub binary_message: String,
+    /// The decoded binary message (base64 encoded), absent when not inline
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub binary_message: Option<String>,
 
     /// Length of the decoded message in bytes
     pub message_length: usize,
+
+    /// Whether the message is included in the response body
+    pub inline: bool,
+
+    /// Set to `false` when the message was stored instead of inlined, to tell
+    /// clients nothing was lost
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message_truncated: Option<bool>,
+
+    /// Where to download the message when it is not inline
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub download_url: Option<String>,
+
+    /// Message length declared in the header (only for partial decodes)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub declared_length: Option<usize>,
+
+    /// Number of message bytes recovered (only for partial decodes)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recovered_length: Option<usize>,
+
+    /// Whether the full declared message was recovered (only for partial decodes)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub complete: Option<bool>,
+
+    /// Whether the message equals the expected one sent with the request
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matches: Option<bool>,
+
+    /// Time spent decoding, in milliseconds (absent for cached results)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub processing_ms: Option<f64>,
+
+    /// Decoding throughput in pixels per second (absent for cached results)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pixels_per_second: Option<f64>,
+
+    /// Things the decode noticed that didn't stop it, such as ignored header flags
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<Warning>,
 }
 
-/// Metadata about an image
+/// Response for a successful image comparison
+#[derive(Debug, Serialize)]
+pub struct DiffResponse {
+    /// Request ID from the original request
+    pub request_id: Uuid,
+
+    /// Status of the operation
+    pub status: String,
+
+    /// Statistics about the differences between the two images
+    pub stats: DiffStats,
+
+    /// ID of the rendered heatmap image (only when rendering was requested)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heatmap_id: Option<Uuid>,
+
+    /// URL path to download the heatmap image (only when rendering was requested)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub download_url: Option<String>,
+}
+
+/// Response for a capacity query
+#[derive(Debug, Serialize)]
+pub struct CapacityResponse {
+    /// Request ID from the original request
+    pub request_id: Uuid,
+
+    /// Status of the operation
+    pub status: String,
+
+    /// Width of the image in pixels
+    pub width: u32,
+
+    /// Height of the image in pixels
+    pub height: u32,
+
+    /// Capacity of the image under the requested options
+    pub capacity: CapacityBreakdown,
+}
+
+/// Response for a cover plan
+#[derive(Debug, Serialize)]
+pub struct PlanResponse {
+    /// Request ID from the original request
+    pub request_id: Uuid,
+
+    /// Status of the operation
+    pub status: String,
+
+    /// Width of the image in pixels
+    pub width: u32,
+
+    /// Height of the image in pixels
+    pub height: u32,
+
+    /// The options considered and the one recommended
+    pub plan: Plan,
+}
+
+/// A supported API version
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiVersion {
+    /// Version name
+    pub version: String,
+
+    /// Path prefix the version is mounted under
+    pub prefix: String,
+
+    /// Status of the version ("current", "deprecated" or "disabled")
+    pub status: String,
+
+    /// When the version will be removed (HTTP date), if scheduled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sunset: Option<String>,
+}
+
+/// Response for the versions endpoint
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VersionsResponse {
+    /// All API versions known to the server
+    pub versions: Vec<ApiVersion>,
+}
+
+/// Response for the metrics endpoint
 #[derive(Debug, Serialize)]
+pub struct MetricsResponse {
+    /// Decode cache counters
+    pub decode_cache: CacheMetrics,
+}
+
+/// Metadata about an image
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ImageMetadata {
     /// Width of the image in pixels
     pub width: u32,
@@ -125,12 +560,85 @@ pub struct ImageMetadata {
     /// Size of the image in bytes
     pub size_bytes: usize,
 
-    /// Maximum message size that could be embedded in this image
+    /// Maximum message size that could be embedded in this image with the
+    /// options of the request, such as its embedding depth
     pub max_message_bytes: usize,
 
     /// Actual message size that was embedded (if applicable)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub embedded_message_bytes: Option<usize>,
+
+    /// Bytes the message took in the image, fewer than
+    /// `embedded_message_bytes` when it was compressed (if applicable)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stored_message_bytes: Option<usize>,
+
+    /// Hex-encoded SHA-256 of the embedded message, for clients to check it
+    /// arrived intact (if applicable)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_sha256: Option<String>,
+
+    /// Hex-encoded SHA-256 of the stored image file (if stored)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_sha256: Option<String>,
+}
+
+/// A cover image offered by the server's cover pool
+#[derive(Debug, Clone, Serialize)]
+pub struct CoverInfo {
+    /// ID to pass as `cover_id` when encoding
+    pub id: String,
+
+    /// Dimensions and capacity of the cover
+    pub metadata: ImageMetadata,
+}
+
+/// A stored image in the image listing
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageListEntry {
+    /// ID of the image
+    pub image_id: Uuid,
+
+    /// When the image was stored
+    pub created_at: chrono::DateTime<chrono::Utc>,
+
+    /// URL to download the image
+    pub download_url: String,
+}
+
+/// Response for the image listing endpoint
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImagesResponse {
+    /// Images on this page, newest first
+    pub images: Vec<ImageListEntry>,
+
+    /// Cursor to pass as `cursor` for the next page, if there is one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+
+    /// Whether more images follow this page
+    pub has_more: bool,
+
+    /// Number of stored images, when the store can count them cheaply
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_count: Option<usize>,
+}
+
+/// Query parameters of the image listing endpoint
+#[derive(Debug, Default, Deserialize)]
+pub struct ListImagesQuery {
+    /// Cursor returned with the previous page
+    pub cursor: Option<String>,
+
+    /// Page size, at most [`crate::api::store::MAX_PAGE_LIMIT`]
+    pub limit: Option<String>,
+}
+
+/// Response for the cover listing endpoint
+#[derive(Debug, Serialize)]
+pub struct CoversResponse {
+    /// Available covers, smallest capacity first
+    pub covers: Vec<CoverInfo>,
 }
 
 /// Information about a stored image
@@ -168,9 +676,9 @@ pub struct ErrorResponse {
     /// Human-readable error message
     pub message: String,
 
-    /// Additional details about the error (if any)
+    /// Additional details about the error (if any); the shape depends on the error code
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub details: Option<serde_json::Value>,
+    pub details: Option<ErrorDetails>,
 }
 
 impl ErrorResponse {
@@ -186,12 +694,248 @@ impl ErrorResponse {
     }
 
     /// Add details to the error response
-    pub fn with_details(mut self, details: serde_json::Value) -> Self {
-        self.details = Some(details);
+    pub fn with_details(mut self, details: impl Into<ErrorDetails>) -> Self {
+        self.details = Some(details.into());
         self
     }
 }
 
+/// Typed contents of [`ErrorResponse::details`]
+///
+/// Each variant serializes as its inner struct; clients pick the struct to
+/// deserialize from the error code.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum ErrorDetails {
+    /// `message_too_large`
+    MessageTooLarge(MessageTooLargeDetails),
+    /// `validation_error` for form fields
+    Validation(ValidationDetails),
+    /// `validation_error` for diff images of different sizes
+    DimensionMismatch(DimensionMismatchDetails),
+    /// `existing_payload`
+    ExistingPayload(ExistingPayloadDetails),
+    /// `output_limit_exceeded`, `pixel_limit_exceeded` and `change_budget_exceeded`
+    Limit(LimitDetails),
+    /// `unsupported_feature`
+    UnsupportedFeature(UnsupportedFeatureDetails),
+    /// `truncated_payload`
+    TruncatedPayload(TruncatedPayloadDetails),
+    /// `dimensions_changed`
+    DimensionsChanged(DimensionsChangedDetails),
+    /// `storage_unavailable`
+    Retry(RetryDetails),
+    /// `warnings_in_strict_mode`
+    Warnings(WarningDetails),
+}
+
+/// Details of a `message_too_large` error
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageTooLargeDetails {
+    /// Length of the message in bytes
+    pub message_bytes: usize,
+
+    /// Largest message that fits, in bytes
+    pub capacity_bytes: usize,
+}
+
+/// Details of a `validation_error` caused by form fields
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValidationDetails {
+    /// The fields that failed validation
+    pub field_errors: Vec<FieldError>,
+}
+
+impl ValidationDetails {
+    /// Details for a single invalid field
+    pub fn field(field: &str, reason: &str, received: Option<&str>) -> Self {
+        Self {
+            field_errors: vec![FieldError {
+                field: field.to_string(),
+                reason: reason.to_string(),
+                received: received.map(str::to_string),
+            }],
+        }
+    }
+
+    /// Details for fields that may not be given together, one entry per field
+    pub fn conflicting(fields: &[&str]) -> Self {
+        let reason = format!("only one of {} may be given", fields.join(", "));
+        Self {
+            field_errors: fields
+                .iter()
+                .map(|field| FieldError {
+                    field: field.to_string(),
+                    reason: reason.clone(),
+                    received: None,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A form field that failed validation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldError {
+    /// Name of the form field
+    pub field: String,
+
+    /// Why the field was rejected
+    pub reason: String,
+
+    /// The value received, for text fields
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub received: Option<String>,
+}
+
+/// Details of a diff between images of different sizes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DimensionMismatchDetails {
+    /// Size of the first image
+    pub image_a: ImageDimensions,
+
+    /// Size of the second image
+    pub image_b: ImageDimensions,
+}
+
+/// Width and height of an image
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageDimensions {
+    /// Width in pixels
+    pub width: u32,
+
+    /// Height in pixels
+    pub height: u32,
+}
+
+impl From<(u32, u32)> for ImageDimensions {
+    fn from((width, height): (u32, u32)) -> Self {
+        Self { width, height }
+    }
+}
+
+/// Details of an `existing_payload` error
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExistingPayloadDetails {
+    /// Length declared by the payload already in the cover
+    pub declared_length: u32,
+}
+
+/// Details of a `truncated_payload` error
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TruncatedPayloadDetails {
+    /// Message length declared by the header, in bytes
+    pub declared_length: usize,
+
+    /// Whole message bytes the image actually holds
+    pub available_length: usize,
+}
+
+/// Details of a `dimensions_changed` error
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DimensionsChangedDetails {
+    /// Size of the cover when the message was encoded
+    pub encoded: ImageDimensions,
+
+    /// Size of the uploaded image
+    pub actual: ImageDimensions,
+}
+
+/// Details of an error caused by exceeding a limit
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LimitDetails {
+    /// Amount the request needed (bytes or pixels, depending on the error code)
+    pub required: u64,
+
+    /// Amount allowed
+    pub limit: u64,
+}
+
+/// Details of an `unsupported_feature` error
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnsupportedFeatureDetails {
+    /// Format version of the payload
+    #[serde(default)]
+    pub format_version: u8,
+
+    /// Header flag bits this server does not understand; 0 if it doesn't know the version
+    pub flags: u16,
+}
+
+/// Details of an error the client should retry later
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetryDetails {
+    /// Seconds to wait before retrying
+    pub retry_after_secs: u64,
+}
+
+/// Details of a request refused in strict mode
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WarningDetails {
+    /// The warnings that would have been returned
+    pub warnings: Vec<Warning>,
+}
+
+impl From<MessageTooLargeDetails> for ErrorDetails {
+    fn from(details: MessageTooLargeDetails) -> Self {
+        Self::MessageTooLarge(details)
+    }
+}
+
+impl From<ValidationDetails> for ErrorDetails {
+    fn from(details: ValidationDetails) -> Self {
+        Self::Validation(details)
+    }
+}
+
+impl From<DimensionMismatchDetails> for ErrorDetails {
+    fn from(details: DimensionMismatchDetails) -> Self {
+        Self::DimensionMismatch(details)
+    }
+}
+
+impl From<ExistingPayloadDetails> for ErrorDetails {
+    fn from(details: ExistingPayloadDetails) -> Self {
+        Self::ExistingPayload(details)
+    }
+}
+
+impl From<TruncatedPayloadDetails> for ErrorDetails {
+    fn from(details: TruncatedPayloadDetails) -> Self {
+        Self::TruncatedPayload(details)
+    }
+}
+
+impl From<LimitDetails> for ErrorDetails {
+    fn from(details: LimitDetails) -> Self {
+        Self::Limit(details)
+    }
+}
+
+impl From<UnsupportedFeatureDetails> for ErrorDetails {
+    fn from(details: UnsupportedFeatureDetails) -> Self {
+        Self::UnsupportedFeature(details)
+    }
+}
+
+impl From<DimensionsChangedDetails> for ErrorDetails {
+    fn from(details: DimensionsChangedDetails) -> Self {
+        Self::DimensionsChanged(details)
+    }
+}
+
+impl From<RetryDetails> for ErrorDetails {
+    fn from(details: RetryDetails) -> Self {
+        Self::Retry(details)
+    }
+}
+
+impl From<WarningDetails> for ErrorDetails {
+    fn from(details: WarningDetails) -> Self {
+        Self::Warnings(details)
+    }
+}
+
 /// Error codes used in API responses
 pub mod error_codes {
     pub const VALIDATION_ERROR: &str = "validation_error";
@@ -201,12 +945,30 @@ pub mod error_codes {
     pub const NO_MESSAGE_FOUND: &str = "no_message_found";
     pub const INTERNAL_ERROR: &str = "internal_error";
     pub const NOT_FOUND: &str = "not_found";
+    pub const STORAGE_UNAVAILABLE: &str = "storage_unavailable";
+    pub const IDEMPOTENCY_KEY_REUSED: &str = "idempotency_key_reused";
+    pub const EXISTING_PAYLOAD: &str = "existing_payload";
+    pub const OUTPUT_LIMIT_EXCEEDED: &str = "output_limit_exceeded";
+    pub const PIXEL_LIMIT_EXCEEDED: &str = "pixel_limit_exceeded";
+    pub const DEADLINE_EXCEEDED: &str = "deadline_exceeded";
+    pub const UNSUPPORTED_FEATURE: &str = "unsupported_feature";
+    pub const CHANGE_BUDGET_EXCEEDED: &str = "change_budget_exceeded";
+    pub const TRUNCATED_PAYLOAD: &str = "truncated_payload";
+    pub const COLOR_CONVERTED: &str = "color_converted";
+    pub const DIMENSIONS_CHANGED: &str = "dimensions_changed";
+    pub const WARNINGS_IN_STRICT_MODE: &str = "warnings_in_strict_mode";
+    pub const INVALID_SIGNATURE: &str = "invalid_signature";
+    pub const LINK_EXPIRED: &str = "link_expired";
+    pub const SELF_CHECK_FAILED: &str = "self_check_failed";
+    pub const ENCRYPTED_PAYLOAD: &str = "encrypted_payload";
+    pub const CHECKSUM_MISMATCH: &str = "checksum_mismatch";
+    pub const UNCORRECTABLE_ERRORS: &str = "uncorrectable_errors";
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use serde_json::{from_str, json, to_string};
+    use serde_json::{from_str, to_string};
 
     #[test]
     fn test_encode_request_serialization() {
@@ -271,7 +1033,16 @@ mod tests {
                 size_bytes: 12345,
                 max_message_bytes: 1000,
                 embedded_message_bytes: Some(100),
+                stored_message_bytes: None,
+                message_sha256: None,
+                content_sha256: None,
             },
+            cover_sha256: None,
+            cover_dimensions: None,
+            processing_ms: None,
+            pixels_per_second: None,
+            warnings: Vec::new(),
+            debug: None,
         };
 
         // Serialize to JSON
@@ -293,10 +1064,11 @@ mod tests {
             error_codes::VALIDATION_ERROR,
             "Invalid input",
         )
-        .with_details(json!({
-            "field": "message",
-            "reason": "Message is too large"
-        }));
+        .with_details(ValidationDetails::field(
+            "message",
+            "Message is too large",
+            None,
+        ));
 
         // Check values
         assert_eq!(err.status, "error");
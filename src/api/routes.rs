@@ -1,14 +1,36 @@
 //! API route definitions for the REST API
 
+use crate::api::disposition::{DispositionKind, DispositionQuery};
 use crate::api::handlers::*;
+use crate::api::models::{
+    error_codes, ApiVersion, CoversResponse, ErrorResponse, ListImagesQuery, MetricsResponse,
+    VersionsResponse,
+};
+use crate::api::signing::SignedQuery;
 use actix_multipart::Multipart;
-use actix_web::{web, HttpResponse, Responder};
+use actix_web::{
+    guard, middleware::DefaultHeaders, web, HttpRequest, HttpResponse, Responder, Scope,
+};
 use std::path::Path;
+use std::sync::atomic::Ordering;
+use uuid::Uuid;
+
+/// Path prefix of the current API version
+pub const API_V1_PREFIX: &str = "/api/v1";
+
+/// Path prefix of the deprecated unversioned API
+pub const LEGACY_API_PREFIX: &str = "/api";
+
+/// Date after which the unversioned API may be removed (HTTP date)
+pub const LEGACY_SUNSET: &str = "Fri, 31 Dec 2027 23:59:59 GMT";
 
 /// Health check endpoint
-pub async fn health_check() -> impl Responder {
+///
+/// Reports `degraded` while the upload directory can't be written to.
+pub async fn health_check(data: Option<web::Data<AppState>>) -> impl Responder {
+    let degraded = data.is_some_and(|state| state.storage_degraded.load(Ordering::SeqCst));
     let response = HealthResponse {
-        status: "ok".to_string(),
+        status: if degraded { "degraded" } else { "ok" }.to_string(),
         version: crate::VERSION.to_string(),
     };
     HttpResponse::Ok().json(response)
@@ -21,35 +43,229 @@ pub async fn ping() -> impl Responder {
 
 /// Encode message endpoint
 /// This endpoint handles steganography encoding
-pub async fn encode(payload: Multipart, data: web::Data<AppState>) -> impl Responder {
-    // Convert String to &Path
-    let upload_dir = Path::new(&data.config.upload_dir);
-    process_encode_form(payload, upload_dir).await
+pub async fn encode(
+    req: HttpRequest,
+    payload: Multipart,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    process_encode_form(&req, payload, &data).await
 }
 
 /// Decode message endpoint
 /// This endpoint handles steganography decoding
-pub async fn decode(payload: Multipart, data: web::Data<AppState>) -> impl Responder {
-    // Convert String to &Path
-    let upload_dir = Path::new(&data.config.upload_dir);
-    process_decode_form(payload, upload_dir).await
+pub async fn decode(
+    req: HttpRequest,
+    payload: Multipart,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    process_decode_form(
+        &req,
+        payload,
+        &data.spool,
+        &data.config,
+        &data.url_builder,
+        &data.decode_cache,
+        &data.storage_degraded,
+    )
+    .await
+}
+
+/// Server metrics endpoint
+pub async fn metrics(data: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(MetricsResponse {
+        decode_cache: data.decode_cache.metrics(),
+    })
+}
+
+/// List the covers available for encoding without an upload
+pub async fn list_covers(data: web::Data<AppState>) -> impl Responder {
+    if !data.cover_pool.is_enabled() {
+        return HttpResponse::NotFound().json(ErrorResponse::new(
+            Uuid::new_v4(),
+            error_codes::NOT_FOUND,
+            "This server has no cover pool",
+        ));
+    }
+
+    HttpResponse::Ok().json(CoversResponse {
+        covers: data.cover_pool.list(),
+    })
+}
+
+/// Image difference endpoint
+/// This endpoint compares two images and optionally renders a heatmap
+pub async fn diff(
+    req: HttpRequest,
+    payload: Multipart,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    process_diff_form(
+        &req,
+        payload,
+        &data.spool,
+        &data.storage_degraded,
+        &data.url_builder,
+    )
+    .await
+}
+
+/// Capacity endpoint
+/// This endpoint reports how much an image can hold under the given encode options
+pub async fn capacity(payload: Multipart, data: web::Data<AppState>) -> impl Responder {
+    process_capacity_form(payload, &data.spool, &data.storage_degraded).await
+}
+
+/// Plan endpoint
+/// This endpoint recommends encode options for a message size and cover
+pub async fn plan(payload: Multipart, data: web::Data<AppState>) -> impl Responder {
+    process_plan_form(payload, &data.spool, &data.storage_degraded).await
+}
+
+/// Image listing endpoint
+///
+/// Pages through the encoded images newest first; see [`crate::api::store`]
+/// for the ordering and cursor semantics.
+pub async fn list_images(
+    req: HttpRequest,
+    query: web::Query<ListImagesQuery>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    list_stored_images(&req, &query, data.image_store.as_ref(), &data.url_builder)
 }
 
 /// Get encoded image endpoint
-pub async fn get_image(path: web::Path<String>, data: web::Data<AppState>) -> impl Responder {
+///
+/// When download URLs are signed, the `expires` and `sig` query parameters
+/// must carry a valid, unexpired signature for the image. Images are shown
+/// inline unless `disposition=attachment` is given.
+pub async fn get_image(
+    path: web::Path<String>,
+    query: web::Query<SignedQuery>,
+    disposition: web::Query<DispositionQuery>,
+    data: web::Data<AppState>,
+) -> impl Responder {
     // Convert String to &Path
     let upload_dir = Path::new(&data.config.upload_dir);
-    serve_encoded_image(path.into_inner(), upload_dir).await
+    serve_encoded_image(
+        path.into_inner(),
+        upload_dir,
+        data.url_builder.signer(),
+        &query,
+        disposition.disposition.unwrap_or(DispositionKind::Inline),
+    )
+    .await
+}
+
+/// Get a stored decoded message endpoint
+///
+/// Messages are signed and downloaded like images, as attachments unless
+/// `disposition=inline` is given, until they expire after
+/// `decoded_message_ttl_secs`.
+pub async fn get_message(
+    path: web::Path<String>,
+    query: web::Query<SignedQuery>,
+    disposition: web::Query<DispositionQuery>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    serve_decoded_message(
+        path.into_inner(),
+        &data.spool,
+        data.url_builder.signer(),
+        &query,
+        disposition
+            .disposition
+            .unwrap_or(DispositionKind::Attachment),
+    )
+    .await
+}
+
+/// List supported API versions
+pub async fn versions(data: web::Data<AppState>) -> impl Responder {
+    let legacy_status = if data.config.enable_legacy_routes {
+        "deprecated"
+    } else {
+        "disabled"
+    };
+
+    HttpResponse::Ok().json(VersionsResponse {
+        versions: vec![
+            ApiVersion {
+                version: "v1".to_string(),
+                prefix: API_V1_PREFIX.to_string(),
+                status: "current".to_string(),
+                sunset: None,
+            },
+            ApiVersion {
+                version: "legacy".to_string(),
+                prefix: LEGACY_API_PREFIX.to_string(),
+                status: legacy_status.to_string(),
+                sunset: Some(LEGACY_SUNSET.to_string()),
+            },
+        ],
+    })
+}
+
+/// Capabilities endpoint
+///
+/// Lists the version, optional features and limits of this deployment so
+/// clients can check them before sending requests.
+pub async fn capabilities(data: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(data.config.capabilities())
+}
+
+/// Build a scope with every API route mounted under the given prefix
+fn api_scope(prefix: &str) -> Scope {
+    web::scope(prefix)
+        .route("/health", web::get().to(health_check))
+        .route("/ping", web::get().to(ping))
+        .route("/metrics", web::get().to(metrics))
+        .route("/covers", web::get().to(list_covers))
+        .route("/encode", web::post().to(encode))
+        .route("/decode", web::post().to(decode))
+        .route("/diff", web::post().to(diff))
+        .route("/capacity", web::post().to(capacity))
+        .route("/plan", web::post().to(plan))
+        .route("/images", web::get().to(list_images))
+        .route("/images/{image_id}", web::get().to(get_image))
+        .route("/messages/{message_id}", web::get().to(get_message))
+}
+
+/// Check whether the legacy routes are enabled for this application
+fn legacy_routes_enabled(ctx: &guard::GuardContext) -> bool {
+    ctx.app_data::<web::Data<AppState>>()
+        .map(|state| state.config.enable_legacy_routes)
+        .unwrap_or(true)
 }
 
 /// Configure all API routes
+///
+/// Routes are served under `/api/v1`. The same routes are also served under
+/// `/api` with deprecation headers unless `enable_legacy_routes` is off.
+/// The application must register the [`AppState`]; see [`crate::api::scope`]
+/// for mounting the API in another application instead.
 pub fn configure_routes(cfg: &mut web::ServiceConfig) {
-    cfg.service(
-        web::scope("/api")
-            .route("/health", web::get().to(health_check))
-            .route("/ping", web::get().to(ping))
-            .route("/encode", web::post().to(encode))
-            .route("/decode", web::post().to(decode))
-            .route("/images/{image_id}", web::get().to(get_image)),
-    );
+    register_routes(cfg, legacy_routes_enabled);
+}
+
+/// Register every route, serving the legacy ones when `legacy_enabled` allows
+pub(crate) fn register_routes(
+    cfg: &mut web::ServiceConfig,
+    legacy_enabled: impl Fn(&guard::GuardContext) -> bool + 'static,
+) {
+    cfg.route("/api/versions", web::get().to(versions))
+        .route("/api/capabilities", web::get().to(capabilities))
+        .service(api_scope(API_V1_PREFIX))
+        .service(
+            api_scope(LEGACY_API_PREFIX)
+                .guard(guard::fn_guard(legacy_enabled))
+                .wrap(
+                    DefaultHeaders::new()
+                        .add(("Deprecation", "true"))
+                        .add(("Sunset", LEGACY_SUNSET))
+                        .add((
+                            "Link",
+                            format!("<{}>; rel=\"successor-version\"", API_V1_PREFIX),
+                        )),
+                ),
+        );
 }
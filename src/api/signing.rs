@@ -0,0 +1,317 @@
+//! Signed, time-limited download links for stored images and decoded messages
+//!
+//! When the server has a URL signing secret, download URLs carry an expiry
+//! time and an HMAC-SHA256 over the image or message ID and that expiry, and
+//! downloads without a valid, unexpired signature are refused.
+
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+/// Query parameter carrying the expiry time, in seconds since the Unix epoch
+pub const EXPIRES_PARAM: &str = "expires";
+
+/// Query parameter carrying the hex-encoded signature
+pub const SIGNATURE_PARAM: &str = "sig";
+
+/// How long links stay valid after their expiry, to allow for clock skew
+/// between the server that signed a link and the one checking it
+pub const DEFAULT_CLOCK_LEEWAY: Duration = Duration::from_secs(30);
+
+/// Why a download link was refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureError {
+    /// The link has no expiry or signature
+    Missing,
+
+    /// The signature does not match the ID and expiry
+    Invalid,
+
+    /// The signature is valid but the link has expired
+    Expired,
+}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Missing => "Download links must be signed",
+            Self::Invalid => "Invalid download link signature",
+            Self::Expired => "Download link has expired",
+        })
+    }
+}
+
+/// The signature query parameters of a download request
+///
+/// Both are kept as text so a malformed value is refused like a wrong one.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SignedQuery {
+    /// Expiry time, in seconds since the Unix epoch
+    pub expires: Option<String>,
+
+    /// Hex-encoded signature
+    pub sig: Option<String>,
+}
+
+/// Signs and checks download links
+#[derive(Clone, PartialEq, Eq)]
+pub struct UrlSigner {
+    /// HMAC key
+    secret: Vec<u8>,
+
+    /// How long signed links stay valid
+    ttl: Duration,
+
+    /// Grace period after expiry, for clock skew
+    leeway: Duration,
+}
+
+impl fmt::Debug for UrlSigner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UrlSigner")
+            .field("secret", &"[redacted]")
+            .field("ttl", &self.ttl)
+            .field("leeway", &self.leeway)
+            .finish()
+    }
+}
+
+impl UrlSigner {
+    /// Create a signer
+    ///
+    /// # Arguments
+    /// * `secret` - Key the signatures are computed with
+    /// * `ttl` - How long signed links stay valid
+    pub fn new(secret: impl Into<Vec<u8>>, ttl: Duration) -> Self {
+        Self {
+            secret: secret.into(),
+            ttl,
+            leeway: DEFAULT_CLOCK_LEEWAY,
+        }
+    }
+
+    /// Set how long links stay valid after their expiry
+    pub fn with_leeway(mut self, leeway: Duration) -> Self {
+        self.leeway = leeway;
+        self
+    }
+
+    /// Build the signature query string for a stored file, valid from now
+    pub fn sign(&self, id: &str) -> String {
+        self.sign_at(id, unix_now())
+    }
+
+    /// Build the signature query string for a stored file, valid from `now`
+    ///
+    /// # Arguments
+    /// * `id` - ID of the image or message the link downloads
+    /// * `now` - Current time, in seconds since the Unix epoch
+    ///
+    /// # Returns
+    /// * `expires=<unix>&sig=<hex>`, without a leading `?`
+    pub fn sign_at(&self, id: &str, now: u64) -> String {
+        let expires = now.saturating_add(self.ttl.as_secs());
+        format!(
+            "{}={}&{}={}",
+            EXPIRES_PARAM,
+            expires,
+            SIGNATURE_PARAM,
+            self.signature(id, expires)
+        )
+    }
+
+    /// Check the signature of a download request for a stored file
+    pub fn verify(&self, id: &str, query: &SignedQuery) -> Result<(), SignatureError> {
+        self.verify_at(id, query, unix_now())
+    }
+
+    /// Check the signature of a download request as of `now`
+    ///
+    /// # Arguments
+    /// * `id` - ID of the image or message requested
+    /// * `query` - The request's signature parameters
+    /// * `now` - Current time, in seconds since the Unix epoch
+    ///
+    /// # Returns
+    /// * `Ok(())`, or why the request is refused; a tampered link is
+    ///   reported as invalid even once it has expired
+    pub fn verify_at(&self, id: &str, query: &SignedQuery, now: u64) -> Result<(), SignatureError> {
+        let (Some(expires), Some(sig)) = (&query.expires, &query.sig) else {
+            return Err(SignatureError::Missing);
+        };
+        let expires: u64 = expires.parse().map_err(|_| SignatureError::Invalid)?;
+        let sig = decode_hex(sig).ok_or(SignatureError::Invalid)?;
+
+        self.mac(id, expires)
+            .verify_slice(&sig)
+            .map_err(|_| SignatureError::Invalid)?;
+
+        if now > expires.saturating_add(self.leeway.as_secs()) {
+            return Err(SignatureError::Expired);
+        }
+        Ok(())
+    }
+
+    /// Hex-encoded signature over an ID and expiry time
+    fn signature(&self, id: &str, expires: u64) -> String {
+        self.mac(id, expires)
+            .finalize()
+            .into_bytes()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// HMAC fed with an ID and expiry time
+    fn mac(&self, id: &str, expires: u64) -> Hmac<Sha256> {
+        // HMAC accepts keys of any length
+        #[allow(clippy::expect_used)]
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC takes keys of any length");
+        mac.update(format!("{}\n{}", id, expires).as_bytes());
+        mac
+    }
+}
+
+/// Current time in seconds since the Unix epoch
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// Decode a hex string, or `None` if it isn't one
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IMAGE_ID: &str = "650e8400-e29b-41d4-a716-446655440001";
+    const NOW: u64 = 1_700_000_000;
+
+    fn signer() -> UrlSigner {
+        UrlSigner::new("top secret", Duration::from_secs(600))
+    }
+
+    /// Parse a query string built by the signer
+    fn query(signed: &str) -> SignedQuery {
+        let mut query = SignedQuery::default();
+        for pair in signed.split('&') {
+            match pair.split_once('=') {
+                Some((EXPIRES_PARAM, value)) => query.expires = Some(value.to_string()),
+                Some((SIGNATURE_PARAM, value)) => query.sig = Some(value.to_string()),
+                _ => panic!("unexpected parameter {}", pair),
+            }
+        }
+        query
+    }
+
+    #[test]
+    fn test_signed_link_is_valid_until_it_expires() {
+        let signer = signer();
+        let signed = query(&signer.sign_at(IMAGE_ID, NOW));
+        assert_eq!(signed.expires.as_deref(), Some("1700000600"));
+
+        assert_eq!(signer.verify_at(IMAGE_ID, &signed, NOW), Ok(()));
+        assert_eq!(signer.verify_at(IMAGE_ID, &signed, NOW + 600), Ok(()));
+        assert_eq!(
+            signer.verify_at(IMAGE_ID, &signed, NOW + 600 + 31),
+            Err(SignatureError::Expired)
+        );
+    }
+
+    #[test]
+    fn test_clock_skew_is_tolerated_within_the_leeway() {
+        let signed = query(&signer().sign_at(IMAGE_ID, NOW));
+
+        // A checking server whose clock runs 30 seconds ahead still accepts the link
+        assert_eq!(signer().verify_at(IMAGE_ID, &signed, NOW + 630), Ok(()));
+
+        let strict = signer().with_leeway(Duration::ZERO);
+        assert_eq!(
+            strict.verify_at(IMAGE_ID, &signed, NOW + 601),
+            Err(SignatureError::Expired)
+        );
+    }
+
+    #[test]
+    fn test_tampered_links_are_invalid() {
+        let signer = signer();
+        let signed = query(&signer.sign_at(IMAGE_ID, NOW));
+
+        // Another image
+        assert_eq!(
+            signer.verify_at("650e8400-e29b-41d4-a716-446655440002", &signed, NOW),
+            Err(SignatureError::Invalid)
+        );
+
+        // A later expiry
+        let extended = SignedQuery {
+            expires: Some((NOW + 6000).to_string()),
+            ..signed.clone()
+        };
+        assert_eq!(
+            signer.verify_at(IMAGE_ID, &extended, NOW),
+            Err(SignatureError::Invalid)
+        );
+
+        // Malformed values, and another secret
+        for (expires, sig) in [("soon", signed.sig.clone()), ("1", Some("zz".into()))] {
+            let query = SignedQuery {
+                expires: Some(expires.to_string()),
+                sig,
+            };
+            assert_eq!(
+                signer.verify_at(IMAGE_ID, &query, NOW),
+                Err(SignatureError::Invalid)
+            );
+        }
+        let other = UrlSigner::new("other secret", Duration::from_secs(600));
+        assert_eq!(
+            other.verify_at(IMAGE_ID, &signed, NOW),
+            Err(SignatureError::Invalid)
+        );
+
+        // A tampered link stays invalid after it would have expired
+        assert_eq!(
+            signer.verify_at(IMAGE_ID, &extended, NOW + 100_000),
+            Err(SignatureError::Invalid)
+        );
+    }
+
+    #[test]
+    fn test_unsigned_links_are_refused() {
+        let signer = signer();
+        assert_eq!(
+            signer.verify_at(IMAGE_ID, &SignedQuery::default(), NOW),
+            Err(SignatureError::Missing)
+        );
+        let expires_only = SignedQuery {
+            expires: Some(NOW.to_string()),
+            sig: None,
+        };
+        assert_eq!(
+            signer.verify_at(IMAGE_ID, &expires_only, NOW),
+            Err(SignatureError::Missing)
+        );
+    }
+
+    #[test]
+    fn test_debug_hides_the_secret() {
+        assert!(!format!("{:?}", signer()).contains("top secret"));
+    }
+}
@@ -0,0 +1,567 @@
+//! Disk budget for the per-request upload spool
+//!
+//! Each request spools its uploads into its own directory under the upload
+//! directory until it finishes. [`SpoolManager`] keeps a running total of the
+//! bytes in those directories, adding as requests write and subtracting as
+//! they clean up, so requests never rescan the disk. Directories left behind
+//! by earlier processes are counted by a rescan at most every refresh
+//! interval.
+//!
+//! Once the total reaches the budget, a new request first triggers an
+//! emergency sweep that deletes leftover directories oldest first, and is
+//! refused if that does not free enough space. Directories of requests still
+//! in flight are never swept.
+//!
+//! Files that outlive their request, such as decoded messages stored for
+//! download, are kept in directories of their own with [`SpoolManager::keep`].
+//! They count against the budget like leftovers, and with a TTL every
+//! leftover directory is deleted at the first rescan after it expires.
+
+use log::{info, warn};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant, SystemTime};
+use uuid::Uuid;
+
+/// How often leftover directories are recounted from disk by default
+pub const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A request or write refused because the spool is over its budget
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpoolFull {
+    /// Bytes in the spool when the request was refused
+    pub used: u64,
+
+    /// The budget in bytes
+    pub budget: u64,
+}
+
+/// Why a file could not be kept in the spool
+#[derive(Debug)]
+pub enum KeepError {
+    /// The file would take the spool past its budget
+    Full(SpoolFull),
+
+    /// Writing the file failed
+    Io(io::Error),
+}
+
+/// Bytes in the spool, by who owns them
+#[derive(Debug, Default)]
+struct Usage {
+    /// Bytes written by each request in flight
+    active: HashMap<Uuid, u64>,
+
+    /// Sum of `active`
+    active_bytes: u64,
+
+    /// Bytes in directories no request in flight owns, as of the last scan
+    leftover_bytes: u64,
+
+    /// When leftover directories were last counted
+    scanned_at: Option<Instant>,
+}
+
+impl Usage {
+    /// Total bytes in the spool
+    fn total(&self) -> u64 {
+        self.active_bytes + self.leftover_bytes
+    }
+}
+
+/// A request directory no request in flight owns
+#[derive(Debug)]
+struct Leftover {
+    /// Path of the directory
+    path: PathBuf,
+
+    /// Bytes of the files in it
+    bytes: u64,
+
+    /// When it was last modified
+    modified: SystemTime,
+}
+
+/// Tracks and limits the disk used by request directories
+///
+/// A budget of 0 disables the limit; usage is still tracked for requests
+/// in flight. A TTL of 0 keeps leftover directories until they are swept
+/// for space.
+#[derive(Debug)]
+pub struct SpoolManager {
+    /// Directory holding the request directories
+    root: PathBuf,
+
+    /// Most bytes the spool may hold
+    budget: u64,
+
+    /// How long a count of leftover directories is trusted
+    refresh_interval: Duration,
+
+    /// How long leftover directories are kept after their last change
+    ttl: Duration,
+
+    /// Current usage
+    usage: Mutex<Usage>,
+}
+
+impl SpoolManager {
+    /// Create a manager for request directories under `root`
+    pub fn new(root: impl Into<PathBuf>, budget: u64) -> Self {
+        Self::with_refresh_interval(root, budget, DEFAULT_REFRESH_INTERVAL)
+    }
+
+    /// Create a manager that recounts leftover directories at the given interval
+    pub fn with_refresh_interval(
+        root: impl Into<PathBuf>,
+        budget: u64,
+        refresh_interval: Duration,
+    ) -> Self {
+        Self {
+            root: root.into(),
+            budget,
+            refresh_interval,
+            ttl: Duration::ZERO,
+            usage: Mutex::new(Usage::default()),
+        }
+    }
+
+    /// Delete leftover directories, and the files kept in them, once they are older than `ttl`
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Directory holding the request directories
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Bytes currently accounted to the spool
+    pub fn used_bytes(&self) -> u64 {
+        self.lock().total()
+    }
+
+    /// Number of requests in flight
+    pub fn active_requests(&self) -> usize {
+        self.lock().active.len()
+    }
+
+    /// Admit a new request before its directory is created
+    ///
+    /// When the spool is at its budget, leftover directories are swept oldest
+    /// first before giving up.
+    ///
+    /// # Arguments
+    /// * `request_id` - ID of the request, which names its directory
+    ///
+    /// # Returns
+    /// * `SpoolFull` if the spool is still at its budget after the sweep
+    pub fn admit(&self, request_id: Uuid) -> Result<(), SpoolFull> {
+        let mut usage = self.lock();
+        self.refresh(&mut usage);
+
+        if self.budget > 0 {
+            // A new request needs room for at least one byte
+            if usage.total() >= self.budget {
+                self.sweep(&mut usage, 1);
+            }
+            if usage.total() >= self.budget {
+                return Err(self.full(&usage));
+            }
+        }
+
+        usage.active.entry(request_id).or_insert(0);
+        Ok(())
+    }
+
+    /// Store a file that outlives the request writing it
+    ///
+    /// The file goes in a directory of its own named by `id`, which then
+    /// counts as a leftover: it is swept oldest first when the spool is over
+    /// its budget, and deleted once it outlives the TTL.
+    ///
+    /// # Arguments
+    /// * `id` - ID naming the file's directory
+    /// * `file_name` - Name of the file in that directory
+    /// * `data` - Contents of the file
+    ///
+    /// # Returns
+    /// * The path of the stored file, or why it could not be stored
+    pub fn keep(&self, id: Uuid, file_name: &str, data: &[u8]) -> Result<PathBuf, KeepError> {
+        let bytes = data.len() as u64;
+        {
+            let mut usage = self.lock();
+            self.refresh(&mut usage);
+            if self.budget > 0 && usage.total().saturating_add(bytes) > self.budget {
+                self.sweep(&mut usage, bytes);
+                if usage.total().saturating_add(bytes) > self.budget {
+                    return Err(KeepError::Full(self.full(&usage)));
+                }
+            }
+
+            // Counted as in flight while writing, so a rescan can't count it twice
+            *usage.active.entry(id).or_insert(0) += bytes;
+            usage.active_bytes += bytes;
+        }
+
+        let dir = self.root.join(id.to_string());
+        let path = dir.join(file_name);
+        if let Err(e) = fs::create_dir_all(&dir).and_then(|()| fs::write(&path, data)) {
+            if let Err(e) = fs::remove_dir_all(&dir) {
+                warn!("Failed to remove spool directory {}: {}", dir.display(), e);
+            }
+            self.release(id);
+            return Err(KeepError::Io(e));
+        }
+
+        let mut usage = self.lock();
+        if let Some(bytes) = usage.active.remove(&id) {
+            usage.active_bytes -= bytes;
+            usage.leftover_bytes += bytes;
+        }
+        Ok(path)
+    }
+
+    /// Path of a file stored with [`keep`](Self::keep), if it is still there and hasn't expired
+    ///
+    /// Directories of requests in flight are never given out.
+    pub fn kept_file(&self, id: Uuid, file_name: &str) -> Option<PathBuf> {
+        if self.lock().active.contains_key(&id) {
+            return None;
+        }
+
+        let path = self.root.join(id.to_string()).join(file_name);
+        let modified = fs::metadata(&path).ok()?.modified().ok()?;
+        (!self.is_expired(modified)).then_some(path)
+    }
+
+    /// Account for bytes a request is about to write
+    ///
+    /// # Arguments
+    /// * `request_id` - ID of the request writing
+    /// * `bytes` - Number of bytes about to be written
+    ///
+    /// # Returns
+    /// * `SpoolFull` if the write would take the spool past its budget
+    pub fn reserve(&self, request_id: Uuid, bytes: u64) -> Result<(), SpoolFull> {
+        let mut usage = self.lock();
+        if self.budget > 0 && usage.total().saturating_add(bytes) > self.budget {
+            return Err(self.full(&usage));
+        }
+
+        *usage.active.entry(request_id).or_insert(0) += bytes;
+        usage.active_bytes += bytes;
+        Ok(())
+    }
+
+    /// Stop accounting for a request once its directory is removed
+    ///
+    /// If the removal failed, the next rescan counts the directory as a leftover.
+    pub fn release(&self, request_id: Uuid) {
+        let mut usage = self.lock();
+        if let Some(bytes) = usage.active.remove(&request_id) {
+            usage.active_bytes -= bytes;
+        }
+    }
+
+    /// Recount leftover directories if the last count is too old, deleting expired ones
+    ///
+    /// Unlimited spools without a TTL have nothing to count or delete, so they never scan.
+    fn refresh(&self, usage: &mut Usage) {
+        if self.budget == 0 && self.ttl.is_zero() {
+            return;
+        }
+        let stale = usage
+            .scanned_at
+            .is_none_or(|at| at.elapsed() >= self.refresh_interval);
+        if stale {
+            usage.leftover_bytes = self.leftovers(usage).iter().map(|dir| dir.bytes).sum();
+            usage.scanned_at = Some(Instant::now());
+        }
+    }
+
+    /// Delete leftover directories oldest first until `incoming` more bytes fit within budget
+    fn sweep(&self, usage: &mut Usage, incoming: u64) {
+        let mut leftovers = self.leftovers(usage);
+        usage.leftover_bytes = leftovers.iter().map(|dir| dir.bytes).sum();
+        usage.scanned_at = Some(Instant::now());
+
+        leftovers.sort_by_key(|dir| dir.modified);
+        for dir in leftovers {
+            if usage.total().saturating_add(incoming) <= self.budget {
+                break;
+            }
+
+            if self.remove(&dir, "Swept") {
+                usage.leftover_bytes = usage.leftover_bytes.saturating_sub(dir.bytes);
+            }
+        }
+    }
+
+    /// Delete a leftover directory, logging the outcome
+    ///
+    /// # Returns
+    /// * Whether the directory was deleted
+    fn remove(&self, dir: &Leftover, action: &str) -> bool {
+        match fs::remove_dir_all(&dir.path) {
+            Ok(()) => {
+                info!(
+                    "{} spool directory {} ({} bytes)",
+                    action,
+                    dir.path.display(),
+                    dir.bytes
+                );
+                true
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to delete spool directory {}: {}",
+                    dir.path.display(),
+                    e
+                );
+                false
+            }
+        }
+    }
+
+    /// Whether a leftover last changed at `modified` has outlived the TTL
+    fn is_expired(&self, modified: SystemTime) -> bool {
+        !self.ttl.is_zero() && modified.elapsed().is_ok_and(|age| age >= self.ttl)
+    }
+
+    /// List request directories that no request in flight owns, deleting expired ones
+    fn leftovers(&self, usage: &Usage) -> Vec<Leftover> {
+        let Ok(entries) = fs::read_dir(&self.root) else {
+            return Vec::new();
+        };
+
+        entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let request_id = Uuid::parse_str(entry.file_name().to_str()?).ok()?;
+                let metadata = entry.metadata().ok()?;
+                if !metadata.is_dir() || usage.active.contains_key(&request_id) {
+                    return None;
+                }
+
+                Some(Leftover {
+                    bytes: dir_size(&entry.path()),
+                    path: entry.path(),
+                    modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                })
+            })
+            .filter(|dir| !(self.is_expired(dir.modified) && self.remove(dir, "Expired")))
+            .collect()
+    }
+
+    /// The error for a refused request or write
+    fn full(&self, usage: &Usage) -> SpoolFull {
+        SpoolFull {
+            used: usage.total(),
+            budget: self.budget,
+        }
+    }
+
+    /// Lock the usage, recovering it if a holder panicked
+    fn lock(&self) -> MutexGuard<'_, Usage> {
+        self.usage.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Total size of the files in a directory tree, skipping unreadable entries
+fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(path) else {
+        return 0;
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| match entry.metadata() {
+            Ok(metadata) if metadata.is_dir() => dir_size(&entry.path()),
+            Ok(metadata) => metadata.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::FileTimes;
+    use std::sync::Arc;
+    use std::thread;
+    use tempfile::tempdir;
+
+    /// Leave a request directory behind, last modified `age_secs` ago
+    fn leftover(root: &Path, bytes: usize, age_secs: u64) -> PathBuf {
+        let dir = root.join(Uuid::new_v4().to_string());
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join("cover.png"), vec![0u8; bytes]).unwrap();
+
+        let modified = SystemTime::now() - Duration::from_secs(age_secs);
+        fs::File::open(&dir)
+            .unwrap()
+            .set_times(FileTimes::new().set_modified(modified))
+            .unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_concurrent_requests_stay_within_budget() {
+        let root = tempdir().unwrap();
+        let spool = Arc::new(SpoolManager::new(root.path(), 1000));
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let spool = Arc::clone(&spool);
+                thread::spawn(move || {
+                    let request_id = Uuid::new_v4();
+                    if spool.admit(request_id).is_err() {
+                        return 0;
+                    }
+
+                    // Write in chunks until refused
+                    let mut written = 0;
+                    while written < 400 && spool.reserve(request_id, 100).is_ok() {
+                        written += 100;
+                        assert!(spool.used_bytes() <= 1000);
+                    }
+                    thread::yield_now();
+                    spool.release(request_id);
+                    written
+                })
+            })
+            .collect();
+
+        let written: u64 = handles.into_iter().map(|h| h.join().unwrap()).sum();
+        assert!(written > 0);
+        assert_eq!(spool.used_bytes(), 0);
+        assert_eq!(spool.active_requests(), 0);
+    }
+
+    #[test]
+    fn test_full_spool_refuses_new_requests() {
+        let root = tempdir().unwrap();
+        let spool = SpoolManager::new(root.path(), 500);
+        let first = Uuid::new_v4();
+
+        spool.admit(first).unwrap();
+        spool.reserve(first, 400).unwrap();
+        assert_eq!(
+            spool.reserve(first, 200),
+            Err(SpoolFull {
+                used: 400,
+                budget: 500
+            })
+        );
+        spool.reserve(first, 100).unwrap();
+
+        // Requests in flight are never swept
+        assert!(spool.admit(Uuid::new_v4()).is_err());
+
+        spool.release(first);
+        assert!(spool.admit(Uuid::new_v4()).is_ok());
+    }
+
+    #[test]
+    fn test_sweep_removes_oldest_leftovers_first() {
+        let root = tempdir().unwrap();
+        let oldest = leftover(root.path(), 300, 300);
+        let older = leftover(root.path(), 300, 200);
+        let newest = leftover(root.path(), 300, 100);
+        fs::write(root.path().join("stored.png"), vec![0u8; 300]).unwrap();
+
+        let spool = SpoolManager::new(root.path(), 700);
+        spool.admit(Uuid::new_v4()).unwrap();
+
+        // Only the oldest had to go to get back under budget
+        assert!(!oldest.exists());
+        assert!(older.exists());
+        assert!(newest.exists());
+        assert_eq!(spool.used_bytes(), 600);
+    }
+
+    #[test]
+    fn test_leftovers_are_recounted_after_the_refresh_interval() {
+        let root = tempdir().unwrap();
+        let spool = SpoolManager::with_refresh_interval(root.path(), 10_000, Duration::ZERO);
+
+        spool.admit(Uuid::new_v4()).unwrap();
+        assert_eq!(spool.used_bytes(), 0);
+
+        leftover(root.path(), 250, 0);
+        spool.admit(Uuid::new_v4()).unwrap();
+        assert_eq!(spool.used_bytes(), 250);
+
+        // An unlimited spool never scans
+        let unlimited = SpoolManager::new(root.path(), 0);
+        unlimited.admit(Uuid::new_v4()).unwrap();
+        assert_eq!(unlimited.used_bytes(), 0);
+    }
+
+    #[test]
+    fn test_kept_files_count_against_the_budget() {
+        let root = tempdir().unwrap();
+        let older = leftover(root.path(), 300, 100);
+        let spool = SpoolManager::new(root.path(), 1000);
+
+        let first = Uuid::new_v4();
+        let path = spool.keep(first, "message.bin", &[1; 400]).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), vec![1; 400]);
+        assert_eq!(spool.kept_file(first, "message.bin"), Some(path));
+        assert_eq!(spool.used_bytes(), 700);
+        assert_eq!(spool.active_requests(), 0);
+
+        // Making room sweeps the oldest leftover, then refuses what still can't fit
+        spool
+            .keep(Uuid::new_v4(), "message.bin", &[2; 500])
+            .unwrap();
+        assert!(!older.exists());
+        assert_eq!(spool.used_bytes(), 900);
+        assert!(matches!(
+            spool.keep(Uuid::new_v4(), "message.bin", &[3; 1001]),
+            Err(KeepError::Full(SpoolFull { budget: 1000, .. }))
+        ));
+        assert_eq!(spool.kept_file(Uuid::new_v4(), "message.bin"), None);
+    }
+
+    #[test]
+    fn test_sweep_stops_once_a_file_fits_exactly() {
+        let root = tempdir().unwrap();
+        let oldest = leftover(root.path(), 300, 200);
+        let newest = leftover(root.path(), 300, 100);
+        let spool = SpoolManager::new(root.path(), 1000);
+
+        // Filling the spool to exactly its budget is allowed, so one sweep is enough
+        spool.keep(Uuid::new_v4(), "message.bin", &[1; 700]).unwrap();
+        assert!(!oldest.exists());
+        assert!(newest.exists());
+        assert_eq!(spool.used_bytes(), 1000);
+    }
+
+    #[test]
+    fn test_expired_leftovers_are_deleted() {
+        let root = tempdir().unwrap();
+        let expired = leftover(root.path(), 100, 120);
+        let fresh = leftover(root.path(), 100, 0);
+        let spool = SpoolManager::new(root.path(), 0).with_ttl(Duration::from_secs(60));
+
+        let kept = Uuid::new_v4();
+        spool.keep(kept, "message.bin", b"message").unwrap();
+        assert!(!expired.exists());
+        assert!(fresh.exists());
+        assert!(spool.kept_file(kept, "message.bin").is_some());
+
+        // Expired files are refused even before the next rescan deletes them
+        let dir = root.path().join(kept.to_string());
+        fs::File::open(dir.join("message.bin"))
+            .unwrap()
+            .set_times(FileTimes::new().set_modified(SystemTime::now() - Duration::from_secs(120)))
+            .unwrap();
+        assert_eq!(spool.kept_file(kept, "message.bin"), None);
+    }
+}
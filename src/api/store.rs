@@ -0,0 +1,444 @@
+//! Metadata about the images stored by the server, and paging through it
+//!
+//! Listings are ordered newest first, with the image ID breaking ties, and are
+//! paged with an opaque cursor holding the last `(created_at, image_id)` pair
+//! returned. Each page continues strictly after that pair, so deleting images
+//! between requests never repeats or skips a remaining image. Images stored
+//! after a walk started appear only if they sort after its cursor.
+//!
+//! The SHA-256 of each stored image is recorded next to it when it is
+//! written, so downloads can be checked against it.
+
+use crate::error::HideError;
+use crate::manifest::sha256_hex;
+use crate::Result;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL, Engine as _};
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{PoisonError, RwLock};
+use uuid::Uuid;
+
+/// Page size used when a request doesn't give one
+pub const DEFAULT_PAGE_LIMIT: usize = 20;
+
+/// Largest page size; larger requests are clamped to it
+pub const MAX_PAGE_LIMIT: usize = 100;
+
+/// Response header carrying the SHA-256 of a downloaded image
+pub const CONTENT_SHA256_HEADER: &str = "X-Content-Sha256";
+
+/// Extension of the file recording a stored image's SHA-256
+pub(crate) const CONTENT_HASH_EXTENSION: &str = "sha256";
+
+/// Record the SHA-256 of a stored image next to it
+///
+/// # Arguments
+/// * `image_path` - Path of the stored image
+///
+/// # Returns
+/// * The hex-encoded SHA-256 of the image file
+pub fn record_content_hash(image_path: &Path) -> Result<String> {
+    let hash = sha256_hex(&fs::read(image_path)?);
+    fs::write(image_path.with_extension(CONTENT_HASH_EXTENSION), &hash)?;
+    Ok(hash)
+}
+
+/// Read the SHA-256 recorded for a stored image
+///
+/// # Arguments
+/// * `image_path` - Path of the stored image
+///
+/// # Returns
+/// * The hex-encoded hash, or `None` for images stored before hashes were recorded
+pub fn recorded_content_hash(image_path: &Path) -> Result<Option<String>> {
+    match fs::read_to_string(image_path.with_extension(CONTENT_HASH_EXTENSION)) {
+        Ok(hash) => Ok(Some(hash.trim().to_string())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Metadata kept for a stored image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageRecord {
+    /// ID the image is served under
+    pub image_id: Uuid,
+
+    /// When the image was stored
+    pub created_at: DateTime<Utc>,
+}
+
+impl ImageRecord {
+    /// Sort key; listings run from the largest key to the smallest
+    fn key(&self) -> (DateTime<Utc>, Uuid) {
+        (self.created_at, self.image_id)
+    }
+}
+
+/// Position in a listing, just after the last record of a page
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+    /// Creation time of the last record returned
+    pub created_at: DateTime<Utc>,
+
+    /// ID of the last record returned
+    pub image_id: Uuid,
+}
+
+impl Cursor {
+    /// The cursor pointing just after a record
+    pub fn after(record: &ImageRecord) -> Self {
+        Self {
+            created_at: record.created_at,
+            image_id: record.image_id,
+        }
+    }
+
+    /// Encode the cursor as an opaque URL-safe token
+    pub fn encode(&self) -> String {
+        let raw = format!(
+            "{}|{}",
+            self.created_at.to_rfc3339_opts(SecondsFormat::Nanos, true),
+            self.image_id
+        );
+        BASE64_URL.encode(raw)
+    }
+
+    /// Decode a token produced by [`Cursor::encode`]
+    ///
+    /// # Returns
+    /// * The cursor, or `InvalidParameters` if the token is malformed
+    pub fn decode(token: &str) -> Result<Self> {
+        let invalid = || HideError::InvalidParameters("Invalid cursor".to_string());
+
+        let raw = BASE64_URL.decode(token).map_err(|_| invalid())?;
+        let raw = String::from_utf8(raw).map_err(|_| invalid())?;
+        let (created_at, image_id) = raw.split_once('|').ok_or_else(invalid)?;
+
+        Ok(Self {
+            created_at: DateTime::parse_from_rfc3339(created_at)
+                .map_err(|_| invalid())?
+                .with_timezone(&Utc),
+            image_id: Uuid::parse_str(image_id).map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// One page of a listing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Page {
+    /// Records on this page, newest first
+    pub records: Vec<ImageRecord>,
+
+    /// Cursor for the next page, if there is one
+    pub next_cursor: Option<Cursor>,
+
+    /// Whether more records follow this page
+    pub has_more: bool,
+
+    /// Number of records in the whole listing, when the store can count them cheaply
+    pub total_count: Option<usize>,
+}
+
+/// A store of image metadata that can be listed page by page
+///
+/// Stores only provide their records; ordering and paging are shared by
+/// [`paginate`] so every backend pages identically.
+pub trait MetadataStore: Send + Sync {
+    /// Every record in the store, in any order
+    fn records(&self) -> Result<Vec<ImageRecord>>;
+
+    /// Read one page of the listing
+    ///
+    /// # Arguments
+    /// * `cursor` - Where the previous page ended, or `None` for the first page
+    /// * `limit` - Page size, clamped to `1..=MAX_PAGE_LIMIT`
+    ///
+    /// # Returns
+    /// * The page, with `total_count` set since every record was loaded
+    fn page(&self, cursor: Option<&Cursor>, limit: usize) -> Result<Page> {
+        let records = self.records()?;
+        let total_count = records.len();
+        Ok(Page {
+            total_count: Some(total_count),
+            ..paginate(records, cursor, limit)
+        })
+    }
+}
+
+/// Order records and cut out the page following a cursor
+///
+/// # Arguments
+/// * `records` - The records to page through, in any order
+/// * `cursor` - Where the previous page ended, or `None` for the first page
+/// * `limit` - Page size, clamped to `1..=MAX_PAGE_LIMIT`
+///
+/// # Returns
+/// * The page, without a `total_count`
+pub fn paginate(mut records: Vec<ImageRecord>, cursor: Option<&Cursor>, limit: usize) -> Page {
+    let limit = limit.clamp(1, MAX_PAGE_LIMIT);
+
+    records.sort_unstable_by_key(|record| std::cmp::Reverse(record.key()));
+    if let Some(cursor) = cursor {
+        let after = (cursor.created_at, cursor.image_id);
+        records.retain(|record| record.key() < after);
+    }
+
+    let has_more = records.len() > limit;
+    records.truncate(limit);
+    let next_cursor = if has_more {
+        records.last().map(Cursor::after)
+    } else {
+        None
+    };
+
+    Page {
+        records,
+        next_cursor,
+        has_more,
+        total_count: None,
+    }
+}
+
+/// Metadata read from the encoded images in the upload directory
+///
+/// Each `<image_id>.png` file, or `<image_id>.jpg` from the experimental
+/// jpeg-dct algorithm, is a record, created when the file was last modified.
+#[derive(Debug, Clone)]
+pub struct FsMetadataStore {
+    /// Directory the images are written to
+    dir: PathBuf,
+}
+
+impl FsMetadataStore {
+    /// Create a store over a directory
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl MetadataStore for FsMetadataStore {
+    fn records(&self) -> Result<Vec<ImageRecord>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            // Nothing has been stored yet
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut records = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if !matches!(
+                path.extension().and_then(|ext| ext.to_str()),
+                Some("png" | "jpg")
+            ) {
+                continue;
+            }
+            let Some(image_id) = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| Uuid::parse_str(stem).ok())
+            else {
+                continue;
+            };
+
+            // The file may have been deleted since the directory was read
+            let Ok(modified) = fs::metadata(&path).and_then(|meta| meta.modified()) else {
+                continue;
+            };
+            records.push(ImageRecord {
+                image_id,
+                created_at: modified.into(),
+            });
+        }
+
+        Ok(records)
+    }
+}
+
+/// Metadata held in memory, for tests and embedding
+#[derive(Debug, Default)]
+pub struct MemoryMetadataStore {
+    /// The stored records
+    records: RwLock<Vec<ImageRecord>>,
+}
+
+impl MemoryMetadataStore {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a record
+    pub fn insert(&self, record: ImageRecord) {
+        self.records
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(record);
+    }
+
+    /// Remove a record, returning whether it was present
+    pub fn remove(&self, image_id: Uuid) -> bool {
+        let mut records = self.records.write().unwrap_or_else(PoisonError::into_inner);
+        let before = records.len();
+        records.retain(|record| record.image_id != image_id);
+        records.len() != before
+    }
+}
+
+impl MetadataStore for MemoryMetadataStore {
+    fn records(&self) -> Result<Vec<ImageRecord>> {
+        Ok(self
+            .records
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    /// 50 records over 10 distinct timestamps, so ties are common
+    fn seeded_store() -> MemoryMetadataStore {
+        let mut rng = StdRng::seed_from_u64(1957);
+        let store = MemoryMetadataStore::new();
+        for _ in 0..50 {
+            let mut id = [0u8; 16];
+            rng.fill(&mut id);
+            store.insert(ImageRecord {
+                image_id: uuid::Builder::from_random_bytes(id).into_uuid(),
+                created_at: Utc
+                    .timestamp_opt(1_700_000_000 + rng.random_range(0..10), 0)
+                    .unwrap(),
+            });
+        }
+        store
+    }
+
+    fn walk(store: &dyn MetadataStore, limit: usize) -> Vec<ImageRecord> {
+        let mut seen = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = store.page(cursor.as_ref(), limit).unwrap();
+            seen.extend(page.records);
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => return seen,
+            }
+        }
+    }
+
+    #[test]
+    fn test_walk_returns_every_record_in_order() {
+        let store = seeded_store();
+        for limit in [1, 7, 10, 50, 1000] {
+            let seen = walk(&store, limit);
+            assert_eq!(seen.len(), 50, "limit {}", limit);
+            assert!(seen.windows(2).all(|pair| pair[0].key() > pair[1].key()));
+        }
+    }
+
+    #[test]
+    fn test_page_flags_and_limits() {
+        let store = seeded_store();
+
+        let first = store.page(None, 20).unwrap();
+        assert_eq!(first.records.len(), 20);
+        assert!(first.has_more);
+        assert_eq!(first.total_count, Some(50));
+        assert_eq!(first.next_cursor, Some(Cursor::after(&first.records[19])));
+
+        let last = store.page(None, 50).unwrap();
+        assert!(!last.has_more);
+        assert!(last.next_cursor.is_none());
+
+        // Out-of-range limits are clamped
+        assert_eq!(store.page(None, 0).unwrap().records.len(), 1);
+        assert_eq!(store.page(None, 1000).unwrap().records.len(), 50);
+        assert_eq!(
+            paginate(vec![ImageRecord::clone(&last.records[0]); 200], None, 1000)
+                .records
+                .len(),
+            MAX_PAGE_LIMIT
+        );
+    }
+
+    #[test]
+    fn test_deletions_between_pages_cause_no_duplicates() {
+        let store = seeded_store();
+        let first = store.page(None, 10).unwrap();
+
+        // Delete the last record returned and one not yet returned
+        let second_preview = store.page(first.next_cursor.as_ref(), 10).unwrap();
+        assert!(store.remove(first.records[9].image_id));
+        assert!(store.remove(second_preview.records[0].image_id));
+
+        let mut seen: Vec<_> = first.records.clone();
+        let mut cursor = first.next_cursor;
+        while let Some(next) = cursor {
+            let page = store.page(Some(&next), 10).unwrap();
+            seen.extend(page.records);
+            cursor = page.next_cursor;
+        }
+
+        let mut ids: Vec<_> = seen.iter().map(|record| record.image_id).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), seen.len());
+        // Only the record deleted before it was reached is missing
+        assert_eq!(seen.len(), 49);
+    }
+
+    #[test]
+    fn test_cursor_round_trip() {
+        let cursor = Cursor {
+            created_at: Utc.timestamp_opt(1_700_000_000, 123_456_789).unwrap(),
+            image_id: Uuid::new_v4(),
+        };
+        assert_eq!(Cursor::decode(&cursor.encode()).unwrap(), cursor);
+
+        for token in ["", "not base64!", &BASE64_URL.encode("no separator")] {
+            assert!(Cursor::decode(token).is_err(), "{:?}", token);
+        }
+    }
+
+    #[test]
+    fn test_fs_store_lists_encoded_images() {
+        let dir = tempfile::tempdir().unwrap();
+        let id = Uuid::new_v4();
+        fs::write(dir.path().join(format!("{}.png", id)), b"png").unwrap();
+        fs::write(dir.path().join(format!("{}.bin", Uuid::new_v4())), b"msg").unwrap();
+        fs::write(dir.path().join("notes.png"), b"png").unwrap();
+        fs::create_dir(dir.path().join(Uuid::new_v4().to_string())).unwrap();
+
+        let records = FsMetadataStore::new(dir.path()).records().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].image_id, id);
+
+        let missing = FsMetadataStore::new(dir.path().join("missing"));
+        assert!(missing.records().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_content_hash_is_recorded_beside_the_image() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(format!("{}.png", Uuid::new_v4()));
+        fs::write(&path, b"png").unwrap();
+        assert_eq!(recorded_content_hash(&path).unwrap(), None);
+
+        let hash = record_content_hash(&path).unwrap();
+        assert_eq!(hash, sha256_hex(b"png"));
+        assert_eq!(recorded_content_hash(&path).unwrap(), Some(hash));
+
+        // The hash file is not listed as an image
+        assert_eq!(FsMetadataStore::new(dir.path()).records().unwrap().len(), 1);
+    }
+}
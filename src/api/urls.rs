@@ -0,0 +1,293 @@
+//! Building the URLs handed out to API clients
+
+use crate::api::routes::API_V1_PREFIX;
+use crate::api::signing::UrlSigner;
+use actix_web::http::header;
+use actix_web::HttpRequest;
+use std::net::SocketAddr;
+use uuid::Uuid;
+
+/// Header carrying the path prefix added by a reverse proxy
+const FORWARDED_PREFIX: &str = "X-Forwarded-Prefix";
+
+/// Header carrying the scheme the client used to reach a reverse proxy
+const FORWARDED_PROTO: &str = "X-Forwarded-Proto";
+
+/// Maps stored image IDs to the URLs clients use to download them
+///
+/// URLs are relative by default. A configured public base URL always wins;
+/// otherwise, when proxies are trusted, the forwarded prefix, scheme and
+/// `Host` header of the request are used. With a signer, image and message
+/// download URLs carry an expiry and a signature.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UrlBuilder {
+    /// External base URL, without a trailing slash
+    public_base_url: Option<String>,
+
+    /// Whether forwarding headers from a reverse proxy are honored
+    trust_proxy: bool,
+
+    /// Path the API is mounted under in its application, without a trailing slash
+    mount_path: String,
+
+    /// Signs download URLs, if they must be signed
+    signer: Option<UrlSigner>,
+}
+
+impl UrlBuilder {
+    /// Create a URL builder
+    ///
+    /// # Arguments
+    /// * `public_base_url` - External base URL such as `https://example.com/hide`
+    /// * `trust_proxy` - Whether to honor `X-Forwarded-*` and `Host` headers
+    pub fn new(public_base_url: Option<String>, trust_proxy: bool) -> Self {
+        let public_base_url = public_base_url
+            .map(|url| url.trim().trim_end_matches('/').to_string())
+            .filter(|url| !url.is_empty());
+
+        Self {
+            public_base_url,
+            trust_proxy,
+            mount_path: String::new(),
+            signer: None,
+        }
+    }
+
+    /// Build URLs for an API mounted under a path of its application
+    ///
+    /// # Arguments
+    /// * `mount_path` - Path such as `/hide`; empty for the application root
+    pub fn with_mount_path(mut self, mount_path: &str) -> Self {
+        self.mount_path = mount_path.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Sign the download URLs this builds
+    pub fn with_signer(mut self, signer: UrlSigner) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    /// The signer downloads are checked with, if they are signed
+    pub fn signer(&self) -> Option<&UrlSigner> {
+        self.signer.as_ref()
+    }
+
+    /// Build the download URL of a stored image, signed if a signer is set
+    pub fn image_url(&self, req: &HttpRequest, image_id: Uuid) -> String {
+        let url = self.url(req, &format!("{}/images/{}", API_V1_PREFIX, image_id));
+        self.signed(url, image_id)
+    }
+
+    /// Build the download URL of a stored decoded message, signed if a signer is set
+    pub fn message_url(&self, req: &HttpRequest, message_id: Uuid) -> String {
+        let url = self.url(req, &format!("{}/messages/{}", API_V1_PREFIX, message_id));
+        self.signed(url, message_id)
+    }
+
+    /// Add the signature of a stored file's ID to its download URL, if a signer is set
+    fn signed(&self, url: String, id: Uuid) -> String {
+        match &self.signer {
+            Some(signer) => format!("{}?{}", url, signer.sign(&id.to_string())),
+            None => url,
+        }
+    }
+
+    /// Build the URL clients should use for an API path
+    ///
+    /// # Arguments
+    /// * `req` - The request being answered
+    /// * `path` - Absolute path of the API route, before the mount path
+    pub fn url(&self, req: &HttpRequest, path: &str) -> String {
+        let path = &format!("{}{}", self.mount_path, path);
+        if let Some(base) = &self.public_base_url {
+            return format!("{}{}", base, path);
+        }
+
+        if !self.trust_proxy {
+            return path.to_string();
+        }
+
+        let prefix = forwarded_prefix(req);
+        match header_value(req, header::HOST.as_str()) {
+            Some(host) => {
+                let scheme = header_value(req, FORWARDED_PROTO)
+                    .unwrap_or_else(|| req.connection_info().scheme().to_string());
+                format!("{}://{}{}{}", scheme, host, prefix, path)
+            }
+            None => format!("{}{}", prefix, path),
+        }
+    }
+
+    /// Get the IP address of the client making a request
+    ///
+    /// When proxies are trusted, the address they forward is used, since
+    /// every request reaches the server from the proxy's own address.
+    pub fn client_ip(&self, req: &HttpRequest) -> String {
+        let peer = req.peer_addr().map(|addr| addr.ip().to_string());
+        if !self.trust_proxy {
+            return peer.unwrap_or_default();
+        }
+
+        let info = req.connection_info();
+        match info.realip_remote_addr() {
+            // Without forwarding headers this is the peer address with its port
+            Some(addr) => addr
+                .parse::<SocketAddr>()
+                .map(|addr| addr.ip().to_string())
+                .unwrap_or_else(|_| addr.to_string()),
+            None => peer.unwrap_or_default(),
+        }
+    }
+}
+
+/// Get the first value of a possibly comma-separated header
+fn header_value(req: &HttpRequest, name: &str) -> Option<String> {
+    req.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(',').next())
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+}
+
+/// Get the forwarded path prefix, normalized to `/prefix` or an empty string
+fn forwarded_prefix(req: &HttpRequest) -> String {
+    match header_value(req, FORWARDED_PREFIX) {
+        Some(prefix) => {
+            let prefix = prefix.trim_matches('/');
+            if prefix.is_empty() {
+                String::new()
+            } else {
+                format!("/{}", prefix)
+            }
+        }
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn proxied_request() -> HttpRequest {
+        TestRequest::default()
+            .insert_header((header::HOST, "example.com"))
+            .insert_header((FORWARDED_PROTO, "https, http"))
+            .insert_header((FORWARDED_PREFIX, "/hide/"))
+            .to_http_request()
+    }
+
+    #[test]
+    fn test_relative_urls_by_default() {
+        let urls = UrlBuilder::default();
+        assert_eq!(
+            urls.url(&proxied_request(), "/api/v1/images/abc"),
+            "/api/v1/images/abc"
+        );
+    }
+
+    #[test]
+    fn test_public_base_url_wins() {
+        let urls = UrlBuilder::new(Some("https://cdn.example.org/stego/".to_string()), true);
+        assert_eq!(
+            urls.url(&proxied_request(), "/api/v1/images/abc"),
+            "https://cdn.example.org/stego/api/v1/images/abc"
+        );
+    }
+
+    #[test]
+    fn test_forwarded_headers_when_trusted() {
+        let urls = UrlBuilder::new(None, true);
+        assert_eq!(
+            urls.url(&proxied_request(), "/api/v1/images/abc"),
+            "https://example.com/hide/api/v1/images/abc"
+        );
+
+        // Without a Host header, only the prefix is applied
+        let req = TestRequest::default()
+            .insert_header((FORWARDED_PREFIX, "hide"))
+            .to_http_request();
+        assert_eq!(
+            urls.url(&req, "/api/v1/images/abc"),
+            "/hide/api/v1/images/abc"
+        );
+
+        // Without a forwarded scheme, the connection scheme is used
+        let req = TestRequest::default()
+            .insert_header((header::HOST, "example.com:8080"))
+            .to_http_request();
+        assert_eq!(
+            urls.url(&req, "/api/v1/images/abc"),
+            "http://example.com:8080/api/v1/images/abc"
+        );
+    }
+
+    #[test]
+    fn test_empty_base_url_is_ignored() {
+        assert_eq!(
+            UrlBuilder::new(Some(" / ".to_string()), false),
+            UrlBuilder::default()
+        );
+    }
+
+    #[test]
+    fn test_image_url() {
+        let id = Uuid::parse_str("650e8400-e29b-41d4-a716-446655440001").unwrap();
+        let urls = UrlBuilder::new(Some("https://example.com".to_string()), false);
+        assert_eq!(
+            urls.image_url(&proxied_request(), id),
+            "https://example.com/api/v1/images/650e8400-e29b-41d4-a716-446655440001"
+        );
+    }
+
+    #[test]
+    fn test_signed_download_urls() {
+        let id = Uuid::parse_str("650e8400-e29b-41d4-a716-446655440001").unwrap();
+        let urls = UrlBuilder::default()
+            .with_signer(UrlSigner::new("secret", std::time::Duration::from_secs(60)));
+        let url = urls.image_url(&proxied_request(), id);
+        assert!(url.starts_with("/api/v1/images/650e8400-e29b-41d4-a716-446655440001?expires="));
+        assert!(url.contains("&sig="));
+
+        let url = urls.message_url(&proxied_request(), id);
+        assert!(url.starts_with("/api/v1/messages/650e8400-e29b-41d4-a716-446655440001?expires="));
+        assert!(url.contains("&sig="));
+    }
+
+    #[test]
+    fn test_mount_path_comes_before_the_route() {
+        let urls = UrlBuilder::new(None, true).with_mount_path("/embedded/hide/");
+        assert_eq!(
+            urls.url(&proxied_request(), "/api/v1/images/abc"),
+            "https://example.com/hide/embedded/hide/api/v1/images/abc"
+        );
+    }
+
+    #[test]
+    fn test_message_url() {
+        let id = Uuid::parse_str("650e8400-e29b-41d4-a716-446655440001").unwrap();
+        let urls = UrlBuilder::default();
+        assert_eq!(
+            urls.message_url(&proxied_request(), id),
+            "/api/v1/messages/650e8400-e29b-41d4-a716-446655440001"
+        );
+    }
+
+    #[test]
+    fn test_client_ip_only_trusts_forwarded_addresses_behind_a_proxy() {
+        let req = TestRequest::default()
+            .peer_addr("10.0.0.1:4000".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "203.0.113.7, 10.0.0.1"))
+            .to_http_request();
+        assert_eq!(UrlBuilder::default().client_ip(&req), "10.0.0.1");
+        assert_eq!(UrlBuilder::new(None, true).client_ip(&req), "203.0.113.7");
+
+        let direct = TestRequest::default()
+            .peer_addr("10.0.0.2:4000".parse().unwrap())
+            .to_http_request();
+        assert_eq!(UrlBuilder::new(None, true).client_ip(&direct), "10.0.0.2");
+    }
+}
@@ -0,0 +1,479 @@
+//! Resumable batch encoding
+//!
+//! A batch hides one message in every cover image below a directory and
+//! mirrors the tree into an output directory as PNG files. Each finished
+//! output is appended to a [`Journal`] in the output directory as a JSON line,
+//! so a batch that stops part way can be resumed without redoing the covers
+//! already done. Transient I/O failures on a file are retried by a
+//! [`RetryPolicy`].
+
+use crate::encoder::Encoder;
+use crate::error::HideError;
+use crate::img::StegoImage;
+use crate::manifest::sha256_hex;
+use crate::Result;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// Name of the journal written to the output directory by [`encode_dir`]
+pub const JOURNAL_FILENAME: &str = "hide-batch-journal.jsonl";
+
+/// Extensions of the cover images a batch picks up
+const COVER_EXTENSIONS: [&str; 6] = ["png", "bmp", "tif", "tiff", "jpg", "jpeg"];
+
+/// A finished output recorded in a batch journal
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// Path of the cover relative to the input directory, using `/` separators
+    pub input: String,
+
+    /// SHA-256 of the cover file (hex)
+    pub input_sha256: String,
+
+    /// SHA-256 of the message hidden in it (hex)
+    pub message_sha256: String,
+
+    /// Path of the stego image relative to the output directory, using `/` separators
+    pub output: String,
+}
+
+/// Progress journal of a batch, one JSON line per finished output
+#[derive(Debug)]
+pub struct Journal {
+    /// The journal file, open for appending
+    file: fs::File,
+
+    /// The latest entry for each input
+    entries: HashMap<String, JournalEntry>,
+}
+
+impl Journal {
+    /// Start a new journal, replacing any earlier one at the path
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(Self {
+            file: fs::File::create(path)?,
+            entries: HashMap::new(),
+        })
+    }
+
+    /// Open a journal to resume from, keeping the entries of earlier runs
+    ///
+    /// Lines that don't parse, such as one cut short when a run was killed,
+    /// are ignored; their inputs are simply done again.
+    pub fn resume<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let mut entries = HashMap::new();
+        if path.exists() {
+            for line in fs::read_to_string(path)?.lines() {
+                if let Ok(entry) = serde_json::from_str::<JournalEntry>(line) {
+                    entries.insert(entry.input.clone(), entry);
+                }
+            }
+        }
+
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        // Start on a fresh line if the last one was cut short
+        if fs::read(path)?.last().is_some_and(|&byte| byte != b'\n') {
+            file.write_all(b"\n")?;
+        }
+
+        Ok(Self { file, entries })
+    }
+
+    /// The entry recorded for an input, if any
+    pub fn entry(&self, input: &str) -> Option<&JournalEntry> {
+        self.entries.get(input)
+    }
+
+    /// Append a finished output, flushing it to disk before returning
+    pub fn record(&mut self, entry: JournalEntry) -> Result<()> {
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.file.sync_data()?;
+
+        self.entries.insert(entry.input.clone(), entry);
+        Ok(())
+    }
+}
+
+/// How many times, and how patiently, to retry transient I/O failures
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt
+    pub retries: u32,
+
+    /// Delay before the first retry; doubled before each later one
+    pub initial_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 0,
+            initial_backoff: Duration::from_millis(100),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Run an operation, retrying it while it fails with [`HideError::Io`]
+    ///
+    /// # Returns
+    /// * The first success, the first error that isn't an I/O error, or the
+    ///   last I/O error once the retries are used up
+    pub fn run<T>(&self, mut operation: impl FnMut() -> Result<T>) -> Result<T> {
+        let mut backoff = self.initial_backoff;
+        let mut attempt = 0;
+        loop {
+            match operation() {
+                Err(HideError::Io(e)) if attempt < self.retries => {
+                    attempt += 1;
+                    warn!(
+                        "I/O error ({}); retry {} of {} in {:?}",
+                        e, attempt, self.retries, backoff
+                    );
+                    thread::sleep(backoff);
+                    backoff = backoff.saturating_mul(2);
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Options for [`encode_dir`]
+#[derive(Debug, Clone, Default)]
+pub struct BatchOptions {
+    /// Skip covers that the journal of an earlier run shows as done
+    pub resume: bool,
+
+    /// Retries for transient I/O failures on each cover
+    pub retry: RetryPolicy,
+}
+
+/// What a batch encode did with each cover
+#[derive(Debug, Default)]
+pub struct BatchReport {
+    /// Covers encoded by this run, relative to the input directory
+    pub encoded: Vec<String>,
+
+    /// Covers skipped because an earlier run finished them
+    pub skipped: Vec<String>,
+
+    /// Covers that failed, with the error of their last attempt
+    pub failed: Vec<(String, HideError)>,
+}
+
+/// Hide a message in every cover image below a directory
+///
+/// A failure on one cover doesn't stop the batch; it is reported and the
+/// cover is tried again when the batch is resumed. Failing to write the
+/// journal does stop it.
+///
+/// # Arguments
+/// * `encoder` - The encoder to hide the message with
+/// * `message` - The message to hide in every cover
+/// * `input_dir` - Directory of cover images (walked recursively)
+/// * `output_dir` - Directory for the stego images and the journal
+/// * `options` - Whether to resume and how to retry
+///
+/// # Returns
+/// * What was done with each cover
+pub fn encode_dir(
+    encoder: &Encoder,
+    message: &[u8],
+    input_dir: &Path,
+    output_dir: &Path,
+    options: &BatchOptions,
+) -> Result<BatchReport> {
+    let mut covers = Vec::new();
+    collect_covers(input_dir, input_dir, &mut covers)?;
+    // An output directory inside the input directory holds outputs, not covers
+    covers.retain(|(_, path)| !path.starts_with(output_dir));
+    covers.sort();
+
+    // Covers differing only in extension would overwrite each other's output
+    let mut outputs = HashSet::new();
+    for (name, _) in &covers {
+        if !outputs.insert(output_name(name)) {
+            return Err(HideError::InvalidParameters(format!(
+                "Two covers would both be written to {}",
+                output_name(name)
+            )));
+        }
+    }
+
+    fs::create_dir_all(output_dir)?;
+    let journal_path = output_dir.join(JOURNAL_FILENAME);
+    let mut journal = if options.resume {
+        Journal::resume(&journal_path)?
+    } else {
+        Journal::create(&journal_path)?
+    };
+
+    let message_sha256 = sha256_hex(message);
+    let mut report = BatchReport::default();
+    for (name, path) in covers {
+        let output = output_name(&name);
+        let output_path = output_dir.join(&output);
+
+        let cover_bytes = match options.retry.run(|| Ok(fs::read(&path)?)) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                report.failed.push((name, e));
+                continue;
+            }
+        };
+        let input_sha256 = sha256_hex(&cover_bytes);
+
+        let done = journal.entry(&name).is_some_and(|entry| {
+            entry.input_sha256 == input_sha256
+                && entry.message_sha256 == message_sha256
+                && output_dir.join(&entry.output).exists()
+        });
+        if options.resume && done {
+            report.skipped.push(name);
+            continue;
+        }
+
+        let encoded = options.retry.run(|| {
+            if let Some(parent) = output_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let stego = encoder.encode(StegoImage::from_bytes(&cover_bytes)?, message)?;
+            stego.save(&output_path)
+        });
+        match encoded {
+            Ok(()) => {
+                journal.record(JournalEntry {
+                    input: name.clone(),
+                    input_sha256,
+                    message_sha256: message_sha256.clone(),
+                    output,
+                })?;
+                report.encoded.push(name);
+            }
+            Err(e) => report.failed.push((name, e)),
+        }
+    }
+
+    Ok(report)
+}
+
+/// Find the images below a directory that a batch would pick up
+///
+/// # Arguments
+/// * `dir` - The directory to walk recursively
+///
+/// # Returns
+/// * The image paths, sorted by their path relative to `dir`
+pub fn find_images(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut images = Vec::new();
+    collect_covers(dir, dir, &mut images)?;
+    images.sort();
+    Ok(images.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Recursively collect the cover images below `dir` with paths relative to `base`
+fn collect_covers(base: &Path, dir: &Path, covers: &mut Vec<(String, PathBuf)>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_covers(base, &path, covers)?;
+            continue;
+        }
+
+        let is_cover = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| COVER_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()));
+        if path.is_file() && is_cover {
+            let relative = path.strip_prefix(base).unwrap_or(&path);
+            let name = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            covers.push((name, path));
+        }
+    }
+
+    Ok(())
+}
+
+/// Size and modification time of a file, which change when it is rewritten
+type FileStamp = (u64, Option<SystemTime>);
+
+/// Polls a directory for cover images that were added or rewritten
+///
+/// A cover is only reported once it looks the same on two polls in a row,
+/// so files still being copied in are not picked up half written.
+#[derive(Debug)]
+pub struct DirWatcher {
+    /// The directory being watched (walked recursively)
+    dir: PathBuf,
+
+    /// Last stamp of each cover and whether it was reported with that stamp
+    seen: HashMap<PathBuf, (FileStamp, bool)>,
+}
+
+impl DirWatcher {
+    /// Watch a directory; covers already in it are reported like new ones
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            seen: HashMap::new(),
+        }
+    }
+
+    /// Look at the directory again
+    ///
+    /// # Returns
+    /// * Paths of the covers that settled since the previous poll, sorted
+    pub fn poll(&mut self) -> Result<Vec<PathBuf>> {
+        let mut covers = Vec::new();
+        collect_covers(&self.dir, &self.dir, &mut covers)?;
+
+        let mut settled = Vec::new();
+        let mut seen = HashMap::with_capacity(covers.len());
+        for (_, path) in covers {
+            let Ok(metadata) = fs::metadata(&path) else {
+                continue;
+            };
+            let stamp = (metadata.len(), metadata.modified().ok());
+            let reported = match self.seen.get(&path) {
+                Some(&(previous, reported)) if previous == stamp => {
+                    if !reported {
+                        settled.push(path.clone());
+                    }
+                    true
+                }
+                _ => false,
+            };
+            seen.insert(path, (stamp, reported));
+        }
+
+        // Forget removed covers, so they are reported again if they come back
+        self.seen = seen;
+        settled.sort();
+        Ok(settled)
+    }
+}
+
+/// The output of a cover: the same relative path, saved as PNG
+fn output_name(input: &str) -> String {
+    let stem = match input.rsplit_once('.') {
+        Some((stem, extension)) if !extension.contains('/') => stem,
+        _ => input,
+    };
+    format!("{}.png", stem)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::io;
+
+    fn fast_retries(retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            retries,
+            initial_backoff: Duration::from_millis(1),
+        }
+    }
+
+    #[test]
+    fn test_retries_only_io_errors() {
+        let attempts = Cell::new(0);
+        let flaky = || {
+            attempts.set(attempts.get() + 1);
+            if attempts.get() < 3 {
+                Err(HideError::Io(io::Error::from(io::ErrorKind::TimedOut)))
+            } else {
+                Ok(attempts.get())
+            }
+        };
+        assert_eq!(fast_retries(2).run(flaky).unwrap(), 3);
+
+        attempts.set(0);
+        assert!(matches!(fast_retries(1).run(flaky), Err(HideError::Io(_))));
+        assert_eq!(attempts.get(), 2);
+
+        attempts.set(0);
+        let result: Result<()> = fast_retries(5).run(|| {
+            attempts.set(attempts.get() + 1);
+            Err(HideError::MessageTooLarge)
+        });
+        assert!(matches!(result, Err(HideError::MessageTooLarge)));
+        assert_eq!(attempts.get(), 1);
+    }
+
+    #[test]
+    fn test_journal_survives_a_torn_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(JOURNAL_FILENAME);
+        let entry = |input: &str| JournalEntry {
+            input: input.to_string(),
+            input_sha256: "in".to_string(),
+            message_sha256: "msg".to_string(),
+            output: output_name(input),
+        };
+
+        let mut journal = Journal::create(&path).unwrap();
+        journal.record(entry("a.png")).unwrap();
+        drop(journal);
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"input\":\"b.pn")
+            .unwrap();
+
+        let mut journal = Journal::resume(&path).unwrap();
+        assert_eq!(journal.entry("a.png"), Some(&entry("a.png")));
+        assert!(journal.entry("b.png").is_none());
+        journal.record(entry("b.jpg")).unwrap();
+
+        let journal = Journal::resume(&path).unwrap();
+        assert_eq!(journal.entry("b.jpg"), Some(&entry("b.jpg")));
+
+        // Starting over forgets earlier runs
+        assert!(Journal::create(&path).unwrap().entry("a.png").is_none());
+    }
+
+    #[test]
+    fn test_dir_watcher_reports_settled_covers_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let cover = dir.path().join("a.png");
+        fs::write(&cover, b"first").unwrap();
+        fs::write(dir.path().join("notes.txt"), b"not a cover").unwrap();
+
+        // A new cover waits one poll to settle, then is reported once
+        let mut watcher = DirWatcher::new(dir.path());
+        assert!(watcher.poll().unwrap().is_empty());
+        assert_eq!(watcher.poll().unwrap(), vec![cover.clone()]);
+        assert!(watcher.poll().unwrap().is_empty());
+
+        // Rewriting it reports it again
+        fs::write(&cover, b"second version").unwrap();
+        assert!(watcher.poll().unwrap().is_empty());
+        assert_eq!(watcher.poll().unwrap(), vec![cover]);
+    }
+
+    #[test]
+    fn test_output_name() {
+        assert_eq!(output_name("a.jpg"), "a.png");
+        assert_eq!(output_name("dir/b.tiff"), "dir/b.png");
+        assert_eq!(output_name("dir.v2/c"), "dir.v2/c.png");
+    }
+}
@@ -0,0 +1,24 @@
+//! Regenerate the conformance test vectors under `tests/vectors/`
+//!
+//! Only run this for an intended change to the payload format; the vectors
+//! record what every implementation must produce.
+
+use hide_rs::conformance;
+use std::fs;
+use std::path::PathBuf;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = std::env::args_os()
+        .nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/vectors"));
+    fs::create_dir_all(&dir)?;
+
+    for vector in conformance::generate_all()? {
+        let path = dir.join(format!("{}.json", vector.name));
+        fs::write(&path, serde_json::to_string_pretty(&vector)? + "\n")?;
+        println!("Wrote {}", path.display());
+    }
+
+    Ok(())
+}
@@ -1,11 +1,37 @@
 //! Command-line interface for hide-rs steganography library
 
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
 use clap::{Parser, Subcommand};
-use hide_rs::decoder::create_decoder;
-use hide_rs::encoder::create_encoder;
+use hide_rs::batch::{self, BatchOptions, RetryPolicy};
+use hide_rs::capabilities::Algorithm;
+use hide_rs::decoder::{
+    self, DecodeManyOptions, DecoderConfig, SearchWindow, DEFAULT_MAX_IN_FLIGHT_BYTES,
+};
+use hide_rs::depth::EmbeddingDepth;
+use hide_rs::ecc::ErrorCorrection;
+use hide_rs::encoder::{self, EncoderConfig};
+use hide_rs::img::{FillStyle, TileLayout};
+#[cfg(feature = "jpeg")]
+use hide_rs::jpeg::JpegStego;
+use hide_rs::manifest;
+use hide_rs::mask::{PixelMask, DEFAULT_MASK_THRESHOLD};
+use hide_rs::pack;
+use hide_rs::planner::{self, PlanConstraints, Robustness};
+use hide_rs::preset::Preset;
 use hide_rs::raw_decoder;
+use hide_rs::report::{self, FileResult, Report, ReportRun};
+use hide_rs::robustness::{self, IntendedChannel, Transform};
+use hide_rs::self_test::{self, SelfTestLevel};
+use hide_rs::utils;
+use hide_rs::warning::StegoWarning;
+use hide_rs::{
+    create_decoder, extract_raw_data, extract_raw_to_writer, Decoder, Encoder, HideError,
+    RawExtractOptions, StegoImage,
+};
+use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::PathBuf;
+use std::io::{BufWriter, IsTerminal, Write};
+use std::path::{Path, PathBuf};
 
 /// Command-line arguments
 #[derive(Parser)]
@@ -26,16 +52,103 @@ enum Commands {
         image: PathBuf,
 
         /// The message to hide (use quotes for multiple words)
-        #[arg(short, long)]
-        message: String,
+        #[arg(short, long, required_unless_present = "file")]
+        message: Option<String>,
 
         /// Path to save the output stego image
         #[arg(short, long)]
         output: PathBuf,
 
         /// Read message from file instead of command line
-        #[arg(short = 'f', long)]
+        #[arg(short = 'f', long, conflicts_with = "message")]
         file: Option<PathBuf>,
+
+        /// Also write a provenance manifest to `<output>.hide.json`
+        #[arg(long)]
+        manifest: bool,
+
+        /// Replace a payload already hidden in the cover image
+        #[arg(long)]
+        overwrite: bool,
+
+        /// Largest share of pixels the payload may change (e.g. `0.5%` or `0.005`)
+        #[arg(long, value_parser = parse_max_change)]
+        max_change: Option<f64>,
+
+        /// Where the image goes next (`png`, `jpeg`, `print`, ...); warns if the payload won't survive it
+        #[arg(long, value_parser = parse_channel)]
+        intended_channel: Option<IntendedChannel>,
+
+        /// Fail instead of warning
+        #[arg(long)]
+        strict: bool,
+
+        /// Mask image the size of the cover; pixels lighter than the threshold are left untouched
+        #[arg(long)]
+        mask: Option<PathBuf>,
+
+        /// Mask luminance (0-255) above which a pixel is left untouched
+        #[arg(long, default_value_t = DEFAULT_MASK_THRESHOLD)]
+        mask_threshold: u8,
+
+        /// Embed a copy of the mask so decoding doesn't need it, if it is small enough
+        #[arg(long, requires = "mask")]
+        embed_mask: bool,
+
+        /// Skip decoding the stego image to check it before saving
+        #[arg(long)]
+        no_verify: bool,
+
+        /// Record the cover's dimensions so failed decodes of resized copies report them
+        #[arg(long)]
+        record_dimensions: bool,
+
+        /// Start from a named set of options (`fast`, `stealth` or `robust`); the other options add to it
+        #[arg(long, value_parser = parse_preset)]
+        preset: Option<Preset>,
+
+        /// Grow a cover too small for the message instead of failing, filling the
+        /// added area with `mirror`, `blur`, `solid` or `solid:#rrggbb`
+        #[arg(long, value_parser = parse_fill_style, conflicts_with = "mask")]
+        auto_extend: Option<FillStyle>,
+
+        /// Create the output's parent directories if they don't exist
+        #[arg(long)]
+        create_dirs: bool,
+
+        /// Embedding algorithm: `bltm3x3`, or the experimental `jpeg-dct`, which
+        /// reads a JPEG cover and writes a `.jpg` without recompressing it
+        #[arg(long, value_parser = parse_algorithm, default_value = "bltm3x3")]
+        algorithm: Algorithm,
+
+        /// Write this pixel density (dots per inch) into a PNG output instead of the cover's
+        #[arg(long)]
+        dpi: Option<u32>,
+
+        /// Deflate the message before hiding it, when that makes it smaller
+        #[arg(long)]
+        compress: bool,
+
+        /// End the payload with a CRC-32 that decoders verify
+        #[arg(long)]
+        checksum: bool,
+
+        /// Add parity that repairs corrupted bytes when decoding: Reed-Solomon
+        /// DATA:PARITY bytes per block (223:32 when given without a value), or
+        /// `hamming74` to repair one flipped bit in every nibble
+        #[arg(
+            long,
+            value_name = "DATA:PARITY|hamming74",
+            num_args = 0..=1,
+            default_missing_value = "223:32",
+            value_parser = parse_ecc
+        )]
+        ecc: Option<ErrorCorrection>,
+
+        /// Bits of each channel the payload takes after the header; 2 doubles
+        /// the capacity but changes pixels more
+        #[arg(long, value_parser = parse_depth, default_value = "1")]
+        depth: EmbeddingDepth,
     },
     /// Extract a hidden message from an image
     Decode {
@@ -44,16 +157,259 @@ enum Commands {
         image: PathBuf,
 
         /// Display output as hexadecimal for binary data
-        #[arg(short, long)]
+        #[arg(long)]
         hex: bool,
 
         /// Extract raw data regardless of header format validity
         #[arg(short, long, help = "Extract raw data without header validation")]
         raw: bool,
 
+        /// Recover as much of the message as possible from a truncated image
+        #[arg(short, long)]
+        partial: bool,
+
         /// Save output to file instead of displaying
         #[arg(short, long)]
         output: Option<PathBuf>,
+
+        /// Append a machine-readable JSON report of this run to the given file
+        #[arg(long)]
+        report: Option<PathBuf>,
+
+        /// Mask image the message was encoded with, if it wasn't embedded
+        #[arg(long)]
+        mask: Option<PathBuf>,
+
+        /// Mask luminance (0-255) above which a pixel was left untouched
+        #[arg(long, default_value_t = DEFAULT_MASK_THRESHOLD)]
+        mask_threshold: u8,
+
+        /// Write an image highlighting the pixels that carried the payload
+        #[arg(long, conflicts_with_all = ["raw", "partial"])]
+        trace: Option<PathBuf>,
+
+        /// Look for a payload moved down the image by padding added after encoding
+        #[arg(long, conflicts_with_all = ["raw", "partial", "trace"])]
+        search: bool,
+
+        /// Try flipping each header bit back if the header read is corrupt
+        #[arg(long, conflicts_with_all = ["raw", "partial", "search"])]
+        repair_header: bool,
+
+        /// With --repair-header, also try flipping pairs of header bits
+        #[arg(long, requires = "repair_header")]
+        repair_pairs: bool,
+
+        /// Return a checksummed message even if it fails its checksum
+        #[arg(long)]
+        skip_checksum: bool,
+
+        /// Print the message as decoded, control characters and all, without a length cap
+        #[arg(long)]
+        no_sanitize: bool,
+
+        /// Largest message written to a file without asking first
+        #[arg(long, default_value_t = DEFAULT_MAX_OUTPUT_BYTES)]
+        max_output_bytes: usize,
+
+        /// Write messages over --max-output-bytes without asking
+        #[arg(short, long)]
+        yes: bool,
+
+        /// Size of the cover when it was encoded (e.g. `800x600`); a failed decode says if the image differs
+        #[arg(long, value_parser = parse_dimensions)]
+        expected_dimensions: Option<(u32, u32)>,
+
+        /// Print the result as JSON, with the message base64 encoded
+        #[arg(long, conflicts_with_all = ["hex", "output", "trace", "report"])]
+        json: bool,
+
+        /// With --json, write the message to this new file instead of inlining it
+        #[arg(long, requires = "json")]
+        payload_file: Option<PathBuf>,
+
+        /// With --json, largest message inlined; larger ones go to a file next to the image
+        #[arg(long, requires = "json", conflicts_with = "payload_file", default_value_t = DEFAULT_MAX_INLINE_BYTES)]
+        max_inline_bytes: usize,
+
+        /// Algorithm the message was hidden with: `bltm3x3` or `jpeg-dct`
+        #[arg(long, value_parser = parse_algorithm, default_value = "bltm3x3")]
+        algorithm: Algorithm,
+    },
+    /// Decode a message from an image stored as separate tiles
+    DecodeTiles {
+        /// JSON file giving the full image's `width` and `height` and its
+        /// `tiles`, each with an `x`, `y` and `path` relative to the file
+        #[arg(long)]
+        manifest: PathBuf,
+
+        /// Write the message to a file instead of showing it
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Check a stego image against its provenance manifest
+    Verify {
+        /// Path to the stego image file
+        #[arg(short, long)]
+        image: PathBuf,
+
+        /// Path to the manifest (defaults to `<image>.hide.json`)
+        #[arg(short, long)]
+        manifest: Option<PathBuf>,
+
+        /// Append a machine-readable JSON report of this run to the given file
+        #[arg(long)]
+        report: Option<PathBuf>,
+    },
+    /// Show how much an image can hide and where its capacity goes
+    Capacity {
+        /// Path to the cover image file
+        #[arg(short, long)]
+        image: PathBuf,
+
+        /// Largest share of pixels the payload may change (e.g. `0.5%` or `0.005`)
+        #[arg(long, value_parser = parse_max_change)]
+        max_change: Option<f64>,
+
+        /// Bits of each channel the payload would take after the header
+        #[arg(long, value_parser = parse_depth, default_value = "1")]
+        depth: EmbeddingDepth,
+    },
+    /// Recommend options for hiding a message of a given size in an image
+    Plan {
+        /// Path to the cover image file
+        #[arg(short, long)]
+        image: PathBuf,
+
+        /// Length of the message in bytes
+        #[arg(long)]
+        size: usize,
+
+        /// Largest share of pixels the payload may change (e.g. `0.5%` or `0.005`)
+        #[arg(long, value_parser = parse_max_change)]
+        max_change: Option<f64>,
+
+        /// Least robustness to accept: none, checked or recoverable
+        #[arg(long, value_parser = parse_robustness, default_value = "none")]
+        robustness: Robustness,
+
+        /// Only accept options that pad the payload to hide the message length
+        #[arg(long)]
+        hide_length: bool,
+
+        /// Print the plan as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Rank candidate cover images by how well they would hide a message
+    Rank {
+        /// Paths to the candidate cover images
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Hide every file of a directory in a directory of cover images
+    Pack {
+        /// Directory of files to hide
+        #[arg(long)]
+        input_dir: PathBuf,
+
+        /// Directory of cover images (one file per cover)
+        #[arg(long)]
+        covers_dir: PathBuf,
+
+        /// Directory to write the stego images and pack manifest to
+        #[arg(long)]
+        output_dir: PathBuf,
+    },
+    /// Hide the same message in every cover image below a directory
+    EncodeBatch {
+        /// Directory of cover images (walked recursively)
+        #[arg(long)]
+        input_dir: PathBuf,
+
+        /// Directory to write the stego images (as PNG) and progress journal to
+        #[arg(long)]
+        output_dir: PathBuf,
+
+        /// The message to hide (use quotes for multiple words)
+        #[arg(short, long, required_unless_present = "file")]
+        message: Option<String>,
+
+        /// Read message from file instead of command line
+        #[arg(short = 'f', long, conflicts_with = "message")]
+        file: Option<PathBuf>,
+
+        /// Skip covers an earlier run finished, according to its journal
+        #[arg(long)]
+        resume: bool,
+
+        /// Times to retry a cover after a transient I/O error, backing off exponentially
+        #[arg(long, default_value_t = 0)]
+        retries: u32,
+    },
+    /// Extract the hidden messages of every image below a directory, in parallel
+    DecodeBatch {
+        /// Directory of stego images (walked recursively)
+        #[arg(long)]
+        input_dir: PathBuf,
+
+        /// Number of images to decode at once (defaults to the number of CPUs)
+        #[arg(long)]
+        parallelism: Option<usize>,
+
+        /// Most megabytes of image files to load at once
+        #[arg(long, default_value_t = DEFAULT_MAX_IN_FLIGHT_BYTES >> 20)]
+        max_in_flight_mb: u64,
+    },
+    /// Convert a stego image to another lossless format, keeping its payload
+    Convert {
+        /// Path to the image to convert
+        #[arg(short, long)]
+        image: PathBuf,
+
+        /// Path to save the converted image (format taken from the extension)
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Check which transformations a stego image's payload survives
+    Robustness {
+        /// Path to the stego image file
+        #[arg(short, long)]
+        image: PathBuf,
+
+        /// Comma-separated transformations, e.g. `png,jpeg:80,resize:0.5,crop:50%,bitdepth:5`
+        #[arg(long, required = true, value_delimiter = ',', value_parser = parse_transform)]
+        ops: Vec<Transform>,
+    },
+    /// Check that this build encodes and decodes correctly on this machine
+    SelfTest {
+        /// Only the small covers (the default)
+        #[arg(long, conflicts_with = "full")]
+        quick: bool,
+
+        /// Also the benchmark-sized (1920x1080) covers
+        #[arg(long)]
+        full: bool,
+
+        /// Print the results as a JSON report instead of a summary
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show the version, features and limits of a hide-rs server
+    RemoteCapabilities {
+        /// Base URL of the server, e.g. `http://localhost:8080` (plain HTTP only)
+        #[arg(long)]
+        server: String,
+    },
+    /// Restore the files hidden in a directory of stego images
+    Unpack {
+        /// Directory of stego images
+        #[arg(long)]
+        input_dir: PathBuf,
+
+        /// Directory to restore the files into
+        #[arg(long)]
+        output_dir: PathBuf,
     },
 }
 
@@ -66,109 +422,1335 @@ fn main() {
             message,
             output,
             file,
+            manifest,
+            overwrite,
+            max_change,
+            intended_channel,
+            strict,
+            mask,
+            mask_threshold,
+            embed_mask,
+            no_verify,
+            record_dimensions,
+            preset,
+            auto_extend,
+            create_dirs,
+            algorithm,
+            dpi,
+            compress,
+            checksum,
+            ecc,
+            depth,
         } => {
-            encode_message(image, message, output, file);
+            let message = message.as_deref().unwrap_or_default();
+            match algorithm {
+                Algorithm::Bltm => {}
+                #[cfg(feature = "jpeg")]
+                Algorithm::JpegDct => {
+                    reject_pixel_options(
+                        *algorithm,
+                        &[
+                            ("--manifest", *manifest),
+                            ("--overwrite", *overwrite),
+                            ("--max-change", max_change.is_some()),
+                            ("--intended-channel", intended_channel.is_some()),
+                            ("--mask", mask.is_some()),
+                            ("--record-dimensions", *record_dimensions),
+                            ("--preset", preset.is_some()),
+                            ("--auto-extend", auto_extend.is_some()),
+                            ("--dpi", dpi.is_some()),
+                            ("--compress", *compress),
+                            ("--checksum", *checksum),
+                            ("--ecc", ecc.is_some()),
+                            ("--depth", *depth != EmbeddingDepth::One),
+                        ],
+                    );
+                    encode_jpeg(image, message, output, file, *create_dirs);
+                    return;
+                }
+            }
+            let base = match preset {
+                Some(preset) => EncoderConfig::preset(*preset),
+                None => EncoderConfig::default().verify_roundtrip(true),
+            };
+            let config = EncoderConfig {
+                emit_manifest: *manifest,
+                overwrite_existing: *overwrite,
+                max_changed_pixels: *max_change,
+                pixel_mask: mask.as_deref().map(|path| load_mask(path, *mask_threshold)),
+                embed_mask: *embed_mask,
+                verify_roundtrip: base.verify_roundtrip && !*no_verify,
+                record_dimensions: base.record_dimensions || *record_dimensions,
+                auto_extend: *auto_extend,
+                create_dirs: *create_dirs,
+                dpi: dpi.map(|dpi| (dpi, dpi)),
+                compress: base.compress || *compress,
+                checksum: base.checksum || *checksum,
+                error_correction: ecc.or(base.error_correction),
+                embedding_depth: *depth,
+                ..base
+            };
+            if config.embed_mask
+                && config
+                    .pixel_mask
+                    .as_ref()
+                    .and_then(PixelMask::embedded_copy)
+                    .is_none()
+            {
+                eprintln!(
+                    "Warning: the mask is too large or covers the first pixels, so it is not \
+                     embedded; decoding will need --mask"
+                );
+            }
+            encode_message(
+                image,
+                message,
+                output,
+                file,
+                config,
+                *intended_channel,
+                *strict,
+            );
         }
         Commands::Decode {
             image,
             hex,
             raw,
+            partial,
             output,
+            report,
+            mask,
+            mask_threshold,
+            trace,
+            search,
+            repair_header,
+            repair_pairs,
+            skip_checksum,
+            no_sanitize,
+            max_output_bytes,
+            yes,
+            expected_dimensions,
+            json,
+            payload_file,
+            max_inline_bytes,
+            algorithm,
         } => {
-            decode_message(image, *hex, *raw, output);
+            let jpeg_mode = match algorithm {
+                Algorithm::Bltm => None,
+                #[cfg(feature = "jpeg")]
+                Algorithm::JpegDct => {
+                    reject_pixel_options(
+                        *algorithm,
+                        &[
+                            ("--raw", *raw),
+                            ("--partial", *partial),
+                            ("--mask", mask.is_some()),
+                            ("--trace", trace.is_some()),
+                            ("--search", *search),
+                            ("--repair-header", *repair_header),
+                            ("--skip-checksum", *skip_checksum),
+                            ("--expected-dimensions", expected_dimensions.is_some()),
+                        ],
+                    );
+                    Some(DecodeMode::Jpeg)
+                }
+            };
+            let decoder = Decoder::with_config(DecoderConfig {
+                mask: mask.as_deref().map(|path| load_mask(path, *mask_threshold)),
+                expected_dimensions: *expected_dimensions,
+                repair_bit_pairs: *repair_pairs,
+                skip_checksum: *skip_checksum,
+                ..Default::default()
+            });
+            if let Some(trace_path) = trace {
+                write_trace(&decoder, image, trace_path);
+            }
+            let mode = if let Some(mode) = jpeg_mode {
+                mode
+            } else if *raw {
+                DecodeMode::Raw
+            } else if *partial {
+                DecodeMode::Partial
+            } else if *search {
+                DecodeMode::Search
+            } else if *repair_header {
+                DecodeMode::Repair
+            } else {
+                DecodeMode::Standard
+            };
+            let output = if *json {
+                DecodeOutput::Json {
+                    payload_file: payload_file.clone(),
+                    max_inline_bytes: *max_inline_bytes,
+                }
+            } else if let Some(output_path) = output {
+                DecodeOutput::File(output_path.clone())
+            } else {
+                DecodeOutput::Display {
+                    hex: *hex,
+                    sanitize: !*no_sanitize,
+                }
+            };
+            let limit = OutputLimit {
+                max_bytes: *max_output_bytes,
+                assume_yes: *yes,
+            };
+            decode_message(&decoder, image, mode, &output, report, limit);
+        }
+        Commands::Verify {
+            image,
+            manifest,
+            report,
+        } => {
+            verify_manifest(image, manifest, report);
+        }
+        Commands::Capacity {
+            image,
+            max_change,
+            depth,
+        } => {
+            show_capacity(image, *max_change, *depth);
+        }
+        Commands::Plan {
+            image,
+            size,
+            max_change,
+            robustness,
+            hide_length,
+            json,
+        } => {
+            let constraints = PlanConstraints {
+                max_change: *max_change,
+                robustness: *robustness,
+                hide_length: *hide_length,
+                ..Default::default()
+            };
+            show_plan(image, *size, constraints, *json);
+        }
+        Commands::Rank { files } => {
+            rank_covers(files);
+        }
+        Commands::Pack {
+            input_dir,
+            covers_dir,
+            output_dir,
+        } => {
+            pack_directory(input_dir, covers_dir, output_dir);
+        }
+        Commands::Unpack {
+            input_dir,
+            output_dir,
+        } => {
+            unpack_directory(input_dir, output_dir);
+        }
+        Commands::DecodeTiles { manifest, output } => {
+            decode_tiles(manifest, output);
+        }
+        Commands::EncodeBatch {
+            input_dir,
+            output_dir,
+            message,
+            file,
+            resume,
+            retries,
+        } => {
+            let options = BatchOptions {
+                resume: *resume,
+                retry: RetryPolicy {
+                    retries: *retries,
+                    ..Default::default()
+                },
+            };
+            encode_batch(input_dir, output_dir, message.as_deref(), file, &options);
+        }
+        Commands::DecodeBatch {
+            input_dir,
+            parallelism,
+            max_in_flight_mb,
+        } => {
+            let defaults = DecodeManyOptions::default();
+            let options = DecodeManyOptions {
+                parallelism: parallelism.unwrap_or(defaults.parallelism),
+                max_in_flight_bytes: max_in_flight_mb.saturating_mul(1 << 20),
+                ..defaults
+            };
+            decode_batch(input_dir, options);
+        }
+        Commands::Convert { image, output } => {
+            convert_image(image, output);
+        }
+        Commands::Robustness { image, ops } => {
+            report_robustness(image, ops);
+        }
+        Commands::SelfTest { full, json, .. } => {
+            let level = if *full {
+                SelfTestLevel::Full
+            } else {
+                SelfTestLevel::Quick
+            };
+            run_self_test(level, *json);
+        }
+        Commands::RemoteCapabilities { server } => {
+            show_remote_capabilities(server);
+        }
+    }
+}
+
+/// Load a mask image, excluding pixels lighter than the threshold
+fn load_mask(path: &Path, threshold: u8) -> PixelMask {
+    let image = StegoImage::from_file(path)
+        .unwrap_or_else(|_| panic!("Failed to load mask: {}", path.display()));
+    PixelMask::from_image(&image, threshold)
+}
+
+/// Write an overlay of the pixels that carried an image's payload
+fn write_trace(decoder: &Decoder, image_path: &Path, trace_path: &Path) {
+    let stego_image = StegoImage::from_file(image_path).expect("Failed to load image");
+    let (_, trace) = match decoder.decode_with_trace(&stego_image) {
+        Ok(decoded) => decoded,
+        Err(e) => {
+            eprintln!("Error: {}", utils::sanitize_for_terminal(&e.to_string()));
+            std::process::exit(1);
+        }
+    };
+
+    trace
+        .to_overlay_image(&stego_image)
+        .and_then(|overlay| overlay.save(trace_path))
+        .expect("Failed to write trace image");
+    println!(
+        "Traced {} payload bytes across {} pixels in {} runs: {}",
+        trace.total_bytes(),
+        trace.pixel_count(),
+        trace.runs().len(),
+        trace_path.display()
+    );
+}
+
+/// Convert an image to the lossless format implied by the output extension
+fn convert_image(image_path: &Path, output_path: &Path) {
+    let format = image::ImageFormat::from_path(output_path)
+        .unwrap_or_else(|_| panic!("Unknown output format: {}", output_path.display()));
+
+    hide_rs::img::convert(image_path, output_path, format).expect("Failed to convert image");
+    println!(
+        "Converted {} to {:?}: {}",
+        image_path.display(),
+        format,
+        output_path.display()
+    );
+}
+
+/// Hide every file of a directory in a directory of covers
+fn pack_directory(input_dir: &Path, covers_dir: &Path, output_dir: &Path) {
+    let manifest =
+        pack::pack_dir(input_dir, covers_dir, output_dir).expect("Failed to pack directory");
+
+    for entry in &manifest.entries {
+        println!("{} -> {} ({} bytes)", entry.file, entry.stego, entry.size);
+    }
+    println!(
+        "Packed {} files into: {}",
+        manifest.entries.len(),
+        output_dir.display()
+    );
+}
+
+/// Hide a message in every cover below a directory, exiting with an error if any cover failed
+fn encode_batch(
+    input_dir: &Path,
+    output_dir: &Path,
+    message_text: Option<&str>,
+    message_file: &Option<PathBuf>,
+    options: &BatchOptions,
+) {
+    let message = match message_file {
+        Some(file_path) => fs::read(file_path)
+            .unwrap_or_else(|_| panic!("Failed to read message file: {}", file_path.display())),
+        None => message_text.unwrap_or_default().as_bytes().to_vec(),
+    };
+
+    let report = batch::encode_dir(&Encoder::new(), &message, input_dir, output_dir, options)
+        .expect("Failed to encode batch");
+
+    for name in &report.encoded {
+        println!("Encoded: {}", utils::sanitize_for_terminal(name));
+    }
+    for (name, e) in &report.failed {
+        eprintln!(
+            "Failed: {}: {}",
+            utils::sanitize_for_terminal(name),
+            utils::sanitize_for_terminal(&e.to_string())
+        );
+    }
+    println!(
+        "Encoded {} covers, skipped {} already done, {} failed",
+        report.encoded.len(),
+        report.skipped.len(),
+        report.failed.len()
+    );
+
+    if !report.failed.is_empty() {
+        eprintln!("Rerun with --resume to retry only the failed covers");
+        std::process::exit(1);
+    }
+}
+
+/// Decode every image below a directory, exiting with an error if any failed
+fn decode_batch(input_dir: &Path, options: DecodeManyOptions) {
+    let images = match batch::find_images(input_dir) {
+        Ok(images) => images,
+        Err(e) => {
+            eprintln!("Error: {}", utils::sanitize_for_terminal(&e.to_string()));
+            std::process::exit(1);
+        }
+    };
+
+    let mut failed = 0;
+    for (path, result) in decoder::decode_many(&images, options) {
+        let name = path.strip_prefix(input_dir).unwrap_or(&path).display();
+        match result {
+            Ok(message) => println!(
+                "{}: {}",
+                utils::sanitize_for_terminal(&name.to_string()),
+                utils::sanitize_for_terminal(&String::from_utf8_lossy(&message))
+            ),
+            Err(e) => {
+                failed += 1;
+                eprintln!(
+                    "Failed: {}: {}",
+                    utils::sanitize_for_terminal(&name.to_string()),
+                    utils::sanitize_for_terminal(&e.to_string())
+                );
+            }
+        }
+    }
+    println!(
+        "Decoded {} images, {} failed",
+        images.len() - failed,
+        failed
+    );
+
+    if failed > 0 {
+        std::process::exit(1);
+    }
+}
+
+/// The tiles an image is stored as, read by `decode-tiles`
+#[derive(Deserialize)]
+struct TileManifest {
+    width: u32,
+    height: u32,
+    tiles: Vec<TileEntry>,
+}
+
+/// One tile of a [`TileManifest`]
+#[derive(Deserialize)]
+struct TileEntry {
+    x: u32,
+    y: u32,
+    path: PathBuf,
+}
+
+/// Decode a message from the tiles listed in a manifest
+fn decode_tiles(manifest_path: &Path, output: &Option<PathBuf>) {
+    let text = fs::read_to_string(manifest_path)
+        .unwrap_or_else(|_| panic!("Failed to read manifest: {}", manifest_path.display()));
+    let manifest: TileManifest = serde_json::from_str(&text).unwrap_or_else(|e| {
+        eprintln!("Error: Invalid tile manifest: {}", e);
+        std::process::exit(1);
+    });
+
+    // Tile paths are relative to the manifest
+    let base = manifest_path.parent().unwrap_or(Path::new(""));
+    let tiles: Vec<_> = manifest
+        .tiles
+        .iter()
+        .map(|tile| {
+            let path = base.join(&tile.path);
+            let image = StegoImage::from_file(&path)
+                .unwrap_or_else(|_| panic!("Failed to load tile: {}", path.display()));
+            (tile.x, tile.y, image)
+        })
+        .collect();
+
+    let layout = TileLayout {
+        width: manifest.width,
+        height: manifest.height,
+    };
+    let message = match Decoder::new().decode_tiles(&tiles, layout) {
+        Ok(message) => message,
+        Err(e) => {
+            eprintln!("Error: {}", utils::sanitize_for_terminal(&e.to_string()));
+            std::process::exit(1);
+        }
+    };
+
+    let output = match output {
+        Some(path) => DecodeOutput::File(path.clone()),
+        None => DecodeOutput::Display {
+            hex: false,
+            sanitize: true,
+        },
+    };
+    show_decoded_message(manifest_path, DecodeMode::Standard, &message, None, &output);
+}
+
+/// Restore the files hidden in a directory of stego images
+fn unpack_directory(input_dir: &Path, output_dir: &Path) {
+    let restored = pack::unpack_dir(input_dir, output_dir).expect("Failed to unpack directory");
+
+    for path in &restored {
+        println!(
+            "Restored: {}",
+            utils::sanitize_for_terminal(&path.display().to_string())
+        );
+    }
+    println!("Restored {} files", restored.len());
+}
+
+/// Parse the `--algorithm` argument
+fn parse_algorithm(value: &str) -> Result<Algorithm, String> {
+    value.parse().map_err(|e: HideError| e.to_string())
+}
+
+/// Exit with an error naming the first BLTM-only option given with another algorithm
+///
+/// # Arguments
+/// * `algorithm` - The algorithm selected
+/// * `options` - Flags by name and whether they were given
+#[cfg(feature = "jpeg")]
+fn reject_pixel_options(algorithm: Algorithm, options: &[(&str, bool)]) {
+    if let Some((name, _)) = options.iter().find(|(_, given)| *given) {
+        eprintln!(
+            "Error: {} is not supported with --algorithm {}",
+            name, algorithm
+        );
+        std::process::exit(1);
+    }
+}
+
+/// Parse the `--preset` argument
+fn parse_preset(value: &str) -> Result<Preset, String> {
+    value.parse().map_err(|e: HideError| e.to_string())
+}
+
+/// Parse the `--ecc` argument
+fn parse_ecc(value: &str) -> Result<ErrorCorrection, String> {
+    value.parse().map_err(|e: HideError| e.to_string())
+}
+
+/// Parse the `--depth` argument
+fn parse_depth(value: &str) -> Result<EmbeddingDepth, String> {
+    value.parse().map_err(|e: HideError| e.to_string())
+}
+
+/// Parse the `--auto-extend` argument
+fn parse_fill_style(value: &str) -> Result<FillStyle, String> {
+    value.parse().map_err(|e: HideError| e.to_string())
+}
+
+/// Parse the `--robustness` argument
+fn parse_robustness(value: &str) -> Result<Robustness, String> {
+    value.parse().map_err(|e: HideError| e.to_string())
+}
+
+/// Parse one transformation of the `--ops` argument
+fn parse_transform(value: &str) -> Result<Transform, String> {
+    value.parse().map_err(|e: HideError| e.to_string())
+}
+
+/// Apply transformations to a stego image and print how the payload fared
+fn report_robustness(image_path: &Path, ops: &[Transform]) {
+    let stego_image = StegoImage::from_file(image_path).expect("Failed to load image");
+    let results = robustness::simulate(&stego_image, ops).expect("Failed to decode payload");
+
+    println!(
+        "{:<16} {:>8} {:>8} {:>10} {:>10}",
+        "transform", "detect", "decode", "recovered", "byte err"
+    );
+    for result in &results {
+        if let Some(error) = &result.error {
+            println!("{:<16} failed: {}", result.transform.to_string(), error);
+            continue;
         }
+        println!(
+            "{:<16} {:>8} {:>8} {:>10} {:>9.1}%",
+            result.transform.to_string(),
+            if result.detected { "yes" } else { "no" },
+            if result.decoded { "yes" } else { "no" },
+            result.recovered_bytes,
+            result.byte_error_rate * 100.0
+        );
     }
 }
 
+/// Run the self-test and print its results, exiting with an error if any check failed
+fn run_self_test(level: SelfTestLevel, json: bool) {
+    let run = self_test::run(level);
+    let failed = self_test::failures(&run).count();
+    let checks = run.files.len();
+
+    if json {
+        let report = Report {
+            runs: vec![run],
+            ..Default::default()
+        };
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&report).expect("Failed to serialize report")
+        );
+    } else {
+        for result in &run.files {
+            match &result.error {
+                Some(error) => println!("FAIL {}: {}", result.path, error),
+                None => println!("ok   {}", result.path),
+            }
+            if let (Some(encode), Some(decode)) = (
+                result.scores.get("encode_mib_per_s"),
+                result.scores.get("decode_mib_per_s"),
+            ) {
+                println!(
+                    "     encode {:.2} MiB/s, decode {:.2} MiB/s",
+                    encode, decode
+                );
+            }
+        }
+        println!("{} checks, {} failed", checks, failed);
+    }
+
+    if failed > 0 {
+        eprintln!("Self-test failed: {} of {} checks", failed, checks);
+        std::process::exit(1);
+    }
+}
+
+/// Fetch and print the capabilities of a hide-rs server
+#[cfg(feature = "client")]
+fn show_remote_capabilities(server: &str) {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("Failed to start the async runtime");
+    let client = hide_rs::client::HideClient::new(server);
+    let capabilities = runtime.block_on(client.capabilities()).unwrap_or_else(|e| {
+        eprintln!(
+            "Error: Failed to get the capabilities of {}: {}",
+            server,
+            utils::sanitize_for_terminal(&e.to_string())
+        );
+        std::process::exit(1);
+    });
+
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&capabilities).expect("Failed to format capabilities")
+    );
+}
+
+/// Refuse to fetch the capabilities of a server in a build without the HTTP client
+#[cfg(not(feature = "client"))]
+fn show_remote_capabilities(_server: &str) {
+    eprintln!(
+        "Error: remote-capabilities needs the `client` feature; rebuild with `--features client`"
+    );
+    std::process::exit(1);
+}
+
+/// Print the capacity breakdown of an image under the given options
+fn show_capacity(
+    image_path: &Path,
+    max_changed_pixels: Option<f64>,
+    embedding_depth: EmbeddingDepth,
+) {
+    let image = StegoImage::from_file(image_path).expect("Failed to load image");
+    let breakdown = encoder::capacity_breakdown(
+        &image,
+        &EncoderConfig {
+            max_changed_pixels,
+            embedding_depth,
+            ..Default::default()
+        },
+    );
+
+    println!(
+        "Capacity of {} ({}x{}):",
+        image_path.display(),
+        image.width(),
+        image.height()
+    );
+    println!("  {:<16} {:>12}", "raw", breakdown.raw_bytes);
+    for overhead in &breakdown.overheads {
+        println!(
+            "  {:<16} {:>12}",
+            format!("- {}", overhead.name),
+            overhead.bytes
+        );
+    }
+    println!("  {:<16} {:>12}", "= message", breakdown.max_message_bytes);
+}
+
+/// Print the options a message size fits an image with, exiting with 1 if none meets the constraints
+fn show_plan(image_path: &Path, size: usize, constraints: PlanConstraints, json: bool) {
+    let image = StegoImage::from_file(image_path).expect("Failed to load image");
+    let plan = planner::plan(&image, size, constraints);
+
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&plan).expect("Failed to serialize plan")
+        );
+    } else {
+        println!(
+            "Plan for {} bytes in {} ({}x{}, cover score {:.4}):",
+            size,
+            image_path.display(),
+            image.width(),
+            image.height(),
+            plan.cover_score
+        );
+        for option in &plan.options {
+            let marker = if plan.recommended.as_deref() == Some(option.name.as_str()) {
+                "*"
+            } else {
+                " "
+            };
+            match &option.predicted {
+                Some(predicted) => println!(
+                    "{} {:<10} {:<12} max {:>10} bytes, {:>9} pixels ({:.2}%), detectability {:.4}{}",
+                    marker,
+                    option.name,
+                    option.robustness,
+                    option.capacity.max_message_bytes,
+                    predicted.pixels_used,
+                    predicted.changed_fraction * 100.0,
+                    predicted.detectability,
+                    if option.meets_constraints { "" } else { " (misses constraints)" }
+                ),
+                None => println!(
+                    "{} {:<10} {:<12} max {:>10} bytes, does not fit",
+                    marker, option.name, option.robustness, option.capacity.max_message_bytes
+                ),
+            }
+        }
+    }
+
+    match plan.recommendation() {
+        Some(option) if !json => println!("Recommended: {}", option.name),
+        Some(_) => {}
+        None => {
+            eprintln!("No option fits {} bytes within the constraints", size);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Rank candidate cover images and print the ranking
+fn rank_covers(files: &[PathBuf]) {
+    // Load every candidate
+    let images: Vec<_> = files
+        .iter()
+        .map(|path| {
+            hide_rs::img::load_image(path)
+                .unwrap_or_else(|_| panic!("Failed to load image: {}", path.display()))
+        })
+        .collect();
+
+    println!("Cover ranking (best first):");
+    for (rank, (index, score)) in hide_rs::img::rank_covers(&images).iter().enumerate() {
+        println!(
+            "{:3}. {} (score {:.4})",
+            rank + 1,
+            files[*index].display(),
+            score
+        );
+    }
+}
+
+/// Parse the `--max-change` argument
+fn parse_max_change(value: &str) -> Result<f64, String> {
+    hide_rs::utils::parse_ratio(value).map_err(|e| e.to_string())
+}
+
+/// Parse the `--expected-dimensions` argument
+fn parse_dimensions(value: &str) -> Result<(u32, u32), String> {
+    utils::parse_dimensions(value).map_err(|e| e.to_string())
+}
+
+fn parse_channel(value: &str) -> Result<IntendedChannel, String> {
+    value.parse().map_err(|e: HideError| e.to_string())
+}
+
 /// Encode a message into an image
 fn encode_message(
     image_path: &PathBuf,
     message_text: &str,
     output_path: &PathBuf,
     message_file: &Option<PathBuf>,
+    config: EncoderConfig,
+    intended_channel: Option<IntendedChannel>,
+    strict: bool,
 ) {
-    // Determine the message source and read it
-    let message = if let Some(file_path) = message_file {
-        // Read from file
-        fs::read(file_path)
-            .unwrap_or_else(|_| panic!("Failed to read message file: {}", file_path.display()))
-    } else {
-        // Use the message from the command line
-        message_text.as_bytes().to_vec()
-    };
+    // Warn before doing any work if the payload won't survive where the image goes
+    if let Some(warning) = intended_channel.and_then(|channel| channel.warning(&config)) {
+        if strict {
+            eprintln!("Error: {}", warning.message);
+            std::process::exit(1);
+        }
+        eprintln!("Warning: {}", warning.message);
+    }
 
+    // A lossy output would silently drop the payload
+    if let Ok(format) = image::ImageFormat::from_path(output_path) {
+        if !hide_rs::img::is_lossless_format(format) {
+            eprintln!(
+                "Error: {}",
+                HideError::LossyFormatRejected(format!("{:?}", format))
+            );
+            if cfg!(feature = "jpeg") && format == image::ImageFormat::Jpeg {
+                eprintln!("To write a JPEG, use --algorithm jpeg-dct with a JPEG cover");
+            }
+            std::process::exit(1);
+        }
+    }
+
+    let message = read_message(message_text, message_file);
     println!("Message size: {} bytes", message.len());
 
     // Create encoder
-    let encoder = create_encoder();
+    let emit_manifest = config.emit_manifest;
+    let auto_extend = config.auto_extend.is_some();
+    let encoder = Encoder::with_config(config);
 
     // Encode the message
     println!("Encoding message into image: {}", image_path.display());
-    encoder
-        .encode_file(image_path, &message, output_path)
-        .expect("Failed to encode message");
+    let stats = match encoder.encode_file(image_path, &message, output_path) {
+        Ok(stats) => stats,
+        Err(e) => {
+            eprintln!("Error: Failed to encode message: {}", e);
+            std::process::exit(1);
+        }
+    };
+    print_warnings(&stats.warnings);
     println!("Message successfully hidden in: {}", output_path.display());
+
+    if auto_extend {
+        if let (Ok(cover), Ok(stego)) = (
+            image::image_dimensions(image_path),
+            image::image_dimensions(output_path),
+        ) {
+            if cover != stego {
+                println!(
+                    "Cover extended from {}x{} to {}x{} to fit the message",
+                    cover.0, cover.1, stego.0, stego.1
+                );
+            }
+        }
+    }
+
+    if emit_manifest {
+        println!(
+            "Manifest written to: {}",
+            manifest::manifest_path_for(output_path).display()
+        );
+    }
+}
+
+/// Read the message from a file, or take it from the command line
+fn read_message(message_text: &str, message_file: &Option<PathBuf>) -> Vec<u8> {
+    match message_file {
+        Some(file_path) => fs::read(file_path)
+            .unwrap_or_else(|_| panic!("Failed to read message file: {}", file_path.display())),
+        None => message_text.as_bytes().to_vec(),
+    }
+}
+
+/// Hide a message in the DCT coefficients of a JPEG cover with the experimental algorithm
+#[cfg(feature = "jpeg")]
+fn encode_jpeg(
+    image_path: &Path,
+    message_text: &str,
+    output_path: &Path,
+    message_file: &Option<PathBuf>,
+    create_dirs: bool,
+) {
+    let is_jpeg_path = output_path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("jpg") || ext.eq_ignore_ascii_case("jpeg"));
+    if !is_jpeg_path {
+        eprintln!("Error: --algorithm jpeg-dct writes JPEG files; use a .jpg or .jpeg output path");
+        std::process::exit(1);
+    }
+
+    let message = read_message(message_text, message_file);
+    println!("Message size: {} bytes", message.len());
+
+    let cover = fs::read(image_path).unwrap_or_else(|e| {
+        eprintln!("Error: Failed to read {}: {}", image_path.display(), e);
+        std::process::exit(1);
+    });
+    if let Err(e) = hide_rs::img::prepare_output_path(output_path, create_dirs) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+
+    println!("Encoding message into JPEG: {}", image_path.display());
+    let stego = match JpegStego::new().encode(&cover, &message) {
+        Ok(stego) => stego,
+        Err(e) => {
+            eprintln!("Error: Failed to encode message: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = fs::write(output_path, stego) {
+        eprintln!("Error: Failed to write {}: {}", output_path.display(), e);
+        std::process::exit(1);
+    }
+    println!("Message successfully hidden in: {}", output_path.display());
+}
+
+/// Verify a stego image against its manifest, exiting with an error on mismatch
+fn verify_manifest(
+    image_path: &PathBuf,
+    manifest_file: &Option<PathBuf>,
+    report_file: &Option<PathBuf>,
+) {
+    let manifest_path = manifest_file
+        .clone()
+        .unwrap_or_else(|| manifest::manifest_path_for(image_path));
+    println!(
+        "Verifying {} against {}",
+        image_path.display(),
+        manifest_path.display()
+    );
+
+    let mut run = ReportRun::new("verify", std::env::args().skip(1).collect());
+
+    let verification =
+        decoder::verify_manifest(image_path, &manifest_path).expect("Failed to verify manifest");
+
+    for mismatch in &verification.mismatches {
+        println!(
+            "Mismatch: {}",
+            utils::sanitize_for_terminal(&mismatch.to_string())
+        );
+    }
+
+    // Append this run to the report if requested
+    if let Some(report_path) = report_file {
+        let mut file_result = FileResult::new(image_path);
+        file_result.detected = verification.is_valid();
+        if !verification.is_valid() {
+            file_result.error = Some(verification.mismatches.join("; "));
+        }
+        run.files.push(file_result);
+        run.finish();
+        report::append_run(report_path, run).expect("Failed to write report file");
+        println!("Report written to: {}", report_path.display());
+    }
+
+    if !verification.is_valid() {
+        println!("Verification FAILED");
+        std::process::exit(1);
+    }
+
+    println!("Verification OK");
+}
+
+/// Append a run to the report file, if one was requested
+fn append_report(report_file: &Option<PathBuf>, mut run: ReportRun, file_result: FileResult) {
+    if let Some(report_path) = report_file {
+        run.files.push(file_result);
+        run.finish();
+        report::append_run(report_path, run).expect("Failed to write report file");
+        println!("Report written to: {}", report_path.display());
+    }
+}
+
+/// Bytes of a decoded message shown in the terminal before the rest is left out
+const DISPLAY_LIMIT: usize = 4096;
+
+/// Largest message `decode --json` inlines by default, the same as the server's
+/// default `max_inline_decode_bytes`
+const DEFAULT_MAX_INLINE_BYTES: usize = 256 * 1024;
+
+/// Largest message `decode` writes to a file without asking first
+const DEFAULT_MAX_OUTPUT_BYTES: usize = 100 << 20;
+
+/// How large a message `decode` writes to disk before asking first
+///
+/// An image can declare a message as large as it holds, so a crafted one
+/// could otherwise fill the disk.
+#[derive(Debug, Clone, Copy)]
+struct OutputLimit {
+    /// Largest message written without confirmation
+    max_bytes: usize,
+
+    /// Write larger messages without asking, as `--yes` does
+    assume_yes: bool,
+}
+
+impl OutputLimit {
+    /// Let a message of `bytes` through, asking first if it is over the limit
+    ///
+    /// Asking needs a terminal on stdin; without one, or if the answer isn't
+    /// yes, the decode stops. Once confirmed, later checks pass.
+    fn check(&mut self, bytes: usize) {
+        if bytes <= self.max_bytes || self.assume_yes {
+            return;
+        }
+
+        if !std::io::stdin().is_terminal() {
+            eprintln!(
+                "Error: the image declares a {}-byte message, over the {}-byte output limit",
+                bytes, self.max_bytes
+            );
+            eprintln!("Pass --yes to write it anyway, or raise --max-output-bytes");
+            std::process::exit(1);
+        }
+
+        eprint!(
+            "The image declares a {}-byte message, over the {}-byte output limit. Write it anyway? [y/N] ",
+            bytes, self.max_bytes
+        );
+        let mut answer = String::new();
+        let confirmed = std::io::stdin().read_line(&mut answer).is_ok()
+            && matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes");
+        if !confirmed {
+            eprintln!("Aborted");
+            std::process::exit(1);
+        }
+        self.assume_yes = true;
+    }
+}
+
+/// How `decode` extracts the message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DecodeMode {
+    /// Validate the header and decode the whole message
+    Standard,
+
+    /// Extract every embedded bit without validating a header
+    Raw,
+
+    /// Recover what is left of the message in a truncated image
+    Partial,
+
+    /// Look for a payload that doesn't start at the first pixel
+    Search,
+
+    /// Flip corrupt header bits back until the payload decodes
+    Repair,
+
+    /// Read the message from the DCT coefficients of a JPEG file
+    #[cfg(feature = "jpeg")]
+    Jpeg,
+}
+
+/// Where `decode` sends the message
+#[derive(Debug, Clone)]
+enum DecodeOutput {
+    /// Print the message, as hex when `hex` is set or it isn't text
+    Display { hex: bool, sanitize: bool },
+
+    /// Write the message to a file
+    File(PathBuf),
+
+    /// Print a JSON document carrying the message, or the path of a file
+    /// holding it when it is written out instead
+    Json {
+        payload_file: Option<PathBuf>,
+        max_inline_bytes: usize,
+    },
+}
+
+/// JSON document printed by `decode --json`
+///
+/// Field names follow the server's decode response, so the same code can read
+/// either; `payload_path` plays the part of the server's `download_url`.
+#[derive(Serialize)]
+struct DecodeJson {
+    /// The message, if it is valid UTF-8 text and inline
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+
+    /// The message base64 encoded, if inline
+    #[serde(skip_serializing_if = "Option::is_none")]
+    binary_message: Option<String>,
+
+    /// Length of the message in bytes
+    length: usize,
+
+    /// Whether the message is included in the document
+    inline: bool,
+
+    /// File the message was written to instead of being inlined
+    #[serde(skip_serializing_if = "Option::is_none")]
+    payload_path: Option<PathBuf>,
+
+    /// SHA-256 of the message as lowercase hex, when written to a file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    payload_sha256: Option<String>,
+
+    /// Whether the full declared message was recovered (only for partial decodes)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    complete: Option<bool>,
+}
+
+/// Print what an encode or decode noticed to stderr, one `warning:` line each
+fn print_warnings(warnings: &[StegoWarning]) {
+    for warning in warnings {
+        eprintln!(
+            "warning: {}",
+            utils::sanitize_for_terminal(&warning.to_string())
+        );
+    }
 }
 
 /// Decode a message from an image and display it in the console
 fn decode_message(
+    decoder: &Decoder,
     image_path: &PathBuf,
-    show_hex: bool,
-    raw_mode: bool,
-    output_file: &Option<PathBuf>,
+    mode: DecodeMode,
+    output: &DecodeOutput,
+    report_file: &Option<PathBuf>,
+    limit: OutputLimit,
 ) {
-    println!("Extracting hidden message from: {}", image_path.display());
+    let json = matches!(output, DecodeOutput::Json { .. });
+    // Messages only shown in the terminal are never written out whole
+    let mut limit = match output {
+        DecodeOutput::Display { .. } => OutputLimit {
+            max_bytes: usize::MAX,
+            ..limit
+        },
+        DecodeOutput::File(_) | DecodeOutput::Json { .. } => limit,
+    };
+    // In JSON mode stdout carries only the document, so progress goes to stderr
+    let note = |text: String| {
+        if json {
+            eprintln!("{}", text);
+        } else {
+            println!("{}", text);
+        }
+    };
+    note(format!(
+        "Extracting hidden message from: {}",
+        image_path.display()
+    ));
+    let run = ReportRun::new("decode", std::env::args().skip(1).collect());
+
+    // The JPEG payload is in the file's coefficients, so the pixels aren't loaded
+    #[cfg(feature = "jpeg")]
+    if mode == DecodeMode::Jpeg {
+        let data = fs::read(image_path).expect("Failed to read image");
+        let message = match JpegStego::new().decode(&data) {
+            Ok(message) => message,
+            Err(e) => {
+                eprintln!("Error: {}", utils::sanitize_for_terminal(&e.to_string()));
+                std::process::exit(1);
+            }
+        };
+        note(format!("Message size: {} bytes", message.len()));
+        limit.check(message.len());
+
+        let mut file_result = FileResult::new(image_path);
+        file_result.detected = true;
+        append_report(report_file, run, file_result);
+        show_decoded_message(image_path, mode, &message, None, output);
+        return;
+    }
 
     // Load the stego image
-    let stego_image =
-        hide_rs::img::StegoImage::from_file(image_path).expect("Failed to load image");
+    let stego_image = StegoImage::from_file(image_path).expect("Failed to load image");
 
-    let decoded_message = if raw_mode {
-        // Use raw decoder to extract all data without header validation
+    // Record what the header says before decoding
+    let mut file_result = FileResult::new(image_path);
+    if let Ok(header) = create_decoder().read_header(&stego_image) {
+        file_result.detected = true;
+        file_result.header = Some(header.into());
+
+        // Raw extraction ignores the header, and is bounded by the image anyway
+        if mode != DecodeMode::Raw {
+            limit.check(header.message_length as usize);
+        }
+    }
+
+    // Stream raw data straight to the output file instead of holding it in memory
+    if let (DecodeMode::Raw, DecodeOutput::File(output_path)) = (mode, output) {
         println!("Using raw extraction mode (ignoring header format)");
-        raw_decoder::extract_raw_data(&stego_image).expect("Failed to extract raw data")
+        let file = fs::File::create(output_path).expect("Failed to create output file");
+        let written = extract_raw_to_writer(
+            &stego_image,
+            BufWriter::new(file),
+            RawExtractOptions::default(),
+        )
+        .expect("Failed to extract raw data");
+        println!("Message size: {} bytes", written);
+
+        append_report(report_file, run, file_result);
+        println!("Output written to: {}", output_path.display());
+
+        // Only the bytes shown in the preview are extracted again
+        let mut head = Vec::new();
+        extract_raw_to_writer(
+            &stego_image,
+            &mut head,
+            RawExtractOptions {
+                max_bytes: Some(32),
+            },
+        )
+        .expect("Failed to extract raw data");
+        println!("\n{}", raw_decoder::format_data_preview(&head, 32));
+        return;
+    }
+
+    let mut complete = None;
+    let decoded_message = if mode == DecodeMode::Raw {
+        // Use raw decoder to extract all data without header validation
+        note("Using raw extraction mode (ignoring header format)".to_string());
+        extract_raw_data(&stego_image).expect("Failed to extract raw data")
+    } else if mode == DecodeMode::Search {
+        match decoder.search_and_decode(&stego_image, SearchWindow::default()) {
+            Ok(found) => {
+                let (x, y) = found.position;
+                note(format!(
+                    "Payload found at pixel ({}, {}), offset {}",
+                    x, y, found.pixel_offset
+                ));
+                print_warnings(&found.warnings);
+                found.data
+            }
+            Err(e) => {
+                eprintln!("Error: {}", utils::sanitize_for_terminal(&e.to_string()));
+                std::process::exit(1);
+            }
+        }
+    } else if mode == DecodeMode::Repair {
+        match decoder.decode_with_repair(&stego_image) {
+            Ok(found) => {
+                if !found.repaired_bits.is_empty() {
+                    let bits: Vec<String> =
+                        found.repaired_bits.iter().map(usize::to_string).collect();
+                    note(format!("Repaired header bit(s): {}", bits.join(", ")));
+                }
+                print_warnings(&found.warnings);
+                found.data
+            }
+            Err(e) => {
+                eprintln!("Error: {}", utils::sanitize_for_terminal(&e.to_string()));
+                std::process::exit(1);
+            }
+        }
+    } else if mode == DecodeMode::Partial {
+        // Use lossy decoder to recover whatever part of the message is present,
+        // warning if the image appears truncated
+        let detailed = decoder
+            .decode_detailed(&stego_image, true)
+            .expect("Failed to decode message");
+        print_warnings(&detailed.warnings);
+        complete = Some(detailed.decoded.complete);
+
+        detailed.decoded.data
     } else {
         // Use standard decoder
-        let decoder = create_decoder();
-        decoder
-            .decode(&stego_image)
-            .expect("Failed to decode message")
+        match decoder.decode_detailed(&stego_image, false) {
+            Ok(detailed) => {
+                print_warnings(&detailed.warnings);
+                detailed.decoded.data
+            }
+            Err(HideError::TruncatedPayload {
+                declared,
+                available,
+            }) => {
+                eprintln!(
+                    "Error: the header declares {} bytes but the image only holds {}",
+                    declared, available
+                );
+                eprintln!("The image may have been cropped; use --partial to recover what is left");
+                std::process::exit(1);
+            }
+            Err(e @ HideError::DimensionsChanged { .. }) => {
+                eprintln!("Error: {}", utils::sanitize_for_terminal(&e.to_string()));
+                eprintln!("Decode the file produced by the encode, not a resized copy of it");
+                std::process::exit(1);
+            }
+            Err(e) => {
+                eprintln!("Error: {}", utils::sanitize_for_terminal(&e.to_string()));
+                std::process::exit(1);
+            }
+        }
     };
 
-    println!("Message size: {} bytes", decoded_message.len());
+    note(format!("Message size: {} bytes", decoded_message.len()));
+    // Compressed messages are only known to be this large once inflated
+    if mode != DecodeMode::Raw {
+        limit.check(decoded_message.len());
+    }
 
-    // Save to file if output was specified
-    if let Some(output_path) = output_file {
-        fs::write(output_path, &decoded_message).expect("Failed to write output file");
-        println!("Output written to: {}", output_path.display());
+    append_report(report_file, run, file_result);
+    show_decoded_message(image_path, mode, &decoded_message, complete, output);
+}
 
-        if raw_mode {
-            // Also generate and display a data preview
-            let preview = raw_decoder::format_data_preview(&decoded_message, 32);
-            println!("\n{}", preview);
+/// Send a decoded message where `decode` was asked to
+fn show_decoded_message(
+    image_path: &Path,
+    mode: DecodeMode,
+    decoded_message: &[u8],
+    complete: Option<bool>,
+    output: &DecodeOutput,
+) {
+    let (show_hex, sanitize) = match output {
+        DecodeOutput::Display { hex, sanitize } => (*hex, *sanitize),
+        DecodeOutput::File(output_path) => {
+            fs::write(output_path, decoded_message).expect("Failed to write output file");
+            println!("Output written to: {}", output_path.display());
+            return; // Don't display content when saving to file
         }
-
-        return; // Don't display content when saving to file
-    }
+        DecodeOutput::Json {
+            payload_file,
+            max_inline_bytes,
+        } => {
+            print_decode_json(
+                image_path,
+                decoded_message,
+                complete,
+                payload_file.as_deref(),
+                *max_inline_bytes,
+            );
+            return;
+        }
+    };
 
     // Display content according to mode and type
-    if raw_mode {
+    if mode == DecodeMode::Raw {
         // In raw mode, always show data analysis
-        let preview = raw_decoder::format_data_preview(&decoded_message, 32);
+        let preview = raw_decoder::format_data_preview(decoded_message, 32);
         println!("\n{}", preview);
     } else {
         // In normal mode, try to display as text if possible
-        match std::str::from_utf8(&decoded_message) {
+        match std::str::from_utf8(decoded_message) {
             Ok(message_str) if !show_hex => {
                 // Message is valid UTF-8 and we're not forcing hex display
                 println!("\n----- DECODED MESSAGE -----");
-                println!("{}", message_str);
+                if sanitize {
+                    let (shown, cut) = utils::truncate_for_display(message_str, DISPLAY_LIMIT);
+                    println!("{}", utils::sanitize_for_terminal(shown));
+                    print_cut_trailer(cut);
+                } else {
+                    println!("{}", message_str);
+                }
                 println!("-------------------------\n");
             }
             _ => {
                 // Message is binary or we want hex display
+                let shown = if sanitize {
+                    decoded_message.len().min(DISPLAY_LIMIT)
+                } else {
+                    decoded_message.len()
+                };
                 println!("\n----- BINARY MESSAGE (hex) -----");
-                for (i, byte) in decoded_message.iter().enumerate() {
+                for (i, byte) in decoded_message.iter().take(shown).enumerate() {
                     print!("{:02x} ", byte);
                     // Add line break every 16 bytes for readability
                     if (i + 1) % 16 == 0 {
@@ -176,11 +1758,110 @@ fn decode_message(
                     }
                 }
                 // Ensure we end with a newline
-                if decoded_message.len() % 16 != 0 {
+                if shown % 16 != 0 {
                     println!();
                 }
+                print_cut_trailer(decoded_message.len() - shown);
                 println!("--------------------------------\n");
             }
         }
     }
 }
+
+/// Print the JSON document of `decode --json`
+///
+/// The message is inlined unless a payload file was given or it is larger than
+/// `max_inline_bytes`, in which case it goes to a new file next to the image.
+fn print_decode_json(
+    image_path: &Path,
+    message: &[u8],
+    complete: Option<bool>,
+    payload_file: Option<&Path>,
+    max_inline_bytes: usize,
+) {
+    let payload_path = match payload_file {
+        Some(path) => Some(write_new_file(path, message)),
+        None if message.len() > max_inline_bytes => Some(write_payload_beside(image_path, message)),
+        None => None,
+    };
+
+    let document = match payload_path {
+        Some(path) => DecodeJson {
+            message: None,
+            binary_message: None,
+            length: message.len(),
+            inline: false,
+            payload_path: Some(path),
+            payload_sha256: Some(manifest::sha256_hex(message)),
+            complete,
+        },
+        None => DecodeJson {
+            message: String::from_utf8(message.to_vec()).ok(),
+            binary_message: Some(BASE64.encode(message)),
+            length: message.len(),
+            inline: true,
+            payload_path: None,
+            payload_sha256: None,
+            complete,
+        },
+    };
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&document).expect("Failed to serialize decode result")
+    );
+}
+
+/// Write data to a file that must not exist yet, exiting if it does
+fn write_new_file(path: &Path, data: &[u8]) -> PathBuf {
+    let mut file = match fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+    {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            eprintln!(
+                "Error: {} already exists; remove it or choose another --payload-file",
+                path.display()
+            );
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("Error: failed to create {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    };
+    file.write_all(data).expect("Failed to write payload file");
+    path.to_path_buf()
+}
+
+/// Write a decoded message to a new file in the image's directory
+///
+/// The file is named after the image, e.g. `stego.payload-a1B2c3.bin`, and is
+/// kept after the command exits.
+fn write_payload_beside(image_path: &Path, data: &[u8]) -> PathBuf {
+    let dir = match image_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let stem = image_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut file = tempfile::Builder::new()
+        .prefix(&format!("{}.payload-", stem))
+        .suffix(".bin")
+        .tempfile_in(dir)
+        .expect("Failed to create payload file");
+    file.write_all(data).expect("Failed to write payload file");
+    let (_, path) = file.keep().expect("Failed to keep payload file");
+    path
+}
+
+/// Note how many bytes of a message were left out of the display
+fn print_cut_trailer(cut: usize) {
+    if cut > 0 {
+        println!("\u{2026} ({} more bytes, use --output)", cut);
+    }
+}
@@ -5,14 +5,34 @@ use actix_web::{middleware::Logger, App, HttpServer};
 use anyhow::Result;
 use dotenv::dotenv;
 use hide_rs::api::{
-    handlers::{AppState, ServerConfig},
+    handlers::{response_timing, AppState, ServerConfig},
     routes::configure_routes,
 };
 use log::{error, info};
 use std::io;
+use std::time::Duration;
 
 // Remove the ServerConfig definition since we're now using the one from handlers
 
+/// Access log format: the default fields plus the timing of encode/decode work
+const ACCESS_LOG_FORMAT: &str = "%a \"%r\" %s %b \"%{Referer}i\" \"%{User-Agent}i\" %T \
+     processing_ms=%{processing_ms}xo pixels_per_second=%{pixels_per_second}xo";
+
+/// Create the access logger
+fn access_logger() -> Logger {
+    Logger::new(ACCESS_LOG_FORMAT)
+        .custom_response_replace("processing_ms", |res| {
+            response_timing(res.response())
+                .map_or_else(|| "-".to_string(), |t| format!("{:.3}", t.processing_ms()))
+        })
+        .custom_response_replace("pixels_per_second", |res| {
+            response_timing(res.response()).map_or_else(
+                || "-".to_string(),
+                |t| format!("{:.0}", t.pixels_per_second()),
+            )
+        })
+}
+
 /// Load configuration from environment or file
 fn load_config() -> Result<ServerConfig> {
     // First try to load .env file if present
@@ -36,6 +56,102 @@ fn load_config() -> Result<ServerConfig> {
         config.upload_dir = upload_dir;
     }
 
+    if let Ok(legacy_str) = std::env::var("HIDE_ENABLE_LEGACY_ROUTES") {
+        if let Ok(enabled) = legacy_str.parse::<bool>() {
+            config.enable_legacy_routes = enabled;
+        }
+    }
+
+    if let Ok(entries_str) = std::env::var("HIDE_DECODE_CACHE_ENTRIES") {
+        if let Ok(entries) = entries_str.parse::<usize>() {
+            config.decode_cache_entries = entries;
+        }
+    }
+
+    if let Ok(bytes_str) = std::env::var("HIDE_DECODE_CACHE_BYTES") {
+        if let Ok(bytes) = bytes_str.parse::<usize>() {
+            config.decode_cache_bytes = bytes;
+        }
+    }
+
+    if let Ok(base_url) = std::env::var("HIDE_PUBLIC_BASE_URL") {
+        config.public_base_url = Some(base_url);
+    }
+
+    if let Ok(trust_str) = std::env::var("HIDE_TRUST_PROXY") {
+        if let Ok(trust) = trust_str.parse::<bool>() {
+            config.trust_proxy = trust;
+        }
+    }
+
+    if let Ok(bytes_str) = std::env::var("HIDE_DECODE_MAX_OUTPUT_BYTES") {
+        if let Ok(bytes) = bytes_str.parse::<usize>() {
+            config.decode_max_output_bytes = bytes;
+        }
+    }
+
+    if let Ok(pixels_str) = std::env::var("HIDE_DECODE_MAX_PIXELS") {
+        if let Ok(pixels) = pixels_str.parse::<u64>() {
+            config.decode_max_pixels = pixels;
+        }
+    }
+
+    if let Ok(deadline_str) = std::env::var("HIDE_DECODE_DEADLINE_MS") {
+        if let Ok(deadline) = deadline_str.parse::<u64>() {
+            config.decode_deadline_ms = deadline;
+        }
+    }
+
+    if let Ok(pool_dir) = std::env::var("HIDE_COVER_POOL_DIR") {
+        config.cover_pool_dir = Some(pool_dir);
+    }
+
+    if let Ok(refresh_str) = std::env::var("HIDE_COVER_POOL_REFRESH_SECS") {
+        if let Ok(refresh) = refresh_str.parse::<u64>() {
+            config.cover_pool_refresh_secs = refresh;
+        }
+    }
+
+    if let Ok(bytes_str) = std::env::var("HIDE_MAX_INLINE_DECODE_BYTES") {
+        if let Ok(bytes) = bytes_str.parse::<usize>() {
+            config.max_inline_decode_bytes = bytes;
+        }
+    }
+
+    if let Ok(window_str) = std::env::var("HIDE_IDEMPOTENCY_WINDOW_SECS") {
+        if let Ok(window) = window_str.parse::<u64>() {
+            config.idempotency_window_secs = window;
+        }
+    }
+
+    if let Ok(bytes_str) = std::env::var("HIDE_MAX_SPOOL_BYTES") {
+        if let Ok(bytes) = bytes_str.parse::<u64>() {
+            config.max_spool_bytes = bytes;
+        }
+    }
+
+    if let Ok(ttl_str) = std::env::var("HIDE_DECODED_MESSAGE_TTL_SECS") {
+        if let Ok(ttl) = ttl_str.parse::<u64>() {
+            config.decoded_message_ttl_secs = ttl;
+        }
+    }
+
+    if let Ok(secret) = std::env::var("HIDE_URL_SIGNING_SECRET") {
+        config.url_signing_secret = Some(secret);
+    }
+
+    if let Ok(ttl_str) = std::env::var("HIDE_SIGNED_URL_TTL_SECS") {
+        if let Ok(ttl) = ttl_str.parse::<u64>() {
+            config.signed_url_ttl_secs = ttl;
+        }
+    }
+
+    if let Ok(debug_str) = std::env::var("HIDE_ALLOW_DEBUG_RESPONSES") {
+        if let Ok(allow) = debug_str.parse::<bool>() {
+            config.allow_debug_responses = allow;
+        }
+    }
+
     // Create upload directory if it doesn't exist
     let upload_dir = std::path::Path::new(&config.upload_dir);
     if !upload_dir.exists() {
@@ -55,21 +171,25 @@ async fn main() -> io::Result<()> {
         Ok(cfg) => cfg,
         Err(err) => {
             error!("Failed to load configuration: {}", err);
-            return Err(io::Error::new(io::ErrorKind::Other, "Configuration error"));
+            return Err(io::Error::other("Configuration error"));
         }
     };
 
     // Create application state
-    let state = actix_web::web::Data::new(AppState {
-        config: config.clone(),
-    });
+    let state = actix_web::web::Data::new(AppState::new(config.clone()));
+
+    // Keep the cover pool listing fresh
+    state
+        .cover_pool
+        .clone()
+        .spawn_refresh(Duration::from_secs(config.cover_pool_refresh_secs));
 
     // Start server
     info!("Starting server at http://{}:{}", config.host, config.port);
 
     HttpServer::new(move || {
         App::new()
-            .wrap(Logger::default())
+            .wrap(access_logger())
             .app_data(state.clone())
             .configure(configure_routes)
             .service(Files::new("/static", "./static").show_files_listing())
@@ -81,7 +201,7 @@ async fn main() -> io::Result<()> {
 
 #[cfg(test)]
 mod tests {
-    
+
     use actix_web::{test, web, App};
     use hide_rs::api::handlers::HealthResponse;
     use hide_rs::api::routes::{health_check, ping};
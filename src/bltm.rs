@@ -1,11 +1,66 @@
 //! Binary Lower Triangular Matrix (BLTM) operations for steganography
 
+use crate::error::HideError;
+use crate::Result;
 use bitvec::prelude::*;
 
+/// Binary lower triangular matrix of any size, all ones on and below the diagonal
+///
+/// Multiplying by it makes each output bit the XOR of the input bits up to
+/// its position. [`BLTM3x3`] is the 3x3 case, written out as a lookup table.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Bltm<const N: usize>;
+
+/// 4x4 BLTM used to embed in the RGBA channels of a pixel
+pub type BLTM4x4 = Bltm<4>;
+
+impl<const N: usize> Bltm<N> {
+    /// Create a new NxN BLTM
+    pub fn new() -> Self {
+        Bltm
+    }
+
+    /// Multiply a vector by the matrix
+    ///
+    /// # Arguments
+    /// * `v` - The vector, one bit per channel
+    ///
+    /// # Returns
+    /// * The product, with bit i the XOR of `v[0..=i]`
+    pub fn multiply(&self, v: [bool; N]) -> [bool; N] {
+        let mut acc = false;
+        v.map(|bit| {
+            acc ^= bit;
+            acc
+        })
+    }
+
+    /// Find the vector whose product is `delta`
+    ///
+    /// This inverts [`Bltm::multiply`]; it plays the role of
+    /// [`BLTM3x3::lookup_vn`] for any size.
+    ///
+    /// # Arguments
+    /// * `delta` - The required product
+    ///
+    /// # Returns
+    /// * Vn, with bit i set where `delta` changes between positions i-1 and i
+    pub fn solve(&self, delta: [bool; N]) -> [bool; N] {
+        let mut previous = false;
+        delta.map(|bit| {
+            let vn = bit ^ previous;
+            previous = bit;
+            vn
+        })
+    }
+}
+
 /// Simple 3x3 Binary Lower Triangular Matrix implementation
 #[derive(Debug, Clone)]
 pub struct BLTM3x3;
 
+static_assertions::assert_impl_all!(BLTM3x3: Send, Sync);
+
 impl Default for BLTM3x3 {
     fn default() -> Self {
         Self::new()
@@ -48,12 +103,22 @@ impl BLTM3x3 {
     }
 
     /// Simple lookup function to find Vn given delta
-    pub fn lookup_vn(&self, delta: &BitSlice<u8, Msb0>) -> BitVec<u8, Msb0> {
+    ///
+    /// # Returns
+    /// * Vn, or `InvalidParameters` if delta is not exactly 3 bits
+    pub fn lookup_vn(&self, delta: &BitSlice<u8, Msb0>) -> Result<BitVec<u8, Msb0>> {
+        if delta.len() != 3 {
+            return Err(HideError::InvalidParameters(format!(
+                "BLTM delta must be 3 bits, got {}",
+                delta.len()
+            )));
+        }
+
         // Convert input to u8 for easier lookup
         let delta_val = Self::bits_to_u8(delta);
 
         // Hardcoded lookup table based on the provided example
-        match delta_val {
+        let vn = match delta_val {
             0 => {
                 let mut bv = BitVec::new();
                 bv.push(false);
@@ -110,23 +175,29 @@ impl BLTM3x3 {
                 bv.push(false);
                 bv
             }
-            _ => panic!("Invalid delta value"),
-        }
+            _ => {
+                return Err(HideError::InvalidParameters(format!(
+                    "Invalid BLTM delta: {}",
+                    delta_val
+                )))
+            }
+        };
+        Ok(vn)
     }
 
     /// Lookup function that accepts Vec<bool> for compatibility
-    pub fn lookup_vn_vec(&self, delta: &[bool]) -> Vec<bool> {
+    ///
+    /// # Returns
+    /// * Vn, or `InvalidParameters` if delta is not exactly 3 bits
+    pub fn lookup_vn_vec(&self, delta: &[bool]) -> Result<Vec<bool>> {
         // Convert Vec<bool> to BitVec
-        let mut delta_bits = BitVec::<u8, Msb0>::new();
-        for &bit in delta.iter().take(3) {
-            delta_bits.push(bit);
-        }
+        let delta_bits: BitVec<u8, Msb0> = delta.iter().copied().collect();
 
         // Use the BitVec version of lookup_vn
-        let result_bits = self.lookup_vn(&delta_bits);
+        let result_bits = self.lookup_vn(&delta_bits)?;
 
         // Convert back to Vec<bool>
-        result_bits.iter().map(|b| *b).collect()
+        Ok(result_bits.iter().map(|b| *b).collect())
     }
 
     /// Helper function to convert Vec<bool> to u8 (for backward compatibility)
@@ -198,7 +269,7 @@ mod tests {
             delta.push((*val & 2) != 0);
             delta.push((*val & 1) != 0);
 
-            let vn = bltm.lookup_vn(&delta);
+            let vn = bltm.lookup_vn(&delta).unwrap();
 
             // Convert expected to BitVec for comparison
             let mut expected_bits = BitVec::<u8, Msb0>::new();
@@ -231,7 +302,7 @@ mod tests {
         ];
 
         for (delta, expected_vn) in test_cases.iter() {
-            let vn = bltm.lookup_vn_vec(delta);
+            let vn = bltm.lookup_vn_vec(delta).unwrap();
             assert_eq!(
                 vn, *expected_vn,
                 "For delta {:?}, expected Vn {:?}, got {:?}",
@@ -240,6 +311,49 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_lookup_rejects_wrong_delta_length() {
+        let bltm = BLTM3x3::new();
+        for len in [0, 2, 4, 8] {
+            let delta = BitVec::<u8, Msb0>::repeat(true, len);
+            assert!(matches!(
+                bltm.lookup_vn(&delta),
+                Err(HideError::InvalidParameters(_))
+            ));
+            assert!(bltm.lookup_vn_vec(&vec![false; len]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_generic_bltm_matches_3x3_table() {
+        let bltm = BLTM3x3::new();
+        let generic = Bltm::<3>::new();
+        for val in 0..8 {
+            let delta = BLTM3x3::u8_to_bits(val);
+            let delta_array = [delta[0], delta[1], delta[2]];
+            let vn: Vec<bool> = generic.solve(delta_array).to_vec();
+            assert_eq!(
+                vn,
+                BLTM3x3::u8_to_bin(BLTM3x3::bits_to_u8(&bltm.lookup_vn(&delta).unwrap()))
+            );
+            assert_eq!(generic.multiply(generic.solve(delta_array)), delta_array);
+        }
+    }
+
+    #[test]
+    fn test_4x4_solve_inverts_multiply() {
+        let bltm = BLTM4x4::new();
+        for val in 0u8..16 {
+            let v = [val & 8 != 0, val & 4 != 0, val & 2 != 0, val & 1 != 0];
+            assert_eq!(bltm.solve(bltm.multiply(v)), v);
+            assert_eq!(bltm.multiply(bltm.solve(v)), v);
+        }
+        assert_eq!(
+            bltm.multiply([true, false, true, true]),
+            [true, true, false, true]
+        );
+    }
+
     #[test]
     fn test_binary_conversion() {
         // Test u8_to_bits and bits_to_u8
@@ -0,0 +1,75 @@
+//! Cooperative cancellation of long-running encode and decode operations
+
+use crate::error::HideError;
+use crate::Result;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Number of pixels processed between cancellation checks
+pub const CHECK_INTERVAL: usize = 4096;
+
+/// Shared flag used to ask an operation to stop early
+///
+/// Clones share the same flag, so a token can be handed to an encoder and
+/// cancelled from another thread.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Create a token that is not cancelled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request cancellation of every operation holding this token
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Check whether cancellation was requested
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Fail with [`HideError::Cancelled`] if cancellation was requested
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            Err(HideError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Check an optional token every [`CHECK_INTERVAL`] pixels
+///
+/// # Arguments
+/// * `token` - The token to check, if any
+/// * `pixel_index` - Index of the pixel about to be processed
+pub(crate) fn check_every(token: Option<&CancellationToken>, pixel_index: usize) -> Result<()> {
+    match token {
+        Some(token) if pixel_index.is_multiple_of(CHECK_INTERVAL) => token.check(),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clones_share_cancellation() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(clone.check().is_ok());
+
+        token.cancel();
+        assert!(clone.is_cancelled());
+        assert!(matches!(clone.check(), Err(HideError::Cancelled)));
+
+        // Only pixels on the interval boundary are checked
+        assert!(check_every(Some(&clone), 1).is_ok());
+        assert!(check_every(Some(&clone), CHECK_INTERVAL).is_err());
+        assert!(check_every(None, 0).is_ok());
+    }
+}
@@ -0,0 +1,265 @@
+//! What this build of hide-rs supports, for clients to check before sending requests
+
+use crate::error::HideError;
+use image::ImageFormat;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Embedding algorithms this build can encode and decode
+#[cfg(not(feature = "jpeg"))]
+pub const ALGORITHMS: &[&str] = &["bltm3x3", "bltm4x4"];
+
+/// Embedding algorithms this build can encode and decode
+#[cfg(feature = "jpeg")]
+pub const ALGORITHMS: &[&str] = &["bltm3x3", "bltm4x4", "jpeg-dct"];
+
+/// Optional features and whether this build supports them
+///
+/// Keys are stable; new features are added as new keys rather than by
+/// changing the meaning of existing ones.
+const FEATURES: &[(&str, bool)] = &[
+    ("async_jobs", false),
+    ("batch", false),
+    ("change_budget", true),
+    ("compression", true),
+    ("containers", true),
+    ("encryption", false),
+    ("jpeg_dct", cfg!(feature = "jpeg")),
+    ("partial_decode", true),
+    ("streams", true),
+    ("watermark", true),
+];
+
+/// Algorithm the CLI and the API embed with or extract from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Algorithm {
+    /// BLTM in the pixels of a lossless image
+    #[default]
+    #[serde(rename = "bltm3x3")]
+    Bltm,
+
+    /// Experimental embedding in the DCT coefficients of a baseline JPEG file,
+    /// see [`JpegStego`](crate::jpeg::JpegStego)
+    #[cfg(feature = "jpeg")]
+    #[serde(rename = "jpeg-dct")]
+    JpegDct,
+}
+
+impl Algorithm {
+    /// The name used by the CLI, the API and [`ALGORITHMS`]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Bltm => "bltm3x3",
+            #[cfg(feature = "jpeg")]
+            Self::JpegDct => "jpeg-dct",
+        }
+    }
+
+    /// Extension of the files the algorithm writes
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Bltm => "png",
+            #[cfg(feature = "jpeg")]
+            Self::JpegDct => "jpg",
+        }
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = HideError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "bltm3x3" | "bltm" => Ok(Self::Bltm),
+            #[cfg(feature = "jpeg")]
+            "jpeg-dct" => Ok(Self::JpegDct),
+            #[cfg(not(feature = "jpeg"))]
+            "jpeg-dct" => Err(HideError::InvalidParameters(
+                "The jpeg-dct algorithm needs hide-rs built with the jpeg feature".to_string(),
+            )),
+            _ => Err(HideError::InvalidParameters(format!(
+                "Unknown algorithm '{}'; expected one of bltm3x3, jpeg-dct",
+                s.trim()
+            ))),
+        }
+    }
+}
+
+/// Versions, features and limits of a hide-rs library or deployment
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    /// Version of hide-rs
+    pub version: String,
+
+    /// Optional features by name and whether they are available
+    pub features: BTreeMap<String, bool>,
+
+    /// Embedding algorithms available
+    pub algorithms: Vec<String>,
+
+    /// Image formats that can be read and written
+    pub formats: Formats,
+
+    /// Size limits enforced by a server, absent for the library
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limits: Option<Limits>,
+}
+
+impl Capabilities {
+    /// Whether a feature is available; unknown features are not
+    pub fn supports(&self, feature: &str) -> bool {
+        self.features.get(feature).copied().unwrap_or(false)
+    }
+}
+
+/// Image formats, by their usual file extension
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Formats {
+    /// Formats cover and stego images can be loaded from
+    pub read: Vec<String>,
+
+    /// Formats stego images can be written as without losing the payload
+    pub write: Vec<String>,
+}
+
+/// Size limits a server enforces on requests
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Limits {
+    /// Largest message an encode request may carry, in bytes
+    pub max_message_bytes: usize,
+
+    /// Largest image upload, in bytes
+    pub max_image_bytes: usize,
+
+    /// Largest message a decode may produce, in bytes
+    pub decode_max_output_bytes: usize,
+
+    /// Most pixels a decode may read
+    pub decode_max_pixels: u64,
+
+    /// Longest a decode may run, in milliseconds
+    pub decode_deadline_ms: u64,
+
+    /// Largest decoded message returned inline rather than stored for download
+    pub max_inline_decode_bytes: usize,
+}
+
+/// Describe what this build of the library supports
+///
+/// Formats reflect the codecs compiled into the `image` crate.
+pub fn capabilities() -> Capabilities {
+    let enabled = |lossless_only: bool, check: fn(&ImageFormat) -> bool| {
+        ImageFormat::all()
+            .filter(check)
+            .filter(|format| !lossless_only || is_lossless(*format))
+            .filter_map(|format| format.extensions_str().first())
+            .map(|ext| ext.to_string())
+            .collect()
+    };
+
+    Capabilities {
+        version: crate::VERSION.to_string(),
+        features: FEATURES
+            .iter()
+            .map(|(name, enabled)| (name.to_string(), *enabled))
+            .collect(),
+        algorithms: ALGORITHMS.iter().map(|name| name.to_string()).collect(),
+        formats: Formats {
+            read: enabled(false, ImageFormat::reading_enabled),
+            write: enabled(true, ImageFormat::writing_enabled),
+        },
+        limits: None,
+    }
+}
+
+/// Whether a format stores RGB pixels exactly
+pub(crate) fn is_lossless(format: ImageFormat) -> bool {
+    matches!(
+        format,
+        ImageFormat::Png
+            | ImageFormat::Bmp
+            | ImageFormat::Tiff
+            | ImageFormat::Pnm
+            | ImageFormat::Tga
+            | ImageFormat::Qoi
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_features(features: &[(&str, bool)]) -> Capabilities {
+        Capabilities {
+            features: features
+                .iter()
+                .map(|(name, enabled)| (name.to_string(), *enabled))
+                .collect(),
+            ..capabilities()
+        }
+    }
+
+    #[test]
+    fn test_library_capabilities() {
+        let caps = capabilities();
+        assert_eq!(caps.version, crate::VERSION);
+        assert!(caps.supports("containers"));
+        assert!(!caps.supports("encryption"));
+        assert!(!caps.supports("no_such_feature"));
+        assert_eq!(caps.algorithms[..2], ["bltm3x3", "bltm4x4"]);
+        assert_eq!(
+            caps.algorithms.contains(&"jpeg-dct".to_string()),
+            cfg!(feature = "jpeg")
+        );
+        assert_eq!(caps.supports("jpeg_dct"), cfg!(feature = "jpeg"));
+        assert!(caps.formats.read.contains(&"png".to_string()));
+        assert!(caps.formats.read.contains(&"jpg".to_string()));
+        assert!(caps.formats.write.contains(&"png".to_string()));
+        assert!(!caps.formats.write.contains(&"jpg".to_string()));
+        assert!(caps.limits.is_none());
+    }
+
+    #[test]
+    fn test_algorithm_names() {
+        assert_eq!("bltm3x3".parse::<Algorithm>().unwrap(), Algorithm::Bltm);
+        assert_eq!(" BLTM ".parse::<Algorithm>().unwrap(), Algorithm::Bltm);
+        assert_eq!(
+            serde_json::to_string(&Algorithm::Bltm).unwrap(),
+            "\"bltm3x3\""
+        );
+        assert!("lsb".parse::<Algorithm>().is_err());
+
+        // Named in the error whether or not this build has it
+        let jpeg = "jpeg-dct".parse::<Algorithm>();
+        assert_eq!(jpeg.is_ok(), cfg!(feature = "jpeg"));
+        if let Ok(algorithm) = jpeg {
+            assert_eq!(algorithm.to_string(), "jpeg-dct");
+            assert_eq!(algorithm.extension(), "jpg");
+            assert!(ALGORITHMS.contains(&algorithm.name()));
+        }
+    }
+
+    #[test]
+    fn test_json_reflects_feature_flags() {
+        for encryption in [false, true] {
+            let caps = with_features(&[("encryption", encryption), ("streams", true)]);
+            let json = serde_json::to_value(&caps).unwrap();
+
+            assert_eq!(json["features"]["encryption"], encryption);
+            assert_eq!(json["features"]["streams"], true);
+            assert_eq!(json["features"].as_object().unwrap().len(), 2);
+            assert!(json.get("limits").is_none());
+
+            let parsed: Capabilities = serde_json::from_value(json).unwrap();
+            assert_eq!(parsed, caps);
+        }
+    }
+}
@@ -0,0 +1,785 @@
+//! Typed async client for the REST API
+//!
+//! Requests and responses use the models in [`crate::api::models`], so the
+//! client and server can't disagree about their shape. Error responses are
+//! mapped back to an [`ErrorCode`] mirroring
+//! [`error_codes`](crate::api::models::error_codes), with the details the
+//! server sent for that code.
+//!
+//! Enabled by the `client` feature.
+
+use crate::api::idempotency::REPLAY_HEADER;
+use crate::api::models::{
+    error_codes, DecodeResponse, DimensionsChangedDetails, EncodeOptions, EncodeResponse,
+    ExistingPayloadDetails, LimitDetails, MessageTooLargeDetails, RetryDetails,
+    TruncatedPayloadDetails, UnsupportedFeatureDetails, ValidationDetails, WarningDetails,
+};
+use crate::api::routes::API_V1_PREFIX;
+use crate::api::store::CONTENT_SHA256_HEADER;
+use crate::capabilities::Capabilities;
+use crate::decoder::Decoder;
+use crate::error::HideError;
+use crate::header::{MessageHeader, EXTENDED_HEADER_SIZE};
+use crate::img::{self, StegoImage};
+use crate::manifest::sha256_hex;
+use crate::png_rows::RowReader;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use reqwest::header::{CONTENT_RANGE, RANGE};
+use reqwest::multipart::{Form, Part};
+use reqwest::{Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use uuid::Uuid;
+
+/// The result type returned by client methods
+pub type Result<T> = std::result::Result<T, ClientError>;
+
+/// Errors returned by [`HideClient`]
+#[derive(Error, Debug)]
+pub enum ClientError {
+    /// The server answered with an error response
+    #[error("Server returned {status} ({}): {message}", .code.as_str())]
+    Api {
+        /// HTTP status of the response
+        status: StatusCode,
+        /// The error code and its details
+        code: ErrorCode,
+        /// Human-readable error message from the server
+        message: String,
+        /// ID of the failed request, when the server reported one
+        request_id: Option<Uuid>,
+    },
+
+    /// The request could not be sent or the response could not be read
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+
+    /// A local image could not be read
+    #[error("Failed to read {}: {source}", .path.display())]
+    Io {
+        /// Path of the image
+        path: PathBuf,
+        /// The underlying error
+        source: std::io::Error,
+    },
+
+    /// The server sent a response the client does not understand
+    #[error("Invalid response from server: {0}")]
+    InvalidResponse(String),
+
+    /// A fetched image could not be read
+    #[error("Failed to read image: {0}")]
+    Image(#[from] HideError),
+
+    /// A download does not match the SHA-256 the server sent with it
+    #[error("Downloaded content has SHA-256 {actual}, expected {expected}")]
+    ContentMismatch {
+        /// Hash sent by the server
+        expected: String,
+        /// Hash of the bytes received
+        actual: String,
+    },
+}
+
+impl ClientError {
+    /// The API error code, if the server answered with an error response
+    pub fn code(&self) -> Option<&ErrorCode> {
+        match self {
+            Self::Api { code, .. } => Some(code),
+            _ => None,
+        }
+    }
+}
+
+/// Error codes returned by the server, with the details sent for each
+///
+/// Details are `None` when the server sent none, as it does for some
+/// causes of the same code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// `validation_error`
+    Validation(Option<ValidationDetails>),
+    /// `image_too_large`
+    ImageTooLarge,
+    /// `message_too_large`
+    MessageTooLarge(Option<MessageTooLargeDetails>),
+    /// `invalid_image`
+    InvalidImage,
+    /// `no_message_found`
+    NoMessageFound,
+    /// `internal_error`
+    Internal,
+    /// `not_found`
+    NotFound,
+    /// `storage_unavailable`
+    StorageUnavailable(Option<RetryDetails>),
+    /// `idempotency_key_reused`
+    IdempotencyKeyReused,
+    /// `existing_payload`
+    ExistingPayload(Option<ExistingPayloadDetails>),
+    /// `output_limit_exceeded`
+    OutputLimitExceeded(Option<LimitDetails>),
+    /// `pixel_limit_exceeded`
+    PixelLimitExceeded(Option<LimitDetails>),
+    /// `deadline_exceeded`
+    DeadlineExceeded,
+    /// `unsupported_feature`
+    UnsupportedFeature(Option<UnsupportedFeatureDetails>),
+    /// `change_budget_exceeded`
+    ChangeBudgetExceeded(Option<LimitDetails>),
+    /// `truncated_payload`
+    TruncatedPayload(Option<TruncatedPayloadDetails>),
+    /// `color_converted`
+    ColorConverted,
+    /// `dimensions_changed`
+    DimensionsChanged(Option<DimensionsChangedDetails>),
+    /// `warnings_in_strict_mode`
+    WarningsInStrictMode(Option<WarningDetails>),
+    /// `invalid_signature`
+    InvalidSignature,
+    /// `link_expired`
+    LinkExpired,
+    /// `self_check_failed`
+    SelfCheckFailed,
+    /// `encrypted_payload`
+    EncryptedPayload,
+    /// `checksum_mismatch`
+    ChecksumMismatch,
+    /// `uncorrectable_errors`
+    UncorrectableErrors,
+    /// A code this client does not know, or the HTTP status of a response without one
+    Other(String),
+}
+
+impl ErrorCode {
+    /// Map an error code and its raw details to the typed code
+    ///
+    /// Details that don't match the shape expected for the code are dropped.
+    ///
+    /// # Arguments
+    /// * `code` - The `error_code` of the response
+    /// * `details` - The `details` of the response, if any
+    pub fn parse(code: &str, details: Option<serde_json::Value>) -> Self {
+        fn typed<T: DeserializeOwned>(details: Option<serde_json::Value>) -> Option<T> {
+            details.and_then(|value| serde_json::from_value(value).ok())
+        }
+
+        match code {
+            error_codes::VALIDATION_ERROR => Self::Validation(typed(details)),
+            error_codes::IMAGE_TOO_LARGE => Self::ImageTooLarge,
+            error_codes::MESSAGE_TOO_LARGE => Self::MessageTooLarge(typed(details)),
+            error_codes::INVALID_IMAGE => Self::InvalidImage,
+            error_codes::NO_MESSAGE_FOUND => Self::NoMessageFound,
+            error_codes::INTERNAL_ERROR => Self::Internal,
+            error_codes::NOT_FOUND => Self::NotFound,
+            error_codes::STORAGE_UNAVAILABLE => Self::StorageUnavailable(typed(details)),
+            error_codes::IDEMPOTENCY_KEY_REUSED => Self::IdempotencyKeyReused,
+            error_codes::EXISTING_PAYLOAD => Self::ExistingPayload(typed(details)),
+            error_codes::OUTPUT_LIMIT_EXCEEDED => Self::OutputLimitExceeded(typed(details)),
+            error_codes::PIXEL_LIMIT_EXCEEDED => Self::PixelLimitExceeded(typed(details)),
+            error_codes::DEADLINE_EXCEEDED => Self::DeadlineExceeded,
+            error_codes::UNSUPPORTED_FEATURE => Self::UnsupportedFeature(typed(details)),
+            error_codes::CHANGE_BUDGET_EXCEEDED => Self::ChangeBudgetExceeded(typed(details)),
+            error_codes::TRUNCATED_PAYLOAD => Self::TruncatedPayload(typed(details)),
+            error_codes::COLOR_CONVERTED => Self::ColorConverted,
+            error_codes::DIMENSIONS_CHANGED => Self::DimensionsChanged(typed(details)),
+            error_codes::WARNINGS_IN_STRICT_MODE => Self::WarningsInStrictMode(typed(details)),
+            error_codes::INVALID_SIGNATURE => Self::InvalidSignature,
+            error_codes::LINK_EXPIRED => Self::LinkExpired,
+            error_codes::SELF_CHECK_FAILED => Self::SelfCheckFailed,
+            error_codes::ENCRYPTED_PAYLOAD => Self::EncryptedPayload,
+            error_codes::CHECKSUM_MISMATCH => Self::ChecksumMismatch,
+            error_codes::UNCORRECTABLE_ERRORS => Self::UncorrectableErrors,
+            other => Self::Other(other.to_string()),
+        }
+    }
+
+    /// The code as sent by the server
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Validation(_) => error_codes::VALIDATION_ERROR,
+            Self::ImageTooLarge => error_codes::IMAGE_TOO_LARGE,
+            Self::MessageTooLarge(_) => error_codes::MESSAGE_TOO_LARGE,
+            Self::InvalidImage => error_codes::INVALID_IMAGE,
+            Self::NoMessageFound => error_codes::NO_MESSAGE_FOUND,
+            Self::Internal => error_codes::INTERNAL_ERROR,
+            Self::NotFound => error_codes::NOT_FOUND,
+            Self::StorageUnavailable(_) => error_codes::STORAGE_UNAVAILABLE,
+            Self::IdempotencyKeyReused => error_codes::IDEMPOTENCY_KEY_REUSED,
+            Self::ExistingPayload(_) => error_codes::EXISTING_PAYLOAD,
+            Self::OutputLimitExceeded(_) => error_codes::OUTPUT_LIMIT_EXCEEDED,
+            Self::PixelLimitExceeded(_) => error_codes::PIXEL_LIMIT_EXCEEDED,
+            Self::DeadlineExceeded => error_codes::DEADLINE_EXCEEDED,
+            Self::UnsupportedFeature(_) => error_codes::UNSUPPORTED_FEATURE,
+            Self::ChangeBudgetExceeded(_) => error_codes::CHANGE_BUDGET_EXCEEDED,
+            Self::TruncatedPayload(_) => error_codes::TRUNCATED_PAYLOAD,
+            Self::ColorConverted => error_codes::COLOR_CONVERTED,
+            Self::DimensionsChanged(_) => error_codes::DIMENSIONS_CHANGED,
+            Self::WarningsInStrictMode(_) => error_codes::WARNINGS_IN_STRICT_MODE,
+            Self::InvalidSignature => error_codes::INVALID_SIGNATURE,
+            Self::LinkExpired => error_codes::LINK_EXPIRED,
+            Self::SelfCheckFailed => error_codes::SELF_CHECK_FAILED,
+            Self::EncryptedPayload => error_codes::ENCRYPTED_PAYLOAD,
+            Self::ChecksumMismatch => error_codes::CHECKSUM_MISMATCH,
+            Self::UncorrectableErrors => error_codes::UNCORRECTABLE_ERRORS,
+            Self::Other(code) => code,
+        }
+    }
+}
+
+/// Body of an error response, with details left raw until the code is known
+#[derive(Debug, Deserialize)]
+struct ErrorBody {
+    #[serde(default)]
+    request_id: Option<Uuid>,
+    error_code: String,
+    message: String,
+    #[serde(default)]
+    details: Option<serde_json::Value>,
+}
+
+/// An image to upload
+#[derive(Debug, Clone)]
+pub enum ImageSource {
+    /// A file read when the request is sent
+    Path(PathBuf),
+
+    /// Image bytes, with the file name to upload them under
+    Bytes {
+        /// Encoded image file contents
+        data: Vec<u8>,
+        /// File name sent with the upload; its extension names the format
+        file_name: String,
+    },
+}
+
+impl ImageSource {
+    /// Image bytes to upload as a PNG file
+    pub fn png(data: impl Into<Vec<u8>>) -> Self {
+        Self::Bytes {
+            data: data.into(),
+            file_name: "image.png".to_string(),
+        }
+    }
+
+    /// Build the multipart part for this image
+    async fn into_part(self) -> Result<Part> {
+        let (data, file_name) = match self {
+            Self::Path(path) => {
+                let data = tokio::fs::read(&path)
+                    .await
+                    .map_err(|source| ClientError::Io {
+                        path: path.clone(),
+                        source,
+                    })?;
+                let file_name = path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| "image.png".to_string());
+                (data, file_name)
+            }
+            Self::Bytes { data, file_name } => (data, file_name),
+        };
+
+        let mime = mime_guess::from_path(&file_name).first_or_octet_stream();
+        Ok(Part::bytes(data)
+            .file_name(file_name)
+            .mime_str(mime.as_ref())?)
+    }
+}
+
+impl From<PathBuf> for ImageSource {
+    fn from(path: PathBuf) -> Self {
+        Self::Path(path)
+    }
+}
+
+impl From<&Path> for ImageSource {
+    fn from(path: &Path) -> Self {
+        Self::Path(path.to_path_buf())
+    }
+}
+
+impl From<Vec<u8>> for ImageSource {
+    fn from(data: Vec<u8>) -> Self {
+        Self::png(data)
+    }
+}
+
+impl From<&[u8]> for ImageSource {
+    fn from(data: &[u8]) -> Self {
+        Self::png(data)
+    }
+}
+
+/// Result of a successful encode
+#[derive(Debug, Clone)]
+pub struct EncodeOutcome {
+    /// The server's response
+    pub response: EncodeResponse,
+
+    /// Whether the server replayed the response of an earlier identical request
+    pub replayed: bool,
+}
+
+/// Result of a successful decode
+#[derive(Debug)]
+pub struct DecodeOutcome {
+    /// The server's response
+    pub response: DecodeResponse,
+
+    /// The decoded message, downloaded if the server did not inline it
+    pub message: Vec<u8>,
+}
+
+/// Options for [`HideClient::remote_detect_with_options`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RemoteDetectOptions {
+    /// Bytes asked for by the first range request; each later one asks for twice as many
+    pub initial_range: u64,
+
+    /// Most bytes to fetch by range before giving up on reading only the header
+    pub max_range_bytes: u64,
+
+    /// Download and decode the whole image when its header can't be read
+    /// from the start of the file, e.g. because the PNG is interlaced
+    pub fallback_to_download: bool,
+}
+
+impl Default for RemoteDetectOptions {
+    fn default() -> Self {
+        Self {
+            initial_range: 16 * 1024,
+            max_range_bytes: 4 * 1024 * 1024,
+            fallback_to_download: false,
+        }
+    }
+}
+
+/// Result of [`HideClient::remote_detect`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Detection {
+    /// The header of the payload the image holds, or `None` if it holds none
+    pub header: Option<MessageHeader>,
+
+    /// Bytes of the image received
+    pub bytes_transferred: u64,
+
+    /// Whether the whole image was downloaded
+    pub full_download: bool,
+}
+
+/// Client for a hide-rs server
+#[derive(Debug, Clone)]
+pub struct HideClient {
+    /// Base URL of the server, without a trailing slash
+    base_url: String,
+
+    /// Underlying HTTP client
+    http: reqwest::Client,
+}
+
+impl HideClient {
+    /// Create a client for the server at `base_url`, e.g. `http://localhost:8080`
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self::with_http_client(base_url, reqwest::Client::new())
+    }
+
+    /// Create a client that sends requests through an existing HTTP client
+    pub fn with_http_client(base_url: impl Into<String>, http: reqwest::Client) -> Self {
+        let base_url = base_url.into().trim_end_matches('/').to_string();
+        Self { base_url, http }
+    }
+
+    /// Base URL of the server
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Hide a message in a cover image
+    ///
+    /// # Arguments
+    /// * `cover` - The cover image
+    /// * `message` - The message to hide
+    /// * `options` - Encoding options
+    ///
+    /// # Returns
+    /// * The server's response, including the ID to download the stego image with
+    pub async fn encode(
+        &self,
+        cover: impl Into<ImageSource>,
+        message: &[u8],
+        options: &EncodeOptions,
+    ) -> Result<EncodeOutcome> {
+        let mut form = Form::new()
+            .part("cover_image", cover.into().into_part().await?)
+            .part("message", Part::bytes(message.to_vec()))
+            .text("output_format", options.output_format.clone())
+            .text("jpeg_quality", options.jpeg_quality.to_string())
+            .text("overwrite", options.overwrite.to_string())
+            .text("strict", options.strict.to_string())
+            .text("verify_roundtrip", options.verify_roundtrip.to_string())
+            .text("record_dimensions", options.record_dimensions.to_string())
+            .text("compress", options.compress.to_string())
+            .text("checksum", options.checksum.to_string())
+            .text("embedding_depth", options.embedding_depth.to_string());
+        if let Some(max_change) = options.max_change {
+            form = form.text("max_change", max_change.to_string());
+        }
+        if let Some(fill) = options.auto_extend {
+            form = form.text("auto_extend", fill.to_string());
+        }
+        if let Some(channel) = &options.intended_channel {
+            form = form.text("intended_channel", channel.clone());
+        }
+
+        let response = self
+            .http
+            .post(self.api_url("/encode"))
+            .multipart(form)
+            .send()
+            .await?;
+        let replayed = response.headers().contains_key(REPLAY_HEADER);
+        Ok(EncodeOutcome {
+            response: json(response).await?,
+            replayed,
+        })
+    }
+
+    /// Extract the message hidden in a stego image
+    ///
+    /// # Arguments
+    /// * `image` - The stego image
+    ///
+    /// # Returns
+    /// * The server's response and the decoded message
+    pub async fn decode(&self, image: impl Into<ImageSource>) -> Result<DecodeOutcome> {
+        let form = Form::new().part("stego_image", image.into().into_part().await?);
+        let response = self
+            .http
+            .post(self.api_url("/decode"))
+            .multipart(form)
+            .send()
+            .await?;
+        let response: DecodeResponse = json(response).await?;
+
+        let message = match (&response.binary_message, &response.download_url) {
+            (Some(encoded), _) => BASE64
+                .decode(encoded)
+                .map_err(|e| ClientError::InvalidResponse(format!("binary_message: {}", e)))?,
+            (None, Some(url)) => self.fetch(&self.resolve(url)).await?,
+            (None, None) => {
+                return Err(ClientError::InvalidResponse(
+                    "decode response has neither a message nor a download URL".to_string(),
+                ))
+            }
+        };
+
+        Ok(DecodeOutcome { response, message })
+    }
+
+    /// Download a stored stego image
+    ///
+    /// # Arguments
+    /// * `image_id` - ID returned by [`encode`](Self::encode)
+    ///
+    /// The contents are checked against the SHA-256 the server recorded
+    /// when it stored the image.
+    ///
+    /// # Returns
+    /// * The image file contents, or `ContentMismatch` if they were corrupted
+    pub async fn download(&self, image_id: Uuid) -> Result<Vec<u8>> {
+        self.fetch(&self.api_url(&format!("/images/{}", image_id)))
+            .await
+    }
+
+    /// Download a stored stego image from the URL the server returned for it
+    ///
+    /// Servers that sign download URLs only serve images through these.
+    ///
+    /// # Arguments
+    /// * `download_url` - The `download_url` of an encode response or image listing
+    ///
+    /// # Returns
+    /// * The image file contents, or `ContentMismatch` if they were corrupted
+    pub async fn download_url(&self, download_url: &str) -> Result<Vec<u8>> {
+        self.fetch(&self.resolve(download_url)).await
+    }
+
+    /// Check whether a remote PNG holds a payload, fetching only its start
+    ///
+    /// See [`remote_detect_with_options`](Self::remote_detect_with_options).
+    pub async fn remote_detect(&self, url: &str) -> Result<Detection> {
+        self.remote_detect_with_options(url, &RemoteDetectOptions::default())
+            .await
+    }
+
+    /// Check whether a remote PNG holds a payload, fetching only its start
+    ///
+    /// The header lives in the first pixels, so the file is requested in
+    /// growing HTTP ranges and only the rows holding the header are inflated.
+    /// Servers that ignore ranges have their response read only until those
+    /// rows arrive.
+    ///
+    /// # Arguments
+    /// * `url` - URL of the image; paths are resolved against the base URL
+    /// * `options` - Range sizes and whether to fall back to a full download
+    ///
+    /// # Returns
+    /// * The detected header, or `Image` if the PNG isn't one whose rows can be
+    ///   read incrementally and `fallback_to_download` is off
+    pub async fn remote_detect_with_options(
+        &self,
+        url: &str,
+        options: &RemoteDetectOptions,
+    ) -> Result<Detection> {
+        let url = self.resolve(url);
+        let header_pixels = img::pixels_for_bits(EXTENDED_HEADER_SIZE as u64 * 8);
+        let mut reader = RowReader::new(header_pixels);
+        let mut transferred = 0;
+
+        let read = self
+            .read_header_rows(&url, options, &mut reader, &mut transferred)
+            .await;
+        match read {
+            Ok(()) => Ok(Detection {
+                header: Decoder::new().detect(&reader),
+                bytes_transferred: transferred,
+                full_download: false,
+            }),
+            Err(ClientError::Image(_)) if options.fallback_to_download => {
+                let body = self.fetch(&url).await?;
+                let image = StegoImage::from_bytes(&body)?;
+                Ok(Detection {
+                    header: Decoder::new().detect(&image),
+                    bytes_transferred: transferred + body.len() as u64,
+                    full_download: true,
+                })
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Fetch the start of a PNG until the reader has the rows it wants
+    ///
+    /// # Returns
+    /// * `Image` if the PNG can't be read incrementally, ends early or needs
+    ///   more than `max_range_bytes`
+    async fn read_header_rows(
+        &self,
+        url: &str,
+        options: &RemoteDetectOptions,
+        reader: &mut RowReader,
+        transferred: &mut u64,
+    ) -> Result<()> {
+        let mut window = options.initial_range.max(1);
+        let mut file_size = None;
+
+        while !reader.is_complete() {
+            let at_end = file_size.is_some_and(|size| *transferred >= size);
+            if at_end || reader.is_finished() {
+                return Err(HideError::InvalidParameters(
+                    "The PNG ended before the header pixels".to_string(),
+                )
+                .into());
+            }
+            if *transferred >= options.max_range_bytes {
+                return Err(HideError::InvalidParameters(format!(
+                    "The header pixels weren't in the first {} bytes",
+                    options.max_range_bytes
+                ))
+                .into());
+            }
+
+            let end = (*transferred + window).min(options.max_range_bytes) - 1;
+            let mut response = check(
+                self.http
+                    .get(url)
+                    .header(RANGE, format!("bytes={}-{}", transferred, end))
+                    .send()
+                    .await?,
+            )
+            .await?;
+
+            if response.status() != StatusCode::PARTIAL_CONTENT {
+                // The server sent the whole file; read it only as far as needed
+                while !reader.is_complete() {
+                    let Some(chunk) = response.chunk().await? else {
+                        break;
+                    };
+                    *transferred += chunk.len() as u64;
+                    reader.push(&chunk)?;
+                }
+                file_size = Some(*transferred);
+                continue;
+            }
+
+            file_size = response
+                .headers()
+                .get(CONTENT_RANGE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.rsplit_once('/'))
+                .and_then(|(_, size)| size.parse().ok())
+                .or(file_size);
+            let body = response.bytes().await?;
+            if body.is_empty() {
+                file_size = Some(*transferred);
+            }
+            *transferred += body.len() as u64;
+            reader.push(&body)?;
+            window = window.saturating_mul(2);
+        }
+
+        Ok(())
+    }
+
+    /// Fetch what the server supports, including its limits
+    pub async fn capabilities(&self) -> Result<Capabilities> {
+        let url = format!("{}/api/capabilities", self.base_url);
+        json(self.http.get(url).send().await?).await
+    }
+
+    /// GET a URL and return the body, mapping error responses
+    ///
+    /// Bodies sent with a content hash are checked against it.
+    async fn fetch(&self, url: &str) -> Result<Vec<u8>> {
+        let response = check(self.http.get(url).send().await?).await?;
+        let expected = response
+            .headers()
+            .get(CONTENT_SHA256_HEADER)
+            .map(|value| {
+                value.to_str().map(str::to_string).map_err(|_| {
+                    ClientError::InvalidResponse(format!("{} is not text", CONTENT_SHA256_HEADER))
+                })
+            })
+            .transpose()?;
+
+        let body = response.bytes().await?.to_vec();
+        if let Some(expected) = expected {
+            let actual = sha256_hex(&body);
+            if !actual.eq_ignore_ascii_case(&expected) {
+                return Err(ClientError::ContentMismatch { expected, actual });
+            }
+        }
+        Ok(body)
+    }
+
+    /// URL of a path under the current API version
+    fn api_url(&self, path: &str) -> String {
+        format!("{}{}{}", self.base_url, API_V1_PREFIX, path)
+    }
+
+    /// Turn a URL from a response into an absolute one
+    ///
+    /// Servers without a public base URL send paths relative to their root.
+    fn resolve(&self, url: &str) -> String {
+        if url.starts_with("http://") || url.starts_with("https://") {
+            url.to_string()
+        } else {
+            format!("{}{}", self.base_url, url)
+        }
+    }
+}
+
+/// Pass successful responses through and map error responses to [`ClientError::Api`]
+async fn check(response: Response) -> Result<Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let body = response.bytes().await?;
+    Err(match serde_json::from_slice::<ErrorBody>(&body) {
+        Ok(error) => ClientError::Api {
+            status,
+            code: ErrorCode::parse(&error.error_code, error.details),
+            message: error.message,
+            request_id: error.request_id,
+        },
+        // Downloads and errors raised outside the handlers have plain text
+        // bodies, so fall back to the status
+        Err(_) => ClientError::Api {
+            status,
+            code: match status {
+                StatusCode::NOT_FOUND => ErrorCode::NotFound,
+                StatusCode::INTERNAL_SERVER_ERROR => ErrorCode::Internal,
+                _ => ErrorCode::Other(status.as_str().to_string()),
+            },
+            message: String::from_utf8_lossy(&body).into_owned(),
+            request_id: None,
+        },
+    })
+}
+
+/// Parse the JSON body of a successful response
+async fn json<T: DeserializeOwned>(response: Response) -> Result<T> {
+    let body = check(response).await?.bytes().await?;
+    serde_json::from_slice(&body).map_err(|e| ClientError::InvalidResponse(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_error_codes_round_trip() {
+        let codes = [
+            error_codes::VALIDATION_ERROR,
+            error_codes::IMAGE_TOO_LARGE,
+            error_codes::MESSAGE_TOO_LARGE,
+            error_codes::INVALID_IMAGE,
+            error_codes::NO_MESSAGE_FOUND,
+            error_codes::INTERNAL_ERROR,
+            error_codes::NOT_FOUND,
+            error_codes::STORAGE_UNAVAILABLE,
+            error_codes::IDEMPOTENCY_KEY_REUSED,
+            error_codes::EXISTING_PAYLOAD,
+            error_codes::OUTPUT_LIMIT_EXCEEDED,
+            error_codes::PIXEL_LIMIT_EXCEEDED,
+            error_codes::DEADLINE_EXCEEDED,
+            error_codes::UNSUPPORTED_FEATURE,
+            error_codes::CHANGE_BUDGET_EXCEEDED,
+            error_codes::TRUNCATED_PAYLOAD,
+            error_codes::COLOR_CONVERTED,
+            error_codes::DIMENSIONS_CHANGED,
+            error_codes::WARNINGS_IN_STRICT_MODE,
+            error_codes::INVALID_SIGNATURE,
+            error_codes::LINK_EXPIRED,
+            error_codes::SELF_CHECK_FAILED,
+            error_codes::ENCRYPTED_PAYLOAD,
+            error_codes::CHECKSUM_MISMATCH,
+            error_codes::UNCORRECTABLE_ERRORS,
+        ];
+        for code in codes {
+            let parsed = ErrorCode::parse(code, None);
+            assert!(!matches!(parsed, ErrorCode::Other(_)), "{} unmapped", code);
+            assert_eq!(parsed.as_str(), code);
+        }
+        assert_eq!(
+            ErrorCode::parse("new_code", None),
+            ErrorCode::Other("new_code".to_string())
+        );
+    }
+
+    #[test]
+    fn test_details_are_typed_by_code() {
+        // Same shape as existing_payload details, but read by the code
+        let details = json!({"declared_length": 100, "available_length": 40});
+        assert_eq!(
+            ErrorCode::parse(error_codes::TRUNCATED_PAYLOAD, Some(details)),
+            ErrorCode::TruncatedPayload(Some(TruncatedPayloadDetails {
+                declared_length: 100,
+                available_length: 40,
+            }))
+        );
+
+        // Mismatched details are dropped rather than failing
+        assert_eq!(
+            ErrorCode::parse(error_codes::MESSAGE_TOO_LARGE, Some(json!({"x": 1}))),
+            ErrorCode::MessageTooLarge(None)
+        );
+    }
+}
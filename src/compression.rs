@@ -0,0 +1,152 @@
+//! Deflating messages before they are embedded
+//!
+//! A compressed message is stored as its original length, a big-endian
+//! `u32`, followed by a raw DEFLATE stream. The header's
+//! [`ExtraFlags::COMPRESSED`](crate::header::ExtraFlags::COMPRESSED) flag
+//! marks it, and its length field counts the compressed bytes, so capacity
+//! checks see what is actually embedded. Messages that don't get smaller are
+//! stored as they are with the flag clear.
+//!
+//! Encrypted payloads are never compressed: ciphertext doesn't shrink, and
+//! compressing first would let the payload's size leak what the message holds.
+
+use crate::error::HideError;
+use crate::header::{get_u32_be, put_u32_be};
+use crate::Result;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use std::io::{Read, Write};
+
+/// Size of the original length that starts a compressed message
+pub const LENGTH_PREFIX_SIZE: usize = 4;
+
+/// Largest message a compressed payload may inflate to, unless a decode sets
+/// a tighter limit
+///
+/// DEFLATE can expand over a thousandfold, so a few megabytes of payload must
+/// not be trusted to say how much memory the decoder should hand out.
+pub const MAX_INFLATED_BYTES: usize = 256 << 20;
+
+/// Deflate a message, if that makes it smaller
+///
+/// # Arguments
+/// * `message` - The message to compress
+///
+/// # Returns
+/// * The length-prefixed compressed message, or `None` if it is no shorter
+///   than the message itself
+pub fn compress(message: &[u8]) -> Result<Option<Vec<u8>>> {
+    let length = u32::try_from(message.len()).map_err(|_| HideError::MessageTooLarge)?;
+    let mut compressed = Vec::with_capacity(LENGTH_PREFIX_SIZE + message.len() / 2);
+    put_u32_be(&mut compressed, length);
+
+    let mut encoder = DeflateEncoder::new(compressed, Compression::best());
+    encoder.write_all(message)?;
+    let compressed = encoder.finish()?;
+
+    Ok((compressed.len() < message.len()).then_some(compressed))
+}
+
+/// Inflate a message stored by [`compress`]
+///
+/// # Arguments
+/// * `payload` - The length-prefixed compressed message
+/// * `limit` - Largest message to inflate, in bytes
+///
+/// # Returns
+/// * The message, [`HideError::OutputLimitExceeded`] if it would be over
+///   `limit`, or an error if the stream is corrupt or doesn't inflate to the
+///   recorded length
+pub fn decompress(payload: &[u8], limit: usize) -> Result<Vec<u8>> {
+    let corrupt = || HideError::InvalidParameters("The compressed message is corrupt".to_string());
+
+    let length = get_u32_be(payload, 0).ok_or_else(corrupt)? as usize;
+    if length > limit {
+        return Err(HideError::OutputLimitExceeded {
+            declared_length: length,
+            limit,
+        });
+    }
+    let stream = payload.get(LENGTH_PREFIX_SIZE..).ok_or_else(corrupt)?;
+
+    // Reading one byte past the recorded length shows whether the stream runs on
+    let mut message = Vec::new();
+    DeflateDecoder::new(stream)
+        .take(length as u64 + 1)
+        .read_to_end(&mut message)
+        .map_err(|_| corrupt())?;
+    if message.len() != length {
+        return Err(corrupt());
+    }
+
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let message = "the quick brown fox jumps over the lazy dog. ".repeat(40);
+        let compressed = compress(message.as_bytes()).unwrap().unwrap();
+        assert!(compressed.len() < message.len() / 4);
+        assert_eq!(&compressed[..4], (message.len() as u32).to_be_bytes());
+        assert_eq!(
+            decompress(&compressed, MAX_INFLATED_BYTES).unwrap(),
+            message.as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_incompressible_messages_are_left_alone() {
+        let noise = crate::testing::noise_image(16, 16, 7)
+            .inner()
+            .as_bytes()
+            .to_vec();
+        assert_eq!(compress(&noise).unwrap(), None);
+        assert_eq!(compress(b"").unwrap(), None);
+        assert_eq!(compress(b"hi").unwrap(), None);
+    }
+
+    #[test]
+    fn test_corrupt_streams_are_rejected() {
+        let message = vec![b'a'; 1000];
+        let compressed = compress(&message).unwrap().unwrap();
+
+        // A wrong recorded length, either way
+        let mut short = compressed.clone();
+        short[3] -= 1;
+        assert!(decompress(&short, MAX_INFLATED_BYTES).is_err());
+        let mut long = compressed.clone();
+        long[3] += 1;
+        assert!(decompress(&long, MAX_INFLATED_BYTES).is_err());
+
+        for cut in [compressed.len() / 2, 3] {
+            assert!(decompress(&compressed[..cut], MAX_INFLATED_BYTES).is_err());
+        }
+    }
+
+    #[test]
+    fn test_inflated_size_is_limited() {
+        let mut bomb = compress(&[0; 4096]).unwrap().unwrap();
+        assert!(matches!(
+            decompress(&bomb, 4095),
+            Err(HideError::OutputLimitExceeded {
+                declared_length: 4096,
+                limit: 4095,
+            })
+        ));
+        assert_eq!(decompress(&bomb, 4096).unwrap(), [0; 4096]);
+
+        bomb[..4].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(matches!(
+            decompress(&bomb, MAX_INFLATED_BYTES),
+            Err(HideError::OutputLimitExceeded {
+                limit: MAX_INFLATED_BYTES,
+                ..
+            })
+        ));
+    }
+}
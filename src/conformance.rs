@@ -0,0 +1,323 @@
+//! Canonical test vectors for the payload format
+//!
+//! Each vector pairs a cover, a message and encoder options with the exact
+//! stego pixels and header an implementation must produce. The vectors are
+//! stored as JSON under `tests/vectors/` and compiled into the library, so
+//! [`verify_all`] checks this build against them and other implementations
+//! can port the same checks. Pixel data and byte strings are base64 encoded
+//! RGB bytes in row-major order.
+//!
+//! The vectors are regenerated with `cargo run --features gen-vectors --bin
+//! gen-vectors`; a change that alters them changes the format.
+
+use crate::decoder::Decoder;
+use crate::encoder::{Encoder, EncoderConfig};
+use crate::error::HideError;
+use crate::header::{VersionedParser, EXTENSION_SIZE};
+use crate::img::StegoImage;
+use crate::raw_decoder;
+use crate::schedule::ChannelSchedule;
+use crate::Result;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use image::{DynamicImage, ImageBuffer, Rgb};
+use serde::{Deserialize, Serialize};
+
+/// Published vectors by file name
+const VECTORS: &[(&str, &str)] = &[
+    (
+        "v1_plain.json",
+        include_str!("../tests/vectors/v1_plain.json"),
+    ),
+    (
+        "v1_empty_message.json",
+        include_str!("../tests/vectors/v1_empty_message.json"),
+    ),
+    (
+        "v1_max_capacity.json",
+        include_str!("../tests/vectors/v1_max_capacity.json"),
+    ),
+    (
+        "v2_rotated_channels.json",
+        include_str!("../tests/vectors/v2_rotated_channels.json"),
+    ),
+];
+
+/// One cover, message and configuration with the output they must produce
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TestVector {
+    /// Name of the vector, matching its file name
+    pub name: String,
+
+    /// What the vector exercises
+    pub description: String,
+
+    /// Width of the cover in pixels
+    pub width: u32,
+
+    /// Height of the cover in pixels
+    pub height: u32,
+
+    /// Cover pixels, base64 encoded
+    pub cover_rgb: String,
+
+    /// Message to embed, base64 encoded
+    pub message: String,
+
+    /// Encoder options that affect the output
+    pub config: VectorConfig,
+
+    /// The header bytes the payload must start with, base64 encoded
+    pub header: String,
+
+    /// Stego pixels the encoder must produce, base64 encoded
+    pub stego_rgb: String,
+}
+
+/// Encoder options recorded in a vector
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VectorConfig {
+    /// Channel schedule of the payload
+    #[serde(default)]
+    pub channel_schedule: ChannelSchedule,
+}
+
+impl VectorConfig {
+    /// The encoder options this stands for
+    pub fn encoder_config(&self) -> EncoderConfig {
+        EncoderConfig {
+            channel_schedule: self.channel_schedule,
+            ..Default::default()
+        }
+    }
+}
+
+/// Outcome of checking one vector
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct VectorResult {
+    /// Name of the vector
+    pub name: String,
+
+    /// What differed from the vector; empty if it passed
+    pub failures: Vec<String>,
+}
+
+impl VectorResult {
+    /// Whether this build reproduced the vector exactly
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Parse the vectors compiled into the library
+///
+/// # Returns
+/// * The vectors, in the order they are listed, or an error naming a file that doesn't parse
+pub fn vectors() -> Result<Vec<TestVector>> {
+    VECTORS
+        .iter()
+        .map(|(file, json)| {
+            serde_json::from_str(json).map_err(|e| {
+                HideError::InvalidParameters(format!("Invalid test vector {}: {}", file, e))
+            })
+        })
+        .collect()
+}
+
+/// Check this build against every published vector
+///
+/// # Returns
+/// * One result per vector; a vector file that doesn't parse is reported as a failure
+pub fn verify_all() -> Vec<VectorResult> {
+    VECTORS
+        .iter()
+        .map(
+            |(file, json)| match serde_json::from_str::<TestVector>(json) {
+                Ok(vector) => verify(&vector),
+                Err(e) => VectorResult {
+                    name: file.to_string(),
+                    failures: vec![format!("Vector does not parse: {}", e)],
+                },
+            },
+        )
+        .collect()
+}
+
+/// Check this build against one vector
+///
+/// The cover is encoded and must give the vector's stego pixels exactly;
+/// the stego pixels must then start with the vector's header and decode
+/// back to the message.
+pub fn verify(vector: &TestVector) -> VectorResult {
+    let mut failures = Vec::new();
+    if let Err(e) = check(vector, &mut failures) {
+        failures.push(e.to_string());
+    }
+
+    VectorResult {
+        name: vector.name.clone(),
+        failures,
+    }
+}
+
+/// Run the checks of [`verify`], recording mismatches in `failures`
+fn check(vector: &TestVector, failures: &mut Vec<String>) -> Result<()> {
+    let cover = image_from_rgb(
+        vector.width,
+        vector.height,
+        &decode_field(&vector.cover_rgb)?,
+    )?;
+    let message = decode_field(&vector.message)?;
+    let expected_stego = decode_field(&vector.stego_rgb)?;
+    let expected_header = decode_field(&vector.header)?;
+
+    let stego = Encoder::with_config(vector.config.encoder_config()).encode(cover, &message)?;
+    let stego_rgb = stego.inner().to_rgb8().into_raw();
+    if stego_rgb != expected_stego {
+        let first = stego_rgb
+            .iter()
+            .zip(&expected_stego)
+            .position(|(actual, expected)| actual != expected)
+            .unwrap_or(stego_rgb.len().min(expected_stego.len()));
+        failures.push(format!(
+            "Stego pixels differ, first at byte {} of the RGB data",
+            first
+        ));
+    }
+
+    // Check the expected stego pixels rather than ours, so a decoder is
+    // tested even when the encoder is not
+    let expected = image_from_rgb(vector.width, vector.height, &expected_stego)?;
+    let raw = raw_decoder::extract_raw_data(&expected)?;
+    if header_bytes(&raw).ok() != Some(expected_header.as_slice()) {
+        failures.push("Header bytes differ".to_string());
+    }
+
+    match Decoder::new().decode(&expected) {
+        Ok(decoded) if decoded == message => {}
+        Ok(_) => failures.push("Decoded message differs".to_string()),
+        Err(e) => failures.push(format!("Decoding failed: {}", e)),
+    }
+
+    Ok(())
+}
+
+/// Encode the stego pixels for a cover, message and configuration as a vector
+///
+/// Used by the `gen-vectors` binary to regenerate the published vectors.
+///
+/// # Arguments
+/// * `name` - Name of the vector
+/// * `description` - What the vector exercises
+/// * `cover` - The cover image
+/// * `message` - The message to embed
+/// * `config` - Encoder options
+///
+/// # Returns
+/// * The vector, recording the output of this build
+pub fn generate(
+    name: &str,
+    description: &str,
+    cover: &StegoImage,
+    message: &[u8],
+    config: VectorConfig,
+) -> Result<TestVector> {
+    let stego = Encoder::with_config(config.encoder_config()).encode(cover.clone(), message)?;
+    let raw = raw_decoder::extract_raw_data(&stego)?;
+    let header = header_bytes(&raw)?;
+
+    Ok(TestVector {
+        name: name.to_string(),
+        description: description.to_string(),
+        width: cover.width(),
+        height: cover.height(),
+        cover_rgb: BASE64.encode(cover.inner().to_rgb8().as_raw()),
+        message: BASE64.encode(message),
+        config,
+        header: BASE64.encode(header),
+        stego_rgb: BASE64.encode(stego.inner().to_rgb8().as_raw()),
+    })
+}
+
+/// Generate every published vector from its definition
+///
+/// Covers are filled with a fixed pattern that exercises every LSB
+/// combination, so the vectors don't depend on any other image code.
+pub fn generate_all() -> Result<Vec<TestVector>> {
+    let plain = b"Hide me!".to_vec();
+    let max_message: Vec<u8> = (0..Encoder::max_message_size_for_dimensions(8, 8))
+        .map(|i| (i * 37 + 11) as u8)
+        .collect();
+    // Rotating channels is a flag, so its header carries the extension
+    let rotated_message = max_message
+        .get(..max_message.len() - EXTENSION_SIZE)
+        .unwrap_or_default();
+
+    Ok(vec![
+        generate(
+            "v1_plain",
+            "A short text message in the fixed channel order",
+            &pattern_cover(8, 8),
+            &plain,
+            VectorConfig::default(),
+        )?,
+        generate(
+            "v1_empty_message",
+            "An empty message: only the header is embedded",
+            &pattern_cover(4, 6),
+            &[],
+            VectorConfig::default(),
+        )?,
+        generate(
+            "v1_max_capacity",
+            "A message filling every pixel of the cover",
+            &pattern_cover(8, 8),
+            &max_message,
+            VectorConfig::default(),
+        )?,
+        generate(
+            "v2_rotated_channels",
+            "A message long enough to pass the header pixels, with rotating channels",
+            &pattern_cover(8, 8),
+            rotated_message,
+            VectorConfig {
+                channel_schedule: ChannelSchedule::Rotating,
+            },
+        )?,
+    ])
+}
+
+/// The header at the start of raw payload bytes, however long its version's is
+fn header_bytes(raw: &[u8]) -> Result<&[u8]> {
+    let length = VersionedParser::header_length(raw)?;
+    raw.get(..length).ok_or(HideError::NoMessageFound)
+}
+
+/// Create a cover with a fixed pattern of pixel values
+pub fn pattern_cover(width: u32, height: u32) -> StegoImage {
+    let img = ImageBuffer::from_fn(width, height, |x, y| {
+        Rgb([
+            x.wrapping_mul(37).wrapping_add(y.wrapping_mul(11)) as u8,
+            x.wrapping_mul(13).wrapping_add(y.wrapping_mul(29)) as u8,
+            x.wrapping_mul(7).wrapping_add(y.wrapping_mul(53)) as u8,
+        ])
+    });
+    StegoImage::from_dynamic_image(DynamicImage::ImageRgb8(img))
+}
+
+/// Decode a base64 field of a vector
+fn decode_field(field: &str) -> Result<Vec<u8>> {
+    BASE64
+        .decode(field)
+        .map_err(|e| HideError::InvalidParameters(format!("Invalid base64 in test vector: {}", e)))
+}
+
+/// Build an RGB image from raw pixel bytes
+fn image_from_rgb(width: u32, height: u32, rgb: &[u8]) -> Result<StegoImage> {
+    let img = ImageBuffer::from_raw(width, height, rgb.to_vec()).ok_or_else(|| {
+        HideError::InvalidParameters(format!(
+            "Test vector pixel data does not match its {}x{} size",
+            width, height
+        ))
+    })?;
+    Ok(StegoImage::from_dynamic_image(DynamicImage::ImageRgb8(img)))
+}
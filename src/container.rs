@@ -0,0 +1,144 @@
+//! Container payloads holding several named entries in one image
+//!
+//! A container is an ordinary payload whose header has
+//! [`HeaderFlags::CONTAINER`](crate::header::HeaderFlags::CONTAINER) set.
+//! The payload is a sequence of entries, each stored as a big-endian `u16` name
+//! length, the UTF-8 name, a big-endian `u32` value length and the value.
+
+use crate::error::HideError;
+use crate::header::{get_u16_be, get_u32_be, put_u16_be, put_u32_be};
+use crate::Result;
+use std::collections::HashSet;
+
+/// Size of the length fields in front of an entry's name and value
+pub(crate) const NAME_LENGTH_SIZE: usize = 2;
+pub(crate) const VALUE_LENGTH_SIZE: usize = 4;
+
+/// A named record stored in a container payload
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContainerEntry {
+    /// Name of the entry
+    pub name: String,
+
+    /// Contents of the entry
+    pub data: Vec<u8>,
+}
+
+/// Serialize entries into a container payload
+///
+/// # Arguments
+/// * `entries` - Names and contents of the entries, in order
+///
+/// # Returns
+/// * The payload bytes, or an error for empty, overlong or duplicate names
+pub fn encode_entries(entries: &[(String, Vec<u8>)]) -> Result<Vec<u8>> {
+    let mut seen = HashSet::new();
+    let mut payload = Vec::new();
+
+    for (name, data) in entries {
+        if name.is_empty() {
+            return Err(HideError::InvalidParameters(
+                "Container entry names cannot be empty".to_string(),
+            ));
+        }
+        if !seen.insert(name.as_str()) {
+            return Err(HideError::InvalidParameters(format!(
+                "Duplicate container entry name: {}",
+                name
+            )));
+        }
+
+        let name_len = u16::try_from(name.len()).map_err(|_| {
+            HideError::InvalidParameters(format!("Container entry name too long: {}", name))
+        })?;
+        let data_len = u32::try_from(data.len()).map_err(|_| {
+            HideError::InvalidParameters(format!("Container entry too large: {}", name))
+        })?;
+
+        put_u16_be(&mut payload, name_len);
+        payload.extend_from_slice(name.as_bytes());
+        put_u32_be(&mut payload, data_len);
+        payload.extend_from_slice(data);
+    }
+
+    Ok(payload)
+}
+
+/// Parse a container payload into its entries
+///
+/// # Arguments
+/// * `payload` - The payload bytes (without the message header)
+///
+/// # Returns
+/// * The entries in order, or an error if the payload is malformed
+pub fn parse_entries(payload: &[u8]) -> Result<Vec<ContainerEntry>> {
+    let mut entries = Vec::new();
+    let mut rest = payload;
+
+    while !rest.is_empty() {
+        let name_len = length_field(take(&mut rest, NAME_LENGTH_SIZE)?, get_u16_be)?;
+        let name = parse_name(take(&mut rest, name_len as usize)?)?;
+        let data_len = length_field(take(&mut rest, VALUE_LENGTH_SIZE)?, get_u32_be)?;
+        let data = take(&mut rest, data_len as usize)?.to_vec();
+
+        entries.push(ContainerEntry { name, data });
+    }
+
+    Ok(entries)
+}
+
+/// Decode an entry name
+pub(crate) fn parse_name(bytes: &[u8]) -> Result<String> {
+    String::from_utf8(bytes.to_vec()).map_err(|_| {
+        HideError::InvalidParameters("Container entry name is not valid UTF-8".to_string())
+    })
+}
+
+/// Read a length field with one of the big endian readers in [`crate::header`]
+pub(crate) fn length_field<T>(bytes: &[u8], read: fn(&[u8], usize) -> Option<T>) -> Result<T> {
+    read(bytes, 0).ok_or_else(truncated_entry)
+}
+
+/// Split `len` bytes off the front of a slice
+fn take<'a>(rest: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    let (head, tail) = rest.split_at_checked(len).ok_or_else(truncated_entry)?;
+    *rest = tail;
+    Ok(head)
+}
+
+/// Error for an entry that runs past the end of the payload
+fn truncated_entry() -> HideError {
+    HideError::InvalidParameters("Truncated container entry".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entries_round_trip() {
+        let entries = vec![
+            ("note".to_string(), b"hello".to_vec()),
+            ("empty".to_string(), Vec::new()),
+        ];
+
+        let parsed = parse_entries(&encode_entries(&entries).unwrap()).unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].name, "note");
+        assert_eq!(parsed[0].data, b"hello");
+        assert_eq!(parsed[1].name, "empty");
+        assert!(parsed[1].data.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_entries_are_rejected() {
+        let duplicate = vec![("sig".to_string(), vec![1]), ("sig".to_string(), vec![2])];
+        assert!(encode_entries(&duplicate).is_err());
+        assert!(encode_entries(&[(String::new(), vec![1])]).is_err());
+
+        // A value length running past the end of the payload
+        let mut payload = encode_entries(&[("note".to_string(), b"hello".to_vec())]).unwrap();
+        payload.truncate(payload.len() - 1);
+        assert!(parse_entries(&payload).is_err());
+    }
+}
@@ -0,0 +1,504 @@
+//! Passphrase encryption of payloads with AES-256-GCM or ChaCha20-Poly1305
+//!
+//! [`encrypt`] derives a key from a passphrase under a random salt and
+//! seals the message under a random nonce with the chosen [`CipherSuite`].
+//! The embedded payload is the suite's ID byte, the salt, the nonce, the
+//! ciphertext and the 16-byte tag, in that order, so decoders pick the
+//! cipher from the payload and a wrong passphrase or any change to the
+//! payload fails authentication instead of decrypting to garbage.
+//!
+//! Keys come from the configured [`SharedKdf`], or from PBKDF2-HMAC-SHA256
+//! with [`PBKDF2_ITERATIONS`] rounds when none is set; decoders must use the
+//! same one. The ciphers and PBKDF2 come from the RustCrypto `aes-gcm`,
+//! `chacha20poly1305` and `pbkdf2` crates, and are checked here against
+//! their published test vectors.
+//!
+//! A wrong passphrase and a tampered payload fail alike, with
+//! [`HideError::DecryptionFailed`]: the AEAD crates check tags in constant
+//! time, and [`reject`] spends the same key derivation and tag check on a
+//! payload its caller already found bad before failing it.
+
+use crate::encoder::Secret;
+use crate::error::HideError;
+use crate::rng::{self, KdfParams, Key, SharedKdf, SharedRng};
+use crate::Result;
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::fmt;
+use std::str::FromStr;
+
+/// Size of the cipher suite ID that starts an encrypted payload
+pub const SUITE_ID_SIZE: usize = 1;
+
+/// Size of the random salt the key is derived under
+pub const SALT_SIZE: usize = 16;
+
+/// Size of the random nonce, the same for both suites
+pub const NONCE_SIZE: usize = 12;
+
+/// Size of the authentication tag, the same for both suites
+pub const TAG_SIZE: usize = 16;
+
+/// Bytes encryption adds to a message
+pub const ENCRYPTION_OVERHEAD: usize = SUITE_ID_SIZE + SALT_SIZE + NONCE_SIZE + TAG_SIZE;
+
+/// PBKDF2 rounds of the built-in key derivation
+pub const PBKDF2_ITERATIONS: u32 = 100_000;
+
+/// Associated data authenticated with every payload, naming its layout;
+/// the suite ID is appended
+const ASSOCIATED_DATA: &[u8] = b"hide-rs encrypted payload v1";
+
+/// The authenticated ciphers payloads can be sealed with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CipherSuite {
+    /// AES-256 in Galois/counter mode, fastest with AES hardware
+    #[default]
+    Aes256Gcm,
+
+    /// ChaCha20 with a Poly1305 tag (RFC 8439), fastest without AES hardware
+    ChaCha20Poly1305,
+}
+
+impl CipherSuite {
+    /// The ID byte that starts payloads sealed with this suite
+    pub fn id(self) -> u8 {
+        match self {
+            Self::Aes256Gcm => 1,
+            Self::ChaCha20Poly1305 => 2,
+        }
+    }
+
+    /// The suite an ID byte names
+    ///
+    /// # Returns
+    /// * The suite, or [`HideError::UnknownCipher`] for an ID no suite has
+    pub fn from_id(id: u8) -> Result<Self> {
+        match id {
+            1 => Ok(Self::Aes256Gcm),
+            2 => Ok(Self::ChaCha20Poly1305),
+            _ => Err(HideError::UnknownCipher { id }),
+        }
+    }
+
+    /// Encrypt and authenticate a message
+    ///
+    /// # Returns
+    /// * The ciphertext followed by the tag, or [`HideError::MessageTooLarge`]
+    ///   for a message longer than the cipher allows
+    fn seal(
+        self,
+        key: &[u8; 32],
+        nonce: &[u8; NONCE_SIZE],
+        message: &[u8],
+        aad: &[u8],
+    ) -> Result<Vec<u8>> {
+        match self {
+            Self::Aes256Gcm => aes_gcm::Aes256Gcm::new(key.into())
+                .encrypt(nonce.into(), Payload { msg: message, aad })
+                .map_err(|_| HideError::MessageTooLarge),
+            Self::ChaCha20Poly1305 => chacha20poly1305::ChaCha20Poly1305::new(key.into())
+                .encrypt(nonce.into(), Payload { msg: message, aad })
+                .map_err(|_| HideError::MessageTooLarge),
+        }
+    }
+
+    /// Check and decrypt a sealed message
+    fn open(
+        self,
+        key: &[u8; 32],
+        nonce: &[u8; NONCE_SIZE],
+        sealed: &[u8],
+        aad: &[u8],
+    ) -> Option<Vec<u8>> {
+        match self {
+            Self::Aes256Gcm => aes_gcm::Aes256Gcm::new(key.into())
+                .decrypt(nonce.into(), Payload { msg: sealed, aad })
+                .ok(),
+            Self::ChaCha20Poly1305 => chacha20poly1305::ChaCha20Poly1305::new(key.into())
+                .decrypt(nonce.into(), Payload { msg: sealed, aad })
+                .ok(),
+        }
+    }
+}
+
+impl fmt::Display for CipherSuite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Aes256Gcm => "aes-256-gcm",
+            Self::ChaCha20Poly1305 => "chacha20-poly1305",
+        })
+    }
+}
+
+impl FromStr for CipherSuite {
+    type Err = HideError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "aes-256-gcm" => Ok(Self::Aes256Gcm),
+            "chacha20-poly1305" => Ok(Self::ChaCha20Poly1305),
+            _ => Err(HideError::InvalidParameters(format!(
+                "Unknown cipher suite: {} (expected aes-256-gcm or chacha20-poly1305)",
+                s
+            ))),
+        }
+    }
+}
+
+/// Encrypt a message under a passphrase
+///
+/// # Arguments
+/// * `message` - The message to encrypt
+/// * `passphrase` - The passphrase the key is derived from
+/// * `suite` - The cipher to seal the message with
+/// * `kdf` - Key derivation to use instead of PBKDF2
+/// * `rng` - Source of the salt and nonce; the system generator when unset
+///
+/// # Returns
+/// * The suite ID, salt, nonce, ciphertext and tag
+pub fn encrypt(
+    message: &[u8],
+    passphrase: &Secret,
+    suite: CipherSuite,
+    kdf: Option<&SharedKdf>,
+    rng: Option<&SharedRng>,
+) -> Result<Vec<u8>> {
+    let mut salt = [0u8; SALT_SIZE];
+    let mut nonce = [0u8; NONCE_SIZE];
+    rng::fill_bytes(rng, &mut salt);
+    rng::fill_bytes(rng, &mut nonce);
+    let key = derive_key(passphrase, &salt, kdf)?;
+
+    let mut payload = Vec::with_capacity(ENCRYPTION_OVERHEAD + message.len());
+    payload.push(suite.id());
+    payload.extend_from_slice(&salt);
+    payload.extend_from_slice(&nonce);
+    payload.extend_from_slice(&suite.seal(
+        key.as_bytes(),
+        &nonce,
+        message,
+        &associated_data(suite),
+    )?);
+    Ok(payload)
+}
+
+/// Decrypt a payload produced by [`encrypt`], with the suite it names
+///
+/// # Arguments
+/// * `payload` - The suite ID, salt, nonce, ciphertext and tag
+/// * `passphrase` - The passphrase the key is derived from
+/// * `kdf` - Key derivation the payload was encrypted with, if not PBKDF2
+///
+/// # Returns
+/// * The message, or [`HideError::DecryptionFailed`] if the passphrase is
+///   wrong, the payload was changed, is too short or names no known suite;
+///   which it was is only logged
+pub fn decrypt(payload: &[u8], passphrase: &Secret, kdf: Option<&SharedKdf>) -> Result<Vec<u8>> {
+    let suite = match payload.first().map(|&id| CipherSuite::from_id(id)) {
+        Some(Ok(suite)) if payload.len() >= ENCRYPTION_OVERHEAD => suite,
+        Some(Err(e)) => {
+            debug!("{}", e);
+            return Err(reject(payload, passphrase, kdf));
+        }
+        _ => {
+            debug!("Encrypted payload of {} bytes is too short", payload.len());
+            return Err(reject(payload, passphrase, kdf));
+        }
+    };
+
+    open_payload(suite, payload, passphrase, kdf)?.ok_or_else(|| {
+        debug!("Authentication tag of the {} payload doesn't match", suite);
+        HideError::DecryptionFailed
+    })
+}
+
+/// Fail a payload found bad before decrypting it, as a wrong passphrase would
+///
+/// The key is derived and a tag checked over the payload, padded out if it
+/// is too short to hold one, with the suite it names or AES-256-GCM. A
+/// payload that fails an integrity check then costs as much work as one
+/// opened with the wrong passphrase, whatever its bytes are.
+///
+/// # Arguments
+/// * `payload` - The payload as read from the image
+/// * `passphrase` - The passphrase the key is derived from
+/// * `kdf` - Key derivation the payload was encrypted with, if not PBKDF2
+///
+/// # Returns
+/// * [`HideError::DecryptionFailed`]
+pub(crate) fn reject(payload: &[u8], passphrase: &Secret, kdf: Option<&SharedKdf>) -> HideError {
+    let suite = payload
+        .first()
+        .and_then(|&id| CipherSuite::from_id(id).ok())
+        .unwrap_or_default();
+    let mut padded = payload.to_vec();
+    padded.resize(payload.len().max(ENCRYPTION_OVERHEAD), 0);
+
+    // Only the work matters, not whether the tag happens to match
+    let _ = std::hint::black_box(open_payload(suite, &padded, passphrase, kdf));
+    HideError::DecryptionFailed
+}
+
+/// Derive the key of a payload and open it with a suite
+///
+/// # Returns
+/// * The message, `None` if the tag doesn't match, or the key derivation's
+///   error; [`HideError::DecryptionFailed`] if the payload is shorter than
+///   [`ENCRYPTION_OVERHEAD`]
+fn open_payload(
+    suite: CipherSuite,
+    payload: &[u8],
+    passphrase: &Secret,
+    kdf: Option<&SharedKdf>,
+) -> Result<Option<Vec<u8>>> {
+    let (salt, rest) = payload
+        .get(SUITE_ID_SIZE..)
+        .and_then(|rest| rest.split_at_checked(SALT_SIZE))
+        .ok_or(HideError::DecryptionFailed)?;
+    let (nonce, sealed) = rest
+        .split_at_checked(NONCE_SIZE)
+        .ok_or(HideError::DecryptionFailed)?;
+    let nonce: &[u8; NONCE_SIZE] = nonce.try_into().map_err(|_| HideError::DecryptionFailed)?;
+
+    let key = derive_key(passphrase, salt, kdf)?;
+    Ok(suite.open(key.as_bytes(), nonce, sealed, &associated_data(suite)))
+}
+
+/// The associated data of a payload sealed with a suite
+fn associated_data(suite: CipherSuite) -> Vec<u8> {
+    let mut aad = ASSOCIATED_DATA.to_vec();
+    aad.push(suite.id());
+    aad
+}
+
+/// Derive the key of a payload from its passphrase and salt
+fn derive_key(passphrase: &Secret, salt: &[u8], kdf: Option<&SharedKdf>) -> Result<Key> {
+    match kdf {
+        Some(kdf) => kdf.derive(passphrase, salt, &KdfParams::default()),
+        None => Ok(pbkdf2_sha256(
+            passphrase.expose().as_bytes(),
+            salt,
+            PBKDF2_ITERATIONS,
+        )),
+    }
+}
+
+/// PBKDF2-HMAC-SHA256 with a 32-byte output (RFC 8018)
+fn pbkdf2_sha256(password: &[u8], salt: &[u8], iterations: u32) -> Key {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(password, salt, iterations, &mut key);
+    Key::new(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Kdf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Fast key derivation that counts the keys it derives
+    struct CountingKdf(Arc<AtomicUsize>);
+
+    impl Kdf for CountingKdf {
+        fn derive(&self, passphrase: &Secret, salt: &[u8], _params: &KdfParams) -> Result<Key> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            let mut key = [0u8; 32];
+            for (i, byte) in passphrase
+                .expose()
+                .bytes()
+                .chain(salt.iter().copied())
+                .enumerate()
+            {
+                key[i % 32] ^= byte;
+            }
+            Ok(Key::new(key))
+        }
+    }
+
+    fn hex(text: &str) -> Vec<u8> {
+        (0..text.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_gcm_matches_nist_vectors() {
+        let gcm = CipherSuite::Aes256Gcm;
+
+        // Test cases 13 and 14 of the GCM specification: zero key and nonce
+        assert_eq!(
+            gcm.seal(&[0; 32], &[0; 12], &[], &[]).unwrap(),
+            hex("530f8afbc74536b9a963b4f1c4cb738b")
+        );
+        assert_eq!(
+            gcm.seal(&[0; 32], &[0; 12], &[0; 16], &[]).unwrap(),
+            hex("cea7403d4d606b6e074ec5d3baf39d18d0d1c8a799996bf0265b98b5d48ab919")
+        );
+
+        // Test case 16: a partial last block and associated data
+        let key: [u8; 32] = hex("feffe9928665731c6d6a8f9467308308feffe9928665731c6d6a8f9467308308")
+            .try_into()
+            .unwrap();
+        let nonce: [u8; 12] = hex("cafebabefacedbaddecaf888").try_into().unwrap();
+        let message = hex(concat!(
+            "d9313225f88406e5a55909c5aff5269a86a7a9531534f7da2e4c303d8a318a72",
+            "1c3c0c95956809532fcf0e2449a6b525b16aedf5aa0de657ba637b39"
+        ));
+        let aad = hex("feedfacedeadbeeffeedfacedeadbeefabaddad2");
+        let sealed = gcm.seal(&key, &nonce, &message, &aad).unwrap();
+        assert_eq!(
+            sealed,
+            hex(concat!(
+                "522dc1f099567d07f47f37a32a84427d643a8cdcbfe5c0c97598a2bd2555d1aa",
+                "8cb08e48590dbb3da7b08b1056828838c5f61e6393ba7a0abcc9f662",
+                "76fc6ece0f4e1768cddf8853bb2d551b"
+            ))
+        );
+        assert_eq!(gcm.open(&key, &nonce, &sealed, &aad).unwrap(), message);
+        assert_eq!(gcm.open(&key, &nonce, &sealed, b"other"), None);
+        assert_eq!(gcm.open(&key, &nonce, &sealed[..10], &aad), None);
+    }
+
+    #[test]
+    fn test_pbkdf2_matches_reference_outputs() {
+        assert_eq!(
+            pbkdf2_sha256(b"passwd", b"salt", 1).as_bytes().to_vec(),
+            hex("55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc")
+        );
+        assert_eq!(
+            pbkdf2_sha256(b"password", b"salt", 4096)
+                .as_bytes()
+                .to_vec(),
+            hex("c5e478d59288c841aa530db6845c4c8d962893a001ce4e11a4963873aa98134a")
+        );
+    }
+
+    #[test]
+    fn test_chacha20_poly1305_matches_rfc_8439() {
+        let chacha = CipherSuite::ChaCha20Poly1305;
+        let key: [u8; 32] = hex(concat!(
+            "808182838485868788898a8b8c8d8e8f",
+            "909192939495969798999a9b9c9d9e9f"
+        ))
+        .try_into()
+        .unwrap();
+        let nonce: [u8; 12] = hex("070000004041424344454647").try_into().unwrap();
+        let aad = hex("50515253c0c1c2c3c4c5c6c7");
+        let message: &[u8] = b"Ladies and Gentlemen of the class of '99: If I could offer you \
+            only one tip for the future, sunscreen would be it.";
+        let sealed = chacha.seal(&key, &nonce, message, &aad).unwrap();
+        assert_eq!(
+            sealed,
+            hex(concat!(
+                "d31a8d34648e60db7b86afbc53ef7ec2a4aded51296e08fea9e2b5a736ee62d6",
+                "3dbea45e8ca9671282fafb69da92728b1a71de0a9e060b2905d6a5b67ecd3b36",
+                "92ddbd7f2d778b8c9803aee328091b58fab324e4fad675945585808b4831d7bc",
+                "3ff4def08e4b7a9de576d26586cec64b6116",
+                "1ae10b594f09e26a7e902ecbd0600691"
+            ))
+        );
+        assert_eq!(chacha.open(&key, &nonce, &sealed, &aad).unwrap(), message);
+        assert_eq!(chacha.open(&key, &nonce, &sealed, b"other"), None);
+    }
+
+    #[test]
+    fn test_payload_round_trip_and_failures() {
+        let passphrase = Secret::new("correct horse");
+        for suite in [CipherSuite::Aes256Gcm, CipherSuite::ChaCha20Poly1305] {
+            let payload = encrypt(b"attack at dawn", &passphrase, suite, None, None).unwrap();
+            assert_eq!(payload.len(), ENCRYPTION_OVERHEAD + 14);
+            assert_eq!(payload[0], suite.id());
+            assert_eq!(
+                decrypt(&payload, &passphrase, None).unwrap(),
+                b"attack at dawn"
+            );
+
+            let wrong = Secret::new("wrong horse");
+            assert!(matches!(
+                decrypt(&payload, &wrong, None),
+                Err(HideError::DecryptionFailed)
+            ));
+            assert!(matches!(
+                decrypt(&payload[..ENCRYPTION_OVERHEAD - 1], &passphrase, None),
+                Err(HideError::DecryptionFailed)
+            ));
+
+            // Fresh salt and nonce every time
+            assert_ne!(
+                encrypt(b"attack at dawn", &passphrase, suite, None, None).unwrap(),
+                payload
+            );
+        }
+        assert!(matches!(
+            decrypt(&[], &passphrase, None),
+            Err(HideError::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn test_suite_ids_and_names() {
+        for suite in [CipherSuite::Aes256Gcm, CipherSuite::ChaCha20Poly1305] {
+            assert_eq!(CipherSuite::from_id(suite.id()).unwrap(), suite);
+            assert_eq!(suite.to_string().parse::<CipherSuite>().unwrap(), suite);
+        }
+        // A payload naming no suite fails like any other bad payload
+        let passphrase = Secret::new("correct horse");
+        for id in [0, 3, 0xff] {
+            assert!(matches!(
+                CipherSuite::from_id(id),
+                Err(HideError::UnknownCipher { id: unknown }) if unknown == id
+            ));
+            let mut payload = vec![0; ENCRYPTION_OVERHEAD + 8];
+            payload[0] = id;
+            assert!(matches!(
+                decrypt(&payload, &passphrase, None),
+                Err(HideError::DecryptionFailed)
+            ));
+        }
+        assert!("des".parse::<CipherSuite>().is_err());
+    }
+
+    #[test]
+    fn test_rejected_payloads_cost_a_key_derivation() {
+        let derived = Arc::new(AtomicUsize::new(0));
+        let kdf = SharedKdf::new(CountingKdf(Arc::clone(&derived)));
+        let passphrase = Secret::new("correct horse");
+        let mut tampered = encrypt(
+            b"attack at dawn",
+            &passphrase,
+            CipherSuite::ChaCha20Poly1305,
+            Some(&kdf),
+            None,
+        )
+        .unwrap();
+        *tampered.last_mut().unwrap() ^= 1;
+
+        // Whatever the bytes, even ones naming no suite, a key is derived
+        let payloads: [&[u8]; 4] = [&[], &[0xff; 3], &[0; ENCRYPTION_OVERHEAD + 8], &tampered];
+        for (count, payload) in payloads.into_iter().enumerate() {
+            let error = reject(payload, &passphrase, Some(&kdf));
+            assert!(matches!(error, HideError::DecryptionFailed));
+            assert_eq!(derived.load(Ordering::SeqCst), 2 + count);
+        }
+
+        // Failing like a wrong passphrase, which derives a key too
+        let wrong = decrypt(&tampered, &Secret::new("wrong horse"), Some(&kdf)).unwrap_err();
+        assert!(matches!(wrong, HideError::DecryptionFailed));
+        assert_eq!(derived.load(Ordering::SeqCst), 2 + payloads.len());
+
+        // So do payloads too short to open or naming no suite
+        let unknown = [&[0xff][..], &tampered[1..]].concat();
+        let bad: [&[u8]; 3] = [&[], &tampered[..ENCRYPTION_OVERHEAD - 1], &unknown];
+        for (count, payload) in bad.into_iter().enumerate() {
+            let error = decrypt(payload, &passphrase, Some(&kdf)).unwrap_err();
+            assert!(matches!(error, HideError::DecryptionFailed));
+            assert_eq!(derived.load(Ordering::SeqCst), 3 + payloads.len() + count);
+        }
+    }
+}
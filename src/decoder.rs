@@ -1,213 +1,2653 @@
 //! Decoding functionality for steganography
 
-use crate::bltm::BLTM3x3;
+use crate::bltm::{BLTM3x3, BLTM4x4, Bltm};
+use crate::cancel::{self, CancellationToken};
+use crate::compression;
+use crate::container::{self, ContainerEntry};
+use crate::crypto;
+use crate::depth::{self, EmbeddingDepth};
+use crate::ecc;
+use crate::encoder::Secret;
 use crate::error::HideError;
-use crate::img::StegoImage;
-use crate::utils;
+use crate::header::{
+    get_u16_be, get_u32_be, VersionedParser, CHECKSUM_SIZE, DIMENSIONS_SIZE, EXTENDED_HEADER_SIZE,
+    HEADER_SIZE,
+};
+use crate::img::{self, PixelSurface, Rect, StegoImage, TileLayout, TiledImage};
+use crate::layout::{BitOffset, PixelIndex, PixelX, PixelY};
+use crate::manifest::{self, Manifest, ManifestVerification};
+use crate::mask::PixelMask;
+use crate::padding;
+use crate::rgba;
+use crate::rng::{Key, SharedKdf};
+use crate::scatter;
+use crate::schedule::{self, ChannelOrder};
+use crate::trace::{PayloadTrace, TraceBuilder};
+use crate::utils::{self, Timing};
+use crate::warning::StegoWarning;
+use crate::watermark::{self, WatermarkResult};
+
+pub use crate::header::MessageHeader;
 use crate::Result;
 use bitvec::prelude::*;
-use std::path::Path;
+use image::{DynamicImage, Rgb, RgbImage, Rgba, RgbaImage};
+use log::{debug, warn};
+use std::borrow::Cow;
+use std::fs;
+use std::io;
+use std::iter::FusedIterator;
+use std::num::NonZeroUsize;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Result of a decode along with how long the core work took
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DetailedDecode {
+    /// The decoded message; always complete unless partial decoding was allowed
+    pub decoded: PartialDecode,
+
+    /// Time spent decoding and the number of pixels read
+    pub timing: Timing,
+
+    /// Things the decode noticed that didn't stop it
+    pub warnings: Vec<StegoWarning>,
+}
+
+/// Result of a lossy decode from a possibly truncated stego image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartialDecode {
+    /// The recovered message bytes (a prefix of the original message)
+    pub data: Vec<u8>,
+
+    /// Message length declared in the header (in bytes)
+    pub declared_length: usize,
+
+    /// Number of message bytes actually recovered
+    pub recovered_length: usize,
+
+    /// Whether the full declared message was recovered
+    pub complete: bool,
+}
+
+/// Hard limits for decoding images from untrusted sources
+///
+/// These bound the work done regardless of what the header declares.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UntrustedLimits {
+    /// Maximum number of message bytes the decode may produce
+    pub max_output_bytes: usize,
+
+    /// Maximum number of pixels the decode may read
+    pub max_pixels: u64,
+
+    /// Maximum wall-clock time the decode may take
+    pub deadline: Duration,
+}
+
+impl Default for UntrustedLimits {
+    fn default() -> Self {
+        Self {
+            max_output_bytes: 1024 * 1024,
+            max_pixels: 25_000_000,
+            deadline: Duration::from_secs(10),
+        }
+    }
+}
+
+/// How far [`Decoder::search_and_decode`] looks for a displaced payload
+///
+/// Every candidate costs one header check of a few dozen pixels, so the
+/// work is bounded by the window rather than the image size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchWindow {
+    /// Most whole rows the payload may have moved down by
+    pub max_rows: u32,
+
+    /// Most pixels the payload may have moved by, for starts inside a row
+    pub max_pixel_offset: u64,
+}
+
+impl Default for SearchWindow {
+    fn default() -> Self {
+        Self {
+            max_rows: 256,
+            max_pixel_offset: 1024,
+        }
+    }
+}
+
+impl SearchWindow {
+    /// Candidate starting pixels in row-major order, the row starts first
+    ///
+    /// # Arguments
+    /// * `width` - Width of the image searched
+    /// * `height` - Height of the image searched
+    fn candidates(&self, width: u32, height: u32) -> impl Iterator<Item = u64> {
+        let pixel_count = img::pixel_count(width, height);
+        let width = width.max(1) as u64;
+        let rows = (0..=self.max_rows.min(height) as u64).map(move |row| row * width);
+        let offsets =
+            (1..=self.max_pixel_offset).filter(move |offset| !offset.is_multiple_of(width));
+        rows.chain(offsets)
+            .filter(move |&offset| offset < pixel_count)
+    }
+}
+
+/// A message found by [`Decoder::search_and_decode`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedMessage {
+    /// The extracted message bytes
+    pub data: Vec<u8>,
+
+    /// Header of the payload
+    pub header: MessageHeader,
+
+    /// Index of the pixel the payload starts at, in row-major order
+    pub pixel_offset: u64,
+
+    /// Column and row of that pixel
+    pub position: (u32, u32),
+
+    /// Things the decode noticed that didn't stop it
+    pub warnings: Vec<StegoWarning>,
+
+    /// Header bits [`Decoder::decode_with_repair`] flipped back, counted from
+    /// the first header bit; empty if the header was used as read
+    pub repaired_bits: Vec<usize>,
+}
+
+/// Options controlling how messages are decoded
+#[derive(Debug, Clone, Default)]
+pub struct DecoderConfig {
+    /// Token to abort a long decode
+    pub cancel: Option<CancellationToken>,
+
+    /// Decode payloads with unknown header flags as raw bytes instead of failing
+    pub ignore_unknown_flags: bool,
+
+    /// Passphrase for payloads embedded with a keyed channel schedule
+    pub passphrase: Option<Secret>,
+
+    /// Key derivation for keyed modes, such as the keyed channel schedule
+    pub kdf: Option<SharedKdf>,
+
+    /// Mask for masked payloads encoded without an embedded copy of it
+    pub mask: Option<PixelMask>,
+
+    /// Width and height the cover had when the message was encoded, if known;
+    /// failed decodes of differently sized images are reported as
+    /// [`HideError::DimensionsChanged`]
+    pub expected_dimensions: Option<(u32, u32)>,
+
+    /// Let [`Decoder::decode_with_repair`] also try flipping pairs of header
+    /// bits, which takes longer and is likelier to accept a wrong header
+    pub repair_bit_pairs: bool,
+
+    /// Key the payload's pixel order was shuffled with (see [`crate::scatter`])
+    pub pixel_key: Option<Key>,
+
+    /// Return checksummed payloads that fail their CRC-32 instead of
+    /// reporting [`HideError::ChecksumMismatch`], to recover what is left of
+    /// a damaged message
+    pub skip_checksum: bool,
+}
+
+/// Decodes a message from a steganography image using BLTM method
+///
+/// Decoders are `Send` and `Sync`, so one can be shared between threads.
+pub struct Decoder {
+    /// The BLTM used for decoding
+    bltm: BLTM3x3,
+
+    /// Options for this decoder
+    config: DecoderConfig,
+}
+
+static_assertions::assert_impl_all!(DecoderConfig: Send, Sync);
+static_assertions::assert_impl_all!(Decoder: Send, Sync);
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder {
+    /// Create a new decoder with a 3x3 BLTM
+    pub fn new() -> Self {
+        Self::with_config(DecoderConfig::default())
+    }
+
+    /// Create a new decoder with the given options
+    pub fn with_config(config: DecoderConfig) -> Self {
+        Self {
+            bltm: BLTM3x3::new(),
+            config,
+        }
+    }
+
+    /// Create a new decoder for payloads embedded with a pixel key
+    ///
+    /// Without the right key the header can't be found, and decodes fail
+    /// with [`HideError::NoMessageFound`].
+    ///
+    /// # Arguments
+    /// * `key` - The key the encoder used
+    pub fn with_key(key: &[u8]) -> Self {
+        Self::with_config(DecoderConfig {
+            pixel_key: Some(scatter::derive_key(key)),
+            ..Default::default()
+        })
+    }
+
+    /// Get the options used by this decoder
+    pub fn config(&self) -> &DecoderConfig {
+        &self.config
+    }
+
+    /// Decode a single pixel to extract message bits
+    ///
+    /// # Arguments
+    /// * `r` - Red component value (0-255)
+    /// * `g` - Green component value (0-255)
+    /// * `b` - Blue component value (0-255)
+    ///
+    /// # Returns
+    /// * 3 bits of the hidden message
+    pub fn decode_pixel(&self, r: u8, g: u8, b: u8) -> BitVec<u8, Msb0> {
+        // Step 5-6: Extract LSBs to form stego vector vs
+        let mut stego_vector = BitVec::<u8, Msb0>::new();
+        stego_vector.push(r & 1 != 0); // LSB of R
+        stego_vector.push(g & 1 != 0); // LSB of G
+        stego_vector.push(b & 1 != 0); // LSB of B
+
+        // Step 7: Find v^T = vs^T (transpose not needed for a single vector)
+
+        // Step 8-9: m = (A × v^T)^T
+        self.matrix_multiply(&stego_vector)
+    }
+
+    /// Decode a pixel whose channels were embedded in the given order
+    fn decode_pixel_in_order(&self, pixel: Rgb<u8>, order: [usize; 3]) -> BitVec<u8, Msb0> {
+        let [r, g, b] = schedule::permute(pixel.0, order);
+        self.decode_pixel(r, g, b)
+    }
+
+    /// Decode a pixel whose channels were embedded in the given order, without allocating
+    ///
+    /// # Returns
+    /// * The pixel's 3 message bits in the low bits of a byte, first bit most significant
+    fn pixel_bits(&self, pixel: Rgb<u8>, order: [usize; 3]) -> u8 {
+        let stego_vector = schedule::permute(pixel.0, order).map(|channel| channel & 1 != 0);
+        Bltm::<3>::new()
+            .multiply(stego_vector)
+            .into_iter()
+            .fold(0, |bits, bit| (bits << 1) | u8::from(bit))
+    }
+
+    /// Channel orders of the payload behind a header
+    fn channel_order(&self, header: &MessageHeader) -> Result<ChannelOrder> {
+        ChannelOrder::new(
+            header.channel_schedule()?,
+            header.pixels(),
+            self.config.passphrase.as_ref(),
+            self.config.kdf.as_ref(),
+        )
+    }
+
+    /// Decode a single RGBA pixel to extract message bits
+    ///
+    /// # Arguments
+    /// * `r` - Red component value (0-255)
+    /// * `g` - Green component value (0-255)
+    /// * `b` - Blue component value (0-255)
+    /// * `a` - Alpha component value (0-255)
+    ///
+    /// # Returns
+    /// * 4 bits of the hidden message
+    pub fn decode_pixel_rgba(&self, r: u8, g: u8, b: u8, a: u8) -> BitVec<u8, Msb0> {
+        let stego_vector = [r, g, b, a].map(|channel| channel & 1 != 0);
+        BLTM4x4::new().multiply(stego_vector).into_iter().collect()
+    }
+
+    /// Matrix-vector multiplication: A × v
+    // Indices stay below 3: the matrix is 3x3 and `v` always holds one pixel's bits
+    #[allow(clippy::needless_range_loop, clippy::indexing_slicing)]
+    fn matrix_multiply(&self, v: &BitSlice<u8, Msb0>) -> BitVec<u8, Msb0> {
+        let columns = self.bltm.columns();
+        let mut result = BitVec::<u8, Msb0>::new();
+
+        // For each row of the matrix
+        for i in 0..3 {
+            // Calculate dot product of row i with vector v
+            let mut bit = false;
+            for j in 0..3 {
+                // Extract the bit from A at position (i,j)
+                // Note: columns are stored from right to left, so we access as columns[2-j]
+                // For a lower triangular matrix, if i < j, the value is 0
+                if i >= j {
+                    let matrix_bit = columns[2 - j][i];
+                    bit ^= matrix_bit && v[j]; // XOR with the AND of matrix bit and vector bit
+                }
+            }
+            result.push(bit);
+        }
+
+        result
+    }
+
+    /// Parse the header fields from the first bytes of a payload
+    fn parse_header(header_bytes: &[u8]) -> Result<MessageHeader> {
+        VersionedParser::parse(header_bytes)
+    }
+
+    /// Apply the unknown flag policy to a header
+    ///
+    /// Unknown flags may change how the payload must be interpreted, so they
+    /// are rejected unless `ignore_unknown_flags` is set.
+    fn check_flags(&self, header: &MessageHeader) -> Result<()> {
+        let bits = header.unknown_flag_bits();
+        if bits == 0 {
+            return Ok(());
+        }
+
+        if !self.config.ignore_unknown_flags {
+            return Err(HideError::UnsupportedFeature {
+                version: header.format_version,
+                bits,
+            });
+        }
+
+        warn!(
+            "Ignoring unknown header flags {:#06x}; returning the raw payload bytes",
+            bits
+        );
+        Ok(())
+    }
+
+    /// Read only the header from an image
+    ///
+    /// # Arguments
+    /// * `stego_image` - The image containing the hidden message
+    ///
+    /// # Returns
+    /// * The header fields, or an error if no valid header is present
+    pub fn read_header(&self, stego_image: &StegoImage) -> Result<MessageHeader> {
+        self.read_header_pixels(&*self.unscatter(stego_image))
+            .map(|(header, _)| header)
+    }
+
+    /// Read the pixels carrying the header and parse it
+    ///
+    /// # Arguments
+    /// * `image` - The image containing the hidden message
+    ///
+    /// # Returns
+    /// * The header and the bits read, which run up to two bits past it
+    fn read_header_pixels<S: PixelSurface + ?Sized>(
+        &self,
+        image: &S,
+    ) -> Result<(MessageHeader, BitVec<u8, Msb0>)> {
+        let bits = self.read_header_bits(image)?;
+        let header_bytes = whole_bytes(&bits);
+        self.check_keyed_header(&header_bytes, image.width(), image.height())?;
+        let header = Self::parse_header(&header_bytes)?;
+        Ok((header, bits))
+    }
+
+    /// Reject a header read with a pixel key unless it looks like a payload
+    ///
+    /// A wrong key reads noise from shuffled pixels, which must not be
+    /// decoded as a message.
+    ///
+    /// # Arguments
+    /// * `header_bytes` - The header as read
+    /// * `width` - Width of the image it was read from
+    /// * `height` - Height of the image it was read from
+    fn check_keyed_header(&self, header_bytes: &[u8], width: u32, height: u32) -> Result<()> {
+        if self.config.pixel_key.is_some()
+            && plausible_header(header_bytes, width, height).is_none()
+        {
+            return Err(HideError::NoMessageFound);
+        }
+        Ok(())
+    }
+
+    /// The image with its pixels back in payload order, when a pixel key is set
+    ///
+    /// Public decode methods take images as they were saved and pass the
+    /// result on; everything they call reads pixels in row-major order.
+    fn unscatter<'a>(&self, stego_image: &'a StegoImage) -> Cow<'a, StegoImage> {
+        let Some(key) = &self.config.pixel_key else {
+            return Cow::Borrowed(stego_image);
+        };
+
+        let mut image = stego_image.clone();
+        let pixel_count = img::pixel_count(image.width(), image.height());
+        img::permute_pixels(
+            &mut image,
+            &scatter::pixel_order(key, pixel_count as usize),
+            false,
+        );
+        Cow::Owned(image)
+    }
+
+    /// Fail when a pixel key is set, for decode methods that don't support one
+    fn reject_pixel_key(&self, what: &str) -> Result<()> {
+        if self.config.pixel_key.is_some() {
+            return Err(HideError::InvalidParameters(format!(
+                "{} doesn't work with a pixel key",
+                what
+            )));
+        }
+        Ok(())
+    }
+
+    /// Read the pixels carrying the header without parsing it
+    ///
+    /// # Arguments
+    /// * `image` - The image containing the hidden message
+    ///
+    /// # Returns
+    /// * The bits read, which run up to two bits past the header
+    fn read_header_bits<S: PixelSurface + ?Sized>(&self, image: &S) -> Result<BitVec<u8, Msb0>> {
+        // Check if the image is big enough to contain a header
+        if img::capacity_bits(image.width(), image.height()) < (HEADER_SIZE * 8) as u64 {
+            return Err(HideError::NoMessageFound);
+        }
+
+        // The header pixels use the fixed order; the header names the rest's
+        let mut bits = BitVec::<u8, Msb0>::with_capacity(EXTENDED_HEADER_SIZE * 8 + 2);
+        self.read_pixels(image, &ChannelOrder::fixed(), 0..HEADER_PIXELS, &mut bits)?;
+        // Unknown versions are left for the parser to report
+        if let Ok(length) = VersionedParser::header_length(&whole_bytes(&bits)) {
+            self.fit_header_bits(image, &mut bits, length)?;
+        }
+        Ok(bits)
+    }
+
+    /// Make header bits read from the first pixels cover a header of the given length
+    ///
+    /// The pixels after the ones read are read in the fixed order, and the
+    /// bits of pixels past the header dropped.
+    ///
+    /// # Returns
+    /// * [`HideError::NoMessageFound`] if the header runs past the last pixel
+    fn fit_header_bits<S: PixelSurface + ?Sized>(
+        &self,
+        image: &S,
+        bits: &mut BitVec<u8, Msb0>,
+        header_length: usize,
+    ) -> Result<()> {
+        let pixels = img::pixels_for_bits(header_length as u64 * 8);
+        if pixels > img::pixel_count(image.width(), image.height()) {
+            return Err(HideError::NoMessageFound);
+        }
+
+        let read = bits.len() as u64 / img::BITS_PER_PIXEL;
+        if pixels > read {
+            self.read_pixels(image, &ChannelOrder::fixed(), read..pixels, bits)?;
+        }
+        bits.truncate((pixels * img::BITS_PER_PIXEL) as usize);
+        Ok(())
+    }
+
+    /// Read the message pixels of a standard payload after its header
+    ///
+    /// # Arguments
+    /// * `image` - The image containing the hidden message
+    /// * `header` - The header already read from the image
+    /// * `bits` - The bits read along with the header
+    ///
+    /// # Returns
+    /// * The message bytes
+    fn read_message_pixels<S: PixelSurface + ?Sized>(
+        &self,
+        image: &S,
+        header: &MessageHeader,
+        bits: BitVec<u8, Msb0>,
+    ) -> Result<Vec<u8>> {
+        check_message_fits(image, header)?;
+
+        // Only the pixels after the header that hold message bits
+        let message_length = u64::from(header.message_length);
+        let mut cursor = PayloadCursor::after_header(self.channel_order(header)?, header, &bits);
+        // Fits in usize because the image holds that many bytes
+        let mut message = Vec::with_capacity(message_length as usize);
+        while let Some(byte) = cursor.next_byte(self, image)? {
+            message.push(byte);
+        }
+        Ok(message)
+    }
+
+    /// Extract the embedded bits from a range of pixels in row-major order
+    ///
+    /// # Arguments
+    /// * `image` - The image to read
+    /// * `order` - Channel orders of the pixels
+    /// * `pixels` - Indices of the pixels to read
+    /// * `bits` - Where the extracted bits are appended
+    fn read_pixels<S: PixelSurface + ?Sized>(
+        &self,
+        image: &S,
+        order: &ChannelOrder,
+        pixels: Range<u64>,
+        bits: &mut BitVec<u8, Msb0>,
+    ) -> Result<()> {
+        for index in pixels {
+            cancel::check_every(self.config.cancel.as_ref(), index as usize)?;
+
+            let pixel = image.get_pixel_at(PixelIndex(index))?;
+            bits.extend_from_bitslice(&self.decode_pixel_in_order(pixel, order.for_pixel(index)));
+        }
+        Ok(())
+    }
+
+    /// Check whether an image already holds a hide-rs payload
+    ///
+    /// Besides a valid header, the declared length must fit in the image and
+    /// the unused header bits must be clear, which makes false positives on
+    /// ordinary images unlikely.
+    ///
+    /// # Arguments
+    /// * `image` - The image to inspect
+    ///
+    /// # Returns
+    /// * The header of the existing payload, or `None` if there is none
+    pub fn detect<S: PixelSurface + ?Sized>(&self, image: &S) -> Option<MessageHeader> {
+        let header_bytes = PixelReader::new(self, image).read_header().ok()?;
+        plausible_header(&header_bytes, image.width(), image.height())
+    }
+
+    /// Decode a message from an image
+    ///
+    /// # Arguments
+    /// * `stego_image` - The image containing the hidden message
+    ///
+    /// # Returns
+    /// * The extracted message bytes; for container payloads these are the raw entry bytes
+    pub fn decode(&self, stego_image: &StegoImage) -> Result<Vec<u8>> {
+        let (header, message_bytes) = self.decode_payload(stego_image)?;
+        check_plain_message(&header)?;
+        Ok(message_bytes)
+    }
+
+    /// Decode a message from an image stored as separate tiles
+    ///
+    /// The tiles are read where they lie, and only the pixels holding the
+    /// header and message are looked up, so tiles past the payload are never
+    /// read and may be missing. RGBA, masked and keyed payloads are spread
+    /// over the whole image; for those the tiles are put together with
+    /// [`img::assemble_tiles`] first and must cover it.
+    ///
+    /// # Arguments
+    /// * `tiles` - The top-left corner of each tile in the full image, and the tile
+    /// * `layout` - Dimensions of the full image
+    ///
+    /// # Returns
+    /// * The message, [`HideError::TruncatedPayload`] if a missing tile held
+    ///   part of it, or [`HideError::NoMessageFound`] if one held part of the header
+    pub fn decode_tiles(
+        &self,
+        tiles: &[(u32, u32, StegoImage)],
+        layout: TileLayout,
+    ) -> Result<Vec<u8>> {
+        if self.config.pixel_key.is_some() {
+            return self.decode(&img::assemble_tiles(tiles, layout.width, layout.height)?);
+        }
+
+        let image = TiledImage::new(tiles, layout)?;
+        let covered = |pixels: u64| (0..pixels).all(|index| image.covers(PixelIndex(index)));
+        if !covered(HEADER_PIXELS) {
+            return Err(HideError::NoMessageFound);
+        }
+        let header_length = VersionedParser::header_length(
+            &PixelReader::new(self, &image).read_bytes(HEADER_SIZE)?,
+        )?;
+        if !covered(img::pixels_for_bits(header_length as u64 * 8)) {
+            return Err(HideError::NoMessageFound);
+        }
+        let (header, header_bits) = self.read_header_pixels(&image)?;
+        self.check_flags(&header)?;
+        if header.is_rgba() || header.is_masked() || header.embedding_depth() != EmbeddingDepth::One
+        {
+            return self.decode(&img::assemble_tiles(tiles, layout.width, layout.height)?);
+        }
+
+        // The message pixels up to the first one no tile covers are all there is
+        let pixels_needed =
+            img::pixels_for_bits(total_bits_with_header(&header, header.message_length))
+                .min(img::pixel_count(layout.width, layout.height));
+        if let Some(missing) =
+            (header.pixels()..pixels_needed).find(|&index| !image.covers(PixelIndex(index)))
+        {
+            return Err(truncated_payload(&header, missing * img::BITS_PER_PIXEL));
+        }
+
+        let payload = self.read_message_pixels(&image, &header, header_bits)?;
+        let message_bytes = self.message_from_payload(&header, payload)?;
+        check_plain_message(&header)?;
+        Ok(message_bytes)
+    }
+
+    /// Decode and decrypt a message encoded with [`Encoder::encode_encrypted`]
+    ///
+    /// The cipher suite is read from the payload. The key is derived with the
+    /// configured `kdf`, or PBKDF2 when none is set, which must match the
+    /// encoder's.
+    ///
+    /// # Arguments
+    /// * `stego_image` - The image containing the encrypted message
+    /// * `passphrase` - The passphrase the message was encrypted under
+    ///
+    /// # Returns
+    /// * The message, or [`HideError::DecryptionFailed`] if the passphrase is
+    ///   wrong or the payload was tampered with
+    ///
+    /// A payload whose checksum, padding, recorded dimensions or cipher suite
+    /// don't check out fails with [`HideError::DecryptionFailed`] too, after deriving the key
+    /// and computing a tag, so neither the error nor its timing tells a
+    /// tampered payload from a wrong passphrase. The precise cause is logged
+    /// at debug level.
+    ///
+    /// [`Encoder::encode_encrypted`]: crate::encoder::Encoder::encode_encrypted
+    pub fn decode_encrypted(
+        &self,
+        stego_image: &StegoImage,
+        passphrase: &Secret,
+    ) -> Result<Vec<u8>> {
+        let (header, payload) = self
+            .read_payload(&self.unscatter(stego_image))
+            .map_err(|e| self.diagnose(stego_image, e))?;
+        if !header.is_encrypted() {
+            return Err(HideError::InvalidParameters(
+                "The payload is not encrypted; decode it without a passphrase".to_string(),
+            ));
+        }
+
+        let kdf = self.config.kdf.as_ref();
+        match self.message_from_payload(&header, payload.clone()) {
+            Ok(sealed) => crypto::decrypt(&sealed, passphrase, kdf),
+            Err(e) => {
+                debug!("Encrypted payload failed its integrity checks: {}", e);
+                Err(crypto::reject(&payload, passphrase, kdf))
+            }
+        }
+    }
+
+    /// Decode every entry of a container payload
+    ///
+    /// # Arguments
+    /// * `stego_image` - The image containing the container
+    ///
+    /// # Returns
+    /// * The entries in the order they were encoded
+    pub fn decode_container(&self, stego_image: &StegoImage) -> Result<Vec<ContainerEntry>> {
+        let (header, payload) = self.decode_payload(stego_image)?;
+        if !header.is_container() {
+            return Err(not_a_container());
+        }
+
+        container::parse_entries(&payload)
+    }
+
+    /// Decode a single entry of a container payload
+    ///
+    /// Only the pixels holding the entry headers up to the requested entry and
+    /// its value are read; the values of other entries are skipped.
+    ///
+    /// # Arguments
+    /// * `stego_image` - The image containing the container
+    /// * `name` - Name of the entry to read
+    ///
+    /// # Returns
+    /// * The entry contents, or `None` if the container has no such entry
+    pub fn decode_entry(&self, stego_image: &StegoImage, name: &str) -> Result<Option<Vec<u8>>> {
+        // Keyed payloads aren't laid out for the pixel reader either
+        if self.config.pixel_key.is_some() {
+            return self.find_entry(stego_image, name);
+        }
+
+        let mut reader = PixelReader::new(self, stego_image);
+
+        let header = Self::parse_header(&reader.read_header()?)?;
+        self.check_flags(&header)?;
+        if !header.is_container() {
+            return Err(not_a_container());
+        }
+        reader.order = self.channel_order(&header)?;
+
+        // RGBA, masked, two-bit, padded, checksummed and error-corrected
+        // payloads aren't laid out for the pixel reader; decode them whole
+        if header.is_rgba()
+            || header.is_masked()
+            || header.embedding_depth() != EmbeddingDepth::One
+            || header.is_padded()
+            || header.is_checksummed()
+            || header.is_error_corrected()
+        {
+            return self.find_entry(stego_image, name);
+        }
+
+        let mut remaining = header.message_length as u64;
+        if header.records_dimensions() {
+            reader.skip_entry_bytes(DIMENSIONS_SIZE as u64, &mut remaining)?;
+        }
+        while remaining > 0 {
+            let name_len = container::length_field(
+                &reader.read_entry_bytes(container::NAME_LENGTH_SIZE, &mut remaining)?,
+                get_u16_be,
+            )?;
+            let entry_name = container::parse_name(
+                &reader.read_entry_bytes(name_len as usize, &mut remaining)?,
+            )?;
+            let data_len = container::length_field(
+                &reader.read_entry_bytes(container::VALUE_LENGTH_SIZE, &mut remaining)?,
+                get_u32_be,
+            )?;
+
+            if entry_name == name {
+                return reader
+                    .read_entry_bytes(data_len as usize, &mut remaining)
+                    .map(Some);
+            }
+            reader.skip_entry_bytes(data_len as u64, &mut remaining)?;
+        }
+
+        Ok(None)
+    }
+
+    /// Decode a message lazily, one byte at a time
+    ///
+    /// The header is read and checked first. After that each byte is decoded
+    /// from its pixels as the iterator advances, holding no more than one
+    /// pixel's bits, so the message is never buffered whole. Recorded cover
+    /// dimensions are skipped, and a checksum is checked after the last byte.
+    /// RGBA, masked and two-bit layouts, and compressed, error-corrected or
+    /// padded payloads, can only be recovered whole with [`Decoder::decode`], and
+    /// encrypted ones with [`Decoder::decode_encrypted`]. With a pixel key the
+    /// pixels are unscrambled into a copy of the image first.
+    ///
+    /// # Arguments
+    /// * `stego_image` - The image containing the hidden message
+    ///
+    /// # Returns
+    /// * An iterator over the message bytes, or an error if the header is
+    ///   invalid, the image is too small for the declared message or the
+    ///   payload can't be read a byte at a time
+    pub fn decode_iter<'a>(&'a self, stego_image: &'a StegoImage) -> Result<PayloadIter<'a>> {
+        let image = self.unscatter(stego_image);
+        let (header, header_bits) = self
+            .read_header_pixels(&*image)
+            .map_err(|e| self.diagnose(stego_image, e))?;
+        self.check_flags(&header)?;
+        check_plain_message(&header)?;
+        if header.is_rgba()
+            || header.is_masked()
+            || header.embedding_depth() != EmbeddingDepth::One
+            || header.is_compressed()
+            || header.is_error_corrected()
+            || header.is_padded()
+        {
+            return Err(HideError::InvalidParameters(
+                "This payload can only be decoded whole, not byte by byte".to_string(),
+            ));
+        }
+        check_message_fits(&*image, &header)?;
+
+        let mut cursor =
+            PayloadCursor::after_header(self.channel_order(&header)?, &header, &header_bits);
+        if header.records_dimensions() {
+            for _ in 0..DIMENSIONS_SIZE {
+                cursor.next_byte(self, &*image)?.ok_or_else(|| {
+                    HideError::InvalidParameters(
+                        "Payload is too short to hold the recorded cover dimensions".to_string(),
+                    )
+                })?;
+            }
+        }
+        let checksum_size = if header.is_checksummed() {
+            CHECKSUM_SIZE
+        } else {
+            0
+        };
+        let remaining = cursor
+            .remaining
+            .checked_sub(checksum_size as u64)
+            .ok_or_else(|| {
+                HideError::InvalidParameters(
+                    "Payload is too short to hold its checksum".to_string(),
+                )
+            })?;
+
+        Ok(PayloadIter {
+            decoder: self,
+            image,
+            cursor,
+            remaining,
+            crc: header.is_checksummed().then_some(0),
+            finished: false,
+        })
+    }
+
+    /// Decode a whole container and pick one entry out of it
+    fn find_entry(&self, stego_image: &StegoImage, name: &str) -> Result<Option<Vec<u8>>> {
+        let entries = self.decode_container(stego_image)?;
+        Ok(entries
+            .into_iter()
+            .find(|entry| entry.name == name)
+            .map(|entry| entry.data))
+    }
+
+    /// Decode a message embedded in a region of an image
+    ///
+    /// Use this for images encoded through a view; the header is read from
+    /// the region's top-left pixel.
+    ///
+    /// # Arguments
+    /// * `stego_image` - The image containing the hidden message
+    /// * `rect` - The region the message was embedded into
+    ///
+    /// # Returns
+    /// * The extracted message bytes
+    pub fn decode_view(&self, stego_image: &StegoImage, rect: Rect) -> Result<Vec<u8>> {
+        rect.check_within(stego_image.width(), stego_image.height())?;
+        let region = stego_image
+            .inner()
+            .crop_imm(rect.x, rect.y, rect.width, rect.height);
+        self.decode(&StegoImage::from_dynamic_image(region))
+    }
+
+    /// Decode a message that may not start at the first pixel
+    ///
+    /// Padding or letterboxing added after encoding moves the payload down
+    /// the image. Each candidate start in the window is checked like
+    /// [`Decoder::detect`] before the payload is decoded from it.
+    ///
+    /// # Arguments
+    /// * `stego_image` - The image containing the hidden message
+    /// * `window` - Which starting pixels to try
+    ///
+    /// # Returns
+    /// * The message and where it starts; if no candidate decodes, the error
+    ///   of the first one that looked like a payload
+    pub fn search_and_decode(
+        &self,
+        stego_image: &StegoImage,
+        window: SearchWindow,
+    ) -> Result<DecodedMessage> {
+        self.reject_pixel_key("Searching for a payload")?;
+        let width = stego_image.width();
+        let mut first_error = None;
+
+        for offset in window.candidates(width, stego_image.height()) {
+            if self
+                .detect(&ShiftedSurface::new(stego_image, offset))
+                .is_none()
+            {
+                continue;
+            }
+
+            let decoded = if offset == 0 {
+                self.decode_payload(stego_image)
+            } else {
+                self.decode_payload(&shifted_image(stego_image, offset))
+            };
+            match decoded {
+                Ok((header, data)) => {
+                    let (x, y) = PixelIndex(offset).to_xy(width);
+                    return Ok(DecodedMessage {
+                        data,
+                        header,
+                        pixel_offset: offset,
+                        position: (x.get(), y.get()),
+                        warnings: header_warnings(&header),
+                        repaired_bits: Vec::new(),
+                    });
+                }
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+
+        Err(first_error.unwrap_or(HideError::NoMessageFound))
+    }
+
+    /// Decode the header and payload bytes from an image
+    pub(crate) fn decode_payload(
+        &self,
+        stego_image: &StegoImage,
+    ) -> Result<(MessageHeader, Vec<u8>)> {
+        self.read_payload(&self.unscatter(stego_image))
+            .and_then(|(header, payload)| {
+                Ok((header, self.message_from_payload(&header, payload)?))
+            })
+            .map_err(|e| self.diagnose(stego_image, e))
+    }
+
+    /// Decode a message, repairing a header with one flipped bit
+    ///
+    /// When the header read from the image doesn't hold up, every header
+    /// with one bit flipped (or two, with
+    /// [`DecoderConfig::repair_bit_pairs`]) is tried in bit order. A
+    /// candidate must look like a payload the way [`Decoder::detect`]
+    /// requires, and its payload must decode. Padded payloads also have to
+    /// match the CRC in their trailer, so a candidate that passes it is
+    /// accepted over any that can't be checked, including the header as
+    /// read. Other payloads carry no checksum, so a flip that leaves the
+    /// header plausible can go unnoticed.
+    ///
+    /// # Arguments
+    /// * `stego_image` - The image containing the hidden message
+    ///
+    /// # Returns
+    /// * The message along with the header bits that were flipped back, or
+    ///   the error of a plain decode if no candidate works
+    pub fn decode_with_repair(&self, stego_image: &StegoImage) -> Result<DecodedMessage> {
+        let image = self.unscatter(stego_image);
+        let (width, height) = stego_image.dimensions();
+        if img::capacity_bits(width, height) < (HEADER_SIZE * 8) as u64 {
+            return Err(HideError::NoMessageFound);
+        }
+
+        // A damaged extension length can't be trusted to say how far to read
+        let pixels = img::pixels_for_bits((EXTENDED_HEADER_SIZE * 8) as u64)
+            .min(img::pixel_count(width, height));
+        let mut bits = BitVec::<u8, Msb0>::with_capacity(EXTENDED_HEADER_SIZE * 8 + 2);
+        self.read_pixels(&*image, &ChannelOrder::fixed(), 0..pixels, &mut bits)?;
+        // Flips past the fields the header writes can't make it plausible
+        let header_bits = VersionedParser::header_length(&whole_bytes(&bits))
+            .unwrap_or(EXTENDED_HEADER_SIZE)
+            .min(bits.len() / 8)
+            .min(EXTENDED_HEADER_SIZE)
+            * 8;
+
+        let singles = (0..header_bits).map(|bit| vec![bit]);
+        let pairs = (0..header_bits)
+            .flat_map(move |first| (first + 1..header_bits).map(move |second| vec![first, second]))
+            .filter(|_| self.config.repair_bit_pairs);
+        let candidates = std::iter::once(Vec::new()).chain(singles).chain(pairs);
+
+        // The first candidate that decodes, in case none can be checked
+        let mut unchecked = None;
+        for flipped in candidates {
+            let mut candidate = bits.clone();
+            for &bit in &flipped {
+                if let Some(mut value) = candidate.get_mut(bit) {
+                    *value = !*value;
+                }
+            }
+            // A flipped extension length moves the end of the header
+            let Ok(length) = VersionedParser::header_length(&whole_bytes(&candidate)) else {
+                continue;
+            };
+            match self.fit_header_bits(&*image, &mut candidate, length) {
+                Ok(()) => {}
+                Err(HideError::Cancelled) => return Err(HideError::Cancelled),
+                Err(_) => continue,
+            }
+            let header_bytes = whole_bytes(&candidate);
+            let Some(header) = plausible_header(&header_bytes, width, height) else {
+                continue;
+            };
+            let checked =
+                header.is_padded() || header.is_checksummed() || header.is_error_corrected();
+            if !checked && unchecked.is_some() {
+                continue;
+            }
+
+            let decoded = self
+                .read_payload_after(&image, header, candidate)
+                .and_then(|(header, payload)| self.message_from_payload(&header, payload));
+            match decoded {
+                Ok(data) if checked => {
+                    return Ok(repaired_message(header, data, flipped));
+                }
+                Ok(data) => {
+                    unchecked = Some(repaired_message(header, data, flipped));
+                }
+                Err(HideError::Cancelled) => return Err(HideError::Cancelled),
+                Err(_) => {}
+            }
+        }
+
+        match unchecked {
+            Some(decoded) => Ok(decoded),
+            None => Err(self
+                .decode_payload(stego_image)
+                .err()
+                .unwrap_or(HideError::NoMessageFound)),
+        }
+    }
+
+    /// Read the header and payload bytes, without diagnosing failures
+    ///
+    /// The header pixels are read and parsed once, and after them only the
+    /// pixels the declared message occupies.
+    fn read_payload(&self, stego_image: &StegoImage) -> Result<(MessageHeader, Vec<u8>)> {
+        let (header, header_bits) = self.read_header_pixels(stego_image)?;
+        self.read_payload_after(stego_image, header, header_bits)
+    }
+
+    /// Read the payload bytes behind a header already read from an image
+    ///
+    /// # Arguments
+    /// * `stego_image` - The image containing the hidden message
+    /// * `header` - The header of the payload
+    /// * `header_bits` - The bits read along with the header
+    fn read_payload_after(
+        &self,
+        stego_image: &StegoImage,
+        header: MessageHeader,
+        header_bits: BitVec<u8, Msb0>,
+    ) -> Result<(MessageHeader, Vec<u8>)> {
+        self.check_flags(&header)?;
+
+        // RGBA, masked and two-bit payloads lay out their pixels differently
+        if header.is_rgba() || header.is_masked() || header.embedding_depth() != EmbeddingDepth::One
+        {
+            let decoded = if header.is_rgba() {
+                self.read_rgba(stego_image, &header, None)?
+            } else if header.embedding_depth() != EmbeddingDepth::One {
+                self.read_deep(stego_image, &header, None)?
+            } else {
+                self.read_masked(stego_image, &header, None)?
+            };
+            if !decoded.complete {
+                return Err(HideError::TruncatedPayload {
+                    declared: decoded.declared_length,
+                    available: decoded.recovered_length,
+                });
+            }
+            return Ok((header, decoded.data));
+        }
+
+        let message_bytes = self.read_message_pixels(stego_image, &header, header_bits)?;
+        Ok((header, message_bytes))
+    }
+
+    /// Decode as much of a message as possible from an image
+    ///
+    /// Unlike [`Decoder::decode`], this does not fail when the image holds fewer
+    /// bits than the header declares (e.g. the image was cropped after encoding).
+    /// A valid header is still required.
+    ///
+    /// # Arguments
+    /// * `stego_image` - The image containing the hidden message
+    ///
+    /// # Returns
+    /// * The recovered message prefix along with the declared and recovered lengths
+    pub fn decode_lossy(&self, stego_image: &StegoImage) -> Result<PartialDecode> {
+        self.read_lossy(&self.unscatter(stego_image))
+            .map(|(_, decoded)| decoded)
+            .map_err(|e| self.diagnose(stego_image, e))
+    }
+
+    /// Recover a message prefix and its header, without diagnosing failures
+    fn read_lossy(&self, stego_image: &StegoImage) -> Result<(MessageHeader, PartialDecode)> {
+        // Read the header (fails if the header itself is missing or invalid)
+        let (header, _) = self.read_header_pixels(stego_image)?;
+        self.check_flags(&header)?;
+        let payload = self.read_lossy_payload(stego_image, &header)?;
+        let decoded = self.partial_message(&header, payload, compression::MAX_INFLATED_BYTES)?;
+        Ok((header, decoded))
+    }
+
+    /// Recover as much of the payload after a header as the image holds
+    fn read_lossy_payload(
+        &self,
+        stego_image: &StegoImage,
+        header: &MessageHeader,
+    ) -> Result<PartialDecode> {
+        if header.is_rgba() {
+            return self.read_rgba(stego_image, header, None);
+        }
+        if header.embedding_depth() != EmbeddingDepth::One {
+            return self.read_deep(stego_image, header, None);
+        }
+        if header.is_masked() {
+            return self.read_masked(stego_image, header, None);
+        }
+
+        // Extract all message bits from the image
+        let all_bits = self.extract_bits(stego_image, &self.channel_order(header)?)?;
+        let message_length = header.message_length;
+        let declared_length = message_length as usize;
+
+        // Only keep the whole bytes that are physically present after the header
+        let available_bytes = all_bits.len().saturating_sub(header.size() * 8) / 8;
+        let recovered_length = declared_length.min(available_bytes);
+        let data = message_bytes(&all_bits, header, recovered_length).unwrap_or_default();
+
+        Ok(PartialDecode {
+            data,
+            declared_length,
+            recovered_length,
+            complete: recovered_length == declared_length,
+        })
+    }
+
+    /// Decode a message and measure how long the decode took
+    ///
+    /// # Arguments
+    /// * `stego_image` - The image containing the hidden message
+    /// * `allow_partial` - Whether to recover a truncated message like [`Decoder::decode_lossy`]
+    ///
+    /// # Returns
+    /// * The decoded message along with its timing
+    pub fn decode_detailed(
+        &self,
+        stego_image: &StegoImage,
+        allow_partial: bool,
+    ) -> Result<DetailedDecode> {
+        let start = Instant::now();
+
+        let (header, decoded) = if allow_partial {
+            self.read_lossy(&self.unscatter(stego_image))
+                .map_err(|e| self.diagnose(stego_image, e))?
+        } else {
+            let (header, data) = self.decode_payload(stego_image)?;
+            check_plain_message(&header)?;
+            let decoded = PartialDecode {
+                declared_length: data.len(),
+                recovered_length: data.len(),
+                complete: true,
+                data,
+            };
+            (header, decoded)
+        };
+
+        // Every pixel is read while decoding
+        let pixels = img::pixel_count(stego_image.width(), stego_image.height());
+
+        Ok(DetailedDecode {
+            warnings: decode_warnings(&header, &decoded),
+            decoded,
+            timing: Timing::since(start, pixels),
+        })
+    }
+
+    /// Decode a message from an untrusted image within hard limits
+    ///
+    /// Only the header and the pixels carrying the declared message are read.
+    /// The declared length is checked against the output cap before any
+    /// message pixel is touched, and the deadline is checked periodically.
+    ///
+    /// # Arguments
+    /// * `stego_image` - The image containing the hidden message
+    /// * `limits` - Output, pixel and time limits for the decode
+    ///
+    /// # Returns
+    /// * The extracted message bytes
+    pub fn decode_untrusted(
+        &self,
+        stego_image: &StegoImage,
+        limits: UntrustedLimits,
+    ) -> Result<Vec<u8>> {
+        Ok(self
+            .decode_bounded(stego_image, &limits, false)
+            .map_err(|e| self.diagnose(stego_image, e))?
+            .1
+            .data)
+    }
+
+    /// Decode an untrusted image within hard limits and measure the decode
+    ///
+    /// # Arguments
+    /// * `stego_image` - The image containing the hidden message
+    /// * `limits` - Output, pixel and time limits for the decode
+    /// * `allow_partial` - Whether to recover a truncated message like [`Decoder::decode_lossy`]
+    ///
+    /// # Returns
+    /// * The decoded message along with its timing
+    pub fn decode_untrusted_detailed(
+        &self,
+        stego_image: &StegoImage,
+        limits: UntrustedLimits,
+        allow_partial: bool,
+    ) -> Result<DetailedDecode> {
+        let start = Instant::now();
+        let (header, decoded) = self
+            .decode_bounded(stego_image, &limits, allow_partial)
+            .map_err(|e| self.diagnose(stego_image, e))?;
+
+        // Only the pixels holding the header and message are read
+        let pixels = img::pixels_for_bits(total_bits_with_header(
+            &header,
+            decoded.recovered_length as u32,
+        ));
+
+        Ok(DetailedDecode {
+            warnings: decode_warnings(&header, &decoded),
+            decoded,
+            timing: Timing::since(start, pixels),
+        })
+    }
+
+    /// Decode the header and as much of the message as the limits allow
+    fn decode_bounded(
+        &self,
+        stego_image: &StegoImage,
+        limits: &UntrustedLimits,
+        allow_partial: bool,
+    ) -> Result<(MessageHeader, PartialDecode)> {
+        let start = Instant::now();
+        let image = self.unscatter(stego_image);
+        let stego_image = &*image;
+
+        // The extension is only read once the pixels it takes are within the limit
+        let mut reader = PixelReader::new(self, stego_image);
+        let mut header_bytes = reader.read_bytes(HEADER_SIZE)?;
+        let header_length = VersionedParser::header_length(&header_bytes)?;
+        let header_pixels = img::pixels_for_bits(header_length as u64 * 8);
+        if header_pixels > limits.max_pixels {
+            return Err(HideError::PixelLimitExceeded {
+                required: header_pixels,
+                limit: limits.max_pixels,
+            });
+        }
+        header_bytes.extend(reader.read_bytes(header_length - HEADER_SIZE)?);
+        self.check_keyed_header(&header_bytes, stego_image.width(), stego_image.height())?;
+        let header = Self::parse_header(&header_bytes)?;
+        self.check_flags(&header)?;
+        let declared_length = header.message_length as usize;
+        if declared_length > limits.max_output_bytes {
+            return Err(HideError::OutputLimitExceeded {
+                declared_length,
+                limit: limits.max_output_bytes,
+            });
+        }
+
+        // Work out how many pixels the message occupies
+        let image_pixels = img::pixel_count(stego_image.width(), stego_image.height());
+        if header.is_rgba() {
+            let required_pixels = header.pixels()
+                + rgba::message_pixels(declared_length, header.embedding_depth()) as u64;
+            if required_pixels.min(image_pixels) > limits.max_pixels {
+                return Err(HideError::PixelLimitExceeded {
+                    required: required_pixels.min(image_pixels),
+                    limit: limits.max_pixels,
+                });
+            }
+
+            let decoded = self.read_rgba(stego_image, &header, Some((start, limits.deadline)))?;
+            if !decoded.complete && !allow_partial {
+                return Err(HideError::TruncatedPayload {
+                    declared: decoded.declared_length,
+                    available: decoded.recovered_length,
+                });
+            }
+            return self
+                .partial_message(&header, decoded, limits.max_output_bytes)
+                .map(|decoded| (header, decoded));
+        }
+        if header.embedding_depth() != EmbeddingDepth::One {
+            let decoded = self.read_deep(stego_image, &header, Some((start, limits)))?;
+            if !decoded.complete && !allow_partial {
+                return Err(HideError::TruncatedPayload {
+                    declared: decoded.declared_length,
+                    available: decoded.recovered_length,
+                });
+            }
+            return self
+                .partial_message(&header, decoded, limits.max_output_bytes)
+                .map(|decoded| (header, decoded));
+        }
+        if header.is_masked() {
+            let decoded = self.read_masked(stego_image, &header, Some((start, limits)))?;
+            if !decoded.complete && !allow_partial {
+                return Err(HideError::TruncatedPayload {
+                    declared: decoded.declared_length,
+                    available: decoded.recovered_length,
+                });
+            }
+            return self
+                .partial_message(&header, decoded, limits.max_output_bytes)
+                .map(|decoded| (header, decoded));
+        }
+        let required_pixels =
+            img::pixels_for_bits(total_bits_with_header(&header, header.message_length));
+        if required_pixels > image_pixels && !allow_partial {
+            return Err(truncated_payload(
+                &header,
+                img::capacity_bits(stego_image.width(), stego_image.height()),
+            ));
+        }
+
+        let pixels_to_read = required_pixels.min(image_pixels);
+        if pixels_to_read > limits.max_pixels {
+            return Err(HideError::PixelLimitExceeded {
+                required: pixels_to_read,
+                limit: limits.max_pixels,
+            });
+        }
+
+        // Read only the pixels holding the header and message
+        let order = self.channel_order(&header)?;
+        let mut all_bits =
+            BitVec::<u8, Msb0>::with_capacity((pixels_to_read * img::BITS_PER_PIXEL) as usize);
+        for index in 0..pixels_to_read {
+            if (index as usize).is_multiple_of(cancel::CHECK_INTERVAL)
+                && start.elapsed() >= limits.deadline
+            {
+                return Err(HideError::DeadlineExceeded);
+            }
+            cancel::check_every(self.config.cancel.as_ref(), index as usize)?;
+
+            let pixel = stego_image.get_pixel_at(PixelIndex(index))?;
+            all_bits
+                .extend_from_bitslice(&self.decode_pixel_in_order(pixel, order.for_pixel(index)));
+        }
+
+        // Keep the whole message bytes that were read
+        let available_bytes = all_bits.len().saturating_sub(header.size() * 8) / 8;
+        let recovered_length = declared_length.min(available_bytes);
+        let data = message_bytes(&all_bits, &header, recovered_length).unwrap_or_default();
+
+        self.partial_message(
+            &header,
+            PartialDecode {
+                data,
+                declared_length,
+                recovered_length,
+                complete: recovered_length == declared_length,
+            },
+            limits.max_output_bytes,
+        )
+        .map(|decoded| (header, decoded))
+    }
+
+    /// Read the message of an RGBA-mode payload, recovering as much as the image holds
+    ///
+    /// # Arguments
+    /// * `stego_image` - The image containing the hidden message
+    /// * `header` - The header already read from the image
+    /// * `deadline` - When the decode started and how long it may take, if limited
+    ///
+    /// # Returns
+    /// * The recovered message prefix along with the declared and recovered lengths
+    fn read_rgba(
+        &self,
+        stego_image: &StegoImage,
+        header: &MessageHeader,
+        deadline: Option<(Instant, Duration)>,
+    ) -> Result<PartialDecode> {
+        let declared_length = header.message_length as usize;
+        let pixels = rgba::rgba_pixels(stego_image);
+
+        let mut bits = BitVec::<u8, Msb0>::new();
+        let payload = rgba::payload_pixels(&pixels, header.pixels(), header.skips_transparent());
+        for (index, pixel) in payload.enumerate() {
+            if bits.len() >= declared_length * 8 {
+                break;
+            }
+            if let Some((start, limit)) = deadline {
+                if index.is_multiple_of(cancel::CHECK_INTERVAL) && start.elapsed() >= limit {
+                    return Err(HideError::DeadlineExceeded);
+                }
+            }
+            cancel::check_every(self.config.cancel.as_ref(), index)?;
+
+            for plane in 0..header.embedding_depth().bits_per_channel() {
+                let [r, g, b, a] = depth::plane(pixel.0, plane);
+                bits.extend_from_bitslice(&self.decode_pixel_rgba(r, g, b, a));
+            }
+        }
+
+        // Keep only the whole bytes of the message that were read
+        bits.truncate((bits.len() - bits.len() % 8).min(declared_length * 8));
+        let data = utils::bits_to_bytes(&bits);
+        let recovered_length = data.len();
+
+        Ok(PartialDecode {
+            data,
+            declared_length,
+            recovered_length,
+            complete: recovered_length == declared_length,
+        })
+    }
+
+    /// Read the message of a payload deeper than one bit, recovering as much as the image holds
+    ///
+    /// The payload starts at the pixel after the header and any embedded
+    /// mask, skipping pixels a mask excludes.
+    ///
+    /// # Arguments
+    /// * `stego_image` - The image containing the hidden message
+    /// * `header` - The header already read from the image
+    /// * `limits` - When the decode started and the limits it runs under, if limited
+    ///
+    /// # Returns
+    /// * The recovered message prefix along with the declared and recovered lengths
+    fn read_deep(
+        &self,
+        stego_image: &StegoImage,
+        header: &MessageHeader,
+        limits: Option<(Instant, &UntrustedLimits)>,
+    ) -> Result<PartialDecode> {
+        let declared_length = header.message_length as usize;
+        let depth = header.embedding_depth();
+        let prefix_size = header.size() + header.embedded_mask_length as usize;
+        let (mask, order) = if header.is_masked() {
+            let (mask, order) = self.payload_mask(stego_image, header)?;
+            (Some(mask), order)
+        } else {
+            (None, self.channel_order(header)?)
+        };
+        let pixels: Box<dyn Iterator<Item = u64>> = match &mask {
+            Some(mask) => Box::new(mask.included()),
+            None => Box::new(0..img::pixel_count(stego_image.width(), stego_image.height())),
+        };
+
+        let required = depth::pixels_with_prefix(prefix_size, declared_length, depth);
+        if let Some((_, limits)) = limits {
+            let available = mask.as_ref().map_or_else(
+                || img::pixel_count(stego_image.width(), stego_image.height()),
+                |mask| mask.included_pixels(),
+            );
+            if required.min(available) > limits.max_pixels {
+                return Err(HideError::PixelLimitExceeded {
+                    required: required.min(available),
+                    limit: limits.max_pixels,
+                });
+            }
+        }
+
+        let first = depth::prefix_pixels(prefix_size);
+        let mut bits = BitVec::<u8, Msb0>::new();
+        for (chunk, index) in (first..required).zip(pixels.skip(first as usize)) {
+            if let Some((start, limits)) = limits {
+                if (chunk as usize).is_multiple_of(cancel::CHECK_INTERVAL)
+                    && start.elapsed() >= limits.deadline
+                {
+                    return Err(HideError::DeadlineExceeded);
+                }
+            }
+            cancel::check_every(self.config.cancel.as_ref(), chunk as usize)?;
+
+            let pixel = stego_image.get_pixel_at(PixelIndex(index))?;
+            for plane in 0..depth.bits_per_channel() {
+                bits.extend_from_bitslice(&self.decode_pixel_in_order(
+                    Rgb(depth::plane(pixel.0, plane)),
+                    order.for_pixel(chunk),
+                ));
+            }
+        }
+
+        // Keep only the whole bytes of the message that were read
+        bits.truncate((bits.len() - bits.len() % 8).min(declared_length * 8));
+        let data = utils::bits_to_bytes(&bits);
+        let recovered_length = data.len();
+
+        Ok(PartialDecode {
+            data,
+            declared_length,
+            recovered_length,
+            complete: recovered_length == declared_length,
+        })
+    }
+
+    /// Read the message of a masked payload, recovering as much as the image holds
+    ///
+    /// # Arguments
+    /// * `stego_image` - The image containing the hidden message
+    /// * `header` - The header already read from the image
+    /// * `limits` - When the decode started and the limits it runs under, if limited
+    ///
+    /// # Returns
+    /// * The recovered message prefix along with the declared and recovered lengths
+    fn read_masked(
+        &self,
+        stego_image: &StegoImage,
+        header: &MessageHeader,
+        limits: Option<(Instant, &UntrustedLimits)>,
+    ) -> Result<PartialDecode> {
+        let declared_length = header.message_length as usize;
+        let mask_length = header.embedded_mask_length as usize;
+        let (mask, order) = self.payload_mask(stego_image, header)?;
+
+        let start_bit = (header.size() + mask_length) * 8;
+        let end_bit = start_bit + declared_length * 8;
+        if let Some((_, limits)) = limits {
+            let required = img::pixels_for_bits(end_bit as u64).min(mask.included_pixels());
+            if required > limits.max_pixels {
+                return Err(HideError::PixelLimitExceeded {
+                    required,
+                    limit: limits.max_pixels,
+                });
+            }
+        }
+
+        let mut bits = BitVec::<u8, Msb0>::new();
+        for (chunk, index) in mask.included().enumerate() {
+            if bits.len() >= end_bit {
+                break;
+            }
+            if let Some((start, limits)) = limits {
+                if chunk.is_multiple_of(cancel::CHECK_INTERVAL)
+                    && start.elapsed() >= limits.deadline
+                {
+                    return Err(HideError::DeadlineExceeded);
+                }
+            }
+            cancel::check_every(self.config.cancel.as_ref(), chunk)?;
+
+            let pixel = stego_image.get_pixel_at(PixelIndex(index))?;
+            bits.extend_from_bitslice(
+                &self.decode_pixel_in_order(pixel, order.for_pixel(chunk as u64)),
+            );
+        }
+
+        // Keep only the whole message bytes that were read
+        let available_bytes = bits.len().saturating_sub(start_bit) / 8;
+        let recovered_length = declared_length.min(available_bytes);
+        let data = bits
+            .get(start_bit..start_bit + recovered_length * 8)
+            .map(utils::bits_to_bytes)
+            .unwrap_or_default();
+
+        Ok(PartialDecode {
+            data,
+            declared_length,
+            recovered_length,
+            complete: recovered_length == declared_length,
+        })
+    }
+
+    /// The mask and channel orders a masked payload was embedded with
+    ///
+    /// An embedded mask follows the header, in pixels the mask leaves free;
+    /// otherwise the mask comes from the decoder configuration.
+    fn payload_mask<'a>(
+        &'a self,
+        stego_image: &StegoImage,
+        header: &MessageHeader,
+    ) -> Result<(Cow<'a, PixelMask>, ChannelOrder)> {
+        let mask_length = header.embedded_mask_length as usize;
+        let (width, height) = stego_image.dimensions();
+        let mut order = self.channel_order(header)?;
+
+        let mask = if mask_length > 0 {
+            let mut reader = PixelReader::new(self, stego_image);
+            reader.order = order;
+            let prefix = reader.read_bytes(header.size() + mask_length)?;
+            order = reader.order;
+            Cow::Owned(PixelMask::from_rle(
+                width,
+                height,
+                prefix.get(header.size()..).unwrap_or(&[]),
+            )?)
+        } else {
+            Cow::Borrowed(self.config.mask.as_ref().ok_or_else(|| {
+                HideError::InvalidParameters(
+                    "The payload skips masked pixels; decode it with the mask it was encoded with"
+                        .to_string(),
+                )
+            })?)
+        };
+        mask.check_dimensions(width, height)?;
+        Ok((mask, order))
+    }
+
+    /// Decode a message and trace the pixels that carried it
+    ///
+    /// # Arguments
+    /// * `stego_image` - The image containing the hidden message
+    ///
+    /// # Returns
+    /// * The extracted message bytes and where each bit of the payload was found
+    pub fn decode_with_trace(&self, stego_image: &StegoImage) -> Result<(Vec<u8>, PayloadTrace)> {
+        self.reject_pixel_key("Tracing a payload")?;
+        let (header, message_bytes) = self.decode_payload(stego_image)?;
+        let trace = self.trace_payload(stego_image, &header)?;
+        Ok((message_bytes, trace))
+    }
+
+    /// Trace the pixels holding a payload, in the order they were read
+    fn trace_payload(
+        &self,
+        stego_image: &StegoImage,
+        header: &MessageHeader,
+    ) -> Result<PayloadTrace> {
+        let (width, height) = stego_image.dimensions();
+        let header_bytes = (header.size() + header.embedded_mask_length as usize) as u64;
+        let total_bytes = header_bytes + u64::from(header.message_length);
+        let mut trace = TraceBuilder::new(width, height, header_bytes, total_bytes);
+
+        let depth = header.embedding_depth();
+        if header.is_rgba() {
+            // The header keeps the RGB layout; the payload starts at the next pixel
+            let fixed = ChannelOrder::fixed().for_pixel(0);
+            for pixel in 0..header.pixels() {
+                trace.push(
+                    pixel,
+                    pixel * img::BITS_PER_PIXEL,
+                    img::BITS_PER_PIXEL,
+                    fixed,
+                );
+            }
+            let message_bits = u64::from(header.message_length) * 8;
+            let bits_per_pixel = rgba::bits_per_pixel(depth);
+            let pixels = rgba::rgba_pixels(stego_image);
+            let payload = pixels
+                .enumerate_pixels()
+                .skip(header.pixels() as usize)
+                .filter(|(_, _, pixel)| !header.skips_transparent() || pixel.0[3] != 0);
+            for (chunk, (x, y, _)) in payload.enumerate() {
+                let first_bit = chunk as u64 * bits_per_pixel;
+                if first_bit >= message_bits {
+                    break;
+                }
+                let pixel = u64::from(y) * u64::from(width) + u64::from(x);
+                trace.push(
+                    pixel,
+                    (header.size() * 8) as u64 + first_bit,
+                    bits_per_pixel,
+                    fixed,
+                );
+            }
+            return Ok(trace.finish());
+        }
+
+        // Masked payloads skip excluded pixels; chunks are numbered in
+        // embedding order either way
+        let (mask, order) = if header.is_masked() {
+            let (mask, order) = self.payload_mask(stego_image, header)?;
+            (Some(mask), order)
+        } else {
+            (None, self.channel_order(header)?)
+        };
+        let pixels: Box<dyn Iterator<Item = u64>> = match &mask {
+            Some(mask) => Box::new(mask.included()),
+            None => Box::new(0..img::pixel_count(width, height)),
+        };
+
+        // Deeper payloads start at the pixel after the one-bit header and embedded mask
+        let prefix_bits = match depth {
+            EmbeddingDepth::One => u64::MAX,
+            _ => header_bytes * 8,
+        };
+        let prefix_pixels = prefix_bits.div_ceil(img::BITS_PER_PIXEL);
+        for (chunk, pixel) in (0..).zip(pixels) {
+            if trace.is_complete() {
+                break;
+            }
+            let first_bit = chunk * img::BITS_PER_PIXEL;
+            if first_bit < prefix_bits {
+                trace.push(
+                    pixel,
+                    first_bit,
+                    img::BITS_PER_PIXEL.min(prefix_bits - first_bit),
+                    order.for_pixel(chunk),
+                );
+            } else {
+                trace.push(
+                    pixel,
+                    prefix_bits + (chunk - prefix_pixels) * depth.bits_per_pixel(),
+                    depth.bits_per_pixel(),
+                    order.for_pixel(chunk),
+                );
+            }
+        }
+        Ok(trace.finish())
+    }
+
+    /// Extract the embedded bits from every pixel of an image
+    fn extract_bits(
+        &self,
+        stego_image: &StegoImage,
+        order: &ChannelOrder,
+    ) -> Result<BitVec<u8, Msb0>> {
+        let total_bits = img::capacity_bits(stego_image.width(), stego_image.height());
+        let mut all_bits =
+            BitVec::<u8, Msb0>::with_capacity(usize::try_from(total_bits).unwrap_or(0));
+
+        let pixels = img::pixel_count(stego_image.width(), stego_image.height());
+        self.read_pixels(stego_image, order, 0..pixels, &mut all_bits)?;
+
+        Ok(all_bits)
+    }
+
+    /// Recover a watermark tiled across an image by [`Encoder::encode_watermark`]
+    ///
+    /// Every pixel is searched for the start of a copy, so cropped images work
+    /// as long as some copy survived intact.
+    ///
+    /// [`Encoder::encode_watermark`]: crate::encoder::Encoder::encode_watermark
+    ///
+    /// # Arguments
+    /// * `stego_image` - The watermarked image, possibly cropped
+    ///
+    /// # Returns
+    /// * The identifier and the number of intact copies found
+    pub fn decode_watermark(&self, stego_image: &StegoImage) -> Result<WatermarkResult> {
+        let bits = self.extract_bits(stego_image, &ChannelOrder::fixed())?;
+        watermark::find_frames(&bits).ok_or(HideError::NoMessageFound)
+    }
+
+    /// Decode a message from an image file
+    ///
+    /// # Arguments
+    /// * `stego_image_path` - Path to the image containing the hidden message
+    ///
+    /// # Returns
+    /// * The extracted message bytes
+    pub fn decode_file<P: AsRef<Path>>(&self, stego_image_path: P) -> Result<Vec<u8>> {
+        // Load the stego image
+        let stego_image = StegoImage::from_file(stego_image_path)?;
+
+        // Decode the message
+        self.decode(&stego_image)
+    }
+
+    /// Point out resizing or color conversion as the likely cause of a failed decode
+    ///
+    /// Errors saying the payload is missing or malformed are wrapped in
+    /// [`HideError::DimensionsChanged`] when the image's size differs from the
+    /// expected dimensions (or the ones its payload recorded), otherwise in
+    /// [`HideError::ColorConverted`] when the image's file carried color
+    /// management chunks; other errors are returned unchanged.
+    fn diagnose(&self, stego_image: &StegoImage, err: HideError) -> HideError {
+        let payload_damaged = matches!(
+            err,
+            HideError::NoMessageFound
+                | HideError::TruncatedPayload { .. }
+                | HideError::InvalidParameters(_)
+                | HideError::UnsupportedFeature { .. }
+        );
+        if !payload_damaged {
+            return err;
+        }
+
+        let actual = stego_image.dimensions();
+        let encoded = self
+            .config
+            .expected_dimensions
+            .or_else(|| self.recorded_dimensions(stego_image))
+            .filter(|&encoded| encoded != actual);
+        if let Some(encoded) = encoded {
+            HideError::DimensionsChanged {
+                encoded,
+                actual,
+                cause: Box::new(err),
+            }
+        } else if stego_image.has_color_profile() {
+            HideError::ColorConverted(Box::new(err))
+        } else {
+            err
+        }
+    }
+
+    /// Read the cover dimensions recorded at the start of a payload
+    ///
+    /// Only the header and the start of the payload need to survive, so
+    /// this works on images cropped or padded without disturbing them.
+    ///
+    /// # Arguments
+    /// * `stego_image` - The image containing the hidden message
+    ///
+    /// # Returns
+    /// * The width and height of the cover, or `None` if none were recorded or they can't be read
+    pub fn recorded_dimensions(&self, stego_image: &StegoImage) -> Option<(u32, u32)> {
+        let image = self.unscatter(stego_image);
+        let (header, _) = self.read_header_pixels(&*image).ok()?;
+        if !header.records_dimensions() {
+            return None;
+        }
+
+        let payload = self.read_lossy_payload(&image, &header).ok()?;
+        let payload = if header.is_error_corrected() {
+            without_parity(&header, payload.data).ok()?
+        } else {
+            payload.data
+        };
+        split_dimensions(&payload).map(|(dimensions, _)| dimensions)
+    }
+
+    /// Get a reference to the BLTM used by this decoder
+    pub fn bltm(&self) -> &BLTM3x3 {
+        &self.bltm
+    }
+
+    /// Find the message in a decoded payload, repairing it with any error
+    /// correction parity, verifying and dropping any checksum, dropping
+    /// recorded dimensions and padding and inflating a compressed message
+    ///
+    /// # Returns
+    /// * The message, or an error if the payload is too short, has more
+    ///   corrupted bytes than its parity repairs, fails its checksum, its
+    ///   padding trailer is corrupt or its compressed message doesn't inflate
+    fn message_from_payload(&self, header: &MessageHeader, payload: Vec<u8>) -> Result<Vec<u8>> {
+        let payload = without_parity(header, payload)?;
+        let payload = self.without_checksum(header, payload)?;
+        let payload = without_dimensions(header, payload)?;
+        let payload = if header.is_padded() {
+            padding::unpad(&payload)?.to_vec()
+        } else {
+            payload
+        };
+
+        if header.is_compressed() {
+            return compression::decompress(&payload, compression::MAX_INFLATED_BYTES);
+        }
+        Ok(payload)
+    }
+
+    /// Check and drop the CRC-32 ending a checksummed payload
+    ///
+    /// # Returns
+    /// * The payload before the checksum, or [`HideError::ChecksumMismatch`]
+    ///   unless `skip_checksum` is set
+    fn without_checksum(&self, header: &MessageHeader, mut payload: Vec<u8>) -> Result<Vec<u8>> {
+        if !header.is_checksummed() {
+            return Ok(payload);
+        }
+        let Some(body_length) = payload.len().checked_sub(CHECKSUM_SIZE) else {
+            return Err(HideError::InvalidParameters(
+                "Payload is too short to hold its checksum".to_string(),
+            ));
+        };
+
+        let expected = get_u32_be(&payload, body_length).unwrap_or_default();
+        payload.truncate(body_length);
+        let actual = utils::crc32(&payload);
+        if actual != expected && !self.config.skip_checksum {
+            return Err(HideError::ChecksumMismatch { expected, actual });
+        }
+        Ok(payload)
+    }
+
+    /// Find the message in a partly recovered payload
+    ///
+    /// Padding can only be told apart from the message once the trailer at the
+    /// end of the payload has been recovered, so incomplete padded payloads keep it.
+    /// Likewise only complete payloads can be checked against their checksum;
+    /// incomplete ones are returned unchecked.
+    /// A compressed message is only inflated whole, to at most `max_output_bytes`,
+    /// and blocks of error-corrected payloads are only told apart from their
+    /// parity in a whole one; part of either is reported as
+    /// [`HideError::TruncatedPayload`].
+    fn partial_message(
+        &self,
+        header: &MessageHeader,
+        mut decoded: PartialDecode,
+        max_output_bytes: usize,
+    ) -> Result<PartialDecode> {
+        if header.is_encrypted() {
+            return Err(HideError::EncryptedPayload);
+        }
+        if (header.is_compressed() || header.is_error_corrected()) && !decoded.complete {
+            return Err(HideError::TruncatedPayload {
+                declared: decoded.declared_length,
+                available: decoded.recovered_length,
+            });
+        }
+        if header.is_error_corrected() {
+            decoded.data = without_parity(header, decoded.data)?;
+            decoded.declared_length = decoded.data.len();
+            decoded.recovered_length = decoded.data.len();
+        }
+
+        if header.is_checksummed() {
+            decoded.declared_length = decoded.declared_length.saturating_sub(CHECKSUM_SIZE);
+            decoded.recovered_length = decoded.recovered_length.min(decoded.declared_length);
+            if decoded.complete {
+                decoded.data = self.without_checksum(header, decoded.data)?;
+            } else {
+                decoded.data.truncate(decoded.recovered_length);
+            }
+        }
+        let mut decoded = partial_without_dimensions(header, decoded);
+        if header.is_padded() && decoded.complete {
+            decoded.data = padding::unpad(&decoded.data)?.to_vec();
+            decoded.declared_length = decoded.data.len();
+            decoded.recovered_length = decoded.data.len();
+        }
+        if header.is_compressed() {
+            decoded.data = compression::decompress(&decoded.data, max_output_bytes)?;
+            decoded.declared_length = decoded.data.len();
+            decoded.recovered_length = decoded.data.len();
+        }
+
+        Ok(decoded)
+    }
+}
+
+/// Number of pixels carrying the first [`HEADER_SIZE`] bytes of every header,
+/// read in the fixed channel order
+const HEADER_PIXELS: u64 = img::pixels_for_bits(HEADER_SIZE as u64 * 8);
+
+/// Parse a header and check that it looks like a payload in an image
+///
+/// Besides parsing, the declared length must fit in the image and the
+/// unused header bits must be clear.
+///
+/// # Arguments
+/// * `header_bytes` - The header bytes of the payload, extension included
+/// * `width`, `height` - Dimensions of the image holding it
+fn plausible_header(header_bytes: &[u8], width: u32, height: u32) -> Option<MessageHeader> {
+    let header = VersionedParser::parse(header_bytes).ok()?;
+
+    let capacity = if header.is_rgba() {
+        rgba::payload_capacity(width, height, header.pixels(), header.embedding_depth())
+    } else {
+        depth::capacity_after_prefix(
+            img::pixel_count(width, height),
+            header.size(),
+            header.embedding_depth(),
+        )
+    };
+    let fits = header.message_length as usize <= capacity;
+    // Writing the header back out clears every reserved bit it doesn't use;
+    // extension fields this version doesn't know are left alone
+    let known = header.size().min(EXTENDED_HEADER_SIZE);
+    let unused_clear = header.unknown_flag_bits() == 0
+        && header_bytes.get(..known) == header.to_bytes().get(..known);
+
+    (fits && unused_clear).then_some(header)
+}
+
+/// Warnings about how the message behind a header is returned
+fn header_warnings(header: &MessageHeader) -> Vec<StegoWarning> {
+    let mut warnings = Vec::new();
+    // Headers with unknown flags only decode when they are ignored
+    let bits = header.unknown_flag_bits();
+    if bits != 0 {
+        warnings.push(StegoWarning::UnknownFlagsIgnored { bits });
+    }
+    if header.is_container() {
+        warnings.push(StegoWarning::RawContainerBytes);
+    } else if header.is_stream_frame() {
+        warnings.push(StegoWarning::RawStreamFrame);
+    }
+    warnings
+}
+
+/// Warnings about a message decoded, perhaps only in part, from behind a header
+fn decode_warnings(header: &MessageHeader, decoded: &PartialDecode) -> Vec<StegoWarning> {
+    let mut warnings = header_warnings(header);
+    if !decoded.complete {
+        warnings.push(StegoWarning::TruncatedPayload {
+            declared: decoded.declared_length,
+            recovered: decoded.recovered_length,
+        });
+    }
+    warnings
+}
+
+/// A message decoded from the start of an image by [`Decoder::decode_with_repair`]
+fn repaired_message(header: MessageHeader, data: Vec<u8>, flipped: Vec<usize>) -> DecodedMessage {
+    DecodedMessage {
+        data,
+        header,
+        pixel_offset: 0,
+        position: (0, 0),
+        warnings: header_warnings(&header),
+        repaired_bits: flipped,
+    }
+}
+
+/// Number of bits occupied by a message of the given length plus its header
+fn total_bits_with_header(header: &MessageHeader, message_length: u32) -> u64 {
+    (header.size() as u64 + message_length as u64) * 8
+}
+
+/// Check that an image has the pixels a standard payload's message needs
+///
+/// # Returns
+/// * [`HideError::TruncatedPayload`] if the message runs past the last pixel
+fn check_message_fits<S: PixelSurface + ?Sized>(image: &S, header: &MessageHeader) -> Result<()> {
+    // The exact number of pixels the header and message occupy
+    let pixels_needed = img::pixels_for_bits(total_bits_with_header(header, header.message_length));
+    if pixels_needed > img::pixel_count(image.width(), image.height()) {
+        return Err(truncated_payload(
+            header,
+            img::capacity_bits(image.width(), image.height()),
+        ));
+    }
+    Ok(())
+}
+
+/// Error for a header declaring more message bytes than the image holds
+///
+/// # Arguments
+/// * `header` - The header declaring the message length
+/// * `available_bits` - Bits the image holds, including the header
+fn truncated_payload(header: &MessageHeader, available_bits: u64) -> HideError {
+    let available = available_bits.saturating_sub((header.size() * 8) as u64) / 8;
+    HideError::TruncatedPayload {
+        declared: header.message_length as usize,
+        available: usize::try_from(available).unwrap_or(usize::MAX),
+    }
+}
+
+/// Repair a decoded payload with its Reed-Solomon parity and drop the parity
+///
+/// # Returns
+/// * The payload as encoded before the parity was added, or
+///   [`HideError::UncorrectableErrors`] if a block is beyond repair
+fn without_parity(header: &MessageHeader, payload: Vec<u8>) -> Result<Vec<u8>> {
+    if !header.is_error_corrected() {
+        return Ok(payload);
+    }
+    ecc::decode(&payload)
+}
+
+/// Split the cover dimensions recorded by a payload from the message after them
+///
+/// # Returns
+/// * The width and height and the rest of the payload, or `None` if the payload is too short
+fn split_dimensions(payload: &[u8]) -> Option<((u32, u32), &[u8])> {
+    let width = get_u16_be(payload, 0)?;
+    let height = get_u16_be(payload, 2)?;
+    let message = payload.get(DIMENSIONS_SIZE..)?;
+    Some(((width as u32, height as u32), message))
+}
+
+/// Drop the recorded cover dimensions from the front of a decoded payload
+///
+/// # Returns
+/// * The message, or an error if the payload is too short to hold the dimensions
+fn without_dimensions(header: &MessageHeader, mut payload: Vec<u8>) -> Result<Vec<u8>> {
+    if !header.records_dimensions() {
+        return Ok(payload);
+    }
+    if payload.len() < DIMENSIONS_SIZE {
+        return Err(HideError::InvalidParameters(
+            "Payload is too short to hold the recorded cover dimensions".to_string(),
+        ));
+    }
+
+    payload.drain(..DIMENSIONS_SIZE);
+    Ok(payload)
+}
+
+/// Refuse encrypted payloads and warn about ones returned as raw bytes
+///
+/// # Returns
+/// * An error if the payload has to be decrypted first
+fn check_plain_message(header: &MessageHeader) -> Result<()> {
+    if header.is_encrypted() {
+        return Err(HideError::EncryptedPayload);
+    } else if header.is_container() {
+        warn!("Image holds a container payload; returning the raw entry bytes");
+    } else if header.is_stream_frame() {
+        warn!("Image holds a stream frame; returning the raw frame bytes");
+    }
+    Ok(())
+}
+
+/// Drop the recorded cover dimensions from a partly recovered payload
+///
+/// The declared and recovered lengths are adjusted to count message bytes only.
+fn partial_without_dimensions(header: &MessageHeader, mut decoded: PartialDecode) -> PartialDecode {
+    if !header.records_dimensions() {
+        return decoded;
+    }
+
+    let prefix = decoded.data.len().min(DIMENSIONS_SIZE);
+    decoded.data.drain(..prefix);
+    decoded.declared_length = decoded.declared_length.saturating_sub(DIMENSIONS_SIZE);
+    decoded.recovered_length = decoded.recovered_length.saturating_sub(DIMENSIONS_SIZE);
+    decoded
+}
+
+/// Check a stego image against the manifest written when it was encoded
+///
+/// Recomputes the stego file hash and decodes the message to compare its
+/// length and hash with the recorded values.
+///
+/// # Arguments
+/// * `stego_path` - Path to the stego image
+/// * `manifest_path` - Path to the manifest
+///
+/// # Returns
+/// * The list of mismatches (empty if the image matches), or an error if either file can't be read
+pub fn verify_manifest<P: AsRef<Path>, Q: AsRef<Path>>(
+    stego_path: P,
+    manifest_path: Q,
+) -> Result<ManifestVerification> {
+    let manifest = Manifest::load(manifest_path)?;
+    let stego_bytes = std::fs::read(&stego_path)?;
+    let mut verification = ManifestVerification::default();
+
+    let stego_sha256 = manifest::sha256_hex(&stego_bytes);
+    if stego_sha256 != manifest.stego_sha256 {
+        verification.mismatches.push(format!(
+            "stego_sha256: expected {}, found {}",
+            manifest.stego_sha256, stego_sha256
+        ));
+    }
+
+    match Decoder::new().decode_file(&stego_path) {
+        Ok(message) => {
+            if message.len() != manifest.message_length {
+                verification.mismatches.push(format!(
+                    "message_length: expected {}, found {}",
+                    manifest.message_length,
+                    message.len()
+                ));
+            }
+
+            let message_sha256 = manifest::sha256_hex(&message);
+            if message_sha256 != manifest.message_sha256 {
+                verification.mismatches.push(format!(
+                    "message_sha256: expected {}, found {}",
+                    manifest.message_sha256, message_sha256
+                ));
+            }
+        }
+        Err(e) => verification
+            .mismatches
+            .push(format!("message: could not be decoded ({})", e)),
+    }
+
+    Ok(verification)
+}
+
+/// Error returned when a container operation finds an ordinary payload
+fn not_a_container() -> HideError {
+    HideError::InvalidParameters("Image does not hold a container payload".to_string())
+}
+
+/// Message bytes decoded as they are read, from [`Decoder::decode_iter`]
+///
+/// An error ends the iteration: [`HideError::ChecksumMismatch`] follows the
+/// last byte of a checksummed message that doesn't match, and
+/// [`HideError::Cancelled`] comes once the decoder's token is cancelled.
+pub struct PayloadIter<'a> {
+    /// Decoder used to extract each pixel's bits
+    decoder: &'a Decoder,
 
-/// Message format version expected by the decoder
-const EXPECTED_FORMAT_VERSION: u8 = 1;
+    /// The image being read, unscrambled if the decoder has a pixel key
+    image: Cow<'a, StegoImage>,
 
-/// Header size in bytes
-const HEADER_SIZE: usize = 8;
+    /// Position in the payload
+    cursor: PayloadCursor,
 
-/// Decodes a message from a steganography image using BLTM method
-pub struct Decoder {
-    /// The BLTM used for decoding
-    bltm: BLTM3x3,
+    /// Message bytes still to yield
+    remaining: u64,
+
+    /// CRC-32 of the bytes yielded so far, for checksummed payloads
+    crc: Option<u32>,
+
+    /// Set once the last item has been yielded
+    finished: bool,
 }
 
-impl Default for Decoder {
-    fn default() -> Self {
-        Self::new()
+impl PayloadIter<'_> {
+    /// Read the checksum after the message and compare it with the bytes yielded
+    fn check_checksum(&mut self) -> Result<()> {
+        let Some(actual) = self.crc else {
+            return Ok(());
+        };
+        let mut expected = 0u32;
+        for _ in 0..CHECKSUM_SIZE {
+            let byte = self.cursor.next_byte(self.decoder, &*self.image)?;
+            expected = (expected << 8) | u32::from(byte.unwrap_or_default());
+        }
+        if actual != expected && !self.decoder.config.skip_checksum {
+            return Err(HideError::ChecksumMismatch { expected, actual });
+        }
+        Ok(())
     }
 }
 
-impl Decoder {
-    /// Create a new decoder with a 3x3 BLTM
-    pub fn new() -> Self {
-        Self {
-            bltm: BLTM3x3::new(),
+impl Iterator for PayloadIter<'_> {
+    type Item = Result<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        if self.remaining == 0 {
+            self.finished = true;
+            return self.check_checksum().err().map(Err);
+        }
+
+        match self.cursor.next_byte(self.decoder, &*self.image) {
+            Ok(Some(byte)) => {
+                self.remaining -= 1;
+                if let Some(crc) = &mut self.crc {
+                    *crc = utils::crc32_update(*crc, &[byte]);
+                }
+                Some(Ok(byte))
+            }
+            Ok(None) => {
+                self.finished = true;
+                None
+            }
+            Err(e) => {
+                self.finished = true;
+                Some(Err(e))
+            }
         }
     }
 
-    /// Decode a single pixel to extract message bits
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.finished {
+            return (0, Some(0));
+        }
+        let remaining = usize::try_from(self.remaining).unwrap_or(usize::MAX);
+        // A checksum that doesn't match adds an error after the last byte
+        (
+            remaining,
+            remaining.checked_add(usize::from(self.crc.is_some())),
+        )
+    }
+}
+
+impl FusedIterator for PayloadIter<'_> {}
+
+/// Reads the bytes of a standard payload one at a time, after its header
+///
+/// Only the bits of the pixel being read are kept, so reading allocates nothing.
+struct PayloadCursor {
+    /// Channel orders of the payload's pixels
+    order: ChannelOrder,
+
+    /// Row-major index of the next pixel to read
+    next_pixel: u64,
+
+    /// Bits read but not yet returned, in the low `bit_count` bits
+    bits: u16,
+
+    /// Number of bits held in `bits`
+    bit_count: u32,
+
+    /// Payload bytes still to read
+    remaining: u64,
+}
+
+impl PayloadCursor {
+    /// Start reading after the header
     ///
     /// # Arguments
-    /// * `r` - Red component value (0-255)
-    /// * `g` - Green component value (0-255)
-    /// * `b` - Blue component value (0-255)
+    /// * `order` - Channel orders of the payload's pixels
+    /// * `header` - The header, whose length is the payload's
+    /// * `header_bits` - The bits read along with the header, which run up to two bits past it
+    fn after_header(
+        order: ChannelOrder,
+        header: &MessageHeader,
+        header_bits: &BitSlice<u8, Msb0>,
+    ) -> Self {
+        let extra = header_bits.get(header.size() * 8..).unwrap_or_default();
+        Self {
+            order,
+            next_pixel: header.pixels(),
+            bits: u16::from(BLTM3x3::bits_to_u8(extra)),
+            bit_count: extra.len() as u32,
+            remaining: u64::from(header.message_length),
+        }
+    }
+
+    /// Read the next payload byte
     ///
     /// # Returns
-    /// * 3 bits of the hidden message
-    pub fn decode_pixel(&self, r: u8, g: u8, b: u8) -> BitVec<u8, Msb0> {
-        // Step 5-6: Extract LSBs to form stego vector vs
-        let mut stego_vector = BitVec::<u8, Msb0>::new();
-        stego_vector.push(r & 1 != 0); // LSB of R
-        stego_vector.push(g & 1 != 0); // LSB of G
-        stego_vector.push(b & 1 != 0); // LSB of B
+    /// * The byte, or `None` once the whole payload has been read
+    fn next_byte<S: PixelSurface + ?Sized>(
+        &mut self,
+        decoder: &Decoder,
+        image: &S,
+    ) -> Result<Option<u8>> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        while self.bit_count < 8 {
+            cancel::check_every(decoder.config.cancel.as_ref(), self.next_pixel as usize)?;
+            let pixel = image.get_pixel_at(PixelIndex(self.next_pixel))?;
+            let pixel_bits = decoder.pixel_bits(pixel, self.order.for_pixel(self.next_pixel));
+            self.bits = (self.bits << img::BITS_PER_PIXEL) | u16::from(pixel_bits);
+            self.bit_count += img::BITS_PER_PIXEL as u32;
+            self.next_pixel += 1;
+        }
+        self.bit_count -= 8;
+        self.remaining -= 1;
+        Ok(Some((self.bits >> self.bit_count) as u8))
+    }
+}
 
-        // Step 7: Find v^T = vs^T (transpose not needed for a single vector)
+/// Reads embedded bytes on demand, visiting only the pixels that hold them
+struct PixelReader<'a, S: PixelSurface + ?Sized> {
+    /// Decoder used to extract each pixel's bits
+    decoder: &'a Decoder,
 
-        // Step 8-9: m = (A × v^T)^T
-        self.matrix_multiply(&stego_vector)
+    /// The image being read
+    image: &'a S,
+
+    /// Position of the next bit to read
+    bit_pos: BitOffset,
+
+    /// Most recently decoded pixel and its bits, first bit most significant
+    cached: Option<(PixelIndex, u8)>,
+
+    /// Channel order of each pixel; fixed until the header has been read
+    order: ChannelOrder,
+}
+
+impl<'a, S: PixelSurface + ?Sized> PixelReader<'a, S> {
+    /// Start reading at the first pixel
+    fn new(decoder: &'a Decoder, image: &'a S) -> Self {
+        Self {
+            decoder,
+            image,
+            bit_pos: BitOffset(0),
+            cached: None,
+            order: ChannelOrder::fixed(),
+        }
     }
 
-    /// Matrix-vector multiplication: A × v
-    fn matrix_multiply(&self, v: &BitSlice<u8, Msb0>) -> BitVec<u8, Msb0> {
-        let columns = self.bltm.columns();
-        let mut result = BitVec::<u8, Msb0>::new();
+    /// Read a header from the first pixels, extension included
+    fn read_header(&mut self) -> Result<Vec<u8>> {
+        let mut bytes = self.read_bytes(HEADER_SIZE)?;
+        let length = VersionedParser::header_length(&bytes)?;
+        bytes.extend(self.read_bytes(length - HEADER_SIZE)?);
+        Ok(bytes)
+    }
 
-        // For each row of the matrix
-        for i in 0..3 {
-            // Calculate dot product of row i with vector v
-            let mut bit = false;
-            for j in 0..3 {
-                // Extract the bit from A at position (i,j)
-                // Note: columns are stored from right to left, so we access as columns[2-j]
-                // For a lower triangular matrix, if i < j, the value is 0
-                if i >= j {
-                    let matrix_bit = columns[2 - j][i];
-                    bit ^= matrix_bit && v[j]; // XOR with the AND of matrix bit and vector bit
-                }
+    /// Read the next `len` bytes
+    fn read_bytes(&mut self, len: usize) -> Result<Vec<u8>> {
+        let pixel_count = img::pixel_count(self.image.width(), self.image.height());
+        let mut bits = BitVec::<u8, Msb0>::with_capacity(len * 8);
+
+        for _ in 0..len * 8 {
+            let pixel = self.bit_pos.pixel();
+            if pixel.get() >= pixel_count {
+                return Err(HideError::NoMessageFound);
             }
-            result.push(bit);
+
+            let pixel_bits = match self.cached {
+                Some((index, pixel_bits)) if index == pixel => pixel_bits,
+                _ => {
+                    let rgb = self.image.get_pixel_at(pixel)?;
+                    let pixel_bits = self
+                        .decoder
+                        .pixel_bits(rgb, self.order.for_pixel(pixel.get()));
+                    self.cached = Some((pixel, pixel_bits));
+                    pixel_bits
+                }
+            };
+
+            let shift = img::BITS_PER_PIXEL - 1 - (self.bit_pos - pixel.first_bit());
+            bits.push((pixel_bits >> shift) & 1 != 0);
+            self.bit_pos += 1;
         }
 
-        result
+        Ok(utils::bits_to_bytes(&bits))
     }
 
-    /// Extract the header from encoded data
-    ///
-    /// # Arguments
-    /// * `bits` - The first HEADER_SIZE*8 bits from the stego image
-    ///
-    /// # Returns
-    /// * The message format version and length
-    fn extract_header(&self, bits: &BitVec<u8, Msb0>) -> Result<(u8, u32)> {
-        if bits.len() < HEADER_SIZE * 8 {
-            return Err(HideError::NoMessageFound);
-        }
+    /// Read bytes belonging to the payload, failing if they run past its end
+    fn read_entry_bytes(&mut self, len: usize, remaining: &mut u64) -> Result<Vec<u8>> {
+        Self::consume(len as u64, remaining)?;
+        self.read_bytes(len)
+    }
 
-        // Convert header bits to bytes
-        let header_bytes = utils::bits_to_bytes(&bits[..HEADER_SIZE * 8]);
+    /// Skip bytes belonging to the payload without reading their pixels
+    fn skip_entry_bytes(&mut self, len: u64, remaining: &mut u64) -> Result<()> {
+        Self::consume(len, remaining)?;
+        self.bit_pos += len * 8;
+        Ok(())
+    }
 
-        // Extract format version
-        let format_version = header_bytes[0];
+    /// Account for `len` bytes of the remaining payload
+    fn consume(len: u64, remaining: &mut u64) -> Result<()> {
+        *remaining = remaining
+            .checked_sub(len)
+            .ok_or_else(|| HideError::InvalidParameters("Truncated container entry".to_string()))?;
+        Ok(())
+    }
+}
 
-        // Verify format version
-        if format_version != EXPECTED_FORMAT_VERSION {
-            return Err(HideError::InvalidParameters(format!(
-                "Unsupported message format version: {}",
-                format_version
-            )));
-        }
+/// An image read as if the pixels before `offset`, in row-major order, were gone
+///
+/// Pixels past the end of the image read as black.
+struct ShiftedSurface<'a> {
+    /// The image being read
+    image: &'a StegoImage,
 
-        // Extract message length (big endian)
-        let message_length = ((header_bytes[1] as u32) << 24)
-            | ((header_bytes[2] as u32) << 16)
-            | ((header_bytes[3] as u32) << 8)
-            | (header_bytes[4] as u32);
+    /// Index of the pixel read as the first one
+    offset: u64,
+}
 
-        Ok((format_version, message_length))
+impl<'a> ShiftedSurface<'a> {
+    fn new(image: &'a StegoImage, offset: u64) -> Self {
+        Self { image, offset }
     }
 
-    /// Decode a message from an image
-    ///
-    /// # Arguments
-    /// * `stego_image` - The image containing the hidden message
-    ///
-    /// # Returns
-    /// * The extracted message bytes
-    pub fn decode(&self, stego_image: &StegoImage) -> Result<Vec<u8>> {
-        // Calculate the total number of bits we can extract
-        let total_bits = (stego_image.width() * stego_image.height() * 3) as usize;
+    /// Coordinates in the image of a pixel of the surface, if it has one
+    fn source(&self, x: PixelX, y: PixelY) -> Option<(PixelX, PixelY)> {
+        let width = self.image.width();
+        let index = PixelIndex::from_xy(x, y, width) + self.offset;
+        (index.get() < img::pixel_count(width, self.image.height())).then(|| index.to_xy(width))
+    }
+}
 
-        // Check if the image is big enough to contain a header
-        if total_bits < HEADER_SIZE * 8 {
-            return Err(HideError::NoMessageFound);
-        }
+impl PixelSurface for ShiftedSurface<'_> {
+    fn width(&self) -> u32 {
+        self.image.width()
+    }
 
-        // Extract all message bits from the image
-        let mut all_bits = BitVec::<u8, Msb0>::with_capacity(total_bits);
+    fn height(&self) -> u32 {
+        let remaining = img::pixel_count(self.image.width(), self.image.height()) - self.offset;
+        remaining.div_ceil(self.image.width().max(1) as u64) as u32
+    }
 
-        // Process each pixel to extract embedded bits
-        let mut pixel_count = 0;
-        let required_pixels = (HEADER_SIZE * 8 + 2) / 3; // Pixels needed for header plus a bit extra
+    fn get_pixel_rgb(&self, x: u32, y: u32) -> Result<Rgb<u8>> {
+        match self.source(PixelX(x), PixelY(y)) {
+            Some((x, y)) => self.image.get_pixel_rgb(x.get(), y.get()),
+            None => Ok(Rgb([0, 0, 0])),
+        }
+    }
 
-        for y in 0..stego_image.height() {
-            for x in 0..stego_image.width() {
-                // Get the current pixel
-                let pixel = stego_image.get_pixel_rgb(x, y)?;
+    fn set_pixel_rgb(&mut self, _x: u32, _y: u32, _pixel: Rgb<u8>) -> Result<()> {
+        Err(HideError::InvalidParameters(
+            "Shifted images are read-only".to_string(),
+        ))
+    }
+}
 
-                // Decode the pixel to extract message bits
-                let pixel_bits = self.decode_pixel(pixel.0[0], pixel.0[1], pixel.0[2]);
-                all_bits.extend_from_bitslice(&pixel_bits);
+/// Copy an image with the pixels before `offset`, in row-major order, removed
+///
+/// The alpha channel is kept so RGBA payloads decode from the copy.
+fn shifted_image(stego_image: &StegoImage, offset: u64) -> StegoImage {
+    let surface = ShiftedSurface::new(stego_image, offset);
+    let (width, height) = (surface.width(), surface.height());
+    let inner = stego_image.inner();
+
+    let shifted = if inner.color().has_alpha() {
+        let pixels = inner.to_rgba8();
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(width, height, |x, y| {
+            surface
+                .source(PixelX(x), PixelY(y))
+                .map_or(Rgba([0, 0, 0, 0]), |(x, y)| {
+                    *pixels.get_pixel(x.get(), y.get())
+                })
+        }))
+    } else {
+        let pixels = inner.to_rgb8();
+        DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+            surface
+                .source(PixelX(x), PixelY(y))
+                .map_or(Rgb([0, 0, 0]), |(x, y)| *pixels.get_pixel(x.get(), y.get()))
+        }))
+    };
+    StegoImage::from_dynamic_image(shifted)
+}
 
-                pixel_count += 1;
+/// Convert the whole bytes of some bits read from the first pixels
+fn whole_bytes(bits: &BitSlice<u8, Msb0>) -> Vec<u8> {
+    bits.get(..bits.len() / 8 * 8)
+        .map(utils::bits_to_bytes)
+        .unwrap_or_default()
+}
 
-                // After we've processed enough pixels for the header, extract and check it
-                if pixel_count == required_pixels {
-                    let (_, message_length) = self.extract_header(&all_bits)?;
+/// Convert the bits of the first `length` message bytes after the header
+///
+/// # Returns
+/// * The bytes, or `None` if fewer bits were extracted
+fn message_bytes(
+    all_bits: &BitSlice<u8, Msb0>,
+    header: &MessageHeader,
+    length: usize,
+) -> Option<Vec<u8>> {
+    let start = header.size() * 8;
+    all_bits
+        .get(start..start + length * 8)
+        .map(utils::bits_to_bytes)
+}
 
-                    // Calculate how many pixels we need in total
-                    let message_bits = message_length as usize * 8;
-                    let total_bits_needed = HEADER_SIZE * 8 + message_bits;
-                    let total_pixels_needed = (total_bits_needed + 2) / 3; // Ceiling division
+/// Default limit on the total size of the image files [`decode_many`] loads at once
+pub const DEFAULT_MAX_IN_FLIGHT_BYTES: u64 = 256 << 20;
 
-                    // Check if the message will fit in the image
-                    if total_pixels_needed > (stego_image.width() * stego_image.height()) as usize {
-                        return Err(HideError::NoMessageFound);
-                    }
-                }
-            }
-        }
+/// Options for [`decode_many`]
+#[derive(Debug, Clone)]
+pub struct DecodeManyOptions {
+    /// Number of images decoded at once
+    pub parallelism: usize,
 
-        // Extract the header
-        let (_, message_length) = self.extract_header(&all_bits)?;
+    /// Largest total size, in bytes, of the image files loaded at once; a
+    /// file larger than this is decoded on its own
+    pub max_in_flight_bytes: u64,
 
-        // Calculate the total number of bits in the message (including header)
-        let message_bits = message_length as usize * 8;
-        let total_bits_needed = HEADER_SIZE * 8 + message_bits;
+    /// Options every image is decoded with
+    pub config: DecoderConfig,
+}
 
-        // Check if we extracted enough bits
-        if all_bits.len() < total_bits_needed {
-            return Err(HideError::NoMessageFound);
+impl Default for DecodeManyOptions {
+    fn default() -> Self {
+        Self {
+            parallelism: thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            max_in_flight_bytes: DEFAULT_MAX_IN_FLIGHT_BYTES,
+            config: DecoderConfig::default(),
         }
+    }
+}
 
-        // Extract the message bits (after the header)
-        let message_start = HEADER_SIZE * 8;
-        let message_end = message_start + message_bits;
-        let message_bits = &all_bits[message_start..message_end];
+/// Bytes of image files the workers of a [`decode_many`] have loaded
+struct InFlight {
+    /// Sum of the sizes of the files being decoded
+    bytes: Mutex<u64>,
 
-        // Convert bits back to bytes
-        let message_bytes = utils::bits_to_bytes(message_bits);
+    /// Signalled whenever a file is done with
+    released: Condvar,
 
-        Ok(message_bytes)
+    /// Most bytes allowed at once
+    limit: u64,
+}
+
+impl InFlight {
+    /// Wait until a file of `bytes` fits under the limit, then count it
+    fn acquire(&self, bytes: u64) -> InFlightGuard<'_> {
+        // A file over the limit waits until nothing else is loaded
+        let bytes = bytes.min(self.limit);
+        let mut in_flight = self.bytes.lock().unwrap_or_else(PoisonError::into_inner);
+        while *in_flight + bytes > self.limit {
+            in_flight = self
+                .released
+                .wait(in_flight)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        *in_flight += bytes;
+        InFlightGuard {
+            in_flight: self,
+            bytes,
+        }
     }
+}
 
-    /// Decode a message from an image file
-    ///
-    /// # Arguments
-    /// * `stego_image_path` - Path to the image containing the hidden message
-    ///
-    /// # Returns
-    /// * The extracted message bytes
-    pub fn decode_file<P: AsRef<Path>>(&self, stego_image_path: P) -> Result<Vec<u8>> {
-        // Load the stego image
-        let stego_image = StegoImage::from_file(stego_image_path)?;
+/// A file counted as in flight until the guard is dropped
+struct InFlightGuard<'a> {
+    in_flight: &'a InFlight,
+    bytes: u64,
+}
 
-        // Decode the message
-        self.decode(&stego_image)
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        *self
+            .in_flight
+            .bytes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) -= self.bytes;
+        self.in_flight.released.notify_all();
     }
+}
 
-    /// Get a reference to the BLTM used by this decoder
-    pub fn bltm(&self) -> &BLTM3x3 {
-        &self.bltm
-    }
+/// Decode many image files in parallel
+///
+/// A pool of `parallelism` workers decodes the files, loading no more than
+/// `max_in_flight_bytes` of them at once, as estimated from their sizes on
+/// disk. A file that fails to load or decode gets its error in the results
+/// without stopping the others.
+///
+/// # Arguments
+/// * `paths` - The stego image files
+/// * `options` - How many files to decode at once and how to decode them
+///
+/// # Returns
+/// * Each path with its message or error, in the order of `paths`
+pub fn decode_many<P: AsRef<Path>>(
+    paths: &[P],
+    options: DecodeManyOptions,
+) -> Vec<(PathBuf, Result<Vec<u8>>)> {
+    let paths: Vec<&Path> = paths.iter().map(AsRef::as_ref).collect();
+    let decoder = Decoder::with_config(options.config);
+    let in_flight = InFlight {
+        bytes: Mutex::new(0),
+        released: Condvar::new(),
+        limit: options.max_in_flight_bytes.max(1),
+    };
+    let next = AtomicUsize::new(0);
+    let workers = options.parallelism.clamp(1, paths.len().max(1));
+
+    let decode_file = |path: &Path| {
+        let size = fs::metadata(path)?.len();
+        let _loaded = in_flight.acquire(size);
+        decoder.decode_file(path)
+    };
+    let results: Mutex<Vec<Option<Result<Vec<u8>>>>> =
+        Mutex::new(paths.iter().map(|_| None).collect());
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                scope.spawn(|| loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(&path) = paths.get(index) else {
+                        return;
+                    };
+                    let result = decode_file(path);
+                    if let Some(slot) = results
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .get_mut(index)
+                    {
+                        *slot = Some(result);
+                    }
+                })
+            })
+            .collect();
+        // Joining keeps a panicking worker from taking the other results with it
+        for handle in handles {
+            let _ = handle.join();
+        }
+    });
+
+    let results = results.into_inner().unwrap_or_else(PoisonError::into_inner);
+    paths
+        .iter()
+        .zip(results)
+        .map(|(path, result)| {
+            // Only a file whose decode panicked is left without a result
+            let result = result.unwrap_or_else(|| {
+                Err(HideError::Io(io::Error::other("Decoding worker panicked")))
+            });
+            (path.to_path_buf(), result)
+        })
+        .collect()
 }
 
 /// Create a new decoder with default settings
@@ -218,9 +2658,10 @@ pub fn create_decoder() -> Decoder {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::encoder::Encoder;
+    use crate::encoder::{Encoder, EncoderConfig};
+    use crate::header::HeaderVersion;
     use crate::img::create_rgb_image;
-    use image::Rgb;
+    use crate::testing::{assert_roundtrip, gradient_image, CountingSurface};
 
     #[test]
     fn test_decode_pixel_example() {
@@ -241,33 +2682,12 @@ mod tests {
 
     #[test]
     fn test_encode_decode_cycle() {
-        let encoder = Encoder::new();
-        let decoder = Decoder::new();
-
-        // Create a test image
-        let mut image = create_rgb_image(10, 10);
-
-        // Fill with some test data
-        for y in 0..10 {
-            for x in 0..10 {
-                let r = (x * 20) as u8;
-                let g = (y * 20) as u8;
-                let b = ((x + y) * 10) as u8;
-                image.set_pixel_rgb(x, y, Rgb([r, g, b])).unwrap();
-            }
-        }
-
-        // Original message to encode
-        let original_message = b"Hello, world!";
-
-        // Encode the message
-        let stego_image = encoder.encode(image, original_message).unwrap();
-
-        // Decode the message
-        let decoded_message = decoder.decode(&stego_image).unwrap();
-
-        // Verify the decoded message matches the original
-        assert_eq!(decoded_message, original_message);
+        // Encode into a varied image, decode and compare
+        assert_roundtrip(
+            gradient_image(10, 10),
+            b"Hello, world!",
+            EncoderConfig::default(),
+        );
     }
 
     #[test]
@@ -276,7 +2696,7 @@ mod tests {
         let decoder = Decoder::new();
 
         // Create a test image
-        let image = create_rgb_image(10, 10);
+        let image = create_rgb_image(10, 10).unwrap();
 
         // Empty message to encode
         let original_message = b"";
@@ -296,15 +2716,15 @@ mod tests {
         let decoder = Decoder::new();
 
         // Create an image without any hidden message
-        let image = create_rgb_image(10, 10);
+        let image = create_rgb_image(10, 10).unwrap();
 
         // Attempt to decode - should fail
         let result = decoder.decode(&image);
 
         assert!(result.is_err());
         match result {
-            Err(HideError::InvalidParameters(_)) => (), // Expected - the format version won't match
-            Err(HideError::NoMessageFound) => (),       // This is also acceptable
+            Err(HideError::UnsupportedFeature { .. }) => (), // Expected - the format version won't match
+            Err(HideError::NoMessageFound) => (),            // This is also acceptable
             err => panic!("Unexpected result: {:?}", err),
         }
     }
@@ -312,7 +2732,7 @@ mod tests {
     #[test]
     fn test_decode_invalid_format() {
         let decoder = Decoder::new();
-        let mut image = create_rgb_image(10, 10);
+        let mut image = create_rgb_image(10, 10).unwrap();
 
         // Create an invalid header by directly manipulating LSBs
         // Set format version to 255 (invalid)
@@ -330,10 +2750,14 @@ mod tests {
             }
         }
 
-        // Attempt to decode - should fail with invalid format
+        // Attempt to decode - should fail naming the unknown version
         let result = decoder.decode(&image);
 
-        assert!(result.is_err());
+        assert!(matches!(
+            result,
+            Err(HideError::UnsupportedFeature { version, bits: 0 })
+                if HeaderVersion::from_byte(version).is_none()
+        ));
     }
 
     #[test]
@@ -342,7 +2766,7 @@ mod tests {
         let decoder = Decoder::new();
 
         // Create a test image
-        let image = create_rgb_image(20, 20);
+        let image = create_rgb_image(20, 20).unwrap();
 
         // Test various message sizes
         let messages = [
@@ -379,7 +2803,7 @@ mod tests {
         // Header is 8 bytes = 64 bits, needs 22 pixels (each pixel stores 3 bits)
         // Plus 1 byte message = 8 bits, needs 3 more pixels
         // Total: 25 pixels, so 6x5 image is sufficient (30 pixels)
-        let image = create_rgb_image(6, 5);
+        let image = create_rgb_image(6, 5).unwrap();
 
         // Message with special characters (small enough to fit)
         let message = b"\x00\x01\xFE";
@@ -393,4 +2817,321 @@ mod tests {
         // Verify the decoded message matches the original
         assert_eq!(decoded_message, message);
     }
+
+    #[test]
+    fn test_decode_reads_only_the_payload_pixels() {
+        let decoder = Decoder::new();
+
+        for length in [0, 1, 2, 3, 100, 1000] {
+            let message = vec![0x5a; length];
+            let stego_image = Encoder::new()
+                .encode(gradient_image(64, 64), &message)
+                .unwrap();
+            let surface = CountingSurface::new(&stego_image);
+
+            // 64 header bits need 22 pixels
+            let (header, bits) = decoder.read_header_pixels(&surface).unwrap();
+            assert_eq!(surface.reads(), 22);
+            assert_eq!(header.message_length as usize, length);
+
+            let decoded = decoder
+                .read_message_pixels(&surface, &header, bits)
+                .unwrap();
+            assert_eq!(decoded, message);
+            assert_eq!(
+                surface.reads(),
+                Encoder::pixels_needed(length) as u64,
+                "Pixel reads for a {} byte message",
+                length
+            );
+        }
+    }
+
+    #[test]
+    fn test_decode_of_truncated_payload_reads_only_the_header() {
+        let decoder = Decoder::new();
+        let stego_image = Encoder::new()
+            .encode(create_rgb_image(20, 20).unwrap(), &[7; 120])
+            .unwrap();
+        let cropped = StegoImage::from_dynamic_image(stego_image.inner().crop_imm(0, 0, 20, 12));
+        let surface = CountingSurface::new(&cropped);
+
+        let (header, bits) = decoder.read_header_pixels(&surface).unwrap();
+        assert!(matches!(
+            decoder.read_message_pixels(&surface, &header, bits),
+            Err(HideError::TruncatedPayload {
+                declared: 120,
+                available: 82
+            })
+        ));
+        assert_eq!(surface.reads(), 22);
+    }
+
+    #[test]
+    fn test_decode_lossy_cropped_image() {
+        let encoder = Encoder::new();
+        let decoder = Decoder::new();
+
+        // 20x20 image holds 142 bytes of message; use most of it
+        let image = create_rgb_image(20, 20).unwrap();
+        let message: Vec<u8> = (0..120u8).collect();
+        let stego_image = encoder.encode(image, &message).unwrap();
+
+        // Crop the bottom 8 rows away
+        let cropped = StegoImage::from_dynamic_image(stego_image.inner().crop_imm(0, 0, 20, 12));
+
+        // Strict decoding refuses the truncated image and says by how much
+        assert!(matches!(
+            decoder.decode(&cropped),
+            Err(HideError::TruncatedPayload {
+                declared: 120,
+                available: 82
+            })
+        ));
+
+        // Lossy decoding recovers the intact prefix
+        let partial = decoder.decode_lossy(&cropped).unwrap();
+        assert!(!partial.complete);
+        assert_eq!(partial.declared_length, message.len());
+        // 12 rows x 20 pixels x 3 bits = 720 bits, minus 64 header bits = 82 bytes
+        assert_eq!(partial.recovered_length, 82);
+        assert_eq!(partial.data.len(), partial.recovered_length);
+        assert_eq!(partial.data, &message[..partial.recovered_length]);
+    }
+
+    #[test]
+    fn test_decode_lossy_complete_image() {
+        let encoder = Encoder::new();
+        let decoder = Decoder::new();
+
+        let image = create_rgb_image(10, 10).unwrap();
+        let message = b"Hello, world!";
+        let stego_image = encoder.encode(image, message).unwrap();
+
+        // An untouched image decodes completely
+        let partial = decoder.decode_lossy(&stego_image).unwrap();
+        assert!(partial.complete);
+        assert_eq!(partial.declared_length, message.len());
+        assert_eq!(partial.recovered_length, message.len());
+        assert_eq!(partial.data, message);
+    }
+
+    #[test]
+    fn test_decode_detailed() {
+        let encoder = Encoder::new();
+        let decoder = Decoder::new();
+
+        let image = create_rgb_image(10, 10).unwrap();
+        let stego_image = encoder.encode(image, b"Hello, world!").unwrap();
+
+        let detailed = decoder.decode_detailed(&stego_image, false).unwrap();
+        assert_eq!(detailed.decoded.data, b"Hello, world!");
+        assert!(detailed.decoded.complete);
+        assert_eq!(detailed.timing.pixels, 100);
+    }
+
+    #[test]
+    fn test_read_header() {
+        let encoder = Encoder::new();
+        let decoder = Decoder::new();
+
+        let image = create_rgb_image(10, 10).unwrap();
+        let stego_image = encoder.encode(image.clone(), b"Hello, world!").unwrap();
+
+        let header = decoder.read_header(&stego_image).unwrap();
+        assert_eq!(header.format_version, HeaderVersion::CURRENT.number());
+        assert_eq!(header.message_length, 13);
+
+        // A blank image has no valid header
+        assert!(decoder.read_header(&image).is_err());
+    }
+
+    #[test]
+    fn test_verify_manifest() {
+        use crate::encoder::Secret;
+
+        let dir = tempfile::tempdir().unwrap();
+        let cover_path = dir.path().join("cover.png");
+        let stego_path = dir.path().join("stego.png");
+        let manifest_path = manifest::manifest_path_for(&stego_path);
+
+        gradient_image(20, 20).save(&cover_path).unwrap();
+
+        let encoder = Encoder::with_config(EncoderConfig {
+            emit_manifest: true,
+            passphrase: Some(Secret::new("correct horse battery staple")),
+            ..Default::default()
+        });
+        encoder
+            .encode_file(&cover_path, b"provenance", &stego_path)
+            .unwrap();
+
+        // The manifest records hashes but neither the message nor the passphrase
+        let manifest_text = std::fs::read_to_string(&manifest_path).unwrap();
+        assert!(!manifest_text.contains("provenance"));
+        assert!(!manifest_text.contains("correct horse"));
+        let manifest = Manifest::load(&manifest_path).unwrap();
+        assert_eq!(manifest.cover_filename, "cover.png");
+        assert_eq!(manifest.message_length, 10);
+        assert_eq!(manifest.stats.message_bytes, 10);
+
+        assert!(verify_manifest(&stego_path, &manifest_path)
+            .unwrap()
+            .is_valid());
+
+        // Re-encoding a different message is detected
+        encoder
+            .encode_file(&cover_path, b"tampered!!", &stego_path)
+            .unwrap();
+        let verification = verify_manifest(&stego_path, dir.path().join("other.json"));
+        assert!(verification.is_err());
+
+        std::fs::write(
+            dir.path().join("other.json"),
+            serde_json::to_vec(&manifest).unwrap(),
+        )
+        .unwrap();
+        let verification = verify_manifest(&stego_path, dir.path().join("other.json")).unwrap();
+        assert!(!verification.is_valid());
+        assert!(verification
+            .mismatches
+            .iter()
+            .any(|m| m.starts_with("message_sha256")));
+        assert!(verification
+            .mismatches
+            .iter()
+            .any(|m| m.starts_with("stego_sha256")));
+    }
+
+    #[test]
+    fn test_decode_lossy_requires_header() {
+        let decoder = Decoder::new();
+
+        // An image without a valid header still fails
+        let image = create_rgb_image(10, 10).unwrap();
+        assert!(decoder.decode_lossy(&image).is_err());
+    }
+
+    fn sample_entries() -> Vec<(String, Vec<u8>)> {
+        vec![
+            ("first".to_string(), b"meta".to_vec()),
+            ("second".to_string(), vec![0xAB; 200]),
+            ("third".to_string(), b"trail".to_vec()),
+        ]
+    }
+
+    #[test]
+    fn test_container_round_trip() {
+        let encoder = Encoder::new();
+        let decoder = Decoder::new();
+        let entries = sample_entries();
+
+        let stego = encoder
+            .encode_container(create_rgb_image(40, 40).unwrap(), &entries)
+            .unwrap();
+        assert!(decoder.read_header(&stego).unwrap().is_container());
+
+        let decoded = decoder.decode_container(&stego).unwrap();
+        assert_eq!(decoded.len(), entries.len());
+        for (entry, (name, data)) in decoded.iter().zip(&entries) {
+            assert_eq!(&entry.name, name);
+            assert_eq!(&entry.data, data);
+        }
+
+        assert_eq!(
+            decoder.decode_entry(&stego, "third").unwrap(),
+            Some(b"trail".to_vec())
+        );
+        assert_eq!(decoder.decode_entry(&stego, "missing").unwrap(), None);
+
+        // Plain decoding still returns the raw entry bytes
+        assert_eq!(
+            decoder.decode(&stego).unwrap(),
+            container::encode_entries(&entries).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_container_rejects_duplicates_and_plain_payloads() {
+        let encoder = Encoder::new();
+        let decoder = Decoder::new();
+
+        let duplicate = vec![("sig".to_string(), vec![1]), ("sig".to_string(), vec![2])];
+        assert!(encoder
+            .encode_container(create_rgb_image(40, 40).unwrap(), &duplicate)
+            .is_err());
+
+        let plain = encoder
+            .encode(create_rgb_image(40, 40).unwrap(), b"not a container")
+            .unwrap();
+        assert!(!decoder.read_header(&plain).unwrap().is_container());
+        assert!(decoder.decode_container(&plain).is_err());
+        assert!(decoder.decode_entry(&plain, "sig").is_err());
+    }
+
+    #[test]
+    fn test_decode_entry_skips_other_entries() {
+        let encoder = Encoder::new();
+        let decoder = Decoder::new();
+
+        let mut stego = encoder
+            .encode_container(create_rgb_image(40, 40).unwrap(), &sample_entries())
+            .unwrap();
+
+        // The value of "second" spans payload bytes 39..239 (pixels 104..638);
+        // corrupt pixels well inside it
+        let width = stego.width();
+        for index in 110..600 {
+            let (x, y) = (index % width, index / width);
+            let mut rgb = stego.get_pixel_rgb(x, y).unwrap();
+            rgb.0[0] ^= 1;
+            stego.set_pixel_rgb(x, y, rgb).unwrap();
+        }
+
+        // Entries on either side are read without touching the corrupted value
+        assert_eq!(
+            decoder.decode_entry(&stego, "first").unwrap(),
+            Some(b"meta".to_vec())
+        );
+        assert_eq!(
+            decoder.decode_entry(&stego, "third").unwrap(),
+            Some(b"trail".to_vec())
+        );
+
+        // A full decode reads every pixel and sees the damage
+        let decoded = decoder.decode_container(&stego).unwrap();
+        assert_ne!(decoded[1].data, vec![0xAB; 200]);
+    }
+
+    #[test]
+    fn test_search_window_tries_row_starts_first() {
+        let window = SearchWindow {
+            max_rows: 2,
+            max_pixel_offset: 5,
+        };
+        let candidates: Vec<_> = window.candidates(4, 10).collect();
+        assert_eq!(candidates, [0, 4, 8, 1, 2, 3, 5]);
+
+        // Candidates never run past the image
+        let candidates: Vec<_> = window.candidates(4, 1).collect();
+        assert_eq!(candidates, [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_shifted_surface_maps_pixels_on_wide_image() {
+        let image = create_rgb_image(7, 3).unwrap();
+        let surface = ShiftedSurface::new(&image, 3);
+
+        // Column 5 of the first row is 8 pixels in: column 1 of the second row
+        assert_eq!(
+            surface.source(PixelX(5), PixelY(0)),
+            Some((PixelX(1), PixelY(1)))
+        );
+        assert_eq!(
+            surface.source(PixelX(0), PixelY(2)),
+            Some((PixelX(3), PixelY(2)))
+        );
+        assert_eq!(surface.source(PixelX(4), PixelY(2)), None);
+        assert_eq!(surface.height(), 3);
+    }
 }
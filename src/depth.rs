@@ -0,0 +1,232 @@
+//! Embedding depth: how many of each channel's lowest bits carry the payload
+//!
+//! By default the payload takes the least significant bit of every channel,
+//! three bits per pixel. With [`EmbeddingDepth::Two`] the payload after the
+//! header takes the two lowest bits, six per pixel: the BLTM runs once over
+//! the bit plane of the LSBs and again over the plane of the bits above them,
+//! each time in the payload's channel order. The header, and the mask
+//! embedded after it, keep the one-bit layout, so any decoder can read them;
+//! the [`ExtraFlags::TWO_BIT_DEPTH`] flag of version 2 headers marks two-bit
+//! payloads, which start at the next pixel. Masked payloads skip excluded
+//! pixels and RGBA ones take two bits of the alpha channel too, so the depth
+//! combines with every other option.
+//!
+//! Doubling the depth doubles the capacity, but changes each channel by up
+//! to 3 instead of 1, which is easier to detect.
+//!
+//! [`ExtraFlags::TWO_BIT_DEPTH`]: crate::header::ExtraFlags::TWO_BIT_DEPTH
+
+use crate::error::HideError;
+use crate::header::EXTENDED_HEADER_SIZE;
+use crate::img;
+use crate::Result;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Pixels holding the header of two-bit payloads at three bits per pixel
+///
+/// The two-bit flag always takes a version 2 header.
+pub const HEADER_PIXELS: usize = img::pixels_for_bits(EXTENDED_HEADER_SIZE as u64 * 8) as usize;
+
+/// How many of each channel's lowest bits the payload after the header takes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum EmbeddingDepth {
+    /// The least significant bit of every channel; readable by every decoder
+    #[default]
+    One,
+
+    /// The two lowest bits of every channel
+    Two,
+}
+
+impl EmbeddingDepth {
+    /// Bits of each channel that carry the payload
+    pub fn bits_per_channel(self) -> u8 {
+        match self {
+            Self::One => 1,
+            Self::Two => 2,
+        }
+    }
+
+    /// Payload bits each pixel after the header carries
+    pub fn bits_per_pixel(self) -> u64 {
+        u64::from(self.bits_per_channel()) * img::BITS_PER_PIXEL
+    }
+}
+
+impl TryFrom<u8> for EmbeddingDepth {
+    type Error = HideError;
+
+    fn try_from(bits: u8) -> Result<Self> {
+        match bits {
+            1 => Ok(Self::One),
+            2 => Ok(Self::Two),
+            _ => Err(HideError::InvalidParameters(format!(
+                "Embedding depth must be 1 or 2 bits per channel, not {}",
+                bits
+            ))),
+        }
+    }
+}
+
+impl From<EmbeddingDepth> for u8 {
+    fn from(depth: EmbeddingDepth) -> Self {
+        depth.bits_per_channel()
+    }
+}
+
+impl fmt::Display for EmbeddingDepth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.bits_per_channel())
+    }
+}
+
+impl FromStr for EmbeddingDepth {
+    type Err = HideError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let bits = s.trim().parse::<u8>().map_err(|_| {
+            HideError::InvalidParameters(format!(
+                "Embedding depth must be 1 or 2 bits per channel, not {:?}",
+                s
+            ))
+        })?;
+        Self::try_from(bits)
+    }
+}
+
+/// Calculate the maximum two-bit message size for an image of the given dimensions
+///
+/// # Arguments
+/// * `width` - Image width in pixels
+/// * `height` - Image height in pixels
+///
+/// # Returns
+/// * Maximum message size in bytes (accounting for header), saturating at `usize::MAX`
+pub fn max_message_size_for_dimensions(width: u32, height: u32) -> usize {
+    capacity_after_prefix(
+        img::pixel_count(width, height),
+        EXTENDED_HEADER_SIZE,
+        EmbeddingDepth::Two,
+    )
+}
+
+/// Whole bytes a payload can take in `pixels` pixels after a one-bit prefix
+///
+/// The prefix is the header and any embedded mask. At a depth of one bit the
+/// payload carries on in the prefix's last pixel; deeper payloads start at
+/// the pixel after it.
+pub(crate) fn capacity_after_prefix(
+    pixels: u64,
+    prefix_bytes: usize,
+    depth: EmbeddingDepth,
+) -> usize {
+    match depth {
+        EmbeddingDepth::One => {
+            bytes_for_pixels(pixels, img::BITS_PER_PIXEL).saturating_sub(prefix_bytes)
+        }
+        EmbeddingDepth::Two => bytes_for_pixels(
+            pixels.saturating_sub(prefix_pixels(prefix_bytes)),
+            depth.bits_per_pixel(),
+        ),
+    }
+}
+
+/// Number of pixels a payload and the one-bit prefix before it touch
+pub(crate) fn pixels_with_prefix(
+    prefix_bytes: usize,
+    payload_length: usize,
+    depth: EmbeddingDepth,
+) -> u64 {
+    match depth {
+        EmbeddingDepth::One => img::pixels_for_bits((prefix_bytes + payload_length) as u64 * 8),
+        EmbeddingDepth::Two => {
+            prefix_pixels(prefix_bytes)
+                + message_pixels(payload_length, depth.bits_per_pixel()) as u64
+        }
+    }
+}
+
+/// Number of pixels holding a one-bit prefix of the given length
+pub(crate) fn prefix_pixels(prefix_bytes: usize) -> u64 {
+    img::pixels_for_bits(prefix_bytes as u64 * 8)
+}
+
+/// Calculate how many pixels a message and its header touch at a depth of two bits
+///
+/// # Arguments
+/// * `message_length` - Length of the message in bytes
+///
+/// # Returns
+/// * Number of pixels that carry header or payload bits
+pub fn pixels_needed(message_length: usize) -> usize {
+    HEADER_PIXELS + message_pixels(message_length, EmbeddingDepth::Two.bits_per_pixel())
+}
+
+/// Number of pixels a message takes after the header, at the given bits per pixel
+pub(crate) fn message_pixels(message_length: usize, bits_per_pixel: u64) -> usize {
+    (message_length * 8).div_ceil(bits_per_pixel as usize)
+}
+
+/// Whole bytes held by the given number of payload pixels, at the given bits per pixel
+pub(crate) fn bytes_for_pixels(pixels: u64, bits_per_pixel: u64) -> usize {
+    usize::try_from(pixels.saturating_mul(bits_per_pixel) / 8).unwrap_or(usize::MAX)
+}
+
+/// The channels of a pixel shifted so the given bit plane is in their LSBs
+pub(crate) fn plane<const N: usize>(pixel: [u8; N], plane: u8) -> [u8; N] {
+    pixel.map(|channel| channel >> plane)
+}
+
+/// A pixel with one bit plane replaced by the LSBs of `bits`
+pub(crate) fn with_plane<const N: usize>(mut pixel: [u8; N], plane: u8, bits: [u8; N]) -> [u8; N] {
+    for (channel, bit) in pixel.iter_mut().zip(bits) {
+        *channel = (*channel & !(1 << plane)) | ((bit & 1) << plane);
+    }
+    pixel
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_depth_parsing() {
+        assert_eq!("1".parse::<EmbeddingDepth>().unwrap(), EmbeddingDepth::One);
+        assert_eq!(" 2".parse::<EmbeddingDepth>().unwrap(), EmbeddingDepth::Two);
+        for invalid in ["0", "3", "two", ""] {
+            assert!(matches!(
+                invalid.parse::<EmbeddingDepth>(),
+                Err(HideError::InvalidParameters(_))
+            ));
+        }
+        assert_eq!(EmbeddingDepth::Two.to_string(), "2");
+        assert_eq!(EmbeddingDepth::default(), EmbeddingDepth::One);
+    }
+
+    #[test]
+    fn test_two_bit_capacity() {
+        // 100 pixels: 32 for the header, 68 at six bits each
+        assert_eq!(max_message_size_for_dimensions(10, 10), 68 * 6 / 8);
+        assert_eq!(max_message_size_for_dimensions(4, 4), 0);
+        assert_eq!(pixels_needed(0), HEADER_PIXELS);
+        assert_eq!(pixels_needed(3), HEADER_PIXELS + 4);
+        assert_eq!(pixels_needed(4), HEADER_PIXELS + 6);
+    }
+
+    #[test]
+    fn test_planes() {
+        let pixel = [0b1010_1101, 0b0000_0010, 0xff];
+        assert_eq!(plane(pixel, 1), [0b0101_0110, 0b0000_0001, 0x7f]);
+        assert_eq!(
+            with_plane(pixel, 1, [1, 0, 0xfe]),
+            [0b1010_1111, 0b0000_0000, 0xfd]
+        );
+        assert_eq!(
+            with_plane(pixel, 0, [0, 1, 1]),
+            [0b1010_1100, 0b0000_0011, 0xff]
+        );
+    }
+}
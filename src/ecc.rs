@@ -0,0 +1,764 @@
+//! Error correction of payloads with Reed-Solomon or Hamming(7,4) codes
+//!
+//! With [`ErrorCorrection::ReedSolomon`], [`encode`] splits a payload into
+//! blocks of up to [`EccParams::data_shards`] bytes and follows each with
+//! [`EccParams::parity_shards`] parity bytes, so [`decode`] can repair up to
+//! half as many corrupted bytes per block as it has parity. With
+//! [`ErrorCorrection::Hamming74`] every nibble becomes a 7-bit codeword
+//! instead, repairing one flipped bit in each for 75% more bytes; scattered
+//! single-bit damage is cheaper to repair that way than with whole parity bytes.
+//!
+//! Either way the payload starts with [`PARAMS_SIZE`] bytes naming the code,
+//! the two counts or zeros for Hamming(7,4), themselves protected by parity,
+//! so decoders don't need to be told them. The header's
+//! [`ExtraFlags::ERROR_CORRECTED`](crate::header::ExtraFlags::ERROR_CORRECTED)
+//! bit marks corrected payloads, and its length field counts the parity. The
+//! header has no bit left to name the code itself.
+//!
+//! The Reed-Solomon code works over GF(2^8) with the polynomial `0x11d` and generator
+//! roots `α^0..α^(parity - 1)`, the usual byte-oriented choice. It is
+//! implemented here because no Reed-Solomon crate is among the dependencies.
+//! A block whose errors are beyond repair is reported as
+//! [`HideError::UncorrectableErrors`] rather than decoded to wrong bytes,
+//! though like any Reed-Solomon decoder it can be fooled by far more errors
+//! than it corrects; a checksum after the parity catches those.
+
+use crate::error::HideError;
+use crate::utils;
+use crate::Result;
+use bitvec::prelude::*;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Size of the protected data and parity counts that start a corrected payload
+pub const PARAMS_SIZE: usize = 6;
+
+/// Counts naming Hamming(7,4), which no Reed-Solomon block layout has
+const HAMMING_74_COUNTS: [u8; 2] = [0, 0];
+
+/// Bits of a Hamming(7,4) codeword, carrying one nibble
+const HAMMING_CODEWORD_BITS: usize = 7;
+
+/// Parity bytes protecting the counts, enough to repair two corrupted bytes
+const PARAMS_PARITY: usize = PARAMS_SIZE - 2;
+
+/// Longest block, data and parity together, a byte-sized field allows
+pub const MAX_BLOCK_SIZE: usize = 255;
+
+/// Data and parity bytes per Reed-Solomon block
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct EccParams {
+    /// Message bytes per block; the last block may hold fewer
+    pub data_shards: u8,
+
+    /// Parity bytes per block, which repair up to half as many corrupted bytes
+    pub parity_shards: u8,
+}
+
+impl Default for EccParams {
+    /// The classic RS(255, 223), correcting 16 bytes in every 255
+    fn default() -> Self {
+        Self {
+            data_shards: 223,
+            parity_shards: 32,
+        }
+    }
+}
+
+impl EccParams {
+    /// Check that the counts describe a usable code
+    ///
+    /// # Returns
+    /// * An error unless there is at least one data byte and two parity bytes
+    ///   and a block is at most [`MAX_BLOCK_SIZE`] bytes long
+    pub fn validate(&self) -> Result<()> {
+        if self.data_shards == 0 || self.parity_shards < 2 {
+            return Err(HideError::InvalidParameters(format!(
+                "Error correction needs at least 1 data and 2 parity bytes per block, got {}:{}",
+                self.data_shards, self.parity_shards
+            )));
+        }
+        if self.block_size() > MAX_BLOCK_SIZE {
+            return Err(HideError::InvalidParameters(format!(
+                "Error correction blocks are at most {} bytes, {}:{} makes {}",
+                MAX_BLOCK_SIZE,
+                self.data_shards,
+                self.parity_shards,
+                self.block_size()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Corrupted bytes each block can repair
+    pub fn correctable_errors(&self) -> usize {
+        usize::from(self.parity_shards) / 2
+    }
+
+    /// Bytes in a full block
+    fn block_size(&self) -> usize {
+        usize::from(self.data_shards) + usize::from(self.parity_shards)
+    }
+}
+
+impl fmt::Display for EccParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.data_shards, self.parity_shards)
+    }
+}
+
+impl FromStr for EccParams {
+    type Err = HideError;
+
+    /// Parse `DATA:PARITY`, such as `223:32`
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || {
+            HideError::InvalidParameters(format!(
+                "Invalid error correction '{}'; expected DATA:PARITY bytes per block, such as 223:32",
+                s.trim()
+            ))
+        };
+        let (data, parity) = s.trim().split_once(':').ok_or_else(invalid)?;
+        let params = Self {
+            data_shards: data.trim().parse().map_err(|_| invalid())?,
+            parity_shards: parity.trim().parse().map_err(|_| invalid())?,
+        };
+        params.validate()?;
+        Ok(params)
+    }
+}
+
+/// The codes payloads can be protected with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorCorrection {
+    /// Reed-Solomon blocks, each repairing up to half as many corrupted bytes as it has parity
+    ReedSolomon(EccParams),
+
+    /// A Hamming(7,4) codeword for every nibble, each repairing one flipped bit
+    Hamming74,
+}
+
+impl From<EccParams> for ErrorCorrection {
+    fn from(params: EccParams) -> Self {
+        Self::ReedSolomon(params)
+    }
+}
+
+impl ErrorCorrection {
+    /// Check that the code is usable
+    ///
+    /// # Returns
+    /// * An error if Reed-Solomon counts are invalid (see [`EccParams::validate`])
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            Self::ReedSolomon(params) => params.validate(),
+            Self::Hamming74 => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for ErrorCorrection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ReedSolomon(params) => write!(f, "{}", params),
+            Self::Hamming74 => f.write_str("hamming74"),
+        }
+    }
+}
+
+impl FromStr for ErrorCorrection {
+    type Err = HideError;
+
+    /// Parse `hamming74`, or Reed-Solomon `DATA:PARITY` counts such as `223:32`
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "hamming74" | "hamming" => Ok(Self::Hamming74),
+            _ => s.parse().map(Self::ReedSolomon),
+        }
+    }
+}
+
+/// Length of the payload [`encode`] makes of `data_length` bytes
+///
+/// # Arguments
+/// * `data_length` - Length of the data in bytes
+/// * `ecc` - The code, or the Reed-Solomon block layout
+///
+/// # Returns
+/// * The counts followed by the data and one run of parity for each started
+///   block, or by a 7-bit codeword for each nibble
+pub fn encoded_length(data_length: usize, ecc: impl Into<ErrorCorrection>) -> usize {
+    match ecc.into() {
+        ErrorCorrection::ReedSolomon(params) => {
+            let blocks = data_length.div_ceil(usize::from(params.data_shards.max(1)));
+            PARAMS_SIZE + data_length + blocks * usize::from(params.parity_shards)
+        }
+        ErrorCorrection::Hamming74 => {
+            PARAMS_SIZE + (data_length * 2 * HAMMING_CODEWORD_BITS).div_ceil(8)
+        }
+    }
+}
+
+/// Most data an encoded payload of `capacity` bytes can carry
+///
+/// # Arguments
+/// * `capacity` - Room for the encoded payload in bytes
+/// * `ecc` - The code, or the Reed-Solomon block layout
+///
+/// # Returns
+/// * The largest length whose [`encoded_length`] is at most `capacity`
+pub fn max_data_length(capacity: usize, ecc: impl Into<ErrorCorrection>) -> usize {
+    let available = capacity.saturating_sub(PARAMS_SIZE);
+    match ecc.into() {
+        ErrorCorrection::ReedSolomon(params) => {
+            let block_size = params.block_size().max(1);
+            let full_blocks = available / block_size;
+            let last_block =
+                (available % block_size).saturating_sub(usize::from(params.parity_shards));
+            full_blocks * usize::from(params.data_shards) + last_block
+        }
+        ErrorCorrection::Hamming74 => available * 8 / (2 * HAMMING_CODEWORD_BITS),
+    }
+}
+
+/// Add error correction parity to a payload
+///
+/// # Arguments
+/// * `data` - The payload to protect
+/// * `ecc` - The code, or the Reed-Solomon block layout
+///
+/// # Returns
+/// * The protected counts followed by each block of data and its parity, or
+///   by the codewords, or an error if the Reed-Solomon counts are invalid
+pub fn encode(data: &[u8], ecc: impl Into<ErrorCorrection>) -> Result<Vec<u8>> {
+    let ecc = ecc.into();
+    ecc.validate()?;
+    let counts = match ecc {
+        ErrorCorrection::ReedSolomon(params) => [params.data_shards, params.parity_shards],
+        ErrorCorrection::Hamming74 => HAMMING_74_COUNTS,
+    };
+    let mut encoded = Vec::with_capacity(encoded_length(data.len(), ecc));
+    encoded.extend_from_slice(&counts);
+    encoded.extend(remainder(&counts, &generator(PARAMS_PARITY)));
+
+    match ecc {
+        ErrorCorrection::ReedSolomon(params) => {
+            let generator = generator(usize::from(params.parity_shards));
+            for block in data.chunks(usize::from(params.data_shards)) {
+                encoded.extend_from_slice(block);
+                encoded.extend(remainder(block, &generator));
+            }
+        }
+        ErrorCorrection::Hamming74 => encoded.extend(hamming_encode(data)),
+    }
+    Ok(encoded)
+}
+
+/// Repair and strip the parity [`encode`] added
+///
+/// Two flipped bits in one Hamming(7,4) codeword can't be told from one, so
+/// they are "repaired" to the wrong nibble; a checksum catches that.
+///
+/// # Arguments
+/// * `payload` - The protected payload as read from an image
+///
+/// # Returns
+/// * The original data, [`HideError::UncorrectableErrors`] if a Reed-Solomon
+///   block has more corrupted bytes than its parity repairs, or an error if
+///   the counts can't be read or the payload doesn't end on a block
+pub fn decode(payload: &[u8]) -> Result<Vec<u8>> {
+    let unreadable = || {
+        HideError::InvalidParameters(
+            "Error correction parameters at the start of the payload are unreadable".to_string(),
+        )
+    };
+    let mut counts = payload.get(..PARAMS_SIZE).ok_or_else(unreadable)?.to_vec();
+    correct(&mut counts, PARAMS_PARITY).ok_or_else(unreadable)?;
+    let blocks = payload.get(PARAMS_SIZE..).unwrap_or_default();
+    if counts.get(..2) == Some(HAMMING_74_COUNTS.as_slice()) {
+        return Ok(hamming_decode(blocks));
+    }
+    let params = EccParams {
+        data_shards: counts.first().copied().unwrap_or_default(),
+        parity_shards: counts.get(1).copied().unwrap_or_default(),
+    };
+    params.validate()?;
+
+    let parity_shards = usize::from(params.parity_shards);
+    let mut data = Vec::with_capacity(blocks.len());
+    let mut corrected = 0;
+    for (index, block) in blocks.chunks(params.block_size()).enumerate() {
+        if block.len() <= parity_shards {
+            return Err(HideError::InvalidParameters(format!(
+                "Error correction block {} has only {} bytes, no more than its {} parity bytes",
+                index,
+                block.len(),
+                parity_shards
+            )));
+        }
+        let mut block = block.to_vec();
+        corrected += correct(&mut block, parity_shards)
+            .ok_or(HideError::UncorrectableErrors { block: index })?;
+        block.truncate(block.len() - parity_shards);
+        data.extend_from_slice(&block);
+    }
+    if corrected > 0 {
+        debug!("Error correction repaired {} payload bytes", corrected);
+    }
+    Ok(data)
+}
+
+/// Hamming(7,4) codewords of a payload's nibbles, high nibble first
+///
+/// # Returns
+/// * The codewords' bits packed into bytes, the last padded with zeros
+fn hamming_encode(data: &[u8]) -> Vec<u8> {
+    let mut bits = BitVec::<u8, Msb0>::with_capacity(data.len() * 2 * HAMMING_CODEWORD_BITS);
+    for &byte in data {
+        for nibble in [byte >> 4, byte & 0x0f] {
+            let codeword = hamming_codeword(nibble);
+            for position in (0..HAMMING_CODEWORD_BITS).rev() {
+                bits.push((codeword >> position) & 1 == 1);
+            }
+        }
+    }
+    utils::bits_to_bytes(&bits)
+}
+
+/// Nibbles of the codewords [`hamming_encode`] packed, each repaired of one flipped bit
+fn hamming_decode(packed: &[u8]) -> Vec<u8> {
+    let bits = utils::bytes_to_bits(packed);
+    let nibbles: Vec<(u8, bool)> = bits
+        .chunks_exact(HAMMING_CODEWORD_BITS)
+        .map(|codeword| {
+            codeword
+                .iter()
+                .fold(0, |value, bit| value << 1 | u8::from(*bit))
+        })
+        .map(hamming_nibble)
+        .collect();
+
+    let corrected = nibbles.iter().filter(|(_, repaired)| *repaired).count();
+    if corrected > 0 {
+        debug!("Error correction repaired {} payload bits", corrected);
+    }
+    nibbles
+        .chunks_exact(2)
+        .map(|pair| match pair {
+            [(high, _), (low, _)] => high << 4 | low,
+            _ => 0,
+        })
+        .collect()
+}
+
+/// The 7-bit codeword of a nibble: parity bits at positions 1, 2 and 4,
+/// counting from the most significant, and the nibble's bits at 3, 5, 6 and 7
+fn hamming_codeword(nibble: u8) -> u8 {
+    let bit = |i: u8| (nibble >> (3 - i)) & 1;
+    let (d1, d2, d3, d4) = (bit(0), bit(1), bit(2), bit(3));
+    [d1 ^ d2 ^ d4, d1 ^ d3 ^ d4, d1, d2 ^ d3 ^ d4, d2, d3, d4]
+        .iter()
+        .fold(0, |codeword, bit| codeword << 1 | bit)
+}
+
+/// The nibble a codeword carries, after flipping the bit its syndrome points at
+///
+/// # Returns
+/// * The nibble and whether a bit was flipped
+fn hamming_nibble(codeword: u8) -> (u8, bool) {
+    let bit = |position: u8| (codeword >> (7 - position)) & 1;
+    let syndrome = (bit(1) ^ bit(3) ^ bit(5) ^ bit(7))
+        | (bit(2) ^ bit(3) ^ bit(6) ^ bit(7)) << 1
+        | (bit(4) ^ bit(5) ^ bit(6) ^ bit(7)) << 2;
+    let codeword = if syndrome == 0 {
+        codeword
+    } else {
+        codeword ^ (1 << (7 - syndrome))
+    };
+    let bit = |position: u8| (codeword >> (7 - position)) & 1;
+    (
+        bit(3) << 3 | bit(5) << 2 | bit(6) << 1 | bit(7),
+        syndrome != 0,
+    )
+}
+
+/// GF(2^8) exponent table, doubled so products of two logarithms need no reduction
+const EXP: [u8; 512] = exp_table();
+
+/// GF(2^8) logarithm table; the entry for 0 is unused
+const LOG: [u8; 256] = log_table();
+
+/// Powers of α modulo the field polynomial
+// Indices are bounded by the loop limits
+#[allow(clippy::indexing_slicing)]
+const fn exp_table() -> [u8; 512] {
+    let mut table = [0; 512];
+    let mut value: u16 = 1;
+    let mut i = 0;
+    while i < 255 {
+        table[i] = value as u8;
+        value <<= 1;
+        if value & 0x100 != 0 {
+            value ^= 0x11d;
+        }
+        i += 1;
+    }
+    while i < 512 {
+        table[i] = table[i - 255];
+        i += 1;
+    }
+    table
+}
+
+/// Inverse of [`exp_table`] over the nonzero elements
+// Indices are bounded by the table sizes
+#[allow(clippy::indexing_slicing)]
+const fn log_table() -> [u8; 256] {
+    let exp = exp_table();
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 255 {
+        table[exp[i] as usize] = i as u8;
+        i += 1;
+    }
+    table
+}
+
+/// Multiply two field elements
+// Two logarithms sum to at most 508, inside the doubled table
+#[allow(clippy::indexing_slicing)]
+fn mul(a: u8, b: u8) -> u8 {
+    if a == 0 || b == 0 {
+        return 0;
+    }
+    EXP[usize::from(LOG[usize::from(a)]) + usize::from(LOG[usize::from(b)])]
+}
+
+/// Divide one field element by a nonzero other
+// The exponent is at most 254 + 255, inside the doubled table
+#[allow(clippy::indexing_slicing)]
+fn div(a: u8, b: u8) -> u8 {
+    if a == 0 {
+        return 0;
+    }
+    EXP[usize::from(LOG[usize::from(a)]) + 255 - usize::from(LOG[usize::from(b)])]
+}
+
+/// α raised to a power
+// The exponent is reduced below 255
+#[allow(clippy::indexing_slicing)]
+fn alpha_pow(power: usize) -> u8 {
+    EXP[power % 255]
+}
+
+/// The generator polynomial `(x - α^0)..(x - α^(parity - 1))`, highest degree first
+fn generator(parity: usize) -> Vec<u8> {
+    let mut generator = vec![1];
+    for i in 0..parity {
+        let root = alpha_pow(i);
+        let mut product = generator.clone();
+        product.push(0);
+        for (j, coefficient) in generator.iter().enumerate() {
+            if let Some(term) = product.get_mut(j + 1) {
+                *term ^= mul(*coefficient, root);
+            }
+        }
+        generator = product;
+    }
+    generator
+}
+
+/// Remainder of `block * x^parity` divided by the generator, the block's parity
+fn remainder(block: &[u8], generator: &[u8]) -> Vec<u8> {
+    let parity = generator.len().saturating_sub(1);
+    let mut remainder = vec![0; parity];
+    for &byte in block {
+        let feedback = byte ^ remainder.first().copied().unwrap_or_default();
+        remainder.rotate_left(1);
+        if let Some(last) = remainder.last_mut() {
+            *last = 0;
+        }
+        for (term, &coefficient) in remainder.iter_mut().zip(generator.iter().skip(1)) {
+            *term ^= mul(coefficient, feedback);
+        }
+    }
+    remainder
+}
+
+/// Evaluate a polynomial, lowest degree first, at a point
+fn eval_low_first(poly: &[u8], x: u8) -> u8 {
+    poly.iter()
+        .rev()
+        .fold(0, |value, &coefficient| mul(value, x) ^ coefficient)
+}
+
+/// The block's syndromes, its value at each generator root
+fn block_syndromes(block: &[u8], parity: usize) -> Vec<u8> {
+    (0..parity)
+        .map(|i| {
+            let root = alpha_pow(i);
+            block.iter().fold(0, |value, &byte| mul(value, root) ^ byte)
+        })
+        .collect()
+}
+
+/// Repair a block of data followed by `parity` parity bytes in place
+///
+/// Errors are located with Berlekamp-Massey and a Chien search over the
+/// block's positions, and their values found with Forney's algorithm.
+///
+/// # Returns
+/// * The number of bytes repaired, or `None` if the errors are beyond repair
+// Syndrome indices stay below `parity`, the syndromes' length, and error
+// positions come from the search over the block's own indices
+#[allow(clippy::indexing_slicing)]
+fn correct(block: &mut [u8], parity: usize) -> Option<usize> {
+    let syndromes = block_syndromes(block, parity);
+    if syndromes.iter().all(|&s| s == 0) {
+        return Some(0);
+    }
+
+    // Berlekamp-Massey: the shortest error locator generating the syndromes
+    let mut locator = vec![1u8];
+    let mut previous = vec![1u8];
+    let mut errors = 0;
+    let mut shift = 1;
+    let mut previous_discrepancy = 1;
+    for step in 0..parity {
+        let mut discrepancy = syndromes[step];
+        for i in 1..=errors.min(locator.len() - 1) {
+            discrepancy ^= mul(locator[i], syndromes[step - i]);
+        }
+        if discrepancy == 0 {
+            shift += 1;
+            continue;
+        }
+
+        let scale = div(discrepancy, previous_discrepancy);
+        let mut updated = locator.clone();
+        updated.resize(updated.len().max(previous.len() + shift), 0);
+        for (i, &coefficient) in previous.iter().enumerate() {
+            updated[i + shift] ^= mul(coefficient, scale);
+        }
+        if 2 * errors <= step {
+            previous = std::mem::replace(&mut locator, updated);
+            errors = step + 1 - errors;
+            previous_discrepancy = discrepancy;
+            shift = 1;
+        } else {
+            locator = updated;
+            shift += 1;
+        }
+    }
+    if 2 * errors > parity {
+        return None;
+    }
+
+    // Chien search: byte `index` is the coefficient of x^(len - 1 - index)
+    let len = block.len();
+    let positions: Vec<usize> = (0..len)
+        .filter(|&index| eval_low_first(&locator, alpha_pow(255 - (len - 1 - index) % 255)) == 0)
+        .collect();
+    if positions.len() != errors {
+        return None;
+    }
+
+    // Forney, for generator roots starting at α^0: e = X Ω(X^-1) / Λ'(X^-1)
+    let mut evaluator = vec![0u8; parity];
+    for (i, &syndrome) in syndromes.iter().enumerate() {
+        for (j, &coefficient) in locator.iter().enumerate().take(parity - i) {
+            evaluator[i + j] ^= mul(syndrome, coefficient);
+        }
+    }
+    let derivative: Vec<u8> = locator
+        .iter()
+        .enumerate()
+        .skip(1)
+        .map(|(i, &coefficient)| if i % 2 == 1 { coefficient } else { 0 })
+        .collect();
+    for &index in &positions {
+        let power = (len - 1 - index) % 255;
+        let x = alpha_pow(power);
+        let x_inverse = alpha_pow(255 - power);
+        let denominator = eval_low_first(&derivative, x_inverse);
+        if denominator == 0 {
+            return None;
+        }
+        block[index] ^= div(mul(x, eval_low_first(&evaluator, x_inverse)), denominator);
+    }
+
+    if block_syndromes(block, parity).iter().any(|&s| s != 0) {
+        return None;
+    }
+    Some(positions.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 37 + 11) as u8).collect()
+    }
+
+    #[test]
+    fn test_round_trip_across_block_boundaries() {
+        let params = EccParams {
+            data_shards: 20,
+            parity_shards: 6,
+        };
+        for len in [0, 1, 19, 20, 21, 100] {
+            let data = sample(len);
+            let encoded = encode(&data, params).unwrap();
+            assert_eq!(encoded.len(), encoded_length(len, params));
+            assert_eq!(decode(&encoded).unwrap(), data);
+        }
+        assert_eq!(
+            decode(&encode(&sample(500), EccParams::default()).unwrap()).unwrap(),
+            sample(500)
+        );
+    }
+
+    #[test]
+    fn test_corrects_up_to_half_the_parity_per_block() {
+        let params = EccParams {
+            data_shards: 30,
+            parity_shards: 8,
+        };
+        let data = sample(75);
+        let encoded = encode(&data, params).unwrap();
+
+        // Four errors in every block, the counts included: two there
+        let mut corrupted = encoded.clone();
+        for index in [0, 3, 6, 7, 20, 43, 44, 50, 60, 70, 90, 100, 101, 102] {
+            corrupted[index] ^= 0x5a;
+        }
+        assert_eq!(decode(&corrupted).unwrap(), data);
+
+        // A fifth error in the second block is one too many
+        corrupted[80] ^= 0xff;
+        assert!(matches!(
+            decode(&corrupted),
+            Err(HideError::UncorrectableErrors { block: 1 })
+        ));
+    }
+
+    #[test]
+    fn test_parity_errors_are_corrected_too() {
+        let params = EccParams {
+            data_shards: 10,
+            parity_shards: 4,
+        };
+        let data = sample(10);
+        let mut encoded = encode(&data, params).unwrap();
+        let last = encoded.len() - 1;
+        encoded[last] ^= 1;
+        encoded[PARAMS_SIZE] ^= 0x80;
+        assert_eq!(decode(&encoded).unwrap(), data);
+    }
+
+    #[test]
+    fn test_max_data_length_inverts_encoded_length() {
+        let params = EccParams {
+            data_shards: 16,
+            parity_shards: 4,
+        };
+        for capacity in 0..200 {
+            let max = max_data_length(capacity, params);
+            if capacity >= PARAMS_SIZE {
+                assert!(encoded_length(max, params) <= capacity);
+            }
+            assert!(encoded_length(max + 1, params) > capacity);
+        }
+    }
+
+    #[test]
+    fn test_invalid_params_are_rejected() {
+        for (data_shards, parity_shards) in [(0, 4), (10, 1), (250, 6)] {
+            let params = EccParams {
+                data_shards,
+                parity_shards,
+            };
+            assert!(encode(b"data", params).is_err());
+        }
+        assert!(decode(&[1, 2, 3]).is_err());
+    }
+
+    #[test]
+    fn test_params_parse_and_display() {
+        let params: EccParams = " 100:20 ".parse().unwrap();
+        assert_eq!(
+            params,
+            EccParams {
+                data_shards: 100,
+                parity_shards: 20,
+            }
+        );
+        assert_eq!(params.to_string(), "100:20");
+        assert_eq!(params.correctable_errors(), 10);
+        for invalid in ["100", "100:", "x:20", "300:20", "240:20"] {
+            assert!(invalid.parse::<EccParams>().is_err(), "{}", invalid);
+        }
+
+        assert_eq!(
+            "Hamming74".parse::<ErrorCorrection>().unwrap(),
+            ErrorCorrection::Hamming74
+        );
+        assert_eq!(
+            "100:20".parse::<ErrorCorrection>().unwrap(),
+            ErrorCorrection::ReedSolomon(params)
+        );
+        assert_eq!(ErrorCorrection::Hamming74.to_string(), "hamming74");
+        assert!("hamming84".parse::<ErrorCorrection>().is_err());
+    }
+
+    #[test]
+    fn test_hamming_codewords_repair_any_single_flipped_bit() {
+        for nibble in 0..16 {
+            let codeword = hamming_codeword(nibble);
+            assert_eq!(hamming_nibble(codeword), (nibble, false));
+            for position in 0..7 {
+                assert_eq!(hamming_nibble(codeword ^ (1 << position)), (nibble, true));
+            }
+        }
+    }
+
+    #[test]
+    fn test_hamming_round_trip_with_a_flipped_bit_per_codeword() {
+        for len in [0, 1, 2, 7, 100] {
+            let data = sample(len);
+            let encoded = encode(&data, ErrorCorrection::Hamming74).unwrap();
+            assert_eq!(
+                encoded.len(),
+                encoded_length(len, ErrorCorrection::Hamming74)
+            );
+            assert_eq!(decode(&encoded).unwrap(), data);
+
+            let mut bits = BitVec::<u8, Msb0>::from_slice(&encoded);
+            for codeword in 0..len * 2 {
+                let bit = PARAMS_SIZE * 8 + codeword * 7 + codeword % 7;
+                let flipped = !bits[bit];
+                bits.set(bit, flipped);
+            }
+            assert_eq!(decode(&bits.into_vec()).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_hamming_capacity_is_four_sevenths() {
+        assert_eq!(
+            max_data_length(PARAMS_SIZE + 700, ErrorCorrection::Hamming74),
+            400
+        );
+        for capacity in 0..100 {
+            let max = max_data_length(capacity, ErrorCorrection::Hamming74);
+            if capacity >= PARAMS_SIZE {
+                assert!(encoded_length(max, ErrorCorrection::Hamming74) <= capacity);
+            }
+            assert!(encoded_length(max + 1, ErrorCorrection::Hamming74) > capacity);
+        }
+    }
+}
@@ -1,25 +1,379 @@
 //! Encoding functionality for steganography
 
-use crate::bltm::BLTM3x3;
+use crate::bltm::{BLTM3x3, BLTM4x4};
+use crate::cancel::{self, CancellationToken};
+use crate::compression;
+use crate::container;
+use crate::crypto::{self, CipherSuite};
+use crate::decoder::{Decoder, DecoderConfig};
+use crate::depth::{self, EmbeddingDepth};
+use crate::ecc::{self, ErrorCorrection};
 use crate::error::HideError;
-use crate::img::StegoImage;
-use crate::utils;
+use crate::header::{
+    put_u16_be, put_u32_be, ExtraFlags, HeaderFlags, MessageHeader, CHECKSUM_SIZE, DIMENSIONS_SIZE,
+    EXTENDED_HEADER_SIZE, HEADER_SIZE, PADDING_TRAILER_SIZE,
+};
+use crate::img::{self, FillStyle, PixelSurface, StegoImage};
+use crate::layout::{BitOffset, ByteOffset, PixelIndex};
+use crate::manifest::{self, Manifest};
+use crate::mask::{self, PixelMask, DEFAULT_MASK_THRESHOLD};
+use crate::padding::{self, PaddingPolicy};
+use crate::progress::{self, ProgressCallback};
+use crate::rgba::{self, KeepAlpha};
+use crate::rng::{self, Key, SharedKdf, SharedRng};
+use crate::scatter;
+use crate::schedule::{self, ChannelOrder, ChannelSchedule};
+use crate::utils::{self, Timing};
+use crate::warning::StegoWarning;
+use crate::watermark;
 use crate::Result;
 use bitvec::prelude::*;
+use image::DynamicImage;
+use serde::{Deserialize, Serialize, Serializer};
+use std::borrow::Cow;
+use std::fmt;
+use std::fs;
 use std::path::Path;
+use std::time::Instant;
 
-/// Message format version
-const MESSAGE_FORMAT_VERSION: u8 = 1;
+/// Secret material (keys, passphrases) that is never printed or serialized
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Secret(String);
 
-/// Header size in bytes
-const HEADER_SIZE: usize = 8;
+impl Secret {
+    /// Wrap a secret value
+    pub fn new(value: impl Into<String>) -> Self {
+        Self(value.into())
+    }
+
+    /// Access the secret value
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret([redacted])")
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str("[redacted]")
+    }
+}
+
+/// Options controlling how messages are encoded
+///
+/// Configs are `Send` and `Sync`. Options carrying state, such as the
+/// generator and the cancellation token, are shared handles behind `Arc`, and
+/// new ones must be too.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EncoderConfig {
+    /// Write a `<output>.hide.json` provenance manifest next to files written by `encode_file`
+    pub emit_manifest: bool,
+
+    /// Passphrase for keyed embedding modes (never written to manifests)
+    #[serde(skip_serializing)]
+    pub passphrase: Option<Secret>,
+
+    /// Encode into images that already hold a payload, scrubbing the old one first
+    pub overwrite_existing: bool,
+
+    /// Largest fraction (0.0-1.0) of the cover's pixels the payload may touch
+    pub max_changed_pixels: Option<f64>,
+
+    /// Embed the payload in the alpha channel too, four bits per pixel (see [`crate::rgba`])
+    pub embed_alpha: bool,
+
+    /// With `embed_alpha`, leave pixels whose alpha is 0 untouched
+    pub skip_transparent: bool,
+
+    /// How payload bits are assigned to color channels (see [`crate::schedule`])
+    pub channel_schedule: ChannelSchedule,
+
+    /// Pixels the payload must leave untouched (see [`crate::mask`])
+    #[serde(skip)]
+    pub pixel_mask: Option<PixelMask>,
+
+    /// With `pixel_mask`, embed a copy of the mask when it is small enough, so
+    /// decoders don't need it
+    pub embed_mask: bool,
+
+    /// Token to abort a long encode; a cancelled encode returns no image
+    #[serde(skip)]
+    pub cancel: Option<CancellationToken>,
+
+    /// Source of random bytes (stream IDs); the system generator when unset
+    #[serde(skip)]
+    pub rng: Option<SharedRng>,
+
+    /// Told how many pixels of each embedding are done
+    #[serde(skip)]
+    pub progress: Option<ProgressCallback>,
+
+    /// Key derivation for keyed modes, such as the keyed channel schedule
+    #[serde(skip)]
+    pub kdf: Option<SharedKdf>,
+
+    /// Decode each stego image before returning it and fail with
+    /// [`HideError::SelfCheckFailed`] if it doesn't give back the payload
+    pub verify_roundtrip: bool,
+
+    /// Start the payload with the cover's width and height
+    /// ([`ExtraFlags::RECORDS_DIMENSIONS`]),
+    /// so failed decodes of resized copies can report the original size;
+    /// `encode_in_place` and `append` never record them
+    pub record_dimensions: bool,
+
+    /// Pad payloads with random bytes (from `rng`) so the header's length
+    /// doesn't reveal the message's ([`ExtraFlags::PADDED`], which also records
+    /// the cover dimensions; see [`crate::padding`])
+    pub padding: Option<PaddingPolicy>,
+
+    /// Create missing parent directories of the output path in `encode_file`
+    pub create_dirs: bool,
+
+    /// Grow covers too small for the payload to the smallest size that fits,
+    /// filling the added area in this style; the payload records the cover's
+    /// size before it grew ([`ExtraFlags::RECORDS_DIMENSIONS`])
+    pub auto_extend: Option<FillStyle>,
+
+    /// Dither the cover's color LSBs with this strength before embedding, to
+    /// make flat covers such as screenshots less conspicuous (see
+    /// [`img::dither`]); nothing about it is recorded in the payload
+    pub pre_dither: Option<f32>,
+
+    /// Visit the pixels in an order shuffled by this key instead of
+    /// row-major, so the payload can't be located without it (see
+    /// [`crate::scatter`]); decoders need the same key
+    #[serde(skip)]
+    pub pixel_key: Option<Key>,
+
+    /// Cipher `encode_encrypted` seals messages with; it is recorded in the
+    /// payload, so decoders don't need to be told
+    pub cipher: CipherSuite,
+
+    /// Pixel density, in dots per inch, to give stego images instead of the
+    /// cover's; PNG saves write it as a `pHYs` chunk
+    pub dpi: Option<(u32, u32)>,
+
+    /// Deflate messages before embedding when that makes them smaller (see
+    /// [`crate::compression`]); encrypted messages are never compressed
+    pub compress: bool,
+
+    /// End the payload with a CRC-32 of everything after the header, which
+    /// decoders verify before returning the message
+    pub checksum: bool,
+
+    /// Protect the payload with Reed-Solomon or Hamming(7,4) parity (see
+    /// [`crate::ecc`]), so decoders repair corrupted bytes before checking
+    /// the checksum; can't be combined with padding
+    pub error_correction: Option<ErrorCorrection>,
+
+    /// How many of each channel's lowest bits the payload after the header
+    /// takes (see [`crate::depth`]); two bits double the capacity
+    pub embedding_depth: EmbeddingDepth,
+}
+
+impl EncoderConfig {
+    /// Leave the pixels a mask image marks untouched
+    ///
+    /// Pixels whose mask luminance is above [`DEFAULT_MASK_THRESHOLD`] are
+    /// excluded; set `pixel_mask` from [`PixelMask::from_image`] for another
+    /// threshold.
+    ///
+    /// # Arguments
+    /// * `mask` - Image the size of the cover; white pixels are left untouched
+    pub fn mask(self, mask: &StegoImage) -> Self {
+        Self {
+            pixel_mask: Some(PixelMask::from_image(mask, DEFAULT_MASK_THRESHOLD)),
+            ..self
+        }
+    }
+
+    /// Check that each stego image decodes before returning it
+    ///
+    /// The check costs one decode of the payload's pixels.
+    pub fn verify_roundtrip(self, verify: bool) -> Self {
+        Self {
+            verify_roundtrip: verify,
+            ..self
+        }
+    }
+
+    /// Pad payloads so the header only reveals the padded size
+    ///
+    /// # Arguments
+    /// * `policy` - How far to pad each payload
+    pub fn pad_to(self, policy: PaddingPolicy) -> Self {
+        Self {
+            padding: Some(policy),
+            ..self
+        }
+    }
+
+    /// Report embedding progress to a callback
+    ///
+    /// # Arguments
+    /// * `callback` - Called with the pixels done and the pixels in total
+    pub fn on_progress(self, callback: impl Fn(u64, u64) + Send + Sync + 'static) -> Self {
+        Self {
+            progress: Some(ProgressCallback::new(callback)),
+            ..self
+        }
+    }
+
+    /// Grow covers that are too small instead of failing with [`HideError::MessageTooLarge`]
+    ///
+    /// See [`img::extend_canvas`] for how the cover grows. Can't be combined
+    /// with a pixel mask, which must match the cover's size.
+    ///
+    /// # Arguments
+    /// * `fill` - How to fill the area added to the cover
+    pub fn auto_extend(self, fill: FillStyle) -> Self {
+        Self {
+            auto_extend: Some(fill),
+            ..self
+        }
+    }
+
+    /// Dither the LSBs of each cover before embedding
+    ///
+    /// The seed comes from `rng`. Can't be combined with a pixel mask, whose
+    /// excluded pixels must stay untouched.
+    ///
+    /// # Arguments
+    /// * `strength` - Share of the color LSBs replaced with noise, from 0 to 1
+    pub fn pre_dither(self, strength: f32) -> Self {
+        Self {
+            pre_dither: Some(strength),
+            ..self
+        }
+    }
+
+    /// Embed in a pixel order shuffled by a key
+    ///
+    /// Can't be combined with a pixel mask, and views, appends and
+    /// watermarks don't support it.
+    ///
+    /// # Arguments
+    /// * `key` - The key, which decoders need too
+    pub fn pixel_key(self, key: &[u8]) -> Self {
+        Self {
+            pixel_key: Some(scatter::derive_key(key)),
+            ..self
+        }
+    }
+
+    /// Seal encrypted messages with another cipher suite
+    ///
+    /// # Arguments
+    /// * `cipher` - The suite `encode_encrypted` uses
+    pub fn cipher(self, cipher: CipherSuite) -> Self {
+        Self { cipher, ..self }
+    }
+
+    /// Give stego images a pixel density
+    ///
+    /// # Arguments
+    /// * `x` - Horizontal density in dots per inch
+    /// * `y` - Vertical density in dots per inch
+    pub fn dpi(self, x: u32, y: u32) -> Self {
+        Self {
+            dpi: Some((x, y)),
+            ..self
+        }
+    }
+
+    /// Deflate messages before embedding them
+    ///
+    /// Messages that don't get smaller are embedded as they are.
+    pub fn compress(self, compress: bool) -> Self {
+        Self { compress, ..self }
+    }
+
+    /// End payloads with a CRC-32 that decoders verify
+    pub fn checksum(self, checksum: bool) -> Self {
+        Self { checksum, ..self }
+    }
+
+    /// Protect payloads with error correction parity
+    ///
+    /// # Arguments
+    /// * `correction` - The code, or the data and parity bytes per Reed-Solomon block
+    pub fn error_correction(self, correction: impl Into<ErrorCorrection>) -> Self {
+        Self {
+            error_correction: Some(correction.into()),
+            ..self
+        }
+    }
+
+    /// Embed the payload after the header in this many of each channel's lowest bits
+    pub fn embedding_depth(self, embedding_depth: EmbeddingDepth) -> Self {
+        Self {
+            embedding_depth,
+            ..self
+        }
+    }
+}
+
+/// Statistics about a completed encode
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EncodeStats {
+    /// Length of the embedded message in bytes
+    pub message_bytes: usize,
+
+    /// Bytes the message took in the image, fewer than `message_bytes` when
+    /// it was compressed
+    #[serde(default)]
+    pub stored_message_bytes: usize,
+
+    /// Length of the header in bytes
+    pub header_bytes: usize,
+
+    /// Random padding and length trailer added to hide the message length
+    #[serde(default)]
+    pub padding_bytes: usize,
+
+    /// Length of the CRC-32 ending the payload, if there is one
+    #[serde(default)]
+    pub checksum_bytes: usize,
+
+    /// Error correction parity and the code's parameters ahead of it, if the payload has any
+    #[serde(default)]
+    pub error_correction_bytes: usize,
+
+    /// Number of pixels that carry payload bits
+    pub pixels_used: usize,
+
+    /// Number of color channel values that were changed
+    pub channels_modified: usize,
+
+    /// Time spent embedding, not recorded in manifests
+    #[serde(skip)]
+    pub timing: Timing,
+
+    /// Things the encode noticed that didn't stop it, not recorded in manifests
+    #[serde(skip)]
+    pub warnings: Vec<StegoWarning>,
+}
 
 /// Encodes a message into an image using the BLTM steganography method
+///
+/// Encoders are `Send` and `Sync`: one encoder can be shared between threads,
+/// e.g. in an `Arc`, and used for concurrent encodes.
 pub struct Encoder {
     /// The BLTM used for encoding
     bltm: BLTM3x3,
+    /// Encoding options
+    config: EncoderConfig,
 }
 
+static_assertions::assert_impl_all!(EncoderConfig: Send, Sync);
+static_assertions::assert_impl_all!(Encoder: Send, Sync);
+
 impl Default for Encoder {
     fn default() -> Self {
         Self::new()
@@ -29,11 +383,32 @@ impl Default for Encoder {
 impl Encoder {
     /// Create a new encoder with a 3x3 BLTM
     pub fn new() -> Self {
+        Self::with_config(EncoderConfig::default())
+    }
+
+    /// Create a new encoder with the given options
+    pub fn with_config(config: EncoderConfig) -> Self {
         Self {
             bltm: BLTM3x3::new(),
+            config,
         }
     }
 
+    /// Create a new encoder that embeds in the pixel order of a key
+    ///
+    /// See [`EncoderConfig::pixel_key`].
+    ///
+    /// # Arguments
+    /// * `key` - The key, which decoders need too
+    pub fn with_key(key: &[u8]) -> Self {
+        Self::with_config(EncoderConfig::default().pixel_key(key))
+    }
+
+    /// Get the options used by this encoder
+    pub fn config(&self) -> &EncoderConfig {
+        &self.config
+    }
+
     /// Encode k bits of message into an RGB pixel using the BLTM algorithm
     ///
     /// # Arguments
@@ -43,14 +418,22 @@ impl Encoder {
     /// * `message_bits` - k bits of the message to encode (in our case, 3 bits)
     ///
     /// # Returns
-    /// * A tuple of the modified RGB values (r, g, b)
+    /// * A tuple of the modified RGB values (r, g, b), or `InvalidParameters`
+    ///   if `message_bits` is not exactly 3 bits
     pub fn encode_pixel(
         &self,
         r: u8,
         g: u8,
         b: u8,
         message_bits: &BitSlice<u8, Msb0>,
-    ) -> (u8, u8, u8) {
+    ) -> Result<(u8, u8, u8)> {
+        if message_bits.len() != 3 {
+            return Err(HideError::InvalidParameters(format!(
+                "A pixel holds 3 message bits, got {}",
+                message_bits.len()
+            )));
+        }
+
         // Step 5-6: Extract LSBs to form cover vector vc
         let mut cover_vector = BitVec::<u8, Msb0>::new();
         cover_vector.push(r & 1 != 0); // LSB of R
@@ -64,48 +447,85 @@ impl Encoder {
         let z = self.matrix_multiply(&cover_vector);
 
         // Step 10-11: Select message bits and compute δ = z ⊕ m
-        let mut delta = BitVec::<u8, Msb0>::new();
-        for i in 0..message_bits.len() {
-            delta.push(z[i] ^ message_bits[i]);
-        }
+        let delta: BitVec<u8, Msb0> = z
+            .iter()
+            .zip(message_bits.iter())
+            .map(|(z, m)| *z ^ *m)
+            .collect();
 
         // Step 12: Find Vn corresponding to δ
-        let vn = self.bltm.lookup_vn(&delta);
+        let vn = self.bltm.lookup_vn(&delta)?;
 
         // Step 13: Compute stego-vector vs = vc ⊕ Vn
-        let mut stego_vector = BitVec::<u8, Msb0>::new();
-        for i in 0..cover_vector.len() {
-            stego_vector.push(cover_vector[i] ^ vn[i]);
+        let stego_vector = cover_vector.iter().zip(vn.iter()).map(|(c, v)| *c ^ *v);
+
+        // Step 14: Replace the LSB of each component with the corresponding bit of vs
+        let mut channels = [r, g, b];
+        for (channel, bit) in channels.iter_mut().zip(stego_vector) {
+            if bit {
+                *channel |= 1; // Set LSB to 1
+            } else {
+                *channel &= !1; // Set LSB to 0
+            }
         }
 
-        // Step 14: Modify RGB components based on vs
-        let mut new_r = r;
-        let mut new_g = g;
-        let mut new_b = b;
-
-        // Replace LSB of each component with corresponding bit from stego_vector
-        if stego_vector[0] {
-            new_r |= 1; // Set LSB to 1
-        } else {
-            new_r &= !1; // Set LSB to 0
-        }
+        Ok((channels[0], channels[1], channels[2]))
+    }
 
-        if stego_vector[1] {
-            new_g |= 1; // Set LSB to 1
-        } else {
-            new_g &= !1; // Set LSB to 0
+    /// Encode 4 bits of message into an RGBA pixel using a 4x4 BLTM
+    ///
+    /// An alpha of 1 whose LSB must flip becomes 2 rather than 0, so a
+    /// visible pixel never turns fully transparent.
+    ///
+    /// # Arguments
+    /// * `r` - Red component value (0-255)
+    /// * `g` - Green component value (0-255)
+    /// * `b` - Blue component value (0-255)
+    /// * `a` - Alpha component value (0-255)
+    /// * `message_bits` - 4 bits of the message to encode
+    ///
+    /// # Returns
+    /// * A tuple of the modified RGBA values (r, g, b, a), or `InvalidParameters`
+    ///   if `message_bits` is not exactly 4 bits
+    pub fn encode_pixel_rgba(
+        &self,
+        r: u8,
+        g: u8,
+        b: u8,
+        a: u8,
+        message_bits: &BitSlice<u8, Msb0>,
+    ) -> Result<(u8, u8, u8, u8)> {
+        if message_bits.len() != 4 {
+            return Err(HideError::InvalidParameters(format!(
+                "An RGBA pixel holds 4 message bits, got {}",
+                message_bits.len()
+            )));
         }
 
-        if stego_vector[2] {
-            new_b |= 1; // Set LSB to 1
-        } else {
-            new_b &= !1; // Set LSB to 0
+        // δ = (A × vc) ⊕ m, then Vn solves A × Vn = δ
+        let bltm = BLTM4x4::new();
+        let channels = [r, g, b, a];
+        let mut delta = bltm.multiply(channels.map(|channel| channel & 1 != 0));
+        for (bit, message_bit) in delta.iter_mut().zip(message_bits.iter().by_vals()) {
+            *bit ^= message_bit;
         }
-
-        (new_r, new_g, new_b)
+        let vn = bltm.solve(delta);
+
+        // Flip the LSBs selected by Vn
+        let [r, g, b, a] = channels;
+        let [flip_r, flip_g, flip_b, flip_a] = vn;
+        let flip = |channel: u8, selected: bool| if selected { channel ^ 1 } else { channel };
+        let a = match (a, flip_a) {
+            (1, true) => 2,
+            _ => flip(a, flip_a),
+        };
+
+        Ok((flip(r, flip_r), flip(g, flip_g), flip(b, flip_b), a))
     }
 
     /// Matrix-vector multiplication: A × v
+    // Indices stay below 3: the matrix is 3x3 and `v` always holds one pixel's bits
+    #[allow(clippy::needless_range_loop, clippy::indexing_slicing)]
     fn matrix_multiply(&self, v: &BitSlice<u8, Msb0>) -> BitVec<u8, Msb0> {
         let columns = self.bltm.columns();
         let mut result = BitVec::<u8, Msb0>::new();
@@ -131,6 +551,9 @@ impl Encoder {
 
     /// Encode an entire message into an image
     ///
+    /// Fails with [`HideError::ExistingPayload`] if the cover already holds a
+    /// payload, unless `overwrite_existing` is set.
+    ///
     /// # Arguments
     /// * `cover_image` - The original image to embed the message into
     /// * `message` - The message bytes to embed
@@ -138,47 +561,901 @@ impl Encoder {
     /// # Returns
     /// * The stego image with the embedded message
     pub fn encode(&self, cover_image: StegoImage, message: &[u8]) -> Result<StegoImage> {
-        // Calculate the maximum message size this image can hold
-        let max_message_size = self.max_message_size(&cover_image);
+        let (stego_image, _) = self.encode_with_stats(cover_image, message)?;
+        Ok(stego_image)
+    }
+
+    /// Encode an entire message into an image and report what changed
+    ///
+    /// # Arguments
+    /// * `cover_image` - The original image to embed the message into
+    /// * `message` - The message bytes to embed
+    ///
+    /// # Returns
+    /// * The stego image with the embedded message and statistics about the encode
+    pub fn encode_with_stats(
+        &self,
+        cover_image: StegoImage,
+        message: &[u8],
+    ) -> Result<(StegoImage, EncodeStats)> {
+        self.encode_payload(cover_image, message, HeaderFlags::empty())
+    }
+
+    /// Encrypt a message under a passphrase and encode it into an image
+    ///
+    /// The message is sealed with the configured `cipher` under a key derived
+    /// from the passphrase, with the configured `kdf` or PBKDF2 when none is
+    /// set, and the header is flagged so plain decodes report the payload as
+    /// encrypted. The suite ID, salt and nonce are embedded with the
+    /// ciphertext, the salt and nonce coming from `rng`; see [`crate::crypto`].
+    ///
+    /// # Arguments
+    /// * `cover_image` - The original image to embed the message into
+    /// * `message` - The message bytes to encrypt and embed
+    /// * `passphrase` - The passphrase the key is derived from
+    ///
+    /// # Returns
+    /// * The stego image with the encrypted message
+    pub fn encode_encrypted(
+        &self,
+        cover_image: StegoImage,
+        message: &[u8],
+        passphrase: &Secret,
+    ) -> Result<StegoImage> {
+        let payload = crypto::encrypt(
+            message,
+            passphrase,
+            self.config.cipher,
+            self.config.kdf.as_ref(),
+            self.config.rng.as_ref(),
+        )?;
+        let (stego_image, _) =
+            self.encode_payload(cover_image, &payload, HeaderFlags::ENCRYPTED)?;
+        Ok(stego_image)
+    }
+
+    /// Encode several named entries into an image as a container payload
+    ///
+    /// # Arguments
+    /// * `cover_image` - The original image to embed the entries into
+    /// * `entries` - Names and contents of the entries; names must be unique
+    ///
+    /// # Returns
+    /// * The stego image with the embedded container
+    pub fn encode_container(
+        &self,
+        cover_image: StegoImage,
+        entries: &[(String, Vec<u8>)],
+    ) -> Result<StegoImage> {
+        let payload = container::encode_entries(entries)?;
+        let (stego_image, _) =
+            self.encode_payload(cover_image, &payload, HeaderFlags::CONTAINER)?;
+        Ok(stego_image)
+    }
+
+    /// Tile a short identifier across every pixel of an image
+    ///
+    /// Unlike a message, the watermark has no header; it is repeated so that
+    /// crops of the image still carry intact copies. See [`crate::watermark`].
+    ///
+    /// # Arguments
+    /// * `cover_image` - The image to watermark
+    /// * `id` - The identifier, at most [`watermark::MAX_WATERMARK_ID_LEN`] bytes
+    ///
+    /// # Returns
+    /// * The watermarked image
+    pub fn encode_watermark(&self, mut cover_image: StegoImage, id: &[u8]) -> Result<StegoImage> {
+        if self.config.pixel_key.is_some() {
+            return Err(HideError::InvalidParameters(
+                "Watermarks are tiled in row-major order and can't use a pixel key".to_string(),
+            ));
+        }
+        let frame = watermark::encode_frame(id)?;
+
+        // At least one whole copy must fit
+        let capacity_bits = img::capacity_bits(cover_image.width(), cover_image.height());
+        if capacity_bits < (frame.len() * 8) as u64 {
+            return Err(HideError::MessageTooLarge);
+        }
+
+        self.prepare_cover(&mut cover_image, None)?;
+
+        let total_bytes = usize::try_from(capacity_bits / 8).unwrap_or(usize::MAX);
+        let tiled: Vec<u8> = frame.iter().copied().cycle().take(total_bytes).collect();
+        self.embed_into(&mut cover_image, &tiled, &ChannelOrder::fixed(), None)?;
+        Ok(cover_image)
+    }
+
+    /// Encode a message directly into an image or a view of one
+    ///
+    /// Capacity is based on the target's own dimensions, so a view holds the
+    /// header and message entirely inside its window and leaves the rest of
+    /// the image untouched. The target may be partially modified on error.
+    ///
+    /// # Arguments
+    /// * `target` - The image or view to embed the message into
+    /// * `message` - The message bytes to embed
+    ///
+    /// # Returns
+    /// * Statistics about the encode
+    pub fn encode_in_place<S: PixelSurface + ?Sized>(
+        &self,
+        target: &mut S,
+        message: &[u8],
+    ) -> Result<EncodeStats> {
+        if self.config.embed_alpha {
+            return Err(HideError::InvalidParameters(
+                "RGBA embedding needs a whole image, not a view".to_string(),
+            ));
+        }
+        if self.config.padding.is_some() {
+            return Err(HideError::InvalidParameters(
+                "Padding needs a whole image, not a view".to_string(),
+            ));
+        }
+        if self.config.pixel_key.is_some() {
+            return Err(HideError::InvalidParameters(
+                "A pixel key needs a whole image, not a view".to_string(),
+            ));
+        }
+        if self.config.checksum {
+            return Err(HideError::InvalidParameters(
+                "Checksums need a whole image, not a view".to_string(),
+            ));
+        }
+        if self.config.error_correction.is_some() {
+            return Err(HideError::InvalidParameters(
+                "Error correction needs a whole image, not a view".to_string(),
+            ));
+        }
+        self.encode_payload_into(target, message, HeaderFlags::empty(), ExtraFlags::empty())
+    }
+
+    /// Append bytes to the message already hidden in an image
+    ///
+    /// Only the pixels carrying the header and the new bytes are rewritten.
+    /// The new bytes are written before the header's length field, so an
+    /// append that fails part way still leaves the original message readable.
+    ///
+    /// # Arguments
+    /// * `stego` - The image or view holding the message
+    /// * `extra` - The bytes to add after the current message
+    pub fn append<S: PixelSurface + ?Sized>(&self, stego: &mut S, extra: &[u8]) -> Result<()> {
+        if self.config.pixel_key.is_some() {
+            return Err(HideError::InvalidParameters(
+                "Can't append to a payload embedded with a pixel key".to_string(),
+            ));
+        }
+        let header = Decoder::new()
+            .detect(stego)
+            .ok_or(HideError::NoMessageFound)?;
+        if header.is_container()
+            || header.is_stream_frame()
+            || header.is_rgba()
+            || header.is_masked()
+            || header.is_padded()
+            || !(header.extra_flags - ExtraFlags::TWO_BIT_DEPTH).is_empty()
+            || header.channel_schedule()? != ChannelSchedule::Fixed
+        {
+            return Err(HideError::InvalidParameters(
+                "Can only append to a plain message payload".to_string(),
+            ));
+        }
+
+        let depth = header.embedding_depth();
+        let current_length = header.message_length as usize;
+        let new_length = current_length + extra.len();
+        let pixel_count = img::pixel_count(stego.width(), stego.height());
+        if new_length > depth::capacity_after_prefix(pixel_count, header.size(), depth) {
+            return Err(HideError::MessageTooLarge);
+        }
+        let new_length_u32 = u32::try_from(new_length).map_err(|_| HideError::MessageTooLarge)?;
+        self.check_change_budget(
+            stego.width(),
+            stego.height(),
+            depth::pixels_with_prefix(header.size(), new_length, depth) as usize,
+        )?;
+
+        // Tail first: until the header is rewritten the new bytes are ignored
+        let extra_bits = utils::bytes_to_bits(extra);
+        if depth == EmbeddingDepth::One {
+            let tail_start = ByteOffset((header.size() + current_length) as u64).first_bit();
+            self.write_bits_at(stego, PixelIndex(0), depth, tail_start, &extra_bits)?;
+        } else {
+            let tail_start = ByteOffset(current_length as u64).first_bit();
+            let first_pixel = PixelIndex(header.pixels());
+            self.write_bits_at(stego, first_pixel, depth, tail_start, &extra_bits)?;
+        }
+
+        let new_header = MessageHeader {
+            message_length: new_length_u32,
+            ..header
+        };
+        self.write_bits_at(
+            stego,
+            PixelIndex(0),
+            EmbeddingDepth::One,
+            BitOffset(0),
+            &utils::bytes_to_bits(&new_header.to_bytes()),
+        )
+    }
+
+    /// Overwrite payload bits from a bit offset onwards
+    ///
+    /// The bits are counted from `first_pixel`, each pixel from there on
+    /// holding as many as `depth` gives it, lowest bit plane first. Pixels
+    /// shared with neighbouring bits are decoded first, so bits outside the
+    /// written range keep their values.
+    fn write_bits_at<S: PixelSurface + ?Sized>(
+        &self,
+        image: &mut S,
+        first_pixel: PixelIndex,
+        depth: EmbeddingDepth,
+        start_bit: BitOffset,
+        bits: &BitSlice<u8, Msb0>,
+    ) -> Result<()> {
+        let decoder = Decoder::new();
+        let bits_per_pixel = depth.bits_per_pixel();
+        let written = start_bit.get()..start_bit.get() + bits.len() as u64;
+
+        for offset in written.start / bits_per_pixel..written.end.div_ceil(bits_per_pixel) {
+            let pixel_idx = first_pixel + offset;
+            cancel::check_every(self.config.cancel.as_ref(), pixel_idx.get() as usize)?;
+
+            let pixel = image.get_pixel_at(pixel_idx)?;
+            let mut new_pixel = pixel.0;
+            for plane in 0..depth.bits_per_channel() {
+                let [r, g, b] = depth::plane(new_pixel, plane);
+                let mut chunk = decoder.decode_pixel(r, g, b);
+                let plane_start = offset * bits_per_pixel + u64::from(plane) * img::BITS_PER_PIXEL;
+                for (index, bit) in (plane_start..plane_start + img::BITS_PER_PIXEL).enumerate() {
+                    if written.contains(&bit) {
+                        chunk.set(index, bits[(bit - written.start) as usize]);
+                    }
+                }
+
+                let (r, g, b) = self.encode_pixel(r, g, b, &chunk)?;
+                new_pixel = depth::with_plane(new_pixel, plane, [r, g, b]);
+            }
+            image.set_pixel_at(pixel_idx, image::Rgb(new_pixel))?;
+        }
+
+        Ok(())
+    }
+
+    /// Encode a payload with the given header flags
+    pub(crate) fn encode_payload(
+        &self,
+        cover_image: StegoImage,
+        message: &[u8],
+        flags: HeaderFlags,
+    ) -> Result<(StegoImage, EncodeStats)> {
+        if let Some(correction) = self.config.error_correction {
+            if self.config.padding.is_some() {
+                return Err(HideError::InvalidParameters(
+                    "Error correction can't be combined with padding".to_string(),
+                ));
+            }
+            correction.validate()?;
+        }
+        let records_dimensions = dimensions_bytes(&self.config) > 0;
+
+        // Ciphertext doesn't shrink, and compressing first would leak through its size
+        let compressed = if self.config.compress && !flags.contains(HeaderFlags::ENCRYPTED) {
+            compression::compress(message)?
+        } else {
+            None
+        };
+        let stored = compressed.as_deref().unwrap_or(message);
+
+        let mut extra_flags = ExtraFlags::empty();
+        extra_flags.set(ExtraFlags::COMPRESSED, compressed.is_some());
+        extra_flags.set(ExtraFlags::CHECKSUMMED, self.config.checksum);
+        extra_flags.set(
+            ExtraFlags::ERROR_CORRECTED,
+            self.config.error_correction.is_some(),
+        );
+        extra_flags.set(ExtraFlags::RECORDS_DIMENSIONS, records_dimensions);
+        extra_flags.set(ExtraFlags::PADDED, self.config.padding.is_some());
+        let checksum_bytes = checksum_bytes(&self.config);
+
+        let (width, height) = cover_image.dimensions();
+        let mut warnings = self.cover_warnings(&cover_image);
+        let mut cover_image = self.extend_to_fit(cover_image, stored.len(), flags)?;
+        // Extended covers are grown to just fit, so they are always near capacity
+        let max_message_bytes = self.max_payload_size(&cover_image, flags);
+        if self.config.auto_extend.is_none()
+            && !stored.is_empty()
+            && stored.len() as u64 * 10 >= max_message_bytes as u64 * 9
+        {
+            warnings.push(StegoWarning::NearCapacity {
+                message_bytes: stored.len(),
+                max_message_bytes,
+            });
+        }
+        self.dither_cover(&mut cover_image)?;
+        let pixel_order = self.scatter_cover(&mut cover_image)?;
+        let mut payload = if !records_dimensions {
+            Cow::Borrowed(stored)
+        } else {
+            let mut payload = Vec::with_capacity(DIMENSIONS_SIZE + stored.len());
+            put_dimensions(&mut payload, width, height)?;
+            payload.extend_from_slice(stored);
+            if let Some(policy) = self.config.padding {
+                // The checksum comes after the padding, inside the padded length
+                let padded_length = policy.padded_length(
+                    payload.len() + PADDING_TRAILER_SIZE + checksum_bytes,
+                    self.payload_capacity(&cover_image, flags),
+                )?;
+                padding::pad(
+                    &mut payload,
+                    stored,
+                    padded_length - checksum_bytes,
+                    self.config.rng.as_ref(),
+                )?;
+            }
+            Cow::Owned(payload)
+        };
+        if self.config.checksum {
+            let checksum = utils::crc32(&payload);
+            put_u32_be(payload.to_mut(), checksum);
+        }
+        let prefix = if records_dimensions {
+            DIMENSIONS_SIZE
+        } else {
+            0
+        };
+        let padding_bytes = payload.len() - stored.len() - prefix - checksum_bytes;
+        let unprotected_length = payload.len();
+        // Parity covers the checksum too, so repairs come before it is checked
+        if let Some(correction) = self.config.error_correction {
+            payload = Cow::Owned(ecc::encode(&payload, correction)?);
+        }
+
+        let mut stats = if self.config.embed_alpha {
+            self.encode_rgba_payload(&mut cover_image, &payload, flags, extra_flags)?
+        } else {
+            self.encode_payload_into(&mut cover_image, &payload, flags, extra_flags)?
+        };
+        if let Some(order) = &pixel_order {
+            img::permute_pixels(&mut cover_image, order, true);
+        }
+        stats.message_bytes = message.len();
+        stats.stored_message_bytes = stored.len();
+        stats.header_bytes += prefix;
+        stats.checksum_bytes = checksum_bytes;
+        stats.padding_bytes = padding_bytes;
+        stats.error_correction_bytes = payload.len() - unprotected_length;
+        stats.warnings = warnings;
+
+        if self.config.verify_roundtrip {
+            self.check_roundtrip(&cover_image, message, flags)?;
+        }
+        if let Some((x, y)) = self.config.dpi {
+            cover_image.set_dpi(x, y);
+        }
+        Ok((cover_image, stats))
+    }
+
+    /// Warn about what embedding will change in a cover besides its LSBs
+    ///
+    /// Payloads are embedded in 8-bit RGB pixels, or RGBA ones in RGBA mode,
+    /// so other covers are converted first.
+    fn cover_warnings(&self, cover_image: &StegoImage) -> Vec<StegoWarning> {
+        let color = cover_image.inner().color();
+        let mut warnings = Vec::new();
+        if color.has_alpha() && !self.config.embed_alpha {
+            warnings.push(StegoWarning::AlphaDropped);
+        }
+        let bits = color.bits_per_pixel() / u16::from(color.channel_count());
+        if bits > 8 {
+            warnings.push(StegoWarning::BitDepthReduced { bits: bits as u8 });
+        }
+        warnings
+    }
+
+    /// Grow a cover too small for a message, when `auto_extend` is set
+    ///
+    /// # Arguments
+    /// * `cover_image` - The cover the message is to be encoded in
+    /// * `message_length` - Length of the message in bytes
+    /// * `flags` - Header flags the payload is to be embedded with
+    ///
+    /// # Returns
+    /// * The cover, grown to the smallest size the payload fits if it didn't
+    fn extend_to_fit(
+        &self,
+        cover_image: StegoImage,
+        message_length: usize,
+        flags: HeaderFlags,
+    ) -> Result<StegoImage> {
+        let Some(fill) = self.config.auto_extend else {
+            return Ok(cover_image);
+        };
+        if self.config.pixel_mask.is_some() {
+            return Err(HideError::InvalidParameters(
+                "Covers can't be extended when encoding with a pixel mask".to_string(),
+            ));
+        }
+
+        // Padding only has to fit its trailer, except for exact sizes
+        let mut payload_length = dimensions_bytes(&self.config)
+            + message_length
+            + padding_trailer_bytes(&self.config)
+            + checksum_bytes(&self.config);
+        if let Some(PaddingPolicy::Exact(bytes)) = self.config.padding {
+            payload_length = payload_length.max(bytes);
+        }
+        let payload_length = error_corrected_length(&self.config, payload_length);
+        let header_size = header_bytes(&self.config, flags);
+        let mut pixels =
+            depth::pixels_with_prefix(header_size, payload_length, self.config.embedding_depth);
+        if let Some(ratio) = self.config.max_changed_pixels {
+            pixels = (pixels as f64 / ratio).ceil() as u64;
+        }
+
+        let (width, height) = cover_image.dimensions();
+        match img::required_dimensions(width, height, pixels) {
+            Some(dimensions) if dimensions == (width, height) => Ok(cover_image),
+            Some((new_width, new_height)) => {
+                img::extend_canvas(&cover_image, new_width, new_height, fill)
+            }
+            None => Err(HideError::MessageTooLarge),
+        }
+    }
+
+    /// Dither a cover before embedding, when `pre_dither` is set
+    ///
+    /// An existing payload is checked for and scrubbed first, since the
+    /// dither would hide it from the check made when embedding.
+    fn dither_cover(&self, cover_image: &mut StegoImage) -> Result<()> {
+        let Some(strength) = self.config.pre_dither else {
+            return Ok(());
+        };
+        if self.config.pixel_mask.is_some() {
+            return Err(HideError::InvalidParameters(
+                "Covers can't be dithered when encoding with a pixel mask".to_string(),
+            ));
+        }
+
+        self.prepare_whole_cover(cover_image)?;
+
+        let mut seed = [0u8; 8];
+        rng::fill_bytes(self.config.rng.as_ref(), &mut seed);
+        img::dither(cover_image, strength, u64::from_le_bytes(seed));
+        Ok(())
+    }
+
+    /// Shuffle a cover into the order of the pixel key, when one is set
+    ///
+    /// An existing payload is checked for and scrubbed first, since one
+    /// embedded without the key can't be found in the shuffled order.
+    ///
+    /// # Returns
+    /// * The order applied, to undo once the payload is embedded
+    fn scatter_cover(&self, cover_image: &mut StegoImage) -> Result<Option<Vec<usize>>> {
+        let Some(key) = &self.config.pixel_key else {
+            return Ok(None);
+        };
+        if self.config.pixel_mask.is_some() {
+            return Err(HideError::InvalidParameters(
+                "A pixel key can't be combined with a pixel mask".to_string(),
+            ));
+        }
+
+        self.prepare_whole_cover(cover_image)?;
+        let pixel_count = img::pixel_count(cover_image.width(), cover_image.height());
+        let order = scatter::pixel_order(key, pixel_count as usize);
+        img::permute_pixels(cover_image, &order, false);
+        Ok(Some(order))
+    }
+
+    /// Check a whole cover for an existing payload in row-major order
+    ///
+    /// For steps that hide an existing payload from the check made when
+    /// embedding; see [`Encoder::prepare_cover`].
+    fn prepare_whole_cover(&self, cover_image: &mut StegoImage) -> Result<()> {
+        if self.config.embed_alpha {
+            self.prepare_cover(&mut KeepAlpha(cover_image), None)
+        } else {
+            self.prepare_cover(cover_image, None)
+        }
+    }
+
+    /// Check that a stego image decodes back to the payload just embedded
+    ///
+    /// # Arguments
+    /// * `stego_image` - The encoded image
+    /// * `message` - The payload embedded after the header
+    /// * `flags` - Header flags the payload was embedded with
+    ///
+    /// # Returns
+    /// * [`HideError::SelfCheckFailed`] describing the first difference found
+    fn check_roundtrip(
+        &self,
+        stego_image: &StegoImage,
+        message: &[u8],
+        flags: HeaderFlags,
+    ) -> Result<()> {
+        let decoder = Decoder::with_config(DecoderConfig {
+            cancel: self.config.cancel.clone(),
+            passphrase: self.config.passphrase.clone(),
+            kdf: self.config.kdf.clone(),
+            mask: self.config.pixel_mask.clone(),
+            pixel_key: self.config.pixel_key.clone(),
+            ..Default::default()
+        });
+        let self_check_failed = |detail: String| HideError::SelfCheckFailed { detail };
+
+        let (header, decoded) = match decoder.decode_payload(stego_image) {
+            Ok(decoded) => decoded,
+            Err(HideError::Cancelled) => return Err(HideError::Cancelled),
+            Err(e) => {
+                return Err(self_check_failed(format!(
+                    "the stego image does not decode: {}",
+                    e
+                )))
+            }
+        };
+        if !header.flags.contains(flags) {
+            return Err(self_check_failed(format!(
+                "header flags {:#04x} are missing {:#04x}",
+                header.flags.bits(),
+                flags.bits()
+            )));
+        }
+        if decoded.len() != message.len() {
+            return Err(self_check_failed(format!(
+                "decoded {} bytes instead of {}",
+                decoded.len(),
+                message.len()
+            )));
+        }
+        if let Some(index) = decoded.iter().zip(message).position(|(a, b)| a != b) {
+            return Err(self_check_failed(format!(
+                "decoded byte {} differs from the message",
+                index
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Encode a payload in RGBA mode, keeping the header in the RGB layout
+    fn encode_rgba_payload(
+        &self,
+        image: &mut StegoImage,
+        message: &[u8],
+        flags: HeaderFlags,
+        extra_flags: ExtraFlags,
+    ) -> Result<EncodeStats> {
+        let start = Instant::now();
+        let skip_transparent = self.config.skip_transparent;
+        let depth = self.config.embedding_depth;
+        if self.config.channel_schedule != ChannelSchedule::Fixed {
+            return Err(HideError::InvalidParameters(
+                "Channel schedules apply to RGB payloads, not RGBA mode".to_string(),
+            ));
+        }
+        if self.config.pixel_mask.is_some() {
+            return Err(HideError::InvalidParameters(
+                "Pixel masks apply to RGB payloads, not RGBA mode".to_string(),
+            ));
+        }
+
+        if message.len() > rgba::payload_bytes(image, skip_transparent, depth) {
+            return Err(HideError::MessageTooLarge);
+        }
+        self.check_change_budget(
+            image.width(),
+            image.height(),
+            rgba::HEADER_PIXELS + rgba::message_pixels(message.len(), depth),
+        )?;
+
+        // Work on RGBA pixels so the header writes below keep the alpha
+        image.to_rgba();
+        self.prepare_cover(&mut KeepAlpha(image), None)?;
+
+        let mut flags = flags | HeaderFlags::RGBA;
+        flags.set(HeaderFlags::SKIP_TRANSPARENT, skip_transparent);
+        let header = self.create_header(message.len() as u32, flags, 0, extra_flags);
+        let header_stats = self.embed_into(
+            &mut KeepAlpha(image),
+            &header.to_bytes(),
+            &ChannelOrder::fixed(),
+            None,
+        )?;
+
+        let mut stats = self.embed_rgba(image, message, skip_transparent)?;
+        stats.pixels_used += header_stats.pixels_used;
+        stats.channels_modified += header_stats.channels_modified;
+        stats.message_bytes = message.len();
+        stats.stored_message_bytes = message.len();
+        stats.header_bytes = header.size();
+        stats.timing = Timing::since(start, stats.pixels_used as u64);
+
+        Ok(stats)
+    }
+
+    /// Embed bytes four bits per bit plane into the RGBA payload pixels of an image
+    ///
+    /// Each pixel takes one chunk per bit plane of the embedding depth, the
+    /// LSBs first.
+    fn embed_rgba(
+        &self,
+        image: &mut StegoImage,
+        message: &[u8],
+        skip_transparent: bool,
+    ) -> Result<EncodeStats> {
+        let DynamicImage::ImageRgba8(pixels) = image.inner_mut() else {
+            return Err(HideError::InvalidParameters(
+                "RGBA embedding needs an RGBA image".to_string(),
+            ));
+        };
+
+        // Whole bytes always split into whole 4-bit chunks
+        let message_bits = utils::bytes_to_bits(message);
+        let mut chunks = message_bits
+            .chunks_exact(rgba::BITS_PER_PIXEL_RGBA as usize)
+            .peekable();
+        let chunk_count = rgba::message_pixels(message.len(), self.config.embedding_depth);
+        let mut stats = EncodeStats::default();
+
+        for (index, pixel) in rgba::payload_pixels_mut(pixels, skip_transparent).enumerate() {
+            if chunks.peek().is_none() {
+                break;
+            }
+            cancel::check_every(self.config.cancel.as_ref(), index)?;
+
+            let mut new_pixel = pixel.0;
+            for plane in 0..self.config.embedding_depth.bits_per_channel() {
+                let Some(chunk) = chunks.next() else {
+                    break;
+                };
+                let [r, g, b, a] = depth::plane(new_pixel, plane);
+                let (r, g, b, a) = self.encode_pixel_rgba(r, g, b, a, chunk)?;
+                new_pixel = depth::with_plane(new_pixel, plane, [r, g, b, a]);
+            }
+            // Zeroed planes would make the pixel transparent; set the bit above them instead
+            if pixel.0[3] != 0 && new_pixel[3] == 0 {
+                new_pixel[3] = 1 << self.config.embedding_depth.bits_per_channel();
+            }
+            stats.channels_modified += pixel
+                .0
+                .iter()
+                .zip(new_pixel.iter())
+                .filter(|(old, new)| old != new)
+                .count();
+            stats.pixels_used += 1;
+            pixel.0 = new_pixel;
+            progress::report_every(self.config.progress.as_ref(), index + 1, chunk_count);
+        }
+
+        // Ran out of payload pixels before the message ended
+        if chunks.next().is_some() {
+            return Err(HideError::MessageTooLarge);
+        }
+
+        Ok(stats)
+    }
+
+    /// Encode a payload with the given header flags into a pixel surface
+    fn encode_payload_into<S: PixelSurface + ?Sized>(
+        &self,
+        target: &mut S,
+        message: &[u8],
+        flags: HeaderFlags,
+        extra_flags: ExtraFlags,
+    ) -> Result<EncodeStats> {
+        let start = Instant::now();
+
+        // A masked payload may carry a copy of its mask after the header
+        let mask = self.config.pixel_mask.as_ref();
+        let embedded_mask = match mask {
+            Some(mask) => {
+                mask.check_dimensions(target.width(), target.height())?;
+                self.config
+                    .embed_mask
+                    .then(|| mask.embedded_copy())
+                    .flatten()
+                    .unwrap_or_default()
+            }
+            None => Vec::new(),
+        };
+
+        // Create a header containing metadata about the message
+        let schedule = self.config.channel_schedule;
+        let mut flags = flags | schedule.flags();
+        if mask.is_some() {
+            flags |= HeaderFlags::MASKED;
+        }
+        // Embedded masks are at most MAX_EMBEDDED_MASK_BYTES long
+        let header = self.create_header(
+            message.len() as u32,
+            flags,
+            embedded_mask.len() as u16,
+            extra_flags,
+        );
+
+        // Calculate the maximum message size this target can hold
+        let depth = self.config.embedding_depth;
+        let prefix_size = header.size() + embedded_mask.len();
+        let max_message_size = match mask {
+            Some(mask) => mask.payload_capacity(embedded_mask.len(), depth),
+            None => depth::capacity_after_prefix(
+                img::pixel_count(target.width(), target.height()),
+                prefix_size,
+                depth,
+            ),
+        };
 
         // Check if the message will fit (accounting for header)
         if message.len() > max_message_size {
             return Err(HideError::MessageTooLarge);
         }
 
-        // Create a header containing metadata about the message
-        let header = self.create_header(message.len() as u32)?;
+        // The header and embedded mask are read from the first pixels without the mask
+        if let Some(mask) = mask {
+            let prefix = mask::prefix_pixels(embedded_mask.len());
+            if let Some(index) = mask.first_excluded().filter(|&index| index < prefix) {
+                return Err(HideError::InvalidParameters(format!(
+                    "The mask excludes pixel {}, but the first {} pixels hold the header",
+                    index, prefix
+                )));
+            }
+        }
+
+        // Fail before modifying anything if the change budget can't be met
+        self.check_change_budget(
+            target.width(),
+            target.height(),
+            depth::pixels_with_prefix(prefix_size, message.len(), depth) as usize,
+        )?;
+        let order = ChannelOrder::new(
+            schedule,
+            header.pixels(),
+            self.config.passphrase.as_ref(),
+            self.config.kdf.as_ref(),
+        )?;
+
+        // Refuse to silently overwrite an existing payload
+        self.prepare_cover(target, mask)?;
+
+        // Combine header, embedded mask and message; deeper payloads start
+        // after the one-bit pixels of the header and embedded mask
+        let mut full_message = Vec::with_capacity(prefix_size + message.len());
+        full_message.extend_from_slice(&header.to_bytes());
+        full_message.extend_from_slice(&embedded_mask);
+        let mut stats = if depth == EmbeddingDepth::One {
+            full_message.extend_from_slice(message);
+            self.embed_into(target, &full_message, &order, mask)?
+        } else {
+            let mut stats = self.embed_into(target, &full_message, &order, mask)?;
+            let deep_stats = self.embed_at_depth(
+                target,
+                message,
+                &order,
+                mask,
+                depth::prefix_pixels(prefix_size),
+            )?;
+            stats.pixels_used += deep_stats.pixels_used;
+            stats.channels_modified += deep_stats.channels_modified;
+            stats
+        };
+        stats.message_bytes = message.len();
+        stats.stored_message_bytes = message.len();
+        stats.header_bytes = header.size();
+        stats.timing = Timing::since(start, stats.pixels_used as u64);
+
+        Ok(stats)
+    }
 
-        // Combine header and message
-        let mut full_message = Vec::with_capacity(header.len() + message.len());
-        full_message.extend_from_slice(&header);
-        full_message.extend_from_slice(message);
+    /// Check that a message stays within the configured change budget
+    ///
+    /// # Arguments
+    /// * `width` - Cover width in pixels
+    /// * `height` - Cover height in pixels
+    /// * `needed` - Number of pixels the header and message touch
+    fn check_change_budget(&self, width: u32, height: u32, needed: usize) -> Result<()> {
+        let Some(ratio) = self.config.max_changed_pixels else {
+            return Ok(());
+        };
+
+        let allowed = allowed_changed_pixels(width, height, ratio);
+        if needed > allowed {
+            return Err(HideError::ChangeBudgetExceeded { needed, allowed });
+        }
 
-        // Encode the full message (header + content)
-        self.encode_message(cover_image, &full_message)
+        Ok(())
     }
 
-    /// Create a header containing metadata about the message
+    /// Calculate how many pixels a message and its header touch
+    ///
+    /// # Arguments
+    /// * `message_length` - Length of the message in bytes
     ///
-    /// Header format (8 bytes total):
-    /// - 1 byte: Message format version
-    /// - 4 bytes: Message length (u32, big endian)
-    /// - 3 bytes: Reserved for future use
-    fn create_header(&self, message_length: u32) -> Result<[u8; HEADER_SIZE]> {
-        let mut header = [0u8; HEADER_SIZE];
+    /// # Returns
+    /// * Number of pixels that carry payload bits
+    pub fn pixels_needed(message_length: usize) -> usize {
+        pixels_with_header(HEADER_SIZE, message_length)
+    }
 
-        // Set format version
-        header[0] = MESSAGE_FORMAT_VERSION;
+    /// Check the cover for an existing payload and scrub it if overwriting is allowed
+    ///
+    /// The whole extent of the old payload is zeroed, so no trailing bits of it
+    /// survive past the end of a shorter new payload; for a two-bit payload
+    /// that includes the bits above the LSBs. Pixels excluded by `mask` are
+    /// left untouched.
+    fn prepare_cover<S: PixelSurface + ?Sized>(
+        &self,
+        target: &mut S,
+        mask: Option<&PixelMask>,
+    ) -> Result<()> {
+        let Some(existing) = Decoder::new().detect(target) else {
+            return Ok(());
+        };
+
+        if !self.config.overwrite_existing {
+            return Err(HideError::ExistingPayload {
+                declared_length: existing.message_length,
+            });
+        }
 
-        // Set message length (big endian)
-        header[1] = (message_length >> 24) as u8;
-        header[2] = (message_length >> 16) as u8;
-        header[3] = (message_length >> 8) as u8;
-        header[4] = message_length as u8;
+        let existing_depth = existing.embedding_depth();
+        if existing_depth != EmbeddingDepth::One {
+            // The header and embedded mask keep one bit per channel; the
+            // payload after them takes more
+            let prefix_size = existing.size() + existing.embedded_mask_length as usize;
+            self.embed_into(
+                target,
+                &vec![0u8; prefix_size],
+                &ChannelOrder::fixed(),
+                mask,
+            )?;
+            let pixels = depth::pixels_with_prefix(
+                prefix_size,
+                existing.message_length as usize,
+                existing_depth,
+            );
+            let payload = (0..img::pixel_count(target.width(), target.height()))
+                .filter(|&index| !mask.is_some_and(|mask| mask.is_excluded(index)))
+                .take(pixels as usize)
+                .skip(depth::prefix_pixels(prefix_size) as usize);
+            for index in payload {
+                cancel::check_every(self.config.cancel.as_ref(), index as usize)?;
+                let pixel = target.get_pixel_at(PixelIndex(index))?;
+                let cleared = (0..existing_depth.bits_per_channel())
+                    .fold(pixel.0, |pixel, plane| {
+                        depth::with_plane(pixel, plane, [0; 3])
+                    });
+                target.set_pixel_at(PixelIndex(index), image::Rgb(cleared))?;
+            }
+            return Ok(());
+        }
 
-        // Reserved bytes are left as zeros
+        // Zero bits clear every LSB, whatever schedule the old payload used
+        let extent = existing.size() + existing.message_length as usize;
+        self.embed_into(target, &vec![0u8; extent], &ChannelOrder::fixed(), mask)?;
+        Ok(())
+    }
 
-        Ok(header)
+    /// Create a header containing metadata about the message
+    ///
+    /// Payloads without flags get an 8-byte version 1 header and flagged ones
+    /// a version 2 header with its extension (see [`crate::header`]); a
+    /// two-bit embedding depth is one of the extra flags.
+    fn create_header(
+        &self,
+        message_length: u32,
+        flags: HeaderFlags,
+        embedded_mask_length: u16,
+        mut extra_flags: ExtraFlags,
+    ) -> MessageHeader {
+        extra_flags.set(
+            ExtraFlags::TWO_BIT_DEPTH,
+            self.config.embedding_depth == EmbeddingDepth::Two,
+        );
+        MessageHeader::new(message_length, flags, extra_flags, embedded_mask_length)
     }
 
     /// Encode a message into an image
@@ -190,45 +1467,151 @@ impl Encoder {
     /// # Returns
     /// * The stego image with the encoded message
     pub fn encode_message(&self, mut image: StegoImage, message: &[u8]) -> Result<StegoImage> {
+        self.embed_into(&mut image, message, &ChannelOrder::fixed(), None)?;
+        Ok(image)
+    }
+
+    /// Embed raw bytes into a pixel surface, counting the pixels and channels touched
+    ///
+    /// Pixels excluded by `mask` are skipped.
+    fn embed_into<S: PixelSurface + ?Sized>(
+        &self,
+        image: &mut S,
+        message: &[u8],
+        order: &ChannelOrder,
+        mask: Option<&PixelMask>,
+    ) -> Result<EncodeStats> {
         // Convert the message to bits
-        let message_bits = utils::bytes_to_bits(message);
+        let mut message_bits = utils::bytes_to_bits(message);
 
         // Check if the message will fit in the image
-        let max_bits = (image.width() * image.height() * 3) as usize;
-        if message_bits.len() > max_bits {
+        let max_bits = img::capacity_bits(image.width(), image.height());
+        if message_bits.len() as u64 > max_bits {
             return Err(HideError::MessageTooLarge);
         }
 
-        // Split message into 3-bit chunks for encoding
-        let chunks = utils::split_bits(&message_bits, 3)?;
+        // Pad to whole 3-bit chunks; capacity is a multiple of 3 so this still fits
+        let chunk_count = message_bits.len().div_ceil(3);
+        message_bits.resize(chunk_count * 3, false);
+        let mut chunks = message_bits.chunks_exact(3);
 
         // Track our position in the chunks
         let mut chunk_idx = 0;
+        let mut stats = EncodeStats {
+            pixels_used: chunk_count,
+            ..Default::default()
+        };
 
         // Iterate through each pixel in the image
+        let mut pixel_idx = 0u64;
         for y in 0..image.height() {
             for x in 0..image.width() {
-                // If we've encoded all chunks, we're done
-                if chunk_idx >= chunks.len() {
-                    return Ok(image);
+                pixel_idx += 1;
+                if mask.is_some_and(|mask| mask.is_excluded(pixel_idx - 1)) {
+                    continue;
                 }
 
+                // If we've encoded all chunks, we're done
+                let Some(chunk) = chunks.next() else {
+                    return Ok(stats);
+                };
+
+                cancel::check_every(self.config.cancel.as_ref(), chunk_idx)?;
+
                 // Get the current pixel
                 let pixel = image.get_pixel_rgb(x, y)?;
 
-                // Encode the current chunk into this pixel
-                let (new_r, new_g, new_b) =
-                    self.encode_pixel(pixel.0[0], pixel.0[1], pixel.0[2], &chunks[chunk_idx]);
+                // Encode the current chunk into this pixel, in the scheduled channel order
+                let channel_order = order.for_pixel(chunk_idx as u64);
+                let [r, g, b] = schedule::permute(pixel.0, channel_order);
+                let (new_r, new_g, new_b) = self.encode_pixel(r, g, b, chunk)?;
 
                 // Update the pixel with the encoded values
-                image.set_pixel_rgb(x, y, image::Rgb([new_r, new_g, new_b]))?;
+                let new_pixel = schedule::unpermute([new_r, new_g, new_b], channel_order);
+                stats.channels_modified += pixel
+                    .0
+                    .iter()
+                    .zip(new_pixel.iter())
+                    .filter(|(old, new)| old != new)
+                    .count();
+                image.set_pixel_rgb(x, y, image::Rgb(new_pixel))?;
 
                 // Move to the next chunk
                 chunk_idx += 1;
+                progress::report_every(self.config.progress.as_ref(), chunk_idx, chunk_count);
             }
         }
 
-        Ok(image)
+        // Ran out of pixels before the message ended
+        if chunks.next().is_some() {
+            return Err(HideError::MessageTooLarge);
+        }
+
+        Ok(stats)
+    }
+
+    /// Embed bytes at the configured depth into the pixels after the first `skip`
+    ///
+    /// Pixels excluded by `mask` are skipped and don't count towards `skip`.
+    /// Each pixel takes three bits per bit plane, the LSBs first, each
+    /// through the BLTM in the pixel's scheduled channel order.
+    fn embed_at_depth<S: PixelSurface + ?Sized>(
+        &self,
+        image: &mut S,
+        message: &[u8],
+        order: &ChannelOrder,
+        mask: Option<&PixelMask>,
+        skip: u64,
+    ) -> Result<EncodeStats> {
+        let depth = self.config.embedding_depth;
+        let bits_per_pixel = depth.bits_per_pixel() as usize;
+        let mut message_bits = utils::bytes_to_bits(message);
+        let chunk_count = message_bits.len().div_ceil(bits_per_pixel);
+        message_bits.resize(chunk_count * bits_per_pixel, false);
+        let mut chunks = message_bits.chunks_exact(bits_per_pixel);
+        let mut stats = EncodeStats {
+            pixels_used: chunk_count,
+            ..Default::default()
+        };
+
+        let pixels = (0..img::pixel_count(image.width(), image.height()))
+            .filter(|&index| !mask.is_some_and(|mask| mask.is_excluded(index)));
+        for (chunk_idx, pixel_idx) in (skip..).zip(pixels.skip(skip as usize)) {
+            let Some(chunk) = chunks.next() else {
+                return Ok(stats);
+            };
+            cancel::check_every(self.config.cancel.as_ref(), chunk_idx as usize)?;
+
+            let pixel_idx = PixelIndex(pixel_idx);
+            let pixel = image.get_pixel_at(pixel_idx)?;
+            let channel_order = order.for_pixel(chunk_idx);
+            let mut new_pixel = pixel.0;
+            for (plane, bits) in (0..).zip(chunk.chunks_exact(img::BITS_PER_PIXEL as usize)) {
+                let [r, g, b] = schedule::permute(depth::plane(new_pixel, plane), channel_order);
+                let (r, g, b) = self.encode_pixel(r, g, b, bits)?;
+                let bits = schedule::unpermute([r, g, b], channel_order);
+                new_pixel = depth::with_plane(new_pixel, plane, bits);
+            }
+            stats.channels_modified += pixel
+                .0
+                .iter()
+                .zip(new_pixel.iter())
+                .filter(|(old, new)| old != new)
+                .count();
+            image.set_pixel_at(pixel_idx, image::Rgb(new_pixel))?;
+            progress::report_every(
+                self.config.progress.as_ref(),
+                (chunk_idx - skip) as usize + 1,
+                chunk_count,
+            );
+        }
+
+        // Ran out of pixels before the message ended
+        if chunks.next().is_some() {
+            return Err(HideError::MessageTooLarge);
+        }
+
+        Ok(stats)
     }
 
     /// Calculate the maximum message size that can be stored in an image
@@ -239,42 +1622,105 @@ impl Encoder {
     /// # Returns
     /// * Maximum message size in bytes (accounting for header)
     pub fn max_message_size(&self, image: &StegoImage) -> usize {
-        let total_pixels = image.width() * image.height();
-        let total_bits = total_pixels * 3; // 3 bits per pixel (R,G,B)
-        let total_bytes = total_bits as usize / 8;
+        self.max_payload_size(image, HeaderFlags::empty())
+    }
+
+    /// Calculate the maximum size of a payload with the given header flags
+    pub(crate) fn max_payload_size(&self, image: &StegoImage, flags: HeaderFlags) -> usize {
+        let payload_size = match self.config.padding {
+            Some(PaddingPolicy::Exact(bytes)) => self.payload_capacity(image, flags).min(bytes),
+            _ => self.payload_capacity(image, flags),
+        };
+        error_correctable_bytes(&self.config, payload_size).saturating_sub(
+            dimensions_bytes(&self.config)
+                + padding_trailer_bytes(&self.config)
+                + checksum_bytes(&self.config),
+        )
+    }
 
-        if total_bytes <= HEADER_SIZE {
-            0 // Image too small to hold a header
+    /// Bytes after the header an image holds with this configuration and header flags
+    fn payload_capacity(&self, image: &StegoImage, flags: HeaderFlags) -> usize {
+        let depth = self.config.embedding_depth;
+        if self.config.embed_alpha {
+            rgba::payload_bytes(image, self.config.skip_transparent, depth)
+        } else if let Some(mask) = &self.config.pixel_mask {
+            mask.payload_capacity(embedded_mask_bytes(&self.config), depth)
         } else {
-            total_bytes - HEADER_SIZE // Subtract header size
+            depth::capacity_after_prefix(
+                img::pixel_count(image.width(), image.height()),
+                header_bytes(&self.config, flags),
+                depth,
+            )
         }
     }
 
+    /// Calculate the maximum message size for an image of the given dimensions
+    ///
+    /// # Arguments
+    /// * `width` - Image width in pixels
+    /// * `height` - Image height in pixels
+    ///
+    /// # Returns
+    /// * Maximum size in bytes of a message without flags (accounting for
+    ///   header), saturating at `usize::MAX`
+    pub fn max_message_size_for_dimensions(width: u32, height: u32) -> usize {
+        // Subtract header size; images too small to hold a header have no capacity
+        img::capacity(width, height).saturating_sub(HEADER_SIZE)
+    }
+
     /// Encode a message into an image and save the result
     ///
+    /// When `emit_manifest` is enabled, a provenance manifest is also written
+    /// to `<output_path>.hide.json`. The output path is checked before any
+    /// encoding, failing with [`HideError::OutputPathInvalid`] if its directory
+    /// is missing (and `create_dirs` is not set) or not writable.
+    ///
     /// # Arguments
     /// * `cover_image_path` - Path to the cover image
     /// * `message` - Message to encode
     /// * `output_path` - Path to save the stego image
     ///
     /// # Returns
-    /// * Result indicating success or failure
+    /// * Statistics about the encode, including its warnings
     pub fn encode_file<P: AsRef<Path>>(
         &self,
         cover_image_path: P,
         message: &[u8],
         output_path: P,
-    ) -> Result<()> {
+    ) -> Result<EncodeStats> {
+        // Fail before the work of encoding if the result can't be saved
+        img::prepare_output_path(output_path.as_ref(), self.config.create_dirs)?;
+
         // Load the cover image
-        let cover_image = StegoImage::from_file(cover_image_path)?;
+        let cover_image = StegoImage::from_file(&cover_image_path)?;
 
         // Encode the message
-        let stego_image = self.encode(cover_image, message)?;
+        let (stego_image, stats) = self.encode_with_stats(cover_image, message)?;
 
         // Save the stego image
-        stego_image.save(output_path)?;
+        stego_image.save(&output_path)?;
+
+        // Record provenance next to the output if requested
+        if self.config.emit_manifest {
+            let cover_path = cover_image_path.as_ref();
+            let manifest = Manifest {
+                hide_rs_version: crate::VERSION.to_string(),
+                created_at: chrono::Utc::now(),
+                cover_filename: cover_path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+                cover_sha256: manifest::sha256_hex(&fs::read(cover_path)?),
+                stego_sha256: manifest::sha256_hex(&fs::read(&output_path)?),
+                message_length: message.len(),
+                message_sha256: manifest::sha256_hex(message),
+                config: serde_json::to_value(&self.config)?,
+                stats: stats.clone(),
+            };
+            manifest.save(manifest::manifest_path_for(output_path.as_ref()))?;
+        }
 
-        Ok(())
+        Ok(stats)
     }
 
     /// Get a reference to the BLTM used by this encoder
@@ -283,6 +1729,303 @@ impl Encoder {
     }
 }
 
+/// Length of the mask copy an encode with this configuration embeds
+fn embedded_mask_bytes(config: &EncoderConfig) -> usize {
+    match &config.pixel_mask {
+        Some(mask) if config.embed_mask => mask.embedded_copy().map_or(0, |copy| copy.len()),
+        _ => 0,
+    }
+}
+
+/// Length of the header an encode with this configuration writes
+///
+/// Any flag, a two-bit embedding depth included, takes a version 2 header
+/// with its extension. Compression only sets its flag when it shrinks the
+/// message, so this may overstate the header of a compressed payload by
+/// [`crate::header::EXTENSION_SIZE`] bytes.
+///
+/// # Arguments
+/// * `config` - The options the message would be encoded with
+/// * `flags` - Header flags of the payload besides the ones `config` sets
+pub(crate) fn header_bytes(config: &EncoderConfig, flags: HeaderFlags) -> usize {
+    let flagged = !flags.is_empty()
+        || config.compress
+        || config.checksum
+        || config.error_correction.is_some()
+        || dimensions_bytes(config) > 0
+        || config.pixel_mask.is_some()
+        || config.embed_alpha
+        || config.channel_schedule != ChannelSchedule::Fixed
+        || config.embedding_depth != EmbeddingDepth::One;
+    if flagged {
+        EXTENDED_HEADER_SIZE
+    } else {
+        HEADER_SIZE
+    }
+}
+
+/// Number of pixels a payload of the given length and its header touch
+pub(crate) fn pixels_with_header(header_size: usize, payload_length: usize) -> usize {
+    ((header_size + payload_length) * 8).div_ceil(3)
+}
+
+/// Length of the cover dimensions an encode with this configuration records
+fn dimensions_bytes(config: &EncoderConfig) -> usize {
+    if config.record_dimensions || config.padding.is_some() || config.auto_extend.is_some() {
+        DIMENSIONS_SIZE
+    } else {
+        0
+    }
+}
+
+/// Length of the trailer an encode with this configuration ends padded payloads with
+fn padding_trailer_bytes(config: &EncoderConfig) -> usize {
+    if config.padding.is_some() {
+        PADDING_TRAILER_SIZE
+    } else {
+        0
+    }
+}
+
+/// Length of the checksum an encode with this configuration ends payloads with
+fn checksum_bytes(config: &EncoderConfig) -> usize {
+    if config.checksum {
+        CHECKSUM_SIZE
+    } else {
+        0
+    }
+}
+
+/// Length a payload takes once an encode with this configuration adds its parity
+fn error_corrected_length(config: &EncoderConfig, payload_length: usize) -> usize {
+    match config.error_correction {
+        Some(correction) => ecc::encoded_length(payload_length, correction),
+        None => payload_length,
+    }
+}
+
+/// Bytes of payload an encode with this configuration fits in `capacity`
+/// bytes once it adds its parity
+fn error_correctable_bytes(config: &EncoderConfig, capacity: usize) -> usize {
+    match config.error_correction {
+        Some(correction) => ecc::max_data_length(capacity, correction),
+        None => capacity,
+    }
+}
+
+/// Append the recorded cover dimensions that start a payload
+///
+/// # Returns
+/// * An error if the width or height doesn't fit a u16
+fn put_dimensions(payload: &mut Vec<u8>, width: u32, height: u32) -> Result<()> {
+    match (u16::try_from(width), u16::try_from(height)) {
+        (Ok(width), Ok(height)) => {
+            put_u16_be(payload, width);
+            put_u16_be(payload, height);
+            Ok(())
+        }
+        _ => Err(HideError::InvalidParameters(format!(
+            "Cannot record the dimensions of a {}x{} cover; both must be at most {}",
+            width,
+            height,
+            u16::MAX
+        ))),
+    }
+}
+
+/// Number of pixels a change budget lets an encode touch
+fn allowed_changed_pixels(width: u32, height: u32, ratio: f64) -> usize {
+    let total_pixels = width as f64 * height as f64;
+    // Absorb float rounding so a budget like 0.48% of 10000 pixels allows 48
+    (total_pixels * ratio * (1.0 + 1e-9)).floor() as usize
+}
+
+/// Bytes of an image's raw capacity taken by one kind of overhead
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CapacityOverhead {
+    /// What takes the bytes (`header`, `mask`, `embedded_mask`,
+    /// `error_correction`, `dimensions`, `checksum`, `padding` or `change_budget`)
+    pub name: String,
+
+    /// Number of bytes taken
+    pub bytes: usize,
+}
+
+/// Where the capacity of an image goes under a given encoder configuration
+///
+/// `max_message_bytes` plus every overhead always adds up to `raw_bytes`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CapacityBreakdown {
+    /// Whole bytes the image's pixels can carry
+    pub raw_bytes: usize,
+
+    /// Bytes not available to the message, by cause
+    pub overheads: Vec<CapacityOverhead>,
+
+    /// Largest message an encode with this configuration accepts
+    pub max_message_bytes: usize,
+}
+
+impl CapacityBreakdown {
+    /// Total bytes taken by overheads
+    pub fn overhead_bytes(&self) -> usize {
+        self.overheads.iter().map(|overhead| overhead.bytes).sum()
+    }
+}
+
+/// Break down the capacity of an image under an encoder configuration
+///
+/// # Arguments
+/// * `image` - The cover image
+/// * `config` - The options the message would be encoded with
+///
+/// # Returns
+/// * The raw capacity, the overheads and the largest message that fits
+pub fn capacity_breakdown(image: &StegoImage, config: &EncoderConfig) -> CapacityBreakdown {
+    let (width, height) = image.dimensions();
+    let header_size = header_bytes(config, HeaderFlags::empty());
+    let depth = config.embedding_depth;
+    let raw_bytes = if config.embed_alpha {
+        img::capacity(width, height).min(header_size)
+            + rgba::payload_bytes(image, config.skip_transparent, depth)
+    } else if depth != EmbeddingDepth::One {
+        img::capacity(width, height).min(header_size)
+            + depth::capacity_after_prefix(img::pixel_count(width, height), header_size, depth)
+    } else {
+        img::capacity(width, height)
+    };
+
+    let header_bytes = raw_bytes.min(header_size);
+    let mut overheads = vec![CapacityOverhead {
+        name: "header".to_string(),
+        bytes: header_bytes,
+    }];
+    let mut max_message_bytes = raw_bytes - header_bytes;
+
+    // Excluded pixels carry nothing, and an embedded mask is carried before the message
+    let mut prefix_size = header_size;
+    if let (false, Some(mask)) = (config.embed_alpha, &config.pixel_mask) {
+        let masked = max_message_bytes.min(mask.payload_capacity(0, depth));
+        overheads.push(CapacityOverhead {
+            name: "mask".to_string(),
+            bytes: max_message_bytes - masked,
+        });
+
+        prefix_size += embedded_mask_bytes(config);
+        let with_copy = masked.min(mask.payload_capacity(embedded_mask_bytes(config), depth));
+        if config.embed_mask {
+            overheads.push(CapacityOverhead {
+                name: "embedded_mask".to_string(),
+                bytes: masked - with_copy,
+            });
+        }
+        max_message_bytes = with_copy;
+    }
+
+    // Parity and the block layout take their share of whatever is left
+    let protected_bytes = error_correctable_bytes(config, max_message_bytes);
+    if config.error_correction.is_some() {
+        overheads.push(CapacityOverhead {
+            name: "error_correction".to_string(),
+            bytes: max_message_bytes - protected_bytes,
+        });
+        max_message_bytes = protected_bytes;
+    }
+
+    // Recorded dimensions are carried before the message too
+    let dimension_bytes = max_message_bytes.min(dimensions_bytes(config));
+    if dimension_bytes > 0 {
+        overheads.push(CapacityOverhead {
+            name: "dimensions".to_string(),
+            bytes: dimension_bytes,
+        });
+        max_message_bytes -= dimension_bytes;
+    }
+
+    // The checksum ends the payload
+    let checksum_bytes = max_message_bytes.min(checksum_bytes(config));
+    if checksum_bytes > 0 {
+        overheads.push(CapacityOverhead {
+            name: "checksum".to_string(),
+            bytes: checksum_bytes,
+        });
+        max_message_bytes -= checksum_bytes;
+    }
+
+    // Padded payloads end with a trailer, and an exact padded size caps the message
+    let trailer_bytes = max_message_bytes.min(padding_trailer_bytes(config));
+    if let Some(policy) = config.padding {
+        let limited = match policy {
+            PaddingPolicy::Exact(bytes) => {
+                bytes.saturating_sub(DIMENSIONS_SIZE + trailer_bytes + checksum_bytes)
+            }
+            _ => usize::MAX,
+        }
+        .min(max_message_bytes - trailer_bytes);
+        overheads.push(CapacityOverhead {
+            name: "padding".to_string(),
+            bytes: max_message_bytes - limited,
+        });
+        max_message_bytes = limited;
+    }
+
+    // The header counts against the change budget too
+    if let Some(ratio) = config.max_changed_pixels {
+        let allowed = allowed_changed_pixels(width, height, ratio);
+        let budget_payload_bytes = if config.embed_alpha {
+            depth::bytes_for_pixels(
+                allowed.saturating_sub(rgba::HEADER_PIXELS) as u64,
+                rgba::bits_per_pixel(depth),
+            )
+        } else {
+            depth::capacity_after_prefix(allowed as u64, prefix_size, depth)
+        };
+        let budget_bytes = error_correctable_bytes(config, budget_payload_bytes)
+            .saturating_sub(dimension_bytes + checksum_bytes + trailer_bytes);
+        let limited = max_message_bytes.min(budget_bytes);
+        overheads.push(CapacityOverhead {
+            name: "change_budget".to_string(),
+            bytes: max_message_bytes - limited,
+        });
+        max_message_bytes = limited;
+    }
+
+    CapacityBreakdown {
+        raw_bytes,
+        overheads,
+        max_message_bytes,
+    }
+}
+
+/// Length of the payload an encode writes after the header
+///
+/// Besides the message this counts an embedded mask, recorded dimensions,
+/// padding with its trailer, a checksum and error correction parity, which
+/// with the header take the pixels the encode touches.
+///
+/// # Arguments
+/// * `image` - The cover image
+/// * `config` - The options the message would be encoded with
+/// * `message_length` - Length of the message in bytes
+///
+/// # Returns
+/// * The length in bytes, or the error the encode fails with when padding doesn't fit
+pub fn payload_length(
+    image: &StegoImage,
+    config: &EncoderConfig,
+    message_length: usize,
+) -> Result<usize> {
+    let body = dimensions_bytes(config) + message_length + checksum_bytes(config);
+    let body = match config.padding {
+        Some(policy) => policy.padded_length(
+            body + PADDING_TRAILER_SIZE,
+            Encoder::with_config(config.clone()).payload_capacity(image, HeaderFlags::empty()),
+        )?,
+        None => body,
+    };
+    Ok(embedded_mask_bytes(config) + error_corrected_length(config, body))
+}
+
 /// Create a new encoder with default settings
 pub fn create_encoder() -> Encoder {
     Encoder::new()
@@ -291,7 +2034,9 @@ pub fn create_encoder() -> Encoder {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::header::{get_u32_be, HeaderVersion, EXTENSION_SIZE};
     use crate::img::create_rgb_image;
+    use crate::testing::{assert_lsb_only_changes, gradient_image};
     use image::Rgb;
 
     #[test]
@@ -307,7 +2052,7 @@ mod tests {
         let message = bitvec![u8, Msb0; 1, 1, 0];
 
         // Encode the pixel
-        let (new_r, new_g, new_b) = encoder.encode_pixel(r, g, b, &message);
+        let (new_r, new_g, new_b) = encoder.encode_pixel(r, g, b, &message).unwrap();
 
         // Expected output from example:
         // Rs = 123 = (01111011)2 - unchanged
@@ -323,6 +2068,43 @@ mod tests {
         assert_eq!(new_b & 1, 1, "LSB of B should be 1");
     }
 
+    #[test]
+    fn test_encode_pixel_rejects_wrong_bit_count() {
+        let encoder = Encoder::new();
+        for message in [
+            bitvec![u8, Msb0;],
+            bitvec![u8, Msb0; 1, 0],
+            bitvec![u8, Msb0; 1, 0, 1, 1],
+        ] {
+            assert!(encoder.encode_pixel(10, 20, 30, &message).is_err());
+        }
+    }
+
+    #[test]
+    fn test_encode_pixel_rgba_roundtrip() {
+        let encoder = Encoder::new();
+        let decoder = Decoder::new();
+
+        for (r, g, b, a) in [(0, 0, 0, 255), (123, 127, 135, 1), (255, 1, 2, 0)] {
+            for value in 0u8..16 {
+                let message: BitVec<u8, Msb0> = (0..4).map(|i| value & (8 >> i) != 0).collect();
+                let (nr, ng, nb, na) = encoder.encode_pixel_rgba(r, g, b, a, &message).unwrap();
+                assert_eq!(decoder.decode_pixel_rgba(nr, ng, nb, na), message);
+
+                for (old, new) in [(r, nr), (g, ng), (b, nb)] {
+                    assert!(old ^ new <= 1);
+                }
+                // Alpha moves by at most one and a visible pixel stays visible
+                assert!(a.abs_diff(na) <= 1);
+                assert!(a == 0 || na != 0);
+            }
+        }
+
+        assert!(encoder
+            .encode_pixel_rgba(1, 2, 3, 4, &bitvec![u8, Msb0; 1, 0, 1])
+            .is_err());
+    }
+
     #[test]
     fn test_matrix_multiply() {
         let encoder = Encoder::new();
@@ -353,56 +2135,16 @@ mod tests {
         let encoder = Encoder::new();
 
         // Create a test image (10x10 = 100 pixels, can store up to 37 bytes)
-        let mut image = create_rgb_image(10, 10);
-
-        // Fill with some test data
-        for y in 0..10 {
-            for x in 0..10 {
-                let r = (x * 20) as u8;
-                let g = (y * 20) as u8;
-                let b = ((x + y) * 10) as u8;
-                image.set_pixel_rgb(x, y, Rgb([r, g, b])).unwrap();
-            }
-        }
+        let image = gradient_image(10, 10);
 
         // Create a test message
         let message = b"Hello, steganography!";
 
         // Encode the message
-        let stego_image = encoder.encode(image, message).unwrap();
-
-        // The resulting image should have same dimensions
-        assert_eq!(stego_image.width(), 10);
-        assert_eq!(stego_image.height(), 10);
-
-        // Check that pixels have been modified (only LSBs should change)
-        let mut changes_count = 0;
-        for y in 0..10 {
-            for x in 0..10 {
-                let original_r = (x * 20) as u8;
-                let original_g = (y * 20) as u8;
-                let original_b = ((x + y) * 10) as u8;
-
-                let stego_pixel = stego_image.get_pixel_rgb(x, y).unwrap();
-
-                // Check that only LSB might have changed
-                if stego_pixel.0[0] != original_r {
-                    assert_eq!(stego_pixel.0[0], original_r ^ 1);
-                    changes_count += 1;
-                }
-                if stego_pixel.0[1] != original_g {
-                    assert_eq!(stego_pixel.0[1], original_g ^ 1);
-                    changes_count += 1;
-                }
-                if stego_pixel.0[2] != original_b {
-                    assert_eq!(stego_pixel.0[2], original_b ^ 1);
-                    changes_count += 1;
-                }
-            }
-        }
+        let stego_image = encoder.encode(image.clone(), message).unwrap();
 
-        // Ensure some pixels were modified
-        assert!(changes_count > 0);
+        // Only LSBs should change, and some of them must have
+        assert!(assert_lsb_only_changes(&image, &stego_image) > 0);
     }
 
     #[test]
@@ -410,7 +2152,7 @@ mod tests {
         let encoder = Encoder::new();
 
         // Create a small image (2x2 = 4 pixels, can store 1 byte of message)
-        let image = create_rgb_image(2, 2);
+        let image = create_rgb_image(2, 2).unwrap();
 
         // Try to encode a message that's too large
         let large_message = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
@@ -424,15 +2166,50 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_encode_stats() {
+        let encoder = Encoder::new();
+        let image = create_rgb_image(10, 10).unwrap();
+        let message = b"Hello";
+
+        let (_, stats) = encoder.encode_with_stats(image, message).unwrap();
+
+        // (8 header + 5 message bytes) * 8 bits / 3 bits per pixel, rounded up
+        assert_eq!(stats.message_bytes, 5);
+        assert_eq!(stats.header_bytes, HEADER_SIZE);
+        assert_eq!(stats.pixels_used, 35);
+        assert!(stats.channels_modified > 0);
+        assert!(stats.channels_modified <= stats.pixels_used * 3);
+    }
+
+    #[test]
+    fn test_config_secrets_are_redacted() {
+        let config = EncoderConfig {
+            emit_manifest: true,
+            passphrase: Some(Secret::new("hunter2")),
+            ..Default::default()
+        };
+
+        let json = serde_json::to_string(&config).unwrap();
+        assert!(!json.contains("hunter2"));
+        assert!(!format!("{:?}", config).contains("hunter2"));
+
+        // The secret itself refuses to serialize its value
+        let json = serde_json::to_string(&Secret::new("hunter2")).unwrap();
+        assert_eq!(json, "\"[redacted]\"");
+    }
+
     #[test]
     fn test_header_creation() {
         let encoder = Encoder::new();
 
         // Create a header for a message
-        let header = encoder.create_header(1234).unwrap();
+        let header = encoder
+            .create_header(1234, HeaderFlags::empty(), 0, ExtraFlags::empty())
+            .to_bytes();
 
         // Check header format
-        assert_eq!(header[0], MESSAGE_FORMAT_VERSION);
+        assert_eq!(header[0], HeaderVersion::CURRENT.number());
 
         // Check message length (big endian)
         assert_eq!(header[1], 0);
@@ -441,11 +2218,12 @@ mod tests {
         assert_eq!(header[4], 210);
 
         // Reconstruct message length
-        let msg_len = (header[1] as u32) << 24
-            | (header[2] as u32) << 16
-            | (header[3] as u32) << 8
-            | (header[4] as u32);
-        assert_eq!(msg_len, 1234);
+        assert_eq!(get_u32_be(&header, 1), Some(1234));
+
+        // Any flag takes a version 2 header with its extension
+        let header = encoder.create_header(1234, HeaderFlags::empty(), 0, ExtraFlags::CHECKSUMMED);
+        assert_eq!(header.format_version, HeaderVersion::V2.number());
+        assert_eq!(header.to_bytes().len(), EXTENDED_HEADER_SIZE);
     }
 
     #[test]
@@ -457,10 +2235,13 @@ mod tests {
             (8, 8, 24 - HEADER_SIZE), // 64 pixels = 24 bytes - 8 bytes header = 16 bytes
             (10, 10, 37 - HEADER_SIZE), // 100 pixels = 37 bytes - 8 bytes header = 29 bytes
             (2, 2, 0),                // 4 pixels = 1 byte (too small for header + message)
+            (1, 1, 0),                // 1 pixel = 0 bytes
+            (1, 30, 11 - HEADER_SIZE), // 30 pixels = 11 bytes - 8 bytes header = 3 bytes
+            (30, 1, 11 - HEADER_SIZE), // Same capacity regardless of orientation
         ];
 
         for (width, height, expected_size) in test_cases.iter() {
-            let image = create_rgb_image(*width, *height);
+            let image = create_rgb_image(*width, *height).unwrap();
             let max_size = encoder.max_message_size(&image);
             assert_eq!(
                 max_size, *expected_size,
@@ -469,4 +2250,151 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_progress_reaches_the_payload_pixels() {
+        let reports = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = std::sync::Arc::clone(&reports);
+        let encoder =
+            Encoder::with_config(EncoderConfig::default().on_progress(move |done, total| {
+                seen.lock().unwrap().push((done, total));
+            }));
+
+        let message = vec![1; 4000];
+        encoder.encode(gradient_image(128, 128), &message).unwrap();
+
+        let total = Encoder::pixels_needed(message.len()) as u64;
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.last(), Some(&(total, total)));
+        assert!(reports.len() > 1);
+        assert!(reports.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+
+    #[test]
+    fn test_pixels_needed() {
+        // 8 header bytes = 64 bits = 22 pixels (rounded up)
+        assert_eq!(Encoder::pixels_needed(0), 22);
+        // 18 bytes = 144 bits = exactly 48 pixels
+        assert_eq!(Encoder::pixels_needed(10), 48);
+    }
+
+    #[test]
+    fn test_max_message_size_for_huge_dimensions() {
+        // 65536 x 65536 pixels would overflow u32 arithmetic (2^32 * 3 bits)
+        let expected = (65536u64 * 65536 * 3 / 8) as usize - HEADER_SIZE;
+        assert_eq!(
+            Encoder::max_message_size_for_dimensions(65536, 65536),
+            expected
+        );
+
+        // The largest possible dimensions saturate instead of overflowing
+        let max = Encoder::max_message_size_for_dimensions(u32::MAX, u32::MAX);
+        assert!(max > 0);
+        assert_eq!(Encoder::max_message_size_for_dimensions(0, u32::MAX), 0);
+    }
+
+    #[test]
+    fn test_verified_encodes_pass_their_self_check() {
+        let message = b"checked before it leaves".repeat(3);
+        let configs = [
+            EncoderConfig::default(),
+            EncoderConfig {
+                channel_schedule: ChannelSchedule::Rotating,
+                ..Default::default()
+            },
+            EncoderConfig {
+                embed_alpha: true,
+                ..Default::default()
+            },
+            EncoderConfig {
+                pixel_mask: Some(PixelMask::from_fn(40, 40, |x, y| y > 5 && x < 10)),
+                ..Default::default()
+            },
+        ];
+
+        for config in configs {
+            let encoder = Encoder::with_config(config.verify_roundtrip(true));
+            assert!(encoder.encode(gradient_image(40, 40), &message).is_ok());
+        }
+
+        let entries = vec![("a".to_string(), b"entry".to_vec())];
+        let encoder = Encoder::with_config(EncoderConfig::default().verify_roundtrip(true));
+        assert!(encoder
+            .encode_container(gradient_image(40, 40), &entries)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_self_check_catches_corrupted_pixels() {
+        let encoder = Encoder::with_config(EncoderConfig::default().verify_roundtrip(true));
+        let message = b"this payload gets damaged".to_vec();
+        let mut stego = Encoder::new()
+            .encode(gradient_image(40, 40), &message)
+            .unwrap();
+        assert!(encoder
+            .check_roundtrip(&stego, &message, HeaderFlags::empty())
+            .is_ok());
+
+        // Flip a red LSB in the message pixels, after the header's 22
+        let original = stego.get_pixel_rgb(30, 0).unwrap();
+        stego
+            .set_pixel_rgb(
+                30,
+                0,
+                Rgb([original.0[0] ^ 1, original.0[1], original.0[2]]),
+            )
+            .unwrap();
+        let err = encoder
+            .check_roundtrip(&stego, &message, HeaderFlags::empty())
+            .unwrap_err();
+        assert!(
+            matches!(&err, HideError::SelfCheckFailed { detail } if detail.contains("byte")),
+            "{:?}",
+            err
+        );
+
+        // A payload missing its flags is caught too
+        let err = encoder
+            .check_roundtrip(&stego, &message, HeaderFlags::CONTAINER)
+            .unwrap_err();
+        assert!(matches!(err, HideError::SelfCheckFailed { .. }));
+
+        // So is an image with no payload at all
+        let err = encoder
+            .check_roundtrip(&gradient_image(40, 40), &message, HeaderFlags::empty())
+            .unwrap_err();
+        assert!(matches!(err, HideError::SelfCheckFailed { .. }));
+    }
+
+    #[test]
+    fn test_recorded_dimensions_start_the_payload() {
+        let encoder = Encoder::with_config(EncoderConfig {
+            record_dimensions: true,
+            verify_roundtrip: true,
+            ..Default::default()
+        });
+        let cover = gradient_image(40, 30);
+        assert_eq!(
+            encoder.max_message_size(&cover),
+            Encoder::new().max_message_size(&cover) - EXTENSION_SIZE - DIMENSIONS_SIZE
+        );
+
+        let (stego, stats) = encoder.encode_with_stats(cover, b"sized").unwrap();
+        assert_eq!(stats.message_bytes, 5);
+        assert_eq!(stats.header_bytes, EXTENDED_HEADER_SIZE + DIMENSIONS_SIZE);
+
+        let decoder = Decoder::new();
+        let header = decoder.read_header(&stego).unwrap();
+        assert_eq!(header.format_version, HeaderVersion::V2.number());
+        assert!(header.records_dimensions());
+        assert_eq!(header.message_length, 5 + DIMENSIONS_SIZE as u32);
+        assert_eq!(decoder.detect(&stego), Some(header));
+        assert_eq!(decoder.recorded_dimensions(&stego), Some((40, 30)));
+        assert_eq!(decoder.decode(&stego).unwrap(), b"sized");
+
+        assert!(matches!(
+            put_dimensions(&mut Vec::new(), 70_000, 10),
+            Err(HideError::InvalidParameters(_))
+        ));
+    }
 }
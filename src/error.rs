@@ -1,3 +1,4 @@
+use std::path::PathBuf;
 use thiserror::Error;
 
 /// Error types for the hide-rs library
@@ -7,6 +8,15 @@ pub enum HideError {
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
+    /// A file can't be written where it was asked to go
+    #[error("Cannot write to {}: {reason}", .path.display())]
+    OutputPathInvalid {
+        /// The output path
+        path: PathBuf,
+        /// Why it can't be written
+        reason: String,
+    },
+
     /// Image processing errors
     #[error("Image error: {0}")]
     Image(#[from] image::ImageError),
@@ -15,10 +25,53 @@ pub enum HideError {
     #[error("Message is too large for the given image")]
     MessageTooLarge,
 
+    /// The cover image already holds a hide-rs payload
+    #[error("Image already contains a hidden payload of {declared_length} bytes")]
+    ExistingPayload {
+        /// Length declared by the existing payload's header
+        declared_length: u32,
+    },
+
+    /// Encoding would touch more pixels than the configured change budget allows
+    #[error("Encoding touches {needed} pixels, exceeding the change budget of {allowed}")]
+    ChangeBudgetExceeded {
+        /// Number of pixels the payload would touch
+        needed: usize,
+        /// Number of pixels the budget allows
+        allowed: usize,
+    },
+
     /// No message found in the image
     #[error("No message found in the image")]
     NoMessageFound,
 
+    /// Decoding failed on an image whose file carried color management chunks
+    #[error("{0}; this image appears to have been color-converted, use the original file")]
+    ColorConverted(Box<HideError>),
+
+    /// Decoding failed on an image whose size differs from the cover's when it was encoded
+    #[error(
+        "{cause}; the image is {}x{} but was encoded at {}x{}, it was likely resized",
+        .actual.0, .actual.1, .encoded.0, .encoded.1
+    )]
+    DimensionsChanged {
+        /// Width and height of the cover the payload was encoded in
+        encoded: (u32, u32),
+        /// Width and height of the image being decoded
+        actual: (u32, u32),
+        /// Why the decode failed
+        cause: Box<HideError>,
+    },
+
+    /// A valid header declares more message bytes than the image holds
+    #[error("Header declares {declared} message bytes but the image only holds {available}")]
+    TruncatedPayload {
+        /// Message length declared by the header, in bytes
+        declared: usize,
+        /// Whole message bytes present after the header
+        available: usize,
+    },
+
     /// Invalid parameters
     #[error("Invalid parameters: {0}")]
     InvalidParameters(String),
@@ -26,4 +79,110 @@ pub enum HideError {
     /// Matrix error
     #[error("Matrix error: {0}")]
     MatrixError(String),
+
+    /// A JPEG file is malformed or uses coding the JPEG algorithm does not support
+    #[error("Invalid or unsupported JPEG: {0}")]
+    InvalidJpeg(String),
+
+    /// The requested output format would not preserve the pixel data
+    #[error("Lossy output format rejected: {0}")]
+    LossyFormatRejected(String),
+
+    /// The header has a format version or sets flag bits this version does not know about
+    #[error("{}", unsupported_feature_message(*.version, *.bits))]
+    UnsupportedFeature {
+        /// Format version of the payload
+        version: u8,
+        /// The unknown flag bits, of the flags byte if any are set there and
+        /// otherwise of the extra flags; 0 if the version itself is unknown
+        bits: u16,
+    },
+
+    /// The declared message is larger than an untrusted decode may produce
+    #[error(
+        "Declared message of {declared_length} bytes exceeds the output limit of {limit} bytes"
+    )]
+    OutputLimitExceeded {
+        /// Length declared by the header
+        declared_length: usize,
+        /// Maximum number of output bytes allowed
+        limit: usize,
+    },
+
+    /// The payload doesn't match the CRC-32 the encoder ended it with
+    #[error("Payload checksum mismatch: expected {expected:08x}, computed {actual:08x}")]
+    ChecksumMismatch {
+        /// Checksum stored in the payload
+        expected: u32,
+        /// Checksum of the payload as read
+        actual: u32,
+    },
+
+    /// A block of an error-corrected payload has more corrupted bytes than its parity repairs
+    #[error("Payload block {block} has more corrupted bytes than error correction can repair")]
+    UncorrectableErrors {
+        /// Index of the first block beyond repair, counting from 0
+        block: usize,
+    },
+
+    /// Decoding would process more pixels than an untrusted decode may read
+    #[error("Decoding requires {required} pixels, exceeding the limit of {limit}")]
+    PixelLimitExceeded {
+        /// Number of pixels the decode would read
+        required: u64,
+        /// Maximum number of pixels allowed
+        limit: u64,
+    },
+
+    /// Creating an image would need a larger pixel buffer than allowed
+    #[error("Image needs {requested} bytes, exceeding the allocation limit of {limit}")]
+    AllocationTooLarge {
+        /// Size of the pixel buffer in bytes
+        requested: u64,
+        /// Largest buffer allowed in bytes
+        limit: u64,
+    },
+
+    /// An untrusted decode ran past its deadline
+    #[error("Decode deadline exceeded")]
+    DeadlineExceeded,
+
+    /// A stego image failed to decode back to the payload embedded in it
+    #[error("Encoded image failed its self-check: {detail}")]
+    SelfCheckFailed {
+        /// What differed
+        detail: String,
+    },
+
+    /// A plain decode found an encrypted payload
+    #[error("The payload is encrypted; decode it with its passphrase")]
+    EncryptedPayload,
+
+    /// An encrypted payload failed to authenticate
+    #[error("Decryption failed: wrong passphrase or tampered payload")]
+    DecryptionFailed,
+
+    /// An encrypted payload names a cipher suite this version does not know
+    #[error("Encrypted payload uses unknown cipher suite {id}")]
+    UnknownCipher {
+        /// The suite ID byte the payload starts with
+        id: u8,
+    },
+
+    /// The operation was cancelled through a cancellation token
+    #[error("Operation cancelled")]
+    Cancelled,
+
+    /// JSON serialization errors
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+}
+
+/// Describe an unsupported feature error
+fn unsupported_feature_message(version: u8, bits: u16) -> String {
+    if bits == 0 {
+        format!("Payload uses unsupported format version {}", version)
+    } else {
+        format!("Payload uses unsupported header flags {:#06x}", bits)
+    }
 }
@@ -0,0 +1,824 @@
+//! Message header layouts and the registry of the flag bits they store
+//!
+//! Every feature that marks payloads through the header must add its flag to
+//! [`HeaderFlags`]; bits not listed there are treated as unknown by decoders.
+//!
+//! Every header layout ever released is listed in [`HeaderVersion`] so old
+//! stego images keep decoding; [`VersionedParser`] picks the layout from the
+//! leading version byte.
+//!
+//! Every multi-byte integer in embedded data is big endian: the header's
+//! length fields, the recorded cover dimensions, container and pack record
+//! lengths, padding trailers and checksums. They are written and read with
+//! [`put_u16_be`], [`put_u32_be`], [`get_u16_be`] and [`get_u32_be`] rather
+//! than shifted by hand, so the wire format can't depend on the platform. The
+//! bits of each byte are embedded most significant first (see
+//! [`crate::utils::bytes_to_bits`]).
+//!
+//! # Byte layout
+//!
+//! Every version starts with the same [`HEADER_SIZE`] bytes:
+//!
+//! | Bytes | Field |
+//! |-------|-------|
+//! | 0 | Format version ([`HeaderVersion`]) |
+//! | 1..5 | Message length in bytes, u32 |
+//! | 5 | Flags ([`HeaderFlags`]) |
+//! | 6..8 | Version 1: u16 holding the [`ExtraFlags`] in its top bits and, for masked payloads, the embedded mask length below them. Version 2: length of the extension that follows, u16 |
+//!
+//! Version 2 headers continue with an extension, [`EXTENSION_SIZE`] bytes as
+//! this version writes it:
+//!
+//! | Bytes | Field |
+//! |-------|-------|
+//! | 8..10 | Extra flags ([`ExtraFlags`]), u16 |
+//! | 10..12 | Embedded mask length of masked payloads, u16 |
+//!
+//! Fields appended to the extension later are counted in its length, so
+//! decoders skip the ones they don't know. A feature that changes how the
+//! payload is read takes an extra flag bit instead, which decoders that don't
+//! know it refuse. The whole header, extension included, is embedded in the
+//! fixed channel order.
+//!
+//! The encoder only writes version 1 headers for payloads that set no flag,
+//! so decoders from before the flags existed, which read version 1 headers
+//! and nothing else, never return a flagged payload as a plain message.
+
+use crate::depth::EmbeddingDepth;
+use crate::error::HideError;
+use crate::img;
+use crate::schedule::ChannelSchedule;
+use crate::Result;
+use bitflags::bitflags;
+
+/// Minimum size of a header: the prefix every version shares
+///
+/// Only version 1 headers end here; the extended headers flagged payloads get
+/// are [`EXTENDED_HEADER_SIZE`] bytes. Bindings and UIs should use
+/// [`VersionedParser::header_length`] for the size of the header at the start
+/// of a payload, and [`MessageHeader::size`] for that of a parsed header,
+/// rather than hardcoding either number.
+pub const HEADER_SIZE: usize = 8;
+
+/// Size of the extension this version writes after the first [`HEADER_SIZE`]
+/// bytes of a version 2 header
+pub const EXTENSION_SIZE: usize = 4;
+
+/// Size of the version 2 headers this version writes
+pub const EXTENDED_HEADER_SIZE: usize = HEADER_SIZE + EXTENSION_SIZE;
+
+/// Offset of the message length (u32, big endian) within the message header
+const LENGTH_OFFSET: usize = 1;
+
+/// Offset of the flags byte within the message header (the first reserved byte)
+pub(crate) const FLAGS_OFFSET: usize = 5;
+
+/// Offset of the extra flags and the embedded mask length of masked payloads
+/// (u16, big endian) in version 1 headers
+const MASK_LENGTH_OFFSET: usize = 6;
+
+/// Offset of the extension length (u16, big endian) in version 2 headers
+const EXTENSION_LENGTH_OFFSET: usize = 6;
+
+/// Offset of the extra flags (u16, big endian) in version 2 headers
+const EXTRA_FLAGS_OFFSET: usize = 8;
+
+/// Offset of the embedded mask length (u16, big endian) in version 2 headers
+const EXTENDED_MASK_LENGTH_OFFSET: usize = 10;
+
+/// Size of the CRC-32 that ends checksummed payloads (u32, big endian)
+pub const CHECKSUM_SIZE: usize = 4;
+
+/// Size of the cover dimensions that start payloads recording them (two u16, big endian)
+pub const DIMENSIONS_SIZE: usize = 4;
+
+/// Size of the trailer that ends padded payloads: the true message length
+/// and a CRC-32 over the message and that length (both u32, big endian)
+pub const PADDING_TRAILER_SIZE: usize = 8;
+
+bitflags! {
+    /// Flags stored in the header's flags byte
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+    pub struct HeaderFlags: u8 {
+        /// The payload is a container of named entries
+        const CONTAINER = 0x01;
+
+        /// The payload is one frame of a stream spread across images
+        const STREAM = 0x02;
+
+        /// The payload after the header is embedded four bits per pixel in the RGBA channels
+        const RGBA = 0x04;
+
+        /// RGBA payload pixels whose alpha is 0 were skipped
+        const SKIP_TRANSPARENT = 0x08;
+
+        /// Payload pixels after the header rotate which channel carries each bit
+        const ROTATED_CHANNELS = 0x10;
+
+        /// Payload pixels after the header assign bits to channels in a passphrase-derived order
+        const KEYED_CHANNELS = 0x20;
+
+        /// Pixels excluded by a mask were skipped; the last two header bytes give the embedded mask's length
+        const MASKED = 0x40;
+
+        /// The payload is encrypted with a passphrase (see [`crate::crypto`])
+        const ENCRYPTED = 0x80;
+    }
+}
+
+impl HeaderFlags {
+    /// Bits that are set but not registered to any feature
+    pub fn unknown_bits(self) -> u8 {
+        self.bits() & !Self::all().bits()
+    }
+}
+
+bitflags! {
+    /// Flags the flags byte has no room left for, stored in the extension of
+    /// version 2 headers
+    ///
+    /// Version 1 headers keep the first four in the top bits of their last
+    /// two bytes, above the embedded mask length of masked payloads; masks
+    /// are at most [`crate::mask::MAX_EMBEDDED_MASK_BYTES`] long, so those
+    /// bits are never needed for it.
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+    pub struct ExtraFlags: u16 {
+        /// The payload starts with the width and height of the cover it was
+        /// encoded in ([`DIMENSIONS_SIZE`] bytes, counted in the message length)
+        const RECORDS_DIMENSIONS = 0x8000;
+
+        /// The message is followed by random padding and a
+        /// [`PADDING_TRAILER_SIZE`]-byte trailer giving its true length; the
+        /// header's length counts all of it
+        const PADDED = 0x4000;
+
+        /// The message is deflated (see [`crate::compression`])
+        const COMPRESSED = 0x2000;
+
+        /// The payload ends with a [`CHECKSUM_SIZE`]-byte CRC-32 over the rest
+        /// of it, counted in the message length
+        const CHECKSUMMED = 0x1000;
+
+        /// The payload is split into blocks followed by Reed-Solomon parity (see
+        /// [`crate::ecc`]), counted in the message length
+        const ERROR_CORRECTED = 0x0800;
+
+        /// The payload after the header takes the two lowest bits of each
+        /// channel (see [`crate::depth`])
+        const TWO_BIT_DEPTH = 0x0400;
+    }
+}
+
+/// Extra flags with room in the last two bytes of version 1 headers
+const LEGACY_EXTRA_FLAGS: ExtraFlags = ExtraFlags::RECORDS_DIMENSIONS
+    .union(ExtraFlags::PADDED)
+    .union(ExtraFlags::COMPRESSED)
+    .union(ExtraFlags::CHECKSUMMED);
+
+/// Header fields read from a stego image, whatever its format version
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageHeader {
+    /// Message format version
+    pub format_version: u8,
+
+    /// Message length declared in the header (in bytes)
+    pub message_length: u32,
+
+    /// Header flags, including any bits this version does not know about
+    pub flags: HeaderFlags,
+
+    /// Length of the mask embedded after the header of a masked payload; 0 if none
+    pub embedded_mask_length: u16,
+
+    /// Flags the flags byte has no room for
+    pub extra_flags: ExtraFlags,
+
+    /// Length of the extension after the first [`HEADER_SIZE`] bytes of a
+    /// version 2 header; 0 for version 1
+    pub extension_length: u16,
+}
+
+impl MessageHeader {
+    /// The header of a payload with the given flags, in the version the encoder writes for them
+    ///
+    /// # Arguments
+    /// * `message_length` - Length of the payload after the header and embedded mask
+    /// * `flags` - Header flags
+    /// * `extra_flags` - Flags the flags byte has no room for
+    /// * `embedded_mask_length` - Length of the mask embedded after the header, 0 if none
+    pub fn new(
+        message_length: u32,
+        flags: HeaderFlags,
+        extra_flags: ExtraFlags,
+        embedded_mask_length: u16,
+    ) -> Self {
+        let version = HeaderVersion::for_flags(flags, extra_flags);
+        Self {
+            format_version: version.number(),
+            message_length,
+            flags,
+            embedded_mask_length,
+            extra_flags,
+            extension_length: version.extension_size() as u16,
+        }
+    }
+
+    /// Size of the header in bytes, extension included
+    pub fn size(&self) -> usize {
+        HEADER_SIZE + self.extension_length as usize
+    }
+
+    /// Number of pixels carrying the header, in the fixed channel order
+    pub fn pixels(&self) -> u64 {
+        img::pixels_for_bits(self.size() as u64 * 8)
+    }
+
+    /// The header bytes that start a payload
+    ///
+    /// The embedded mask length is only written for masked payloads; its
+    /// field is zero otherwise. Extension bytes past the fields this version
+    /// knows are written as zeros.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let embedded_mask_length = if self.is_masked() {
+            self.embedded_mask_length
+        } else {
+            0
+        };
+
+        let mut bytes = Vec::with_capacity(self.size());
+        bytes.push(self.format_version);
+        put_u32_be(&mut bytes, self.message_length);
+        bytes.push(self.flags.bits());
+        if !self.version().is_some_and(HeaderVersion::has_extension) {
+            // Unknown bits of unmasked headers have nowhere else to be
+            let (embedded_mask_length, extra_flags) = if self.is_masked() {
+                (
+                    embedded_mask_length & !LEGACY_EXTRA_FLAGS.bits(),
+                    self.extra_flags.intersection(LEGACY_EXTRA_FLAGS),
+                )
+            } else {
+                (0, self.extra_flags)
+            };
+            put_u16_be(&mut bytes, embedded_mask_length | extra_flags.bits());
+            return bytes;
+        }
+
+        put_u16_be(&mut bytes, self.extension_length);
+        put_u16_be(&mut bytes, self.extra_flags.bits());
+        put_u16_be(&mut bytes, embedded_mask_length);
+        bytes.resize(self.size(), 0);
+        bytes
+    }
+
+    /// The layout of the header, if this version knows it
+    fn version(&self) -> Option<HeaderVersion> {
+        HeaderVersion::from_byte(self.format_version)
+    }
+
+    /// Whether an extra flag is set, in a version that has room for it
+    fn has_extra_flag(&self, flag: ExtraFlags) -> bool {
+        self.version()
+            .is_some_and(|version| version.extra_flags().contains(flag))
+            && self.extra_flags.contains(flag)
+    }
+
+    /// Flag bits set in the header that this version doesn't know
+    ///
+    /// Unknown bits of the flags byte are given if there are any, otherwise
+    /// extra flag bits that no feature of the header's version uses.
+    pub fn unknown_flag_bits(&self) -> u16 {
+        match self.flags.unknown_bits() {
+            0 => {
+                let known = self
+                    .version()
+                    .map(HeaderVersion::extra_flags)
+                    .unwrap_or_default();
+                self.extra_flags.bits() & !known.bits()
+            }
+            bits => u16::from(bits),
+        }
+    }
+
+    /// Whether the message is deflated
+    pub fn is_compressed(&self) -> bool {
+        self.has_extra_flag(ExtraFlags::COMPRESSED)
+    }
+
+    /// Whether the payload ends with a CRC-32 over the rest of it
+    pub fn is_checksummed(&self) -> bool {
+        self.has_extra_flag(ExtraFlags::CHECKSUMMED)
+    }
+
+    /// Whether the payload carries Reed-Solomon parity
+    pub fn is_error_corrected(&self) -> bool {
+        self.has_extra_flag(ExtraFlags::ERROR_CORRECTED)
+    }
+
+    /// Whether the payload is encrypted with a passphrase
+    pub fn is_encrypted(&self) -> bool {
+        self.flags.contains(HeaderFlags::ENCRYPTED)
+    }
+
+    /// Whether the payload is a container of named entries
+    pub fn is_container(&self) -> bool {
+        self.flags.contains(HeaderFlags::CONTAINER)
+    }
+
+    /// Whether the payload is a frame of a stream spread across images
+    pub fn is_stream_frame(&self) -> bool {
+        self.flags.contains(HeaderFlags::STREAM)
+    }
+
+    /// Whether the payload is embedded in RGBA mode
+    pub fn is_rgba(&self) -> bool {
+        self.flags.contains(HeaderFlags::RGBA)
+    }
+
+    /// Whether an RGBA-mode payload skips pixels whose alpha is 0
+    pub fn skips_transparent(&self) -> bool {
+        self.flags.contains(HeaderFlags::SKIP_TRANSPARENT)
+    }
+
+    /// How the payload's bits are assigned to color channels
+    pub fn channel_schedule(&self) -> Result<ChannelSchedule> {
+        ChannelSchedule::from_flags(self.flags)
+    }
+
+    /// Whether the payload skips pixels excluded by a mask
+    pub fn is_masked(&self) -> bool {
+        self.flags.contains(HeaderFlags::MASKED)
+    }
+
+    /// Whether the payload starts with the width and height of the cover it was encoded in
+    pub fn records_dimensions(&self) -> bool {
+        self.has_extra_flag(ExtraFlags::RECORDS_DIMENSIONS)
+    }
+
+    /// Whether the payload is padded, so the header's length is not the message's
+    pub fn is_padded(&self) -> bool {
+        self.has_extra_flag(ExtraFlags::PADDED)
+    }
+
+    /// How many of each channel's lowest bits the payload after the header takes
+    pub fn embedding_depth(&self) -> EmbeddingDepth {
+        if self.has_extra_flag(ExtraFlags::TWO_BIT_DEPTH) {
+            EmbeddingDepth::Two
+        } else {
+            EmbeddingDepth::One
+        }
+    }
+}
+
+/// Message format versions, oldest first
+///
+/// Adding a version takes a variant here, its arms in the methods below and
+/// a fixture image under `tests/fixtures/format_versions`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum HeaderVersion {
+    /// Version byte, big endian length and flags byte, with the embedded mask
+    /// length of masked payloads in the last two bytes
+    V1,
+
+    /// The V1 layout up to the flags byte, then the length of an extension
+    /// holding the [`ExtraFlags`] and the embedded mask length
+    V2,
+}
+
+impl HeaderVersion {
+    /// Every version, oldest first
+    pub const ALL: [HeaderVersion; 2] = [Self::V1, Self::V2];
+
+    /// The version the encoder writes for payloads that set no flag
+    pub const CURRENT: HeaderVersion = Self::V1;
+
+    /// The version the encoder writes for a payload with the given flags
+    ///
+    /// Decoders that only know version 1 ignore the extra flags, so any
+    /// flag takes a version 2 header, which they refuse.
+    pub fn for_flags(flags: HeaderFlags, extra_flags: ExtraFlags) -> Self {
+        if flags.is_empty() && extra_flags.is_empty() {
+            Self::CURRENT
+        } else {
+            Self::V2
+        }
+    }
+
+    /// The version stored as a given leading header byte, if there is one
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|version| version.number() == byte)
+    }
+
+    /// The leading header byte of this version
+    pub fn number(self) -> u8 {
+        match self {
+            Self::V1 => 1,
+            Self::V2 => 2,
+        }
+    }
+
+    /// Whether headers of this version continue with an extension after the first [`HEADER_SIZE`] bytes
+    pub fn has_extension(self) -> bool {
+        self >= Self::V2
+    }
+
+    /// Size of the extension the encoder writes in headers of this version
+    pub fn extension_size(self) -> usize {
+        if self.has_extension() {
+            EXTENSION_SIZE
+        } else {
+            0
+        }
+    }
+
+    /// Extra flags headers of this version have room for
+    pub fn extra_flags(self) -> ExtraFlags {
+        if self.has_extension() {
+            ExtraFlags::all()
+        } else {
+            LEGACY_EXTRA_FLAGS
+        }
+    }
+
+    /// What this version changed, for the format changelog
+    pub fn changes(self) -> &'static str {
+        match self {
+            Self::V1 => {
+                "Initial layout: version, big endian message length, flags byte \
+                 and two bytes reserved for the embedded mask length"
+            }
+            Self::V2 => {
+                "The header continues with an extension of declared length holding \
+                 the extra flags and embedded mask length, so decoders that only know \
+                 version 1 refuse flagged payloads and later fields can be skipped"
+            }
+        }
+    }
+
+    /// Parse a header laid out in this version
+    ///
+    /// # Arguments
+    /// * `start` - The first [`HEADER_SIZE`] bytes of the header
+    /// * `extension` - The extension after them; empty in version 1
+    fn parse(self, start: &[u8; HEADER_SIZE], extension: &[u8]) -> MessageHeader {
+        match self {
+            Self::V1 => parse_v1(start),
+            Self::V2 => parse_v2(start, extension),
+        }
+    }
+}
+
+/// Parses the headers of every format version
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VersionedParser;
+
+impl VersionedParser {
+    /// Parse a header in whichever version its leading byte names
+    ///
+    /// # Arguments
+    /// * `bytes` - The first bytes of a payload; only the header's are read
+    ///
+    /// # Returns
+    /// * The header, [`HideError::NoMessageFound`] if there are too few
+    ///   bytes, or [`HideError::UnsupportedFeature`] naming an unknown version
+    pub fn parse(bytes: &[u8]) -> Result<MessageHeader> {
+        let (version, length) = Self::layout(bytes)?;
+        let (start, rest) = bytes
+            .split_first_chunk::<HEADER_SIZE>()
+            .ok_or(HideError::NoMessageFound)?;
+        let extension = rest
+            .get(..length - HEADER_SIZE)
+            .ok_or(HideError::NoMessageFound)?;
+        Ok(version.parse(start, extension))
+    }
+
+    /// Size of a header, extension included, from its first bytes
+    ///
+    /// Decoders read the first [`HEADER_SIZE`] bytes, then the rest of the
+    /// header this gives before parsing it.
+    ///
+    /// # Arguments
+    /// * `bytes` - The first bytes of a payload; only the first [`HEADER_SIZE`] are read
+    ///
+    /// # Returns
+    /// * The size in bytes, [`HideError::NoMessageFound`] if there are too few
+    ///   bytes, or [`HideError::UnsupportedFeature`] naming an unknown version
+    pub fn header_length(bytes: &[u8]) -> Result<usize> {
+        Self::layout(bytes).map(|(_, length)| length)
+    }
+
+    /// The version and size of a header, from its first [`HEADER_SIZE`] bytes
+    fn layout(bytes: &[u8]) -> Result<(HeaderVersion, usize)> {
+        let start = bytes
+            .first_chunk::<HEADER_SIZE>()
+            .ok_or(HideError::NoMessageFound)?;
+        let version = HeaderVersion::from_byte(start[0]).ok_or(HideError::UnsupportedFeature {
+            version: start[0],
+            bits: 0,
+        })?;
+
+        let mut length = HEADER_SIZE;
+        if version.has_extension() {
+            length += get_u16_be(start, EXTENSION_LENGTH_OFFSET).unwrap_or_default() as usize;
+        }
+        Ok((version, length))
+    }
+
+    /// The changelog of the format, as version numbers and their changes, oldest first
+    pub fn changelog() -> impl Iterator<Item = (u8, &'static str)> {
+        HeaderVersion::ALL
+            .into_iter()
+            .map(|version| (version.number(), version.changes()))
+    }
+}
+
+/// Append a `u16` to embedded data, big endian
+pub fn put_u16_be(bytes: &mut Vec<u8>, value: u16) {
+    bytes.extend_from_slice(&value.to_be_bytes());
+}
+
+/// Append a `u32` to embedded data, big endian
+pub fn put_u32_be(bytes: &mut Vec<u8>, value: u32) {
+    bytes.extend_from_slice(&value.to_be_bytes());
+}
+
+/// Read a big endian `u16` from embedded data
+///
+/// # Returns
+/// * The value starting at `offset`, or `None` if fewer than 2 bytes are left there
+pub fn get_u16_be(bytes: &[u8], offset: usize) -> Option<u16> {
+    let field = bytes.get(offset..)?.first_chunk::<2>()?;
+    Some(u16::from_be_bytes(*field))
+}
+
+/// Read a big endian `u32` from embedded data
+///
+/// # Returns
+/// * The value starting at `offset`, or `None` if fewer than 4 bytes are left there
+pub fn get_u32_be(bytes: &[u8], offset: usize) -> Option<u32> {
+    let field = bytes.get(offset..)?.first_chunk::<4>()?;
+    Some(u32::from_be_bytes(*field))
+}
+
+/// Parse a version 1 header
+fn parse_v1(bytes: &[u8; HEADER_SIZE]) -> MessageHeader {
+    let flags = HeaderFlags::from_bits_retain(bytes[FLAGS_OFFSET]);
+    // The fields all lie within a full header, so the reads can't come up short
+    let reserved = get_u16_be(bytes, MASK_LENGTH_OFFSET).unwrap_or_default();
+    // Bits that aren't the mask length are kept as flags even if no feature
+    // uses them, so the decoder can refuse them
+    let (embedded_mask_length, extra_bits) = if flags.contains(HeaderFlags::MASKED) {
+        (
+            reserved & !LEGACY_EXTRA_FLAGS.bits(),
+            reserved & LEGACY_EXTRA_FLAGS.bits(),
+        )
+    } else {
+        (0, reserved)
+    };
+    MessageHeader {
+        format_version: bytes[0],
+        message_length: get_u32_be(bytes, LENGTH_OFFSET).unwrap_or_default(),
+        flags,
+        embedded_mask_length,
+        extra_flags: ExtraFlags::from_bits_retain(extra_bits),
+        extension_length: 0,
+    }
+}
+
+/// Parse a version 2 header
+///
+/// Fields the extension is too short to hold read as 0, and bytes after
+/// the ones this version knows are skipped.
+///
+/// # Arguments
+/// * `bytes` - The first [`HEADER_SIZE`] bytes of the header
+/// * `extension` - The extension after them
+fn parse_v2(bytes: &[u8; HEADER_SIZE], extension: &[u8]) -> MessageHeader {
+    let flags = HeaderFlags::from_bits_retain(bytes[FLAGS_OFFSET]);
+    let field = |offset| get_u16_be(extension, offset - HEADER_SIZE).unwrap_or_default();
+    let embedded_mask_length = if flags.contains(HeaderFlags::MASKED) {
+        field(EXTENDED_MASK_LENGTH_OFFSET)
+    } else {
+        0
+    };
+
+    MessageHeader {
+        format_version: bytes[0],
+        message_length: get_u32_be(bytes, LENGTH_OFFSET).unwrap_or_default(),
+        flags,
+        embedded_mask_length,
+        extra_flags: ExtraFlags::from_bits_retain(field(EXTRA_FLAGS_OFFSET)),
+        extension_length: get_u16_be(bytes, EXTENSION_LENGTH_OFFSET).unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unknown_bits() {
+        assert_eq!(HeaderFlags::empty().unknown_bits(), 0);
+        assert_eq!(
+            (HeaderFlags::CONTAINER | HeaderFlags::STREAM).unknown_bits(),
+            0
+        );
+
+        // Every bit of the flags byte is registered
+        let flags = HeaderFlags::from_bits_retain(0x81);
+        assert!(flags.contains(HeaderFlags::CONTAINER | HeaderFlags::ENCRYPTED));
+        assert_eq!(flags.unknown_bits(), 0);
+        assert_eq!(HeaderFlags::all().bits(), 0xff);
+    }
+
+    #[test]
+    fn test_unknown_extra_flag_bits_are_kept() {
+        // Version 1 only has room for four extra flags
+        let header = VersionedParser::parse(&[1, 0, 0, 0, 5, 0, 0x10, 0x01]).unwrap();
+        assert_eq!(header.extra_flags.bits(), 0x1001);
+        assert_eq!(header.unknown_flag_bits(), 0x0001);
+        assert!(header.is_checksummed());
+        assert_eq!(header.to_bytes(), [1, 0, 0, 0, 5, 0, 0x10, 0x01]);
+
+        // In masked headers the bits below the flags are the mask length
+        let header = VersionedParser::parse(&[1, 0, 0, 0, 5, 0x40, 0x10, 0x01]).unwrap();
+        assert_eq!(header.embedded_mask_length, 1);
+        assert_eq!(header.unknown_flag_bits(), 0);
+
+        let header = VersionedParser::parse(&[2, 0, 0, 0, 5, 0, 0, 4, 0x00, 0x02, 0, 0]).unwrap();
+        assert_eq!(header.unknown_flag_bits(), 0x0002);
+        let header = VersionedParser::parse(&[2, 0, 0, 0, 5, 0, 0, 4, 0x10, 0x00, 0, 0]).unwrap();
+        assert_eq!(header.unknown_flag_bits(), 0);
+    }
+
+    #[test]
+    fn test_integers_are_big_endian() {
+        let mut bytes = vec![0xaa];
+        put_u16_be(&mut bytes, 0x0102);
+        put_u32_be(&mut bytes, 0x0304_0506);
+        assert_eq!(bytes, [0xaa, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06]);
+
+        assert_eq!(get_u16_be(&bytes, 1), Some(0x0102));
+        assert_eq!(get_u32_be(&bytes, 3), Some(0x0304_0506));
+        assert_eq!(get_u32_be(&bytes, 4), None);
+        assert_eq!(get_u16_be(&bytes, 100), None);
+    }
+
+    #[test]
+    fn test_header_bytes_round_trip() {
+        let header = MessageHeader {
+            format_version: 1,
+            message_length: 0x0102_0304,
+            flags: HeaderFlags::MASKED | HeaderFlags::CONTAINER,
+            embedded_mask_length: 0x0506,
+            extra_flags: ExtraFlags::empty(),
+            extension_length: 0,
+        };
+        let bytes = header.to_bytes();
+        assert_eq!(bytes, [1, 1, 2, 3, 4, 0x41, 5, 6]);
+        assert_eq!(VersionedParser::parse(&bytes).unwrap(), header);
+
+        // The extra flags share the mask length's field
+        let flagged = MessageHeader {
+            extra_flags: ExtraFlags::COMPRESSED | ExtraFlags::CHECKSUMMED,
+            ..header
+        };
+        assert_eq!(flagged.to_bytes(), [1, 1, 2, 3, 4, 0x41, 0x35, 6]);
+        assert_eq!(
+            VersionedParser::parse(&flagged.to_bytes()).unwrap(),
+            flagged
+        );
+        assert!(flagged.is_compressed() && flagged.is_checksummed());
+
+        // Reserved bytes stay zero unless the payload is masked
+        let unmasked = MessageHeader {
+            flags: HeaderFlags::CONTAINER,
+            ..header
+        };
+        assert_eq!(unmasked.to_bytes(), [1, 1, 2, 3, 4, 0x01, 0, 0]);
+        let unmasked = MessageHeader {
+            extra_flags: ExtraFlags::CHECKSUMMED,
+            ..unmasked
+        };
+        assert_eq!(unmasked.to_bytes(), [1, 1, 2, 3, 4, 0x01, 0x10, 0]);
+        let parsed = VersionedParser::parse(&unmasked.to_bytes()).unwrap();
+        assert!(parsed.is_checksummed() && !parsed.is_compressed());
+        assert_eq!(parsed.embedded_mask_length, 0);
+    }
+
+    #[test]
+    fn test_extended_header_round_trip() {
+        let header = MessageHeader::new(
+            0x0102_0304,
+            HeaderFlags::MASKED,
+            ExtraFlags::CHECKSUMMED | ExtraFlags::PADDED,
+            0x0506,
+        );
+        assert_eq!(header.format_version, HeaderVersion::V2.number());
+        assert_eq!(header.size(), EXTENDED_HEADER_SIZE);
+        assert_eq!(header.pixels(), 32);
+        let bytes = header.to_bytes();
+        assert_eq!(bytes, [2, 1, 2, 3, 4, 0x40, 0, 4, 0x50, 0, 5, 6]);
+        assert_eq!(VersionedParser::header_length(&bytes).unwrap(), 12);
+        assert_eq!(VersionedParser::parse(&bytes).unwrap(), header);
+        assert!(header.is_checksummed() && header.is_padded());
+        assert!(!header.records_dimensions());
+
+        // Longer extensions are skipped past the fields this version knows
+        let mut longer = bytes.clone();
+        longer[7] = 6;
+        longer.extend_from_slice(&[0xaa, 0xbb, 0xcc]);
+        assert_eq!(VersionedParser::header_length(&longer).unwrap(), 14);
+        let parsed = VersionedParser::parse(&longer).unwrap();
+        assert_eq!(parsed.extension_length, 6);
+        assert_eq!(parsed.embedded_mask_length, 0x0506);
+        assert_eq!(&parsed.to_bytes()[..12], &longer[..12]);
+        assert_eq!(&parsed.to_bytes()[12..], [0, 0]);
+
+        // Shorter ones leave the fields they can't hold at 0
+        let shorter = VersionedParser::parse(&[2, 0, 0, 0, 1, 0x40, 0, 2, 0x10, 0]).unwrap();
+        assert!(shorter.is_checksummed());
+        assert_eq!(shorter.embedded_mask_length, 0);
+        assert!(matches!(
+            VersionedParser::parse(&bytes[..11]),
+            Err(HideError::NoMessageFound)
+        ));
+    }
+
+    #[test]
+    fn test_flagless_payloads_keep_version_1() {
+        let plain = MessageHeader::new(3, HeaderFlags::empty(), ExtraFlags::empty(), 0);
+        assert_eq!(plain.format_version, HeaderVersion::CURRENT.number());
+        assert_eq!(plain.to_bytes(), [1, 0, 0, 0, 3, 0, 0, 0]);
+
+        for (flags, extra_flags) in [
+            (HeaderFlags::ENCRYPTED, ExtraFlags::empty()),
+            (HeaderFlags::empty(), ExtraFlags::COMPRESSED),
+            (HeaderFlags::empty(), ExtraFlags::RECORDS_DIMENSIONS),
+        ] {
+            let header = MessageHeader::new(3, flags, extra_flags, 0);
+            assert_eq!(header.format_version, HeaderVersion::V2.number());
+            assert_eq!(header.to_bytes().len(), EXTENDED_HEADER_SIZE);
+        }
+
+        // Version 1 headers have no room for the newer extra flags, so
+        // decoders refuse them as unknown
+        let legacy = MessageHeader {
+            extra_flags: ExtraFlags::ERROR_CORRECTED,
+            ..plain
+        };
+        assert!(!legacy.is_error_corrected());
+        assert_eq!(legacy.unknown_flag_bits(), 0x0800);
+        assert_eq!(legacy.to_bytes(), [1, 0, 0, 0, 3, 0, 0x08, 0]);
+    }
+
+    #[test]
+    fn test_versions_round_trip_through_their_byte() {
+        for version in HeaderVersion::ALL {
+            assert_eq!(HeaderVersion::from_byte(version.number()), Some(version));
+        }
+        assert_eq!(HeaderVersion::from_byte(0), None);
+        assert!(HeaderVersion::ALL.contains(&HeaderVersion::CURRENT));
+        assert!(HeaderVersion::ALL.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn test_parse_dispatches_on_the_version_byte() {
+        let header = VersionedParser::parse(&[1, 0, 0, 1, 2, 0x40, 0, 9]).unwrap();
+        assert_eq!(header.format_version, 1);
+        assert_eq!(header.message_length, 258);
+        assert!(header.is_masked());
+        assert_eq!(header.embedded_mask_length, 9);
+
+        assert!(matches!(
+            VersionedParser::parse(&[7, 0, 0, 0, 1, 0, 0, 0]),
+            Err(HideError::UnsupportedFeature {
+                version: 7,
+                bits: 0
+            })
+        ));
+        assert!(!header.records_dimensions());
+
+        // Extra flags sit above the mask length
+        let header = VersionedParser::parse(&[1, 0, 0, 0, 4, 0x40, 0x80, 9]).unwrap();
+        assert!(header.records_dimensions());
+        assert_eq!(header.embedded_mask_length, 9);
+        let header = VersionedParser::parse(&[1, 0, 0, 0, 4, 0, 0x80, 9]).unwrap();
+        assert!(header.records_dimensions() && !header.is_padded());
+        assert_eq!(header.embedded_mask_length, 0);
+        let header = VersionedParser::parse(&[1, 0, 0, 0, 12, 0, 0xc0, 0]).unwrap();
+        assert!(header.records_dimensions() && header.is_padded());
+        let header = VersionedParser::parse(&[2, 0, 0, 0, 12, 0, 0, 4, 0xc0, 0, 0, 0]).unwrap();
+        assert!(header.records_dimensions() && header.is_padded());
+        assert_eq!(header.size(), EXTENDED_HEADER_SIZE);
+        assert_eq!(header.embedding_depth(), EmbeddingDepth::One);
+        let two_bit = VersionedParser::parse(&[2, 0, 0, 0, 9, 0, 0, 4, 0x04, 0, 0, 0]).unwrap();
+        assert!(!two_bit.records_dimensions() && !two_bit.is_padded());
+        assert_eq!(two_bit.embedding_depth(), EmbeddingDepth::Two);
+        assert!(matches!(
+            VersionedParser::parse(&[1, 0, 0]),
+            Err(HideError::NoMessageFound)
+        ));
+    }
+}
@@ -1,45 +1,405 @@
 use crate::error::HideError;
+use crate::header::{get_u32_be, put_u32_be};
+use crate::layout::{PixelIndex, PixelX, PixelY};
+use crate::png_rows::PNG_SIGNATURE;
+use crate::utils;
 use crate::Result;
-use image::{DynamicImage, GenericImageView, ImageBuffer, Pixel, Rgb, Rgba};
+use bitflags::bitflags;
+use image::{
+    imageops, ColorType, DynamicImage, GenericImage, GenericImageView, ImageBuffer, ImageFormat,
+    Pixel, Rgb, Rgba, RgbaImage,
+};
+use rand::{rngs::StdRng, RngCore, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::fmt;
+use std::fs;
+use std::io::Cursor;
 use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
+
+/// Number of message bits each pixel can carry (one per RGB channel)
+pub const BITS_PER_PIXEL: u64 = 3;
+
+/// Largest pixel buffer [`StegoImage::new_rgb`] allocates, in bytes (1 GiB)
+pub const DEFAULT_MAX_IMAGE_BYTES: u64 = 1 << 30;
+
+/// Variance at which the noise component of the cover score reaches one half
+const NOISE_SCORE_SCALE: f64 = 100.0;
+
+/// Standard deviation of the blur [`FillStyle::Blur`] applies to mirrored content
+const EXTENSION_BLUR_SIGMA: f32 = 8.0;
+
+/// Statistics about an image, computed in a single pass over its pixels
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    /// Value histograms for the R, G and B channels
+    pub histograms: [[u32; 256]; 3],
+
+    /// Number of values with the least significant bit set, per channel
+    pub lsb_set: [u64; 3],
+
+    /// Total number of pixels
+    pub pixel_count: u64,
+
+    /// Mean variance of the brightness in each pixel's 3x3 neighbourhood
+    pub noise_estimate: f64,
+}
+
+impl Stats {
+    /// Compute statistics for an image
+    fn compute(image: &DynamicImage) -> Self {
+        let rgb = image.to_rgb8();
+        let (width, height) = rgb.dimensions();
+
+        let mut stats = Stats {
+            histograms: [[0; 256]; 3],
+            lsb_set: [0; 3],
+            pixel_count: width as u64 * height as u64,
+            noise_estimate: 0.0,
+        };
+
+        let brightness = |x: u32, y: u32| {
+            let p = rgb.get_pixel(x, y).0;
+            (p[0] as f64 + p[1] as f64 + p[2] as f64) / 3.0
+        };
+
+        let mut variance_sum = 0.0;
+        for (x, y, pixel) in rgb.enumerate_pixels() {
+            for ((histogram, lsb_set), &value) in stats
+                .histograms
+                .iter_mut()
+                .zip(stats.lsb_set.iter_mut())
+                .zip(pixel.0.iter())
+            {
+                // A u8 always indexes a 256-entry histogram
+                #[allow(clippy::indexing_slicing)]
+                {
+                    histogram[value as usize] += 1;
+                }
+                *lsb_set += (value & 1) as u64;
+            }
+
+            // Local variance over the neighbourhood, clamped at the edges
+            let (mut sum, mut sum_sq, mut n) = (0.0, 0.0, 0.0);
+            for ny in y.saturating_sub(1)..=(y + 1).min(height - 1) {
+                for nx in x.saturating_sub(1)..=(x + 1).min(width - 1) {
+                    let v = brightness(nx, ny);
+                    sum += v;
+                    sum_sq += v * v;
+                    n += 1.0;
+                }
+            }
+            let mean = sum / n;
+            variance_sum += sum_sq / n - mean * mean;
+        }
+
+        if stats.pixel_count > 0 {
+            stats.noise_estimate = variance_sum / stats.pixel_count as f64;
+        }
+
+        stats
+    }
+}
+
+/// Color management chunks found in the file an image was loaded from
+///
+/// Viewers that honor these may convert the pixels to another colorspace,
+/// which changes more than the LSBs and destroys any hidden payload.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ColorMetadata {
+    /// An embedded ICC profile (`iCCP`)
+    pub icc_profile: bool,
+
+    /// A gamma value (`gAMA`)
+    pub gamma: bool,
+
+    /// Primary chromaticities (`cHRM`)
+    pub chromaticities: bool,
+}
+
+impl ColorMetadata {
+    /// Read the color chunks of a PNG file; other formats have none
+    ///
+    /// Only the chunk headers before the image data are walked; nothing is
+    /// decompressed or validated.
+    pub fn from_png_bytes(bytes: &[u8]) -> Self {
+        let mut metadata = Self::default();
+        for (chunk_type, _) in png_header_chunks(bytes) {
+            match &chunk_type {
+                b"iCCP" => metadata.icc_profile = true,
+                b"gAMA" => metadata.gamma = true,
+                b"cHRM" => metadata.chromaticities = true,
+                _ => {}
+            }
+        }
+
+        metadata
+    }
+
+    /// Whether any color management chunk was present
+    pub fn is_present(&self) -> bool {
+        self.icc_profile || self.gamma || self.chromaticities
+    }
+}
+
+/// Meters in an inch, for converting the PNG's pixels per meter
+const METERS_PER_INCH: f64 = 0.0254;
+
+/// Pixel density from a PNG `pHYs` chunk
+///
+/// Layout and print software scale images by it, so stego images keep their
+/// cover's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PhysicalDimensions {
+    /// Pixels per unit horizontally
+    pub x_pixels_per_unit: u32,
+
+    /// Pixels per unit vertically
+    pub y_pixels_per_unit: u32,
+
+    /// Whether the unit is the meter; if not, only the aspect ratio is known
+    pub in_meters: bool,
+}
+
+impl PhysicalDimensions {
+    /// Size of the `pHYs` chunk's data
+    const CHUNK_SIZE: usize = 9;
+
+    /// Read the `pHYs` chunk of a PNG file; other formats have none
+    ///
+    /// Only the chunk headers before the image data are walked; nothing is
+    /// decompressed or validated.
+    pub fn from_png_bytes(bytes: &[u8]) -> Option<Self> {
+        let (_, data) = png_header_chunks(bytes).find(|(chunk_type, _)| chunk_type == b"pHYs")?;
+        if data.len() != Self::CHUNK_SIZE {
+            return None;
+        }
+        Some(Self {
+            x_pixels_per_unit: get_u32_be(data, 0)?,
+            y_pixels_per_unit: get_u32_be(data, 4)?,
+            in_meters: data.get(8) == Some(&1),
+        })
+    }
+
+    /// A density given in dots per inch
+    pub fn from_dpi(x: u32, y: u32) -> Self {
+        let per_meter = |dpi: u32| (f64::from(dpi) / METERS_PER_INCH).round() as u32;
+        Self {
+            x_pixels_per_unit: per_meter(x),
+            y_pixels_per_unit: per_meter(y),
+            in_meters: true,
+        }
+    }
+
+    /// The density in dots per inch, if the unit is known
+    pub fn dpi(&self) -> Option<(u32, u32)> {
+        let per_inch = |per_meter: u32| (f64::from(per_meter) * METERS_PER_INCH).round() as u32;
+        self.in_meters.then(|| {
+            (
+                per_inch(self.x_pixels_per_unit),
+                per_inch(self.y_pixels_per_unit),
+            )
+        })
+    }
+
+    /// The `pHYs` chunk, from its length to its CRC
+    fn to_chunk(self) -> Vec<u8> {
+        let mut chunk = Vec::with_capacity(Self::CHUNK_SIZE + 12);
+        put_u32_be(&mut chunk, Self::CHUNK_SIZE as u32);
+        chunk.extend_from_slice(b"pHYs");
+        put_u32_be(&mut chunk, self.x_pixels_per_unit);
+        put_u32_be(&mut chunk, self.y_pixels_per_unit);
+        chunk.push(u8::from(self.in_meters));
+        let crc = utils::crc32(chunk.get(4..).unwrap_or_default());
+        put_u32_be(&mut chunk, crc);
+        chunk
+    }
+}
+
+/// The chunks of a PNG file before its image data, as their type and data
+///
+/// Ancillary chunks describing the image must come before the image data,
+/// so the walk stops there. Nothing is decompressed or validated; a chunk cut
+/// short by the end of the file is returned with the data there is.
+fn png_header_chunks(bytes: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    let mut rest = bytes.strip_prefix(&PNG_SIGNATURE).unwrap_or_default();
+    std::iter::from_fn(move || {
+        // Each chunk is a length, a type, the data and a CRC
+        let (length, chunk) = rest.split_first_chunk::<4>()?;
+        let (chunk_type, data) = chunk.split_first_chunk::<4>()?;
+        if chunk_type == b"IDAT" || chunk_type == b"IEND" {
+            return None;
+        }
+        let length = u32::from_be_bytes(*length) as usize;
+        let data = data.get(..length).unwrap_or(data);
+        rest = rest.get(length.saturating_add(12)..).unwrap_or_default();
+        Some((*chunk_type, data))
+    })
+}
+
+/// Put a `pHYs` chunk right after the `IHDR` chunk of a PNG file
+///
+/// Files written by the image crate have no `pHYs` chunk of their own.
+fn insert_phys_chunk(png: &mut Vec<u8>, physical: PhysicalDimensions) {
+    let ihdr_length = png
+        .get(PNG_SIGNATURE.len()..)
+        .and_then(|rest| get_u32_be(rest, 0))
+        .unwrap_or(0) as usize;
+    let ihdr_end = PNG_SIGNATURE.len() + ihdr_length + 12;
+    if ihdr_end <= png.len() {
+        png.splice(ihdr_end..ihdr_end, physical.to_chunk());
+    }
+}
 
 /// Represents an image that can be used for steganography
+///
+/// Images are `Send` and `Sync`. They can be moved to another thread to encode
+/// or decode, and shared for reading, since the statistics cache is a `OnceLock`.
+///
+/// Clones share their pixel buffer until one of them is modified; the first
+/// write to a shared buffer copies it, so the other handles never see it.
 #[derive(Clone)]
 pub struct StegoImage {
-    /// The underlying image data
-    image: DynamicImage,
+    /// The underlying image data, shared between clones until either writes
+    image: Arc<DynamicImage>,
     /// Whether the image has been modified
     modified: bool,
+    /// Cached statistics, cleared whenever the image is modified
+    stats: OnceLock<Stats>,
+    /// Color chunks of the file the image was loaded from
+    color: ColorMetadata,
+    /// Pixel density of the file the image was loaded from, or as set
+    physical: Option<PhysicalDimensions>,
 }
 
+static_assertions::assert_impl_all!(StegoImage: Send, Sync);
+
 impl StegoImage {
     /// Load an image from a file
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let image = image::open(path)?;
+        let bytes = fs::read(path)?;
+        Self::from_bytes(&bytes)
+    }
 
-        Ok(Self {
-            image,
-            modified: false,
-        })
+    /// Load an image from the contents of an image file
+    ///
+    /// # Arguments
+    /// * `bytes` - The encoded image, in any format the image crate can read
+    ///
+    /// # Returns
+    /// * The image, with the color chunks of PNG files recorded
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let image = image::load_from_memory(bytes)?;
+
+        // Reject degenerate images up front rather than failing later
+        if image.width() == 0 || image.height() == 0 {
+            return Err(HideError::InvalidParameters(format!(
+                "Image has zero dimension ({}x{})",
+                image.width(),
+                image.height()
+            )));
+        }
+
+        let mut loaded = Self::from_dynamic_image(image);
+        loaded.color = ColorMetadata::from_png_bytes(bytes);
+        loaded.physical = PhysicalDimensions::from_png_bytes(bytes);
+        Ok(loaded)
     }
 
     /// Create a new StegoImage from a DynamicImage
     pub fn from_dynamic_image(image: DynamicImage) -> Self {
         Self {
-            image,
+            image: Arc::new(image),
             modified: false,
+            stats: OnceLock::new(),
+            color: ColorMetadata::default(),
+            physical: None,
         }
     }
 
-    /// Create a new RGB image with the specified dimensions
-    pub fn new_rgb(width: u32, height: u32) -> Self {
-        let img_buffer = ImageBuffer::new(width, height);
-        let image = DynamicImage::ImageRgb8(img_buffer);
+    /// Color chunks found in the file the image was loaded from
+    pub fn color_metadata(&self) -> ColorMetadata {
+        self.color
+    }
 
-        Self {
-            image,
-            modified: false,
+    /// Whether the source file carried an ICC profile or gamma/chromaticity chunks
+    pub fn has_color_profile(&self) -> bool {
+        self.color.is_present()
+    }
+
+    /// Pixel density of the file the image was loaded from, or as set
+    ///
+    /// PNG saves write it as a `pHYs` chunk.
+    pub fn physical_dimensions(&self) -> Option<PhysicalDimensions> {
+        self.physical
+    }
+
+    /// Pixel density in dots per inch, if one is known in physical units
+    pub fn dpi(&self) -> Option<(u32, u32)> {
+        self.physical.and_then(|physical| physical.dpi())
+    }
+
+    /// Set the pixel density PNG saves write, in dots per inch
+    ///
+    /// # Arguments
+    /// * `x` - Horizontal density
+    /// * `y` - Vertical density
+    pub fn set_dpi(&mut self, x: u32, y: u32) {
+        self.physical = Some(PhysicalDimensions::from_dpi(x, y));
+    }
+
+    /// Create a new black RGB image with the specified dimensions
+    ///
+    /// The pixel buffer may take at most [`DEFAULT_MAX_IMAGE_BYTES`]; use
+    /// [`StegoImage::new_rgb_with_limit`] for another cap.
+    pub fn new_rgb(width: u32, height: u32) -> Result<Self> {
+        Self::new_rgb_with_limit(width, height, DEFAULT_MAX_IMAGE_BYTES)
+    }
+
+    /// Create a new black RGB image, capping the size of its pixel buffer
+    ///
+    /// # Arguments
+    /// * `width` - Image width, at least 1
+    /// * `height` - Image height, at least 1
+    /// * `max_bytes` - Largest pixel buffer to allocate
+    ///
+    /// # Returns
+    /// * The image, or an error for empty or oversized dimensions
+    pub fn new_rgb_with_limit(width: u32, height: u32, max_bytes: u64) -> Result<Self> {
+        if width == 0 || height == 0 {
+            return Err(HideError::InvalidParameters(format!(
+                "Image dimensions must be non-zero, got {}x{}",
+                width, height
+            )));
+        }
+
+        let requested = pixel_count(width, height).saturating_mul(3);
+        let too_large = || HideError::AllocationTooLarge {
+            requested,
+            limit: max_bytes,
+        };
+        if requested > max_bytes {
+            return Err(too_large());
         }
+
+        // Fail with an error rather than aborting if the memory isn't there
+        let len = usize::try_from(requested).map_err(|_| too_large())?;
+        let mut buffer = Vec::new();
+        buffer.try_reserve_exact(len).map_err(|_| too_large())?;
+        buffer.resize(len, 0);
+
+        let img_buffer = ImageBuffer::from_raw(width, height, buffer).ok_or_else(|| {
+            HideError::InvalidParameters(format!(
+                "Pixel buffer does not match {}x{} dimensions",
+                width, height
+            ))
+        })?;
+
+        Ok(Self::from_dynamic_image(DynamicImage::ImageRgb8(
+            img_buffer,
+        )))
     }
 
     /// Get the image width
@@ -63,9 +423,19 @@ impl StegoImage {
     }
 
     /// Get a mutable reference to the underlying dynamic image
+    ///
+    /// A buffer shared with clones is copied first.
     pub fn inner_mut(&mut self) -> &mut DynamicImage {
-        self.modified = true;
-        &mut self.image
+        self.mark_modified();
+        Arc::make_mut(&mut self.image)
+    }
+
+    /// Whether the pixel buffer is shared with clones of this image
+    ///
+    /// The next write to a shared image copies its buffer, leaving the
+    /// clones as they were.
+    pub fn is_shared(&self) -> bool {
+        Arc::strong_count(&self.image) > 1
     }
 
     /// Get the RGB value of a pixel at the given coordinates
@@ -95,20 +465,20 @@ impl StegoImage {
             )));
         }
 
-        // Convert to the appropriate image format if needed
-        match self.image {
-            DynamicImage::ImageRgb8(ref mut img) => {
+        // Convert to the appropriate image format if needed; the conversion
+        // makes a new buffer, so only writes in place copy a shared one
+        if is_rgb8(&self.image) {
+            if let DynamicImage::ImageRgb8(img) = Arc::make_mut(&mut self.image) {
                 img.put_pixel(x, y, pixel);
             }
-            _ => {
-                // For other formats, we need to convert to RGB first
-                let mut rgb_img = self.image.to_rgb8();
-                rgb_img.put_pixel(x, y, pixel);
-                self.image = DynamicImage::ImageRgb8(rgb_img);
-            }
+        } else {
+            // For other formats, we need to convert to RGB first
+            let mut rgb_img = self.image.to_rgb8();
+            rgb_img.put_pixel(x, y, pixel);
+            self.image = Arc::new(DynamicImage::ImageRgb8(rgb_img));
         }
 
-        self.modified = true;
+        self.mark_modified();
         Ok(())
     }
 
@@ -140,37 +510,33 @@ impl StegoImage {
         }
 
         // Convert to the appropriate image format if needed
-        match self.image {
-            DynamicImage::ImageRgba8(ref mut img) => {
+        if is_rgba8(&self.image) {
+            if let DynamicImage::ImageRgba8(img) = Arc::make_mut(&mut self.image) {
                 img.put_pixel(x, y, pixel);
             }
-            _ => {
-                // For other formats, we need to convert to RGBA first
-                let mut rgba_img = self.image.to_rgba8();
-                rgba_img.put_pixel(x, y, pixel);
-                self.image = DynamicImage::ImageRgba8(rgba_img);
-            }
+        } else {
+            // For other formats, we need to convert to RGBA first
+            let mut rgba_img = self.image.to_rgba8();
+            rgba_img.put_pixel(x, y, pixel);
+            self.image = Arc::new(DynamicImage::ImageRgba8(rgba_img));
         }
 
-        self.modified = true;
+        self.mark_modified();
         Ok(())
     }
 
     /// Modify the least significant bit of a color channel
     pub fn set_lsb(&mut self, x: u32, y: u32, channel: usize, bit: bool) -> Result<()> {
-        if channel > 2 {
-            return Err(HideError::InvalidParameters(format!(
-                "Invalid color channel index: {}. Must be 0 (R), 1 (G), or 2 (B)",
-                channel
-            )));
-        }
-
         let mut pixel = self.get_pixel_rgb(x, y)?;
+        let value = pixel
+            .0
+            .get_mut(channel)
+            .ok_or_else(|| invalid_channel(channel))?;
 
         if bit {
-            pixel.0[channel] |= 1; // Set LSB to 1
+            *value |= 1; // Set LSB to 1
         } else {
-            pixel.0[channel] &= !1; // Set LSB to 0
+            *value &= !1; // Set LSB to 0
         }
 
         self.set_pixel_rgb(x, y, pixel)
@@ -178,38 +544,64 @@ impl StegoImage {
 
     /// Get the least significant bit of a color channel
     pub fn get_lsb(&self, x: u32, y: u32, channel: usize) -> Result<bool> {
-        if channel > 2 {
-            return Err(HideError::InvalidParameters(format!(
-                "Invalid color channel index: {}. Must be 0 (R), 1 (G), or 2 (B)",
-                channel
-            )));
-        }
-
         let pixel = self.get_pixel_rgb(x, y)?;
-        Ok((pixel.0[channel] & 1) == 1)
+        let value = pixel
+            .0
+            .get(channel)
+            .ok_or_else(|| invalid_channel(channel))?;
+        Ok((value & 1) == 1)
     }
 
     /// Save the image to a file
+    ///
+    /// The format comes from the extension. PNG files get a `pHYs` chunk
+    /// when the image has a pixel density.
     pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        if self.physical.is_some() && ImageFormat::from_path(path).ok() == Some(ImageFormat::Png) {
+            fs::write(path, self.to_bytes(ImageFormat::Png)?)?;
+            return Ok(());
+        }
+
         self.image.save(path)?;
         Ok(())
     }
 
+    /// Encode the image as the contents of an image file
+    ///
+    /// # Arguments
+    /// * `format` - Format to encode in; only lossless formats keep a payload
+    ///
+    /// # Returns
+    /// * The encoded image, or `LossyFormatRejected` for lossy formats
+    pub fn to_bytes(&self, format: ImageFormat) -> Result<Vec<u8>> {
+        if !is_lossless_format(format) {
+            return Err(HideError::LossyFormatRejected(format!("{:?}", format)));
+        }
+
+        let mut bytes = Vec::new();
+        self.image.write_to(&mut Cursor::new(&mut bytes), format)?;
+        if let (Some(physical), ImageFormat::Png) = (self.physical, format) {
+            insert_phys_chunk(&mut bytes, physical);
+        }
+        Ok(bytes)
+    }
+
     /// Convert the image to RGB format
     pub fn to_rgb(&mut self) {
-        if !matches!(self.image, DynamicImage::ImageRgb8(_)) {
+        if !is_rgb8(&self.image) {
             let rgb_image = self.image.to_rgb8();
-            self.image = DynamicImage::ImageRgb8(rgb_image);
-            self.modified = true;
+            self.image = Arc::new(DynamicImage::ImageRgb8(rgb_image));
+            self.mark_modified();
         }
     }
 
     /// Convert the image to RGBA format
     pub fn to_rgba(&mut self) {
-        if !matches!(self.image, DynamicImage::ImageRgba8(_)) {
+        if !is_rgba8(&self.image) {
             let rgba_image = self.image.to_rgba8();
-            self.image = DynamicImage::ImageRgba8(rgba_image);
-            self.modified = true;
+            self.image = Arc::new(DynamicImage::ImageRgba8(rgba_image));
+            self.mark_modified();
         }
     }
 
@@ -218,120 +610,1402 @@ impl StegoImage {
         self.modified
     }
 
+    /// Flag the image as modified and drop any cached statistics
+    fn mark_modified(&mut self) {
+        self.modified = true;
+        self.stats = OnceLock::new();
+    }
+
+    /// Get statistics about the image, computing them on first use
+    pub fn stats(&self) -> &Stats {
+        self.stats.get_or_init(|| Stats::compute(&self.image))
+    }
+
+    /// Get the value histogram of a color channel
+    pub fn histogram(&self, channel: usize) -> Result<[u32; 256]> {
+        self.stats()
+            .histograms
+            .get(channel)
+            .copied()
+            .ok_or_else(|| invalid_channel(channel))
+    }
+
+    /// Get the fraction of values in a color channel whose LSB is set
+    pub fn lsb_bias(&self, channel: usize) -> Result<f64> {
+        let stats = self.stats();
+        let lsb_set = stats
+            .lsb_set
+            .get(channel)
+            .ok_or_else(|| invalid_channel(channel))?;
+        if stats.pixel_count == 0 {
+            return Ok(0.0);
+        }
+
+        Ok(*lsb_set as f64 / stats.pixel_count as f64)
+    }
+
+    /// Get the fraction of LSBs set in each color channel over the first pixels
+    ///
+    /// Counting only the pixels that carry a payload shows how evenly its
+    /// bits are spread over the channels, which [`StegoImage::lsb_bias`]
+    /// dilutes with the rest of the image.
+    ///
+    /// # Arguments
+    /// * `pixels` - Number of pixels to count, in row-major order
+    ///
+    /// # Returns
+    /// * The fraction for the R, G and B channels
+    pub fn payload_lsb_bias(&self, pixels: u64) -> Result<[f64; 3]> {
+        let pixels = pixels.min(pixel_count(self.width(), self.height()));
+        if pixels == 0 {
+            return Ok([0.0; 3]);
+        }
+
+        let mut lsb_set = [0u64; 3];
+        for index in 0..pixels {
+            let pixel = PixelSurface::get_pixel_at(self, PixelIndex(index))?;
+            for (count, value) in lsb_set.iter_mut().zip(pixel.0) {
+                *count += (value & 1) as u64;
+            }
+        }
+
+        Ok(lsb_set.map(|count| count as f64 / pixels as f64))
+    }
+
+    /// Estimate the image noise as the mean local brightness variance
+    pub fn noise_estimate(&self) -> f64 {
+        self.stats().noise_estimate
+    }
+
     /// Calculate the maximum message size (in bytes) that can be stored in this image
     /// Each pixel can store 3 bits (one in each RGB channel)
     pub fn max_message_size(&self) -> usize {
-        let total_pixels = self.width() * self.height();
-        let total_bits = total_pixels * 3; // 3 bits per pixel (R,G,B)
-        total_bits as usize / 8 // convert to bytes
+        capacity(self.width(), self.height())
     }
-}
 
-/// Create a new blank RGB image with the specified dimensions
-pub fn create_rgb_image(width: u32, height: u32) -> StegoImage {
-    StegoImage::new_rgb(width, height)
+    /// Borrow a rectangular window of the image for in-place encoding
+    ///
+    /// # Arguments
+    /// * `x`, `y` - Top-left corner of the window
+    /// * `width`, `height` - Size of the window
+    ///
+    /// # Returns
+    /// * The view, or an error if the window is empty or extends past the image
+    pub fn view_mut(&mut self, x: u32, y: u32, width: u32, height: u32) -> Result<StegoView<'_>> {
+        let rect = Rect::new(x, y, width, height);
+        rect.check_within(self.width(), self.height())?;
+        Ok(StegoView { image: self, rect })
+    }
 }
 
-/// Load an image from a file
-pub fn load_image<P: AsRef<Path>>(path: P) -> Result<StegoImage> {
-    StegoImage::from_file(path)
+/// A rectangular region of an image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    /// Left edge of the region
+    pub x: u32,
+
+    /// Top edge of the region
+    pub y: u32,
+
+    /// Width of the region in pixels
+    pub width: u32,
+
+    /// Height of the region in pixels
+    pub height: u32,
 }
 
-/// Save an image to a file
-pub fn save_image<P: AsRef<Path>>(image: &StegoImage, path: P) -> Result<()> {
-    image.save(path)
+impl Rect {
+    /// Create a rectangle from its top-left corner and size
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Check that the rectangle is non-empty and lies within an image
+    pub(crate) fn check_within(&self, width: u32, height: u32) -> Result<()> {
+        let fits = |start: u32, len: u32, limit: u32| {
+            start.checked_add(len).is_some_and(|end| end <= limit)
+        };
+        if self.width == 0
+            || self.height == 0
+            || !fits(self.x, self.width, width)
+            || !fits(self.y, self.height, height)
+        {
+            return Err(HideError::InvalidParameters(format!(
+                "Region {}x{} at ({}, {}) does not fit in a {}x{} image",
+                self.width, self.height, self.x, self.y, width, height
+            )));
+        }
+        Ok(())
+    }
+
+    /// Whether a pixel lies inside the rectangle
+    pub(crate) fn contains(&self, x: u32, y: u32) -> bool {
+        let inside = |value: u32, start: u32, len: u32| {
+            value >= start && u64::from(value) < u64::from(start) + u64::from(len)
+        };
+        inside(x, self.x, self.width) && inside(y, self.y, self.height)
+    }
+
+    /// Whether the rectangle shares any pixel with another
+    pub(crate) fn overlaps(&self, other: &Rect) -> bool {
+        let apart = |start: u32, len: u32, other_start: u32, other_len: u32| {
+            u64::from(start) + u64::from(len) <= u64::from(other_start)
+                || u64::from(other_start) + u64::from(other_len) <= u64::from(start)
+        };
+        !apart(self.x, self.width, other.x, other.width)
+            && !apart(self.y, self.height, other.y, other.height)
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use image::ImageFormat;
-    use std::io::Cursor;
+/// Pixel access shared by whole images and views into them
+///
+/// The encoder embeds through this trait, so the same code serves images and
+/// borrowed windows.
+pub trait PixelSurface {
+    /// Width in pixels
+    fn width(&self) -> u32;
 
-    fn create_test_image() -> StegoImage {
-        let img_data = vec![
-            255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 0, 0, 255, 255, 255, 0, 255, 128, 128, 128,
-            255, 255, 255, 0, 0, 0, 100, 100, 100, 50, 150, 250, 200, 50, 150,
-        ];
+    /// Height in pixels
+    fn height(&self) -> u32;
 
-        let img_buffer = ImageBuffer::from_raw(4, 3, img_data).unwrap();
-        StegoImage::from_dynamic_image(DynamicImage::ImageRgb8(img_buffer))
+    /// Get the RGB value of a pixel
+    fn get_pixel_rgb(&self, x: u32, y: u32) -> Result<Rgb<u8>>;
+
+    /// Set the RGB value of a pixel
+    fn set_pixel_rgb(&mut self, x: u32, y: u32, pixel: Rgb<u8>) -> Result<()>;
+
+    /// Get the RGB value of the pixel at a row-major index
+    fn get_pixel_at(&self, index: PixelIndex) -> Result<Rgb<u8>> {
+        let (x, y) = index.to_xy(self.width());
+        self.get_pixel_rgb(x.get(), y.get())
     }
 
-    #[test]
-    fn test_image_dimensions() {
-        let img = create_test_image();
-        assert_eq!(img.width(), 4);
-        assert_eq!(img.height(), 3);
-        assert_eq!(img.dimensions(), (4, 3));
+    /// Set the RGB value of the pixel at a row-major index
+    fn set_pixel_at(&mut self, index: PixelIndex, pixel: Rgb<u8>) -> Result<()> {
+        let (x, y) = index.to_xy(self.width());
+        self.set_pixel_rgb(x.get(), y.get(), pixel)
     }
+}
 
-    #[test]
-    fn test_get_set_pixel_rgb() {
-        let mut img = create_test_image();
+impl PixelSurface for StegoImage {
+    fn width(&self) -> u32 {
+        StegoImage::width(self)
+    }
 
-        // Test getting a pixel
-        let pixel = img.get_pixel_rgb(0, 0).unwrap();
-        assert_eq!(pixel.0, [255, 0, 0]);
+    fn height(&self) -> u32 {
+        StegoImage::height(self)
+    }
 
-        // Test setting a pixel
-        img.set_pixel_rgb(0, 0, Rgb([10, 20, 30])).unwrap();
-        let updated_pixel = img.get_pixel_rgb(0, 0).unwrap();
-        assert_eq!(updated_pixel.0, [10, 20, 30]);
+    fn get_pixel_rgb(&self, x: u32, y: u32) -> Result<Rgb<u8>> {
+        StegoImage::get_pixel_rgb(self, x, y)
+    }
 
-        // Test out of bounds access
-        assert!(img.get_pixel_rgb(10, 10).is_err());
-        assert!(img.set_pixel_rgb(10, 10, Rgb([0, 0, 0])).is_err());
+    fn set_pixel_rgb(&mut self, x: u32, y: u32, pixel: Rgb<u8>) -> Result<()> {
+        StegoImage::set_pixel_rgb(self, x, y, pixel)
     }
+}
 
-    #[test]
-    fn test_lsb_operations() {
-        let mut img = create_test_image();
+/// A mutable window into part of an image
+///
+/// Coordinates are relative to the window's top-left corner; pixels outside
+/// the window can't be reached through the view.
+pub struct StegoView<'a> {
+    /// The image the window belongs to
+    image: &'a mut StegoImage,
 
-        // Test getting LSB
-        assert!(img.get_lsb(0, 0, 0).unwrap()); // Red: 255 (odd)
-        assert!(!img.get_lsb(0, 0, 1).unwrap()); // Green: 0 (even)
-        assert!(!img.get_lsb(0, 0, 2).unwrap()); // Blue: 0 (even)
+    /// Position and size of the window
+    rect: Rect,
+}
 
-        // Test setting LSB
-        img.set_lsb(0, 0, 0, false).unwrap(); // Set red LSB to 0
-        assert!(!img.get_lsb(0, 0, 0).unwrap());
-        assert_eq!(img.get_pixel_rgb(0, 0).unwrap().0[0], 254); // 255 -> 254
+impl StegoView<'_> {
+    /// Get the region of the image covered by this view
+    pub fn rect(&self) -> Rect {
+        self.rect
+    }
 
-        img.set_lsb(0, 0, 1, true).unwrap(); // Set green LSB to 1
-        assert!(img.get_lsb(0, 0, 1).unwrap());
-        assert_eq!(img.get_pixel_rgb(0, 0).unwrap().0[1], 1); // 0 -> 1
+    /// Check that view coordinates fall inside the window
+    fn check_bounds(&self, x: u32, y: u32) -> Result<()> {
+        if x >= self.rect.width || y >= self.rect.height {
+            return Err(HideError::InvalidParameters(format!(
+                "Coordinates ({}, {}) out of view bounds ({}x{})",
+                x, y, self.rect.width, self.rect.height
+            )));
+        }
+        Ok(())
+    }
+}
 
-        // Test invalid channel
-        assert!(img.get_lsb(0, 0, 3).is_err());
-        assert!(img.set_lsb(0, 0, 3, true).is_err());
+impl PixelSurface for StegoView<'_> {
+    fn width(&self) -> u32 {
+        self.rect.width
     }
 
-    #[test]
-    fn test_save_load_image() {
-        let img = create_test_image();
+    fn height(&self) -> u32 {
+        self.rect.height
+    }
 
-        // Save to memory buffer
-        let mut buffer = Cursor::new(Vec::new());
-        img.inner().write_to(&mut buffer, ImageFormat::Png).unwrap();
+    fn get_pixel_rgb(&self, x: u32, y: u32) -> Result<Rgb<u8>> {
+        self.check_bounds(x, y)?;
+        self.image.get_pixel_rgb(self.rect.x + x, self.rect.y + y)
+    }
 
-        // Load from memory buffer
-        buffer.set_position(0);
-        let loaded = image::load(buffer, ImageFormat::Png).unwrap();
-        let loaded_img = StegoImage::from_dynamic_image(loaded);
+    fn set_pixel_rgb(&mut self, x: u32, y: u32, pixel: Rgb<u8>) -> Result<()> {
+        self.check_bounds(x, y)?;
+        self.image
+            .set_pixel_rgb(self.rect.x + x, self.rect.y + y, pixel)
+    }
+}
 
-        // Verify dimensions match
-        assert_eq!(loaded_img.dimensions(), img.dimensions());
+/// Dimensions of the image a set of tiles was cut from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TileLayout {
+    /// Width of the full image in pixels
+    pub width: u32,
 
-        // Verify some pixel values
-        assert_eq!(
-            loaded_img.get_pixel_rgb(0, 0).unwrap().0,
-            img.get_pixel_rgb(0, 0).unwrap().0
-        );
-        assert_eq!(
-            loaded_img.get_pixel_rgb(1, 1).unwrap().0,
-            img.get_pixel_rgb(1, 1).unwrap().0
-        );
+    /// Height of the full image in pixels
+    pub height: u32,
+}
+
+/// Tiles placed at their offsets in a larger image, read without assembling it
+///
+/// Pixels no tile covers can't be read. Lookups start at the tile the last
+/// pixel came from, so reading in row-major order goes through the tiles of
+/// a row in turn instead of searching all of them for every pixel.
+pub struct TiledImage<'a> {
+    /// Where each tile lies in the full image, and the tile
+    tiles: Vec<(Rect, &'a StegoImage)>,
+
+    /// Dimensions of the full image
+    layout: TileLayout,
+
+    /// Index of the tile the last lookup found
+    last: Cell<usize>,
+}
+
+impl<'a> TiledImage<'a> {
+    /// Place tiles in an image
+    ///
+    /// # Arguments
+    /// * `tiles` - The top-left corner of each tile in the full image, and the tile
+    /// * `layout` - Dimensions of the full image
+    ///
+    /// # Returns
+    /// * The tiled image, or an error if a tile is empty, extends past the
+    ///   image or overlaps another
+    pub fn new(tiles: &'a [(u32, u32, StegoImage)], layout: TileLayout) -> Result<Self> {
+        let rects = check_tiles(tiles, layout)?;
+        Ok(Self {
+            tiles: rects
+                .into_iter()
+                .zip(tiles.iter().map(|(_, _, tile)| tile))
+                .collect(),
+            layout,
+            last: Cell::new(0),
+        })
+    }
+
+    /// Whether a tile covers the pixel at a row-major index of the full image
+    pub fn covers(&self, index: PixelIndex) -> bool {
+        let (x, y) = index.to_xy(self.layout.width);
+        self.tile_at(x.get(), y.get()).is_some()
+    }
+
+    /// The tile covering a pixel, and where it lies
+    fn tile_at(&self, x: u32, y: u32) -> Option<(Rect, &'a StegoImage)> {
+        let last = self.last.get();
+        if let Some(&(rect, tile)) = self.tiles.get(last) {
+            if rect.contains(x, y) {
+                return Some((rect, tile));
+            }
+        }
+
+        let index = self
+            .tiles
+            .iter()
+            .position(|(rect, _)| rect.contains(x, y))?;
+        self.last.set(index);
+        self.tiles.get(index).copied()
+    }
+}
+
+impl PixelSurface for TiledImage<'_> {
+    fn width(&self) -> u32 {
+        self.layout.width
+    }
+
+    fn height(&self) -> u32 {
+        self.layout.height
+    }
+
+    fn get_pixel_rgb(&self, x: u32, y: u32) -> Result<Rgb<u8>> {
+        let (rect, tile) = self.tile_at(x, y).ok_or_else(|| {
+            HideError::InvalidParameters(format!("No tile covers pixel ({}, {})", x, y))
+        })?;
+        tile.get_pixel_rgb(x - rect.x, y - rect.y)
+    }
+
+    fn set_pixel_rgb(&mut self, _x: u32, _y: u32, _pixel: Rgb<u8>) -> Result<()> {
+        Err(HideError::InvalidParameters(
+            "Tiled images are read-only".to_string(),
+        ))
+    }
+}
+
+/// Check that tiles lie inside an image without overlapping
+///
+/// # Returns
+/// * Where each tile lies in the image, in the order given
+fn check_tiles(tiles: &[(u32, u32, StegoImage)], layout: TileLayout) -> Result<Vec<Rect>> {
+    if layout.width == 0 || layout.height == 0 {
+        return Err(HideError::InvalidParameters(format!(
+            "Image has zero dimension ({}x{})",
+            layout.width, layout.height
+        )));
+    }
+
+    let mut rects: Vec<Rect> = Vec::with_capacity(tiles.len());
+    for (x, y, tile) in tiles {
+        let rect = Rect::new(*x, *y, tile.width(), tile.height());
+        rect.check_within(layout.width, layout.height)?;
+        if let Some(other) = rects.iter().find(|other| other.overlaps(&rect)) {
+            return Err(HideError::InvalidParameters(format!(
+                "Tile {}x{} at ({}, {}) overlaps the tile at ({}, {})",
+                rect.width, rect.height, rect.x, rect.y, other.x, other.y
+            )));
+        }
+        rects.push(rect);
+    }
+
+    Ok(rects)
+}
+
+/// Put an image stored as separate tiles back together
+///
+/// The image is RGBA when any tile has alpha and RGB otherwise.
+///
+/// # Arguments
+/// * `tiles` - The top-left corner of each tile in the full image, and the tile
+/// * `full_width` - Width of the full image
+/// * `full_height` - Height of the full image
+///
+/// # Returns
+/// * The full image, or an error if the tiles overlap, extend past it or
+///   leave part of it uncovered
+pub fn assemble_tiles(
+    tiles: &[(u32, u32, StegoImage)],
+    full_width: u32,
+    full_height: u32,
+) -> Result<StegoImage> {
+    let rects = check_tiles(
+        tiles,
+        TileLayout {
+            width: full_width,
+            height: full_height,
+        },
+    )?;
+
+    // Tiles inside the image that don't overlap cover it when their areas add up to it
+    let covered: u64 = rects
+        .iter()
+        .map(|rect| pixel_count(rect.width, rect.height))
+        .sum();
+    let total = pixel_count(full_width, full_height);
+    if covered != total {
+        return Err(HideError::InvalidParameters(format!(
+            "Tiles cover {} of the {} pixels of a {}x{} image",
+            covered, total, full_width, full_height
+        )));
+    }
+
+    let color = if tiles
+        .iter()
+        .any(|(_, _, tile)| tile.inner().color().has_alpha())
+    {
+        ColorType::Rgba8
+    } else {
+        ColorType::Rgb8
+    };
+    let requested = total.saturating_mul(color.bytes_per_pixel() as u64);
+    if requested > DEFAULT_MAX_IMAGE_BYTES {
+        return Err(HideError::AllocationTooLarge {
+            requested,
+            limit: DEFAULT_MAX_IMAGE_BYTES,
+        });
+    }
+
+    let mut canvas = DynamicImage::new(full_width, full_height, color);
+    for (x, y, tile) in tiles {
+        imageops::replace(&mut canvas, tile.inner(), i64::from(*x), i64::from(*y));
+    }
+
+    let mut assembled = StegoImage::from_dynamic_image(canvas);
+    if let Some((_, _, tile)) = tiles.first() {
+        assembled.color = tile.color;
+        assembled.physical = tile.physical;
+    }
+    Ok(assembled)
+}
+
+/// Whether an image is stored as 8-bit RGB, the layout pixels are written in
+fn is_rgb8(image: &DynamicImage) -> bool {
+    matches!(image, DynamicImage::ImageRgb8(_))
+}
+
+/// Whether an image is stored as 8-bit RGBA, the layout RGBA pixels are written in
+fn is_rgba8(image: &DynamicImage) -> bool {
+    matches!(image, DynamicImage::ImageRgba8(_))
+}
+
+/// Error for a color channel index other than 0, 1 or 2
+fn invalid_channel(channel: usize) -> HideError {
+    HideError::InvalidParameters(format!(
+        "Invalid color channel index: {}. Must be 0 (R), 1 (G), or 2 (B)",
+        channel
+    ))
+}
+
+/// Number of pixels in an image of the given dimensions
+///
+/// Computed in `u64`, which cannot overflow for any pair of `u32` dimensions.
+pub fn pixel_count(width: u32, height: u32) -> u64 {
+    width as u64 * height as u64
+}
+
+/// Number of pixels needed to carry the given number of embedded bits
+pub const fn pixels_for_bits(bits: u64) -> u64 {
+    bits.div_ceil(BITS_PER_PIXEL)
+}
+
+/// Number of bits that can be embedded in an image of the given dimensions
+///
+/// Saturates at `u64::MAX` for dimensions close to `u32::MAX`.
+pub fn capacity_bits(width: u32, height: u32) -> u64 {
+    pixel_count(width, height).saturating_mul(BITS_PER_PIXEL)
+}
+
+/// Number of whole bytes that can be embedded in an image of the given dimensions
+///
+/// Saturates at `usize::MAX` on platforms where the byte count does not fit.
+pub fn capacity(width: u32, height: u32) -> usize {
+    usize::try_from(capacity_bits(width, height) / 8).unwrap_or(usize::MAX)
+}
+
+/// Create a new blank RGB image with the specified dimensions
+///
+/// See [`StegoImage::new_rgb`] for the errors.
+pub fn create_rgb_image(width: u32, height: u32) -> Result<StegoImage> {
+    StegoImage::new_rgb(width, height)
+}
+
+/// Load an image from a file
+pub fn load_image<P: AsRef<Path>>(path: P) -> Result<StegoImage> {
+    StegoImage::from_file(path)
+}
+
+/// Save an image to a file
+pub fn save_image<P: AsRef<Path>>(image: &StegoImage, path: P) -> Result<()> {
+    image.save(path)
+}
+
+/// Check that a file can be written to a path before doing the work to produce it
+///
+/// # Arguments
+/// * `path` - Where the file will be written
+/// * `create_dirs` - Create missing parent directories
+///
+/// # Returns
+/// * `OutputPathInvalid` naming the path if it can't be written
+pub fn prepare_output_path(path: &Path, create_dirs: bool) -> Result<()> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    prepare_output_dir(parent, create_dirs).map_err(|e| match e {
+        HideError::OutputPathInvalid { reason, .. } => HideError::OutputPathInvalid {
+            path: path.to_path_buf(),
+            reason,
+        },
+        e => e,
+    })
+}
+
+/// Check that new files can be written to a directory
+///
+/// The directory must exist, or be created when `create_dirs` is set, and
+/// must accept a new file.
+///
+/// # Arguments
+/// * `dir` - The directory files will be written to
+/// * `create_dirs` - Create the directory and its parents if missing
+///
+/// # Returns
+/// * `OutputPathInvalid` naming the directory if files can't be written to it
+pub fn prepare_output_dir(dir: &Path, create_dirs: bool) -> Result<()> {
+    let invalid = |reason: String| HideError::OutputPathInvalid {
+        path: dir.to_path_buf(),
+        reason,
+    };
+
+    if !dir.exists() {
+        if !create_dirs {
+            return Err(invalid(format!(
+                "directory {} does not exist",
+                dir.display()
+            )));
+        }
+        fs::create_dir_all(dir).map_err(|e| {
+            invalid(format!(
+                "failed to create directory {}: {}",
+                dir.display(),
+                e
+            ))
+        })?;
+    }
+    if !dir.is_dir() {
+        return Err(invalid(format!("{} is not a directory", dir.display())));
+    }
+
+    // Permission bits don't tell the whole story (ACLs, read-only mounts), so try it
+    tempfile::Builder::new()
+        .prefix(".hide-write-check")
+        .tempfile_in(dir)
+        .map(drop)
+        .map_err(|e| {
+            invalid(format!(
+                "directory {} is not writable: {}",
+                dir.display(),
+                e
+            ))
+        })
+}
+
+/// Check whether a format stores RGB pixel data without loss
+pub fn is_lossless_format(format: ImageFormat) -> bool {
+    matches!(
+        format,
+        ImageFormat::Png
+            | ImageFormat::Bmp
+            | ImageFormat::Tiff
+            | ImageFormat::Tga
+            | ImageFormat::Pnm
+            | ImageFormat::Qoi
+            | ImageFormat::Farbfeld
+    )
+}
+
+/// Convert an image file to another lossless format, preserving any payload
+///
+/// The output is read back and its RGB data compared with the input; if the
+/// pixels changed, the output is removed and an error is returned.
+///
+/// # Arguments
+/// * `input` - Path of the image to convert
+/// * `output` - Path to write the converted image to
+/// * `format` - Format of the output image
+///
+/// # Returns
+/// * `LossyFormatRejected` for formats that would not preserve the pixels
+pub fn convert(input: &Path, output: &Path, format: ImageFormat) -> Result<()> {
+    if !is_lossless_format(format) {
+        return Err(HideError::LossyFormatRejected(format!("{:?}", format)));
+    }
+
+    let image = StegoImage::from_file(input)?;
+    image.inner().save_with_format(output, format)?;
+
+    // Verify that the payload-carrying pixels survived the round trip
+    let verified = StegoImage::from_file(output).and_then(|converted| {
+        if converted.inner().to_rgb8() == image.inner().to_rgb8() {
+            Ok(())
+        } else {
+            Err(HideError::LossyFormatRejected(format!(
+                "{:?} did not preserve the pixel data",
+                format
+            )))
+        }
+    });
+
+    if verified.is_err() {
+        let _ = fs::remove_file(output);
+    }
+    verified
+}
+
+/// How [`extend_canvas`] fills the area it adds to an image
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum FillStyle {
+    /// Reflect the image across its right and bottom edges
+    Mirror,
+
+    /// Reflect the image, then blur the reflection so it reads as background
+    Blur,
+
+    /// Fill with a single color
+    Solid(Rgb<u8>),
+}
+
+impl fmt::Display for FillStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Mirror => f.write_str("mirror"),
+            Self::Blur => f.write_str("blur"),
+            Self::Solid(Rgb([r, g, b])) => write!(f, "solid:#{:02x}{:02x}{:02x}", r, g, b),
+        }
+    }
+}
+
+impl FromStr for FillStyle {
+    type Err = HideError;
+
+    /// Parse `mirror`, `blur`, `solid` (black) or `solid:#rrggbb`
+    fn from_str(s: &str) -> Result<Self> {
+        let name = s.trim().to_ascii_lowercase();
+        let invalid = || {
+            HideError::InvalidParameters(format!(
+                "Unknown fill style '{}'; expected mirror, blur, solid or solid:#rrggbb",
+                s.trim()
+            ))
+        };
+
+        match name.as_str() {
+            "mirror" => return Ok(Self::Mirror),
+            "blur" => return Ok(Self::Blur),
+            "solid" => return Ok(Self::Solid(Rgb([0, 0, 0]))),
+            _ => {}
+        }
+        let hex = name
+            .strip_prefix("solid:")
+            .map(|color| color.trim_start_matches('#'))
+            .filter(|hex| hex.len() == 6)
+            .ok_or_else(invalid)?;
+        let color = u32::from_str_radix(hex, 16).map_err(|_| invalid())?;
+        let [_, r, g, b] = color.to_be_bytes();
+        Ok(Self::Solid(Rgb([r, g, b])))
+    }
+}
+
+impl From<FillStyle> for String {
+    fn from(fill: FillStyle) -> Self {
+        fill.to_string()
+    }
+}
+
+impl TryFrom<String> for FillStyle {
+    type Error = HideError;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+/// Grow an image's canvas, keeping its pixels in the top-left corner
+///
+/// The original pixels are copied unchanged, in the image's own color type;
+/// only the added area is filled. Sizes smaller than the image's are raised
+/// to them.
+///
+/// # Arguments
+/// * `image` - The image to grow
+/// * `new_width` - Width of the grown image
+/// * `new_height` - Height of the grown image
+/// * `fill` - How to fill the added area
+///
+/// # Returns
+/// * The grown image, or [`HideError::AllocationTooLarge`] if its pixel buffers
+///   would take more than [`DEFAULT_MAX_IMAGE_BYTES`]
+pub fn extend_canvas(
+    image: &StegoImage,
+    new_width: u32,
+    new_height: u32,
+    fill: FillStyle,
+) -> Result<StegoImage> {
+    let (width, height) = image.dimensions();
+    let (new_width, new_height) = (new_width.max(width), new_height.max(height));
+
+    // The canvas in the image's color type, plus the RGBA buffer the fill is drawn in
+    let bytes_per_pixel = image.inner().color().bytes_per_pixel() as u64 + 4;
+    let requested = pixel_count(new_width, new_height).saturating_mul(bytes_per_pixel);
+    if requested > DEFAULT_MAX_IMAGE_BYTES {
+        return Err(HideError::AllocationTooLarge {
+            requested,
+            limit: DEFAULT_MAX_IMAGE_BYTES,
+        });
+    }
+
+    let source = image.inner();
+    let mut filler = RgbaImage::from_fn(new_width, new_height, |x, y| match fill {
+        FillStyle::Solid(color) => color.to_rgba(),
+        FillStyle::Mirror | FillStyle::Blur => {
+            source.get_pixel(reflect(x, width), reflect(y, height))
+        }
+    });
+    if fill == FillStyle::Blur {
+        filler = imageops::fast_blur(&filler, EXTENSION_BLUR_SIGMA);
+    }
+
+    let mut canvas = DynamicImage::new(new_width, new_height, source.color());
+    for (x, y, pixel) in filler.enumerate_pixels() {
+        if x >= width || y >= height {
+            canvas.put_pixel(x, y, *pixel);
+        }
+    }
+    imageops::replace(&mut canvas, source, 0, 0);
+
+    let mut extended = StegoImage::from_dynamic_image(canvas);
+    extended.color = image.color;
+    extended.physical = image.physical;
+    Ok(extended)
+}
+
+/// Coordinate of the pixel a mirrored extension copies, reflecting at each edge
+fn reflect(coordinate: u32, size: u32) -> u32 {
+    let period = u64::from(size) * 2;
+    let offset = u64::from(coordinate) % period.max(1);
+    let reflected = if offset < u64::from(size) {
+        offset
+    } else {
+        period - 1 - offset
+    };
+    // Always below `size`
+    reflected as u32
+}
+
+/// Smallest dimensions, scaling both sides of an image alike, with enough pixels
+///
+/// # Arguments
+/// * `width` - Width of the image
+/// * `height` - Height of the image
+/// * `pixels` - Number of pixels needed
+///
+/// # Returns
+/// * The image's own dimensions if they already suffice, larger ones of
+///   about the same aspect ratio if not, or `None` for an empty image or when
+///   a side would not fit a `u32`
+pub fn required_dimensions(width: u32, height: u32, pixels: u64) -> Option<(u32, u32)> {
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let current = pixel_count(width, height);
+    if current >= pixels {
+        return Some((width, height));
+    }
+
+    let scale = (pixels as f64 / current as f64).sqrt();
+    let (width, height) = (u64::from(width), u64::from(height));
+    let mut new_width = ((width as f64 * scale).floor() as u64).max(width);
+    let mut new_height = ((height as f64 * scale).floor() as u64).max(height);
+    // Grow whichever side is further behind the aspect ratio until the pixels suffice
+    while new_width.checked_mul(new_height)? < pixels {
+        if new_width * height <= new_height * width {
+            new_width += 1;
+        } else {
+            new_height += 1;
+        }
+    }
+
+    Some((
+        u32::try_from(new_width).ok()?,
+        u32::try_from(new_height).ok()?,
+    ))
+}
+
+/// Score how well an image would hide a payload, between 0 and 1
+///
+/// Noisy images with balanced LSBs score highest; flat images score zero.
+pub fn cover_score(image: &StegoImage) -> f64 {
+    let noise = image.noise_estimate();
+    let noise_score = noise / (noise + NOISE_SCORE_SCALE);
+
+    // Penalize channels whose LSBs are far from an even split
+    let stats = image.stats();
+    let mut imbalance = 0.0;
+    if stats.pixel_count > 0 {
+        for set in stats.lsb_set {
+            let bias = set as f64 / stats.pixel_count as f64;
+            imbalance += (bias - 0.5).abs() * 2.0;
+        }
+        imbalance /= 3.0;
+    }
+
+    noise_score * (1.0 - imbalance)
+}
+
+/// Rank candidate cover images by suitability
+///
+/// # Arguments
+/// * `images` - The candidate cover images
+///
+/// # Returns
+/// * `(index, score)` pairs, best cover first
+pub fn rank_covers(images: &[StegoImage]) -> Vec<(usize, f64)> {
+    let mut ranking: Vec<(usize, f64)> = images
+        .iter()
+        .enumerate()
+        .map(|(i, image)| (i, cover_score(image)))
+        .collect();
+
+    ranking.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    ranking
+}
+
+/// Statistics describing how two images of the same size differ
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiffStats {
+    /// Width of the compared images in pixels
+    pub width: u32,
+
+    /// Height of the compared images in pixels
+    pub height: u32,
+
+    /// Total number of pixels compared
+    pub total_pixels: usize,
+
+    /// Number of pixels where at least one channel differs
+    pub changed_pixels: usize,
+
+    /// Number of individual RGB channel values that differ
+    pub changed_channels: usize,
+
+    /// Number of changed channels where only the least significant bit differs
+    pub lsb_only_changes: usize,
+
+    /// Largest absolute difference seen in any channel
+    pub max_channel_delta: u8,
+
+    /// Number of individual bits that differ across all RGB channels
+    #[serde(default)]
+    pub changed_bits: u64,
+
+    /// Changed bits per block, when a grid was asked for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blocks: Option<BlockDiff>,
+}
+
+/// Changed bits in each block of a grid laid over two compared images
+///
+/// Blocks in the last column and row are cut short where the image ends.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockDiff {
+    /// Width of a block in pixels
+    pub block_width: u32,
+
+    /// Height of a block in pixels
+    pub block_height: u32,
+
+    /// Number of blocks across the image
+    pub columns: u32,
+
+    /// Number of blocks down the image
+    pub rows: u32,
+
+    /// Changed bits in each block, row by row
+    pub counts: Vec<u64>,
+}
+
+impl BlockDiff {
+    /// Create a grid of empty counts covering an image
+    ///
+    /// # Arguments
+    /// * `width`, `height` - Dimensions of the compared images
+    /// * `block_size` - Width and height of a block in pixels
+    ///
+    /// # Returns
+    /// * The grid, or an error if a block dimension is zero
+    fn new(width: u32, height: u32, (block_width, block_height): (u32, u32)) -> Result<Self> {
+        if block_width == 0 || block_height == 0 {
+            return Err(HideError::InvalidParameters(format!(
+                "Invalid block size {}x{}: both dimensions must be non-zero",
+                block_width, block_height
+            )));
+        }
+
+        let columns = width.div_ceil(block_width);
+        let rows = height.div_ceil(block_height);
+        Ok(Self {
+            block_width,
+            block_height,
+            columns,
+            rows,
+            counts: vec![0; columns as usize * rows as usize],
+        })
+    }
+
+    /// Changed bits in the block holding a pixel
+    ///
+    /// # Arguments
+    /// * `x`, `y` - Coordinates of any pixel in the block
+    ///
+    /// # Returns
+    /// * The count, or `None` if the pixel is outside the compared images
+    pub fn count_at(&self, x: PixelX, y: PixelY) -> Option<u64> {
+        self.block_index(x, y)
+            .and_then(|index| self.counts.get(index))
+            .copied()
+    }
+
+    /// Position in `counts` of the block holding a pixel
+    fn block_index(&self, x: PixelX, y: PixelY) -> Option<usize> {
+        let column = x.get().checked_div(self.block_width)?;
+        let row = y.get().checked_div(self.block_height)?;
+        (column < self.columns && row < self.rows)
+            .then(|| row as usize * self.columns as usize + column as usize)
+    }
+
+    /// Count changed bits in the block holding a pixel
+    fn add(&mut self, x: PixelX, y: PixelY, bits: u64) {
+        if let Some(count) = self
+            .block_index(x, y)
+            .and_then(|index| self.counts.get_mut(index))
+        {
+            *count += bits;
+        }
+    }
+}
+
+/// Ensure two images have the same dimensions before comparing them
+fn check_same_dimensions(a: &StegoImage, b: &StegoImage) -> Result<()> {
+    if a.dimensions() != b.dimensions() {
+        return Err(HideError::InvalidParameters(format!(
+            "Image dimensions differ: {}x{} vs {}x{}",
+            a.width(),
+            a.height(),
+            b.width(),
+            b.height()
+        )));
+    }
+
+    Ok(())
+}
+
+/// Compare two images pixel by pixel
+///
+/// Images of different color types are compared on their RGB values.
+///
+/// # Arguments
+/// * `a` - The first image (usually the cover)
+/// * `b` - The second image (usually the stego image)
+/// * `block_size` - Width and height of the blocks to count changed bits in, if wanted
+///
+/// # Returns
+/// * Statistics about the differing pixels, or an error if the dimensions
+///   differ or a block dimension is zero
+pub fn compare(
+    a: &StegoImage,
+    b: &StegoImage,
+    block_size: Option<(u32, u32)>,
+) -> Result<DiffStats> {
+    check_same_dimensions(a, b)?;
+
+    let mut stats = DiffStats {
+        width: a.width(),
+        height: a.height(),
+        total_pixels: pixel_count(a.width(), a.height()) as usize,
+        changed_pixels: 0,
+        changed_channels: 0,
+        lsb_only_changes: 0,
+        max_channel_delta: 0,
+        changed_bits: 0,
+        blocks: block_size
+            .map(|size| BlockDiff::new(a.width(), a.height(), size))
+            .transpose()?,
+    };
+
+    for y in 0..a.height() {
+        for x in 0..a.width() {
+            let pa = a.get_pixel_rgb(x, y)?;
+            let pb = b.get_pixel_rgb(x, y)?;
+
+            let mut pixel_changed = false;
+            for (&va, &vb) in pa.0.iter().zip(pb.0.iter()) {
+                if va == vb {
+                    continue;
+                }
+
+                pixel_changed = true;
+                stats.changed_channels += 1;
+                if va ^ vb == 1 {
+                    stats.lsb_only_changes += 1;
+                }
+                stats.max_channel_delta = stats.max_channel_delta.max(va.abs_diff(vb));
+
+                let bits = (va ^ vb).count_ones() as u64;
+                stats.changed_bits += bits;
+                if let Some(blocks) = &mut stats.blocks {
+                    blocks.add(PixelX(x), PixelY(y), bits);
+                }
+            }
+
+            if pixel_changed {
+                stats.changed_pixels += 1;
+            }
+        }
+    }
+
+    Ok(stats)
+}
+
+/// Render a heatmap of the channels that differ between two images
+///
+/// Each output channel is set to 255 where the corresponding channel differs
+/// between the inputs and 0 where it is identical, so single-bit LSB changes
+/// become clearly visible.
+///
+/// # Arguments
+/// * `a` - The first image (usually the cover)
+/// * `b` - The second image (usually the stego image)
+///
+/// # Returns
+/// * The heatmap image, or an error if the dimensions differ
+pub fn lsb_diff(a: &StegoImage, b: &StegoImage) -> Result<StegoImage> {
+    check_same_dimensions(a, b)?;
+
+    let mut heatmap = StegoImage::new_rgb(a.width(), a.height())?;
+
+    for y in 0..a.height() {
+        for x in 0..a.width() {
+            let pa = a.get_pixel_rgb(x, y)?;
+            let pb = b.get_pixel_rgb(x, y)?;
+
+            let mut marker = [0u8; 3];
+            for ((value, va), vb) in marker.iter_mut().zip(pa.0).zip(pb.0) {
+                if va != vb {
+                    *value = 255;
+                }
+            }
+
+            heatmap.set_pixel_rgb(x, y, Rgb(marker))?;
+        }
+    }
+
+    Ok(heatmap)
+}
+
+bitflags! {
+    /// Color channels selected by bit-plane operations such as [`clear_lsbs`]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct ChannelMask: u8 {
+        /// The red channel
+        const RED = 0x01;
+
+        /// The green channel
+        const GREEN = 0x02;
+
+        /// The blue channel
+        const BLUE = 0x04;
+
+        /// The alpha channel; selecting it converts the image to RGBA
+        const ALPHA = 0x08;
+
+        /// The three color channels
+        const RGB = Self::RED.bits() | Self::GREEN.bits() | Self::BLUE.bits();
+    }
+}
+
+/// Clear or set bit planes of the selected channels across a whole image
+///
+/// Works on the raw pixel buffer in a single pass, so it is cheap enough to
+/// build zero-LSB baselines of large images. Images are converted to RGB, or
+/// to RGBA when they have alpha or `channels` selects it.
+///
+/// # Arguments
+/// * `image` - The image to modify
+/// * `channels` - Channels whose bits are changed
+/// * `planes` - Bit planes to change, as a mask where `0x01` is the LSB
+/// * `value` - Whether the selected bits are set (`true`) or cleared
+pub fn clear_lsbs(image: &mut StegoImage, channels: ChannelMask, planes: u8, value: bool) {
+    let (buffer, masks) = channel_buffer(image, channels, planes);
+    let stride = masks.len();
+
+    for pixel in buffer.chunks_exact_mut(stride) {
+        for (channel, &mask) in pixel.iter_mut().zip(&masks) {
+            if value {
+                *channel |= mask;
+            } else {
+                *channel &= !mask;
+            }
+        }
+    }
+}
+
+/// Dither the LSBs of the color channels with blue noise
+///
+/// Screenshots and flat graphics have LSB planes so regular that anything
+/// embedded in them stands out. This replaces a share of the color LSBs with
+/// bits of interleaved gradient noise, a cheap stand-in for blue noise, so the
+/// plane looks more like a photo's. No value moves by more than one.
+///
+/// # Arguments
+/// * `image` - The image to modify
+/// * `strength` - Share of the LSBs replaced, from 0 (none) to 1 (all)
+/// * `seed` - Seed shifting the noise; the same seed always gives the same bits
+pub fn dither(image: &mut StegoImage, strength: f32, seed: u64) {
+    let strength = f64::from(strength.clamp(0.0, 1.0));
+    let width = u64::from(image.width().max(1));
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    // Each channel picks its LSBs and their new values from shifted patterns
+    let offsets: Vec<[f64; 4]> = (0..3)
+        .map(|_| [0; 4].map(|_: u8| f64::from(rng.next_u32() % 4096)))
+        .collect();
+
+    let (buffer, masks) = channel_buffer(image, ChannelMask::RGB, 0x01);
+    let stride = masks.len();
+    for (index, pixel) in buffer.chunks_exact_mut(stride).enumerate() {
+        let index = index as u64;
+        let (x, y) = ((index % width) as f64, (index / width) as f64);
+        for ((channel, &mask), offset) in pixel.iter_mut().zip(&masks).zip(&offsets) {
+            if mask == 0 || gradient_noise(x + offset[0], y + offset[1]) >= strength {
+                continue;
+            }
+            let bit = gradient_noise(x + offset[2], y + offset[3]) >= 0.5;
+            *channel = (*channel & !1) | u8::from(bit);
+        }
+    }
+}
+
+/// Move every pixel of an image to a new position
+///
+/// Pixel `i` of the result, counting in row-major order, is pixel `order[i]`
+/// of the image, or the other way round with `inverse`. Images are
+/// converted to RGB, or to RGBA when they have alpha.
+///
+/// # Arguments
+/// * `image` - The image to rearrange
+/// * `order` - A permutation of the image's pixel indices
+/// * `inverse` - Whether to undo the permutation instead of applying it
+pub(crate) fn permute_pixels(image: &mut StegoImage, order: &[usize], inverse: bool) {
+    let (buffer, masks) = channel_buffer(image, ChannelMask::empty(), 0);
+    let stride = masks.len();
+    let source = buffer.to_vec();
+
+    for (index, &moved) in order.iter().enumerate() {
+        let (to, from) = if inverse {
+            (moved, index)
+        } else {
+            (index, moved)
+        };
+        let pixel = source.get(from * stride..(from + 1) * stride);
+        if let (Some(target), Some(pixel)) = (buffer.get_mut(to * stride..(to + 1) * stride), pixel)
+        {
+            target.copy_from_slice(pixel);
+        }
+    }
+}
+
+/// Interleaved gradient noise at a position, between 0 and 1
+///
+/// Neighbouring values differ strongly, which gives the high-frequency
+/// spectrum dithering wants without a precomputed noise texture.
+fn gradient_noise(x: f64, y: f64) -> f64 {
+    (52.982_918_9 * (0.067_110_56 * x + 0.005_837_15 * y).fract()).fract()
+}
+
+/// Replace the LSBs of the selected channels with random bits
+///
+/// The stochastic counterpart of [`clear_lsbs`], with the same single pass
+/// over the raw pixel buffer. The same seed always gives the same bits.
+///
+/// # Arguments
+/// * `image` - The image to modify
+/// * `channels` - Channels whose LSBs are randomized
+/// * `seed` - Seed of the generator drawing the bits
+pub fn randomize_lsbs(image: &mut StegoImage, channels: ChannelMask, seed: u64) {
+    let (buffer, masks) = channel_buffer(image, channels, 0x01);
+    let stride = masks.len();
+    let mut rng = StdRng::seed_from_u64(seed);
+    let (mut bits, mut bits_left) = (0u64, 0u32);
+
+    for pixel in buffer.chunks_exact_mut(stride) {
+        for (channel, &mask) in pixel.iter_mut().zip(&masks) {
+            if mask == 0 {
+                continue;
+            }
+            if bits_left == 0 {
+                bits = rng.next_u64();
+                bits_left = u64::BITS;
+            }
+
+            *channel = (*channel & !1) | (bits & 1) as u8;
+            bits >>= 1;
+            bits_left -= 1;
+        }
+    }
+}
+
+/// The raw RGB or RGBA buffer of an image and the bit mask to apply to each channel
+///
+/// # Returns
+/// * The buffer, marked modified, and one mask per channel of a pixel (3 or 4)
+fn channel_buffer(
+    image: &mut StegoImage,
+    channels: ChannelMask,
+    planes: u8,
+) -> (&mut [u8], Vec<u8>) {
+    if channels.contains(ChannelMask::ALPHA) || image.inner().color().has_alpha() {
+        image.to_rgba();
+    } else {
+        image.to_rgb();
+    }
+
+    let mask_for = |channel: ChannelMask| {
+        if channels.contains(channel) {
+            planes
+        } else {
+            0
+        }
+    };
+    let mut masks = vec![
+        mask_for(ChannelMask::RED),
+        mask_for(ChannelMask::GREEN),
+        mask_for(ChannelMask::BLUE),
+    ];
+    match image.inner_mut() {
+        DynamicImage::ImageRgba8(buffer) => {
+            masks.push(mask_for(ChannelMask::ALPHA));
+            (&mut **buffer, masks)
+        }
+        DynamicImage::ImageRgb8(buffer) => (&mut **buffer, masks),
+        // Converted to one of the layouts above
+        _ => (&mut [], masks),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{gradient_image, noise_image};
+    use image::ImageFormat;
+
+    fn create_test_image() -> StegoImage {
+        let img_data = vec![
+            255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 0, 0, 255, 255, 255, 0, 255, 128, 128, 128,
+            255, 255, 255, 0, 0, 0, 100, 100, 100, 50, 150, 250, 200, 50, 150,
+        ];
+
+        let img_buffer = ImageBuffer::from_raw(4, 3, img_data).unwrap();
+        StegoImage::from_dynamic_image(DynamicImage::ImageRgb8(img_buffer))
+    }
+
+    #[test]
+    fn test_image_dimensions() {
+        let img = create_test_image();
+        assert_eq!(img.width(), 4);
+        assert_eq!(img.height(), 3);
+        assert_eq!(img.dimensions(), (4, 3));
+    }
+
+    #[test]
+    fn test_get_set_pixel_rgb() {
+        let mut img = create_test_image();
+
+        // Test getting a pixel
+        let pixel = img.get_pixel_rgb(0, 0).unwrap();
+        assert_eq!(pixel.0, [255, 0, 0]);
+
+        // Test setting a pixel
+        img.set_pixel_rgb(0, 0, Rgb([10, 20, 30])).unwrap();
+        let updated_pixel = img.get_pixel_rgb(0, 0).unwrap();
+        assert_eq!(updated_pixel.0, [10, 20, 30]);
+
+        // Test out of bounds access
+        assert!(img.get_pixel_rgb(10, 10).is_err());
+        assert!(img.set_pixel_rgb(10, 10, Rgb([0, 0, 0])).is_err());
+    }
+
+    #[test]
+    fn test_clones_copy_the_buffer_on_write() {
+        let original = create_test_image();
+        assert!(!original.is_shared());
+
+        let mut copy = original.clone();
+        assert!(original.is_shared() && copy.is_shared());
+        copy.set_pixel_rgb(0, 0, Rgb([10, 20, 30])).unwrap();
+        assert!(!original.is_shared() && !copy.is_shared());
+        assert_eq!(original.get_pixel_rgb(0, 0).unwrap().0, [255, 0, 0]);
+        assert_eq!(copy.get_pixel_rgb(0, 0).unwrap().0, [10, 20, 30]);
+
+        let writes: [fn(&mut StegoImage); 3] = [
+            |image| image.set_lsb(1, 0, 1, false).unwrap(),
+            |image| image.inner_mut().invert(),
+            |image| image.set_pixel_rgba(1, 0, Rgba([1, 2, 3, 4])).unwrap(),
+        ];
+        for write in writes {
+            let mut copy = original.clone();
+            write(&mut copy);
+            assert!(!original.is_shared());
+            assert_eq!(original.get_pixel_rgb(1, 0).unwrap().0, [0, 255, 0]);
+            assert_ne!(copy.get_pixel_rgb(1, 0).unwrap().0, [0, 255, 0]);
+        }
+    }
+
+    #[test]
+    fn test_lsb_operations() {
+        let mut img = create_test_image();
+
+        // Test getting LSB
+        assert!(img.get_lsb(0, 0, 0).unwrap()); // Red: 255 (odd)
+        assert!(!img.get_lsb(0, 0, 1).unwrap()); // Green: 0 (even)
+        assert!(!img.get_lsb(0, 0, 2).unwrap()); // Blue: 0 (even)
+
+        // Test setting LSB
+        img.set_lsb(0, 0, 0, false).unwrap(); // Set red LSB to 0
+        assert!(!img.get_lsb(0, 0, 0).unwrap());
+        assert_eq!(img.get_pixel_rgb(0, 0).unwrap().0[0], 254); // 255 -> 254
+
+        img.set_lsb(0, 0, 1, true).unwrap(); // Set green LSB to 1
+        assert!(img.get_lsb(0, 0, 1).unwrap());
+        assert_eq!(img.get_pixel_rgb(0, 0).unwrap().0[1], 1); // 0 -> 1
+
+        // Test invalid channel
+        assert!(img.get_lsb(0, 0, 3).is_err());
+        assert!(img.set_lsb(0, 0, 3, true).is_err());
+    }
+
+    #[test]
+    fn test_to_bytes_round_trips_lossless_formats() {
+        let img = create_test_image();
+
+        for format in [ImageFormat::Png, ImageFormat::Bmp] {
+            let loaded = StegoImage::from_bytes(&img.to_bytes(format).unwrap()).unwrap();
+            assert_eq!(loaded.inner().to_rgb8(), img.inner().to_rgb8());
+        }
+        assert!(matches!(
+            img.to_bytes(ImageFormat::Jpeg),
+            Err(HideError::LossyFormatRejected(_))
+        ));
+    }
+
+    #[test]
+    fn test_save_load_image() {
+        let img = create_test_image();
+
+        // Save to memory buffer
+        let mut buffer = Cursor::new(Vec::new());
+        img.inner().write_to(&mut buffer, ImageFormat::Png).unwrap();
+
+        // Load from memory buffer
+        buffer.set_position(0);
+        let loaded = image::load(buffer, ImageFormat::Png).unwrap();
+        let loaded_img = StegoImage::from_dynamic_image(loaded);
+
+        // Verify dimensions match
+        assert_eq!(loaded_img.dimensions(), img.dimensions());
+
+        // Verify some pixel values
+        assert_eq!(
+            loaded_img.get_pixel_rgb(0, 0).unwrap().0,
+            img.get_pixel_rgb(0, 0).unwrap().0
+        );
+        assert_eq!(
+            loaded_img.get_pixel_rgb(1, 1).unwrap().0,
+            img.get_pixel_rgb(1, 1).unwrap().0
+        );
     }
 
     #[test]
@@ -340,4 +2014,547 @@ mod tests {
         // 4×3 image = 12 pixels × 3 bits per pixel = 36 bits = 4.5 bytes
         assert_eq!(img.max_message_size(), 4);
     }
+
+    fn create_flat_image() -> StegoImage {
+        let img = ImageBuffer::from_pixel(16, 16, Rgb([128, 128, 128]));
+        StegoImage::from_dynamic_image(DynamicImage::ImageRgb8(img))
+    }
+
+    #[test]
+    fn test_histogram_and_lsb_bias() {
+        let flat = create_flat_image();
+        let histogram = flat.histogram(0).unwrap();
+        assert_eq!(histogram[128], 256);
+        assert_eq!(histogram.iter().sum::<u32>(), 256);
+        assert_eq!(flat.lsb_bias(0).unwrap(), 0.0);
+        assert_eq!(flat.noise_estimate(), 0.0);
+
+        for image in [gradient_image(16, 16), noise_image(16, 16, 42)] {
+            for channel in 0..3 {
+                assert_eq!(image.histogram(channel).unwrap().iter().sum::<u32>(), 256);
+                let bias = image.lsb_bias(channel).unwrap();
+                assert!((0.0..=1.0).contains(&bias));
+            }
+        }
+
+        // Invalid channels are rejected
+        assert!(flat.histogram(3).is_err());
+        assert!(flat.lsb_bias(3).is_err());
+    }
+
+    #[test]
+    fn test_stats_cache_invalidated_on_mutation() {
+        let mut image = create_flat_image();
+        assert_eq!(image.lsb_bias(0).unwrap(), 0.0);
+
+        image.set_lsb(0, 0, 0, true).unwrap();
+        assert_eq!(image.lsb_bias(0).unwrap(), 1.0 / 256.0);
+        assert_eq!(image.histogram(0).unwrap()[129], 1);
+    }
+
+    #[test]
+    fn test_rank_covers() {
+        let images = [
+            create_flat_image(),
+            noise_image(16, 16, 42),
+            gradient_image(16, 16),
+        ];
+
+        assert!(images[1].noise_estimate() > images[2].noise_estimate());
+        assert!(images[2].noise_estimate() > images[0].noise_estimate());
+
+        let ranking = rank_covers(&images);
+        let order: Vec<usize> = ranking.iter().map(|(i, _)| *i).collect();
+        assert_eq!(order, vec![1, 2, 0]);
+        assert_eq!(ranking[2].1, 0.0);
+    }
+
+    #[test]
+    fn test_capacity_math_is_overflow_safe() {
+        assert_eq!(pixel_count(1, 1), 1);
+        assert_eq!(capacity_bits(1, 1), 3);
+        assert_eq!(capacity(1, 1), 0);
+        assert_eq!(capacity(0, 100), 0);
+        assert_eq!(capacity(100, 0), 0);
+
+        // Dimensions whose product overflows u32
+        assert_eq!(pixel_count(65536, 65536), 1 << 32);
+        assert_eq!(capacity_bits(65536, 65536), 3 << 32);
+        assert_eq!(capacity(1, u32::MAX), (u32::MAX as u64 * 3 / 8) as usize);
+
+        // The extreme case saturates rather than wrapping
+        assert_eq!(pixel_count(u32::MAX, u32::MAX), (u32::MAX as u64).pow(2));
+        assert_eq!(capacity_bits(u32::MAX, u32::MAX), u64::MAX);
+    }
+
+    #[test]
+    fn test_compare_and_lsb_diff() {
+        let original = create_test_image();
+        let mut modified = original.clone();
+
+        // Flip one LSB and make one larger change
+        modified.set_lsb(0, 0, 0, false).unwrap(); // 255 -> 254
+        modified.set_pixel_rgb(3, 2, Rgb([200, 50, 100])).unwrap(); // blue 150 -> 100
+
+        let stats = compare(&original, &modified, None).unwrap();
+        assert_eq!(stats.total_pixels, 12);
+        assert_eq!(stats.changed_pixels, 2);
+        assert_eq!(stats.changed_channels, 2);
+        assert_eq!(stats.lsb_only_changes, 1);
+        assert_eq!(stats.max_channel_delta, 50);
+        // 150 ^ 100 has 5 bits set
+        assert_eq!(stats.changed_bits, 6);
+        assert!(stats.blocks.is_none());
+
+        let heatmap = lsb_diff(&original, &modified).unwrap();
+        assert_eq!(heatmap.get_pixel_rgb(0, 0).unwrap().0, [255, 0, 0]);
+        assert_eq!(heatmap.get_pixel_rgb(3, 2).unwrap().0, [0, 0, 255]);
+        assert_eq!(heatmap.get_pixel_rgb(1, 1).unwrap().0, [0, 0, 0]);
+
+        // Mismatched dimensions are rejected
+        let other = create_rgb_image(2, 2).unwrap();
+        assert!(compare(&original, &other, None).is_err());
+        assert!(lsb_diff(&original, &other).is_err());
+    }
+
+    #[test]
+    fn test_compare_counts_changed_bits_per_block() {
+        let original = create_test_image();
+        let mut modified = original.clone();
+        modified.set_lsb(0, 0, 0, false).unwrap();
+        modified.set_pixel_rgb(3, 2, Rgb([200, 50, 100])).unwrap();
+
+        // A 4x3 image in 2x2 blocks has a short last row of blocks
+        let blocks = compare(&original, &modified, Some((2, 2)))
+            .unwrap()
+            .blocks
+            .unwrap();
+        assert_eq!((blocks.columns, blocks.rows), (2, 2));
+        assert_eq!(blocks.counts, vec![1, 0, 0, 5]);
+
+        // Blocks larger than the image give a single count
+        let blocks = compare(&original, &modified, Some((100, 100)))
+            .unwrap()
+            .blocks
+            .unwrap();
+        assert_eq!(blocks.counts, vec![6]);
+
+        assert!(matches!(
+            compare(&original, &modified, Some((0, 8))),
+            Err(HideError::InvalidParameters(_))
+        ));
+    }
+
+    #[test]
+    fn test_block_counts_on_wide_image_keep_columns_and_rows_apart() {
+        // 7x3 in 4x1 blocks: 2 columns by 3 rows, so a swapped x/y lands
+        // in another block or outside the grid
+        let original = create_rgb_image(7, 3).unwrap();
+        let mut modified = original.clone();
+        modified.set_pixel_rgb(5, 0, Rgb([1, 0, 0])).unwrap();
+        modified.set_pixel_rgb(1, 2, Rgb([3, 0, 0])).unwrap();
+
+        let blocks = compare(&original, &modified, Some((4, 1)))
+            .unwrap()
+            .blocks
+            .unwrap();
+        assert_eq!((blocks.columns, blocks.rows), (2, 3));
+        assert_eq!(blocks.counts, vec![0, 1, 0, 0, 2, 0]);
+        assert_eq!(blocks.count_at(PixelX(5), PixelY(0)), Some(1));
+        assert_eq!(blocks.count_at(PixelX(1), PixelY(2)), Some(2));
+        assert_eq!(blocks.count_at(PixelX(2), PixelY(1)), Some(0));
+        assert_eq!(blocks.count_at(PixelX(0), PixelY(5)), None);
+    }
+
+    #[test]
+    fn test_pixel_index_access_on_tall_image() {
+        let mut image = create_rgb_image(3, 7).unwrap();
+        image.set_pixel_at(PixelIndex(7), Rgb([9, 9, 9])).unwrap();
+
+        // Index 7 in a 3-wide image is column 1, row 2
+        assert_eq!(image.get_pixel_rgb(1, 2).unwrap().0, [9, 9, 9]);
+        assert_eq!(image.get_pixel_rgb(2, 1).unwrap().0, [0, 0, 0]);
+        assert_eq!(image.get_pixel_at(PixelIndex(7)).unwrap().0, [9, 9, 9]);
+        assert!(image.get_pixel_at(PixelIndex(21)).is_err());
+    }
+
+    #[test]
+    fn test_compare_across_color_types_uses_rgb_values() {
+        let original = create_test_image();
+        let rgba =
+            StegoImage::from_dynamic_image(DynamicImage::ImageRgba8(original.inner().to_rgba8()));
+
+        let stats = compare(&original, &rgba, Some((2, 2))).unwrap();
+        assert_eq!(stats.changed_bits, 0);
+        assert_eq!(stats.blocks.unwrap().counts, vec![0; 4]);
+    }
+
+    /// Check every channel value against its original under a per-channel check
+    fn assert_channels(
+        before: &StegoImage,
+        after: &StegoImage,
+        check: impl Fn(usize, u8, u8) -> bool,
+    ) {
+        let before = before.inner().to_rgba8();
+        let after = after.inner().to_rgba8();
+        for (old, new) in before.pixels().zip(after.pixels()) {
+            for (channel, (&old, &new)) in old.0.iter().zip(&new.0).enumerate() {
+                assert!(
+                    check(channel, old, new),
+                    "channel {}: {} -> {}",
+                    channel,
+                    old,
+                    new
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_clear_lsbs() {
+        let original = noise_image(16, 16, 3);
+
+        let mut cleared = original.clone();
+        clear_lsbs(&mut cleared, ChannelMask::RGB, 0x01, false);
+        assert!(cleared.is_modified());
+        assert_channels(&original, &cleared, |channel, old, new| {
+            channel == 3 || new == old & !1
+        });
+        assert_eq!(cleared.lsb_bias(0).unwrap(), 0.0);
+
+        // Set two planes of one channel, leaving the rest alone
+        let mut set = original.clone();
+        clear_lsbs(&mut set, ChannelMask::GREEN, 0b110, true);
+        assert_channels(&original, &set, |channel, old, new| match channel {
+            1 => new == old | 0b110,
+            _ => new == old,
+        });
+    }
+
+    #[test]
+    fn test_clear_lsbs_keeps_alpha_unless_selected() {
+        let mut original = noise_image(8, 8, 4);
+        original.to_rgba();
+        for (i, pixel) in original
+            .inner_mut()
+            .as_mut_rgba8()
+            .unwrap()
+            .pixels_mut()
+            .enumerate()
+        {
+            pixel.0[3] = i as u8 | 1;
+        }
+
+        let mut colors = original.clone();
+        clear_lsbs(&mut colors, ChannelMask::RGB, 0x01, false);
+        assert!(matches!(colors.inner(), DynamicImage::ImageRgba8(_)));
+        assert_channels(&original, &colors, |channel, old, new| match channel {
+            3 => new == old,
+            _ => new == old & !1,
+        });
+
+        // Selecting alpha converts an RGB image to RGBA
+        let mut rgb = noise_image(8, 8, 4);
+        clear_lsbs(&mut rgb, ChannelMask::ALPHA, 0x01, false);
+        assert!(matches!(rgb.inner(), DynamicImage::ImageRgba8(_)));
+        assert!(rgb
+            .inner()
+            .to_rgba8()
+            .pixels()
+            .all(|pixel| pixel.0[3] == 254));
+    }
+
+    #[test]
+    fn test_randomize_lsbs() {
+        let original = gradient_image(64, 64);
+
+        let mut randomized = original.clone();
+        randomize_lsbs(&mut randomized, ChannelMask::RED | ChannelMask::BLUE, 7);
+        assert!(randomized.is_modified());
+        assert_channels(&original, &randomized, |channel, old, new| match channel {
+            0 | 2 => new & !1 == old & !1,
+            _ => new == old,
+        });
+
+        // Roughly half the LSBs end up set, the same ones for the same seed
+        let bias = randomized.lsb_bias(0).unwrap();
+        assert!((0.45..0.55).contains(&bias), "{}", bias);
+
+        let mut again = original.clone();
+        randomize_lsbs(&mut again, ChannelMask::RED | ChannelMask::BLUE, 7);
+        assert_eq!(again.inner().as_bytes(), randomized.inner().as_bytes());
+        randomize_lsbs(&mut again, ChannelMask::RED | ChannelMask::BLUE, 8);
+        assert_ne!(again.inner().as_bytes(), randomized.inner().as_bytes());
+    }
+
+    #[test]
+    fn test_dither_only_touches_color_lsbs() {
+        let original = gradient_image(64, 64);
+
+        for strength in [0.25, 0.5, 1.0] {
+            let mut dithered = original.clone();
+            dither(&mut dithered, strength, 3);
+            assert_channels(&original, &dithered, |_, old, new| new & !1 == old & !1);
+
+            // At most the configured share of LSBs is replaced, about half flip
+            let total = original.inner().as_bytes().len() as f64;
+            let changed = original
+                .inner()
+                .as_bytes()
+                .iter()
+                .zip(dithered.inner().as_bytes())
+                .filter(|(old, new)| old != new)
+                .count() as f64;
+            let share = changed / total;
+            assert!(
+                share <= f64::from(strength) * 0.6,
+                "{}: {}",
+                strength,
+                share
+            );
+            assert!(
+                share >= f64::from(strength) * 0.3,
+                "{}: {}",
+                strength,
+                share
+            );
+        }
+
+        let mut untouched = original.clone();
+        dither(&mut untouched, 0.0, 3);
+        assert_eq!(untouched.inner().as_bytes(), original.inner().as_bytes());
+    }
+
+    #[test]
+    fn test_dither_is_deterministic_under_a_seed() {
+        let original = gradient_image(64, 64);
+        let mut first = original.clone();
+        dither(&mut first, 0.5, 11);
+        let mut again = original.clone();
+        dither(&mut again, 0.5, 11);
+        assert_eq!(first.inner().as_bytes(), again.inner().as_bytes());
+
+        let mut other = original.clone();
+        dither(&mut other, 0.5, 12);
+        assert_ne!(first.inner().as_bytes(), other.inner().as_bytes());
+    }
+
+    #[test]
+    fn test_dither_improves_flat_covers() {
+        // A screenshot-like cover: flat panels with hard edges
+        let mut screenshot = StegoImage::new_rgb(64, 64).unwrap();
+        for y in 0..64 {
+            for x in 0..64 {
+                let value = if x < 20 { 240 } else { 32 };
+                screenshot
+                    .set_pixel_rgb(x, y, Rgb([value, value, 200]))
+                    .unwrap();
+            }
+        }
+
+        let before = cover_score(&screenshot);
+        dither(&mut screenshot, 1.0, 5);
+        assert!(cover_score(&screenshot) > before, "{}", before);
+    }
+
+    #[test]
+    fn test_bit_planes_of_a_4k_image_take_one_pass() {
+        let mut image = StegoImage::new_rgb(3840, 2160).unwrap();
+        clear_lsbs(&mut image, ChannelMask::RGB, 0xff, true);
+        assert!(image.inner().as_bytes().iter().all(|&value| value == 255));
+
+        // Fast even unoptimized, which per-pixel get/set calls are not
+        let start = std::time::Instant::now();
+        clear_lsbs(&mut image, ChannelMask::RGB, 0x01, false);
+        randomize_lsbs(&mut image, ChannelMask::RGB, 1);
+        assert!(start.elapsed() < std::time::Duration::from_secs(10));
+        assert!(image.inner().as_bytes().iter().all(|&value| value >= 254));
+    }
+
+    #[test]
+    fn test_fill_style_names_round_trip() {
+        for fill in [
+            FillStyle::Mirror,
+            FillStyle::Blur,
+            FillStyle::Solid(Rgb([0x12, 0xab, 0xff])),
+        ] {
+            assert_eq!(fill.to_string().parse::<FillStyle>().unwrap(), fill);
+            assert_eq!(
+                serde_json::to_string(&fill).unwrap(),
+                format!("\"{}\"", fill)
+            );
+        }
+        assert_eq!(
+            "Solid".parse::<FillStyle>().unwrap(),
+            FillStyle::Solid(Rgb([0, 0, 0]))
+        );
+        assert_eq!(
+            "solid:FFFFFF".parse::<FillStyle>().unwrap(),
+            FillStyle::Solid(Rgb([255, 255, 255]))
+        );
+        for invalid in ["stretch", "solid:#fff", "solid:#gggggg"] {
+            assert!(invalid.parse::<FillStyle>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_extend_canvas_keeps_the_original_pixels() {
+        let original = noise_image(10, 6, 3);
+
+        let mirrored = extend_canvas(&original, 25, 9, FillStyle::Mirror).unwrap();
+        assert_eq!(mirrored.dimensions(), (25, 9));
+        for (x, y, pixel) in original.inner().pixels() {
+            assert_eq!(mirrored.inner().get_pixel(x, y), pixel);
+        }
+        // Reflected at the right edge, then again at the mirror's own edge
+        assert_eq!(
+            mirrored.get_pixel_rgb(10, 2).unwrap(),
+            original.get_pixel_rgb(9, 2).unwrap()
+        );
+        assert_eq!(
+            mirrored.get_pixel_rgb(20, 2).unwrap(),
+            original.get_pixel_rgb(0, 2).unwrap()
+        );
+        assert_eq!(
+            mirrored.get_pixel_rgb(3, 8).unwrap(),
+            original.get_pixel_rgb(3, 3).unwrap()
+        );
+
+        let solid = extend_canvas(&original, 12, 6, FillStyle::Solid(Rgb([1, 2, 3]))).unwrap();
+        assert_eq!(solid.get_pixel_rgb(11, 5).unwrap(), Rgb([1, 2, 3]));
+
+        // 16-bit images stay 16-bit, with their original samples untouched
+        let deep =
+            StegoImage::from_dynamic_image(DynamicImage::ImageRgb16(original.inner().to_rgb16()));
+        let blurred = extend_canvas(&deep, 16, 16, FillStyle::Blur).unwrap();
+        assert_eq!(blurred.inner().color(), image::ColorType::Rgb16);
+        let crop = blurred.inner().crop_imm(0, 0, 10, 6);
+        assert_eq!(crop.as_bytes(), deep.inner().as_bytes());
+
+        // Smaller sizes leave the image as it was
+        let same = extend_canvas(&original, 4, 4, FillStyle::Mirror).unwrap();
+        assert_eq!(same.inner().as_bytes(), original.inner().as_bytes());
+    }
+
+    #[test]
+    fn test_required_dimensions_keep_the_aspect_ratio() {
+        assert_eq!(required_dimensions(40, 30, 1200), Some((40, 30)));
+        assert_eq!(required_dimensions(40, 30, 4800), Some((80, 60)));
+
+        let (width, height) = required_dimensions(40, 30, 5000).unwrap();
+        assert!(pixel_count(width, height) >= 5000);
+        assert!(pixel_count(width - 1, height) < 5000 || pixel_count(width, height - 1) < 5000);
+        assert!((width as f64 / height as f64 - 4.0 / 3.0).abs() < 0.05);
+
+        assert_eq!(required_dimensions(0, 30, 10), None);
+        assert_eq!(required_dimensions(1, 1, u64::MAX), None);
+    }
+
+    // Helper to cut an image into tiles of at most `size` pixels a side
+    fn cut_tiles(image: &StegoImage, size: u32) -> Vec<(u32, u32, StegoImage)> {
+        let mut tiles = Vec::new();
+        for y in (0..image.height()).step_by(size as usize) {
+            for x in (0..image.width()).step_by(size as usize) {
+                let width = size.min(image.width() - x);
+                let height = size.min(image.height() - y);
+                let tile = image.inner().crop_imm(x, y, width, height);
+                tiles.push((x, y, StegoImage::from_dynamic_image(tile)));
+            }
+        }
+        tiles
+    }
+
+    #[test]
+    fn test_assemble_tiles_restores_the_image() {
+        let original = gradient_image(23, 17);
+        let mut tiles = cut_tiles(&original, 8);
+        tiles.reverse();
+
+        let assembled = assemble_tiles(&tiles, 23, 17).unwrap();
+        assert_eq!(assembled.inner().as_bytes(), original.inner().as_bytes());
+
+        let tiled = TiledImage::new(
+            &tiles,
+            TileLayout {
+                width: 23,
+                height: 17,
+            },
+        )
+        .unwrap();
+        for (x, y) in [(0, 0), (22, 0), (8, 8), (22, 16), (7, 9)] {
+            assert_eq!(
+                tiled.get_pixel_rgb(x, y).unwrap(),
+                original.get_pixel_rgb(x, y).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_assemble_tiles_rejects_bad_layouts() {
+        let tiles = cut_tiles(&gradient_image(16, 16), 8);
+
+        // A gap
+        assert!(assemble_tiles(tiles.get(1..).unwrap(), 16, 16).is_err());
+
+        // An overlap, even when the areas add up
+        let mut overlapping = tiles.clone();
+        overlapping[1].0 = 4;
+        assert!(assemble_tiles(&overlapping, 16, 16).is_err());
+
+        // A tile past the edge
+        assert!(assemble_tiles(&tiles, 12, 16).is_err());
+        assert!(assemble_tiles(&[], 0, 0).is_err());
+
+        // Missing tiles are fine until a missing pixel is read
+        let partial = tiles.get(..3).unwrap();
+        let tiled = TiledImage::new(
+            partial,
+            TileLayout {
+                width: 16,
+                height: 16,
+            },
+        )
+        .unwrap();
+        assert!(tiled.covers(PixelIndex(0)));
+        assert!(!tiled.covers(PixelIndex(16 * 16 - 1)));
+        assert!(tiled.get_pixel_rgb(15, 15).is_err());
+    }
+
+    #[test]
+    fn test_dpi_converts_through_pixels_per_meter() {
+        for dpi in [72, 96, 150, 300, 600, 1200] {
+            let physical = PhysicalDimensions::from_dpi(dpi, dpi * 2);
+            assert_eq!(physical.dpi(), Some((dpi, dpi * 2)));
+        }
+        assert_eq!(
+            PhysicalDimensions::from_dpi(300, 300).x_pixels_per_unit,
+            11811
+        );
+
+        // An aspect ratio alone has no DPI, and a cut-short chunk is ignored
+        let mut png = StegoImage::new_rgb(2, 2)
+            .unwrap()
+            .to_bytes(ImageFormat::Png)
+            .unwrap();
+        let aspect_only = PhysicalDimensions {
+            x_pixels_per_unit: 1,
+            y_pixels_per_unit: 2,
+            in_meters: false,
+        };
+        insert_phys_chunk(&mut png, aspect_only);
+        let loaded = StegoImage::from_bytes(&png).unwrap();
+        assert_eq!(loaded.physical_dimensions(), Some(aspect_only));
+        assert_eq!(loaded.dpi(), None);
+        assert_eq!(PhysicalDimensions::from_png_bytes(&png[..40]), None);
+    }
+
+    #[test]
+    fn test_rect_overlaps() {
+        let rect = Rect::new(4, 4, 4, 4);
+        assert!(rect.overlaps(&Rect::new(7, 7, 2, 2)));
+        assert!(!rect.overlaps(&Rect::new(8, 4, 4, 4)));
+        assert!(!rect.overlaps(&Rect::new(4, 0, 4, 4)));
+        assert!(rect.contains(7, 7));
+        assert!(!rect.contains(8, 7));
+    }
 }
@@ -0,0 +1,1270 @@
+//! Experimental embedding in the DCT coefficients of baseline JPEG files
+//!
+//! The BLTM algorithms change pixel LSBs, which JPEG compression throws
+//! away. [`JpegStego`] works on the JPEG file itself instead: it decodes the
+//! entropy-coded data of every scan to quantized DCT coefficients, sets the
+//! least significant bit of the magnitude of AC coefficients of magnitude 2
+//! or more, and writes the scans back with the file's own Huffman tables.
+//! Nothing is decoded to pixels or recompressed. Such coefficients keep
+//! their Huffman symbol when their LSB changes, so the stego file is as
+//! large as the cover give or take byte stuffing, and every marker segment
+//! is copied unchanged.
+//!
+//! Only Huffman-coded baseline and extended sequential JPEGs with 8-bit
+//! samples are supported. The payload has a header of its own, unrelated to
+//! [`MessageHeader`](crate::header::MessageHeader): the magic `HJD`, a
+//! format version and the message length as a big-endian `u32`.
+//!
+//! This module is experimental and only built with the `jpeg` feature; its
+//! payload format may change between releases.
+
+use crate::error::HideError;
+use crate::utils;
+use crate::Result;
+use serde::Serialize;
+use std::ops::Range;
+
+/// Magic bytes starting the payload header
+const MAGIC: [u8; 3] = *b"HJD";
+
+/// Version of the payload format
+const FORMAT_VERSION: u8 = 1;
+
+/// Size of the payload header in bytes
+pub const JPEG_HEADER_SIZE: usize = 8;
+
+/// Smallest coefficient magnitude that can carry a bit
+///
+/// A coefficient of magnitude 1 could become 0, which would change the
+/// zero runs around it and which coefficients carry the later bits.
+pub const MIN_CARRIER_MAGNITUDE: u16 = 2;
+
+/// Coefficients in an 8x8 block
+const BLOCK_LEN: usize = 64;
+
+/// Start of image marker
+const SOI: u8 = 0xD8;
+
+/// End of image marker
+const EOI: u8 = 0xD9;
+
+/// Start of frame markers for baseline and extended sequential Huffman coding
+const SOF0: u8 = 0xC0;
+const SOF1: u8 = 0xC1;
+
+/// Define Huffman tables marker
+const DHT: u8 = 0xC4;
+
+/// Define quantization tables marker
+const DQT: u8 = 0xDB;
+
+/// Define restart interval marker
+const DRI: u8 = 0xDD;
+
+/// Start of scan marker
+const SOS: u8 = 0xDA;
+
+/// Define number of lines marker
+const DNL: u8 = 0xDC;
+
+/// First of the eight restart markers
+const RST0: u8 = 0xD0;
+
+/// Luminance quantization table of Annex K of the JPEG standard, in zigzag order
+#[rustfmt::skip]
+const STD_LUMA_QUANT: [u16; BLOCK_LEN] = [
+    16, 11, 12, 14, 12, 10, 16, 14,
+    13, 14, 18, 17, 16, 19, 24, 40,
+    26, 24, 22, 22, 24, 49, 35, 37,
+    29, 40, 58, 51, 61, 60, 57, 51,
+    56, 55, 64, 72, 92, 78, 64, 68,
+    87, 69, 55, 56, 80, 109, 81, 87,
+    95, 98, 103, 104, 103, 62, 77, 113,
+    121, 112, 100, 120, 92, 101, 103, 99,
+];
+
+/// Options for [`JpegStego`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JpegConfig {
+    /// Smallest AC coefficient magnitude that carries a bit, at least 2
+    ///
+    /// Raising it leaves more small coefficients untouched at the cost of
+    /// capacity. Decoding must use the value the message was encoded with.
+    pub min_magnitude: u16,
+
+    /// Refuse to encode into covers of a lower estimated quality
+    ///
+    /// Heavily quantized covers have few large coefficients left, so each
+    /// changed one stands out more.
+    pub min_quality: Option<u8>,
+}
+
+impl Default for JpegConfig {
+    fn default() -> Self {
+        Self {
+            min_magnitude: MIN_CARRIER_MAGNITUDE,
+            min_quality: None,
+        }
+    }
+}
+
+/// How much a JPEG cover can hide
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct JpegCapacity {
+    /// Width of the image in pixels
+    pub width: u32,
+
+    /// Height of the image in pixels
+    pub height: u32,
+
+    /// Quality (1-100) estimated from the luminance quantization table
+    pub quality: Option<u8>,
+
+    /// Number of 8x8 coefficient blocks in all scans
+    pub blocks: usize,
+
+    /// Number of AC coefficients that can carry a bit
+    pub carrier_coefficients: usize,
+
+    /// Largest message that fits, in bytes
+    pub max_message_bytes: usize,
+}
+
+/// Hides messages in the quantized DCT coefficients of JPEG files
+#[derive(Debug, Clone, Default)]
+pub struct JpegStego {
+    /// Options for encoding and decoding
+    config: JpegConfig,
+}
+
+impl JpegStego {
+    /// Create a JPEG stego codec with default options
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a JPEG stego codec with custom options
+    pub fn with_config(config: JpegConfig) -> Self {
+        Self { config }
+    }
+
+    /// Report how much a JPEG file can hide
+    ///
+    /// # Arguments
+    /// * `jpeg` - The JPEG file
+    ///
+    /// # Returns
+    /// * The capacity, or `InvalidJpeg` if the file can't be parsed
+    pub fn capacity(&self, jpeg: &[u8]) -> Result<JpegCapacity> {
+        self.check_config()?;
+        let file = JpegFile::parse(jpeg)?;
+        let carriers = file.carriers(self.config.min_magnitude).count();
+        Ok(JpegCapacity {
+            width: u32::from(file.width),
+            height: u32::from(file.height),
+            quality: file.quality(),
+            blocks: file.scans.iter().map(|scan| scan.blocks.len()).sum(),
+            carrier_coefficients: carriers,
+            max_message_bytes: (carriers / 8).saturating_sub(JPEG_HEADER_SIZE),
+        })
+    }
+
+    /// Hide a message in a JPEG file
+    ///
+    /// # Arguments
+    /// * `jpeg` - The cover JPEG file
+    /// * `message` - The message to hide
+    ///
+    /// # Returns
+    /// * The stego JPEG file, `MessageTooLarge` if the message doesn't fit,
+    ///   or `InvalidJpeg` if the cover can't be parsed
+    pub fn encode(&self, jpeg: &[u8], message: &[u8]) -> Result<Vec<u8>> {
+        self.check_config()?;
+        let mut file = JpegFile::parse(jpeg)?;
+        if let (Some(min), Some(quality)) = (self.config.min_quality, file.quality()) {
+            if quality < min {
+                return Err(HideError::InvalidParameters(format!(
+                    "Cover JPEG quality is about {}, below the minimum of {}",
+                    quality, min
+                )));
+            }
+        }
+
+        let length = u32::try_from(message.len()).map_err(|_| HideError::MessageTooLarge)?;
+        let mut payload = Vec::with_capacity(JPEG_HEADER_SIZE + message.len());
+        payload.extend_from_slice(&MAGIC);
+        payload.push(FORMAT_VERSION);
+        payload.extend_from_slice(&length.to_be_bytes());
+        payload.extend_from_slice(message);
+
+        let bits = utils::bytes_to_bits(&payload);
+        if bits.len() > file.carriers(self.config.min_magnitude).count() {
+            return Err(HideError::MessageTooLarge);
+        }
+        for (coefficient, bit) in file
+            .carriers_mut(self.config.min_magnitude)
+            .zip(bits.iter())
+        {
+            let magnitude = (coefficient.unsigned_abs() & !1) | u16::from(*bit);
+            *coefficient = magnitude as i16 * coefficient.signum();
+        }
+
+        file.to_bytes()
+    }
+
+    /// Extract a message hidden by [`JpegStego::encode`]
+    ///
+    /// # Arguments
+    /// * `jpeg` - The stego JPEG file
+    ///
+    /// # Returns
+    /// * The message, `NoMessageFound` if the file holds no payload,
+    ///   `TruncatedPayload` if it holds less than its header declares, or
+    ///   `InvalidJpeg` if it can't be parsed
+    pub fn decode(&self, jpeg: &[u8]) -> Result<Vec<u8>> {
+        self.check_config()?;
+        let file = JpegFile::parse(jpeg)?;
+        let mut bits = file
+            .carriers(self.config.min_magnitude)
+            .map(|coefficient| coefficient.unsigned_abs() & 1 == 1);
+
+        let header = utils::bits_to_bytes(
+            &bits
+                .by_ref()
+                .take(JPEG_HEADER_SIZE * 8)
+                .collect::<bitvec::vec::BitVec<u8, bitvec::order::Msb0>>(),
+        );
+        let (magic, rest) = header.split_at(header.len().min(MAGIC.len()));
+        let (version, length) = match rest {
+            [version, a, b, c, d] => (*version, u32::from_be_bytes([*a, *b, *c, *d]) as usize),
+            _ => return Err(HideError::NoMessageFound),
+        };
+        if magic != MAGIC {
+            return Err(HideError::NoMessageFound);
+        }
+        if version != FORMAT_VERSION {
+            return Err(HideError::UnsupportedFeature { version, bits: 0 });
+        }
+
+        let message_bits: bitvec::vec::BitVec<u8, bitvec::order::Msb0> =
+            bits.take(length.saturating_mul(8)).collect();
+        if message_bits.len() < length * 8 {
+            return Err(HideError::TruncatedPayload {
+                declared: length,
+                available: message_bits.len() / 8,
+            });
+        }
+        Ok(utils::bits_to_bytes(&message_bits))
+    }
+
+    /// Ensure the options can round-trip a message
+    fn check_config(&self) -> Result<()> {
+        if self.config.min_magnitude < MIN_CARRIER_MAGNITUDE {
+            return Err(HideError::InvalidParameters(format!(
+                "Minimum coefficient magnitude must be at least {}",
+                MIN_CARRIER_MAGNITUDE
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Check whether data starts like a JPEG file
+pub fn is_jpeg(data: &[u8]) -> bool {
+    data.starts_with(&[0xFF, SOI, 0xFF])
+}
+
+/// Build an `InvalidJpeg` error
+fn invalid(reason: &str) -> HideError {
+    HideError::InvalidJpeg(reason.to_string())
+}
+
+/// A parsed JPEG file and the coefficients of all its scans
+struct JpegFile<'a> {
+    /// The file as read
+    data: &'a [u8],
+
+    /// Width of the image in pixels
+    width: u16,
+
+    /// Height of the image in pixels
+    height: u16,
+
+    /// Quantization table of the first component, in zigzag order
+    luma_quant: Option<[u16; BLOCK_LEN]>,
+
+    /// The scans, in file order
+    scans: Vec<Scan>,
+}
+
+/// A component of the frame
+#[derive(Debug, Clone, Copy)]
+struct FrameComponent {
+    /// Component identifier
+    id: u8,
+
+    /// Horizontal sampling factor
+    h: u8,
+
+    /// Vertical sampling factor
+    v: u8,
+
+    /// Quantization table selector
+    quant_table: u8,
+}
+
+/// The frame header
+struct Frame {
+    /// Width of the image in pixels
+    width: u16,
+
+    /// Height of the image in pixels
+    height: u16,
+
+    /// The components, in frame order
+    components: Vec<FrameComponent>,
+}
+
+impl Frame {
+    /// Largest horizontal and vertical sampling factors
+    fn max_sampling(&self) -> (usize, usize) {
+        self.components.iter().fold((1, 1), |(h, v), component| {
+            (
+                h.max(usize::from(component.h)),
+                v.max(usize::from(component.v)),
+            )
+        })
+    }
+}
+
+/// A component of a scan with its Huffman tables
+struct ScanComponent {
+    /// Table for DC coefficient differences
+    dc: HuffmanTable,
+
+    /// Table for AC coefficients
+    ac: HuffmanTable,
+}
+
+/// One scan and its decoded coefficients
+struct Scan {
+    /// Position of the entropy-coded data in the file
+    data: Range<usize>,
+
+    /// MCUs between restart markers, 0 if there are none
+    restart_interval: usize,
+
+    /// The components of the scan
+    components: Vec<ScanComponent>,
+
+    /// Component of each block of an MCU, as an index into `components`
+    mcu_layout: Vec<usize>,
+
+    /// Coefficients of every block in coding order, zigzag ordered, with the
+    /// DC coefficient stored as the difference from the previous block's
+    blocks: Vec<[i16; BLOCK_LEN]>,
+}
+
+impl<'a> JpegFile<'a> {
+    /// Parse a JPEG file and decode the coefficients of its scans
+    fn parse(data: &'a [u8]) -> Result<Self> {
+        if !data.starts_with(&[0xFF, SOI]) {
+            return Err(invalid("not a JPEG file"));
+        }
+
+        let mut frame: Option<Frame> = None;
+        let mut quant_tables: [Option<[u16; BLOCK_LEN]>; 4] = [None; 4];
+        let mut dc_tables: [Option<HuffmanTable>; 4] = Default::default();
+        let mut ac_tables: [Option<HuffmanTable>; 4] = Default::default();
+        let mut restart_interval = 0;
+        let mut scans = Vec::new();
+        let mut pos = 2;
+        loop {
+            // Markers may be preceded by any number of fill bytes
+            if data.get(pos) != Some(&0xFF) {
+                return Err(invalid("expected a marker"));
+            }
+            while data.get(pos) == Some(&0xFF) {
+                pos += 1;
+            }
+            let marker = *data
+                .get(pos)
+                .ok_or_else(|| invalid("missing end of image"))?;
+            pos += 1;
+            if marker == EOI {
+                break;
+            }
+
+            let length = read_u16(data, pos)? as usize;
+            let segment = data
+                .get(pos + 2..pos + length)
+                .ok_or_else(|| invalid("marker segment runs past the end of the file"))?;
+            pos += length;
+            match marker {
+                SOF0 | SOF1 => {
+                    if frame.is_some() {
+                        return Err(invalid("more than one frame"));
+                    }
+                    frame = Some(parse_frame(segment)?);
+                }
+                0xC2 | 0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => {
+                    return Err(invalid(
+                        "only baseline and extended sequential Huffman-coded JPEGs are supported",
+                    ));
+                }
+                DHT => parse_huffman_tables(segment, &mut dc_tables, &mut ac_tables)?,
+                DQT => parse_quant_tables(segment, &mut quant_tables)?,
+                DRI => restart_interval = read_u16(segment, 0)? as usize,
+                DNL => return Err(invalid("DNL markers are not supported")),
+                SOS => {
+                    let frame = frame
+                        .as_ref()
+                        .ok_or_else(|| invalid("scan before the frame header"))?;
+                    let end = scan_end(data, pos);
+                    let scan = Scan::decode(
+                        segment,
+                        frame,
+                        &dc_tables,
+                        &ac_tables,
+                        restart_interval,
+                        data.get(pos..end).unwrap_or_default(),
+                    )?;
+                    scans.push(Scan {
+                        data: pos..end,
+                        ..scan
+                    });
+                    pos = end;
+                }
+                _ => {} // Application data and comments are copied as they are
+            }
+        }
+
+        let frame = frame.ok_or_else(|| invalid("no frame header"))?;
+        if scans.is_empty() {
+            return Err(invalid("no scans"));
+        }
+        let luma_quant = frame
+            .components
+            .first()
+            .and_then(|component| quant_tables.get(usize::from(component.quant_table)))
+            .copied()
+            .flatten();
+        Ok(Self {
+            data,
+            width: frame.width,
+            height: frame.height,
+            luma_quant,
+            scans,
+        })
+    }
+
+    /// Estimate the quality the file was compressed at
+    ///
+    /// Compares the luminance quantization table with the standard one,
+    /// scaled the way libjpeg scales it for a quality setting.
+    fn quality(&self) -> Option<u8> {
+        let table = self.luma_quant?;
+        let scale = table
+            .iter()
+            .zip(STD_LUMA_QUANT)
+            .map(|(&q, standard)| f64::from(q) * 100.0 / f64::from(standard))
+            .sum::<f64>()
+            / BLOCK_LEN as f64;
+        let quality = if scale <= 100.0 {
+            (200.0 - scale) / 2.0
+        } else {
+            5000.0 / scale
+        };
+        Some(quality.round().clamp(1.0, 100.0) as u8)
+    }
+
+    /// The AC coefficients able to carry a bit, in coding order
+    fn carriers(&self, min_magnitude: u16) -> impl Iterator<Item = &i16> {
+        self.scans
+            .iter()
+            .flat_map(|scan| &scan.blocks)
+            .flat_map(|block| block.iter().skip(1))
+            .filter(move |coefficient| coefficient.unsigned_abs() >= min_magnitude)
+    }
+
+    /// Mutable access to the AC coefficients able to carry a bit, in coding order
+    fn carriers_mut(&mut self, min_magnitude: u16) -> impl Iterator<Item = &mut i16> {
+        self.scans
+            .iter_mut()
+            .flat_map(|scan| &mut scan.blocks)
+            .flat_map(|block| block.iter_mut().skip(1))
+            .filter(move |coefficient| coefficient.unsigned_abs() >= min_magnitude)
+    }
+
+    /// Write the file back with the scans re-encoded from their coefficients
+    fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut out = Vec::with_capacity(self.data.len());
+        let mut copied = 0;
+        for scan in &self.scans {
+            out.extend_from_slice(self.data.get(copied..scan.data.start).unwrap_or_default());
+            scan.encode(&mut out)?;
+            copied = scan.data.end;
+        }
+        out.extend_from_slice(self.data.get(copied..).unwrap_or_default());
+        Ok(out)
+    }
+}
+
+impl Scan {
+    /// Decode the coefficients of a scan
+    ///
+    /// # Arguments
+    /// * `header` - The SOS segment, without its length
+    /// * `frame` - The frame the scan belongs to
+    /// * `dc_tables`, `ac_tables` - The Huffman tables defined so far
+    /// * `restart_interval` - The restart interval defined so far
+    /// * `data` - The entropy-coded data, restart markers included
+    fn decode(
+        header: &[u8],
+        frame: &Frame,
+        dc_tables: &[Option<HuffmanTable>; 4],
+        ac_tables: &[Option<HuffmanTable>; 4],
+        restart_interval: usize,
+        data: &[u8],
+    ) -> Result<Self> {
+        let count = usize::from(*header.first().ok_or_else(|| invalid("empty scan header"))?);
+        let selectors = header
+            .get(1..1 + 2 * count)
+            .ok_or_else(|| invalid("scan header is too short"))?;
+        if header.get(1 + 2 * count..) != Some(&[0, 63, 0][..]) {
+            return Err(invalid("scan is not sequential"));
+        }
+
+        let mut components = Vec::with_capacity(count);
+        let mut frame_components = Vec::with_capacity(count);
+        for selector in selectors.chunks_exact(2) {
+            let (id, tables) = match selector {
+                [id, tables] => (*id, *tables),
+                _ => return Err(invalid("scan header is too short")),
+            };
+            let component = frame
+                .components
+                .iter()
+                .find(|component| component.id == id)
+                .ok_or_else(|| invalid("scan refers to an unknown component"))?;
+            let table = |tables: &[Option<HuffmanTable>; 4], index: u8| {
+                tables
+                    .get(usize::from(index))
+                    .cloned()
+                    .flatten()
+                    .ok_or_else(|| invalid("scan uses an undefined Huffman table"))
+            };
+            components.push(ScanComponent {
+                dc: table(dc_tables, tables >> 4)?,
+                ac: table(ac_tables, tables & 0x0F)?,
+            });
+            frame_components.push(*component);
+        }
+
+        // A single component is coded block by block over its own size;
+        // several are interleaved in MCUs covering the largest sampling
+        let (h_max, v_max) = frame.max_sampling();
+        let (width, height) = (usize::from(frame.width), usize::from(frame.height));
+        let (mcus, mcu_layout) = match frame_components.as_slice() {
+            [component] => {
+                let columns = (width * usize::from(component.h)).div_ceil(h_max);
+                let rows = (height * usize::from(component.v)).div_ceil(v_max);
+                (columns.div_ceil(8) * rows.div_ceil(8), vec![0])
+            }
+            _ => {
+                let layout = frame_components
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(index, component)| {
+                        std::iter::repeat_n(index, usize::from(component.h * component.v))
+                    })
+                    .collect();
+                let mcus = width.div_ceil(8 * h_max) * height.div_ceil(8 * v_max);
+                (mcus, layout)
+            }
+        };
+
+        let mut reader = BitReader::new(data);
+        let mut blocks = Vec::new();
+        for mcu in 0..mcus {
+            if restart_interval > 0 && mcu > 0 && mcu % restart_interval == 0 {
+                reader.restart((mcu / restart_interval - 1) % 8)?;
+            }
+            for &index in &mcu_layout {
+                let component = components
+                    .get(index)
+                    .ok_or_else(|| invalid("scan refers to an unknown component"))?;
+                blocks.push(read_block(&mut reader, &component.dc, &component.ac)?);
+            }
+        }
+
+        Ok(Self {
+            data: 0..0,
+            restart_interval,
+            components,
+            mcu_layout,
+            blocks,
+        })
+    }
+
+    /// Write the entropy-coded data of the scan from its coefficients
+    fn encode(&self, out: &mut Vec<u8>) -> Result<()> {
+        let mut writer = BitWriter::new(out);
+        let per_mcu = self.mcu_layout.len().max(1);
+        for (mcu, blocks) in self.blocks.chunks(per_mcu).enumerate() {
+            if self.restart_interval > 0 && mcu > 0 && mcu % self.restart_interval == 0 {
+                writer.restart((mcu / self.restart_interval - 1) % 8);
+            }
+            for (block, &index) in blocks.iter().zip(&self.mcu_layout) {
+                let component = self
+                    .components
+                    .get(index)
+                    .ok_or_else(|| invalid("scan refers to an unknown component"))?;
+                write_block(&mut writer, block, &component.dc, &component.ac)?;
+            }
+        }
+        writer.flush();
+        Ok(())
+    }
+}
+
+/// Read a big-endian `u16`
+fn read_u16(data: &[u8], pos: usize) -> Result<u16> {
+    match data.get(pos..pos + 2) {
+        Some(&[high, low]) => Ok(u16::from_be_bytes([high, low])),
+        _ => Err(invalid("file ends in the middle of a marker segment")),
+    }
+}
+
+/// Parse a frame header
+fn parse_frame(segment: &[u8]) -> Result<Frame> {
+    let (precision, count) = match segment {
+        [precision, _, _, _, _, count, ..] => (*precision, usize::from(*count)),
+        _ => return Err(invalid("frame header is too short")),
+    };
+    if precision != 8 {
+        return Err(invalid("only 8-bit samples are supported"));
+    }
+    let height = read_u16(segment, 1)?;
+    let width = read_u16(segment, 3)?;
+    if width == 0 || height == 0 {
+        return Err(invalid(
+            "image has no pixels or defines its height in a DNL marker",
+        ));
+    }
+
+    let components = segment
+        .get(6..6 + 3 * count)
+        .ok_or_else(|| invalid("frame header is too short"))?
+        .chunks_exact(3)
+        .map(|component| match component {
+            &[id, sampling, quant_table] => {
+                let (h, v) = (sampling >> 4, sampling & 0x0F);
+                if !(1..=4).contains(&h) || !(1..=4).contains(&v) || quant_table > 3 {
+                    return Err(invalid("invalid frame component"));
+                }
+                Ok(FrameComponent {
+                    id,
+                    h,
+                    v,
+                    quant_table,
+                })
+            }
+            _ => Err(invalid("frame header is too short")),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if components.is_empty() {
+        return Err(invalid("frame has no components"));
+    }
+    Ok(Frame {
+        width,
+        height,
+        components,
+    })
+}
+
+/// Parse a DQT segment into the quantization tables
+fn parse_quant_tables(
+    mut segment: &[u8],
+    tables: &mut [Option<[u16; BLOCK_LEN]>; 4],
+) -> Result<()> {
+    while let Some((&spec, rest)) = segment.split_first() {
+        let wide = spec >> 4 == 1;
+        let size = if wide { 2 * BLOCK_LEN } else { BLOCK_LEN };
+        let values = rest
+            .get(..size)
+            .ok_or_else(|| invalid("quantization table is too short"))?;
+        let mut table = [0; BLOCK_LEN];
+        if wide {
+            for (entry, pair) in table.iter_mut().zip(values.chunks_exact(2)) {
+                *entry = pair.iter().fold(0, |acc, &byte| acc << 8 | u16::from(byte));
+            }
+        } else {
+            for (entry, &value) in table.iter_mut().zip(values) {
+                *entry = u16::from(value);
+            }
+        }
+        *tables
+            .get_mut(usize::from(spec & 0x0F))
+            .ok_or_else(|| invalid("invalid quantization table index"))? = Some(table);
+        segment = rest.get(size..).unwrap_or_default();
+    }
+    Ok(())
+}
+
+/// Parse a DHT segment into the DC and AC Huffman tables
+fn parse_huffman_tables(
+    mut segment: &[u8],
+    dc_tables: &mut [Option<HuffmanTable>; 4],
+    ac_tables: &mut [Option<HuffmanTable>; 4],
+) -> Result<()> {
+    while let Some((&spec, rest)) = segment.split_first() {
+        let counts: [u8; 16] = rest
+            .get(..16)
+            .and_then(|counts| counts.try_into().ok())
+            .ok_or_else(|| invalid("Huffman table is too short"))?;
+        let total: usize = counts.iter().map(|&count| usize::from(count)).sum();
+        let values = rest
+            .get(16..16 + total)
+            .ok_or_else(|| invalid("Huffman table is too short"))?;
+        let tables = match spec >> 4 {
+            0 => &mut *dc_tables,
+            1 => &mut *ac_tables,
+            _ => return Err(invalid("invalid Huffman table class")),
+        };
+        *tables
+            .get_mut(usize::from(spec & 0x0F))
+            .ok_or_else(|| invalid("invalid Huffman table index"))? =
+            Some(HuffmanTable::new(&counts, values)?);
+        segment = rest.get(16 + total..).unwrap_or_default();
+    }
+    Ok(())
+}
+
+/// Find where the entropy-coded data starting at `start` ends
+///
+/// The data runs up to the first marker other than a restart marker; a
+/// stuffed `0xFF 0x00` is data.
+fn scan_end(data: &[u8], start: usize) -> usize {
+    let mut pos = start;
+    while let Some(&byte) = data.get(pos) {
+        if byte == 0xFF {
+            match data.get(pos + 1) {
+                Some(0) => pos += 2,
+                Some(marker) if (RST0..RST0 + 8).contains(marker) => pos += 2,
+                _ => return pos,
+            }
+        } else {
+            pos += 1;
+        }
+    }
+    pos
+}
+
+/// A Huffman table, for both decoding and encoding
+#[derive(Debug, Clone)]
+struct HuffmanTable {
+    /// Largest code of each length, -1 if there is none; index 0 is unused
+    max_code: [i32; 17],
+
+    /// Index in `values` of each length's first symbol minus its code
+    offset: [i32; 17],
+
+    /// The symbols, in order of increasing code
+    values: Vec<u8>,
+
+    /// Code and code length of each symbol
+    codes: Vec<Option<(u16, u8)>>,
+}
+
+impl HuffmanTable {
+    /// Build the canonical codes from the number of codes of each length
+    fn new(counts: &[u8; 16], values: &[u8]) -> Result<Self> {
+        let mut max_code = [-1; 17];
+        let mut offset = [0; 17];
+        let mut codes = vec![None; 256];
+        let mut code: u32 = 0;
+        let mut index = 0;
+        for ((length, &count), (max, first)) in (1u8..)
+            .zip(counts)
+            .zip(max_code.iter_mut().zip(&mut offset).skip(1))
+        {
+            let count = usize::from(count);
+            if count > 0 {
+                *first = index as i32 - code as i32;
+                for &value in values.get(index..index + count).unwrap_or_default() {
+                    if let Some(entry) = codes.get_mut(usize::from(value)) {
+                        *entry = Some((code as u16, length));
+                    }
+                    code += 1;
+                }
+                if code > 1 << length {
+                    return Err(invalid("Huffman table has more codes than fit"));
+                }
+                *max = code as i32 - 1;
+                index += count;
+            }
+            code <<= 1;
+        }
+
+        Ok(Self {
+            max_code,
+            offset,
+            values: values.to_vec(),
+            codes,
+        })
+    }
+
+    /// Read the next symbol
+    fn decode(&self, reader: &mut BitReader) -> Result<u8> {
+        let mut code = 0;
+        for (max, offset) in self.max_code.iter().zip(&self.offset).skip(1) {
+            code = (code << 1) | reader.bit()? as i32;
+            if code <= *max {
+                return self
+                    .values
+                    .get((code + offset) as usize)
+                    .copied()
+                    .ok_or_else(|| invalid("invalid Huffman code"));
+            }
+        }
+        Err(invalid("invalid Huffman code"))
+    }
+
+    /// Write a symbol
+    fn encode(&self, writer: &mut BitWriter, symbol: u8) -> Result<()> {
+        let (code, length) = self
+            .codes
+            .get(usize::from(symbol))
+            .copied()
+            .flatten()
+            .ok_or_else(|| invalid("Huffman table has no code for a symbol"))?;
+        writer.write(u32::from(code), length);
+        Ok(())
+    }
+}
+
+/// Read the coefficients of one block
+fn read_block(
+    reader: &mut BitReader,
+    dc: &HuffmanTable,
+    ac: &HuffmanTable,
+) -> Result<[i16; BLOCK_LEN]> {
+    let mut block = [0; BLOCK_LEN];
+    let size = dc.decode(reader)?;
+    if size > 11 {
+        return Err(invalid("DC difference is too large"));
+    }
+    if let Some(first) = block.first_mut() {
+        *first = reader.receive_extend(size)?;
+    }
+
+    let mut k = 1;
+    while k < BLOCK_LEN {
+        let symbol = ac.decode(reader)?;
+        let (run, size) = (usize::from(symbol >> 4), symbol & 0x0F);
+        if size == 0 {
+            match run {
+                0 => break,
+                15 => {
+                    k += 16;
+                    continue;
+                }
+                _ => return Err(invalid("invalid AC symbol")),
+            }
+        }
+        if size > 10 {
+            return Err(invalid("AC coefficient is too large"));
+        }
+        k += run;
+        *block
+            .get_mut(k)
+            .ok_or_else(|| invalid("AC coefficients run past the end of a block"))? =
+            reader.receive_extend(size)?;
+        k += 1;
+    }
+    if k > BLOCK_LEN {
+        return Err(invalid("AC coefficients run past the end of a block"));
+    }
+    Ok(block)
+}
+
+/// Write the coefficients of one block
+fn write_block(
+    writer: &mut BitWriter,
+    block: &[i16; BLOCK_LEN],
+    dc: &HuffmanTable,
+    ac: &HuffmanTable,
+) -> Result<()> {
+    let (dc_value, ac_values) = block.split_first().ok_or_else(|| invalid("empty block"))?;
+    let (size, bits) = magnitude_bits(*dc_value);
+    dc.encode(writer, size)?;
+    writer.write(bits, size);
+
+    let mut run = 0;
+    for &value in ac_values {
+        if value == 0 {
+            run += 1;
+            continue;
+        }
+        while run >= 16 {
+            ac.encode(writer, 0xF0)?;
+            run -= 16;
+        }
+        let (size, bits) = magnitude_bits(value);
+        ac.encode(writer, (run << 4) | size)?;
+        writer.write(bits, size);
+        run = 0;
+    }
+    if run > 0 {
+        ac.encode(writer, 0x00)?;
+    }
+    Ok(())
+}
+
+/// Size category of a coefficient and the bits that follow its symbol
+///
+/// Negative values are written as their ones' complement.
+fn magnitude_bits(value: i16) -> (u8, u32) {
+    let size = (16 - value.unsigned_abs().leading_zeros()) as u8;
+    let mask = (1u32 << size) - 1;
+    let bits = if value < 0 {
+        (i32::from(value) - 1) as u32 & mask
+    } else {
+        value as u32
+    };
+    (size, bits)
+}
+
+/// Reads bits from entropy-coded data, removing byte stuffing
+struct BitReader<'a> {
+    /// The entropy-coded data
+    data: &'a [u8],
+
+    /// Position of the next byte
+    pos: usize,
+
+    /// The byte being read
+    byte: u8,
+
+    /// Bits of `byte` not yet read
+    bits_left: u8,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            byte: 0,
+            bits_left: 0,
+        }
+    }
+
+    /// Read one bit
+    fn bit(&mut self) -> Result<u32> {
+        if self.bits_left == 0 {
+            let byte = *self
+                .data
+                .get(self.pos)
+                .ok_or_else(|| invalid("scan data ends in the middle of a block"))?;
+            self.pos += 1;
+            if byte == 0xFF {
+                if self.data.get(self.pos) != Some(&0) {
+                    return Err(invalid("unexpected marker in scan data"));
+                }
+                self.pos += 1;
+            }
+            self.byte = byte;
+            self.bits_left = 8;
+        }
+        self.bits_left -= 1;
+        Ok(u32::from(self.byte >> self.bits_left) & 1)
+    }
+
+    /// Read a coefficient of the given size category
+    fn receive_extend(&mut self, size: u8) -> Result<i16> {
+        let mut value = 0i32;
+        for _ in 0..size {
+            value = (value << 1) | self.bit()? as i32;
+        }
+        if size > 0 && value < 1 << (size - 1) {
+            value += 1 - (1 << size);
+        }
+        Ok(value as i16)
+    }
+
+    /// Skip to the byte after the next restart marker
+    fn restart(&mut self, index: usize) -> Result<()> {
+        self.bits_left = 0;
+        match self.data.get(self.pos..self.pos + 2) {
+            Some(&[0xFF, marker]) if usize::from(marker) == usize::from(RST0) + index => {
+                self.pos += 2;
+                Ok(())
+            }
+            _ => Err(invalid("missing restart marker")),
+        }
+    }
+}
+
+/// Writes entropy-coded data, stuffing a zero after every `0xFF`
+struct BitWriter<'a> {
+    /// Where the data goes
+    out: &'a mut Vec<u8>,
+
+    /// Bits not yet written, in the lowest `count` bits
+    pending: u32,
+
+    /// Number of pending bits, always below 8 between writes
+    count: u8,
+}
+
+impl<'a> BitWriter<'a> {
+    fn new(out: &'a mut Vec<u8>) -> Self {
+        Self {
+            out,
+            pending: 0,
+            count: 0,
+        }
+    }
+
+    /// Write the lowest `length` bits of `bits`, most significant first
+    fn write(&mut self, bits: u32, length: u8) {
+        if length == 0 {
+            return;
+        }
+        self.pending = (self.pending << length) | (bits & ((1 << length) - 1));
+        self.count += length;
+        while self.count >= 8 {
+            self.count -= 8;
+            let byte = (self.pending >> self.count) as u8;
+            self.out.push(byte);
+            if byte == 0xFF {
+                self.out.push(0);
+            }
+        }
+        self.pending &= (1 << self.count) - 1;
+    }
+
+    /// Pad the last byte with one bits
+    fn flush(&mut self) {
+        if self.count > 0 {
+            self.write(0xFF, 8 - self.count);
+        }
+    }
+
+    /// Finish the current interval and write a restart marker
+    fn restart(&mut self, index: usize) {
+        self.flush();
+        self.out.push(0xFF);
+        self.out.push(RST0 + (index % 8) as u8);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::noise_image;
+    use image::codecs::jpeg::JpegEncoder;
+    use image::DynamicImage;
+
+    /// Compress a noisy image to a baseline JPEG with the image crate
+    fn baseline_jpeg(image: DynamicImage, quality: u8) -> Vec<u8> {
+        let mut jpeg = Vec::new();
+        JpegEncoder::new_with_quality(&mut jpeg, quality)
+            .encode_image(&image)
+            .unwrap();
+        jpeg
+    }
+
+    fn color_jpeg(quality: u8) -> Vec<u8> {
+        baseline_jpeg(noise_image(40, 24, 7).inner().clone(), quality)
+    }
+
+    /// Add a restart interval to a single-scan JPEG, re-encoding its scan
+    fn with_restarts(jpeg: &[u8], interval: u16) -> Vec<u8> {
+        let mut file = JpegFile::parse(jpeg).unwrap();
+        let scan = &mut file.scans[0];
+        scan.restart_interval = usize::from(interval);
+
+        // The DC predictors start over at every marker, so the stored
+        // differences have to be recomputed against the new boundaries
+        let mcu_len = scan.mcu_layout.len();
+        let mut absolute = vec![0i16; scan.components.len()];
+        let mut previous = vec![0i16; scan.components.len()];
+        for (i, block) in scan.blocks.iter_mut().enumerate() {
+            let mcu = i / mcu_len;
+            if i.is_multiple_of(mcu_len) && mcu.is_multiple_of(usize::from(interval)) {
+                previous.fill(0);
+            }
+            let component = scan.mcu_layout[i % mcu_len];
+            absolute[component] += block[0];
+            block[0] = absolute[component] - previous[component];
+            previous[component] = absolute[component];
+        }
+        let mut rewritten = file.to_bytes().unwrap();
+
+        let sos = rewritten
+            .windows(2)
+            .position(|window| window == [0xFF, SOS])
+            .unwrap();
+        let mut dri = vec![0xFF, DRI, 0, 4];
+        dri.extend_from_slice(&interval.to_be_bytes());
+        rewritten.splice(sos..sos, dri);
+        rewritten
+    }
+
+    #[test]
+    fn test_rewriting_unchanged_coefficients_reproduces_the_file() {
+        for jpeg in [
+            color_jpeg(90),
+            baseline_jpeg(
+                DynamicImage::ImageLuma8(noise_image(33, 17, 3).inner().to_luma8()),
+                75,
+            ),
+        ] {
+            let file = JpegFile::parse(&jpeg).unwrap();
+            assert_eq!(file.to_bytes().unwrap(), jpeg);
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let cover = color_jpeg(90);
+        let stego = JpegStego::new();
+        let message = b"hidden in the coefficients";
+
+        let encoded = stego.encode(&cover, message).unwrap();
+        assert_eq!(stego.decode(&encoded).unwrap(), message);
+        assert!(encoded.len().abs_diff(cover.len()) < 16);
+
+        // The stego file still decodes as an image of the same size
+        let image = image::load_from_memory(&encoded).unwrap();
+        assert_eq!((image.width(), image.height()), (40, 24));
+    }
+
+    #[test]
+    fn test_round_trip_with_restart_markers() {
+        let cover = with_restarts(&color_jpeg(85), 2);
+        assert_eq!(
+            image::load_from_memory(&cover).unwrap().to_rgb8(),
+            image::load_from_memory(&color_jpeg(85)).unwrap().to_rgb8()
+        );
+
+        let encoded = JpegStego::new().encode(&cover, b"restarts").unwrap();
+        assert_eq!(JpegStego::new().decode(&encoded).unwrap(), b"restarts");
+        assert!(image::load_from_memory(&encoded).is_ok());
+    }
+
+    #[test]
+    fn test_capacity_and_quality() {
+        let cover = color_jpeg(75);
+        let capacity = JpegStego::new().capacity(&cover).unwrap();
+        assert_eq!((capacity.width, capacity.height), (40, 24));
+        assert_eq!(capacity.blocks, 3 * 5 * 3);
+        assert!(capacity
+            .quality
+            .is_some_and(|quality| quality.abs_diff(75) <= 2));
+        assert_eq!(
+            capacity.max_message_bytes,
+            capacity.carrier_coefficients / 8 - JPEG_HEADER_SIZE
+        );
+
+        let message = vec![0x5A; capacity.max_message_bytes];
+        assert!(JpegStego::new().encode(&cover, &message).is_ok());
+        assert!(matches!(
+            JpegStego::new().encode(&cover, &[message, vec![0]].concat()),
+            Err(HideError::MessageTooLarge)
+        ));
+    }
+
+    #[test]
+    fn test_higher_min_magnitude_uses_fewer_coefficients() {
+        let cover = color_jpeg(90);
+        let strict = JpegStego::with_config(JpegConfig {
+            min_magnitude: 4,
+            ..Default::default()
+        });
+        assert!(
+            strict.capacity(&cover).unwrap().carrier_coefficients
+                < JpegStego::new()
+                    .capacity(&cover)
+                    .unwrap()
+                    .carrier_coefficients
+        );
+
+        let encoded = strict.encode(&cover, b"sparse").unwrap();
+        assert_eq!(strict.decode(&encoded).unwrap(), b"sparse");
+
+        let loose = JpegStego::with_config(JpegConfig {
+            min_magnitude: 1,
+            ..Default::default()
+        });
+        assert!(matches!(
+            loose.capacity(&cover),
+            Err(HideError::InvalidParameters(_))
+        ));
+    }
+
+    #[test]
+    fn test_min_quality_rejects_low_quality_covers() {
+        let picky = JpegStego::with_config(JpegConfig {
+            min_quality: Some(80),
+            ..Default::default()
+        });
+        assert!(matches!(
+            picky.encode(&color_jpeg(50), b"x"),
+            Err(HideError::InvalidParameters(_))
+        ));
+        assert!(picky.encode(&color_jpeg(95), b"x").is_ok());
+    }
+
+    #[test]
+    fn test_cover_without_payload() {
+        assert!(matches!(
+            JpegStego::new().decode(&color_jpeg(90)),
+            Err(HideError::NoMessageFound)
+        ));
+    }
+
+    #[test]
+    fn test_rejects_unsupported_and_malformed_files() {
+        let png = noise_image(8, 8, 1)
+            .to_bytes(image::ImageFormat::Png)
+            .unwrap();
+        assert!(!is_jpeg(&png));
+        assert!(matches!(
+            JpegStego::new().capacity(&png),
+            Err(HideError::InvalidJpeg(_))
+        ));
+
+        // A progressive frame header
+        let mut progressive = color_jpeg(90);
+        let sof = progressive
+            .windows(2)
+            .position(|window| window == [0xFF, SOF0])
+            .unwrap();
+        progressive[sof + 1] = 0xC2;
+        assert!(matches!(
+            JpegStego::new().capacity(&progressive),
+            Err(HideError::InvalidJpeg(_))
+        ));
+
+        // Truncated scan data
+        let jpeg = color_jpeg(90);
+        assert!(matches!(
+            JpegStego::new().capacity(&jpeg[..jpeg.len() / 2]),
+            Err(HideError::InvalidJpeg(_))
+        ));
+    }
+
+    #[test]
+    fn test_magnitude_bits() {
+        assert_eq!(magnitude_bits(0), (0, 0));
+        assert_eq!(magnitude_bits(1), (1, 1));
+        assert_eq!(magnitude_bits(-1), (1, 0));
+        assert_eq!(magnitude_bits(-2), (2, 1));
+        assert_eq!(magnitude_bits(-3), (2, 0));
+        assert_eq!(magnitude_bits(5), (3, 5));
+    }
+}
@@ -0,0 +1,214 @@
+//! Typed positions in an image and its payload
+//!
+//! The payload layout mixes several kinds of numbers: pixel columns and
+//! rows, row-major pixel indices, and bit or byte offsets into the payload.
+//! They are all plain integers, so swapping `x` and `y` or passing a byte
+//! offset where a bit offset is expected compiles without complaint. The
+//! newtypes here keep them apart; conversions between kinds go through
+//! named methods that spell out the arithmetic.
+//!
+//! Long-standing APIs such as [`StegoImage::get_pixel_rgb`] keep taking
+//! plain `u32` coordinates.
+//!
+//! [`StegoImage::get_pixel_rgb`]: crate::img::StegoImage::get_pixel_rgb
+
+use crate::img::BITS_PER_PIXEL;
+use std::fmt;
+use std::ops::{Add, AddAssign, Sub};
+
+/// Column of a pixel, counted from the left edge
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PixelX(pub u32);
+
+/// Row of a pixel, counted from the top edge
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PixelY(pub u32);
+
+/// Row-major index of a pixel
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PixelIndex(pub u64);
+
+/// Position of a bit, counted from the start of the payload
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BitOffset(pub u64);
+
+/// Position of a byte, counted from the start of the payload
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ByteOffset(pub u64);
+
+/// Conversions, formatting and arithmetic shared by all positions
+///
+/// Adding a plain count moves a position; subtracting two positions of the
+/// same kind gives the plain distance between them.
+macro_rules! position {
+    ($name:ident, $inner:ty) => {
+        impl $name {
+            /// The underlying number
+            pub const fn get(self) -> $inner {
+                self.0
+            }
+        }
+
+        impl From<$inner> for $name {
+            fn from(value: $inner) -> Self {
+                Self(value)
+            }
+        }
+
+        impl From<$name> for $inner {
+            fn from(value: $name) -> Self {
+                value.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+
+        impl Add<$inner> for $name {
+            type Output = Self;
+
+            fn add(self, rhs: $inner) -> Self {
+                Self(self.0 + rhs)
+            }
+        }
+
+        impl AddAssign<$inner> for $name {
+            fn add_assign(&mut self, rhs: $inner) {
+                self.0 += rhs;
+            }
+        }
+
+        impl Sub<$inner> for $name {
+            type Output = Self;
+
+            fn sub(self, rhs: $inner) -> Self {
+                Self(self.0 - rhs)
+            }
+        }
+
+        impl Sub for $name {
+            type Output = $inner;
+
+            fn sub(self, rhs: Self) -> $inner {
+                self.0 - rhs.0
+            }
+        }
+    };
+}
+
+position!(PixelX, u32);
+position!(PixelY, u32);
+position!(PixelIndex, u64);
+position!(BitOffset, u64);
+position!(ByteOffset, u64);
+
+impl PixelIndex {
+    /// Index of the pixel at a column and row
+    ///
+    /// # Arguments
+    /// * `x`, `y` - Coordinates of the pixel
+    /// * `width` - Width of the image in pixels
+    pub const fn from_xy(x: PixelX, y: PixelY, width: u32) -> Self {
+        Self(y.0 as u64 * width as u64 + x.0 as u64)
+    }
+
+    /// Column and row of the pixel
+    ///
+    /// # Arguments
+    /// * `width` - Width of the image in pixels, treated as 1 if zero
+    pub const fn to_xy(self, width: u32) -> (PixelX, PixelY) {
+        let width = if width == 0 { 1 } else { width as u64 };
+        (
+            PixelX((self.0 % width) as u32),
+            PixelY((self.0 / width) as u32),
+        )
+    }
+
+    /// Position of the first payload bit the pixel carries
+    pub const fn first_bit(self) -> BitOffset {
+        BitOffset(self.0 * BITS_PER_PIXEL)
+    }
+}
+
+impl BitOffset {
+    /// The pixel carrying this bit
+    pub const fn pixel(self) -> PixelIndex {
+        PixelIndex(self.0 / BITS_PER_PIXEL)
+    }
+
+    /// The byte holding this bit
+    pub const fn byte(self) -> ByteOffset {
+        ByteOffset(self.0 / 8)
+    }
+
+    /// Number of pixels needed to carry every bit before this one
+    pub const fn pixels_before(self) -> u64 {
+        self.0.div_ceil(BITS_PER_PIXEL)
+    }
+}
+
+impl ByteOffset {
+    /// Position of the first bit of the byte
+    pub const fn first_bit(self) -> BitOffset {
+        BitOffset(self.0 * 8)
+    }
+}
+
+impl From<ByteOffset> for BitOffset {
+    fn from(offset: ByteOffset) -> Self {
+        offset.first_bit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index_round_trip_on_wide_image() {
+        // 7x3: swapping x and y would either overflow the row or land elsewhere
+        let index = PixelIndex::from_xy(PixelX(5), PixelY(2), 7);
+        assert_eq!(index, PixelIndex(19));
+        assert_eq!(index.to_xy(7), (PixelX(5), PixelY(2)));
+        assert_ne!(PixelIndex::from_xy(PixelX(2), PixelY(5), 7), index);
+    }
+
+    #[test]
+    fn test_index_round_trip_on_tall_image() {
+        for index in 0..3 * 7 {
+            let (x, y) = PixelIndex(index).to_xy(3);
+            assert!(x.get() < 3 && y.get() < 7);
+            assert_eq!(PixelIndex::from_xy(x, y, 3), PixelIndex(index));
+        }
+    }
+
+    #[test]
+    fn test_zero_width_does_not_divide_by_zero() {
+        assert_eq!(PixelIndex(4).to_xy(0), (PixelX(0), PixelY(4)));
+    }
+
+    #[test]
+    fn test_bit_and_byte_conversions() {
+        assert_eq!(BitOffset::from(ByteOffset(8)), BitOffset(64));
+        assert_eq!(BitOffset(64).byte(), ByteOffset(8));
+        assert_eq!(BitOffset(65).byte(), ByteOffset(8));
+        assert_eq!(BitOffset(64).pixel(), PixelIndex(21));
+        assert_eq!(BitOffset(64).pixels_before(), 22);
+        assert_eq!(PixelIndex(21).first_bit(), BitOffset(63));
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let mut bit = BitOffset(10) + 5;
+        bit += 1;
+        assert_eq!(bit, BitOffset(16));
+        assert_eq!(bit - BitOffset(4), 12);
+        assert_eq!(bit - 6, BitOffset(10));
+        assert_eq!(PixelX(3) + 1, PixelX(4));
+        assert_eq!(u64::from(PixelIndex(9)), 9);
+        assert_eq!(PixelY::from(2).to_string(), "2");
+    }
+}
@@ -3,15 +3,87 @@
 //! `hide-rs` is a steganography library that implements the Binary Linear Transformation Matrix
 //! (BLTM) method for hiding messages within images. This library provides functionality to
 //! encode messages into images and decode them back without visible changes to the image.
+//!
+//! The types most programs need are re-exported here:
+//!
+//! ```
+//! use hide_rs::{create_decoder, create_encoder, Decoder, Encoder, HideError, StegoImage};
+//!
+//! # fn main() -> hide_rs::Result<()> {
+//! let cover = StegoImage::new_rgb(64, 64)?;
+//! let capacity = Encoder::max_message_size_for_dimensions(64, 64);
+//!
+//! let stego = create_encoder().encode(cover, b"meet at noon")?;
+//! assert_eq!(create_decoder().decode(&stego)?, b"meet at noon");
+//!
+//! // Messages are checked against the cover before anything is written
+//! let too_long = vec![0; capacity + 1];
+//! let cover = StegoImage::new_rgb(64, 64)?;
+//! assert!(matches!(
+//!     Encoder::new().encode(cover, &too_long),
+//!     Err(HideError::MessageTooLarge)
+//! ));
+//!
+//! // Without a header check, the raw bits start with the header itself
+//! let raw = hide_rs::extract_raw_data(&stego)?;
+//! assert_eq!(raw.first(), Decoder::new().read_header(&stego)?.to_bytes().first());
+//! # Ok(())
+//! # }
+//! ```
+
+#![warn(clippy::unwrap_used, clippy::expect_used, clippy::indexing_slicing)]
 
 pub mod api;
+pub mod batch;
 pub mod bltm;
+pub mod cancel;
+pub mod capabilities;
+#[cfg(feature = "client")]
+pub mod client;
+pub mod compression;
+pub mod conformance;
+pub mod container;
+pub mod crypto;
 pub mod decoder;
+pub mod depth;
+pub mod ecc;
 pub mod encoder;
 pub mod error;
+pub mod header;
 pub mod img;
+#[cfg(feature = "jpeg")]
+pub mod jpeg;
+pub mod layout;
+pub mod manifest;
+pub mod mask;
+pub mod pack;
+pub mod padding;
+pub mod planner;
+pub mod png_rows;
+pub mod preset;
+pub mod progress;
 pub mod raw_decoder;
+pub mod report;
+pub mod rgba;
+pub mod rng;
+pub mod robustness;
+pub mod scatter;
+pub mod schedule;
+pub mod self_test;
+pub mod stream;
+#[doc(hidden)]
+pub mod testing;
+pub mod trace;
 pub mod utils;
+pub mod warning;
+pub mod watermark;
+
+pub use capabilities::{capabilities, Capabilities};
+pub use decoder::{create_decoder, Decoder};
+pub use encoder::{create_encoder, Encoder};
+pub use error::HideError;
+pub use img::StegoImage;
+pub use raw_decoder::{extract_raw_data, extract_raw_to_writer, RawExtractOptions};
 
 /// The result type returned by functions in this library.
 pub type Result<T> = std::result::Result<T, error::HideError>;
@@ -26,7 +98,7 @@ mod tests {
     #[test]
     fn test_image_operations() {
         // Create a small test image
-        let mut img = img::create_rgb_image(2, 2);
+        let mut img = img::create_rgb_image(2, 2).unwrap();
 
         // Set some pixel values
         img.set_pixel_rgb(0, 0, image::Rgb([255, 0, 0])).unwrap();
@@ -0,0 +1,112 @@
+//! Provenance manifests written next to stego images
+
+use crate::encoder::EncodeStats;
+use crate::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Suffix appended to a stego image path to name its manifest
+pub const MANIFEST_SUFFIX: &str = ".hide.json";
+
+/// Provenance record for an encoded image
+///
+/// The manifest never contains the message itself, only its length and hash,
+/// and the encoder configuration is recorded without any secret material.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Version of hide-rs that encoded the image
+    pub hide_rs_version: String,
+
+    /// When the image was encoded
+    pub created_at: DateTime<Utc>,
+
+    /// File name of the cover image
+    pub cover_filename: String,
+
+    /// SHA-256 of the cover image file (hex)
+    pub cover_sha256: String,
+
+    /// SHA-256 of the stego image file (hex)
+    pub stego_sha256: String,
+
+    /// Length of the embedded message in bytes
+    pub message_length: usize,
+
+    /// SHA-256 of the embedded message (hex)
+    pub message_sha256: String,
+
+    /// Encoder configuration, with secrets removed
+    pub config: serde_json::Value,
+
+    /// Statistics about the encode
+    pub stats: EncodeStats,
+}
+
+impl Manifest {
+    /// Write the manifest as pretty-printed JSON
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Read a manifest from a file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let data = fs::read(path)?;
+        Ok(serde_json::from_slice(&data)?)
+    }
+}
+
+/// Result of checking a stego image against its manifest
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ManifestVerification {
+    /// Descriptions of every field that did not match
+    pub mismatches: Vec<String>,
+}
+
+impl ManifestVerification {
+    /// Whether the image matched the manifest
+    pub fn is_valid(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+/// Get the manifest path for a stego image path
+pub fn manifest_path_for(stego_path: &Path) -> PathBuf {
+    let mut name = stego_path.as_os_str().to_os_string();
+    name.push(MANIFEST_SUFFIX);
+    PathBuf::from(name)
+}
+
+/// Compute the SHA-256 of some data as lowercase hex
+pub fn sha256_hex(data: &[u8]) -> String {
+    to_hex(&Sha256::digest(data))
+}
+
+/// Format a digest as lowercase hex
+pub fn to_hex(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_path_for() {
+        assert_eq!(
+            manifest_path_for(Path::new("out/stego.png")),
+            PathBuf::from("out/stego.png.hide.json")
+        );
+    }
+
+    #[test]
+    fn test_sha256_hex() {
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
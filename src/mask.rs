@@ -0,0 +1,329 @@
+//! Pixel masks keeping payloads away from chosen pixels
+//!
+//! A [`PixelMask`] marks the cover pixels an encode must leave untouched,
+//! usually built from a mask image whose light pixels are excluded. Masked
+//! payloads set [`HeaderFlags::MASKED`] and skip the excluded pixels: the bits
+//! an excluded pixel would have carried go into the next included one.
+//!
+//! Decoders need the mask to find the payload's pixels. A run-length encoded
+//! copy of it can be embedded right after the header, with its length in the
+//! header's extension; otherwise the mask must be passed to the decoder
+//! through [`DecoderConfig::mask`]. The header and the embedded copy fill the
+//! first pixels in row-major order, so the mask must leave those free.
+//!
+//! [`HeaderFlags::MASKED`]: crate::header::HeaderFlags::MASKED
+//! [`DecoderConfig::mask`]: crate::decoder::DecoderConfig::mask
+
+use crate::depth::{self, EmbeddingDepth};
+use crate::error::HideError;
+use crate::header::EXTENDED_HEADER_SIZE;
+use crate::img::{self, StegoImage};
+use crate::Result;
+use bitvec::prelude::*;
+use std::fmt;
+
+/// Mask luminance above which a pixel is excluded, unless another is given
+pub const DEFAULT_MASK_THRESHOLD: u8 = 127;
+
+/// Largest run-length encoded mask embedded in an image
+///
+/// Its length shares two header bytes with the extra flags, so it stays below
+/// the lowest of them ([`crate::header::ExtraFlags`]).
+pub const MAX_EMBEDDED_MASK_BYTES: usize = 4095;
+
+/// Pixels of an image that payloads must not touch
+#[derive(Clone, PartialEq, Eq)]
+pub struct PixelMask {
+    /// Width of the masked image in pixels
+    width: u32,
+
+    /// Height of the masked image in pixels
+    height: u32,
+
+    /// One bit per pixel in row-major order, set for excluded pixels
+    excluded: BitVec<u8, Msb0>,
+}
+
+impl fmt::Debug for PixelMask {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PixelMask")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("excluded_pixels", &self.excluded_pixels())
+            .finish()
+    }
+}
+
+impl PixelMask {
+    /// Build a mask from a mask image
+    ///
+    /// # Arguments
+    /// * `mask` - Image the size of the cover; light pixels mark pixels to leave untouched
+    /// * `threshold` - Luminance above which a pixel is excluded
+    ///
+    /// # Returns
+    /// * The mask
+    pub fn from_image(mask: &StegoImage, threshold: u8) -> Self {
+        let luma = mask.inner().to_luma8();
+        Self {
+            width: mask.width(),
+            height: mask.height(),
+            excluded: luma.pixels().map(|pixel| pixel.0[0] > threshold).collect(),
+        }
+    }
+
+    /// Build a mask excluding the pixels a predicate picks
+    ///
+    /// # Arguments
+    /// * `width` - Width of the masked image in pixels
+    /// * `height` - Height of the masked image in pixels
+    /// * `excluded` - Whether the pixel at `(x, y)` is excluded
+    pub fn from_fn(width: u32, height: u32, mut excluded: impl FnMut(u32, u32) -> bool) -> Self {
+        Self {
+            width,
+            height,
+            excluded: (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .map(|(x, y)| excluded(x, y))
+                .collect(),
+        }
+    }
+
+    /// Width and height of the masked image
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Whether the pixel at a row-major index is excluded
+    pub fn is_excluded(&self, index: u64) -> bool {
+        usize::try_from(index)
+            .ok()
+            .and_then(|index| self.excluded.get(index).as_deref().copied())
+            .unwrap_or(false)
+    }
+
+    /// Number of excluded pixels
+    pub fn excluded_pixels(&self) -> u64 {
+        self.excluded.count_ones() as u64
+    }
+
+    /// Number of pixels payloads may use
+    pub fn included_pixels(&self) -> u64 {
+        self.excluded.count_zeros() as u64
+    }
+
+    /// Row-major index of the first excluded pixel, if any
+    pub fn first_excluded(&self) -> Option<u64> {
+        self.excluded.first_one().map(|index| index as u64)
+    }
+
+    /// Row-major indices of the pixels payloads may use, in embedding order
+    pub(crate) fn included(&self) -> impl Iterator<Item = u64> + '_ {
+        self.excluded.iter_zeros().map(|index| index as u64)
+    }
+
+    /// Check that the mask was made for an image of the given size
+    ///
+    /// # Returns
+    /// * `Ok(())`, or `InvalidParameters` if the sizes differ
+    pub fn check_dimensions(&self, width: u32, height: u32) -> Result<()> {
+        if (self.width, self.height) != (width, height) {
+            return Err(HideError::InvalidParameters(format!(
+                "Mask is {}x{} but the image is {}x{}",
+                self.width, self.height, width, height
+            )));
+        }
+        Ok(())
+    }
+
+    /// Run-length encode the mask
+    ///
+    /// Runs alternate between included and excluded pixels, starting with
+    /// included ones, and are written as LEB128 varints. The dimensions are
+    /// not stored; they are those of the image the mask travels with.
+    pub fn to_rle(&self) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        let mut run_excluded = false;
+        let mut run = 0u64;
+        for excluded in self.excluded.iter().by_vals() {
+            if excluded != run_excluded {
+                write_varint(&mut encoded, run);
+                run_excluded = excluded;
+                run = 0;
+            }
+            run += 1;
+        }
+        write_varint(&mut encoded, run);
+        encoded
+    }
+
+    /// Decode a mask written by [`PixelMask::to_rle`]
+    ///
+    /// # Arguments
+    /// * `width` - Width of the masked image in pixels
+    /// * `height` - Height of the masked image in pixels
+    /// * `data` - The run-length encoded mask
+    ///
+    /// # Returns
+    /// * The mask, or `InvalidParameters` if the runs don't cover the image exactly
+    pub fn from_rle(width: u32, height: u32, data: &[u8]) -> Result<Self> {
+        let invalid = || HideError::InvalidParameters("Invalid embedded mask".to_string());
+        let pixels = img::pixel_count(width, height);
+
+        let mut excluded = BitVec::<u8, Msb0>::new();
+        let mut run_excluded = false;
+        let mut rest = data;
+        while !rest.is_empty() {
+            let run = read_varint(&mut rest).ok_or_else(invalid)?;
+            if run > pixels - excluded.len() as u64 {
+                return Err(invalid());
+            }
+            let run = usize::try_from(run).map_err(|_| invalid())?;
+            excluded.resize(excluded.len() + run, run_excluded);
+            run_excluded = !run_excluded;
+        }
+
+        if excluded.len() as u64 != pixels {
+            return Err(invalid());
+        }
+        Ok(Self {
+            width,
+            height,
+            excluded,
+        })
+    }
+
+    /// The copy of the mask to embed after the header, if it can be embedded
+    ///
+    /// # Returns
+    /// * The run-length encoded mask, or `None` if it is over
+    ///   [`MAX_EMBEDDED_MASK_BYTES`] or excludes a pixel it would be embedded in
+    pub fn embedded_copy(&self) -> Option<Vec<u8>> {
+        let encoded = self.to_rle();
+        let fits = encoded.len() <= MAX_EMBEDDED_MASK_BYTES
+            && self
+                .first_excluded()
+                .is_none_or(|index| index >= prefix_pixels(encoded.len()));
+        fits.then_some(encoded)
+    }
+
+    /// Largest message a masked payload can hold
+    ///
+    /// # Arguments
+    /// * `embedded_mask_bytes` - Length of the embedded copy of the mask, 0 if it isn't embedded
+    ///
+    /// # Returns
+    /// * Maximum message size in bytes (accounting for the header and embedded mask)
+    pub fn max_message_size(&self, embedded_mask_bytes: usize) -> usize {
+        self.payload_capacity(embedded_mask_bytes, EmbeddingDepth::One)
+    }
+
+    /// Largest payload the included pixels hold after the header and embedded mask at a given depth
+    pub(crate) fn payload_capacity(
+        &self,
+        embedded_mask_bytes: usize,
+        depth: EmbeddingDepth,
+    ) -> usize {
+        depth::capacity_after_prefix(
+            self.included_pixels(),
+            EXTENDED_HEADER_SIZE + embedded_mask_bytes,
+            depth,
+        )
+    }
+}
+
+/// Number of leading pixels holding the header and an embedded mask
+///
+/// # Arguments
+/// * `embedded_mask_bytes` - Length of the embedded mask, 0 if none
+pub(crate) fn prefix_pixels(embedded_mask_bytes: usize) -> u64 {
+    img::pixels_for_bits((EXTENDED_HEADER_SIZE + embedded_mask_bytes) as u64 * 8)
+}
+
+/// Append an unsigned LEB128 varint
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Read an unsigned LEB128 varint from the front of a slice
+///
+/// # Returns
+/// * The value, or `None` if the slice ends inside it or it overflows 64 bits
+fn read_varint(data: &mut &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = data.split_first()?;
+        *data = rest;
+        value |= u64::from(byte & 0x7f).checked_shl(shift)?;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, GrayImage, Luma};
+
+    #[test]
+    fn test_from_image_uses_threshold() {
+        let gray = GrayImage::from_fn(4, 2, |x, _| Luma([(x * 80) as u8]));
+        let image = StegoImage::from_dynamic_image(DynamicImage::ImageLuma8(gray));
+
+        let mask = PixelMask::from_image(&image, DEFAULT_MASK_THRESHOLD);
+        assert_eq!(mask.excluded_pixels(), 4);
+        assert!(!mask.is_excluded(1));
+        assert!(mask.is_excluded(2));
+        assert_eq!(mask.first_excluded(), Some(2));
+        assert!(!mask.is_excluded(100));
+
+        let strict = PixelMask::from_image(&image, 200);
+        assert_eq!(strict.excluded_pixels(), 2);
+    }
+
+    #[test]
+    fn test_rle_round_trip() {
+        let masks = [
+            PixelMask::from_fn(7, 5, |x, y| (x + y) % 3 == 0),
+            PixelMask::from_fn(300, 2, |x, _| x >= 10),
+            PixelMask::from_fn(3, 3, |_, _| false),
+            PixelMask::from_fn(3, 3, |_, _| true),
+        ];
+        for mask in masks {
+            let (width, height) = mask.dimensions();
+            assert_eq!(
+                PixelMask::from_rle(width, height, &mask.to_rle()).unwrap(),
+                mask
+            );
+        }
+
+        // Runs of 290 take two-byte varints
+        let mask = PixelMask::from_fn(300, 2, |x, _| x >= 10);
+        assert_eq!(mask.to_rle().len(), 6);
+    }
+
+    #[test]
+    fn test_rle_rejects_runs_not_covering_the_image() {
+        let mask = PixelMask::from_fn(4, 4, |x, _| x == 3);
+        let encoded = mask.to_rle();
+        assert!(PixelMask::from_rle(4, 5, &encoded).is_err());
+        assert!(PixelMask::from_rle(4, 3, &encoded).is_err());
+        assert!(PixelMask::from_rle(4, 4, &[0x80]).is_err());
+    }
+
+    #[test]
+    fn test_embedded_copy_needs_free_prefix() {
+        // The header and a two-byte mask fill the first 27 pixels
+        let late = PixelMask::from_fn(10, 10, |_, y| y >= 5);
+        assert!(late.embedded_copy().is_some());
+
+        let early = PixelMask::from_fn(10, 10, |x, y| (x, y) == (3, 1));
+        assert!(early.embedded_copy().is_none());
+    }
+}
@@ -0,0 +1,413 @@
+//! Packing a directory of files into a directory of cover images
+//!
+//! Each file is embedded into its own cover together with its relative path,
+//! so a directory of stego images can be restored to the original tree.
+
+use crate::decoder::Decoder;
+use crate::encoder::Encoder;
+use crate::error::HideError;
+use crate::header::{get_u16_be, put_u16_be};
+use crate::img::StegoImage;
+use crate::manifest::sha256_hex;
+use crate::utils::safe_filename;
+use crate::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Name of the manifest written to the output directory by [`pack_dir`]
+pub const PACK_MANIFEST_FILENAME: &str = "pack-manifest.json";
+
+/// Marks a payload as a packed file record
+const RECORD_MAGIC: &[u8; 4] = b"HPK1";
+
+/// Image extensions that are considered covers or packed images (lossless only)
+const IMAGE_EXTENSIONS: [&str; 4] = ["png", "bmp", "tif", "tiff"];
+
+/// A file packed into a stego image
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackEntry {
+    /// Path of the file relative to the input directory, using `/` separators
+    pub file: String,
+
+    /// File name of the cover image that was used
+    pub cover: String,
+
+    /// File name of the stego image in the output directory
+    pub stego: String,
+
+    /// Size of the file in bytes
+    pub size: usize,
+
+    /// SHA-256 of the file contents (hex)
+    pub sha256: String,
+}
+
+/// Mapping of packed files to the stego images holding them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PackManifest {
+    /// Version of hide-rs that packed the files
+    pub hide_rs_version: String,
+
+    /// When the files were packed
+    pub created_at: DateTime<Utc>,
+
+    /// One entry per packed file
+    pub entries: Vec<PackEntry>,
+}
+
+impl PackManifest {
+    /// Write the manifest as pretty-printed JSON
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Read a manifest from a file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let data = fs::read(path)?;
+        Ok(serde_json::from_slice(&data)?)
+    }
+}
+
+/// Build the payload embedded for a packed file
+///
+/// # Arguments
+/// * `name` - Relative path of the file
+/// * `data` - The file contents
+///
+/// # Returns
+/// * The magic, the big-endian name length, the name and the contents
+pub fn encode_record(name: &str, data: &[u8]) -> Result<Vec<u8>> {
+    let name_len = u16::try_from(name.len())
+        .map_err(|_| HideError::InvalidParameters(format!("File name too long: {}", name)))?;
+
+    let mut record = Vec::with_capacity(RECORD_MAGIC.len() + 2 + name.len() + data.len());
+    record.extend_from_slice(RECORD_MAGIC);
+    put_u16_be(&mut record, name_len);
+    record.extend_from_slice(name.as_bytes());
+    record.extend_from_slice(data);
+    Ok(record)
+}
+
+/// Split a payload into the file name and contents of a packed file
+///
+/// # Returns
+/// * `None` if the payload is not a packed file record
+pub fn decode_record(payload: &[u8]) -> Option<(String, &[u8])> {
+    let rest = payload.strip_prefix(RECORD_MAGIC)?;
+    let name_len = get_u16_be(rest, 0)?;
+    let (name, data) = rest.get(2..)?.split_at_checked(name_len as usize)?;
+
+    let name = String::from_utf8(name.to_vec()).ok()?;
+    Some((name, data))
+}
+
+/// Assign each payload to its own cover
+///
+/// Payloads are placed largest first, each into the smallest unused cover
+/// that can hold it.
+///
+/// # Arguments
+/// * `payload_sizes` - Size of each payload in bytes
+/// * `capacities` - Capacity of each cover in bytes
+///
+/// # Returns
+/// * The index of the cover assigned to each payload, or the index of the
+///   first payload (largest first) that no remaining cover can hold
+// Every index is drawn from `0..len` of the slice it indexes
+#[allow(clippy::indexing_slicing)]
+pub fn assign_covers(
+    payload_sizes: &[usize],
+    capacities: &[usize],
+) -> std::result::Result<Vec<usize>, usize> {
+    let mut payloads: Vec<usize> = (0..payload_sizes.len()).collect();
+    payloads.sort_by_key(|&i| std::cmp::Reverse(payload_sizes[i]));
+
+    let mut covers: Vec<usize> = (0..capacities.len()).collect();
+    covers.sort_by_key(|&i| capacities[i]);
+
+    let mut used = vec![false; capacities.len()];
+    let mut assignment = vec![0; payload_sizes.len()];
+    for payload in payloads {
+        let cover = covers
+            .iter()
+            .copied()
+            .find(|&c| !used[c] && capacities[c] >= payload_sizes[payload])
+            .ok_or(payload)?;
+        used[cover] = true;
+        assignment[payload] = cover;
+    }
+
+    Ok(assignment)
+}
+
+/// Embed every file of a directory into a directory of cover images
+///
+/// The whole assignment is planned before anything is written, so
+/// insufficient capacity fails without touching the output directory.
+///
+/// # Arguments
+/// * `input_dir` - Directory of files to pack (walked recursively)
+/// * `covers_dir` - Directory of lossless cover images
+/// * `output_dir` - Directory for the stego images and `pack-manifest.json`
+///
+/// # Returns
+/// * The manifest that was written
+// Cover indices come from `assign_covers`, which only returns positions in
+// `capacities` (built from `cover_paths`); payload indices from `files`
+#[allow(clippy::indexing_slicing)]
+pub fn pack_dir(input_dir: &Path, covers_dir: &Path, output_dir: &Path) -> Result<PackManifest> {
+    // Read every input file and build its payload
+    let mut files = Vec::new();
+    collect_files(input_dir, input_dir, &mut files)?;
+    files.sort();
+
+    let mut payloads = Vec::with_capacity(files.len());
+    for (name, path) in &files {
+        let data = fs::read(path)?;
+        payloads.push((encode_record(name, &data)?, data));
+    }
+
+    // Measure the covers without decoding them
+    let cover_paths = image_files(covers_dir)?;
+    let mut capacities = Vec::with_capacity(cover_paths.len());
+    for path in &cover_paths {
+        let (width, height) = image::image_dimensions(path)?;
+        capacities.push(Encoder::max_message_size_for_dimensions(width, height));
+    }
+
+    // Plan the whole pack up front
+    let sizes: Vec<usize> = payloads.iter().map(|(record, _)| record.len()).collect();
+    let assignment = assign_covers(&sizes, &capacities).map_err(|i| {
+        HideError::InvalidParameters(format!(
+            "Insufficient cover capacity: no remaining cover can hold {} ({} bytes)",
+            files[i].0, sizes[i]
+        ))
+    })?;
+
+    let mut stego_names = HashSet::new();
+    for &cover in &assignment {
+        let stego_name = stego_file_name(&cover_paths[cover]);
+        if !stego_names.insert(stego_name.clone()) {
+            return Err(HideError::InvalidParameters(format!(
+                "Two covers would both be written to {}",
+                stego_name
+            )));
+        }
+    }
+
+    // Encode and write every stego image
+    fs::create_dir_all(output_dir)?;
+    let encoder = Encoder::new();
+    let mut entries = Vec::with_capacity(files.len());
+    for (((name, _), (record, data)), &cover) in files.iter().zip(&payloads).zip(&assignment) {
+        let cover_path = &cover_paths[cover];
+        let stego_name = stego_file_name(cover_path);
+
+        let stego_image = encoder.encode(StegoImage::from_file(cover_path)?, record)?;
+        stego_image.save(output_dir.join(&stego_name))?;
+
+        entries.push(PackEntry {
+            file: name.clone(),
+            cover: file_name(cover_path),
+            stego: stego_name,
+            size: data.len(),
+            sha256: sha256_hex(data),
+        });
+    }
+
+    let manifest = PackManifest {
+        hide_rs_version: crate::VERSION.to_string(),
+        created_at: Utc::now(),
+        entries,
+    };
+    manifest.save(output_dir.join(PACK_MANIFEST_FILENAME))?;
+
+    Ok(manifest)
+}
+
+/// Restore packed files from a directory of stego images
+///
+/// Images without a packed file record are skipped. When the directory holds a
+/// `pack-manifest.json`, the restored contents are checked against its hashes.
+///
+/// # Arguments
+/// * `input_dir` - Directory of stego images
+/// * `output_dir` - Directory to restore the files into
+///
+/// # Returns
+/// * The paths of the restored files
+pub fn unpack_dir(input_dir: &Path, output_dir: &Path) -> Result<Vec<PathBuf>> {
+    let manifest_path = input_dir.join(PACK_MANIFEST_FILENAME);
+    let manifest = if manifest_path.exists() {
+        Some(PackManifest::load(&manifest_path)?)
+    } else {
+        None
+    };
+
+    let decoder = Decoder::new();
+    let mut restored = Vec::new();
+    for path in image_files(input_dir)? {
+        let image = StegoImage::from_file(&path)?;
+
+        // Not every image in the directory has to carry a packed file
+        let Ok(payload) = decoder.decode(&image) else {
+            continue;
+        };
+        let Some((name, data)) = decode_record(&payload) else {
+            continue;
+        };
+
+        let relative = safe_relative_path(&name)?;
+
+        // Check the contents against the manifest when there is one
+        if let Some(entry) = manifest
+            .as_ref()
+            .and_then(|m| m.entries.iter().find(|e| e.file == name))
+        {
+            if entry.sha256 != sha256_hex(data) {
+                return Err(HideError::InvalidParameters(format!(
+                    "Hash mismatch for {} in {}",
+                    name,
+                    path.display()
+                )));
+            }
+        }
+
+        let output_path = output_dir.join(relative);
+        if let Some(parent) = output_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&output_path, data)?;
+        restored.push(output_path);
+    }
+
+    Ok(restored)
+}
+
+/// Recursively collect the files below `dir` with paths relative to `base`
+fn collect_files(base: &Path, dir: &Path, files: &mut Vec<(String, PathBuf)>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(base, &path, files)?;
+        } else if path.is_file() {
+            let relative = path.strip_prefix(base).unwrap_or(&path);
+            let name = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            files.push((name, path));
+        }
+    }
+
+    Ok(())
+}
+
+/// List the lossless images directly inside a directory, sorted by path
+fn image_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_image = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()));
+        if path.is_file() && is_image {
+            paths.push(path);
+        }
+    }
+
+    paths.sort();
+    Ok(paths)
+}
+
+/// Get the file name of a path as a string
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Name of the stego image written for a cover (always PNG)
+fn stego_file_name(cover_path: &Path) -> String {
+    let stem = cover_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    format!("{}.png", stem)
+}
+
+/// Reject restored names that would escape the output directory
+///
+/// Each component is passed through [`safe_filename`] so names that can't be
+/// created on every platform, such as `CON`, are replaced.
+fn safe_relative_path(name: &str) -> Result<PathBuf> {
+    let path = PathBuf::from(name);
+    let is_safe = !name.is_empty()
+        && path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+
+    if is_safe {
+        Ok(path
+            .components()
+            .map(|component| {
+                safe_filename(Some(&component.as_os_str().to_string_lossy()), "restored")
+            })
+            .collect())
+    } else {
+        Err(HideError::InvalidParameters(format!(
+            "Refusing to restore unsafe path: {}",
+            name
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_round_trip() {
+        let record = encode_record("docs/a.txt", b"hello").unwrap();
+        let (name, data) = decode_record(&record).unwrap();
+        assert_eq!(name, "docs/a.txt");
+        assert_eq!(data, b"hello");
+
+        // Ordinary messages are not records
+        assert!(decode_record(b"hello").is_none());
+        assert!(decode_record(b"HPK1\x00\x09abc").is_none());
+    }
+
+    #[test]
+    fn test_assign_covers_prefers_smallest_fit() {
+        // Each payload takes the smallest cover that still fits it
+        let assignment = assign_covers(&[10, 50, 20], &[100, 25, 60, 15]).unwrap();
+        assert_eq!(assignment, vec![3, 2, 1]);
+
+        // Three payloads can't share two covers
+        assert_eq!(assign_covers(&[10, 10, 10], &[100, 100]), Err(2));
+
+        // A payload too large for every cover is reported
+        assert_eq!(assign_covers(&[10, 500], &[100, 100]), Err(1));
+    }
+
+    #[test]
+    fn test_safe_relative_path() {
+        assert!(safe_relative_path("docs/a.txt").is_ok());
+        assert!(safe_relative_path("../a.txt").is_err());
+        assert!(safe_relative_path("/etc/passwd").is_err());
+        assert!(safe_relative_path("").is_err());
+
+        // Names that can't be created everywhere are replaced, not refused
+        assert_eq!(
+            safe_relative_path("docs/CON/a\u{0}.txt").unwrap(),
+            PathBuf::from("docs/restored/a.txt")
+        );
+    }
+}
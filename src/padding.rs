@@ -0,0 +1,205 @@
+//! Padding payloads so the header doesn't reveal the message length
+//!
+//! A padded payload is marked with
+//! [`ExtraFlags::PADDED`](crate::header::ExtraFlags::PADDED). After the
+//! recorded cover dimensions comes the message, then random padding, then a
+//! [`PADDING_TRAILER_SIZE`]-byte trailer: the true message length as a
+//! big-endian `u32` and a CRC-32 over the message and that length. The
+//! header's length field counts the padding and trailer, so reading the
+//! header only reveals the padded size.
+
+use crate::error::HideError;
+use crate::header::{get_u32_be, put_u32_be, PADDING_TRAILER_SIZE};
+use crate::rng::{self, SharedRng};
+use crate::utils;
+use crate::Result;
+use serde::{Deserialize, Serialize};
+
+/// How far a payload is padded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PaddingPolicy {
+    /// Pad every payload to this many bytes after the header
+    Exact(usize),
+
+    /// Pad to the next power of two, or to the image's capacity when that is smaller
+    NextPowerOfTwo,
+
+    /// Pad to the image's capacity
+    FillCapacity,
+}
+
+impl PaddingPolicy {
+    /// Work out the padded size of a payload
+    ///
+    /// # Arguments
+    /// * `needed` - Bytes after the header before padding, trailer included
+    /// * `capacity` - Bytes after the header the image holds
+    ///
+    /// # Returns
+    /// * The padded size, [`HideError::MessageTooLarge`] if it doesn't fit
+    ///   the image, or an error if an exact size is smaller than the payload
+    pub fn padded_length(self, needed: usize, capacity: usize) -> Result<usize> {
+        let padded = match self {
+            Self::Exact(bytes) => {
+                if needed > bytes {
+                    return Err(HideError::InvalidParameters(format!(
+                        "A {}-byte payload does not fit the {}-byte padded size",
+                        needed, bytes
+                    )));
+                }
+                bytes
+            }
+            Self::NextPowerOfTwo => needed
+                .checked_next_power_of_two()
+                .unwrap_or(usize::MAX)
+                .min(capacity)
+                .max(needed),
+            Self::FillCapacity => capacity.max(needed),
+        };
+
+        if padded > capacity {
+            return Err(HideError::MessageTooLarge);
+        }
+        Ok(padded)
+    }
+}
+
+/// Pad a payload up to a size and end it with the message's trailer
+///
+/// # Arguments
+/// * `payload` - The payload so far, ending with the message
+/// * `message` - The message, whose length and checksum go in the trailer
+/// * `padded_length` - Size of the payload once padded, trailer included
+/// * `rng` - Source of the padding bytes; the system generator when unset
+pub(crate) fn pad(
+    payload: &mut Vec<u8>,
+    message: &[u8],
+    padded_length: usize,
+    rng: Option<&SharedRng>,
+) -> Result<()> {
+    let length = u32::try_from(message.len())
+        .map_err(|_| HideError::InvalidParameters("Message too large to pad".to_string()))?;
+    let fill = padded_length
+        .checked_sub(payload.len() + PADDING_TRAILER_SIZE)
+        .ok_or_else(|| {
+            HideError::InvalidParameters(format!(
+                "A {}-byte payload does not fit the {}-byte padded size",
+                payload.len() + PADDING_TRAILER_SIZE,
+                padded_length
+            ))
+        })?;
+
+    let start = payload.len();
+    payload.resize(start + fill, 0);
+    if let Some(padding) = payload.get_mut(start..) {
+        rng::fill_bytes(rng, padding);
+    }
+    put_u32_be(payload, length);
+    put_u32_be(payload, checksum(message, length));
+    Ok(())
+}
+
+/// Find the message in a padded payload
+///
+/// # Arguments
+/// * `payload` - The payload after any recorded dimensions, trailer included
+///
+/// # Returns
+/// * The message, or an error if the trailer is missing or doesn't match it
+pub(crate) fn unpad(payload: &[u8]) -> Result<&[u8]> {
+    let corrupt = || {
+        HideError::InvalidParameters(
+            "The padded payload's length trailer is missing or corrupt".to_string(),
+        )
+    };
+
+    let (body, trailer) = payload
+        .split_last_chunk::<PADDING_TRAILER_SIZE>()
+        .ok_or_else(corrupt)?;
+    let length = get_u32_be(trailer, 0).ok_or_else(corrupt)?;
+    let message = body.get(..length as usize).ok_or_else(corrupt)?;
+    if Some(checksum(message, length)) != get_u32_be(trailer, 4) {
+        return Err(corrupt());
+    }
+
+    Ok(message)
+}
+
+/// CRC-32 over a message and its big-endian length
+fn checksum(message: &[u8], length: u32) -> u32 {
+    let mut checked = Vec::with_capacity(message.len() + 4);
+    checked.extend_from_slice(message);
+    put_u32_be(&mut checked, length);
+    utils::crc32(&checked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_padded_length() {
+        assert_eq!(PaddingPolicy::Exact(64).padded_length(20, 100).unwrap(), 64);
+        assert!(PaddingPolicy::Exact(16).padded_length(20, 100).is_err());
+        assert!(matches!(
+            PaddingPolicy::Exact(200).padded_length(20, 100),
+            Err(HideError::MessageTooLarge)
+        ));
+
+        assert_eq!(
+            PaddingPolicy::NextPowerOfTwo
+                .padded_length(20, 100)
+                .unwrap(),
+            32
+        );
+        assert_eq!(
+            PaddingPolicy::NextPowerOfTwo
+                .padded_length(70, 100)
+                .unwrap(),
+            100
+        );
+        assert_eq!(
+            PaddingPolicy::FillCapacity.padded_length(20, 100).unwrap(),
+            100
+        );
+        assert!(matches!(
+            PaddingPolicy::FillCapacity.padded_length(120, 100),
+            Err(HideError::MessageTooLarge)
+        ));
+    }
+
+    #[test]
+    fn test_pad_round_trip() {
+        let mut payload = b"hello".to_vec();
+        pad(&mut payload, b"hello", 32, None).unwrap();
+        assert_eq!(payload.len(), 32);
+        assert_eq!(unpad(&payload).unwrap(), b"hello");
+
+        // No room for padding still leaves room for the trailer
+        let mut payload = b"hello".to_vec();
+        pad(&mut payload, b"hello", 5 + PADDING_TRAILER_SIZE, None).unwrap();
+        assert_eq!(unpad(&payload).unwrap(), b"hello");
+
+        let mut payload = b"hello".to_vec();
+        assert!(pad(&mut payload, b"hello", 8, None).is_err());
+    }
+
+    #[test]
+    fn test_unpad_rejects_corrupt_trailers() {
+        let mut payload = b"hello".to_vec();
+        pad(&mut payload, b"hello", 24, None).unwrap();
+
+        let mut flipped = payload.clone();
+        flipped[0] ^= 1;
+        assert!(unpad(&flipped).is_err());
+
+        // A length past the padding
+        let mut overlong = payload.clone();
+        let length_at = overlong.len() - PADDING_TRAILER_SIZE;
+        overlong[length_at] = 0xff;
+        assert!(unpad(&overlong).is_err());
+
+        assert!(unpad(&payload[..4]).is_err());
+    }
+}
@@ -0,0 +1,339 @@
+//! Choosing encoder options for a message before encoding it
+//!
+//! [`plan`] answers "will this message fit in this cover, with which options,
+//! and how noticeable will it be?" in one call. It works out the capacity of
+//! each candidate configuration with [`capacity_breakdown`], predicts the
+//! pixels an encode would touch, and recommends the least invasive candidate
+//! that meets the [`PlanConstraints`].
+//!
+//! The candidates are the plain payload, the self-checked one, one recording
+//! the cover dimensions, and the stealth and robust [`Preset`]s. This build has
+//! no error correction or encryption, so no candidate uses them.
+
+use crate::encoder::{self, capacity_breakdown, CapacityBreakdown, EncoderConfig};
+use crate::error::HideError;
+use crate::header::HeaderFlags;
+use crate::img::{self, StegoImage};
+use crate::mask::PixelMask;
+use crate::preset::Preset;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// How well a configuration guards against a payload being lost or misread
+///
+/// Levels are ordered; each includes the guarantees of the ones before it.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Robustness {
+    /// No guarantees beyond the header's own checks
+    #[default]
+    None,
+
+    /// Every stego image is decoded again before it is returned
+    Checked,
+
+    /// Also records the cover dimensions, so decodes of resized copies say so
+    Recoverable,
+}
+
+impl Robustness {
+    /// Every level, weakest first
+    pub const ALL: [Robustness; 3] = [Self::None, Self::Checked, Self::Recoverable];
+
+    /// The name used by the CLI and the API
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Checked => "checked",
+            Self::Recoverable => "recoverable",
+        }
+    }
+
+    /// The level a configuration provides
+    fn of(config: &EncoderConfig) -> Self {
+        let records_dimensions = config.record_dimensions || config.padding.is_some();
+        match (config.verify_roundtrip, records_dimensions) {
+            (true, true) => Self::Recoverable,
+            (true, false) => Self::Checked,
+            (false, _) => Self::None,
+        }
+    }
+}
+
+impl fmt::Display for Robustness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Robustness {
+    type Err = HideError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_ascii_lowercase();
+        Self::ALL
+            .into_iter()
+            .find(|level| level.name() == name)
+            .ok_or_else(|| {
+                HideError::InvalidParameters(format!(
+                    "Unknown robustness '{}'; expected one of none, checked, recoverable",
+                    s.trim()
+                ))
+            })
+    }
+}
+
+/// What a recommended configuration must satisfy
+#[derive(Debug, Clone, Default)]
+pub struct PlanConstraints {
+    /// Largest fraction (0.0-1.0) of the cover's pixels the payload may touch
+    pub max_change: Option<f64>,
+
+    /// Least robustness the configuration must provide
+    pub robustness: Robustness,
+
+    /// Pad the payload, so the header doesn't reveal the message length
+    pub hide_length: bool,
+
+    /// Pixels the payload must leave untouched
+    pub pixel_mask: Option<PixelMask>,
+}
+
+/// What an encode with a candidate configuration is predicted to do
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PredictedStats {
+    /// Bytes written after the header: the message plus any dimensions and padding
+    pub payload_bytes: usize,
+
+    /// Pixels that carry header or payload bits
+    pub pixels_used: usize,
+
+    /// Share of the cover's pixels that carry header or payload bits
+    pub changed_fraction: f64,
+
+    /// Rough score between 0 and 1 of how noticeable the payload is: the share
+    /// of pixels used, discounted by how well the cover hides changes
+    pub detectability: f64,
+}
+
+/// A configuration the planner considered
+#[derive(Debug, Clone, Serialize)]
+pub struct PlanOption {
+    /// Name of the candidate, e.g. `plain` or `robust`
+    pub name: String,
+
+    /// The options to encode with
+    pub config: EncoderConfig,
+
+    /// Robustness the options provide
+    pub robustness: Robustness,
+
+    /// Capacity of the cover under the options
+    pub capacity: CapacityBreakdown,
+
+    /// Whether the message fits, within the change budget if there is one
+    pub fits: bool,
+
+    /// Whether the options meet every constraint
+    pub meets_constraints: bool,
+
+    /// Predicted encode statistics, absent when the message doesn't fit
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub predicted: Option<PredictedStats>,
+}
+
+/// The planner's answer for a cover and message size
+#[derive(Debug, Clone, Serialize)]
+pub struct Plan {
+    /// Length of the message planned for, in bytes
+    pub message_bytes: usize,
+
+    /// How well the cover hides changes, between 0 and 1 (see [`img::rank_covers`])
+    pub cover_score: f64,
+
+    /// Name of the least invasive option meeting the constraints, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recommended: Option<String>,
+
+    /// Every option considered, in the order they were considered
+    pub options: Vec<PlanOption>,
+}
+
+impl Plan {
+    /// The recommended option, if any meets the constraints
+    pub fn recommendation(&self) -> Option<&PlanOption> {
+        let name = self.recommended.as_deref()?;
+        self.options.iter().find(|option| option.name == name)
+    }
+}
+
+/// Work out which options a message can be hidden in a cover with
+///
+/// # Arguments
+/// * `image` - The cover image
+/// * `message_len` - Length of the message in bytes
+/// * `constraints` - What the recommended options must satisfy
+///
+/// # Returns
+/// * Every candidate with its capacity and predicted statistics, and the
+///   least invasive one meeting the constraints
+pub fn plan(image: &StegoImage, message_len: usize, constraints: PlanConstraints) -> Plan {
+    let cover_score = img::cover_score(image);
+    let total_pixels = img::pixel_count(image.width(), image.height()).max(1) as f64;
+
+    let options: Vec<PlanOption> = candidates()
+        .into_iter()
+        .map(|(name, config)| {
+            let config = EncoderConfig {
+                max_changed_pixels: constraints.max_change,
+                pixel_mask: constraints.pixel_mask.clone(),
+                ..config
+            };
+            let capacity = capacity_breakdown(image, &config);
+            let payload_bytes = encoder::payload_length(image, &config, message_len).ok();
+            let fits = message_len <= capacity.max_message_bytes && payload_bytes.is_some();
+
+            let predicted = payload_bytes.filter(|_| fits).map(|payload_bytes| {
+                let header_size = encoder::header_bytes(&config, HeaderFlags::empty());
+                let pixels_used = encoder::pixels_with_header(header_size, payload_bytes);
+                let changed_fraction = pixels_used as f64 / total_pixels;
+                PredictedStats {
+                    payload_bytes,
+                    pixels_used,
+                    changed_fraction,
+                    detectability: changed_fraction * (1.0 - cover_score),
+                }
+            });
+
+            let robustness = Robustness::of(&config);
+            let meets_constraints = fits
+                && robustness >= constraints.robustness
+                && (!constraints.hide_length || config.padding.is_some());
+            PlanOption {
+                name: name.to_string(),
+                config,
+                robustness,
+                capacity,
+                fits,
+                meets_constraints,
+                predicted,
+            }
+        })
+        .collect();
+
+    // Fewest touched pixels wins; ties go to the simpler, earlier candidate
+    let recommended = options
+        .iter()
+        .filter(|option| option.meets_constraints)
+        .min_by_key(|option| option.predicted.as_ref().map(|p| p.pixels_used))
+        .map(|option| option.name.clone());
+
+    Plan {
+        message_bytes: message_len,
+        cover_score,
+        recommended,
+        options,
+    }
+}
+
+/// The configurations the planner considers, simplest first
+fn candidates() -> Vec<(&'static str, EncoderConfig)> {
+    vec![
+        ("plain", EncoderConfig::preset(Preset::Fast)),
+        ("checked", EncoderConfig::default().verify_roundtrip(true)),
+        (
+            "recorded",
+            EncoderConfig {
+                record_dimensions: true,
+                ..Default::default()
+            }
+            .verify_roundtrip(true),
+        ),
+        (
+            Preset::Stealth.name(),
+            EncoderConfig::preset(Preset::Stealth),
+        ),
+        (Preset::Robust.name(), EncoderConfig::preset(Preset::Robust)),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::Encoder;
+    use crate::testing::{gradient_image, noise_image};
+
+    #[test]
+    fn test_least_invasive_option_is_recommended() {
+        let cover = noise_image(64, 64, 1);
+
+        let plan = plan(&cover, 100, PlanConstraints::default());
+        assert_eq!(plan.recommended.as_deref(), Some("plain"));
+        assert_eq!(plan.options.len(), 5);
+
+        let checked = plan.options.iter().find(|o| o.name == "checked").unwrap();
+        let recorded = plan.options.iter().find(|o| o.name == "recorded").unwrap();
+        let pixels = |option: &PlanOption| option.predicted.as_ref().unwrap().pixels_used;
+        assert_eq!(pixels(checked), Encoder::pixels_needed(100));
+        assert!(pixels(recorded) > pixels(checked));
+
+        let plan = super::plan(
+            &cover,
+            100,
+            PlanConstraints {
+                robustness: Robustness::Recoverable,
+                ..Default::default()
+            },
+        );
+        assert_eq!(plan.recommended.as_deref(), Some("recorded"));
+
+        let plan = super::plan(
+            &cover,
+            100,
+            PlanConstraints {
+                hide_length: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(plan.recommended.as_deref(), Some("stealth"));
+    }
+
+    #[test]
+    fn test_nothing_is_recommended_when_nothing_fits() {
+        let cover = gradient_image(16, 16);
+        let plan = plan(&cover, 1000, PlanConstraints::default());
+        assert!(plan.recommendation().is_none());
+        assert!(plan
+            .options
+            .iter()
+            .all(|option| !option.fits && option.predicted.is_none()));
+
+        // A change budget can rule out what would otherwise fit
+        let plan = super::plan(
+            &cover,
+            60,
+            PlanConstraints {
+                max_change: Some(0.5),
+                ..Default::default()
+            },
+        );
+        assert!(plan.recommendation().is_none());
+        assert!(plan.options.iter().any(|option| option
+            .capacity
+            .overheads
+            .iter()
+            .any(|overhead| overhead.name == "change_budget" && overhead.bytes > 0)));
+    }
+
+    #[test]
+    fn test_robustness_names_round_trip() {
+        for level in Robustness::ALL {
+            assert_eq!(level.name().parse::<Robustness>().unwrap(), level);
+        }
+        assert!(Robustness::None < Robustness::Checked);
+        assert!("bulletproof".parse::<Robustness>().is_err());
+    }
+}
@@ -0,0 +1,571 @@
+//! Reading the first rows of a PNG without the rest of the file
+//!
+//! A [`RowReader`] is fed the file a piece at a time, as it arrives over the
+//! network, and inflates the image data only until it has the rows holding
+//! the pixels asked for. It implements [`PixelSurface`] over those rows, so
+//! [`Decoder::detect`](crate::decoder::Decoder::detect) can look for a header
+//! in them.
+//!
+//! Only what stego images are saved as is supported: 8-bit grayscale, RGB and
+//! their alpha variants without interlacing. Anything else is reported as an
+//! unsupported PNG feature, so callers can fall back to decoding the whole
+//! file. Chunk CRCs are not checked; the header's own checks catch corrupted
+//! pixels.
+
+use crate::error::HideError;
+use crate::header::get_u32_be;
+use crate::img::PixelSurface;
+use crate::Result;
+use flate2::{Decompress, FlushDecompress, Status};
+use image::error::{DecodingError, ImageFormatHint, UnsupportedError, UnsupportedErrorKind};
+use image::{ImageError, ImageFormat, Rgb};
+
+/// The eight bytes every PNG file starts with
+pub const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// Longest row the reader buffers, in bytes (64 MiB)
+const MAX_ROW_BYTES: u64 = 64 << 20;
+
+/// Bytes of inflated data produced per call to the inflater
+const INFLATE_CHUNK: usize = 16 * 1024;
+
+/// Size of a chunk's length and type fields
+const CHUNK_HEADER_SIZE: usize = 8;
+
+/// Size of a chunk's CRC
+const CHUNK_CRC_SIZE: u32 = 4;
+
+/// Size of the IHDR chunk's data
+const IHDR_SIZE: usize = 13;
+
+/// Type of the header chunk
+const IHDR: [u8; 4] = *b"IHDR";
+
+/// Type of the image data chunks
+const IDAT: [u8; 4] = *b"IDAT";
+
+/// Type of the chunk ending the file
+const IEND: [u8; 4] = *b"IEND";
+
+/// The image properties of a PNG's IHDR chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PngInfo {
+    /// Width in pixels
+    pub width: u32,
+
+    /// Height in pixels
+    pub height: u32,
+
+    /// Bits per sample
+    pub bit_depth: u8,
+
+    /// PNG color type: 0 grayscale, 2 RGB, 3 palette, 4 grayscale with alpha, 6 RGBA
+    pub color_type: u8,
+
+    /// Whether the image data is Adam7 interlaced
+    pub interlaced: bool,
+}
+
+impl PngInfo {
+    /// Samples per pixel of the color types the reader supports
+    fn channels(&self) -> Option<usize> {
+        match self.color_type {
+            0 => Some(1),
+            2 => Some(3),
+            4 => Some(2),
+            6 => Some(4),
+            _ => None,
+        }
+    }
+}
+
+/// Where the reader is in the file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Reading the file signature
+    Signature,
+
+    /// Reading a chunk's length and type
+    ChunkHeader,
+
+    /// Reading a chunk's data
+    Chunk {
+        /// The chunk type
+        kind: [u8; 4],
+        /// Bytes of data left
+        remaining: u32,
+    },
+
+    /// Skipping a chunk's CRC
+    Crc {
+        /// Bytes of the CRC left
+        remaining: u32,
+    },
+
+    /// The IEND chunk was reached
+    End,
+}
+
+/// Incremental reader of the first rows of a PNG
+#[derive(Debug)]
+pub struct RowReader {
+    /// Where the reader is in the file
+    state: State,
+
+    /// Bytes of the signature, chunk header or IHDR read so far
+    pending: Vec<u8>,
+
+    /// The image properties, once the IHDR chunk has been read
+    info: Option<PngInfo>,
+
+    /// Pixels, counted in row order, whose rows the caller needs
+    pixels_wanted: u64,
+
+    /// Rows needed to cover the wanted pixels, once the width is known
+    rows_wanted: u32,
+
+    /// Inflater of the concatenated IDAT data
+    inflater: Decompress,
+
+    /// Inflated bytes not yet split into rows
+    inflated: Vec<u8>,
+
+    /// Unfiltered rows read so far, without their filter bytes
+    rows: Vec<Vec<u8>>,
+}
+
+impl RowReader {
+    /// Start reading a PNG
+    ///
+    /// # Arguments
+    /// * `pixels_wanted` - How many pixels, counted in row order from the
+    ///   top left, the caller needs; rows past them are not decoded
+    pub fn new(pixels_wanted: u64) -> Self {
+        Self {
+            state: State::Signature,
+            pending: Vec::new(),
+            info: None,
+            pixels_wanted,
+            rows_wanted: 0,
+            inflater: Decompress::new(true),
+            inflated: Vec::new(),
+            rows: Vec::new(),
+        }
+    }
+
+    /// The image properties, once enough of the file has been read
+    pub fn info(&self) -> Option<&PngInfo> {
+        self.info.as_ref()
+    }
+
+    /// Number of rows decoded so far
+    pub fn rows_read(&self) -> u32 {
+        self.rows.len() as u32
+    }
+
+    /// Whether every wanted pixel has been decoded
+    pub fn is_complete(&self) -> bool {
+        self.info.is_some() && self.rows_read() >= self.rows_wanted
+    }
+
+    /// Whether the end of the PNG has been reached
+    pub fn is_finished(&self) -> bool {
+        self.state == State::End
+    }
+
+    /// Read the next piece of the file
+    ///
+    /// Bytes past the wanted rows are ignored.
+    ///
+    /// # Arguments
+    /// * `bytes` - The bytes following those already pushed
+    ///
+    /// # Returns
+    /// * An unsupported-feature [`HideError::Image`] for PNGs the reader can't
+    ///   handle, or a decoding error for malformed ones
+    pub fn push(&mut self, mut bytes: &[u8]) -> Result<()> {
+        while !bytes.is_empty() && !self.is_complete() {
+            match self.state {
+                State::Signature => {
+                    if !self.fill(&mut bytes, PNG_SIGNATURE.len()) {
+                        break;
+                    }
+                    if self.pending != PNG_SIGNATURE {
+                        return Err(corrupt("not a PNG file"));
+                    }
+                    self.pending.clear();
+                    self.state = State::ChunkHeader;
+                }
+                State::ChunkHeader => {
+                    if !self.fill(&mut bytes, CHUNK_HEADER_SIZE) {
+                        break;
+                    }
+                    self.start_chunk()?;
+                }
+                State::Chunk {
+                    kind: IHDR,
+                    remaining,
+                } => {
+                    if !self.fill(&mut bytes, IHDR_SIZE) {
+                        break;
+                    }
+                    self.read_ihdr()?;
+                    self.state = State::Crc {
+                        remaining: CHUNK_CRC_SIZE + remaining - IHDR_SIZE as u32,
+                    };
+                }
+                State::Chunk { kind, remaining } => {
+                    let (data, rest) = bytes.split_at(bytes.len().min(remaining as usize));
+                    bytes = rest;
+                    if kind == IDAT {
+                        self.inflate(data)?;
+                    }
+
+                    let remaining = remaining - data.len() as u32;
+                    self.state = if remaining == 0 {
+                        State::Crc {
+                            remaining: CHUNK_CRC_SIZE,
+                        }
+                    } else {
+                        State::Chunk { kind, remaining }
+                    };
+                }
+                State::Crc { remaining } => {
+                    let skipped = bytes.len().min(remaining as usize);
+                    bytes = bytes.get(skipped..).unwrap_or_default();
+
+                    let remaining = remaining - skipped as u32;
+                    self.state = if remaining == 0 {
+                        State::ChunkHeader
+                    } else {
+                        State::Crc { remaining }
+                    };
+                }
+                State::End => break,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Move bytes into `pending` until it holds `len`
+    ///
+    /// # Returns
+    /// * Whether `pending` is full
+    fn fill(&mut self, bytes: &mut &[u8], len: usize) -> bool {
+        let wanted = len.saturating_sub(self.pending.len()).min(bytes.len());
+        let (taken, rest) = bytes.split_at(wanted);
+        self.pending.extend_from_slice(taken);
+        *bytes = rest;
+        self.pending.len() == len
+    }
+
+    /// Start the chunk whose length and type are in `pending`
+    fn start_chunk(&mut self) -> Result<()> {
+        let length = get_u32_be(&self.pending, 0).ok_or_else(|| corrupt("short chunk header"))?;
+        let kind: [u8; 4] = self
+            .pending
+            .get(4..CHUNK_HEADER_SIZE)
+            .and_then(|kind| kind.try_into().ok())
+            .ok_or_else(|| corrupt("short chunk header"))?;
+        self.pending.clear();
+
+        if self.info.is_none() && (kind != IHDR || length < IHDR_SIZE as u32) {
+            return Err(corrupt("the IHDR chunk must come first"));
+        }
+        if kind == IEND {
+            self.state = State::End;
+            return Ok(());
+        }
+
+        self.state = if length == 0 {
+            State::Crc {
+                remaining: CHUNK_CRC_SIZE,
+            }
+        } else {
+            State::Chunk {
+                kind,
+                remaining: length,
+            }
+        };
+        Ok(())
+    }
+
+    /// Parse the IHDR data in `pending` and check the reader supports it
+    fn read_ihdr(&mut self) -> Result<()> {
+        let ihdr = std::mem::take(&mut self.pending);
+        let field = |offset: usize| ihdr.get(offset).copied().unwrap_or_default();
+        let info = PngInfo {
+            width: get_u32_be(&ihdr, 0).unwrap_or_default(),
+            height: get_u32_be(&ihdr, 4).unwrap_or_default(),
+            bit_depth: field(8),
+            color_type: field(9),
+            interlaced: field(12) != 0,
+        };
+
+        if info.width == 0 || info.height == 0 {
+            return Err(corrupt("the image has a zero dimension"));
+        }
+        if field(10) != 0 || field(11) != 0 {
+            return Err(corrupt("unknown compression or filter method"));
+        }
+        if info.interlaced {
+            return Err(unsupported("interlaced images"));
+        }
+        if info.bit_depth != 8 {
+            return Err(unsupported(&format!("bit depth {}", info.bit_depth)));
+        }
+        let channels = info
+            .channels()
+            .ok_or_else(|| unsupported(&format!("color type {}", info.color_type)))?;
+        if info.width as u64 * channels as u64 + 1 > MAX_ROW_BYTES {
+            return Err(unsupported(&format!("rows {} pixels wide", info.width)));
+        }
+
+        let rows = self.pixels_wanted.div_ceil(info.width as u64);
+        self.rows_wanted = rows.min(info.height as u64) as u32;
+        self.info = Some(info);
+        Ok(())
+    }
+
+    /// Inflate a piece of IDAT data and split off the complete rows
+    fn inflate(&mut self, mut data: &[u8]) -> Result<()> {
+        while !data.is_empty() && !self.is_complete() {
+            let total_in = self.inflater.total_in();
+            let total_out = self.inflater.total_out();
+            let start = self.inflated.len();
+            self.inflated.resize(start + INFLATE_CHUNK, 0);
+
+            let output = self.inflated.get_mut(start..).unwrap_or_default();
+            let status = self
+                .inflater
+                .decompress(data, output, FlushDecompress::None)
+                .map_err(|e| corrupt(&e.to_string()))?;
+            let consumed = (self.inflater.total_in() - total_in) as usize;
+            let produced = (self.inflater.total_out() - total_out) as usize;
+            self.inflated.truncate(start + produced);
+            data = data.get(consumed..).unwrap_or_default();
+
+            self.take_rows()?;
+            if status == Status::StreamEnd || (consumed == 0 && produced == 0) {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Unfilter the complete rows at the front of the inflated data
+    fn take_rows(&mut self) -> Result<()> {
+        let Some(info) = self.info else {
+            return Ok(());
+        };
+        let channels = info.channels().unwrap_or(1);
+        let stride = info.width as usize * channels + 1;
+
+        while self.inflated.len() >= stride && !self.is_complete() {
+            let mut row: Vec<u8> = self.inflated.drain(..stride).collect();
+            let filter = row.remove(0);
+            let previous = self.rows.last().map(Vec::as_slice).unwrap_or_default();
+            unfilter(filter, channels, previous, &mut row)?;
+            self.rows.push(row);
+        }
+
+        Ok(())
+    }
+}
+
+impl PixelSurface for RowReader {
+    fn width(&self) -> u32 {
+        self.info.map_or(0, |info| info.width)
+    }
+
+    fn height(&self) -> u32 {
+        self.info.map_or(0, |info| info.height)
+    }
+
+    fn get_pixel_rgb(&self, x: u32, y: u32) -> Result<Rgb<u8>> {
+        let channels = self.info.and_then(|info| info.channels()).unwrap_or(1);
+        let offset = x as usize * channels;
+        let samples = self
+            .rows
+            .get(y as usize)
+            .and_then(|row| row.get(offset..offset + channels))
+            .ok_or_else(|| {
+                HideError::InvalidParameters(format!(
+                    "Pixel ({}, {}) has not been read ({} rows read)",
+                    x,
+                    y,
+                    self.rows.len()
+                ))
+            })?;
+
+        Ok(match *samples {
+            [gray] | [gray, _] => Rgb([gray, gray, gray]),
+            [r, g, b, ..] => Rgb([r, g, b]),
+            [] => Rgb([0, 0, 0]),
+        })
+    }
+
+    fn set_pixel_rgb(&mut self, _x: u32, _y: u32, _pixel: Rgb<u8>) -> Result<()> {
+        Err(HideError::InvalidParameters(
+            "Rows read from a PNG stream are read-only".to_string(),
+        ))
+    }
+}
+
+/// Undo a row's filter in place
+///
+/// # Arguments
+/// * `filter` - The filter type byte the row started with
+/// * `bpp` - Bytes per pixel, the distance to the byte a filter calls "left"
+/// * `previous` - The unfiltered row above, empty for the first row
+/// * `row` - The filtered row, without its filter byte
+fn unfilter(filter: u8, bpp: usize, previous: &[u8], row: &mut [u8]) -> Result<()> {
+    if filter > 4 {
+        return Err(corrupt(&format!("unknown row filter {}", filter)));
+    }
+
+    for i in 0..row.len() {
+        let left = i
+            .checked_sub(bpp)
+            .and_then(|j| row.get(j))
+            .copied()
+            .unwrap_or(0);
+        let up = previous.get(i).copied().unwrap_or(0);
+        let up_left = i
+            .checked_sub(bpp)
+            .and_then(|j| previous.get(j))
+            .copied()
+            .unwrap_or(0);
+
+        let predictor = match filter {
+            1 => left,
+            2 => up,
+            3 => ((left as u16 + up as u16) / 2) as u8,
+            4 => paeth(left, up, up_left),
+            _ => 0,
+        };
+        if let Some(byte) = row.get_mut(i) {
+            *byte = byte.wrapping_add(predictor);
+        }
+    }
+
+    Ok(())
+}
+
+/// The Paeth predictor: whichever neighbor is closest to `left + up - up_left`
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let distance = |value: u8| (estimate - value as i16).abs();
+
+    if distance(left) <= distance(up) && distance(left) <= distance(up_left) {
+        left
+    } else if distance(up) <= distance(up_left) {
+        up
+    } else {
+        up_left
+    }
+}
+
+/// Error for a PNG using a feature the reader doesn't support
+fn unsupported(feature: &str) -> HideError {
+    HideError::Image(ImageError::Unsupported(
+        UnsupportedError::from_format_and_kind(
+            ImageFormatHint::Exact(ImageFormat::Png),
+            UnsupportedErrorKind::GenericFeature(feature.to_string()),
+        ),
+    ))
+}
+
+/// Error for a malformed PNG
+fn corrupt(detail: &str) -> HideError {
+    HideError::Image(ImageError::Decoding(DecodingError::new(
+        ImageFormatHint::Exact(ImageFormat::Png),
+        detail.to_string(),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::img::StegoImage;
+    use crate::testing::{gradient_image, noise_image};
+    use std::io::Cursor;
+
+    fn png_bytes(image: &StegoImage) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        image
+            .inner()
+            .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_rows_match_the_full_decode() {
+        for image in [gradient_image(37, 20), noise_image(37, 20, 3)] {
+            let bytes = png_bytes(&image);
+
+            // Fed a few bytes at a time, as a slow connection would
+            let mut reader = RowReader::new(37 * 5 + 1);
+            for piece in bytes.chunks(7) {
+                reader.push(piece).unwrap();
+            }
+            assert!(reader.is_complete());
+            assert_eq!(reader.rows_read(), 6);
+
+            for y in 0..6 {
+                for x in 0..37 {
+                    assert_eq!(
+                        reader.get_pixel_rgb(x, y).unwrap(),
+                        image.get_pixel_rgb(x, y).unwrap()
+                    );
+                }
+            }
+            assert!(reader.get_pixel_rgb(0, 6).is_err());
+        }
+    }
+
+    #[test]
+    fn test_all_filters_unfilter() {
+        let previous = [10, 20, 30, 40];
+        for filter in 0..=4 {
+            let original = [5u8, 200, 7, 250];
+            let mut filtered = original;
+            // Filter by hand, right to left so "left" is still unfiltered
+            for i in (0..4).rev() {
+                let left = if i >= 1 { original[i - 1] } else { 0 };
+                let up_left = if i >= 1 { previous[i - 1] } else { 0 };
+                let predictor = match filter {
+                    1 => left,
+                    2 => previous[i],
+                    3 => ((left as u16 + previous[i] as u16) / 2) as u8,
+                    4 => paeth(left, previous[i], up_left),
+                    _ => 0,
+                };
+                filtered[i] = original[i].wrapping_sub(predictor);
+            }
+
+            unfilter(filter, 1, &previous, &mut filtered).unwrap();
+            assert_eq!(filtered, original, "filter {}", filter);
+        }
+        assert!(unfilter(5, 1, &previous, &mut [0; 4]).is_err());
+    }
+
+    #[test]
+    fn test_unsupported_pngs_are_reported() {
+        let mut bytes = png_bytes(&gradient_image(8, 8));
+        // The interlace method is the last byte of the IHDR data
+        bytes[PNG_SIGNATURE.len() + CHUNK_HEADER_SIZE + IHDR_SIZE - 1] = 1;
+        let error = RowReader::new(8).push(&bytes).unwrap_err();
+        assert!(
+            matches!(error, HideError::Image(ImageError::Unsupported(_))),
+            "{}",
+            error
+        );
+
+        let error = RowReader::new(8).push(b"GIF89a not a png").unwrap_err();
+        assert!(matches!(error, HideError::Image(ImageError::Decoding(_))));
+    }
+}
@@ -0,0 +1,125 @@
+//! Named sets of encoder options shared by the CLI and the API
+//!
+//! A [`Preset`] stands for a trade-off rather than a single option, so
+//! clients can ask for "the robust one" without tracking which options that
+//! takes in this version. [`EncoderConfig::preset`] builds the options;
+//! options set after it override the preset's.
+
+use crate::encoder::EncoderConfig;
+use crate::error::HideError;
+use crate::padding::PaddingPolicy;
+use crate::schedule::ChannelSchedule;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// A named trade-off between speed, what the image gives away and checking
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Preset {
+    /// The plain version 1 payload with no self-check: the fewest changed
+    /// pixels and the quickest encode
+    Fast,
+
+    /// Pad to the next power of two and rotate channels, so the header only
+    /// reveals a rough size and bits aren't all in the same channel
+    Stealth,
+
+    /// Fill the image's capacity, record the cover dimensions and check every
+    /// output decodes; there is no error correction, so this hardens against
+    /// mistakes and length analysis rather than lossy channels
+    Robust,
+}
+
+impl Preset {
+    /// Every preset
+    pub const ALL: [Preset; 3] = [Self::Fast, Self::Stealth, Self::Robust];
+
+    /// The name used by the CLI and the API
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Fast => "fast",
+            Self::Stealth => "stealth",
+            Self::Robust => "robust",
+        }
+    }
+}
+
+impl fmt::Display for Preset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Preset {
+    type Err = HideError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_ascii_lowercase();
+        Self::ALL
+            .into_iter()
+            .find(|preset| preset.name() == name)
+            .ok_or_else(|| {
+                HideError::InvalidParameters(format!(
+                    "Unknown preset '{}'; expected one of fast, stealth, robust",
+                    s.trim()
+                ))
+            })
+    }
+}
+
+impl EncoderConfig {
+    /// The options a preset stands for
+    ///
+    /// # Arguments
+    /// * `preset` - The trade-off to encode with
+    pub fn preset(preset: Preset) -> Self {
+        match preset {
+            Preset::Fast => Self::default(),
+            Preset::Stealth => Self {
+                channel_schedule: ChannelSchedule::Rotating,
+                ..Default::default()
+            }
+            .pad_to(PaddingPolicy::NextPowerOfTwo)
+            .verify_roundtrip(true),
+            Preset::Robust => Self {
+                record_dimensions: true,
+                ..Default::default()
+            }
+            .pad_to(PaddingPolicy::FillCapacity)
+            .verify_roundtrip(true),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names_round_trip() {
+        for preset in Preset::ALL {
+            assert_eq!(preset.name().parse::<Preset>().unwrap(), preset);
+            assert_eq!(
+                serde_json::to_string(&preset).unwrap(),
+                format!("\"{}\"", preset)
+            );
+        }
+        assert_eq!(" Robust ".parse::<Preset>().unwrap(), Preset::Robust);
+        assert!("hardened".parse::<Preset>().is_err());
+    }
+
+    #[test]
+    fn test_presets_differ() {
+        let fast = EncoderConfig::preset(Preset::Fast);
+        assert!(fast.padding.is_none() && !fast.verify_roundtrip);
+
+        let stealth = EncoderConfig::preset(Preset::Stealth);
+        assert_eq!(stealth.padding, Some(PaddingPolicy::NextPowerOfTwo));
+        assert_eq!(stealth.channel_schedule, ChannelSchedule::Rotating);
+
+        let robust = EncoderConfig::preset(Preset::Robust);
+        assert_eq!(robust.padding, Some(PaddingPolicy::FillCapacity));
+        assert!(robust.record_dimensions && robust.verify_roundtrip);
+    }
+}
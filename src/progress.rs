@@ -0,0 +1,75 @@
+//! Progress reporting for long-running encodes
+
+use crate::cancel::CHECK_INTERVAL;
+use std::fmt;
+use std::sync::Arc;
+
+/// Callback told how many pixels of an embedding are done
+///
+/// Called with `(done, total)` every [`CHECK_INTERVAL`] pixels and once more
+/// when the embedding finishes. Clones share the same callback, so it must be
+/// `Send` and `Sync` like the rest of an encoder's config.
+#[derive(Clone)]
+pub struct ProgressCallback(Arc<dyn Fn(u64, u64) + Send + Sync>);
+
+impl ProgressCallback {
+    /// Wrap a callback
+    pub fn new(callback: impl Fn(u64, u64) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(callback))
+    }
+
+    /// Report progress to the callback
+    pub fn report(&self, done: u64, total: u64) {
+        (self.0)(done, total)
+    }
+}
+
+impl fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressCallback")
+    }
+}
+
+/// Report to an optional callback every [`CHECK_INTERVAL`] pixels
+///
+/// # Arguments
+/// * `progress` - The callback to report to, if any
+/// * `done` - Number of pixels processed so far
+/// * `total` - Number of pixels the embedding processes
+pub(crate) fn report_every(progress: Option<&ProgressCallback>, done: usize, total: usize) {
+    if let Some(progress) = progress {
+        if done.is_multiple_of(CHECK_INTERVAL) || done == total {
+            progress.report(done as u64, total as u64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_reports_on_the_interval_and_at_the_end() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&reports);
+        let progress = ProgressCallback::new(move |done, total| {
+            seen.lock().unwrap().push((done, total));
+        });
+
+        let total = CHECK_INTERVAL * 2 + 5;
+        for done in 1..=total {
+            report_every(Some(&progress), done, total);
+        }
+        report_every(None, total, total);
+
+        assert_eq!(
+            *reports.lock().unwrap(),
+            vec![
+                (CHECK_INTERVAL as u64, total as u64),
+                (CHECK_INTERVAL as u64 * 2, total as u64),
+                (total as u64, total as u64)
+            ]
+        );
+    }
+}
@@ -1,104 +1,213 @@
 //! Utility for raw data extraction from steganographic images without header validation
 
 use crate::decoder::Decoder;
-use crate::img::StegoImage;
+use crate::img::{PixelSurface, StegoImage};
 use crate::Result;
-use bitvec::prelude::*;
+use std::io::Write;
+
+/// Options for raw extraction
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RawExtractOptions {
+    /// Stop after this many bytes; rows past them are never read
+    pub max_bytes: Option<u64>,
+}
 
 /// Extract raw data from a steganographic image without header validation
 pub fn extract_raw_data(stego_image: &StegoImage) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    extract_raw_to_writer(stego_image, &mut bytes, RawExtractOptions::default())?;
+    Ok(bytes)
+}
+
+/// Extract raw data row by row into a writer without header validation
+///
+/// Only one row of bytes is held in memory at a time. A final partial byte is
+/// padded with zero bits, as in [`extract_raw_data`].
+///
+/// # Arguments
+/// * `stego_image` - The image or view to read
+/// * `writer` - Where to write the extracted bytes
+/// * `options` - Extraction options
+///
+/// # Returns
+/// * The number of bytes written
+pub fn extract_raw_to_writer<S: PixelSurface + ?Sized, W: Write>(
+    stego_image: &S,
+    mut writer: W,
+    options: RawExtractOptions,
+) -> Result<u64> {
     let decoder = Decoder::new();
+    let max_bytes = options.max_bytes.unwrap_or(u64::MAX);
 
-    // Extract all bits from the image
-    let mut all_bits = BitVec::<u8, Msb0>::new();
+    // Bits not yet forming a whole byte, kept in the low end of `pending`
+    let mut pending = 0u16;
+    let mut pending_bits = 0;
+    let mut row_bytes = Vec::with_capacity((stego_image.width() as usize * 3).div_ceil(8) + 1);
+    let mut written = 0u64;
 
-    // Process each pixel to extract embedded bits
     for y in 0..stego_image.height() {
+        if written >= max_bytes {
+            break;
+        }
+
         for x in 0..stego_image.width() {
-            // Get the current pixel
             let pixel = stego_image.get_pixel_rgb(x, y)?;
-
-            // Decode the pixel to extract message bits
-            let pixel_bits = decoder.decode_pixel(pixel.0[0], pixel.0[1], pixel.0[2]);
-            all_bits.extend_from_bitslice(&pixel_bits);
+            for bit in decoder.decode_pixel(pixel.0[0], pixel.0[1], pixel.0[2]) {
+                pending = (pending << 1) | bit as u16;
+                pending_bits += 1;
+                if pending_bits == 8 {
+                    row_bytes.push(pending as u8);
+                    pending = 0;
+                    pending_bits = 0;
+                }
+            }
         }
+
+        written += write_capped(&mut writer, &mut row_bytes, max_bytes - written)?;
     }
 
-    // Convert all bits to bytes
-    let bytes = bits_to_bytes(&all_bits);
+    // Pad the trailing bits of the last pixel to a whole byte
+    if pending_bits > 0 && written < max_bytes {
+        row_bytes.push((pending << (8 - pending_bits)) as u8);
+        written += write_capped(&mut writer, &mut row_bytes, max_bytes - written)?;
+    }
 
-    Ok(bytes)
+    writer.flush()?;
+    Ok(written)
 }
 
-/// Convert bits to bytes without any validation
-fn bits_to_bytes(bits: &BitSlice<u8, Msb0>) -> Vec<u8> {
-    let byte_count = (bits.len() + 7) / 8; // Ceiling division
-    let mut bytes = vec![0u8; byte_count];
+/// Write at most `limit` bytes of a buffer and clear it
+fn write_capped<W: Write>(writer: &mut W, bytes: &mut Vec<u8>, limit: u64) -> Result<u64> {
+    bytes.truncate(usize::try_from(limit).unwrap_or(usize::MAX));
+    writer.write_all(bytes)?;
+    let count = bytes.len() as u64;
+    bytes.clear();
+    Ok(count)
+}
 
-    for (i, byte) in bytes.iter_mut().enumerate() {
-        let start_bit = i * 8;
-        let end_bit = std::cmp::min(start_bit + 8, bits.len());
+/// Layout of a data preview
+///
+/// The defaults give the layout [`format_data_preview`] has always produced,
+/// which tools may parse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PreviewOptions {
+    /// Bytes per row of the hex view (at least one is shown)
+    pub bytes_per_hex_row: usize,
 
-        // Fill byte with available bits
-        for j in start_bit..end_bit {
-            if bits[j] {
-                *byte |= 1 << (7 - (j % 8));
-            }
+    /// Bytes per row of the binary view (at least one is shown)
+    pub bytes_per_bin_row: usize,
+
+    /// Whether to read the first bytes as a header before the views
+    pub show_header_analysis: bool,
+
+    /// Whether to end each hex row with its printable ASCII, as `hexdump -C` does
+    pub show_ascii_column: bool,
+}
+
+impl Default for PreviewOptions {
+    fn default() -> Self {
+        Self {
+            bytes_per_hex_row: 16,
+            bytes_per_bin_row: 4,
+            show_header_analysis: true,
+            show_ascii_column: false,
         }
     }
-
-    bytes
 }
 
 /// Format the first N bytes of data in a human-readable way (hex and binary)
 pub fn format_data_preview(data: &[u8], n: usize) -> String {
-    let n = std::cmp::min(n, data.len());
+    format_data_preview_with(data, n, &PreviewOptions::default())
+}
+
+/// Format the first N bytes of data with a custom layout
+///
+/// Only the first `n` bytes are read, whatever the options.
+///
+/// # Arguments
+/// * `data` - The data to preview
+/// * `n` - Number of bytes to show
+/// * `options` - Row widths and optional sections
+///
+/// # Returns
+/// * The preview, without a trailing newline
+pub fn format_data_preview_with(data: &[u8], n: usize, options: &PreviewOptions) -> String {
+    let shown = data.get(..n).unwrap_or(data);
     let mut result = String::new();
 
     result.push_str("Raw data preview:\n");
-
-    // Header format info
-    if n >= 8 {
-        result.push_str("Potential header: \n");
-        result.push_str(&format!("  Format version: {} (expected: 1)\n", data[0]));
-
-        // Extract message length (big endian)
-        let message_length = ((data[1] as u32) << 24)
-            | ((data[2] as u32) << 16)
-            | ((data[3] as u32) << 8)
-            | (data[4] as u32);
-        result.push_str(&format!("  Message length: {} bytes\n", message_length));
-
-        // Reserved bytes
-        result.push_str(&format!(
-            "  Reserved bytes: {:02X} {:02X} {:02X}\n",
-            data[5], data[6], data[7]
-        ));
+    if options.show_header_analysis {
+        push_header_analysis(&mut result, shown);
     }
 
     // Hex view
+    let hex_width = options.bytes_per_hex_row.max(1);
     result.push_str("Hex view:\n");
-    for i in 0..n {
-        if i % 16 == 0 {
-            if i > 0 {
-                result.push('\n');
+    let hex_rows: Vec<String> = shown
+        .chunks(hex_width)
+        .enumerate()
+        .map(|(row, bytes)| {
+            let mut line = format!("{:04X}: ", row * hex_width);
+            for byte in bytes {
+                line.push_str(&format!("{:02X} ", byte));
             }
-            result.push_str(&format!("{:04X}: ", i));
-        }
-        result.push_str(&format!("{:02X} ", data[i]));
-    }
+            if options.show_ascii_column {
+                // Short last rows are padded so the gutters line up
+                line.push_str(&"   ".repeat(hex_width - bytes.len()));
+                line.push('|');
+                line.extend(bytes.iter().map(|&byte| {
+                    if byte.is_ascii_graphic() || byte == b' ' {
+                        byte as char
+                    } else {
+                        '.'
+                    }
+                }));
+                line.push('|');
+            }
+            line
+        })
+        .collect();
+    result.push_str(&hex_rows.join("\n"));
 
     // Binary view
+    let bin_width = options.bytes_per_bin_row.max(1);
     result.push_str("\n\nBinary view:\n");
-    for i in 0..n {
-        if i % 4 == 0 {
-            if i > 0 {
-                result.push('\n');
+    let bin_rows: Vec<String> = shown
+        .chunks(bin_width)
+        .enumerate()
+        .map(|(row, bytes)| {
+            let mut line = format!("{:04X}: ", row * bin_width);
+            for byte in bytes {
+                line.push_str(&format!("{:08b} ", byte));
             }
-            result.push_str(&format!("{:04X}: ", i));
-        }
-        result.push_str(&format!("{:08b} ", data[i]));
-    }
+            line
+        })
+        .collect();
+    result.push_str(&bin_rows.join("\n"));
 
     result
 }
+
+/// Append what the first bytes of a preview would mean as a header
+fn push_header_analysis(result: &mut String, shown: &[u8]) {
+    let Some(header) = shown.first_chunk::<8>() else {
+        result.push_str(&format!(
+            "Potential header: too short ({} of 8 bytes)\n",
+            shown.len()
+        ));
+        return;
+    };
+
+    result.push_str("Potential header: \n");
+    result.push_str(&format!("  Format version: {} (expected: 1)\n", header[0]));
+
+    // Extract message length (big endian)
+    let message_length = u32::from_be_bytes([header[1], header[2], header[3], header[4]]);
+    result.push_str(&format!("  Message length: {} bytes\n", message_length));
+
+    // Reserved bytes
+    result.push_str(&format!(
+        "  Reserved bytes: {:02X} {:02X} {:02X}\n",
+        header[5], header[6], header[7]
+    ));
+}
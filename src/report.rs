@@ -0,0 +1,282 @@
+//! Machine-readable run reports shared by the CLI and the library
+
+use crate::decoder::MessageHeader;
+use crate::error::HideError;
+use crate::img::DiffStats;
+use crate::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// Number of attempts made to acquire the report lock
+const LOCK_RETRIES: u32 = 200;
+
+/// Delay between attempts to acquire the report lock
+const LOCK_RETRY_DELAY: Duration = Duration::from_millis(25);
+
+/// A report file collecting the results of one or more runs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Report {
+    /// Version of hide-rs that last wrote the report
+    pub hide_rs_version: String,
+
+    /// Runs recorded in this report, oldest first
+    pub runs: Vec<ReportRun>,
+}
+
+impl Default for Report {
+    fn default() -> Self {
+        Self {
+            hide_rs_version: crate::VERSION.to_string(),
+            runs: Vec::new(),
+        }
+    }
+}
+
+/// A single command invocation recorded in a report
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReportRun {
+    /// Name of the command that was run
+    pub command: String,
+
+    /// Arguments passed to the command
+    pub arguments: Vec<String>,
+
+    /// When the command started
+    pub started_at: DateTime<Utc>,
+
+    /// When the command finished
+    pub finished_at: DateTime<Utc>,
+
+    /// Version of hide-rs that produced this run
+    pub hide_rs_version: String,
+
+    /// Per-file results
+    pub files: Vec<FileResult>,
+}
+
+impl ReportRun {
+    /// Start recording a run of the given command
+    pub fn new(command: &str, arguments: Vec<String>) -> Self {
+        let now = Utc::now();
+
+        Self {
+            command: command.to_string(),
+            arguments,
+            started_at: now,
+            finished_at: now,
+            hide_rs_version: crate::VERSION.to_string(),
+            files: Vec::new(),
+        }
+    }
+
+    /// Mark the run as finished now
+    pub fn finish(&mut self) {
+        self.finished_at = Utc::now();
+    }
+}
+
+/// The result of processing a single file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileResult {
+    /// Path of the processed file
+    pub path: String,
+
+    /// Whether a hidden payload was detected
+    pub detected: bool,
+
+    /// Header fields, if a valid header was found
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub header: Option<HeaderFields>,
+
+    /// Difference statistics, if the file was compared against another
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff: Option<DiffStats>,
+
+    /// Named analysis scores
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub scores: BTreeMap<String, f64>,
+
+    /// Error message, if processing the file failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl FileResult {
+    /// Create an empty result for a file
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().display().to_string(),
+            detected: false,
+            header: None,
+            diff: None,
+            scores: BTreeMap::new(),
+            error: None,
+        }
+    }
+}
+
+/// Header fields as recorded in a report
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeaderFields {
+    /// Message format version
+    pub format_version: u8,
+
+    /// Declared message length in bytes
+    pub message_length: u32,
+}
+
+impl From<MessageHeader> for HeaderFields {
+    fn from(header: MessageHeader) -> Self {
+        Self {
+            format_version: header.format_version,
+            message_length: header.message_length,
+        }
+    }
+}
+
+/// Exclusive lock on a report file, released when dropped
+struct ReportLock {
+    /// Path of the lock file
+    path: PathBuf,
+}
+
+impl ReportLock {
+    /// Acquire the lock for a report file, waiting for other writers
+    fn acquire(report_path: &Path) -> Result<Self> {
+        let mut lock_name = report_path.as_os_str().to_os_string();
+        lock_name.push(".lock");
+        let path = PathBuf::from(lock_name);
+
+        for _ in 0..LOCK_RETRIES {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(_) => return Ok(Self { path }),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => thread::sleep(LOCK_RETRY_DELAY),
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Err(HideError::InvalidParameters(format!(
+            "Timed out waiting for report lock: {}",
+            path.display()
+        )))
+    }
+}
+
+impl Drop for ReportLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Load a report from a file
+pub fn load_report<P: AsRef<Path>>(path: P) -> Result<Report> {
+    let data = fs::read(path)?;
+    Ok(serde_json::from_slice(&data)?)
+}
+
+/// Append a run to a report file, creating the file if needed
+///
+/// Writers sharing the same report path are serialized with a lock file, and
+/// the report is replaced atomically so readers never see a partial write.
+pub fn append_run<P: AsRef<Path>>(path: P, run: ReportRun) -> Result<()> {
+    let path = path.as_ref();
+    let _lock = ReportLock::acquire(path)?;
+
+    // Start from the existing report if there is one
+    let mut report = match fs::read(path) {
+        Ok(data) if !data.is_empty() => serde_json::from_slice(&data)?,
+        Ok(_) => Report::default(),
+        Err(e) if e.kind() == ErrorKind::NotFound => Report::default(),
+        Err(e) => return Err(e.into()),
+    };
+
+    report.hide_rs_version = crate::VERSION.to_string();
+    report.runs.push(run);
+
+    // Write to a sibling file and rename it over the report
+    let mut tmp_name = path.as_os_str().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_name);
+    fs::write(&tmp_path, serde_json::to_vec_pretty(&report)?)?;
+    fs::rename(&tmp_path, path)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn sample_run(command: &str, file: &str) -> ReportRun {
+        let mut run = ReportRun::new(command, vec!["--image".to_string(), file.to_string()]);
+        let mut result = FileResult::new(file);
+        result.detected = true;
+        result.header = Some(HeaderFields {
+            format_version: 1,
+            message_length: 13,
+        });
+        result.scores.insert("lsb_bias".to_string(), 0.5);
+        run.files.push(result);
+        run.finish();
+        run
+    }
+
+    #[test]
+    fn test_append_creates_and_merges() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("report.json");
+
+        append_run(&path, sample_run("decode", "a.png")).unwrap();
+        append_run(&path, sample_run("decode", "b.png")).unwrap();
+
+        let report = load_report(&path).unwrap();
+        assert_eq!(report.hide_rs_version, crate::VERSION);
+        assert_eq!(report.runs.len(), 2);
+        assert_eq!(report.runs[0].files[0].path, "a.png");
+        assert_eq!(report.runs[1].files[0].path, "b.png");
+        assert_eq!(report.runs[1].files[0].header.unwrap().message_length, 13);
+    }
+
+    #[test]
+    fn test_concurrent_appends_are_serialized() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("report.json");
+
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let path = path.clone();
+                thread::spawn(move || {
+                    append_run(&path, sample_run("decode", &format!("{}.png", i))).unwrap();
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        // Every run must have survived and the lock must be released
+        let report = load_report(&path).unwrap();
+        assert_eq!(report.runs.len(), 8);
+        assert!(!dir.path().join("report.json.lock").exists());
+    }
+
+    #[test]
+    fn test_malformed_report_is_rejected() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("report.json");
+        fs::write(&path, b"not json").unwrap();
+
+        assert!(matches!(
+            append_run(&path, sample_run("decode", "a.png")),
+            Err(HideError::Serialization(_))
+        ));
+    }
+}
@@ -0,0 +1,169 @@
+//! RGBA embedding mode
+//!
+//! With [`EncoderConfig::embed_alpha`] set, the payload is embedded four bits
+//! per pixel through a 4x4 BLTM over the red, green, blue and alpha LSBs.
+//! The header keeps the RGB layout in the first [`HEADER_PIXELS`] pixels, so
+//! any decoder can read it and find the [`HeaderFlags::RGBA`] flag; the
+//! payload starts at the next pixel in row-major order. Payloads encoded
+//! before version 2 headers have a shorter header, and start after its pixels. When
+//! [`HeaderFlags::SKIP_TRANSPARENT`] is set, payload pixels with an alpha of
+//! 0 are skipped and left untouched. At a two-bit embedding depth (see
+//! [`crate::depth`]) each payload pixel carries eight bits, four in each of
+//! its two lowest bit planes.
+//!
+//! [`EncoderConfig::embed_alpha`]: crate::encoder::EncoderConfig::embed_alpha
+//! [`HeaderFlags::RGBA`]: crate::header::HeaderFlags::RGBA
+//! [`HeaderFlags::SKIP_TRANSPARENT`]: crate::header::HeaderFlags::SKIP_TRANSPARENT
+
+use crate::depth::{self, EmbeddingDepth};
+use crate::header::EXTENDED_HEADER_SIZE;
+use crate::img::{self, PixelSurface, StegoImage};
+use crate::Result;
+use image::{GenericImageView, Rgb, Rgba, RgbaImage};
+use std::borrow::Cow;
+
+/// Number of message bits each payload pixel carries (one per RGBA channel)
+pub const BITS_PER_PIXEL_RGBA: u64 = 4;
+
+/// Pixels holding the header of RGBA payloads at three bits per pixel
+///
+/// The RGBA flag always takes a version 2 header.
+pub const HEADER_PIXELS: usize = img::pixels_for_bits(EXTENDED_HEADER_SIZE as u64 * 8) as usize;
+
+/// Calculate the maximum message size an image can hold in RGBA mode
+///
+/// # Arguments
+/// * `image` - The cover image
+/// * `skip_transparent` - Whether pixels with an alpha of 0 are skipped
+///
+/// # Returns
+/// * Maximum message size in bytes (accounting for header)
+pub fn max_message_size(image: &StegoImage, skip_transparent: bool) -> usize {
+    payload_bytes(image, skip_transparent, EmbeddingDepth::One)
+}
+
+/// Bytes an image holds in RGBA mode after the header at a given embedding depth
+pub(crate) fn payload_bytes(
+    image: &StegoImage,
+    skip_transparent: bool,
+    depth: EmbeddingDepth,
+) -> usize {
+    if !skip_transparent {
+        return payload_capacity(image.width(), image.height(), HEADER_PIXELS as u64, depth);
+    }
+
+    let usable = image
+        .inner()
+        .pixels()
+        .skip(HEADER_PIXELS)
+        .filter(|(_, _, pixel)| pixel.0[3] != 0)
+        .count();
+    depth::bytes_for_pixels(usable as u64, bits_per_pixel(depth))
+}
+
+/// Calculate the maximum RGBA message size for an image of the given dimensions
+///
+/// This assumes no pixel is skipped, so it bounds the size for any cover.
+///
+/// # Arguments
+/// * `width` - Image width in pixels
+/// * `height` - Image height in pixels
+///
+/// # Returns
+/// * Maximum message size in bytes (accounting for header), saturating at `usize::MAX`
+pub fn max_message_size_for_dimensions(width: u32, height: u32) -> usize {
+    payload_capacity(width, height, HEADER_PIXELS as u64, EmbeddingDepth::One)
+}
+
+/// Bytes an image holds in RGBA mode at a given embedding depth after the header's pixels
+pub(crate) fn payload_capacity(
+    width: u32,
+    height: u32,
+    header_pixels: u64,
+    depth: EmbeddingDepth,
+) -> usize {
+    depth::bytes_for_pixels(
+        img::pixel_count(width, height).saturating_sub(header_pixels),
+        bits_per_pixel(depth),
+    )
+}
+
+/// Calculate how many pixels a message and its header touch in RGBA mode
+///
+/// Skipped transparent pixels are not counted.
+///
+/// # Arguments
+/// * `message_length` - Length of the message in bytes
+///
+/// # Returns
+/// * Number of pixels that carry header or payload bits
+pub fn pixels_needed(message_length: usize) -> usize {
+    HEADER_PIXELS + message_pixels(message_length, EmbeddingDepth::One)
+}
+
+/// Number of pixels a message takes after the header in RGBA mode at a given embedding depth
+pub(crate) fn message_pixels(message_length: usize, depth: EmbeddingDepth) -> usize {
+    depth::message_pixels(message_length, bits_per_pixel(depth))
+}
+
+/// Message bits each payload pixel carries at a given embedding depth
+pub(crate) fn bits_per_pixel(depth: EmbeddingDepth) -> u64 {
+    BITS_PER_PIXEL_RGBA * u64::from(depth.bits_per_channel())
+}
+
+/// Borrow an image's pixels as RGBA, converting only if it has another layout
+pub(crate) fn rgba_pixels(image: &StegoImage) -> Cow<'_, RgbaImage> {
+    match image.inner().as_rgba8() {
+        Some(pixels) => Cow::Borrowed(pixels),
+        None => Cow::Owned(image.inner().to_rgba8()),
+    }
+}
+
+/// Pixels carrying payload bits after the header's pixels, in embedding order
+pub(crate) fn payload_pixels(
+    pixels: &RgbaImage,
+    header_pixels: u64,
+    skip_transparent: bool,
+) -> impl Iterator<Item = &Rgba<u8>> {
+    pixels
+        .pixels()
+        .skip(header_pixels as usize)
+        .filter(move |pixel| !skip_transparent || pixel.0[3] != 0)
+}
+
+/// Pixels carrying payload bits, in embedding order, for writing
+pub(crate) fn payload_pixels_mut(
+    pixels: &mut RgbaImage,
+    skip_transparent: bool,
+) -> impl Iterator<Item = &mut Rgba<u8>> {
+    pixels
+        .pixels_mut()
+        .skip(HEADER_PIXELS)
+        .filter(move |pixel| !skip_transparent || pixel.0[3] != 0)
+}
+
+/// RGB access to an image that keeps each pixel's alpha
+///
+/// Used to write the header of an RGBA-mode payload, since setting RGB
+/// pixels on the image itself converts it to RGB and drops the alpha.
+pub(crate) struct KeepAlpha<'a>(pub(crate) &'a mut StegoImage);
+
+impl PixelSurface for KeepAlpha<'_> {
+    fn width(&self) -> u32 {
+        self.0.width()
+    }
+
+    fn height(&self) -> u32 {
+        self.0.height()
+    }
+
+    fn get_pixel_rgb(&self, x: u32, y: u32) -> Result<Rgb<u8>> {
+        self.0.get_pixel_rgb(x, y)
+    }
+
+    fn set_pixel_rgb(&mut self, x: u32, y: u32, pixel: Rgb<u8>) -> Result<()> {
+        let [r, g, b] = pixel.0;
+        let alpha = self.0.get_pixel_rgba(x, y)?.0[3];
+        self.0.set_pixel_rgba(x, y, Rgba([r, g, b, alpha]))
+    }
+}
@@ -0,0 +1,213 @@
+//! Injectable randomness and key derivation
+//!
+//! Encoding features that need random bytes draw them from the [`SharedRng`]
+//! in their config, falling back to the operating system's generator when
+//! none is set. Keyed modes derive their keys through a [`SharedKdf`], so
+//! reviewers can substitute approved implementations of both.
+
+use crate::encoder::Secret;
+use crate::Result;
+use rand::RngCore;
+use std::cell::Cell;
+use std::fmt;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// A random number generator that can be injected into an encoder
+pub trait StegoRng: RngCore + Send {}
+
+impl<T: RngCore + Send> StegoRng for T {}
+
+/// Shared handle to an injected generator
+///
+/// Clones draw from the same generator, so a handle can be kept to inspect
+/// or reuse it after handing a clone to a config.
+#[derive(Clone)]
+pub struct SharedRng(Arc<Mutex<Box<dyn StegoRng>>>);
+
+impl SharedRng {
+    /// Wrap a generator
+    pub fn new(rng: impl StegoRng + 'static) -> Self {
+        Self(Arc::new(Mutex::new(Box::new(rng))))
+    }
+
+    /// Fill a buffer with bytes from the generator
+    pub fn fill_bytes(&self, dest: &mut [u8]) {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .fill_bytes(dest);
+    }
+}
+
+impl fmt::Debug for SharedRng {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SharedRng")
+    }
+}
+
+/// A derived key that is never printed
+#[derive(Clone, PartialEq, Eq)]
+pub struct Key([u8; 32]);
+
+impl Key {
+    /// Wrap raw key bytes
+    pub fn new(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// Access the key bytes
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Key([redacted])")
+    }
+}
+
+/// Cost parameters passed to a key derivation function
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    /// Number of iterations (passes for memory-hard functions)
+    pub iterations: u32,
+
+    /// Memory to use in KiB, for memory-hard functions
+    pub memory_kib: u32,
+
+    /// Degree of parallelism, for functions that support it
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            iterations: 3,
+            memory_kib: 64 * 1024,
+            parallelism: 1,
+        }
+    }
+}
+
+/// A key derivation function that turns a passphrase into a key
+pub trait Kdf: Send + Sync {
+    /// Derive a key from a passphrase
+    ///
+    /// # Arguments
+    /// * `passphrase` - The passphrase to derive from
+    /// * `salt` - Random salt stored alongside the payload
+    /// * `params` - Cost parameters
+    ///
+    /// # Returns
+    /// * The derived key
+    fn derive(&self, passphrase: &Secret, salt: &[u8], params: &KdfParams) -> Result<Key>;
+}
+
+/// Shared handle to an injected key derivation function
+#[derive(Clone)]
+pub struct SharedKdf(Arc<dyn Kdf>);
+
+impl SharedKdf {
+    /// Wrap a key derivation function
+    pub fn new(kdf: impl Kdf + 'static) -> Self {
+        Self(Arc::new(kdf))
+    }
+
+    /// Derive a key with the wrapped function
+    pub fn derive(&self, passphrase: &Secret, salt: &[u8], params: &KdfParams) -> Result<Key> {
+        self.0.derive(passphrase, salt, params)
+    }
+}
+
+impl fmt::Debug for SharedKdf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SharedKdf")
+    }
+}
+
+thread_local! {
+    /// Whether falling back to the system generator panics on this thread
+    static DEFAULT_RNG_DENIED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Make falling back to the system generator panic on this thread
+///
+/// Used by [`crate::testing::deny_default_rng`] to prove every code path
+/// honors an injected generator.
+pub(crate) fn set_default_rng_denied(denied: bool) {
+    DEFAULT_RNG_DENIED.with(|flag| flag.set(denied));
+}
+
+/// Fill a buffer from an injected generator or the system generator
+///
+/// # Arguments
+/// * `rng` - The injected generator, if any
+/// * `dest` - The buffer to fill
+pub(crate) fn fill_bytes(rng: Option<&SharedRng>, dest: &mut [u8]) {
+    match rng {
+        Some(rng) => rng.fill_bytes(dest),
+        None => {
+            assert!(
+                !DEFAULT_RNG_DENIED.with(Cell::get),
+                "Fell back to the system RNG while it was denied"
+            );
+            rand::rng().fill_bytes(dest);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    struct XorKdf;
+
+    impl Kdf for XorKdf {
+        fn derive(&self, passphrase: &Secret, salt: &[u8], _params: &KdfParams) -> Result<Key> {
+            let mut key = [0u8; 32];
+            for (i, byte) in passphrase
+                .expose()
+                .bytes()
+                .chain(salt.iter().copied())
+                .enumerate()
+            {
+                key[i % 32] ^= byte;
+            }
+            Ok(Key::new(key))
+        }
+    }
+
+    #[test]
+    fn test_shared_rng_clones_share_state() {
+        let rng = SharedRng::new(StdRng::seed_from_u64(1));
+        let clone = rng.clone();
+
+        let (mut a, mut b) = ([0u8; 8], [0u8; 8]);
+        rng.fill_bytes(&mut a);
+        clone.fill_bytes(&mut b);
+        assert_ne!(a, b);
+
+        let mut expected = [0u8; 16];
+        StdRng::seed_from_u64(1).fill_bytes(&mut expected);
+        assert_eq!([a, b].concat(), expected);
+    }
+
+    #[test]
+    fn test_injected_kdf_and_redacted_key() {
+        let kdf = SharedKdf::new(XorKdf);
+        let key = kdf
+            .derive(&Secret::new("pass"), b"salt", &KdfParams::default())
+            .unwrap();
+        assert_eq!(&key.as_bytes()[..8], b"passsalt");
+        assert_eq!(format!("{:?}", key), "Key([redacted])");
+    }
+
+    #[test]
+    #[should_panic(expected = "system RNG")]
+    fn test_denied_default_panics() {
+        set_default_rng_denied(true);
+        fill_bytes(None, &mut [0u8; 4]);
+    }
+}
@@ -0,0 +1,462 @@
+//! Simulating what a stego image goes through on its way to the recipient
+//!
+//! Each [`Transform`] is applied in memory to a copy of the stego image, which
+//! is then decoded again to see how much of the payload survived.
+
+use crate::capabilities;
+use crate::decoder::Decoder;
+use crate::encoder::EncoderConfig;
+use crate::error::HideError;
+use crate::header::HEADER_SIZE;
+use crate::img::StegoImage;
+use crate::raw_decoder;
+use crate::utils;
+use crate::warning::{warning_codes, Warning};
+use crate::Result;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat};
+use std::fmt;
+use std::io::Cursor;
+use std::str::FromStr;
+
+/// A transformation a channel may apply to an image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transform {
+    /// Save and reload as PNG
+    PngResave,
+
+    /// Re-encode as JPEG at the given quality (1-100)
+    Jpeg {
+        /// JPEG quality
+        quality: u8,
+    },
+
+    /// Scale both dimensions by a factor
+    Scale {
+        /// Scale factor, e.g. 0.5 for half size
+        factor: f64,
+    },
+
+    /// Resize to exact dimensions
+    Resize {
+        /// New width in pixels
+        width: u32,
+        /// New height in pixels
+        height: u32,
+    },
+
+    /// Keep only the top rows of the image
+    ///
+    /// Rows are cut from the bottom, so the start of the payload is kept and
+    /// [`Decoder::decode_lossy`] can still recover a prefix.
+    Crop {
+        /// Share of rows to keep, between 0 and 1
+        keep: f64,
+    },
+
+    /// Reduce every channel to fewer bits
+    BitDepth {
+        /// Bits kept per channel (1-8)
+        bits: u8,
+    },
+}
+
+impl Transform {
+    /// Apply the transformation to a copy of an image
+    ///
+    /// # Arguments
+    /// * `image` - The image to transform
+    ///
+    /// # Returns
+    /// * The transformed image
+    pub fn apply(&self, image: &StegoImage) -> Result<StegoImage> {
+        let rgb = image.inner().to_rgb8();
+        let (width, height) = rgb.dimensions();
+
+        let transformed = match *self {
+            Transform::PngResave => {
+                let mut buffer = Cursor::new(Vec::new());
+                rgb.write_to(&mut buffer, ImageFormat::Png)?;
+                image::load_from_memory_with_format(buffer.get_ref(), ImageFormat::Png)?
+            }
+            Transform::Jpeg { quality } => {
+                let mut buffer = Vec::new();
+                JpegEncoder::new_with_quality(&mut buffer, quality).encode_image(&rgb)?;
+                image::load_from_memory_with_format(&buffer, ImageFormat::Jpeg)?
+            }
+            Transform::Scale { factor } => {
+                let new_width = ((width as f64 * factor).round() as u32).max(1);
+                let new_height = ((height as f64 * factor).round() as u32).max(1);
+                DynamicImage::ImageRgb8(rgb).resize_exact(
+                    new_width,
+                    new_height,
+                    FilterType::Triangle,
+                )
+            }
+            Transform::Resize {
+                width: new_width,
+                height: new_height,
+            } => DynamicImage::ImageRgb8(rgb).resize_exact(
+                new_width,
+                new_height,
+                FilterType::Triangle,
+            ),
+            Transform::Crop { keep } => {
+                let rows = ((height as f64 * keep).round() as u32).max(1);
+                DynamicImage::ImageRgb8(rgb).crop_imm(0, 0, width, rows)
+            }
+            Transform::BitDepth { bits } => {
+                let mask = !(0xFFu8.checked_shr(bits as u32).unwrap_or(0));
+                let mut rgb = rgb;
+                for pixel in rgb.pixels_mut() {
+                    for channel in pixel.0.iter_mut() {
+                        *channel &= mask;
+                    }
+                }
+                DynamicImage::ImageRgb8(rgb)
+            }
+        };
+
+        Ok(StegoImage::from_dynamic_image(transformed))
+    }
+}
+
+impl fmt::Display for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Transform::PngResave => write!(f, "png"),
+            Transform::Jpeg { quality } => write!(f, "jpeg:{}", quality),
+            Transform::Scale { factor } => write!(f, "resize:{}", factor),
+            Transform::Resize { width, height } => write!(f, "resize:{}x{}", width, height),
+            Transform::Crop { keep } => write!(f, "crop:{}", keep),
+            Transform::BitDepth { bits } => write!(f, "bitdepth:{}", bits),
+        }
+    }
+}
+
+impl FromStr for Transform {
+    type Err = HideError;
+
+    /// Parse a transform such as `png`, `jpeg:80`, `resize:0.5`,
+    /// `resize:640x480`, `crop:50%` or `bitdepth:5`
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || HideError::InvalidParameters(format!("Invalid transform: {}", s));
+        let (name, value) = match s.split_once(':') {
+            Some((name, value)) => (name, Some(value)),
+            None => (s, None),
+        };
+
+        match (name, value) {
+            ("png", None) => Ok(Transform::PngResave),
+            ("jpeg", Some(value)) => match value.parse() {
+                Ok(quality @ 1..=100) => Ok(Transform::Jpeg { quality }),
+                _ => Err(invalid()),
+            },
+            ("resize", Some(value)) => match value.split_once('x') {
+                Some((width, height)) => match (width.parse(), height.parse()) {
+                    (Ok(width @ 1..), Ok(height @ 1..)) => Ok(Transform::Resize { width, height }),
+                    _ => Err(invalid()),
+                },
+                None => match value.parse::<f64>() {
+                    Ok(factor) if factor > 0.0 && factor.is_finite() => {
+                        Ok(Transform::Scale { factor })
+                    }
+                    _ => Err(invalid()),
+                },
+            },
+            ("crop", Some(value)) => {
+                let keep = utils::parse_ratio(value)?;
+                if keep == 0.0 {
+                    return Err(invalid());
+                }
+                Ok(Transform::Crop { keep })
+            }
+            ("bitdepth", Some(value)) => match value.parse() {
+                Ok(bits @ 1..=8) => Ok(Transform::BitDepth { bits }),
+                _ => Err(invalid()),
+            },
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// How well a payload survived a transformation
+#[derive(Debug, Clone, PartialEq)]
+pub struct SurvivalResult {
+    /// The transformation applied
+    pub transform: Transform,
+
+    /// Whether [`Decoder::detect`] still finds the payload
+    pub detected: bool,
+
+    /// Whether [`Decoder::decode`] still returns the exact payload
+    pub decoded: bool,
+
+    /// Payload bytes [`Decoder::decode_lossy`] recovers intact, counted from the start
+    pub recovered_bytes: usize,
+
+    /// Share of payload bytes that differ from the original or are missing
+    pub byte_error_rate: f64,
+
+    /// Why the transformation itself failed, if it did
+    pub error: Option<String>,
+}
+
+/// Simulate transformations on a stego image, decoding its payload first
+///
+/// # Arguments
+/// * `stego` - The stego image to test
+/// * `ops` - The transformations to try, each applied on its own
+///
+/// # Returns
+/// * One result per transformation, in order
+pub fn simulate(stego: &StegoImage, ops: &[Transform]) -> Result<Vec<SurvivalResult>> {
+    let payload = Decoder::new().decode(stego)?;
+    Ok(simulate_with_payload(stego, &payload, ops))
+}
+
+/// Simulate transformations on a stego image with a known payload
+///
+/// # Arguments
+/// * `stego` - The stego image to test
+/// * `payload` - The payload originally hidden in the image
+/// * `ops` - The transformations to try, each applied on its own
+///
+/// # Returns
+/// * One result per transformation, in order
+pub fn simulate_with_payload(
+    stego: &StegoImage,
+    payload: &[u8],
+    ops: &[Transform],
+) -> Vec<SurvivalResult> {
+    ops.iter()
+        .map(|&transform| match transform.apply(stego) {
+            Ok(transformed) => measure(transform, &transformed, payload),
+            Err(e) => SurvivalResult {
+                transform,
+                detected: false,
+                decoded: false,
+                recovered_bytes: 0,
+                byte_error_rate: 1.0,
+                error: Some(e.to_string()),
+            },
+        })
+        .collect()
+}
+
+/// Decode a transformed image and compare it with the original payload
+fn measure(transform: Transform, image: &StegoImage, payload: &[u8]) -> SurvivalResult {
+    let decoder = Decoder::new();
+
+    let recovered_bytes = decoder
+        .decode_lossy(image)
+        .map(|partial| {
+            partial
+                .data
+                .iter()
+                .zip(payload)
+                .take_while(|(a, b)| a == b)
+                .count()
+        })
+        .unwrap_or(0);
+
+    // Compare payload positions directly so a damaged header still gives a rate
+    let raw = raw_decoder::extract_raw_data(image).unwrap_or_default();
+    let present = raw.get(HEADER_SIZE..).unwrap_or_default();
+    let matching = payload.iter().zip(present).filter(|(a, b)| a == b).count();
+    let byte_error_rate = if payload.is_empty() {
+        0.0
+    } else {
+        (payload.len() - matching) as f64 / payload.len() as f64
+    };
+
+    SurvivalResult {
+        transform,
+        detected: decoder.detect(image).is_some(),
+        decoded: decoder.decode(image).is_ok_and(|data| data == payload),
+        recovered_bytes,
+        byte_error_rate,
+        error: None,
+    }
+}
+
+/// Where a stego image goes after it is encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntendedChannel {
+    /// Sent as a file, possibly re-encoded into this format on the way
+    Format(ImageFormat),
+
+    /// Printed, then scanned or photographed
+    Print,
+}
+
+impl IntendedChannel {
+    /// Warn if the payload will not survive this channel
+    ///
+    /// # Arguments
+    /// * `config` - The options the image is encoded with
+    ///
+    /// # Returns
+    /// * A [`warning_codes::LOSSY_CHANNEL`] warning, or `None` if the channel keeps the payload
+    pub fn warning(&self, config: &EncoderConfig) -> Option<Warning> {
+        match *self {
+            IntendedChannel::Format(format) => channel_warning(config, format),
+            IntendedChannel::Print => Some(Warning::new(
+                warning_codes::LOSSY_CHANNEL,
+                "Printing does not preserve exact pixel values, so the hidden payload will \
+                 not survive it",
+            )),
+        }
+    }
+}
+
+impl fmt::Display for IntendedChannel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntendedChannel::Format(format) => {
+                write!(
+                    f,
+                    "{}",
+                    format.extensions_str().first().unwrap_or(&"unknown")
+                )
+            }
+            IntendedChannel::Print => write!(f, "print"),
+        }
+    }
+}
+
+impl FromStr for IntendedChannel {
+    type Err = HideError;
+
+    /// Parse `print` or an image format extension such as `png` or `jpeg`
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim().to_lowercase();
+        if s == "print" {
+            return Ok(IntendedChannel::Print);
+        }
+        ImageFormat::from_extension(&s)
+            .map(IntendedChannel::Format)
+            .ok_or_else(|| HideError::InvalidParameters(format!("Unknown channel: {}", s)))
+    }
+}
+
+/// Warn before encoding if an image will be re-encoded into a lossy format
+///
+/// Lossy formats such as JPEG rewrite the low bits the payload lives in, so
+/// an image saved as PNG and then converted on the way loses its message.
+/// No encoder option currently protects against that, but the options are
+/// taken so ones that do can lift the warning.
+///
+/// # Arguments
+/// * `config` - The options the image is encoded with
+/// * `intended_format` - The format the image will end up in
+///
+/// # Returns
+/// * A [`warning_codes::LOSSY_CHANNEL`] warning, or `None` if the format is lossless
+pub fn channel_warning(_config: &EncoderConfig, intended_format: ImageFormat) -> Option<Warning> {
+    if capabilities::is_lossless(intended_format) {
+        return None;
+    }
+
+    Some(Warning::new(
+        warning_codes::LOSSY_CHANNEL,
+        format!(
+            "{:?} is a lossy format; re-encoding the image as {:?} will destroy the hidden \
+             payload. Send it as PNG or another lossless format instead",
+            intended_format, intended_format
+        ),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_transforms() {
+        for (text, transform) in [
+            ("png", Transform::PngResave),
+            ("jpeg:80", Transform::Jpeg { quality: 80 }),
+            ("resize:0.5", Transform::Scale { factor: 0.5 }),
+            (
+                "resize:64x32",
+                Transform::Resize {
+                    width: 64,
+                    height: 32,
+                },
+            ),
+            ("crop:50%", Transform::Crop { keep: 0.5 }),
+            ("bitdepth:5", Transform::BitDepth { bits: 5 }),
+        ] {
+            assert_eq!(text.parse::<Transform>().unwrap(), transform);
+        }
+
+        for text in [
+            "gif",
+            "jpeg",
+            "jpeg:0",
+            "jpeg:101",
+            "resize:0",
+            "resize:0x10",
+            "crop:0",
+            "crop:150%",
+            "bitdepth:9",
+        ] {
+            assert!(text.parse::<Transform>().is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_display_round_trips() {
+        for text in [
+            "png",
+            "jpeg:75",
+            "resize:0.25",
+            "resize:10x20",
+            "bitdepth:4",
+        ] {
+            assert_eq!(text.parse::<Transform>().unwrap().to_string(), text);
+        }
+    }
+
+    #[test]
+    fn test_channel_warnings() {
+        let config = EncoderConfig::default();
+
+        let warning = channel_warning(&config, ImageFormat::Jpeg).unwrap();
+        assert_eq!(warning.code, warning_codes::LOSSY_CHANNEL);
+        assert!(warning.message.contains("Jpeg"));
+        assert!(channel_warning(&config, ImageFormat::WebP).is_some());
+        assert!(channel_warning(&config, ImageFormat::Png).is_none());
+        assert!(channel_warning(&config, ImageFormat::Bmp).is_none());
+
+        for (text, lossy) in [
+            ("jpeg", true),
+            ("jpg", true),
+            ("png", false),
+            ("print", true),
+        ] {
+            let channel: IntendedChannel = text.parse().unwrap();
+            assert_eq!(channel.warning(&config).is_some(), lossy, "{}", text);
+        }
+        assert_eq!("JPG".parse::<IntendedChannel>().unwrap().to_string(), "jpg");
+        assert!("fax".parse::<IntendedChannel>().is_err());
+    }
+
+    #[test]
+    fn test_bit_depth_masks_low_bits() {
+        let image = crate::testing::gradient_image(16, 16);
+        let reduced = Transform::BitDepth { bits: 4 }.apply(&image).unwrap();
+        for y in 0..16 {
+            for x in 0..16 {
+                let pixel = reduced.get_pixel_rgb(x, y).unwrap();
+                assert!(pixel.0.iter().all(|&c| c & 0x0F == 0));
+            }
+        }
+
+        // Keeping all 8 bits leaves the image untouched
+        let same = Transform::BitDepth { bits: 8 }.apply(&image).unwrap();
+        assert_eq!(same.inner().as_bytes(), image.inner().as_bytes());
+    }
+}
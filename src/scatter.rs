@@ -0,0 +1,122 @@
+//! Key-seeded pixel orders
+//!
+//! Payloads are normally embedded in row-major order from the top-left
+//! pixel, which makes the header trivial to locate. With a pixel key the
+//! encoder and decoder visit the pixels in an order shuffled by a generator
+//! seeded from the SHA-256 of the key, header included, so without the key
+//! there is nothing to find at the top-left. Images encoded without a key
+//! are unaffected.
+//!
+//! The shuffle draws from SHA-256 in counter mode rather than a `rand`
+//! generator, so the order stays the same across platforms and dependency
+//! versions.
+
+use crate::rng::Key;
+use sha2::{Digest, Sha256};
+
+/// Derive the seed of a pixel order from a key
+///
+/// # Arguments
+/// * `key` - The key shared by the encoder and decoder
+///
+/// # Returns
+/// * The SHA-256 of the key
+pub fn derive_key(key: &[u8]) -> Key {
+    Key::new(Sha256::digest(key).into())
+}
+
+/// The order a key visits the pixels of an image in
+///
+/// # Arguments
+/// * `key` - Seed derived with [`derive_key`]
+/// * `pixel_count` - Number of pixels in the image
+///
+/// # Returns
+/// * The row-major index of the pixel visited at each step
+pub(crate) fn pixel_order(key: &Key, pixel_count: usize) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..pixel_count).collect();
+    let mut stream = KeyStream::new(key);
+
+    // Fisher-Yates, drawing each index by multiplying down a 64-bit sample
+    for i in (1..pixel_count).rev() {
+        let j = ((u128::from(stream.next_u64()) * (i as u128 + 1)) >> 64) as usize;
+        order.swap(i, j);
+    }
+
+    order
+}
+
+/// Pseudorandom bytes from SHA-256 of a key and a block counter
+struct KeyStream<'a> {
+    /// The key seeding the stream
+    key: &'a Key,
+
+    /// Index of the next block to hash
+    counter: u64,
+
+    /// The current block and how much of it is used
+    block: [u8; 32],
+    used: usize,
+}
+
+impl<'a> KeyStream<'a> {
+    fn new(key: &'a Key) -> Self {
+        Self {
+            key,
+            counter: 0,
+            block: [0; 32],
+            used: 32,
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        if self.used + 8 > self.block.len() {
+            let mut hasher = Sha256::new();
+            hasher.update(self.key.as_bytes());
+            hasher.update(self.counter.to_be_bytes());
+            self.block = hasher.finalize().into();
+            self.counter += 1;
+            self.used = 0;
+        }
+
+        let mut bytes = [0; 8];
+        if let Some(sample) = self.block.get(self.used..self.used + 8) {
+            bytes.copy_from_slice(sample);
+        }
+        self.used += 8;
+        u64::from_be_bytes(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_order_is_a_permutation() {
+        let order = pixel_order(&derive_key(b"key"), 1000);
+        let mut sorted = order.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..1000).collect::<Vec<_>>());
+
+        // Shuffled, not merely rotated
+        assert!(order.iter().enumerate().filter(|(i, &p)| *i == p).count() < 20);
+        assert!(pixel_order(&derive_key(b"key"), 0).is_empty());
+    }
+
+    #[test]
+    fn test_order_depends_only_on_the_key() {
+        let key = derive_key(b"correct horse");
+        assert_eq!(pixel_order(&key, 500), pixel_order(&key, 500));
+        assert_ne!(
+            pixel_order(&key, 500),
+            pixel_order(&derive_key(b"wrong horse"), 500)
+        );
+
+        // Pinned, since the order is part of the format
+        assert_eq!(
+            pixel_order(&derive_key(b"hide-rs"), 8),
+            [6, 0, 1, 4, 2, 5, 7, 3]
+        );
+    }
+}
@@ -0,0 +1,246 @@
+//! Assignment of payload bits to color channels
+//!
+//! By default the first bit of every 3-bit chunk is embedded through the red
+//! channel, the second through green and the third through blue, so
+//! structured messages leave channel-specific traces in the LSBs. Other
+//! [`ChannelSchedule`]s permute each pixel's channels before embedding,
+//! either rotating them pixel by pixel or in an order derived from the
+//! passphrase. The schedule is recorded in the header flags so decoders
+//! follow it.
+//!
+//! The pixels holding the header always use the fixed order, since decoders
+//! need the header to learn the schedule.
+
+use crate::encoder::Secret;
+use crate::error::HideError;
+use crate::header::HeaderFlags;
+use crate::rng::{KdfParams, Key, SharedKdf};
+use crate::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::cell::Cell;
+
+/// Salt for deriving schedule keys; decoders only have the passphrase to go on
+const SCHEDULE_SALT: &[u8] = b"hide-rs channel schedule";
+
+/// Channel orders a pixel can be embedded in, as indices into R, G, B
+const PERMUTATIONS: [[usize; 3]; 6] = [
+    [0, 1, 2],
+    [1, 2, 0],
+    [2, 0, 1],
+    [0, 2, 1],
+    [1, 0, 2],
+    [2, 1, 0],
+];
+
+/// Pixels whose orders are drawn from one keyed hash
+const KEYED_BLOCK_PIXELS: u64 = 32;
+
+/// How payload bits are assigned to the channels of each pixel
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelSchedule {
+    /// Red, green, blue for every pixel; readable by every decoder
+    #[default]
+    Fixed,
+
+    /// R,G,B then G,B,R then B,R,G, repeating pixel by pixel
+    Rotating,
+
+    /// An order per pixel derived from the passphrase
+    Keyed,
+}
+
+impl ChannelSchedule {
+    /// Header flags recording the schedule
+    pub fn flags(self) -> HeaderFlags {
+        match self {
+            Self::Fixed => HeaderFlags::empty(),
+            Self::Rotating => HeaderFlags::ROTATED_CHANNELS,
+            Self::Keyed => HeaderFlags::KEYED_CHANNELS,
+        }
+    }
+
+    /// Read the schedule recorded in header flags
+    ///
+    /// # Returns
+    /// * The schedule, or `InvalidParameters` if more than one is flagged
+    pub fn from_flags(flags: HeaderFlags) -> Result<Self> {
+        match (
+            flags.contains(HeaderFlags::ROTATED_CHANNELS),
+            flags.contains(HeaderFlags::KEYED_CHANNELS),
+        ) {
+            (false, false) => Ok(Self::Fixed),
+            (true, false) => Ok(Self::Rotating),
+            (false, true) => Ok(Self::Keyed),
+            (true, true) => Err(HideError::InvalidParameters(
+                "Header flags both a rotating and a keyed channel schedule".to_string(),
+            )),
+        }
+    }
+}
+
+/// Channel order of each pixel under a schedule
+pub(crate) struct ChannelOrder {
+    /// The schedule being followed
+    schedule: ChannelSchedule,
+
+    /// Key for keyed schedules
+    key: Option<Key>,
+
+    /// Number of leading pixels holding the header, which keep the fixed order
+    header_pixels: u64,
+
+    /// Most recently hashed block of keyed orders
+    block: Cell<Option<(u64, [u8; 32])>>,
+}
+
+impl ChannelOrder {
+    /// The fixed red, green, blue order
+    pub(crate) fn fixed() -> Self {
+        Self {
+            schedule: ChannelSchedule::Fixed,
+            key: None,
+            header_pixels: 0,
+            block: Cell::new(None),
+        }
+    }
+
+    /// Prepare to follow a schedule
+    ///
+    /// # Arguments
+    /// * `schedule` - The schedule to follow
+    /// * `header_pixels` - Number of leading pixels holding the header
+    /// * `passphrase` - Passphrase keyed schedules derive their orders from
+    /// * `kdf` - Key derivation for keyed schedules
+    ///
+    /// # Returns
+    /// * The orders, or `InvalidParameters` if a keyed schedule lacks its passphrase or KDF
+    pub(crate) fn new(
+        schedule: ChannelSchedule,
+        header_pixels: u64,
+        passphrase: Option<&Secret>,
+        kdf: Option<&SharedKdf>,
+    ) -> Result<Self> {
+        let key = match schedule {
+            ChannelSchedule::Keyed => {
+                let (Some(passphrase), Some(kdf)) = (passphrase, kdf) else {
+                    return Err(HideError::InvalidParameters(
+                        "A keyed channel schedule needs a passphrase and a key derivation function"
+                            .to_string(),
+                    ));
+                };
+                Some(kdf.derive(passphrase, SCHEDULE_SALT, &KdfParams::default())?)
+            }
+            _ => None,
+        };
+
+        Ok(Self {
+            schedule,
+            key,
+            header_pixels,
+            block: Cell::new(None),
+        })
+    }
+
+    /// Channel order of a pixel, by its index in row-major order
+    ///
+    /// # Returns
+    /// * Indices into R, G, B of the channels carrying the chunk's first, second and third bits
+    // Indices are reduced modulo the length of what they index
+    #[allow(clippy::indexing_slicing)]
+    pub(crate) fn for_pixel(&self, index: u64) -> [usize; 3] {
+        if index < self.header_pixels {
+            return PERMUTATIONS[0];
+        }
+
+        let permutation = match (self.schedule, &self.key) {
+            (ChannelSchedule::Rotating, _) => index % 3,
+            (ChannelSchedule::Keyed, Some(key)) => {
+                let block = index / KEYED_BLOCK_PIXELS;
+                let hash = match self.block.get() {
+                    Some((cached, hash)) if cached == block => hash,
+                    _ => {
+                        let mut hasher = Sha256::new();
+                        hasher.update(key.as_bytes());
+                        hasher.update(block.to_be_bytes());
+                        let hash: [u8; 32] = hasher.finalize().into();
+                        self.block.set(Some((block, hash)));
+                        hash
+                    }
+                };
+                // The slight modulo bias towards the first orders is harmless here
+                hash[(index % KEYED_BLOCK_PIXELS) as usize] as u64 % 6
+            }
+            _ => 0,
+        };
+        PERMUTATIONS[permutation as usize]
+    }
+}
+
+/// Rearrange a pixel's channels into embedding order
+// Orders only hold channel indices below 3
+#[allow(clippy::indexing_slicing)]
+pub(crate) fn permute(channels: [u8; 3], order: [usize; 3]) -> [u8; 3] {
+    order.map(|channel| channels[channel])
+}
+
+/// Put channels in embedding order back in R, G, B order
+// Orders only hold channel indices below 3
+#[allow(clippy::indexing_slicing)]
+pub(crate) fn unpermute(permuted: [u8; 3], order: [usize; 3]) -> [u8; 3] {
+    let mut channels = [0; 3];
+    for (value, channel) in permuted.into_iter().zip(order) {
+        channels[channel] = value;
+    }
+    channels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotation_skips_header_pixels() {
+        let start = 32;
+        let order = ChannelOrder::new(ChannelSchedule::Rotating, start, None, None).unwrap();
+
+        assert_eq!(order.for_pixel(start - 1), [0, 1, 2]);
+        let rotations: Vec<_> = (start..start + 3).map(|i| order.for_pixel(i)).collect();
+        assert!(rotations.contains(&[0, 1, 2]));
+        assert!(rotations.contains(&[1, 2, 0]));
+        assert!(rotations.contains(&[2, 0, 1]));
+    }
+
+    #[test]
+    fn test_permute_round_trip() {
+        for order in PERMUTATIONS {
+            let permuted = permute([10, 20, 30], order);
+            assert_eq!(permuted[0], [10, 20, 30][order[0]]);
+            assert_eq!(unpermute(permuted, order), [10, 20, 30]);
+        }
+    }
+
+    #[test]
+    fn test_flags_round_trip() {
+        for schedule in [
+            ChannelSchedule::Fixed,
+            ChannelSchedule::Rotating,
+            ChannelSchedule::Keyed,
+        ] {
+            assert_eq!(
+                ChannelSchedule::from_flags(schedule.flags()).unwrap(),
+                schedule
+            );
+        }
+        assert!(ChannelSchedule::from_flags(
+            HeaderFlags::ROTATED_CHANNELS | HeaderFlags::KEYED_CHANNELS
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_keyed_schedule_needs_a_key() {
+        assert!(ChannelOrder::new(ChannelSchedule::Keyed, 32, None, None).is_err());
+    }
+}
@@ -0,0 +1,292 @@
+//! Checking that this build encodes and decodes correctly where it runs
+//!
+//! [`run`] round-trips messages through generated covers with a range of
+//! encoder options, checks the conformance vectors, times encoding and
+//! decoding, and saves and reloads stego images through the system temp
+//! directory. Each check is recorded in a [`ReportRun`] as a [`FileResult`]
+//! named after it; a check failed when its `error` is set.
+
+use crate::conformance;
+use crate::decoder::Decoder;
+use crate::encoder::{Encoder, EncoderConfig};
+use crate::error::HideError;
+use crate::img::StegoImage;
+use crate::padding::PaddingPolicy;
+use crate::report::{FileResult, ReportRun};
+use crate::schedule::ChannelSchedule;
+use crate::testing::{checkerboard, gradient_image, noise_image};
+use crate::Result;
+use std::time::Instant;
+
+/// Name the self-test is recorded under in reports
+pub const COMMAND: &str = "self-test";
+
+/// Size of the covers every level round-trips through
+const SMALL_COVER: (u32, u32) = (64, 48);
+
+/// Size of the benchmark covers only the full self-test uses
+const LARGE_COVER: (u32, u32) = (1920, 1080);
+
+/// How much of the self-test to run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SelfTestLevel {
+    /// Small covers only; takes well under a second in release builds
+    #[default]
+    Quick,
+
+    /// Also the benchmark-sized covers
+    Full,
+}
+
+impl SelfTestLevel {
+    /// The CLI flag selecting this level
+    pub fn flag(self) -> &'static str {
+        match self {
+            Self::Quick => "--quick",
+            Self::Full => "--full",
+        }
+    }
+}
+
+/// Run the self-test
+///
+/// # Arguments
+/// * `level` - How much of the self-test to run
+///
+/// # Returns
+/// * One result per check, in the order they ran
+pub fn run(level: SelfTestLevel) -> ReportRun {
+    let mut report = ReportRun::new(COMMAND, vec![level.flag().to_string()]);
+
+    let mut covers = vec![
+        ("gradient", gradient_image(SMALL_COVER.0, SMALL_COVER.1)),
+        ("noise", noise_image(SMALL_COVER.0, SMALL_COVER.1, 7)),
+        ("checkerboard", checkerboard(SMALL_COVER.0, SMALL_COVER.1)),
+    ];
+    let configs = vec![
+        ("default", EncoderConfig::default()),
+        (
+            "rotating",
+            EncoderConfig {
+                channel_schedule: ChannelSchedule::Rotating,
+                ..Default::default()
+            },
+        ),
+        (
+            "alpha",
+            EncoderConfig {
+                embed_alpha: true,
+                ..Default::default()
+            },
+        ),
+        (
+            "dimensions",
+            EncoderConfig {
+                record_dimensions: true,
+                ..Default::default()
+            },
+        ),
+        (
+            "padded",
+            EncoderConfig::default().pad_to(PaddingPolicy::NextPowerOfTwo),
+        ),
+        ("verified", EncoderConfig::default().verify_roundtrip(true)),
+    ];
+    let mut throughput_sizes = vec![(256, 256)];
+    if level == SelfTestLevel::Full {
+        covers.push((
+            "large-gradient",
+            gradient_image(LARGE_COVER.0, LARGE_COVER.1),
+        ));
+        covers.push(("large-noise", noise_image(LARGE_COVER.0, LARGE_COVER.1, 11)));
+        throughput_sizes.push(LARGE_COVER);
+    }
+
+    for (cover_name, cover) in &covers {
+        for (config_name, config) in &configs {
+            let name = format!("roundtrip/{}/{}", cover_name, config_name);
+            report.files.push(check(&name, || roundtrip(cover, config)));
+        }
+        let name = format!("roundtrip/{}/container", cover_name);
+        report.files.push(check(&name, || container(cover)));
+    }
+
+    report.files.push(check("conformance", || {
+        let failures: Vec<String> = conformance::verify_all()
+            .into_iter()
+            .filter(|result| !result.passed())
+            .map(|result| format!("{}: {}", result.name, result.failures.join("; ")))
+            .collect();
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(mismatch(&failures.join(", ")))
+        }
+    }));
+
+    for (width, height) in throughput_sizes {
+        let name = format!("throughput/{}x{}", width, height);
+        let mut result = FileResult::new(&name);
+        match throughput(&gradient_image(width, height)) {
+            Ok(scores) => {
+                result.detected = true;
+                result.scores.extend(scores);
+            }
+            Err(e) => result.error = Some(e.to_string()),
+        }
+        report.files.push(result);
+    }
+
+    for extension in ["png", "bmp", "tiff"] {
+        let name = format!("save-load/{}", extension);
+        report.files.push(check(&name, || save_and_load(extension)));
+    }
+
+    report.finish();
+    report
+}
+
+/// The checks of a self-test run that failed
+pub fn failures(report: &ReportRun) -> impl Iterator<Item = &FileResult> {
+    report.files.iter().filter(|result| result.error.is_some())
+}
+
+/// Record the outcome of a check that either passes or fails
+fn check(name: &str, body: impl FnOnce() -> Result<()>) -> FileResult {
+    let mut result = FileResult::new(name);
+    match body() {
+        Ok(()) => result.detected = true,
+        Err(e) => result.error = Some(e.to_string()),
+    }
+    result
+}
+
+/// The error of a check whose decode didn't give back what was encoded
+fn mismatch(detail: &str) -> HideError {
+    HideError::SelfCheckFailed {
+        detail: detail.to_string(),
+    }
+}
+
+/// A message of the given length that exercises every byte value
+fn message(length: usize) -> Vec<u8> {
+    (0..length).map(|i| (i * 31 % 256) as u8).collect()
+}
+
+/// Hide a message in a cover and check it decodes back unchanged
+fn roundtrip(cover: &StegoImage, config: &EncoderConfig) -> Result<()> {
+    let encoder = Encoder::with_config(config.clone());
+    let message = message(encoder.max_message_size(cover).min(100));
+    let stego = encoder.encode(cover.clone(), &message)?;
+
+    if Decoder::new().decode(&stego)? != message {
+        return Err(mismatch("the decoded message differs from the one encoded"));
+    }
+    Ok(())
+}
+
+/// Hide named entries in a cover and check each decodes back unchanged
+fn container(cover: &StegoImage) -> Result<()> {
+    let entries = vec![
+        ("first".to_string(), message(20)),
+        ("second".to_string(), b"second entry".to_vec()),
+    ];
+    let stego = Encoder::new().encode_container(cover.clone(), &entries)?;
+
+    let decoded = Decoder::new().decode_container(&stego)?;
+    let matches = decoded.len() == entries.len()
+        && decoded
+            .iter()
+            .zip(&entries)
+            .all(|(entry, (name, data))| entry.name == *name && entry.data == *data);
+    if !matches {
+        return Err(mismatch("the decoded entries differ from the ones encoded"));
+    }
+    Ok(())
+}
+
+/// Time a round trip that fills most of a cover's capacity
+///
+/// # Returns
+/// * Milliseconds taken and MiB of message per second, for encoding and decoding
+fn throughput(cover: &StegoImage) -> Result<Vec<(String, f64)>> {
+    let length = Encoder::new().max_message_size(cover) * 9 / 10;
+    let message = message(length);
+    let mebibytes = length as f64 / (1024.0 * 1024.0);
+
+    let started = Instant::now();
+    let stego = Encoder::new().encode(cover.clone(), &message)?;
+    let encode_secs = started.elapsed().as_secs_f64();
+
+    let started = Instant::now();
+    let decoded = Decoder::new().decode(&stego)?;
+    let decode_secs = started.elapsed().as_secs_f64();
+    if decoded != message {
+        return Err(mismatch("the decoded message differs from the one encoded"));
+    }
+
+    Ok(vec![
+        ("encode_ms".to_string(), encode_secs * 1000.0),
+        ("decode_ms".to_string(), decode_secs * 1000.0),
+        (
+            "encode_mib_per_s".to_string(),
+            mebibytes / encode_secs.max(f64::EPSILON),
+        ),
+        (
+            "decode_mib_per_s".to_string(),
+            mebibytes / decode_secs.max(f64::EPSILON),
+        ),
+    ])
+}
+
+/// Save a stego image to a temp file in a lossless format, reload it and decode it
+fn save_and_load(extension: &str) -> Result<()> {
+    let message = message(64);
+    let stego = Encoder::new().encode(gradient_image(SMALL_COVER.0, SMALL_COVER.1), &message)?;
+
+    let dir = tempfile::Builder::new()
+        .prefix("hide-self-test")
+        .tempdir()?;
+    let path = dir.path().join(format!("stego.{}", extension));
+    stego.save(&path)?;
+    let reloaded = StegoImage::from_file(&path)?;
+
+    if Decoder::new().decode(&reloaded)? != message {
+        return Err(mismatch(&format!(
+            "the message did not survive saving to {}",
+            path.display()
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quick_self_test_passes() {
+        let report = run(SelfTestLevel::Quick);
+        assert_eq!(report.command, COMMAND);
+        assert_eq!(report.arguments, ["--quick"]);
+        assert_eq!(failures(&report).count(), 0, "{:?}", report.files);
+        assert!(report.files.iter().all(|result| result.detected));
+
+        let throughput = report
+            .files
+            .iter()
+            .find(|result| result.path == "throughput/256x256")
+            .unwrap();
+        assert!(throughput.scores["encode_mib_per_s"] > 0.0);
+    }
+
+    #[test]
+    fn test_failed_checks_carry_their_error() {
+        let result = check("broken", || Err(HideError::MessageTooLarge));
+        assert!(!result.detected);
+        assert_eq!(
+            result.error.as_deref(),
+            Some(HideError::MessageTooLarge.to_string().as_str())
+        );
+    }
+}
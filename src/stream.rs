@@ -0,0 +1,375 @@
+//! Streaming a continuous byte source across a sequence of cover images
+//!
+//! Each image holds one frame: an ordinary payload whose header has
+//! [`HeaderFlags::STREAM`] set. The payload starts with a frame header (the 16-byte
+//! stream ID, a big-endian `u32` sequence number and a flags byte marking the
+//! last frame) followed by the next chunk of the stream.
+
+use crate::decoder::Decoder;
+use crate::encoder::{Encoder, EncoderConfig};
+use crate::error::HideError;
+use crate::header::HeaderFlags;
+use crate::img::StegoImage;
+use crate::rng;
+use crate::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read};
+use std::ops::RangeInclusive;
+use std::path::Path;
+use uuid::Uuid;
+
+/// Size of the frame header at the start of every frame payload
+pub const FRAME_HEADER_SIZE: usize = 21;
+
+/// Frame flag marking the last frame of a stream
+const FINAL_FRAME: u8 = 0x01;
+
+/// A decoded stream frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamFrame {
+    /// ID of the stream the frame belongs to
+    pub stream_id: Uuid,
+
+    /// Position of the frame in the stream, starting at 0
+    pub sequence: u32,
+
+    /// Whether this is the last frame of the stream
+    pub is_final: bool,
+
+    /// The chunk of the stream carried by this frame
+    pub data: Vec<u8>,
+}
+
+impl StreamFrame {
+    /// Serialize the frame into a payload
+    fn to_payload(&self) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(FRAME_HEADER_SIZE + self.data.len());
+        payload.extend_from_slice(self.stream_id.as_bytes());
+        payload.extend_from_slice(&self.sequence.to_be_bytes());
+        payload.push(if self.is_final { FINAL_FRAME } else { 0 });
+        payload.extend_from_slice(&self.data);
+        payload
+    }
+
+    /// Parse a frame from a payload
+    fn from_payload(payload: &[u8]) -> Result<Self> {
+        let truncated =
+            || HideError::InvalidParameters("Truncated stream frame header".to_string());
+
+        let (header, data) = payload
+            .split_first_chunk::<FRAME_HEADER_SIZE>()
+            .ok_or_else(truncated)?;
+        let (stream_id, rest) = header.split_first_chunk::<16>().ok_or_else(truncated)?;
+        let (sequence, rest) = rest.split_first_chunk::<4>().ok_or_else(truncated)?;
+        let flags = rest.first().ok_or_else(truncated)?;
+
+        Ok(Self {
+            stream_id: Uuid::from_bytes(*stream_id),
+            sequence: u32::from_be_bytes(*sequence),
+            is_final: flags & FINAL_FRAME != 0,
+            data: data.to_vec(),
+        })
+    }
+}
+
+/// Spreads the bytes of a reader across a sequence of cover images
+pub struct StreamEncoder<R> {
+    /// Encoder used for every frame
+    encoder: Encoder,
+
+    /// Where the stream bytes come from
+    source: R,
+
+    /// ID written into every frame
+    stream_id: Uuid,
+
+    /// Sequence number of the next frame
+    next_sequence: u32,
+
+    /// Whether the final frame has been written
+    finished: bool,
+}
+
+impl<R: Read> StreamEncoder<R> {
+    /// Create a stream encoder with a new random stream ID
+    ///
+    /// The ID is drawn from the config's RNG when one is set.
+    ///
+    /// # Arguments
+    /// * `source` - Reader supplying the stream bytes
+    /// * `config` - Options for encoding each frame
+    pub fn new(source: R, config: EncoderConfig) -> Self {
+        let mut id_bytes = [0u8; 16];
+        rng::fill_bytes(config.rng.as_ref(), &mut id_bytes);
+
+        Self {
+            encoder: Encoder::with_config(config),
+            source,
+            stream_id: uuid::Builder::from_random_bytes(id_bytes).into_uuid(),
+            next_sequence: 0,
+            finished: false,
+        }
+    }
+
+    /// Get the ID written into every frame of this stream
+    pub fn stream_id(&self) -> Uuid {
+        self.stream_id
+    }
+
+    /// Whether the final frame has been written
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Embed the next chunk of the stream into a cover
+    ///
+    /// As many bytes as fit are read from the source. The frame is marked as
+    /// final once the source is exhausted.
+    ///
+    /// # Arguments
+    /// * `cover` - The cover image for the next frame
+    ///
+    /// # Returns
+    /// * The stego image holding the frame
+    pub fn next_cover(&mut self, cover: StegoImage) -> Result<StegoImage> {
+        if self.finished {
+            return Err(HideError::InvalidParameters(
+                "Stream has already been fully encoded".to_string(),
+            ));
+        }
+
+        let chunk_size = self
+            .encoder
+            .max_payload_size(&cover, HeaderFlags::STREAM)
+            .checked_sub(FRAME_HEADER_SIZE)
+            .filter(|&size| size > 0)
+            .ok_or(HideError::MessageTooLarge)?;
+
+        // Fill the chunk; a short read means the source is exhausted
+        let mut data = Vec::with_capacity(chunk_size);
+        let read = (&mut self.source)
+            .take(chunk_size as u64)
+            .read_to_end(&mut data)?;
+        let is_final = read < chunk_size;
+
+        let frame = StreamFrame {
+            stream_id: self.stream_id,
+            sequence: self.next_sequence,
+            is_final,
+            data,
+        };
+        let (stego_image, _) =
+            self.encoder
+                .encode_payload(cover, &frame.to_payload(), HeaderFlags::STREAM)?;
+
+        self.next_sequence += 1;
+        self.finished = is_final;
+        Ok(stego_image)
+    }
+}
+
+/// Reassembles a stream from frames added in any order
+///
+/// The reconstructed bytes are available through [`Read`]; frames are dropped
+/// once they have been read. The state can be saved and loaded to resume
+/// after a restart.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StreamDecoder {
+    /// ID of the stream, taken from the first frame added
+    stream_id: Option<Uuid>,
+
+    /// Frames received but not yet read, by sequence number
+    frames: BTreeMap<u32, Vec<u8>>,
+
+    /// Sequence number of the last frame, once it has been received
+    final_sequence: Option<u32>,
+
+    /// Sequence number of the next frame to read
+    next_sequence: u32,
+
+    /// Read position within the next frame
+    offset: usize,
+}
+
+impl StreamDecoder {
+    /// Create an empty stream decoder
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the ID of the stream being decoded, once known
+    pub fn stream_id(&self) -> Option<Uuid> {
+        self.stream_id
+    }
+
+    /// Decode a frame from an image and add it to the stream
+    ///
+    /// # Arguments
+    /// * `image` - An image produced by [`StreamEncoder::next_cover`]
+    ///
+    /// # Returns
+    /// * The sequence number of the frame
+    pub fn add_image(&mut self, image: &StegoImage) -> Result<u32> {
+        let (header, payload) = Decoder::new().decode_payload(image)?;
+        if !header.is_stream_frame() {
+            return Err(HideError::InvalidParameters(
+                "Image does not hold a stream frame".to_string(),
+            ));
+        }
+
+        let frame = StreamFrame::from_payload(&payload)?;
+        let sequence = frame.sequence;
+        self.add_frame(frame)?;
+        Ok(sequence)
+    }
+
+    /// Add an already decoded frame to the stream
+    ///
+    /// Frames that were already added or read are ignored.
+    pub fn add_frame(&mut self, frame: StreamFrame) -> Result<()> {
+        match self.stream_id {
+            Some(id) if id != frame.stream_id => {
+                return Err(HideError::InvalidParameters(format!(
+                    "Frame belongs to stream {}, expected {}",
+                    frame.stream_id, id
+                )));
+            }
+            _ => self.stream_id = Some(frame.stream_id),
+        }
+
+        if frame.is_final {
+            self.final_sequence = Some(frame.sequence);
+        }
+        if frame.sequence >= self.next_sequence {
+            self.frames.entry(frame.sequence).or_insert(frame.data);
+        }
+
+        Ok(())
+    }
+
+    /// Whether every frame up to the final one has been received
+    pub fn is_complete(&self) -> bool {
+        self.final_sequence.is_some() && self.missing().is_empty()
+    }
+
+    /// Ranges of sequence numbers that have not been received yet
+    ///
+    /// Only gaps before the final frame, or before the highest frame received
+    /// if the final one hasn't arrived, are reported.
+    pub fn missing(&self) -> Vec<RangeInclusive<u32>> {
+        let last = match (self.final_sequence, self.frames.keys().next_back()) {
+            (Some(last), _) | (None, Some(&last)) => last,
+            (None, None) => return Vec::new(),
+        };
+
+        let mut gaps = Vec::new();
+        let mut expected = self.next_sequence;
+        for &sequence in self.frames.keys() {
+            if sequence > expected {
+                gaps.push(expected..=sequence - 1);
+            }
+            expected = sequence + 1;
+        }
+        if expected <= last {
+            gaps.push(expected..=last);
+        }
+
+        gaps
+    }
+
+    /// Write the decoder state as JSON
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path, serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+
+    /// Read decoder state written by [`StreamDecoder::save`]
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let data = fs::read(path)?;
+        Ok(serde_json::from_slice(&data)?)
+    }
+}
+
+impl Read for StreamDecoder {
+    /// Read the reconstructed stream
+    ///
+    /// Returns `Ok(0)` at the end of the stream and a `WouldBlock` error when
+    /// the next frame hasn't been received yet.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self
+                .final_sequence
+                .is_some_and(|last| self.next_sequence > last)
+            {
+                return Ok(0);
+            }
+
+            let Some(data) = self.frames.get(&self.next_sequence) else {
+                return Err(io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    format!("Waiting for stream frame {}", self.next_sequence),
+                ));
+            };
+
+            let mut remaining = data.get(self.offset..).unwrap_or_default();
+            if !remaining.is_empty() {
+                let n = remaining.read(buf)?;
+                self.offset += n;
+                return Ok(n);
+            }
+
+            // The frame is used up; move on to the next one
+            self.frames.remove(&self.next_sequence);
+            self.next_sequence += 1;
+            self.offset = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(sequence: u32, is_final: bool, data: &[u8]) -> StreamFrame {
+        StreamFrame {
+            stream_id: Uuid::nil(),
+            sequence,
+            is_final,
+            data: data.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_frame_round_trip() {
+        let original = frame(7, true, b"chunk");
+        let parsed = StreamFrame::from_payload(&original.to_payload()).unwrap();
+        assert_eq!(parsed, original);
+        assert!(StreamFrame::from_payload(&[0; FRAME_HEADER_SIZE - 1]).is_err());
+    }
+
+    #[test]
+    fn test_missing_ranges() {
+        let mut decoder = StreamDecoder::new();
+        assert!(decoder.missing().is_empty());
+
+        decoder.add_frame(frame(0, false, b"a")).unwrap();
+        decoder.add_frame(frame(3, false, b"d")).unwrap();
+        assert_eq!(decoder.missing(), vec![1..=2]);
+
+        decoder.add_frame(frame(6, true, b"g")).unwrap();
+        assert_eq!(decoder.missing(), vec![1..=2, 4..=5]);
+        assert!(!decoder.is_complete());
+    }
+
+    #[test]
+    fn test_frames_from_other_streams_are_rejected() {
+        let mut decoder = StreamDecoder::new();
+        decoder.add_frame(frame(0, false, b"a")).unwrap();
+
+        let mut other = frame(1, false, b"b");
+        other.stream_id = Uuid::new_v4();
+        assert!(decoder.add_frame(other).is_err());
+    }
+}
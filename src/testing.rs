@@ -0,0 +1,284 @@
+//! Deterministic images and assertions shared by the test suites
+//!
+//! Not part of the supported API; the patterns may change whenever the tests
+//! need them to.
+//!
+//! ```
+//! use hide_rs::encoder::EncoderConfig;
+//! use hide_rs::testing::{assert_roundtrip, gradient_image};
+//!
+//! assert_roundtrip(gradient_image(20, 20), b"doc test", EncoderConfig::default());
+//! ```
+
+// These are assertions for test suites, where panicking is how they report
+#![allow(clippy::unwrap_used, clippy::expect_used, clippy::indexing_slicing)]
+
+use crate::decoder::Decoder;
+use crate::encoder::{Encoder, EncoderConfig};
+use crate::error::HideError;
+use crate::img::{PixelSurface, StegoImage};
+use crate::rng;
+use crate::Result;
+use image::{DynamicImage, ImageBuffer, Rgb};
+use rand::{rngs::StdRng, Rng, RngCore, SeedableRng};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Create an image with smoothly varying values in every channel
+pub fn gradient_image(width: u32, height: u32) -> StegoImage {
+    let img = ImageBuffer::from_fn(width, height, |x, y| {
+        Rgb([
+            x.wrapping_mul(15).wrapping_add(y) as u8,
+            y.wrapping_mul(15).wrapping_add(x) as u8,
+            x.wrapping_add(y).wrapping_mul(7) as u8,
+        ])
+    });
+    StegoImage::from_dynamic_image(DynamicImage::ImageRgb8(img))
+}
+
+/// Create an image of random pixels, the same for the same seed
+pub fn noise_image(width: u32, height: u32, seed: u64) -> StegoImage {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let img = ImageBuffer::from_fn(width, height, |_, _| Rgb(rng.random::<[u8; 3]>()));
+    StegoImage::from_dynamic_image(DynamicImage::ImageRgb8(img))
+}
+
+/// Create an image of alternating black and white pixels
+///
+/// Every channel sits at 0 or 255, where LSB changes are easiest to get wrong.
+pub fn checkerboard(width: u32, height: u32) -> StegoImage {
+    let img = ImageBuffer::from_fn(width, height, |x, y| {
+        if (x + y) % 2 == 0 {
+            Rgb([0, 0, 0])
+        } else {
+            Rgb([255, 255, 255])
+        }
+    });
+    StegoImage::from_dynamic_image(DynamicImage::ImageRgb8(img))
+}
+
+/// Create a gradient image holding a message encoded with the default options
+pub fn image_with_payload(width: u32, height: u32, message: &[u8]) -> StegoImage {
+    Encoder::new()
+        .encode(gradient_image(width, height), message)
+        .expect("Message does not fit the test image")
+}
+
+/// Assert that two images differ only in the least significant bits
+///
+/// # Returns
+/// * The number of channel values that changed
+#[track_caller]
+pub fn assert_lsb_only_changes(cover: &StegoImage, stego: &StegoImage) -> usize {
+    assert_eq!(
+        cover.dimensions(),
+        stego.dimensions(),
+        "Image dimensions changed"
+    );
+
+    let mut changed = 0;
+    for y in 0..cover.height() {
+        for x in 0..cover.width() {
+            let before = cover.get_pixel_rgb(x, y).unwrap();
+            let after = stego.get_pixel_rgb(x, y).unwrap();
+            for channel in 0..3 {
+                let diff = before.0[channel] ^ after.0[channel];
+                assert!(
+                    diff <= 1,
+                    "Channel {} of pixel ({}, {}) changed beyond its LSB: {} -> {}",
+                    channel,
+                    x,
+                    y,
+                    before.0[channel],
+                    after.0[channel]
+                );
+                changed += diff as usize;
+            }
+        }
+    }
+
+    changed
+}
+
+/// Encode a message, check that only LSBs changed and that it decodes again
+///
+/// # Returns
+/// * The stego image
+#[track_caller]
+pub fn assert_roundtrip(cover: StegoImage, message: &[u8], config: EncoderConfig) -> StegoImage {
+    let stego = Encoder::with_config(config)
+        .encode(cover.clone(), message)
+        .expect("Encoding failed");
+    assert_lsb_only_changes(&cover, &stego);
+
+    let decoded = Decoder::new().decode(&stego).expect("Decoding failed");
+    assert_eq!(decoded, message, "Decoded message differs");
+    stego
+}
+
+/// Deterministic generator that hands out 0, 1, 2, ... as bytes
+///
+/// Clones share the count of bytes handed out, so a test can keep one and
+/// inject another.
+#[derive(Debug, Clone, Default)]
+pub struct CountingRng {
+    drawn: Arc<AtomicU64>,
+}
+
+impl CountingRng {
+    /// Create a generator starting at 0
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of bytes handed out so far by this generator and its clones
+    pub fn bytes_drawn(&self) -> u64 {
+        self.drawn.load(Ordering::SeqCst)
+    }
+}
+
+impl RngCore for CountingRng {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0u8; 4];
+        self.fill_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0u8; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        let start = self.drawn.fetch_add(dest.len() as u64, Ordering::SeqCst);
+        for (i, byte) in dest.iter_mut().enumerate() {
+            *byte = (start + i as u64) as u8;
+        }
+    }
+}
+
+/// Read-only view of an image that counts the pixels read through it
+pub struct CountingSurface<'a> {
+    image: &'a StegoImage,
+    reads: Cell<u64>,
+}
+
+impl<'a> CountingSurface<'a> {
+    /// Wrap an image with the count at 0
+    pub fn new(image: &'a StegoImage) -> Self {
+        Self {
+            image,
+            reads: Cell::new(0),
+        }
+    }
+
+    /// Number of `get_pixel_rgb` calls made so far
+    pub fn reads(&self) -> u64 {
+        self.reads.get()
+    }
+}
+
+impl PixelSurface for CountingSurface<'_> {
+    fn width(&self) -> u32 {
+        self.image.width()
+    }
+
+    fn height(&self) -> u32 {
+        self.image.height()
+    }
+
+    fn get_pixel_rgb(&self, x: u32, y: u32) -> Result<Rgb<u8>> {
+        self.reads.set(self.reads.get() + 1);
+        self.image.get_pixel_rgb(x, y)
+    }
+
+    fn set_pixel_rgb(&mut self, _x: u32, _y: u32, _pixel: Rgb<u8>) -> Result<()> {
+        Err(HideError::InvalidParameters(
+            "Counting surfaces are read-only".to_string(),
+        ))
+    }
+}
+
+/// Guard returned by [`deny_default_rng`]
+pub struct DefaultRngGuard(());
+
+impl Drop for DefaultRngGuard {
+    fn drop(&mut self) {
+        rng::set_default_rng_denied(false);
+    }
+}
+
+/// Make any fallback to the system RNG on this thread panic until the guard drops
+pub fn deny_default_rng() -> DefaultRngGuard {
+    rng::set_default_rng_denied(true);
+    DefaultRngGuard(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_images_are_deterministic() {
+        let a = noise_image(8, 8, 7);
+        assert_eq!(
+            a.inner().as_bytes(),
+            noise_image(8, 8, 7).inner().as_bytes()
+        );
+        assert_ne!(
+            a.inner().as_bytes(),
+            noise_image(8, 8, 8).inner().as_bytes()
+        );
+
+        let board = checkerboard(4, 4);
+        assert_eq!(board.get_pixel_rgb(0, 0).unwrap().0, [0, 0, 0]);
+        assert_eq!(board.get_pixel_rgb(1, 0).unwrap().0, [255, 255, 255]);
+        assert_eq!(board.get_pixel_rgb(1, 1).unwrap().0, [0, 0, 0]);
+
+        // Large coordinates wrap rather than overflow
+        assert_eq!(gradient_image(300, 2).width(), 300);
+    }
+
+    #[test]
+    fn test_image_with_payload() {
+        let stego = image_with_payload(20, 20, b"fixture");
+        assert_eq!(Decoder::new().decode(&stego).unwrap(), b"fixture");
+    }
+
+    #[test]
+    fn test_assert_lsb_only_changes() {
+        let cover = gradient_image(4, 4);
+        let mut stego = cover.clone();
+        assert_eq!(assert_lsb_only_changes(&cover, &stego), 0);
+
+        let mut pixel = stego.get_pixel_rgb(1, 2).unwrap();
+        pixel.0[0] ^= 1;
+        pixel.0[2] ^= 1;
+        stego.set_pixel_rgb(1, 2, pixel).unwrap();
+        assert_eq!(assert_lsb_only_changes(&cover, &stego), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "beyond its LSB")]
+    fn test_assert_lsb_only_changes_rejects_larger_changes() {
+        let cover = gradient_image(4, 4);
+        let mut stego = cover.clone();
+        let mut pixel = stego.get_pixel_rgb(0, 0).unwrap();
+        pixel.0[1] ^= 2;
+        stego.set_pixel_rgb(0, 0, pixel).unwrap();
+        assert_lsb_only_changes(&cover, &stego);
+    }
+
+    #[test]
+    fn test_assert_roundtrip() {
+        for cover in [
+            gradient_image(20, 20),
+            noise_image(20, 20, 1),
+            checkerboard(20, 20),
+        ] {
+            assert_roundtrip(cover, b"round trip", EncoderConfig::default());
+        }
+    }
+}
@@ -0,0 +1,432 @@
+//! Traces of the pixels that carried a payload
+//!
+//! [`Decoder::decode_with_trace`] records which pixels held which bits of
+//! the payload it decoded, following the same layout the payload was read
+//! in: row-major order, skipping masked pixels and, in RGBA mode,
+//! transparent ones. Consecutive pixels carrying consecutive bits are
+//! stored as one [`TraceRun`], so a plain payload traces to a single run.
+//! Payloads with a rotating or keyed channel schedule also record the
+//! channel order of every traced pixel.
+//!
+//! Bit positions count from the start of the payload: the 8 header bytes,
+//! then an embedded mask if there is one, then the message.
+//!
+//! [`Decoder::decode_with_trace`]: crate::decoder::Decoder::decode_with_trace
+
+use crate::error::HideError;
+use crate::img::StegoImage;
+use crate::layout::{BitOffset, PixelIndex, PixelX, PixelY};
+use crate::Result;
+use image::Rgb;
+use std::ops::Range;
+
+/// Color blended over pixels carrying the header and an embedded mask
+const HEADER_COLOR: [u8; 3] = [0, 128, 255];
+
+/// Color blended over the pixels carrying the first message bytes
+const MESSAGE_START_COLOR: [u8; 3] = [255, 255, 0];
+
+/// Color blended over the pixels carrying the last message bytes
+const MESSAGE_END_COLOR: [u8; 3] = [255, 0, 0];
+
+/// Share of an overlay pixel taken by the trace color, out of 255
+const OVERLAY_OPACITY: u16 = 160;
+
+/// Channel order of pixels embedded red, green, blue
+const FIXED_ORDER: [u8; 3] = [0, 1, 2];
+
+/// Consecutive pixels carrying consecutive payload bits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceRun {
+    /// Row-major index of the first pixel
+    pub first_pixel: u64,
+
+    /// Number of pixels in the run
+    pub pixel_count: u64,
+
+    /// Position of the first bit the run carries
+    pub first_bit: u64,
+
+    /// Bits each pixel of the run carries
+    pub bits_per_pixel: u64,
+}
+
+impl TraceRun {
+    /// Row-major indices of the run's pixels
+    pub fn pixels(&self) -> Range<u64> {
+        self.first_pixel..self.first_pixel + self.pixel_count
+    }
+
+    /// Positions of the bits the run's pixels can hold
+    ///
+    /// The last pixel of a run may hold padding past the bits it carries.
+    pub fn bits(&self) -> Range<u64> {
+        self.first_bit..self.first_bit + self.pixel_count * self.bits_per_pixel
+    }
+}
+
+/// One pixel of a trace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TracedPixel {
+    /// Column of the pixel
+    pub x: u32,
+
+    /// Row of the pixel
+    pub y: u32,
+
+    /// Positions of the payload bits the pixel carries
+    pub bits: Range<u64>,
+
+    /// Indices into R, G, B of the channels carrying the pixel's first,
+    /// second and third bits; RGBA payload pixels always use R, G, B, A
+    pub channel_order: [usize; 3],
+}
+
+/// Where each bit of a decoded payload was found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PayloadTrace {
+    /// Width of the traced image in pixels
+    width: u32,
+
+    /// Height of the traced image in pixels
+    height: u32,
+
+    /// Length of the header and any embedded mask, in bytes
+    header_bytes: u64,
+
+    /// Length of the whole payload, header included, in bytes
+    total_bytes: u64,
+
+    /// The traced pixels, in embedding order
+    runs: Vec<TraceRun>,
+
+    /// Channel order of each traced pixel; empty if all use the fixed order
+    channel_orders: Vec<[u8; 3]>,
+}
+
+impl PayloadTrace {
+    /// Width and height of the traced image
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Length of the header and any embedded mask, in bytes
+    pub fn header_bytes(&self) -> u64 {
+        self.header_bytes
+    }
+
+    /// Length of the whole payload, header included, in bytes
+    pub fn total_bytes(&self) -> u64 {
+        self.total_bytes
+    }
+
+    /// The traced pixels as runs, in embedding order
+    pub fn runs(&self) -> &[TraceRun] {
+        &self.runs
+    }
+
+    /// Number of pixels carrying payload bits
+    pub fn pixel_count(&self) -> u64 {
+        self.runs.iter().map(|run| run.pixel_count).sum()
+    }
+
+    /// Iterate over the traced pixels in embedding order
+    pub fn pixels(&self) -> impl Iterator<Item = TracedPixel> + '_ {
+        let total_bits = self.total_bytes * 8;
+        self.runs
+            .iter()
+            .flat_map(move |run| {
+                run.pixels().map(move |pixel| {
+                    let first_bit = run.first_bit + (pixel - run.first_pixel) * run.bits_per_pixel;
+                    (
+                        pixel,
+                        first_bit..(first_bit + run.bits_per_pixel).min(total_bits),
+                    )
+                })
+            })
+            .enumerate()
+            .map(move |(ordinal, (pixel, bits))| {
+                let (x, y) = PixelIndex(pixel).to_xy(self.width);
+                TracedPixel {
+                    x: x.get(),
+                    y: y.get(),
+                    bits,
+                    channel_order: self
+                        .channel_orders
+                        .get(ordinal)
+                        .copied()
+                        .unwrap_or(FIXED_ORDER)
+                        .map(usize::from),
+                }
+            })
+    }
+
+    /// Coordinates of the pixel carrying a payload bit
+    ///
+    /// # Arguments
+    /// * `bit` - Position of the bit, counted from the start of the payload
+    ///
+    /// # Returns
+    /// * The pixel, or `None` if the bit is past the end of the payload
+    pub fn pixel_for_bit(&self, bit: BitOffset) -> Option<(PixelX, PixelY)> {
+        if bit.get() >= self.total_bytes * 8 {
+            return None;
+        }
+        self.runs
+            .iter()
+            .find(|run| run.bits().contains(&bit.get()))
+            .map(|run| {
+                let pixel = run.first_pixel + (bit.get() - run.first_bit) / run.bits_per_pixel;
+                PixelIndex(pixel).to_xy(self.width)
+            })
+    }
+
+    /// Coordinates of the pixels carrying any bit of a range of payload bytes
+    ///
+    /// # Arguments
+    /// * `bytes` - Byte positions counted from the start of the payload
+    ///
+    /// # Returns
+    /// * The pixels in embedding order
+    pub fn pixels_for_bytes(&self, bytes: Range<u64>) -> Vec<(u32, u32)> {
+        let bits = bytes.start * 8..bytes.end * 8;
+        self.pixels()
+            .filter(|pixel| pixel.bits.start < bits.end && bits.start < pixel.bits.end)
+            .map(|pixel| (pixel.x, pixel.y))
+            .collect()
+    }
+
+    /// Render the trace as a translucent heatmap over an image
+    ///
+    /// Pixels carrying the header and any embedded mask are tinted blue;
+    /// message pixels go from yellow for the first bytes to red for the
+    /// last. Pixels outside the trace are left as they are.
+    ///
+    /// # Arguments
+    /// * `image` - The image to draw over, usually the traced stego image
+    ///
+    /// # Returns
+    /// * The overlay, or `InvalidParameters` if the image is not the traced size
+    pub fn to_overlay_image(&self, image: &StegoImage) -> Result<StegoImage> {
+        if image.dimensions() != self.dimensions() {
+            return Err(HideError::InvalidParameters(format!(
+                "Trace is for a {}x{} image, not {}x{}",
+                self.width,
+                self.height,
+                image.width(),
+                image.height()
+            )));
+        }
+
+        let mut overlay =
+            StegoImage::from_dynamic_image(image::DynamicImage::ImageRgb8(image.inner().to_rgb8()));
+        let header_bits = self.header_bytes * 8;
+        let message_bits = (self.total_bytes * 8).saturating_sub(header_bits).max(1);
+        for pixel in self.pixels() {
+            let color = if pixel.bits.start < header_bits {
+                HEADER_COLOR
+            } else {
+                let position = (pixel.bits.start - header_bits) * 255 / message_bits;
+                mix(MESSAGE_START_COLOR, MESSAGE_END_COLOR, position as u16)
+            };
+            let original = overlay.get_pixel_rgb(pixel.x, pixel.y)?;
+            overlay.set_pixel_rgb(
+                pixel.x,
+                pixel.y,
+                Rgb(mix(original.0, color, OVERLAY_OPACITY)),
+            )?;
+        }
+        Ok(overlay)
+    }
+}
+
+/// Blend two colors, taking `weight` out of 255 from the second
+fn mix(a: [u8; 3], b: [u8; 3], weight: u16) -> [u8; 3] {
+    let weight = weight.min(255);
+    let mut mixed = [0; 3];
+    for ((out, a), b) in mixed.iter_mut().zip(a).zip(b) {
+        *out = ((u16::from(a) * (255 - weight) + u16::from(b) * weight) / 255) as u8;
+    }
+    mixed
+}
+
+/// Collects the pixels of a payload into a [`PayloadTrace`]
+pub(crate) struct TraceBuilder {
+    /// The trace being built
+    trace: PayloadTrace,
+
+    /// Whether a pixel used an order other than the fixed one
+    reordered: bool,
+}
+
+impl TraceBuilder {
+    /// Start a trace of an image
+    ///
+    /// # Arguments
+    /// * `width` - Width of the image in pixels
+    /// * `height` - Height of the image in pixels
+    /// * `header_bytes` - Length of the header and any embedded mask
+    /// * `total_bytes` - Length of the whole payload, header included
+    pub(crate) fn new(width: u32, height: u32, header_bytes: u64, total_bytes: u64) -> Self {
+        Self {
+            trace: PayloadTrace {
+                width,
+                height,
+                header_bytes,
+                total_bytes,
+                runs: Vec::new(),
+                channel_orders: Vec::new(),
+            },
+            reordered: false,
+        }
+    }
+
+    /// Record the next pixel of the payload
+    ///
+    /// # Arguments
+    /// * `pixel` - Row-major index of the pixel
+    /// * `first_bit` - Position of the first bit it carries
+    /// * `bits_per_pixel` - Bits it carries
+    /// * `order` - Channel order it was embedded in
+    pub(crate) fn push(
+        &mut self,
+        pixel: u64,
+        first_bit: u64,
+        bits_per_pixel: u64,
+        order: [usize; 3],
+    ) {
+        // Orders only hold channel indices below 3
+        let order = order.map(|channel| channel as u8);
+        self.reordered |= order != FIXED_ORDER;
+        self.trace.channel_orders.push(order);
+
+        match self.trace.runs.last_mut() {
+            Some(run)
+                if run.pixels().end == pixel
+                    && run.bits().end == first_bit
+                    && run.bits_per_pixel == bits_per_pixel =>
+            {
+                run.pixel_count += 1;
+            }
+            _ => self.trace.runs.push(TraceRun {
+                first_pixel: pixel,
+                pixel_count: 1,
+                first_bit,
+                bits_per_pixel,
+            }),
+        }
+    }
+
+    /// Whether the pixels recorded so far carry every bit of the payload
+    pub(crate) fn is_complete(&self) -> bool {
+        self.trace
+            .runs
+            .last()
+            .is_some_and(|run| run.bits().end >= self.trace.total_bytes * 8)
+    }
+
+    /// Finish the trace
+    pub(crate) fn finish(mut self) -> PayloadTrace {
+        if !self.reordered {
+            self.trace.channel_orders = Vec::new();
+        }
+        self.trace
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_consecutive_pixels_form_one_run() {
+        let mut builder = TraceBuilder::new(10, 10, 8, 12);
+        for pixel in 0..32 {
+            builder.push(pixel, pixel * 3, 3, [0, 1, 2]);
+        }
+        assert!(builder.is_complete());
+
+        let trace = builder.finish();
+        assert_eq!(trace.runs().len(), 1);
+        assert_eq!(trace.pixel_count(), 32);
+        assert!(trace.channel_orders.is_empty());
+
+        // Byte 8 holds bits 64..72, carried by pixels 21 to 23
+        assert_eq!(trace.pixels_for_bytes(8..9), [(1, 2), (2, 2), (3, 2)]);
+
+        // The last pixel holds bits 93..96 of a 96-bit payload
+        let last = trace.pixels().last().unwrap();
+        assert_eq!((last.x, last.y, last.bits), (1, 3, 93..96));
+    }
+
+    #[test]
+    fn test_pixel_for_bit_on_wide_image() {
+        // Every other pixel of a 7x3 image: bit 14 is carried by pixel 8,
+        // column 1 of the second row, which a 3-wide reading puts at (2, 2)
+        let mut builder = TraceBuilder::new(7, 3, 2, 3);
+        for pixel in 0..8 {
+            builder.push(pixel * 2, pixel * 3, 3, [0, 1, 2]);
+        }
+
+        let trace = builder.finish();
+        assert_eq!(
+            trace.pixel_for_bit(BitOffset(14)),
+            Some((PixelX(1), PixelY(1)))
+        );
+        assert_eq!(
+            trace.pixel_for_bit(BitOffset(0)),
+            Some((PixelX(0), PixelY(0)))
+        );
+        assert_eq!(
+            trace.pixel_for_bit(BitOffset(23)),
+            Some((PixelX(0), PixelY(2)))
+        );
+        assert_eq!(trace.pixel_for_bit(BitOffset(24)), None);
+        assert_eq!(
+            trace.pixels().map(|pixel| (pixel.x, pixel.y)).nth(4),
+            Some((1, 1))
+        );
+    }
+
+    #[test]
+    fn test_gaps_start_new_runs() {
+        let mut builder = TraceBuilder::new(4, 4, 8, 9);
+        for (pixel, first_bit) in [(0, 0), (1, 3), (3, 6), (4, 9)] {
+            builder.push(pixel, first_bit, 3, [1, 2, 0]);
+        }
+        builder.push(5, 16, 4, [0, 1, 2]);
+
+        let trace = builder.finish();
+        assert_eq!(
+            trace
+                .runs()
+                .iter()
+                .map(TraceRun::pixels)
+                .collect::<Vec<_>>(),
+            [0..2, 3..5, 5..6]
+        );
+        assert_eq!(trace.pixels().next().unwrap().channel_order, [1, 2, 0]);
+        assert_eq!(trace.pixels().last().unwrap().channel_order, [0, 1, 2]);
+    }
+
+    #[test]
+    fn test_overlay_tints_traced_pixels() {
+        let mut builder = TraceBuilder::new(4, 4, 1, 2);
+        for pixel in 0..6 {
+            builder.push(pixel, pixel * 3, 3, [0, 1, 2]);
+        }
+        let trace = builder.finish();
+
+        let image = crate::img::create_rgb_image(4, 4).unwrap();
+        let overlay = trace.to_overlay_image(&image).unwrap();
+        let before = image.get_pixel_rgb(0, 0).unwrap();
+        assert_ne!(overlay.get_pixel_rgb(0, 0).unwrap(), before);
+        assert_eq!(
+            overlay.get_pixel_rgb(3, 3).unwrap(),
+            image.get_pixel_rgb(3, 3).unwrap()
+        );
+
+        let other = crate::img::create_rgb_image(5, 4).unwrap();
+        assert!(trace.to_overlay_image(&other).is_err());
+    }
+}
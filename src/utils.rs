@@ -1,6 +1,8 @@
 use crate::error::HideError;
 use crate::Result;
 use bitvec::prelude::*;
+use std::time::{Duration, Instant};
+use subtle::ConstantTimeEq;
 
 /// Extract `k` least significant bits from a byte
 ///
@@ -77,23 +79,24 @@ pub fn set_lsbs(byte: u8, bits: u8, k: u8) -> Result<u8> {
 
 /// Convert a sequence of bytes to a bit vector
 ///
+/// Bits are taken most significant first, the order they are embedded in, so
+/// the first bit of `[0x80]` is set.
+///
 /// # Arguments
 /// * `bytes` - The byte slice to convert
 ///
 /// # Returns
 /// * A bit vector containing all bits from the input bytes
 pub fn bytes_to_bits(bytes: &[u8]) -> BitVec<u8, Msb0> {
-    let mut bits = BitVec::<u8, Msb0>::with_capacity(bytes.len() * 8);
-    for &byte in bytes {
-        for i in 0..8 {
-            bits.push(((byte >> (7 - i)) & 1) == 1);
-        }
-    }
-    bits
+    // Msb0 ordering over u8 storage matches the byte's bit order, so copy directly
+    BitVec::from_slice(bytes)
 }
 
 /// Convert a bit vector to a sequence of bytes
 ///
+/// The inverse of [`bytes_to_bits`]: the first bit becomes the most
+/// significant bit of the first byte.
+///
 /// # Arguments
 /// * `bits` - The bits to convert
 ///
@@ -101,7 +104,7 @@ pub fn bytes_to_bits(bytes: &[u8]) -> BitVec<u8, Msb0> {
 /// * A vector of bytes constructed from the input bits
 /// * If the bit vector length is not a multiple of 8, the last byte is padded with 0s
 pub fn bits_to_bytes(bits: &BitSlice<u8, Msb0>) -> Vec<u8> {
-    let mut bytes = Vec::with_capacity((bits.len() + 7) / 8);
+    let mut bytes = Vec::with_capacity(bits.len().div_ceil(8));
     let mut byte = 0u8;
     let mut bit_count = 0;
 
@@ -245,10 +248,278 @@ pub fn set_lsb(byte: &mut u8, bit: bool) {
     }
 }
 
+/// Parse a ratio given either as a fraction or as a percentage
+///
+/// # Arguments
+/// * `value` - A fraction such as `0.005` or a percentage such as `0.5%`
+///
+/// # Returns
+/// * The ratio as a fraction between 0.0 and 1.0
+pub fn parse_ratio(value: &str) -> Result<f64> {
+    let value = value.trim();
+    let (number, scale) = match value.strip_suffix('%') {
+        Some(percent) => (percent.trim(), 100.0),
+        None => (value, 1.0),
+    };
+
+    let ratio = number
+        .parse::<f64>()
+        .map(|n| n / scale)
+        .map_err(|_| HideError::InvalidParameters(format!("Invalid ratio: {}", value)))?;
+    if !(0.0..=1.0).contains(&ratio) {
+        return Err(HideError::InvalidParameters(format!(
+            "Ratio must be between 0 and 1 (or 0% and 100%): {}",
+            value
+        )));
+    }
+
+    Ok(ratio)
+}
+
+/// Parse image dimensions written as `<width>x<height>`
+///
+/// # Arguments
+/// * `value` - Dimensions such as `800x600`
+///
+/// # Returns
+/// * The width and height, both non-zero
+pub fn parse_dimensions(value: &str) -> Result<(u32, u32)> {
+    let value = value.trim();
+    let invalid = || {
+        HideError::InvalidParameters(format!(
+            "Invalid dimensions (expected <width>x<height>): {}",
+            value
+        ))
+    };
+
+    let (width, height) = value.split_once(['x', 'X']).ok_or_else(invalid)?;
+    let width = width.trim().parse::<u32>().map_err(|_| invalid())?;
+    let height = height.trim().parse::<u32>().map_err(|_| invalid())?;
+    if width == 0 || height == 0 {
+        return Err(invalid());
+    }
+
+    Ok((width, height))
+}
+
+/// Parse a block size written as `<size>` for square blocks or `<width>x<height>`
+///
+/// # Arguments
+/// * `value` - Block size such as `8` or `16x8`
+///
+/// # Returns
+/// * The width and height, both non-zero
+pub fn parse_block_size(value: &str) -> Result<(u32, u32)> {
+    match value.trim().parse::<u32>() {
+        Ok(size) if size > 0 => Ok((size, size)),
+        Ok(_) => Err(HideError::InvalidParameters(
+            "Block size must be non-zero".to_string(),
+        )),
+        Err(_) => parse_dimensions(value),
+    }
+}
+
+/// Make text safe to print to a terminal
+///
+/// Control characters are shown as their Unicode control pictures (an escape
+/// becomes `␛`) so they can't move the cursor, clear the screen or spoof
+/// output. Invisible formatting characters such as bidirectional overrides
+/// become `\u{...}` escapes. Newlines and tabs are kept.
+///
+/// # Arguments
+/// * `text` - Text that may come from a payload or another untrusted source
+///
+/// # Returns
+/// * The text with every unsafe character replaced
+pub fn sanitize_for_terminal(text: &str) -> String {
+    let mut sanitized = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\n' | '\t' => sanitized.push(c),
+            '\u{0}'..='\u{1f}' => sanitized
+                .push(char::from_u32(0x2400 + c as u32).unwrap_or(char::REPLACEMENT_CHARACTER)),
+            '\u{7f}' => sanitized.push('\u{2421}'),
+            '\u{80}'..='\u{9f}'
+            | '\u{200b}'..='\u{200f}'
+            | '\u{202a}'..='\u{202e}'
+            | '\u{2066}'..='\u{2069}'
+            | '\u{feff}' => sanitized.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => sanitized.push(c),
+        }
+    }
+    sanitized
+}
+
+/// Longest file name, in bytes, returned by [`safe_filename`]
+pub const MAX_FILENAME_BYTES: usize = 128;
+
+/// Longest extension, dot included, that [`safe_filename`] keeps when it shortens a name
+const MAX_EXTENSION_BYTES: usize = 16;
+
+/// Turn a client-supplied file name into one that is safe to create on disk
+///
+/// Only the last path component is kept, whichever separator the client
+/// used. Control characters, characters illegal on Windows, dot-only names and
+/// Windows reserved names such as `CON` are removed. Long names are cut to
+/// [`MAX_FILENAME_BYTES`] while keeping their extension.
+///
+/// # Arguments
+/// * `original` - The name as supplied, if any
+/// * `fallback` - Name to use when nothing usable is left of the original
+///
+/// # Returns
+/// * A non-empty name without path separators
+pub fn safe_filename(original: Option<&str>, fallback: &str) -> String {
+    let options = sanitize_filename::Options {
+        windows: true,
+        truncate: false,
+        replacement: "",
+    };
+
+    let base = original
+        .and_then(|name| name.rsplit(['/', '\\']).next())
+        .unwrap_or_default();
+    let mut name = sanitize_filename::sanitize_with_options(base, options.clone());
+    if name.is_empty() {
+        name = sanitize_filename::sanitize_with_options(fallback, options);
+    }
+    if name.is_empty() {
+        name = "file".to_string();
+    }
+
+    if name.len() <= MAX_FILENAME_BYTES {
+        return name;
+    }
+
+    // Shorten the stem so the extension survives
+    let (stem, extension) = match name.rfind('.') {
+        Some(dot) if dot > 0 && name.len() - dot <= MAX_EXTENSION_BYTES => name.split_at(dot),
+        _ => (name.as_str(), ""),
+    };
+    let (stem, _) = truncate_for_display(stem, MAX_FILENAME_BYTES - extension.len());
+    format!("{}{}", stem, extension)
+}
+
+/// Cut text down to at most `limit` bytes for display
+///
+/// # Returns
+/// * The text shown, ending on a character boundary, and how many bytes were cut
+pub fn truncate_for_display(text: &str, limit: usize) -> (&str, usize) {
+    let mut end = limit.min(text.len());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    (text.get(..end).unwrap_or_default(), text.len() - end)
+}
+
+/// CRC-32 (IEEE 802.3) of some bytes, as used by PNG chunks
+pub fn crc32(data: &[u8]) -> u32 {
+    crc32_update(0, data)
+}
+
+/// Continue a CRC-32 over more bytes
+///
+/// Starting from 0, updating with each part of some bytes in turn gives
+/// the [`crc32`] of all of them.
+pub fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// Compare two byte strings in time that depends only on their lengths
+///
+/// For checking a decoded message against the one a client expects, where an
+/// early exit would reveal how long a prefix matched. The lengths themselves
+/// are not hidden. Authentication tags are checked inside the AEAD ciphers.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.ct_eq(b).into()
+}
+
+/// Time spent in a library call and the number of pixels it processed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timing {
+    /// Wall-clock time spent on the work
+    pub duration: Duration,
+
+    /// Number of pixels processed
+    pub pixels: u64,
+}
+
+impl Timing {
+    /// Measure the time elapsed since `start`
+    ///
+    /// # Arguments
+    /// * `start` - When the work started
+    /// * `pixels` - Number of pixels processed
+    pub fn since(start: Instant, pixels: u64) -> Self {
+        Self {
+            duration: start.elapsed(),
+            pixels,
+        }
+    }
+
+    /// Duration in milliseconds
+    pub fn processing_ms(&self) -> f64 {
+        self.duration.as_secs_f64() * 1000.0
+    }
+
+    /// Pixel throughput, or 0 if no time was measured
+    pub fn pixels_per_second(&self) -> f64 {
+        let secs = self.duration.as_secs_f64();
+        if secs > 0.0 {
+            self.pixels as f64 / secs
+        } else {
+            0.0
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(
+            crc32_update(crc32_update(0, b"1234"), b"56789"),
+            0xCBF4_3926
+        );
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(constant_time_eq(b"", b""));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secrets"));
+
+        // A decoded message differing anywhere, or cut short or extended, doesn't match
+        let expected = b"Meet at the old mill at dawn".to_vec();
+        for position in [0, expected.len() / 2, expected.len() - 1] {
+            let mut decoded = expected.clone();
+            decoded[position] ^= 1;
+            assert!(!constant_time_eq(&decoded, &expected));
+        }
+        assert!(!constant_time_eq(
+            &expected[..expected.len() - 1],
+            &expected
+        ));
+        assert!(!constant_time_eq(
+            &[expected.as_slice(), &[0]].concat(),
+            &expected
+        ));
+        assert!(!constant_time_eq(&[], &expected));
+    }
+
     #[test]
     fn test_get_lsbs() {
         // Test extracting different numbers of LSBs
@@ -303,6 +574,16 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_bits_are_most_significant_first() {
+        let bits = bytes_to_bits(&[0x80, 0x01]);
+        let expected: Vec<bool> = (0..16).map(|i| i == 0 || i == 15).collect();
+        assert_eq!(bits.iter().map(|bit| *bit).collect::<Vec<_>>(), expected);
+
+        let bits: BitVec<u8, Msb0> = [true, false, true].into_iter().collect();
+        assert_eq!(bits_to_bytes(&bits), [0xA0]);
+    }
+
     #[test]
     fn test_bytes_to_bits_partial() {
         // Create a bitvec with a length that's not a multiple of 8
@@ -414,4 +695,102 @@ mod tests {
         set_lsb(&mut byte, false);
         assert_eq!(byte, 0b10101010);
     }
+
+    #[test]
+    fn test_timing() {
+        let timing = Timing {
+            duration: Duration::from_millis(500),
+            pixels: 1000,
+        };
+        assert_eq!(timing.processing_ms(), 500.0);
+        assert_eq!(timing.pixels_per_second(), 2000.0);
+
+        // No measured time means no throughput rather than infinity
+        assert_eq!(Timing::default().pixels_per_second(), 0.0);
+    }
+
+    #[test]
+    fn test_parse_ratio() {
+        assert_eq!(parse_ratio("0.5%").unwrap(), 0.005);
+        assert_eq!(parse_ratio("0.25").unwrap(), 0.25);
+        assert_eq!(parse_ratio(" 100 % ").unwrap(), 1.0);
+        assert!(parse_ratio("150%").is_err());
+        assert!(parse_ratio("-0.1").is_err());
+        assert!(parse_ratio("half").is_err());
+    }
+
+    #[test]
+    fn test_parse_dimensions() {
+        assert_eq!(parse_dimensions("800x600").unwrap(), (800, 600));
+        assert_eq!(parse_dimensions(" 64 X 48 ").unwrap(), (64, 48));
+        assert!(parse_dimensions("800").is_err());
+        assert!(parse_dimensions("0x10").is_err());
+        assert!(parse_dimensions("wide x tall").is_err());
+    }
+
+    #[test]
+    fn test_parse_block_size() {
+        assert_eq!(parse_block_size("8").unwrap(), (8, 8));
+        assert_eq!(parse_block_size("16x8").unwrap(), (16, 8));
+        assert!(parse_block_size("0").is_err());
+        assert!(parse_block_size("8x0").is_err());
+        assert!(parse_block_size("big").is_err());
+    }
+
+    #[test]
+    fn test_sanitize_for_terminal() {
+        assert_eq!(
+            sanitize_for_terminal("\x1b[2J\x1b[Hgotcha\x07"),
+            "\u{241b}[2J\u{241b}[Hgotcha\u{2407}"
+        );
+        assert_eq!(
+            sanitize_for_terminal("two\nlines\tand\rback"),
+            "two\nlines\tand\u{240d}back"
+        );
+        assert_eq!(
+            sanitize_for_terminal("\u{202e}txt.exe \u{9b}31m\u{7f}"),
+            "\\u{202e}txt.exe \\u{9b}31m\u{2421}"
+        );
+        assert_eq!(sanitize_for_terminal("plain ünïcode ✓"), "plain ünïcode ✓");
+    }
+
+    #[test]
+    fn test_safe_filename() {
+        assert_eq!(safe_filename(Some("photo.png"), "cover.png"), "photo.png");
+        assert_eq!(safe_filename(None, "cover.png"), "cover.png");
+
+        // Path components are dropped, whichever separator is used
+        assert_eq!(safe_filename(Some("../../x.png"), "cover.png"), "x.png");
+        assert_eq!(safe_filename(Some("..\\..\\x.png"), "cover.png"), "x.png");
+        assert_eq!(safe_filename(Some("/etc/passwd"), "cover.png"), "passwd");
+
+        // Names that sanitize to nothing fall back
+        for hostile in [
+            "", "..", "...", "dir/", "\0\0", "CON", "con.png", "LPT1.txt",
+        ] {
+            assert_eq!(safe_filename(Some(hostile), "cover.png"), "cover.png");
+        }
+        assert_eq!(safe_filename(Some("a\0b\x1f.png"), "cover.png"), "ab.png");
+        assert_eq!(safe_filename(Some("what?.png"), "cover.png"), "what.png");
+
+        // Long names are cut on a character boundary and keep their extension
+        let long = format!("{}.png", "é".repeat(300));
+        let name = safe_filename(Some(&long), "cover.png");
+        assert!(name.len() <= MAX_FILENAME_BYTES);
+        assert!(name.ends_with(".png"));
+        assert!(name.trim_end_matches(".png").chars().all(|c| c == 'é'));
+
+        // An overlong "extension" is just part of the name
+        let name = safe_filename(Some(&format!("a.{}", "b".repeat(300))), "cover.png");
+        assert_eq!(name.len(), MAX_FILENAME_BYTES);
+    }
+
+    #[test]
+    fn test_truncate_for_display() {
+        assert_eq!(truncate_for_display("short", 10), ("short", 0));
+        assert_eq!(truncate_for_display("abcdef", 4), ("abcd", 2));
+
+        // The cut never splits a character
+        assert_eq!(truncate_for_display("aé", 2), ("a", 2));
+    }
 }
@@ -0,0 +1,195 @@
+//! Non-fatal problems reported alongside a successful result
+//!
+//! Warnings carry a stable machine-readable code from [`warning_codes`] and a
+//! message for people. Callers that would rather fail than proceed with a
+//! warning can treat any warning as an error.
+//!
+//! The library reports what it noticed while encoding or decoding as
+//! [`StegoWarning`]s, in [`EncodeStats`](crate::encoder::EncodeStats) and the
+//! decoder's detailed results; each converts to a [`Warning`].
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Something the caller should know about a result that still succeeded
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Warning {
+    /// Stable code identifying the kind of warning
+    pub code: String,
+
+    /// Explanation for people
+    pub message: String,
+}
+
+impl Warning {
+    /// Create a warning
+    pub fn new(code: &str, message: impl Into<String>) -> Self {
+        Self {
+            code: code.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.code)
+    }
+}
+
+/// Something an encode or decode noticed that didn't stop it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "code", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum StegoWarning {
+    /// The cover's alpha channel was dropped to embed in its RGB channels
+    AlphaDropped,
+
+    /// The cover's samples were reduced to 8 bits to embed in them
+    BitDepthReduced {
+        /// Bits per sample of the cover
+        bits: u8,
+    },
+
+    /// The message takes most of the cover's capacity, so the payload
+    /// reaches its last pixels
+    NearCapacity {
+        /// Bytes the message took in the image
+        message_bytes: usize,
+        /// Largest message the cover holds
+        max_message_bytes: usize,
+    },
+
+    /// Header flags this version doesn't know were ignored, so the message is
+    /// the payload's raw bytes
+    UnknownFlagsIgnored {
+        /// The unknown flag bits
+        bits: u16,
+    },
+
+    /// The payload is a container, returned as its raw entry bytes
+    RawContainerBytes,
+
+    /// The payload is a stream frame, returned as its raw frame bytes
+    RawStreamFrame,
+
+    /// Only the start of the message was recovered
+    TruncatedPayload {
+        /// Message length declared in the header
+        declared: usize,
+        /// Bytes recovered
+        recovered: usize,
+    },
+}
+
+impl StegoWarning {
+    /// Stable code identifying the kind of warning, one of [`warning_codes`]
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::AlphaDropped => warning_codes::ALPHA_DROPPED,
+            Self::BitDepthReduced { .. } => warning_codes::BIT_DEPTH_REDUCED,
+            Self::NearCapacity { .. } => warning_codes::NEAR_CAPACITY,
+            Self::UnknownFlagsIgnored { .. } => warning_codes::UNKNOWN_FLAGS_IGNORED,
+            Self::RawContainerBytes => warning_codes::RAW_CONTAINER_BYTES,
+            Self::RawStreamFrame => warning_codes::RAW_STREAM_FRAME,
+            Self::TruncatedPayload { .. } => warning_codes::TRUNCATED_PAYLOAD,
+        }
+    }
+}
+
+impl fmt::Display for StegoWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AlphaDropped => write!(
+                f,
+                "the cover's transparency was dropped; embed in the alpha channel to keep it"
+            ),
+            Self::BitDepthReduced { bits } => {
+                write!(f, "the cover's {}-bit samples were reduced to 8 bits", bits)
+            }
+            Self::NearCapacity {
+                message_bytes,
+                max_message_bytes,
+            } => write!(
+                f,
+                "the message fills {} of the cover's {} bytes of capacity",
+                message_bytes, max_message_bytes
+            ),
+            Self::UnknownFlagsIgnored { bits } => write!(
+                f,
+                "ignored unknown header flags {:#06x}; the message is the raw payload bytes",
+                bits
+            ),
+            Self::RawContainerBytes => write!(
+                f,
+                "the image holds a container payload; the message is the raw entry bytes"
+            ),
+            Self::RawStreamFrame => write!(
+                f,
+                "the image holds a stream frame; the message is the raw frame bytes"
+            ),
+            Self::TruncatedPayload {
+                declared,
+                recovered,
+            } => write!(
+                f,
+                "the image appears truncated; recovered {} of {} declared bytes",
+                recovered, declared
+            ),
+        }
+    }
+}
+
+impl From<&StegoWarning> for Warning {
+    fn from(warning: &StegoWarning) -> Self {
+        Self::new(warning.code(), warning.to_string())
+    }
+}
+
+/// Warning codes
+pub mod warning_codes {
+    pub const LOSSY_CHANNEL: &str = "lossy_channel";
+    pub const ALPHA_DROPPED: &str = "alpha_dropped";
+    pub const BIT_DEPTH_REDUCED: &str = "bit_depth_reduced";
+    pub const NEAR_CAPACITY: &str = "near_capacity";
+    pub const UNKNOWN_FLAGS_IGNORED: &str = "unknown_flags_ignored";
+    pub const RAW_CONTAINER_BYTES: &str = "raw_container_bytes";
+    pub const RAW_STREAM_FRAME: &str = "raw_stream_frame";
+    pub const TRUNCATED_PAYLOAD: &str = "truncated_payload";
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codes_match_the_serialized_tags() {
+        let warnings = [
+            StegoWarning::AlphaDropped,
+            StegoWarning::BitDepthReduced { bits: 16 },
+            StegoWarning::NearCapacity {
+                message_bytes: 95,
+                max_message_bytes: 100,
+            },
+            StegoWarning::UnknownFlagsIgnored { bits: 0x40 },
+            StegoWarning::RawContainerBytes,
+            StegoWarning::RawStreamFrame,
+            StegoWarning::TruncatedPayload {
+                declared: 10,
+                recovered: 4,
+            },
+        ];
+        for warning in warnings {
+            let json = serde_json::to_value(&warning).unwrap();
+            assert_eq!(json["code"], warning.code());
+            assert_eq!(
+                serde_json::from_value::<StegoWarning>(json).unwrap(),
+                warning
+            );
+
+            let converted = Warning::from(&warning);
+            assert_eq!(converted.code, warning.code());
+            assert_eq!(converted.message, warning.to_string());
+        }
+    }
+}
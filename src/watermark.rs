@@ -0,0 +1,159 @@
+//! Watermarks: a short identifier repeated across every pixel of an image
+//!
+//! Each copy is a frame made of a 2-byte sync marker, a length byte, the
+//! identifier and a big-endian CRC-32 of the length and identifier, padded
+//! with zeros to a multiple of 3 bytes so every frame covers whole pixels.
+//! Frames are tiled from the first pixel onwards; a decoder finds them by
+//! looking for the sync marker at every pixel, so any crop that keeps a row
+//! segment as long as one frame still yields the identifier.
+
+use crate::error::HideError;
+use crate::header::{get_u32_be, put_u32_be};
+use crate::utils;
+use crate::Result;
+use bitvec::prelude::*;
+use std::collections::HashMap;
+
+/// Longest identifier a watermark can carry, in bytes
+pub const MAX_WATERMARK_ID_LEN: usize = 64;
+
+/// Marker at the start of every watermark frame
+const SYNC_MARKER: [u8; 2] = [0xD5, 0x6A];
+
+/// Bytes of a frame besides the identifier: sync marker, length and CRC
+const FRAME_OVERHEAD: usize = SYNC_MARKER.len() + 1 + 4;
+
+/// Bits carried by one pixel
+const BITS_PER_PIXEL: usize = 3;
+
+/// Identifier recovered from a watermarked image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatermarkResult {
+    /// The watermark identifier
+    pub id: Vec<u8>,
+
+    /// Number of intact copies of the identifier found
+    pub copies: usize,
+}
+
+/// Build the frame repeated across a watermarked image
+///
+/// # Arguments
+/// * `id` - The identifier, 1 to [`MAX_WATERMARK_ID_LEN`] bytes
+///
+/// # Returns
+/// * The frame bytes, a multiple of 3 bytes long
+pub(crate) fn encode_frame(id: &[u8]) -> Result<Vec<u8>> {
+    if id.is_empty() || id.len() > MAX_WATERMARK_ID_LEN {
+        return Err(HideError::InvalidParameters(format!(
+            "Watermark IDs must be 1 to {} bytes, got {}",
+            MAX_WATERMARK_ID_LEN,
+            id.len()
+        )));
+    }
+
+    // Length byte and identifier, covered by the CRC
+    let mut body = Vec::with_capacity(1 + id.len());
+    body.push(id.len() as u8);
+    body.extend_from_slice(id);
+
+    let mut frame = Vec::with_capacity(frame_len(id.len()));
+    frame.extend_from_slice(&SYNC_MARKER);
+    frame.extend_from_slice(&body);
+    put_u32_be(&mut frame, utils::crc32(&body));
+    frame.resize(frame_len(id.len()), 0);
+    Ok(frame)
+}
+
+/// Find every intact frame in the bits extracted from an image
+///
+/// # Arguments
+/// * `bits` - Embedded bits of consecutive pixels, 3 per pixel
+///
+/// # Returns
+/// * The most frequent identifier and its number of copies, or `None` if no
+///   frame passed its CRC
+pub(crate) fn find_frames(bits: &BitSlice<u8, Msb0>) -> Option<WatermarkResult> {
+    let mut counts: HashMap<Vec<u8>, usize> = HashMap::new();
+    let sync_bits = SYNC_MARKER.len() * 8;
+
+    let mut start = 0;
+    while start + (SYNC_MARKER.len() + 1) * 8 <= bits.len() {
+        match read_frame(&bits[start..], sync_bits) {
+            Some((id, len)) => {
+                *counts.entry(id).or_default() += 1;
+                start += len * 8;
+            }
+            None => start += BITS_PER_PIXEL,
+        }
+    }
+
+    counts
+        .into_iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)))
+        .map(|(id, copies)| WatermarkResult { id, copies })
+}
+
+/// Read a frame at the start of a bit slice
+///
+/// # Returns
+/// * The identifier and the frame length in bytes, if a valid frame starts here
+fn read_frame(bits: &BitSlice<u8, Msb0>, sync_bits: usize) -> Option<(Vec<u8>, usize)> {
+    if utils::bits_to_bytes(bits.get(..sync_bits)?) != SYNC_MARKER {
+        return None;
+    }
+
+    let id_len = *utils::bits_to_bytes(bits.get(sync_bits..sync_bits + 8)?).first()? as usize;
+    if id_len == 0 || id_len > MAX_WATERMARK_ID_LEN {
+        return None;
+    }
+
+    // Length byte, identifier and CRC
+    let body_len = 1 + id_len + 4;
+    let body = utils::bits_to_bytes(bits.get(sync_bits..sync_bits + body_len * 8)?);
+
+    let (checked, crc) = body.split_at_checked(1 + id_len)?;
+    if Some(utils::crc32(checked)) != get_u32_be(crc, 0) {
+        return None;
+    }
+
+    Some((checked.get(1..)?.to_vec(), frame_len(id_len)))
+}
+
+/// Length of the frame for an identifier, padded to whole pixels
+fn frame_len(id_len: usize) -> usize {
+    (FRAME_OVERHEAD + id_len).div_ceil(BITS_PER_PIXEL) * BITS_PER_PIXEL
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_round_trip() {
+        let frame = encode_frame(b"asset-42").unwrap();
+        assert_eq!(frame.len() % 3, 0);
+
+        // Frames are found behind arbitrary pixel offsets and garbage
+        let mut bytes = vec![0xFF; 6];
+        bytes.extend_from_slice(&frame);
+        bytes.extend_from_slice(&frame);
+        let result = find_frames(utils::bytes_to_bits(&bytes).as_bitslice()).unwrap();
+        assert_eq!(result.id, b"asset-42");
+        assert_eq!(result.copies, 2);
+    }
+
+    #[test]
+    fn test_corrupted_frames_are_ignored() {
+        let mut frame = encode_frame(b"id").unwrap();
+        frame[4] ^= 0x01;
+        assert!(find_frames(utils::bytes_to_bits(&frame).as_bitslice()).is_none());
+    }
+
+    #[test]
+    fn test_id_length_limits() {
+        assert!(encode_frame(b"").is_err());
+        assert!(encode_frame(&[7; MAX_WATERMARK_ID_LEN]).is_ok());
+        assert!(encode_frame(&[7; MAX_WATERMARK_ID_LEN + 1]).is_err());
+    }
+}
@@ -0,0 +1,108 @@
+use hide_rs::decoder::Decoder;
+use hide_rs::encoder::{Encoder, EncoderConfig};
+use hide_rs::error::HideError;
+use hide_rs::layout::PixelIndex;
+use hide_rs::testing::{image_with_payload, noise_image};
+
+#[test]
+fn test_append_twice_decodes_concatenation() {
+    let mut stego = image_with_payload(40, 40, b"record 1;");
+
+    let encoder = Encoder::new();
+    encoder.append(&mut stego, b"record 2;").unwrap();
+    encoder.append(&mut stego, b"record 3;").unwrap();
+
+    assert_eq!(
+        Decoder::new().decode(&stego).unwrap(),
+        b"record 1;record 2;record 3;"
+    );
+}
+
+#[test]
+fn test_append_to_empty_message() {
+    let mut stego = image_with_payload(20, 20, b"");
+    Encoder::new().append(&mut stego, b"first").unwrap();
+    assert_eq!(Decoder::new().decode(&stego).unwrap(), b"first");
+}
+
+#[test]
+fn test_append_only_touches_header_and_tail() {
+    // A 10-byte message ends at bit 144, so pixels 22..48 hold only its bytes
+    let before = image_with_payload(40, 40, b"0123456789");
+    let mut after = before.clone();
+    Encoder::new().append(&mut after, b"tail").unwrap();
+
+    for index in 22..48 {
+        let (x, y) = (index % 40, index / 40);
+        assert_eq!(
+            before.get_pixel_rgb(x, y).unwrap(),
+            after.get_pixel_rgb(x, y).unwrap(),
+            "pixel {} changed",
+            index
+        );
+    }
+}
+
+#[test]
+fn test_append_on_narrow_image_writes_the_right_rows() {
+    // 9 pixels wide, so the header, message and tail span several rows
+    let before = image_with_payload(9, 50, b"0123456789");
+    let mut after = before.clone();
+    Encoder::new().append(&mut after, b"tail").unwrap();
+    assert_eq!(Decoder::new().decode(&after).unwrap(), b"0123456789tail");
+
+    for index in 22..48 {
+        let (x, y) = PixelIndex(index).to_xy(9);
+        assert_eq!(
+            before.get_pixel_rgb(x.get(), y.get()).unwrap(),
+            after.get_pixel_rgb(x.get(), y.get()).unwrap(),
+            "pixel {} changed",
+            index
+        );
+    }
+}
+
+#[test]
+fn test_append_over_capacity_leaves_payload_intact() {
+    let mut stego = image_with_payload(10, 10, b"existing");
+    let original = stego.inner().as_bytes().to_vec();
+
+    let capacity = Encoder::new().max_message_size(&stego);
+    let result = Encoder::new().append(&mut stego, &vec![b'x'; capacity]);
+    assert!(matches!(result, Err(HideError::MessageTooLarge)));
+
+    assert_eq!(stego.inner().as_bytes(), original);
+    assert_eq!(Decoder::new().decode(&stego).unwrap(), b"existing");
+}
+
+#[test]
+fn test_append_respects_change_budget() {
+    let mut stego = image_with_payload(40, 40, b"short");
+    let encoder = Encoder::with_config(EncoderConfig {
+        max_changed_pixels: Some(0.05),
+        ..Default::default()
+    });
+
+    assert!(matches!(
+        encoder.append(&mut stego, &[b'x'; 100]),
+        Err(HideError::ChangeBudgetExceeded { .. })
+    ));
+    assert_eq!(Decoder::new().decode(&stego).unwrap(), b"short");
+}
+
+#[test]
+fn test_append_requires_plain_payload() {
+    let mut cover = noise_image(20, 20, 7);
+    assert!(matches!(
+        Encoder::new().append(&mut cover, b"data"),
+        Err(HideError::NoMessageFound)
+    ));
+
+    let mut container = Encoder::new()
+        .encode_container(noise_image(40, 40, 7), &[("a".to_string(), b"1".to_vec())])
+        .unwrap();
+    assert!(matches!(
+        Encoder::new().append(&mut container, b"data"),
+        Err(HideError::InvalidParameters(_))
+    ));
+}
@@ -0,0 +1,126 @@
+use hide_rs::decoder::Decoder;
+use hide_rs::encoder::{Encoder, EncoderConfig};
+use hide_rs::error::HideError;
+use hide_rs::img::{FillStyle, StegoImage};
+use hide_rs::mask::PixelMask;
+use hide_rs::padding::PaddingPolicy;
+use hide_rs::testing::{assert_lsb_only_changes, gradient_image, noise_image};
+use image::Rgb;
+use std::process::Command;
+use tempfile::tempdir;
+
+/// The top-left corner of an image, the size of the cover it was grown from
+fn original_region(stego: &StegoImage, cover: &StegoImage) -> StegoImage {
+    StegoImage::from_dynamic_image(stego.inner().crop_imm(0, 0, cover.width(), cover.height()))
+}
+
+#[test]
+fn test_covers_too_small_are_grown_to_fit() {
+    let cover = noise_image(40, 30, 4);
+    let message = b"more than forty by thirty pixels hold ".repeat(16);
+
+    let result = Encoder::new().encode(cover.clone(), &message);
+    assert!(matches!(result, Err(HideError::MessageTooLarge)));
+
+    for fill in [
+        FillStyle::Mirror,
+        FillStyle::Blur,
+        FillStyle::Solid(Rgb([200, 10, 10])),
+    ] {
+        let config = EncoderConfig::default()
+            .auto_extend(fill)
+            .verify_roundtrip(true);
+        let (stego, stats) = Encoder::with_config(config)
+            .encode_with_stats(cover.clone(), &message)
+            .unwrap();
+
+        let (width, height) = stego.dimensions();
+        assert!(width > 40 && height > 30, "{}: {}x{}", fill, width, height);
+        assert!(stats.pixels_used <= (width * height) as usize);
+        assert_lsb_only_changes(&cover, &original_region(&stego, &cover));
+
+        let decoder = Decoder::new();
+        assert_eq!(decoder.decode(&stego).unwrap(), message);
+        assert_eq!(decoder.recorded_dimensions(&stego), Some((40, 30)));
+    }
+}
+
+#[test]
+fn test_covers_that_fit_are_left_alone() {
+    let cover = gradient_image(40, 30);
+    let stego = Encoder::with_config(EncoderConfig::default().auto_extend(FillStyle::Mirror))
+        .encode(cover.clone(), b"fits already")
+        .unwrap();
+
+    assert_eq!(stego.dimensions(), (40, 30));
+    assert_lsb_only_changes(&cover, &stego);
+    // The payload still says the cover may have grown
+    assert_eq!(Decoder::new().recorded_dimensions(&stego), Some((40, 30)));
+}
+
+#[test]
+fn test_growth_respects_padding_and_the_change_budget() {
+    let cover = noise_image(40, 30, 5);
+    let message = vec![0x5a; 1000];
+
+    let config = EncoderConfig {
+        max_changed_pixels: Some(0.25),
+        ..Default::default()
+    }
+    .pad_to(PaddingPolicy::Exact(1200))
+    .auto_extend(FillStyle::Blur);
+    let (stego, stats) = Encoder::with_config(config)
+        .encode_with_stats(cover.clone(), &message)
+        .unwrap();
+
+    let total = stego.width() as usize * stego.height() as usize;
+    assert!(
+        stats.pixels_used * 4 <= total,
+        "{} of {}",
+        stats.pixels_used,
+        total
+    );
+    assert_eq!(Decoder::new().decode(&stego).unwrap(), message);
+    assert_eq!(Decoder::new().recorded_dimensions(&stego), Some((40, 30)));
+}
+
+#[test]
+fn test_masked_encodes_cannot_grow() {
+    let cover = gradient_image(40, 30);
+    let config = EncoderConfig {
+        pixel_mask: Some(PixelMask::from_fn(40, 30, |x, _| x > 35)),
+        ..Default::default()
+    }
+    .auto_extend(FillStyle::Mirror);
+
+    let result = Encoder::with_config(config).encode(cover, &[1; 2000]);
+    assert!(matches!(result, Err(HideError::InvalidParameters(_))));
+}
+
+#[test]
+fn test_cli_auto_extend() {
+    let temp_dir = tempdir().unwrap();
+    let cover_path = temp_dir.path().join("cover.png");
+    let stego_path = temp_dir.path().join("stego.png");
+    gradient_image(20, 20).save(&cover_path).unwrap();
+    let message = "x".repeat(500);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_hide"))
+        .args(["encode", "--auto-extend", "mirror", "--message", &message])
+        .arg("--image")
+        .arg(&cover_path)
+        .arg("--output")
+        .arg(&stego_path)
+        .output()
+        .expect("Failed to run hide");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Cover extended from 20x20 to"));
+
+    let stego = StegoImage::from_file(&stego_path).unwrap();
+    assert_eq!(Decoder::new().decode(&stego).unwrap(), message.as_bytes());
+    assert_eq!(Decoder::new().recorded_dimensions(&stego), Some((20, 20)));
+}
@@ -0,0 +1,179 @@
+use hide_rs::batch::{encode_dir, BatchOptions, BatchReport, RetryPolicy, JOURNAL_FILENAME};
+use hide_rs::decoder::Decoder;
+use hide_rs::encoder::Encoder;
+use hide_rs::error::HideError;
+use hide_rs::img::StegoImage;
+use hide_rs::testing::gradient_image;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+use tempfile::tempdir;
+
+const MESSAGE: &[u8] = b"one message, many covers";
+
+// Helper to fill a directory with covers, two of them in a subdirectory
+fn write_covers(input_dir: &Path) {
+    fs::create_dir_all(input_dir.join("sub")).unwrap();
+    for name in ["a.png", "b.png", "sub/c.png", "sub/d.png"] {
+        gradient_image(24, 24).save(input_dir.join(name)).unwrap();
+    }
+}
+
+// Helper to block an output subdirectory by putting a file where it goes
+fn block(output_dir: &Path, subdir: &str) {
+    fs::create_dir_all(output_dir).unwrap();
+    fs::write(output_dir.join(subdir), b"not a directory").unwrap();
+}
+
+fn run(input_dir: &Path, output_dir: &Path, options: &BatchOptions) -> BatchReport {
+    encode_dir(&Encoder::new(), MESSAGE, input_dir, output_dir, options).unwrap()
+}
+
+fn resume() -> BatchOptions {
+    BatchOptions {
+        resume: true,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_resume_processes_exactly_the_missing_files() {
+    let temp_dir = tempdir().unwrap();
+    let input_dir = temp_dir.path().join("covers");
+    let output_dir = temp_dir.path().join("out");
+    write_covers(&input_dir);
+
+    // The share hiccups: one output directory can't be created
+    block(&output_dir, "sub");
+    let report = run(&input_dir, &output_dir, &BatchOptions::default());
+    assert_eq!(report.encoded, ["a.png", "b.png"]);
+    let failed: Vec<_> = report
+        .failed
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect();
+    assert_eq!(failed, ["sub/c.png", "sub/d.png"]);
+    assert!(report
+        .failed
+        .iter()
+        .all(|(_, e)| matches!(e, HideError::Io(_))));
+
+    fs::remove_file(output_dir.join("sub")).unwrap();
+    let report = run(&input_dir, &output_dir, &resume());
+    assert_eq!(report.skipped, ["a.png", "b.png"]);
+    assert_eq!(report.encoded, ["sub/c.png", "sub/d.png"]);
+    assert!(report.failed.is_empty());
+
+    for name in ["a.png", "b.png", "sub/c.png", "sub/d.png"] {
+        let stego = StegoImage::from_file(output_dir.join(name)).unwrap();
+        assert_eq!(Decoder::new().decode(&stego).unwrap(), MESSAGE);
+    }
+
+    // Nothing is left to do
+    let report = run(&input_dir, &output_dir, &resume());
+    assert!(report.encoded.is_empty());
+    assert_eq!(report.skipped.len(), 4);
+}
+
+#[test]
+fn test_resume_redoes_changed_or_missing_outputs() {
+    let temp_dir = tempdir().unwrap();
+    let input_dir = temp_dir.path().join("covers");
+    let output_dir = temp_dir.path().join("out");
+    write_covers(&input_dir);
+    run(&input_dir, &output_dir, &BatchOptions::default());
+
+    // A replaced cover and a deleted output are both done again
+    gradient_image(30, 30)
+        .save(input_dir.join("b.png"))
+        .unwrap();
+    fs::remove_file(output_dir.join("sub/d.png")).unwrap();
+    let report = run(&input_dir, &output_dir, &resume());
+    assert_eq!(report.encoded, ["b.png", "sub/d.png"]);
+
+    // A different message invalidates every entry
+    let report = encode_dir(
+        &Encoder::new(),
+        b"another message",
+        &input_dir,
+        &output_dir,
+        &resume(),
+    )
+    .unwrap();
+    assert_eq!(report.encoded.len(), 4);
+
+    // Without --resume the journal starts over
+    let report = run(&input_dir, &output_dir, &BatchOptions::default());
+    assert_eq!(report.encoded.len(), 4);
+    let journal = fs::read_to_string(output_dir.join(JOURNAL_FILENAME)).unwrap();
+    assert_eq!(journal.lines().count(), 4);
+}
+
+#[test]
+fn test_retries_outlast_a_transient_failure() {
+    let temp_dir = tempdir().unwrap();
+    let input_dir = temp_dir.path().join("covers");
+    let output_dir = temp_dir.path().join("out");
+    write_covers(&input_dir);
+    block(&output_dir, "sub");
+
+    // The blocker clears well before the backoff runs out
+    let blocker = output_dir.join("sub");
+    let clear = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        fs::remove_file(blocker).unwrap();
+    });
+
+    let options = BatchOptions {
+        retry: RetryPolicy {
+            retries: 6,
+            initial_backoff: Duration::from_millis(50),
+        },
+        ..Default::default()
+    };
+    let report = run(&input_dir, &output_dir, &options);
+    clear.join().unwrap();
+
+    assert!(report.failed.is_empty(), "{:?}", report.failed);
+    assert_eq!(report.encoded.len(), 4);
+}
+
+#[test]
+fn test_cli_resumes_a_failed_batch() {
+    let temp_dir = tempdir().unwrap();
+    let input_dir = temp_dir.path().join("covers");
+    let output_dir = temp_dir.path().join("out");
+    write_covers(&input_dir);
+    block(&output_dir, "sub");
+
+    let encode_batch = |extra: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_hide"))
+            .args(["encode-batch", "--message", "from the cli", "--input-dir"])
+            .arg(&input_dir)
+            .arg("--output-dir")
+            .arg(&output_dir)
+            .args(extra)
+            .output()
+            .expect("Failed to run hide")
+    };
+
+    let output = encode_batch(&[]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Failed: sub/c.png"), "{}", stderr);
+    assert!(stderr.contains("--resume"), "{}", stderr);
+
+    fs::remove_file(output_dir.join("sub")).unwrap();
+    let output = encode_batch(&["--resume", "--retries", "2"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("Encoded: sub/c.png"), "{}", stdout);
+    assert!(!stdout.contains("Encoded: a.png"), "{}", stdout);
+    assert!(
+        stdout.contains("Encoded 2 covers, skipped 2 already done, 0 failed"),
+        "{}",
+        stdout
+    );
+}
@@ -0,0 +1,61 @@
+use hide_rs::cancel::CancellationToken;
+use hide_rs::decoder::{Decoder, DecoderConfig};
+use hide_rs::encoder::{Encoder, EncoderConfig};
+use hide_rs::error::HideError;
+use hide_rs::img::create_rgb_image;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[test]
+fn test_cancel_encode_from_another_thread() {
+    let cover = create_rgb_image(1500, 1500).unwrap();
+    let encoder = Encoder::new();
+    let message = vec![0xA5u8; encoder.max_message_size(&cover)];
+
+    let token = CancellationToken::new();
+    let encoder = Encoder::with_config(EncoderConfig {
+        cancel: Some(token.clone()),
+        ..Default::default()
+    });
+
+    // Cancel shortly after the encode starts
+    let canceller = {
+        let token = token.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            token.cancel();
+            Instant::now()
+        })
+    };
+
+    let result = encoder.encode(cover, &message);
+    let returned_at = Instant::now();
+    let cancelled_at = canceller.join().unwrap();
+
+    assert!(matches!(result, Err(HideError::Cancelled)));
+    assert!(
+        returned_at.duration_since(cancelled_at) < Duration::from_secs(2),
+        "Encode kept running for {:?} after cancellation",
+        returned_at.duration_since(cancelled_at)
+    );
+}
+
+#[test]
+fn test_cancelled_decode_returns_early() {
+    let stego = Encoder::new()
+        .encode(create_rgb_image(200, 200).unwrap(), b"Never read")
+        .unwrap();
+
+    let token = CancellationToken::new();
+    token.cancel();
+    let decoder = Decoder::with_config(DecoderConfig {
+        cancel: Some(token),
+        ..Default::default()
+    });
+
+    assert!(matches!(decoder.decode(&stego), Err(HideError::Cancelled)));
+    assert!(matches!(
+        decoder.decode_lossy(&stego),
+        Err(HideError::Cancelled)
+    ));
+}
@@ -0,0 +1,87 @@
+use hide_rs::encoder::{capacity_breakdown, Encoder, EncoderConfig};
+use hide_rs::testing::gradient_image;
+
+// Configurations with different overheads
+fn configs() -> Vec<EncoderConfig> {
+    let budget = |ratio| EncoderConfig {
+        max_changed_pixels: Some(ratio),
+        ..Default::default()
+    };
+    vec![
+        EncoderConfig::default(),
+        budget(0.1),
+        budget(0.5),
+        budget(1.0),
+    ]
+}
+
+#[test]
+fn test_breakdown_sums_to_raw_capacity() {
+    for (width, height) in [(40, 40), (7, 3), (1, 1), (333, 17)] {
+        let image = gradient_image(width, height);
+        for config in configs() {
+            let breakdown = capacity_breakdown(&image, &config);
+            assert_eq!(
+                breakdown.max_message_bytes + breakdown.overhead_bytes(),
+                breakdown.raw_bytes,
+                "{}x{} with {:?}",
+                width,
+                height,
+                config.max_changed_pixels
+            );
+        }
+    }
+}
+
+#[test]
+fn test_breakdown_matches_what_encode_accepts() {
+    let cover = gradient_image(40, 40);
+
+    for config in configs() {
+        let breakdown = capacity_breakdown(&cover, &config);
+        let encoder = Encoder::with_config(config.clone());
+
+        let fits = vec![b'x'; breakdown.max_message_bytes];
+        assert!(
+            encoder.encode(cover.clone(), &fits).is_ok(),
+            "{:?}",
+            config.max_changed_pixels
+        );
+
+        let too_large = vec![b'x'; breakdown.max_message_bytes + 1];
+        assert!(
+            encoder.encode(cover.clone(), &too_large).is_err(),
+            "{:?}",
+            config.max_changed_pixels
+        );
+    }
+}
+
+#[test]
+fn test_overheads_are_itemized() {
+    // 1600 pixels carry 600 bytes
+    let cover = gradient_image(40, 40);
+
+    let plain = capacity_breakdown(&cover, &EncoderConfig::default());
+    assert_eq!(plain.raw_bytes, 600);
+    assert_eq!(plain.max_message_bytes, 592);
+    assert_eq!(plain.overheads.len(), 1);
+    assert_eq!(plain.overheads[0].name, "header");
+    assert_eq!(plain.overheads[0].bytes, 8);
+
+    // 10% allows 160 pixels, or 60 bytes including the header
+    let budgeted = capacity_breakdown(
+        &cover,
+        &EncoderConfig {
+            max_changed_pixels: Some(0.1),
+            ..Default::default()
+        },
+    );
+    assert_eq!(budgeted.max_message_bytes, 52);
+    assert_eq!(budgeted.overheads[1].name, "change_budget");
+    assert_eq!(budgeted.overheads[1].bytes, 540);
+
+    let json = serde_json::to_value(&budgeted).unwrap();
+    assert_eq!(json["max_message_bytes"], 52);
+    assert_eq!(json["overheads"][0]["name"], "header");
+}
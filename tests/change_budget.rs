@@ -0,0 +1,54 @@
+use hide_rs::decoder::Decoder;
+use hide_rs::encoder::{Encoder, EncoderConfig};
+use hide_rs::error::HideError;
+use hide_rs::img::create_rgb_image;
+use hide_rs::utils::parse_ratio;
+
+fn budget_encoder(budget: &str) -> Encoder {
+    Encoder::with_config(EncoderConfig {
+        max_changed_pixels: Some(parse_ratio(budget).unwrap()),
+        ..Default::default()
+    })
+}
+
+#[test]
+fn test_budget_just_above_requirement() {
+    // A 10 byte message plus header touches 48 of the 10000 pixels (0.48%)
+    let message = b"0123456789";
+    assert_eq!(Encoder::pixels_needed(message.len()), 48);
+
+    let stego = budget_encoder("0.5%")
+        .encode(create_rgb_image(100, 100).unwrap(), message)
+        .unwrap();
+    assert_eq!(Decoder::new().decode(&stego).unwrap(), message);
+
+    // A budget of exactly the requirement is met as well
+    assert!(budget_encoder("0.48%")
+        .encode(create_rgb_image(100, 100).unwrap(), message)
+        .is_ok());
+}
+
+#[test]
+fn test_budget_just_below_requirement_fails_before_modifying() {
+    let message = b"0123456789";
+    let mut cover = create_rgb_image(100, 100).unwrap();
+    let original = cover.clone();
+
+    let result = budget_encoder("0.47%").encode_in_place(&mut cover, message);
+    assert!(matches!(
+        result,
+        Err(HideError::ChangeBudgetExceeded {
+            needed: 48,
+            allowed: 47
+        })
+    ));
+    assert_eq!(cover.inner().as_bytes(), original.inner().as_bytes());
+}
+
+#[test]
+fn test_no_budget_by_default() {
+    // Filling the image far exceeds any small budget but has no limit by default
+    let cover = create_rgb_image(20, 20).unwrap();
+    let message = vec![b'x'; Encoder::new().max_message_size(&cover)];
+    assert!(Encoder::new().encode(cover, &message).is_ok());
+}
@@ -0,0 +1,199 @@
+use hide_rs::decoder::{Decoder, DecoderConfig, UntrustedLimits};
+use hide_rs::encoder::{Encoder, EncoderConfig, Secret};
+use hide_rs::error::HideError;
+use hide_rs::header::HeaderFlags;
+use hide_rs::img::StegoImage;
+use hide_rs::rng::{Kdf, KdfParams, Key, SharedKdf};
+use hide_rs::schedule::ChannelSchedule;
+use hide_rs::testing::{assert_lsb_only_changes, assert_roundtrip, gradient_image};
+use image::{DynamicImage, ImageBuffer, Rgb};
+use sha2::{Digest, Sha256};
+
+/// Fast stand-in for a real key derivation function
+struct HashKdf;
+
+impl Kdf for HashKdf {
+    fn derive(
+        &self,
+        passphrase: &Secret,
+        salt: &[u8],
+        _params: &KdfParams,
+    ) -> hide_rs::Result<Key> {
+        let mut hasher = Sha256::new();
+        hasher.update(passphrase.expose());
+        hasher.update(salt);
+        Ok(Key::new(hasher.finalize().into()))
+    }
+}
+
+fn config(schedule: ChannelSchedule) -> EncoderConfig {
+    EncoderConfig {
+        channel_schedule: schedule,
+        passphrase: Some(Secret::new("open sesame")),
+        kdf: Some(SharedKdf::new(HashKdf)),
+        ..Default::default()
+    }
+}
+
+fn keyed_decoder(passphrase: &str) -> Decoder {
+    Decoder::with_config(DecoderConfig {
+        passphrase: Some(Secret::new(passphrase)),
+        kdf: Some(SharedKdf::new(HashKdf)),
+        ..Default::default()
+    })
+}
+
+/// Create a cover whose LSBs are all clear, so every set LSB comes from the payload
+fn even_image(width: u32, height: u32) -> StegoImage {
+    let img = ImageBuffer::from_pixel(width, height, Rgb([128, 64, 200]));
+    StegoImage::from_dynamic_image(DynamicImage::ImageRgb8(img))
+}
+
+/// Largest difference between the channels' LSB fractions over a payload's pixels
+fn channel_spread(stego: &StegoImage, message_len: usize) -> f64 {
+    let bias = stego
+        .payload_lsb_bias(Encoder::pixels_needed(message_len) as u64)
+        .unwrap();
+    let max = bias.iter().copied().fold(f64::MIN, f64::max);
+    let min = bias.iter().copied().fold(f64::MAX, f64::min);
+    max - min
+}
+
+#[test]
+fn test_rotating_schedule_roundtrip() {
+    let message = b"rotating through red, green and blue".repeat(4);
+    let stego = assert_roundtrip(
+        gradient_image(40, 40),
+        &message,
+        config(ChannelSchedule::Rotating),
+    );
+
+    let decoder = Decoder::new();
+    let header = decoder.read_header(&stego).unwrap();
+    assert!(header.flags.contains(HeaderFlags::ROTATED_CHANNELS));
+    assert_eq!(
+        header.channel_schedule().unwrap(),
+        ChannelSchedule::Rotating
+    );
+
+    // Every decode path follows the schedule
+    assert_eq!(decoder.decode_lossy(&stego).unwrap().data, message);
+    assert_eq!(
+        decoder
+            .decode_untrusted(&stego, UntrustedLimits::default())
+            .unwrap(),
+        message
+    );
+    assert!(decoder.detect(&stego).is_some());
+
+    let entries = vec![("a.txt".to_string(), message.clone())];
+    let stego = Encoder::with_config(config(ChannelSchedule::Rotating))
+        .encode_container(gradient_image(40, 40), &entries)
+        .unwrap();
+    assert_eq!(
+        decoder.decode_entry(&stego, "a.txt").unwrap(),
+        Some(message)
+    );
+}
+
+#[test]
+fn test_keyed_schedule_roundtrip() {
+    let cover = gradient_image(40, 40);
+    let message = b"only readable with the passphrase".repeat(4);
+    let stego = Encoder::with_config(config(ChannelSchedule::Keyed))
+        .encode(cover.clone(), &message)
+        .unwrap();
+    assert_lsb_only_changes(&cover, &stego);
+
+    let decoder = keyed_decoder("open sesame");
+    assert_eq!(decoder.decode(&stego).unwrap(), message);
+    assert_eq!(decoder.decode_lossy(&stego).unwrap().data, message);
+    assert_eq!(
+        decoder
+            .decode_untrusted(&stego, UntrustedLimits::default())
+            .unwrap(),
+        message
+    );
+
+    // The header is readable by anyone, the payload only with the passphrase
+    assert_eq!(
+        Decoder::new().read_header(&stego).unwrap().message_length as usize,
+        message.len()
+    );
+    assert!(matches!(
+        Decoder::new().decode(&stego),
+        Err(HideError::InvalidParameters(_))
+    ));
+    assert_ne!(keyed_decoder("guess").decode(&stego).unwrap(), message);
+}
+
+#[test]
+fn test_fixed_schedule_is_the_default() {
+    let stego = assert_roundtrip(gradient_image(20, 20), b"plain", EncoderConfig::default());
+    let header = Decoder::new().read_header(&stego).unwrap();
+    assert!(header.flags.is_empty());
+    assert_eq!(header.channel_schedule().unwrap(), ChannelSchedule::Fixed);
+}
+
+#[test]
+fn test_schedules_spread_structured_messages_across_channels() {
+    // Every 3-bit chunk of 0xff bytes lands on the same LSB pattern
+    let message = vec![0xffu8; 600];
+    let spread = |schedule| {
+        let stego = Encoder::with_config(config(schedule))
+            .encode(even_image(60, 60), &message)
+            .unwrap();
+        channel_spread(&stego, message.len())
+    };
+
+    let fixed = spread(ChannelSchedule::Fixed);
+    let rotating = spread(ChannelSchedule::Rotating);
+    let keyed = spread(ChannelSchedule::Keyed);
+    assert!(fixed > 0.9, "fixed spread {}", fixed);
+    assert!(rotating < 0.05, "rotating spread {}", rotating);
+    assert!(keyed < 0.1, "keyed spread {}", keyed);
+}
+
+#[test]
+fn test_schedules_with_other_options() {
+    // Overwriting a scheduled payload with a fixed one leaves nothing behind
+    let stego = Encoder::with_config(config(ChannelSchedule::Keyed))
+        .encode(gradient_image(40, 40), &[0xaa; 200])
+        .unwrap();
+    let rewritten = Encoder::with_config(EncoderConfig {
+        overwrite_existing: true,
+        ..Default::default()
+    })
+    .encode(stego, b"short")
+    .unwrap();
+    assert_eq!(Decoder::new().decode(&rewritten).unwrap(), b"short");
+
+    // Appending only knows the fixed channel order, so it refuses scheduled payloads
+    let mut stego = Encoder::with_config(config(ChannelSchedule::Rotating))
+        .encode(gradient_image(40, 40), b"start")
+        .unwrap();
+    assert!(matches!(
+        Encoder::new().append(&mut stego, b" more"),
+        Err(HideError::InvalidParameters(_))
+    ));
+
+    // RGBA mode has its own channel layout
+    let rgba = EncoderConfig {
+        embed_alpha: true,
+        ..config(ChannelSchedule::Rotating)
+    };
+    assert!(matches!(
+        Encoder::with_config(rgba).encode(gradient_image(40, 40), b"x"),
+        Err(HideError::InvalidParameters(_))
+    ));
+
+    // A keyed schedule can't be encoded without its key
+    let keyless = EncoderConfig {
+        channel_schedule: ChannelSchedule::Keyed,
+        ..Default::default()
+    };
+    assert!(matches!(
+        Encoder::with_config(keyless).encode(gradient_image(40, 40), b"x"),
+        Err(HideError::InvalidParameters(_))
+    ));
+}
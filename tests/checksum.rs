@@ -0,0 +1,181 @@
+use hide_rs::decoder::{Decoder, DecoderConfig};
+use hide_rs::encoder::{self, Encoder, EncoderConfig};
+use hide_rs::error::HideError;
+use hide_rs::header::{CHECKSUM_SIZE, EXTENSION_SIZE};
+use hide_rs::img::StegoImage;
+use hide_rs::padding::PaddingPolicy;
+use hide_rs::testing::gradient_image;
+use image::DynamicImage;
+use std::process::Command;
+use tempfile::tempdir;
+
+const MESSAGE: &[u8] = b"Forty bytes of message, give or take one";
+
+fn checksummed() -> EncoderConfig {
+    EncoderConfig::default().checksum(true)
+}
+
+// Helper flipping the red LSB of a pixel the message occupies
+fn flip_payload_bit(image: &mut StegoImage) {
+    let bit = image.get_lsb(8, 1, 0).unwrap();
+    image.set_lsb(8, 1, 0, !bit).unwrap();
+}
+
+#[test]
+fn test_checksum_round_trips() {
+    let (stego, stats) = Encoder::with_config(checksummed())
+        .encode_with_stats(gradient_image(32, 32), MESSAGE)
+        .unwrap();
+
+    let header = Decoder::new().read_header(&stego).unwrap();
+    assert!(header.is_checksummed());
+    assert_eq!(
+        header.message_length as usize,
+        MESSAGE.len() + CHECKSUM_SIZE
+    );
+    assert_eq!(stats.checksum_bytes, CHECKSUM_SIZE);
+    assert_eq!(stats.padding_bytes, 0);
+    assert_eq!(Decoder::new().decode(&stego).unwrap(), MESSAGE);
+
+    let (plain, stats) = Encoder::new()
+        .encode_with_stats(gradient_image(32, 32), MESSAGE)
+        .unwrap();
+    assert!(!Decoder::new().read_header(&plain).unwrap().is_checksummed());
+    assert_eq!(stats.checksum_bytes, 0);
+}
+
+#[test]
+fn test_a_flipped_bit_fails_the_checksum() {
+    let mut stego = Encoder::with_config(checksummed())
+        .encode(gradient_image(32, 32), MESSAGE)
+        .unwrap();
+    flip_payload_bit(&mut stego);
+
+    assert!(matches!(
+        Decoder::new().decode(&stego),
+        Err(HideError::ChecksumMismatch { expected, actual }) if expected != actual
+    ));
+
+    // Skipping the check returns the damaged message
+    let decoder = Decoder::with_config(DecoderConfig {
+        skip_checksum: true,
+        ..Default::default()
+    });
+    let damaged = decoder.decode(&stego).unwrap();
+    assert_eq!(damaged.len(), MESSAGE.len());
+    assert_ne!(damaged, MESSAGE);
+
+    // Without a checksum the same damage goes unnoticed
+    let mut plain = Encoder::new()
+        .encode(gradient_image(32, 32), MESSAGE)
+        .unwrap();
+    flip_payload_bit(&mut plain);
+    assert_ne!(Decoder::new().decode(&plain).unwrap(), MESSAGE);
+}
+
+#[test]
+fn test_checksum_combines_with_other_options() {
+    let rgba_cover = || {
+        StegoImage::from_dynamic_image(DynamicImage::ImageRgba8(
+            gradient_image(64, 64).inner().to_rgba8(),
+        ))
+    };
+    let message = "Meet at the old mill at noon. ".repeat(8).into_bytes();
+    let configs = [
+        checksummed().pad_to(PaddingPolicy::NextPowerOfTwo),
+        checksummed().pad_to(PaddingPolicy::Exact(512)),
+        checksummed().compress(true),
+        checksummed()
+            .compress(true)
+            .pad_to(PaddingPolicy::FillCapacity),
+        EncoderConfig {
+            record_dimensions: true,
+            embed_alpha: true,
+            ..checksummed()
+        },
+    ];
+    for config in configs {
+        let padding = config.padding;
+        let stego = Encoder::with_config(config.verify_roundtrip(true))
+            .encode(rgba_cover(), &message)
+            .unwrap();
+        let header = Decoder::new().read_header(&stego).unwrap();
+        assert!(header.is_checksummed());
+        if let Some(PaddingPolicy::Exact(bytes)) = padding {
+            assert_eq!(header.message_length as usize, bytes);
+        }
+        assert_eq!(Decoder::new().decode(&stego).unwrap(), message);
+    }
+
+    let stego = Encoder::with_config(checksummed().pixel_key(b"sum"))
+        .encode(gradient_image(32, 32), MESSAGE)
+        .unwrap();
+    assert_eq!(Decoder::with_key(b"sum").decode(&stego).unwrap(), MESSAGE);
+}
+
+#[test]
+fn test_checksum_counts_against_capacity() {
+    let cover = gradient_image(32, 32);
+    let encoder = Encoder::with_config(checksummed());
+    let capacity = encoder.max_message_size(&cover);
+    assert_eq!(
+        capacity + EXTENSION_SIZE + CHECKSUM_SIZE,
+        Encoder::new().max_message_size(&cover)
+    );
+
+    let breakdown = encoder::capacity_breakdown(&cover, &checksummed());
+    assert_eq!(breakdown.max_message_bytes, capacity);
+    assert!(breakdown
+        .overheads
+        .iter()
+        .any(|overhead| overhead.name == "checksum" && overhead.bytes == CHECKSUM_SIZE));
+
+    let message = vec![b'c'; capacity];
+    let stego = encoder.encode(cover.clone(), &message).unwrap();
+    assert_eq!(Decoder::new().decode(&stego).unwrap(), message);
+    assert!(matches!(
+        encoder.encode(cover, &[message, vec![b'c']].concat()),
+        Err(HideError::MessageTooLarge)
+    ));
+}
+
+#[test]
+fn test_cli_checksum() {
+    let temp_dir = tempdir().unwrap();
+    let cover = temp_dir.path().join("cover.png");
+    gradient_image(32, 32).save(&cover).unwrap();
+    let output = temp_dir.path().join("stego.png");
+    let message = String::from_utf8(MESSAGE.to_vec()).unwrap();
+
+    let encode = Command::new(env!("CARGO_BIN_EXE_hide"))
+        .args(["encode", "--checksum", "-m", &message, "-i"])
+        .arg(&cover)
+        .arg("-o")
+        .arg(&output)
+        .output()
+        .unwrap();
+    assert!(
+        encode.status.success(),
+        "{}",
+        String::from_utf8_lossy(&encode.stderr)
+    );
+
+    let mut stego = StegoImage::from_file(&output).unwrap();
+    flip_payload_bit(&mut stego);
+    stego.save(&output).unwrap();
+
+    let decode = Command::new(env!("CARGO_BIN_EXE_hide"))
+        .args(["decode", "-i"])
+        .arg(&output)
+        .output()
+        .unwrap();
+    assert!(!decode.status.success());
+    assert!(String::from_utf8_lossy(&decode.stderr).contains("checksum"));
+
+    let decode = Command::new(env!("CARGO_BIN_EXE_hide"))
+        .args(["decode", "--skip-checksum", "-i"])
+        .arg(&output)
+        .output()
+        .unwrap();
+    assert!(decode.status.success());
+}
@@ -0,0 +1,65 @@
+use hide_rs::encoder::Encoder;
+use hide_rs::testing::gradient_image;
+use std::path::Path;
+use std::process::Command;
+use tempfile::tempdir;
+
+/// Hide a message in a new image and return the decode command's stdout
+fn decode_output(dir: &Path, message: &[u8], extra_args: &[&str]) -> String {
+    let stego_path = dir.join("stego.png");
+    Encoder::new()
+        .encode(gradient_image(200, 200), message)
+        .unwrap()
+        .save(&stego_path)
+        .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_hide"))
+        .args(["decode", "--image"])
+        .arg(&stego_path)
+        .args(extra_args)
+        .output()
+        .expect("Failed to run hide");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn test_escape_sequences_are_neutralized() {
+    let temp_dir = tempdir().unwrap();
+    let message = b"\x1b[2J\x1b[1;1HAll clear\x07\rnothing to see";
+
+    let stdout = decode_output(temp_dir.path(), message, &[]);
+    assert!(!stdout.contains('\x1b'), "{:?}", stdout);
+    assert!(!stdout.contains('\x07'), "{:?}", stdout);
+    assert!(!stdout.contains('\r'), "{:?}", stdout);
+    assert!(stdout.contains("\u{241b}[2J\u{241b}[1;1HAll clear\u{2407}\u{240d}nothing to see"));
+
+    // The opt-out prints the bytes as they are
+    let stdout = decode_output(temp_dir.path(), message, &["--no-sanitize"]);
+    assert!(stdout.contains("\x1b[2J\x1b[1;1HAll clear\x07\rnothing to see"));
+}
+
+#[test]
+fn test_long_messages_are_cut_short() {
+    let temp_dir = tempdir().unwrap();
+    let message = "a".repeat(5000);
+
+    let stdout = decode_output(temp_dir.path(), message.as_bytes(), &[]);
+    assert!(stdout.contains(&"a".repeat(4096)));
+    assert!(!stdout.contains(&"a".repeat(4097)));
+    assert!(stdout.contains("\u{2026} (904 more bytes, use --output)"));
+
+    let stdout = decode_output(temp_dir.path(), message.as_bytes(), &["--no-sanitize"]);
+    assert!(stdout.contains(&message));
+    assert!(!stdout.contains("more bytes"));
+
+    // Binary messages shown as hex are cut at the same length
+    let binary = vec![0xff; 5000];
+    let stdout = decode_output(temp_dir.path(), &binary, &[]);
+    assert_eq!(stdout.matches("ff ").count(), 4096);
+    assert!(stdout.contains("(904 more bytes, use --output)"));
+}
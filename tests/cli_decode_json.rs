@@ -0,0 +1,103 @@
+use hide_rs::encoder::Encoder;
+use hide_rs::manifest::sha256_hex;
+use hide_rs::testing::gradient_image;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use tempfile::tempdir;
+
+/// Hide a message in a new image in `dir` and return its path
+fn stego_file(dir: &Path, message: &[u8]) -> PathBuf {
+    let stego_path = dir.join("stego.png");
+    Encoder::new()
+        .encode(gradient_image(200, 200), message)
+        .unwrap()
+        .save(&stego_path)
+        .unwrap();
+    stego_path
+}
+
+/// Run `hide decode --json` on an image with extra arguments
+fn decode_json(stego_path: &Path, extra_args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_hide"))
+        .args(["decode", "--json", "--image"])
+        .arg(stego_path)
+        .args(extra_args)
+        .output()
+        .expect("Failed to run hide")
+}
+
+fn parse(output: &Output) -> serde_json::Value {
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+#[test]
+fn test_small_messages_are_inlined() {
+    let temp_dir = tempdir().unwrap();
+    let stego_path = stego_file(temp_dir.path(), b"inline me");
+
+    let json = parse(&decode_json(&stego_path, &[]));
+    assert_eq!(json["inline"], true);
+    assert_eq!(json["length"], 9);
+    assert_eq!(json["message"], "inline me");
+    assert_eq!(json["binary_message"], "aW5saW5lIG1l");
+    assert!(json.get("payload_path").is_none());
+}
+
+#[test]
+fn test_payload_file_replaces_the_inline_message() {
+    let temp_dir = tempdir().unwrap();
+    let message = vec![0xa5; 3000];
+    let stego_path = stego_file(temp_dir.path(), &message);
+    let payload_path = temp_dir.path().join("payload.bin");
+
+    let json = parse(&decode_json(
+        &stego_path,
+        &["--payload-file", payload_path.to_str().unwrap()],
+    ));
+    assert_eq!(json["inline"], false);
+    assert_eq!(json["length"], 3000);
+    assert_eq!(json["payload_path"], payload_path.to_str().unwrap());
+    assert_eq!(json["payload_sha256"], sha256_hex(&message));
+    assert!(json.get("binary_message").is_none());
+    assert_eq!(fs::read(&payload_path).unwrap(), message);
+
+    // An existing file is left alone
+    let output = decode_json(
+        &stego_path,
+        &["--payload-file", payload_path.to_str().unwrap()],
+    );
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("already exists"));
+    assert_eq!(fs::read(&payload_path).unwrap(), message);
+}
+
+#[test]
+fn test_large_messages_switch_to_a_file_next_to_the_image() {
+    let temp_dir = tempdir().unwrap();
+    let message = b"over the threshold".repeat(10);
+    let stego_path = stego_file(temp_dir.path(), &message);
+
+    let json = parse(&decode_json(&stego_path, &["--max-inline-bytes", "100"]));
+    assert_eq!(json["inline"], false);
+    assert_eq!(json["length"], 180);
+    assert_eq!(json["payload_sha256"], sha256_hex(&message));
+
+    let payload_path = PathBuf::from(json["payload_path"].as_str().unwrap());
+    assert_eq!(payload_path.parent().unwrap(), temp_dir.path());
+    assert!(payload_path
+        .file_name()
+        .unwrap()
+        .to_string_lossy()
+        .starts_with("stego.payload-"));
+    assert_eq!(fs::read(&payload_path).unwrap(), message);
+
+    // At the threshold the message is still inlined
+    let json = parse(&decode_json(&stego_path, &["--max-inline-bytes", "180"]));
+    assert_eq!(json["inline"], true);
+}
@@ -0,0 +1,77 @@
+use hide_rs::testing::gradient_image;
+use std::process::Command;
+use tempfile::tempdir;
+
+#[test]
+fn test_intended_channel_warns_or_fails() {
+    let temp_dir = tempdir().unwrap();
+    let cover_path = temp_dir.path().join("cover.png");
+    gradient_image(40, 40).save(&cover_path).unwrap();
+
+    let run = |channel: &str, strict: bool| {
+        let output_path = temp_dir.path().join(format!("{}-{}.png", channel, strict));
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_hide"));
+        cmd.arg("encode")
+            .arg("--image")
+            .arg(&cover_path)
+            .arg("--message")
+            .arg("Secret")
+            .arg("--output")
+            .arg(&output_path)
+            .arg("--intended-channel")
+            .arg(channel);
+        if strict {
+            cmd.arg("--strict");
+        }
+        let output = cmd.output().expect("Failed to run hide");
+        (
+            output.status.success(),
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+            output_path.exists(),
+        )
+    };
+
+    let (success, stderr, written) = run("jpeg", false);
+    assert!(success && written);
+    assert!(stderr.contains("Warning:"), "{}", stderr);
+
+    let (success, stderr, written) = run("png", false);
+    assert!(success && written);
+    assert!(!stderr.contains("Warning:"), "{}", stderr);
+
+    // Strict mode refuses before writing anything
+    let (success, stderr, written) = run("jpeg", true);
+    assert!(!success && !written);
+    assert!(stderr.contains("Error:"), "{}", stderr);
+
+    let (success, _, written) = run("print", false);
+    assert!(success && written);
+}
+
+#[test]
+fn test_lossy_output_is_refused_before_encoding() {
+    let temp_dir = tempdir().unwrap();
+    let cover_path = temp_dir.path().join("cover.png");
+    gradient_image(40, 40).save(&cover_path).unwrap();
+    let output_path = temp_dir.path().join("stego.jpg");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_hide"))
+        .arg("encode")
+        .arg("--image")
+        .arg(&cover_path)
+        .arg("--message")
+        .arg("Secret")
+        .arg("--output")
+        .arg(&output_path)
+        .output()
+        .expect("Failed to run hide");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Lossy output format rejected"),
+        "{}",
+        stderr
+    );
+    assert!(!output_path.exists());
+}
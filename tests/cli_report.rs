@@ -0,0 +1,51 @@
+use hide_rs::encoder::Encoder;
+use hide_rs::img::create_rgb_image;
+use hide_rs::report::load_report;
+use std::process::Command;
+use tempfile::tempdir;
+
+#[test]
+fn test_decode_runs_merge_into_one_report() {
+    // Create a temporary directory for the test
+    let temp_dir = tempdir().unwrap();
+    let stego_path = temp_dir.path().join("stego.png");
+    let report_path = temp_dir.path().join("report.json");
+
+    // Create a stego image to decode
+    let stego_image = Encoder::new()
+        .encode(create_rgb_image(20, 20).unwrap(), b"Report me")
+        .unwrap();
+    stego_image.save(&stego_path).unwrap();
+
+    // Run two commands against the same report path
+    for extra in [None, Some("--hex")] {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_hide"));
+        cmd.arg("decode")
+            .arg("--image")
+            .arg(&stego_path)
+            .arg("--report")
+            .arg(&report_path);
+        if let Some(flag) = extra {
+            cmd.arg(flag);
+        }
+
+        let status = cmd.status().expect("Failed to run hide");
+        assert!(status.success());
+    }
+
+    // Both runs must be present in the merged report
+    let report = load_report(&report_path).expect("Report does not match the schema");
+    assert_eq!(report.hide_rs_version, hide_rs::VERSION);
+    assert_eq!(report.runs.len(), 2);
+
+    for run in &report.runs {
+        assert_eq!(run.command, "decode");
+        assert!(run.finished_at >= run.started_at);
+        assert_eq!(run.files.len(), 1);
+
+        let file = &run.files[0];
+        assert!(file.detected);
+        assert_eq!(file.header.unwrap().message_length, 9);
+    }
+    assert!(report.runs[1].arguments.contains(&"--hex".to_string()));
+}
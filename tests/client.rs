@@ -0,0 +1,227 @@
+#![cfg(feature = "client")]
+
+use actix_web::{App, HttpServer};
+use hide_rs::api::handlers::ServerConfig;
+use hide_rs::api::models::{EncodeOptions, MessageTooLargeDetails, UnsupportedFeatureDetails};
+use hide_rs::api::routes::configure_routes;
+use hide_rs::client::{ClientError, ErrorCode, HideClient, ImageSource};
+use hide_rs::decoder::Decoder;
+use hide_rs::img::StegoImage;
+use hide_rs::testing::gradient_image;
+use reqwest::StatusCode;
+use std::path::Path;
+use tempfile::{tempdir, TempDir};
+use uuid::Uuid;
+
+mod common;
+
+use common::create_state_with;
+
+/// Start a server on a free local port and return a client for it
+fn start_server(config: ServerConfig) -> (HideClient, TempDir) {
+    let temp_dir = tempdir().unwrap();
+    let state = create_state_with(temp_dir.path(), config);
+
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(state.clone())
+            .configure(configure_routes)
+    })
+    .workers(1)
+    .bind(("127.0.0.1", 0))
+    .unwrap();
+    let addr = server.addrs()[0];
+    actix_web::rt::spawn(server.run());
+
+    (HideClient::new(format!("http://{}", addr)), temp_dir)
+}
+
+/// Save a gradient cover as a PNG file
+fn cover_file(dir: &Path, width: u32, height: u32) -> std::path::PathBuf {
+    let path = dir.join("cover.png");
+    gradient_image(width, height).save(&path).unwrap();
+    path
+}
+
+#[actix_web::test]
+async fn test_encode_download_decode_roundtrip() {
+    let (client, _uploads) = start_server(ServerConfig::default());
+    let covers = tempdir().unwrap();
+    let cover = cover_file(covers.path(), 40, 40);
+    let message = b"\x00binary\xffmessage";
+
+    let encoded = client
+        .encode(cover.as_path(), message, &EncodeOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(encoded.response.status, "success");
+    assert_eq!(
+        encoded.response.metadata.embedded_message_bytes,
+        Some(message.len())
+    );
+
+    let stego = client.download(encoded.response.image_id).await.unwrap();
+    assert_eq!(
+        Decoder::new()
+            .decode(&StegoImage::from_bytes(&stego).unwrap())
+            .unwrap(),
+        message
+    );
+
+    let decoded = client.decode(stego).await.unwrap();
+    assert_eq!(decoded.message, message);
+    assert_eq!(decoded.response.message_length, message.len());
+}
+
+#[actix_web::test]
+async fn test_identical_encode_is_replayed() {
+    let (client, _uploads) = start_server(ServerConfig::default());
+    let covers = tempdir().unwrap();
+    let cover = cover_file(covers.path(), 40, 40);
+
+    let first = client
+        .encode(cover.clone(), b"once", &EncodeOptions::default())
+        .await
+        .unwrap();
+    let second = client
+        .encode(cover, b"once", &EncodeOptions::default())
+        .await
+        .unwrap();
+    assert!(!first.replayed);
+    assert!(second.replayed);
+    assert_eq!(first.response.image_id, second.response.image_id);
+}
+
+#[actix_web::test]
+async fn test_large_decoded_message_is_downloaded() {
+    let (client, _uploads) = start_server(ServerConfig {
+        max_inline_decode_bytes: 16,
+        ..Default::default()
+    });
+    let covers = tempdir().unwrap();
+    let cover = cover_file(covers.path(), 60, 60);
+    let message = vec![b'x'; 500];
+
+    let encoded = client
+        .encode(cover.as_path(), &message, &EncodeOptions::default())
+        .await
+        .unwrap();
+    let stego = client.download(encoded.response.image_id).await.unwrap();
+
+    let decoded = client.decode(ImageSource::png(stego)).await.unwrap();
+    assert!(!decoded.response.inline);
+    assert_eq!(decoded.message, message);
+}
+
+#[actix_web::test]
+async fn test_error_responses_map_to_typed_codes() {
+    let (client, _uploads) = start_server(ServerConfig::default());
+    let covers = tempdir().unwrap();
+    let cover = cover_file(covers.path(), 10, 10);
+
+    let err = client
+        .encode(cover.as_path(), &[7u8; 200], &EncodeOptions::default())
+        .await
+        .unwrap_err();
+    let ClientError::Api {
+        status,
+        code,
+        request_id,
+        ..
+    } = err
+    else {
+        panic!("expected an API error, got {:?}", err);
+    };
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(request_id.is_some());
+    assert!(matches!(
+        code,
+        ErrorCode::MessageTooLarge(Some(MessageTooLargeDetails {
+            message_bytes: 200,
+            ..
+        }))
+    ));
+
+    // A cover without a payload starts with a version byte no release has used
+    let err = client.decode(cover.as_path()).await.unwrap_err();
+    assert!(matches!(
+        err.code(),
+        Some(ErrorCode::UnsupportedFeature(Some(
+            UnsupportedFeatureDetails { flags: 0, .. }
+        )))
+    ));
+
+    let err = client.download(Uuid::new_v4()).await.unwrap_err();
+    assert_eq!(err.code().map(ErrorCode::as_str), Some("not_found"));
+
+    let err = client
+        .decode(covers.path().join("missing.png"))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, ClientError::Io { .. }));
+}
+
+#[actix_web::test]
+async fn test_download_is_checked_against_the_content_hash() {
+    let (client, uploads) = start_server(ServerConfig::default());
+    let covers = tempdir().unwrap();
+    let cover = cover_file(covers.path(), 40, 40);
+
+    let encoded = client
+        .encode(cover.as_path(), b"intact", &EncodeOptions::default())
+        .await
+        .unwrap();
+    let image_id = encoded.response.image_id;
+    let content_sha256 = encoded.response.metadata.content_sha256.unwrap();
+    let stego = client.download(image_id).await.unwrap();
+    assert_eq!(hide_rs::manifest::sha256_hex(&stego), content_sha256);
+
+    // The server refuses to serve a corrupted file
+    let stored_path = uploads.path().join(format!("{}.png", image_id));
+    let mut stored = std::fs::read(&stored_path).unwrap();
+    stored[0] ^= 0xff;
+    std::fs::write(&stored_path, stored).unwrap();
+
+    let err = client.download(image_id).await.unwrap_err();
+    assert_eq!(err.code(), Some(&ErrorCode::Internal));
+}
+
+#[actix_web::test]
+async fn test_signed_download_urls() {
+    let (client, _uploads) = start_server(ServerConfig {
+        url_signing_secret: Some("client test secret".to_string()),
+        ..Default::default()
+    });
+    let covers = tempdir().unwrap();
+    let cover = cover_file(covers.path(), 40, 40);
+
+    let encoded = client
+        .encode(cover.as_path(), b"signed", &EncodeOptions::default())
+        .await
+        .unwrap();
+    let stego = client
+        .download_url(&encoded.response.download_url)
+        .await
+        .unwrap();
+    let stego = StegoImage::from_bytes(&stego).unwrap();
+    assert_eq!(Decoder::new().decode(&stego).unwrap(), b"signed");
+
+    // Building the URL from the image ID leaves out the signature
+    let err = client
+        .download(encoded.response.image_id)
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Some(&ErrorCode::InvalidSignature));
+}
+
+#[actix_web::test]
+async fn test_capabilities() {
+    let (client, _uploads) = start_server(ServerConfig {
+        decode_max_output_bytes: 4096,
+        ..Default::default()
+    });
+
+    let capabilities = client.capabilities().await.unwrap();
+    assert_eq!(capabilities.version, hide_rs::VERSION);
+    assert_eq!(capabilities.limits.unwrap().decode_max_output_bytes, 4096);
+}
@@ -0,0 +1,136 @@
+use hide_rs::decoder::Decoder;
+use hide_rs::error::HideError;
+use hide_rs::img::{ColorMetadata, StegoImage};
+use hide_rs::testing::image_with_payload;
+use hide_rs::utils::crc32;
+use image::{ImageFormat, Rgb};
+use std::io::Cursor;
+use tempfile::tempdir;
+
+/// Length of the PNG signature plus the IHDR chunk
+const AFTER_IHDR: usize = 8 + 25;
+
+// Encode an image as PNG
+fn png_bytes(image: &StegoImage) -> Vec<u8> {
+    let mut buffer = Cursor::new(Vec::new());
+    image
+        .inner()
+        .write_to(&mut buffer, ImageFormat::Png)
+        .unwrap();
+    buffer.into_inner()
+}
+
+// Insert chunks right after IHDR, as color-managing tools do
+fn with_chunks(png: &[u8], chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+    let mut out = png[..AFTER_IHDR].to_vec();
+    for (chunk_type, data) in chunks {
+        out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        let start = out.len();
+        out.extend_from_slice(*chunk_type);
+        out.extend_from_slice(data);
+        let crc = crc32(&out[start..]);
+        out.extend_from_slice(&crc.to_be_bytes());
+    }
+    out.extend_from_slice(&png[AFTER_IHDR..]);
+    out
+}
+
+// An iCCP chunk: profile name, compression method and a (fake) compressed profile
+fn iccp_chunk() -> Vec<u8> {
+    let mut data = b"Display P3\0\0".to_vec();
+    data.extend_from_slice(&[0x78, 0x9c, 0x03, 0x00, 0x00, 0x00, 0x00, 0x01]);
+    data
+}
+
+// Nudge every channel the way a colorspace conversion would
+fn color_convert(image: &StegoImage) -> StegoImage {
+    let mut converted = image.clone();
+    for y in 0..image.height() {
+        for x in 0..image.width() {
+            let pixel = image.get_pixel_rgb(x, y).unwrap();
+            let shifted = pixel.0.map(|c| (c as f32 * 0.93 + 9.0) as u8);
+            converted.set_pixel_rgb(x, y, Rgb(shifted)).unwrap();
+        }
+    }
+    converted
+}
+
+#[test]
+fn test_plain_png_has_no_color_profile() {
+    let stego = image_with_payload(30, 30, b"plain");
+    let loaded = StegoImage::from_bytes(&png_bytes(&stego)).unwrap();
+    assert!(!loaded.has_color_profile());
+    assert_eq!(Decoder::new().decode(&loaded).unwrap(), b"plain");
+}
+
+#[test]
+fn test_iccp_chunk_is_detected_from_file() {
+    let stego = image_with_payload(30, 30, b"profiled");
+    let fixture = with_chunks(&png_bytes(&stego), &[(b"iCCP", &iccp_chunk())]);
+
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("profiled.png");
+    std::fs::write(&path, &fixture).unwrap();
+
+    let loaded = StegoImage::from_file(&path).unwrap();
+    assert!(loaded.has_color_profile());
+    assert!(loaded.color_metadata().icc_profile);
+    assert!(!loaded.color_metadata().gamma);
+
+    // A profile alone leaves the pixels, and so the payload, intact
+    assert_eq!(Decoder::new().decode(&loaded).unwrap(), b"profiled");
+}
+
+#[test]
+fn test_failed_decode_points_at_color_conversion() {
+    let stego = color_convert(&image_with_payload(30, 30, b"converted"));
+    let fixture = with_chunks(&png_bytes(&stego), &[(b"iCCP", &iccp_chunk())]);
+    let loaded = StegoImage::from_bytes(&fixture).unwrap();
+
+    let err = Decoder::new().decode(&loaded).unwrap_err();
+    assert!(matches!(err, HideError::ColorConverted(_)), "{:?}", err);
+    assert!(err
+        .to_string()
+        .ends_with("this image appears to have been color-converted, use the original file"));
+
+    // Without the chunks there is nothing to point at
+    let bare = StegoImage::from_bytes(&png_bytes(&stego)).unwrap();
+    assert!(!matches!(
+        Decoder::new().decode(&bare),
+        Err(HideError::ColorConverted(_))
+    ));
+}
+
+#[test]
+fn test_gamma_and_chromaticity_chunks() {
+    let png = png_bytes(&image_with_payload(10, 10, b""));
+    let gamma = 45455u32.to_be_bytes();
+    let fixture = with_chunks(&png, &[(b"gAMA", &gamma), (b"cHRM", &[0; 32])]);
+
+    assert_eq!(
+        ColorMetadata::from_png_bytes(&fixture),
+        ColorMetadata {
+            icc_profile: false,
+            gamma: true,
+            chromaticities: true,
+        }
+    );
+}
+
+#[test]
+fn test_chunk_walker_tolerates_bad_input() {
+    let signature = b"\x89PNG\r\n\x1a\n";
+    for bytes in [
+        &b""[..],
+        &signature[..],
+        &[&signature[..], &[0xFF; 6]].concat(),
+        &[&signature[..], &[0xFF, 0xFF, 0xFF, 0xFF], b"iCCP"].concat(),
+        b"GIF89a",
+    ] {
+        ColorMetadata::from_png_bytes(bytes);
+    }
+
+    // A chunk whose length runs past the end is still recognized
+    let truncated = [&signature[..], &[0, 0, 1, 0], b"iCCP", &[0; 4]].concat();
+    assert!(ColorMetadata::from_png_bytes(&truncated).icc_profile);
+}
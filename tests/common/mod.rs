@@ -0,0 +1,121 @@
+//! Helpers shared by the actix integration tests
+//!
+//! Each test file includes this with `mod common;` and uses only some of the
+//! helpers, so the rest are allowed to go unused there.
+#![allow(dead_code)]
+
+use actix_web::{test, web};
+use hide_rs::api::handlers::{AppState, ServerConfig};
+use std::fs;
+use std::path::Path;
+
+/// Boundary of the multipart forms built here
+pub const BOUNDARY: &str = "------------------------abcdef1234567890";
+
+/// Create the application state for an upload directory
+pub fn create_state(upload_dir: &Path) -> web::Data<AppState> {
+    create_state_with(upload_dir, ServerConfig::default())
+}
+
+/// Create the application state for an upload directory and the rest of a configuration
+pub fn create_state_with(upload_dir: &Path, config: ServerConfig) -> web::Data<AppState> {
+    web::Data::new(AppState::new(ServerConfig {
+        upload_dir: upload_dir.to_string_lossy().to_string(),
+        ..config
+    }))
+}
+
+/// Content type of the multipart forms built here
+pub fn content_type() -> String {
+    format!("multipart/form-data; boundary={}", BOUNDARY)
+}
+
+/// Build a multipart body from raw field contents
+///
+/// Fields given a filename are sent as file uploads.
+pub fn multipart(fields: &[(&str, Option<&str>, &[u8])]) -> Vec<u8> {
+    let mut body = Vec::new();
+    for (name, filename, data) in fields {
+        let disposition = match filename {
+            Some(filename) => format!(
+                "Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n\
+                 Content-Type: image/png\r\n",
+                name, filename
+            ),
+            None => format!("Content-Disposition: form-data; name=\"{}\"\r\n", name),
+        };
+        body.extend_from_slice(format!("--{}\r\n{}\r\n", BOUNDARY, disposition).as_bytes());
+        body.extend_from_slice(data);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{}--\r\n", BOUNDARY).as_bytes());
+    body
+}
+
+/// Build a multipart form uploading file contents, followed by text fields
+///
+/// # Returns
+/// * The body and its content type
+pub fn upload_multipart(
+    file_field: &str,
+    filename: &str,
+    data: &[u8],
+    fields: &[(&str, &str)],
+) -> (Vec<u8>, String) {
+    let mut parts = vec![(file_field, Some(filename), data)];
+    parts.extend(
+        fields
+            .iter()
+            .map(|(name, value)| (*name, None, value.as_bytes())),
+    );
+    (multipart(&parts), content_type())
+}
+
+/// Build a multipart form uploading a file under its own name, followed by text fields
+pub fn file_multipart(file_field: &str, path: &Path, fields: &[(&str, &str)]) -> (Vec<u8>, String) {
+    let data = fs::read(path).unwrap();
+    let filename = path.file_name().unwrap().to_str().unwrap();
+    upload_multipart(file_field, filename, &data, fields)
+}
+
+/// Build an encode form hiding a message in a cover image
+pub fn create_encode_multipart(cover_path: &Path, message: &str) -> (Vec<u8>, String) {
+    file_multipart("cover_image", cover_path, &[("message", message)])
+}
+
+/// Build a decode form for a stego image, with extra text fields
+pub fn create_decode_multipart(image_path: &Path, fields: &[(&str, &str)]) -> (Vec<u8>, String) {
+    file_multipart("stego_image", image_path, fields)
+}
+
+/// Build a request posting a multipart form to an API path
+pub fn post_multipart(uri: &str, (body, content_type): (Vec<u8>, String)) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri(uri)
+        .insert_header(("content-type", content_type))
+        .set_payload(body)
+}
+
+/// Build an encode request hiding a message, followed by extra text fields
+///
+/// The cover is uploaded from `cover_path`; without one the fields have to
+/// pick a cover, e.g. with `cover_id`.
+pub fn encode_request(
+    cover_path: Option<&Path>,
+    message: &str,
+    fields: &[(&str, &str)],
+) -> test::TestRequest {
+    let mut form = vec![("message", message)];
+    form.extend_from_slice(fields);
+    let form = match cover_path {
+        Some(cover_path) => file_multipart("cover_image", cover_path, &form),
+        None => {
+            let parts: Vec<_> = form
+                .iter()
+                .map(|(name, value)| (*name, None, value.as_bytes()))
+                .collect();
+            (multipart(&parts), content_type())
+        }
+    };
+    post_multipart("/api/v1/encode", form)
+}
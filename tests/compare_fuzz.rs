@@ -0,0 +1,60 @@
+use hide_rs::img::{self, StegoImage};
+use image::{DynamicImage, RgbImage};
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+
+/// Random image with its color type picked at random too
+fn random_image(rng: &mut StdRng, width: u32, height: u32) -> StegoImage {
+    let mut bytes = vec![0u8; width as usize * height as usize * 3];
+    rng.fill_bytes(&mut bytes);
+    let rgb = DynamicImage::ImageRgb8(RgbImage::from_raw(width, height, bytes).unwrap());
+    StegoImage::from_dynamic_image(match rng.random_range(0..4) {
+        0 => rgb,
+        1 => DynamicImage::ImageRgba8(rgb.to_rgba8()),
+        2 => DynamicImage::ImageLuma8(rgb.to_luma8()),
+        _ => DynamicImage::ImageRgb16(rgb.to_rgb16()),
+    })
+}
+
+#[test]
+fn test_block_counts_sum_to_the_global_count() {
+    let mut rng = StdRng::seed_from_u64(1995);
+    for _ in 0..200 {
+        let (width, height) = (rng.random_range(1..40), rng.random_range(1..40));
+        let a = random_image(&mut rng, width, height);
+        let b = random_image(&mut rng, width, height);
+        let block_size = (rng.random_range(1..20), rng.random_range(1..20));
+
+        let stats = img::compare(&a, &b, Some(block_size)).unwrap();
+        let blocks = stats.blocks.as_ref().unwrap();
+        assert_eq!(
+            blocks.counts.len(),
+            blocks.columns as usize * blocks.rows as usize
+        );
+        assert_eq!(blocks.columns, width.div_ceil(block_size.0));
+        assert_eq!(blocks.rows, height.div_ceil(block_size.1));
+        assert_eq!(blocks.counts.iter().sum::<u64>(), stats.changed_bits);
+
+        // The breakdown doesn't change the global statistics
+        let plain = img::compare(&a, &b, None).unwrap();
+        assert_eq!(plain.changed_bits, stats.changed_bits);
+        assert_eq!(plain.changed_pixels, stats.changed_pixels);
+    }
+}
+
+#[test]
+fn test_mismatched_images_are_errors_not_panics() {
+    let mut rng = StdRng::seed_from_u64(1996);
+    for _ in 0..100 {
+        // Small ranges, so some pairs do match
+        let size_a = (rng.random_range(1..6), rng.random_range(1..6));
+        let size_b = (rng.random_range(1..6), rng.random_range(1..6));
+        let a = random_image(&mut rng, size_a.0, size_a.1);
+        let b = random_image(&mut rng, size_b.0, size_b.1);
+        let block_size = (rng.random_range(0..8), rng.random_range(0..8));
+
+        let result = img::compare(&a, &b, Some(block_size));
+        let valid = a.dimensions() == b.dimensions() && block_size.0 > 0 && block_size.1 > 0;
+        assert_eq!(result.is_ok(), valid);
+    }
+}
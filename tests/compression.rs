@@ -0,0 +1,187 @@
+use hide_rs::compression::LENGTH_PREFIX_SIZE;
+use hide_rs::decoder::{Decoder, UntrustedLimits};
+use hide_rs::encoder::{Encoder, EncoderConfig, Secret};
+use hide_rs::error::HideError;
+use hide_rs::img::StegoImage;
+use hide_rs::padding::PaddingPolicy;
+use hide_rs::testing::{gradient_image, noise_image};
+use image::DynamicImage;
+use std::process::Command;
+use tempfile::tempdir;
+
+fn compressing() -> Encoder {
+    Encoder::with_config(EncoderConfig::default().compress(true))
+}
+
+fn rgba_cover() -> StegoImage {
+    StegoImage::from_dynamic_image(DynamicImage::ImageRgba8(
+        gradient_image(64, 64).inner().to_rgba8(),
+    ))
+}
+
+fn text(repeats: usize) -> Vec<u8> {
+    "Meet at the old mill at noon; bring the maps. "
+        .repeat(repeats)
+        .into_bytes()
+}
+
+#[test]
+fn test_text_is_stored_compressed() {
+    let message = text(20);
+    let (stego, stats) = compressing()
+        .encode_with_stats(gradient_image(64, 64), &message)
+        .unwrap();
+
+    let header = Decoder::new().read_header(&stego).unwrap();
+    assert!(header.is_compressed());
+    assert_eq!(header.message_length as usize, stats.stored_message_bytes);
+    assert_eq!(stats.message_bytes, message.len());
+    assert!(stats.stored_message_bytes < message.len() / 4);
+    assert_eq!(Decoder::new().decode(&stego).unwrap(), message);
+
+    // Without the option nothing changes
+    let (plain, stats) = Encoder::new()
+        .encode_with_stats(gradient_image(64, 64), &message)
+        .unwrap();
+    assert!(!Decoder::new().read_header(&plain).unwrap().is_compressed());
+    assert_eq!(stats.stored_message_bytes, message.len());
+}
+
+#[test]
+fn test_compression_fits_messages_over_capacity() {
+    let capacity = Encoder::max_message_size_for_dimensions(32, 32);
+    let message = text(capacity / 40);
+    assert!(message.len() > capacity);
+
+    assert!(matches!(
+        Encoder::new().encode(gradient_image(32, 32), &message),
+        Err(HideError::MessageTooLarge)
+    ));
+    let stego = compressing()
+        .encode(gradient_image(32, 32), &message)
+        .unwrap();
+    assert_eq!(Decoder::new().decode(&stego).unwrap(), message);
+}
+
+#[test]
+fn test_incompressible_messages_are_stored_as_is() {
+    let message = noise_image(12, 12, 3).inner().as_bytes().to_vec();
+    let (stego, stats) = compressing()
+        .encode_with_stats(gradient_image(64, 64), &message)
+        .unwrap();
+
+    let header = Decoder::new().read_header(&stego).unwrap();
+    assert!(!header.is_compressed());
+    assert_eq!(header.message_length as usize, message.len());
+    assert_eq!(stats.stored_message_bytes, message.len());
+    assert_eq!(Decoder::new().decode(&stego).unwrap(), message);
+
+    // Too short for the length prefix to pay off
+    let stego = compressing().encode(gradient_image(16, 16), b"hi").unwrap();
+    assert!(!Decoder::new().read_header(&stego).unwrap().is_compressed());
+    assert_eq!(Decoder::new().decode(&stego).unwrap(), b"hi");
+}
+
+#[test]
+fn test_compression_combines_with_other_options() {
+    let message = text(10);
+    let configs = [
+        EncoderConfig::default().pad_to(PaddingPolicy::NextPowerOfTwo),
+        EncoderConfig {
+            record_dimensions: true,
+            ..Default::default()
+        },
+        EncoderConfig {
+            embed_alpha: true,
+            ..Default::default()
+        },
+        EncoderConfig::default().pixel_key(b"compressed"),
+    ];
+    for config in configs {
+        let key = config.pixel_key.clone();
+        let stego = Encoder::with_config(config.compress(true).verify_roundtrip(true))
+            .encode(rgba_cover(), &message)
+            .unwrap();
+        let decoder = match key {
+            Some(_) => Decoder::with_key(b"compressed"),
+            None => Decoder::new(),
+        };
+        assert!(decoder.read_header(&stego).unwrap().is_compressed());
+        assert_eq!(decoder.decode(&stego).unwrap(), message);
+    }
+}
+
+#[test]
+fn test_encrypted_messages_are_not_compressed() {
+    let message = text(10);
+    let passphrase = Secret::new("mill");
+    let stego = compressing()
+        .encode_encrypted(gradient_image(64, 64), &message, &passphrase)
+        .unwrap();
+
+    assert!(!Decoder::new().read_header(&stego).unwrap().is_compressed());
+    assert_eq!(
+        Decoder::new()
+            .decode_encrypted(&stego, &passphrase)
+            .unwrap(),
+        message
+    );
+}
+
+#[test]
+fn test_untrusted_decodes_limit_the_inflated_size() {
+    let message = vec![b'z'; 50_000];
+    let stego = compressing()
+        .encode(gradient_image(64, 64), &message)
+        .unwrap();
+    let stored = Decoder::new().read_header(&stego).unwrap().message_length as usize;
+    assert!(stored > LENGTH_PREFIX_SIZE && stored < 1000);
+
+    let limits = UntrustedLimits {
+        max_output_bytes: 10_000,
+        ..Default::default()
+    };
+    assert!(matches!(
+        Decoder::new().decode_untrusted(&stego, limits),
+        Err(HideError::OutputLimitExceeded {
+            declared_length: 50_000,
+            limit: 10_000,
+        })
+    ));
+    assert_eq!(
+        Decoder::new()
+            .decode_untrusted(&stego, UntrustedLimits::default())
+            .unwrap(),
+        message
+    );
+}
+
+#[test]
+fn test_cli_compress() {
+    let temp_dir = tempdir().unwrap();
+    let cover = temp_dir.path().join("cover.png");
+    gradient_image(32, 32).save(&cover).unwrap();
+    let output = temp_dir.path().join("stego.png");
+    let message = String::from_utf8(text(10)).unwrap();
+
+    let encode = Command::new(env!("CARGO_BIN_EXE_hide"))
+        .args(["encode", "--compress", "-m", &message, "-i"])
+        .arg(&cover)
+        .arg("-o")
+        .arg(&output)
+        .output()
+        .unwrap();
+    assert!(
+        encode.status.success(),
+        "{}",
+        String::from_utf8_lossy(&encode.stderr)
+    );
+
+    let decode = Command::new(env!("CARGO_BIN_EXE_hide"))
+        .args(["decode", "-i"])
+        .arg(&output)
+        .output()
+        .unwrap();
+    assert!(decode.status.success());
+    assert!(String::from_utf8_lossy(&decode.stdout).contains(&message));
+}
@@ -0,0 +1,42 @@
+use hide_rs::decoder::Decoder;
+use hide_rs::encoder::{Encoder, EncoderConfig};
+use hide_rs::padding::PaddingPolicy;
+use hide_rs::rng::SharedRng;
+use hide_rs::schedule::ChannelSchedule;
+use hide_rs::testing::noise_image;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::sync::Arc;
+use std::thread;
+
+#[test]
+fn test_one_encoder_serves_many_threads() {
+    // Padding draws from the shared generator, so threads contend for it
+    let encoder = Arc::new(Encoder::with_config(
+        EncoderConfig {
+            channel_schedule: ChannelSchedule::Rotating,
+            rng: Some(SharedRng::new(StdRng::seed_from_u64(8))),
+            ..Default::default()
+        }
+        .pad_to(PaddingPolicy::NextPowerOfTwo)
+        .verify_roundtrip(true),
+    ));
+    let decoder = Arc::new(Decoder::new());
+
+    let workers: Vec<_> = (0..8u64)
+        .map(|worker| {
+            let encoder = Arc::clone(&encoder);
+            let decoder = Arc::clone(&decoder);
+            thread::spawn(move || {
+                let message = format!("message from worker {}", worker).repeat(worker as usize + 1);
+                let cover = noise_image(96, 64, worker);
+                let stego = encoder.encode(cover, message.as_bytes()).unwrap();
+                assert_eq!(decoder.decode(&stego).unwrap(), message.as_bytes());
+            })
+        })
+        .collect();
+
+    for worker in workers {
+        worker.join().unwrap();
+    }
+}
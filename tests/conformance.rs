@@ -0,0 +1,77 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use hide_rs::conformance;
+use hide_rs::decoder::Decoder;
+use hide_rs::header::{get_u32_be, VersionedParser};
+use hide_rs::img::StegoImage;
+use image::{DynamicImage, RgbImage};
+
+#[test]
+fn test_published_vectors_pass() {
+    let results = conformance::verify_all();
+    assert_eq!(results.len(), 4);
+    for result in results {
+        assert!(
+            result.passed(),
+            "{} failed: {:?}",
+            result.name,
+            result.failures
+        );
+    }
+}
+
+#[test]
+fn test_vectors_match_their_definitions() {
+    // Catches vector files edited by hand instead of regenerated
+    assert_eq!(
+        conformance::vectors().unwrap(),
+        conformance::generate_all().unwrap()
+    );
+}
+
+#[test]
+fn test_tampered_vector_fails() {
+    let mut vector = conformance::vectors().unwrap().remove(0);
+    vector.message = "AAAA".to_string();
+
+    let result = conformance::verify(&vector);
+    assert!(!result.passed());
+    assert!(result
+        .failures
+        .iter()
+        .any(|failure| failure.contains("Stego pixels differ")));
+}
+
+#[test]
+fn test_vectors_store_integers_big_endian() {
+    // Assembles each length byte by byte, so a target whose native byte order
+    // or word size differs still checks against the published layout
+    for vector in conformance::vectors().unwrap() {
+        let header = BASE64.decode(&vector.header).unwrap();
+        let message = BASE64.decode(&vector.message).unwrap();
+        let length = header[1..5]
+            .iter()
+            .fold(0u64, |length, &byte| length * 256 + byte as u64);
+        assert_eq!(length, message.len() as u64, "{}", vector.name);
+        assert_eq!(
+            get_u32_be(&header, 1),
+            Some(message.len() as u32),
+            "{}",
+            vector.name
+        );
+
+        let parsed = VersionedParser::parse(&header).unwrap();
+        assert_eq!(parsed.to_bytes(), header, "{}", vector.name);
+
+        let stego_rgb = BASE64.decode(&vector.stego_rgb).unwrap();
+        let stego = StegoImage::from_dynamic_image(DynamicImage::ImageRgb8(
+            RgbImage::from_raw(vector.width, vector.height, stego_rgb).unwrap(),
+        ));
+        assert_eq!(
+            Decoder::new().read_header(&stego).unwrap(),
+            parsed,
+            "{}",
+            vector.name
+        );
+        assert_eq!(Decoder::new().decode(&stego).unwrap(), message);
+    }
+}
@@ -0,0 +1,43 @@
+use hide_rs::decoder::Decoder;
+use hide_rs::error::HideError;
+use hide_rs::img::{self, StegoImage};
+use hide_rs::testing::image_with_payload;
+use image::ImageFormat;
+use std::path::Path;
+use tempfile::tempdir;
+
+#[test]
+fn test_convert_preserves_payload() {
+    let temp_dir = tempdir().unwrap();
+    let stego_path = temp_dir.path().join("stego.png");
+    create_stego_image(&stego_path, b"Survives conversion");
+
+    for (name, format) in [
+        ("stego.bmp", ImageFormat::Bmp),
+        ("stego.tiff", ImageFormat::Tiff),
+    ] {
+        let output_path = temp_dir.path().join(name);
+        img::convert(&stego_path, &output_path, format).unwrap();
+
+        let converted = StegoImage::from_file(&output_path).unwrap();
+        let message = Decoder::new().decode(&converted).unwrap();
+        assert_eq!(message, b"Survives conversion", "Payload lost in {}", name);
+    }
+}
+
+#[test]
+fn test_convert_rejects_jpeg() {
+    let temp_dir = tempdir().unwrap();
+    let stego_path = temp_dir.path().join("stego.png");
+    let output_path = temp_dir.path().join("stego.jpg");
+    create_stego_image(&stego_path, b"Would be destroyed");
+
+    let result = img::convert(&stego_path, &output_path, ImageFormat::Jpeg);
+    assert!(matches!(result, Err(HideError::LossyFormatRejected(_))));
+    assert!(!output_path.exists());
+}
+
+// Helper to create a stego image with some texture
+fn create_stego_image(path: &Path, message: &[u8]) {
+    image_with_payload(40, 40, message).save(path).unwrap();
+}
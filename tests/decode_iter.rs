@@ -0,0 +1,158 @@
+use hide_rs::decoder::Decoder;
+use hide_rs::depth::EmbeddingDepth;
+use hide_rs::encoder::{Encoder, EncoderConfig, Secret};
+use hide_rs::error::HideError;
+use hide_rs::img::StegoImage;
+use hide_rs::padding::PaddingPolicy;
+use hide_rs::schedule::ChannelSchedule;
+use hide_rs::testing::noise_image;
+
+// Helper making a message whose bits vary throughout
+fn message(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 37 + 11) as u8).collect()
+}
+
+#[test]
+fn test_iterator_matches_decode() {
+    let configs = [
+        EncoderConfig::default(),
+        EncoderConfig::default().checksum(true),
+        EncoderConfig {
+            record_dimensions: true,
+            ..Default::default()
+        },
+        EncoderConfig {
+            channel_schedule: ChannelSchedule::Rotating,
+            ..Default::default()
+        }
+        .checksum(true),
+    ];
+    for len in [0, 1, 2, 3, 100, 1000] {
+        for config in configs.clone() {
+            let stego = Encoder::with_config(config)
+                .encode(noise_image(64, 64, len as u64), &message(len))
+                .unwrap();
+            let decoder = Decoder::new();
+            let iter = decoder.decode_iter(&stego).unwrap();
+            assert_eq!(iter.size_hint().0, len);
+            let collected: Vec<u8> = iter.collect::<Result<_, _>>().unwrap();
+            assert_eq!(collected, decoder.decode(&stego).unwrap());
+            assert_eq!(collected, message(len));
+        }
+    }
+
+    let stego = Encoder::with_config(EncoderConfig::default().pixel_key(b"shuffled"))
+        .encode(noise_image(64, 64, 1), &message(500))
+        .unwrap();
+    let decoder = Decoder::with_key(b"shuffled");
+    let collected: Vec<u8> = decoder
+        .decode_iter(&stego)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(collected, message(500));
+}
+
+#[test]
+fn test_size_hint_counts_down() {
+    let stego = Encoder::new()
+        .encode(noise_image(32, 32, 2), &message(10))
+        .unwrap();
+    let decoder = Decoder::new();
+    let mut iter = decoder.decode_iter(&stego).unwrap();
+    assert_eq!(iter.size_hint(), (10, Some(10)));
+    iter.nth(3).unwrap().unwrap();
+    assert_eq!(iter.size_hint(), (6, Some(6)));
+    assert_eq!(iter.by_ref().count(), 6);
+    assert_eq!(iter.size_hint(), (0, Some(0)));
+    assert!(iter.next().is_none());
+}
+
+#[test]
+fn test_dropping_the_iterator_early() {
+    let stego = Encoder::new()
+        .encode(noise_image(64, 64, 3), &message(1000))
+        .unwrap();
+    let decoder = Decoder::new();
+
+    let prefix: Vec<u8> = decoder
+        .decode_iter(&stego)
+        .unwrap()
+        .take(10)
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(prefix, message(10));
+
+    // A partly read iterator leaves nothing behind; the image decodes again
+    let mut iter = decoder.decode_iter(&stego).unwrap();
+    assert_eq!(iter.next().unwrap().unwrap(), message(1)[0]);
+    assert_eq!(decoder.decode(&stego).unwrap(), message(1000));
+}
+
+#[test]
+fn test_checksum_mismatch_ends_the_iteration() {
+    let mut stego = Encoder::with_config(EncoderConfig::default().checksum(true))
+        .encode(noise_image(32, 32, 4), &message(50))
+        .unwrap();
+    // Flip an LSB among the message pixels
+    let mut pixel = stego.get_pixel_rgb(10, 1).unwrap();
+    pixel.0[0] ^= 1;
+    stego.set_pixel_rgb(10, 1, pixel).unwrap();
+
+    let decoder = Decoder::new();
+    let items: Vec<_> = decoder.decode_iter(&stego).unwrap().collect();
+    assert_eq!(items.len(), 51);
+    assert!(items[..50].iter().all(Result::is_ok));
+    assert!(matches!(items[50], Err(HideError::ChecksumMismatch { .. })));
+    assert!(matches!(
+        decoder.decode(&stego),
+        Err(HideError::ChecksumMismatch { .. })
+    ));
+}
+
+#[test]
+fn test_payloads_needing_the_whole_message_are_refused() {
+    let cover = noise_image(64, 64, 5);
+    let configs = [
+        EncoderConfig::default().compress(true),
+        EncoderConfig::default().embedding_depth(EmbeddingDepth::Two),
+        EncoderConfig {
+            padding: Some(PaddingPolicy::Exact(400)),
+            ..Default::default()
+        },
+        EncoderConfig {
+            embed_alpha: true,
+            ..Default::default()
+        },
+    ];
+    for config in configs {
+        // Repetitive enough to be stored compressed
+        let stego = Encoder::with_config(config)
+            .encode(cover.clone(), &[b'a'; 200])
+            .unwrap();
+        assert!(matches!(
+            Decoder::new().decode_iter(&stego),
+            Err(HideError::InvalidParameters(_))
+        ));
+    }
+
+    let stego = Encoder::new()
+        .encode_encrypted(cover.clone(), b"sealed", &Secret::new("key"))
+        .unwrap();
+    assert!(matches!(
+        Decoder::new().decode_iter(&stego),
+        Err(HideError::EncryptedPayload)
+    ));
+}
+
+#[test]
+fn test_truncated_image_fails_before_iterating() {
+    let stego = Encoder::new()
+        .encode(noise_image(64, 64, 6), &message(1000))
+        .unwrap();
+    let top = StegoImage::from_dynamic_image(stego.inner().crop_imm(0, 0, 64, 16));
+    assert!(matches!(
+        Decoder::new().decode_iter(&top),
+        Err(HideError::TruncatedPayload { declared: 1000, .. })
+    ));
+}
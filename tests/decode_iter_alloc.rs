@@ -0,0 +1,70 @@
+//! Checks that iterating over a payload allocates nothing
+//!
+//! Kept apart from the other decode tests because the counting allocator
+//! replaces the global one for the whole test binary.
+
+use hide_rs::decoder::Decoder;
+use hide_rs::encoder::{Encoder, EncoderConfig};
+use hide_rs::testing::noise_image;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Counts the allocations made on threads that enable counting
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static COUNTING: Cell<bool> = const { Cell::new(false) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if COUNTING.with(Cell::get) {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        }
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if COUNTING.with(Cell::get) {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        }
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+#[test]
+fn test_iterating_a_large_payload_allocates_nothing() {
+    // About 370 KB, filling most of a 1000x1000 cover
+    let message: Vec<u8> = (0..370_000).map(|i| (i * 31 % 251) as u8).collect();
+    let stego = Encoder::with_config(EncoderConfig::default().checksum(true))
+        .encode(noise_image(1000, 1000, 9), &message)
+        .unwrap();
+    let decoder = Decoder::new();
+    let mut iter = decoder.decode_iter(&stego).unwrap();
+
+    COUNTING.with(|counting| counting.set(true));
+    let mut mismatches = 0usize;
+    let mut count = 0usize;
+    for byte in iter.by_ref() {
+        let Ok(byte) = byte else {
+            break;
+        };
+        mismatches += usize::from(message.get(count) != Some(&byte));
+        count += 1;
+    }
+    COUNTING.with(|counting| counting.set(false));
+
+    assert_eq!(ALLOCATIONS.load(Ordering::Relaxed), 0);
+    assert_eq!(count, message.len());
+    assert_eq!(mismatches, 0);
+}
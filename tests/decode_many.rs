@@ -0,0 +1,113 @@
+use hide_rs::decoder::{self, DecodeManyOptions, DecoderConfig};
+use hide_rs::encoder::Encoder;
+use hide_rs::error::HideError;
+use hide_rs::testing::gradient_image;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tempfile::tempdir;
+
+// Helper filling a directory with stego images, a plain cover and a corrupt file
+fn mixed_dir(dir: &Path) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    for i in 0..6 {
+        let path = dir.join(format!("stego_{}.png", i));
+        Encoder::new()
+            .encode(gradient_image(24, 24), format!("message {}", i).as_bytes())
+            .unwrap()
+            .save(&path)
+            .unwrap();
+        paths.push(path);
+    }
+
+    let cover = dir.join("cover.png");
+    gradient_image(24, 24).save(&cover).unwrap();
+    let corrupt = dir.join("corrupt.png");
+    fs::write(&corrupt, b"not an image at all").unwrap();
+
+    // Interleave the failures with the stego images
+    paths.insert(2, cover);
+    paths.insert(5, corrupt);
+    paths
+}
+
+#[test]
+fn test_results_keep_the_input_order() {
+    let temp_dir = tempdir().unwrap();
+    let mut paths = mixed_dir(temp_dir.path());
+    paths.push(temp_dir.path().join("missing.png"));
+
+    let results = decoder::decode_many(
+        &paths,
+        DecodeManyOptions {
+            parallelism: 3,
+            ..Default::default()
+        },
+    );
+    assert_eq!(results.len(), paths.len());
+    let returned: Vec<_> = results.iter().map(|(path, _)| path.clone()).collect();
+    assert_eq!(returned, paths);
+
+    let mut message = 0;
+    for (path, result) in &results {
+        let name = path.file_name().unwrap().to_str().unwrap();
+        match name {
+            "cover.png" => assert!(result.is_err()),
+            "corrupt.png" => assert!(matches!(result, Err(HideError::Image(_)))),
+            "missing.png" => assert!(matches!(result, Err(HideError::Io(_)))),
+            _ => {
+                assert_eq!(
+                    result.as_ref().unwrap(),
+                    format!("message {}", message).as_bytes()
+                );
+                message += 1;
+            }
+        }
+    }
+    assert_eq!(message, 6);
+}
+
+#[test]
+fn test_a_small_memory_budget_still_decodes_everything() {
+    let temp_dir = tempdir().unwrap();
+    let paths = mixed_dir(temp_dir.path());
+
+    // Every file is larger than the budget, so they are decoded one at a time
+    let results = decoder::decode_many(
+        &paths,
+        DecodeManyOptions {
+            parallelism: 4,
+            max_in_flight_bytes: 1,
+            config: DecoderConfig::default(),
+        },
+    );
+    assert_eq!(
+        results.iter().filter(|(_, result)| result.is_ok()).count(),
+        6
+    );
+
+    assert!(decoder::decode_many::<PathBuf>(&[], DecodeManyOptions::default()).is_empty());
+}
+
+#[test]
+fn test_cli_decode_batch() {
+    let temp_dir = tempdir().unwrap();
+    mixed_dir(temp_dir.path());
+
+    let output = Command::new(env!("CARGO_BIN_EXE_hide"))
+        .args(["decode-batch", "--parallelism", "2", "--input-dir"])
+        .arg(temp_dir.path())
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<_> = stdout.lines().collect();
+    assert_eq!(lines.first(), Some(&"stego_0.png: message 0"));
+    assert_eq!(lines.get(5), Some(&"stego_5.png: message 5"));
+    assert_eq!(lines.last(), Some(&"Decoded 6 images, 2 failed"));
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Failed: corrupt.png"));
+    assert!(stderr.contains("Failed: cover.png"));
+}
@@ -0,0 +1,101 @@
+use hide_rs::img::{create_rgb_image, StegoImage};
+use hide_rs::testing::gradient_image;
+use hide_rs::Encoder;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use tempfile::tempdir;
+
+// Helper writing the LSBs of a header declaring `length` bytes into a cover,
+// followed by `message`
+fn crafted_stego(width: u32, height: u32, length: u32, message: &[u8]) -> StegoImage {
+    let mut raw = vec![1];
+    raw.extend_from_slice(&length.to_be_bytes());
+    raw.extend_from_slice(&[0, 0, 0]);
+    raw.extend_from_slice(message);
+    Encoder::new()
+        .encode_message(create_rgb_image(width, height).unwrap(), &raw)
+        .unwrap()
+}
+
+// Helper running `hide decode` on an image without a terminal on stdin
+fn decode(image: &Path, output: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_hide"))
+        .args(["decode", "-i"])
+        .arg(image)
+        .arg("-o")
+        .arg(output)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_default_limit_stops_before_extracting() {
+    let temp_dir = tempdir().unwrap();
+    let image = temp_dir.path().join("huge.png");
+    let output = temp_dir.path().join("out.bin");
+
+    // Declares 200 MB; the image holds nowhere near that, but the limit is
+    // checked before the payload is read
+    crafted_stego(64, 64, 200 << 20, b"").save(&image).unwrap();
+
+    let result = decode(&image, &output, &[]);
+    assert!(!result.status.success());
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(
+        stderr.contains("declares a 209715200-byte message"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("104857600-byte output limit"));
+    assert!(stderr.contains("--yes"));
+    assert!(!output.exists());
+}
+
+#[test]
+fn test_messages_over_the_limit_need_confirmation() {
+    let temp_dir = tempdir().unwrap();
+    let image = temp_dir.path().join("stego.png");
+    let output = temp_dir.path().join("out.bin");
+    let message = vec![b'm'; 3000];
+    crafted_stego(100, 100, message.len() as u32, &message)
+        .save(&image)
+        .unwrap();
+
+    let refused = decode(&image, &output, &["--max-output-bytes", "1000"]);
+    assert!(!refused.status.success());
+    assert!(String::from_utf8_lossy(&refused.stderr).contains("declares a 3000-byte message"));
+    assert!(!output.exists());
+
+    let confirmed = decode(&image, &output, &["--max-output-bytes", "1000", "--yes"]);
+    assert!(
+        confirmed.status.success(),
+        "{}",
+        String::from_utf8_lossy(&confirmed.stderr)
+    );
+    assert_eq!(std::fs::read(&output).unwrap(), message);
+
+    let raised = decode(&image, &output, &["--max-output-bytes", "3000"]);
+    assert!(raised.status.success());
+}
+
+#[test]
+fn test_display_output_is_not_limited() {
+    let temp_dir = tempdir().unwrap();
+    let image = temp_dir.path().join("stego.png");
+    Encoder::new()
+        .encode(gradient_image(32, 32), b"shown, not written")
+        .unwrap()
+        .save(&image)
+        .unwrap();
+
+    let result = Command::new(env!("CARGO_BIN_EXE_hide"))
+        .args(["decode", "--max-output-bytes", "4", "-i"])
+        .arg(&image)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert!(result.status.success());
+    assert!(String::from_utf8_lossy(&result.stdout).contains("shown, not written"));
+}
@@ -0,0 +1,197 @@
+use hide_rs::decoder::{Decoder, DecoderConfig};
+use hide_rs::encoder::{Encoder, EncoderConfig};
+use hide_rs::error::HideError;
+use hide_rs::img::StegoImage;
+use hide_rs::testing::gradient_image;
+use image::imageops::FilterType;
+use std::process::Command;
+use tempfile::tempdir;
+
+// Helper to encode a message while recording the cover's dimensions
+fn encode_recording(width: u32, height: u32, message: &[u8]) -> StegoImage {
+    Encoder::with_config(EncoderConfig {
+        record_dimensions: true,
+        ..Default::default()
+    })
+    .encode(gradient_image(width, height), message)
+    .unwrap()
+}
+
+// Helper to shrink an image the way a resizing proxy would
+fn shrink(image: &StegoImage, width: u32, height: u32) -> StegoImage {
+    StegoImage::from_dynamic_image(
+        image
+            .inner()
+            .resize_exact(width, height, FilterType::Triangle),
+    )
+}
+
+#[test]
+fn test_resized_image_reports_both_sizes() {
+    let stego = encode_recording(64, 48, b"sent through a proxy");
+    let resized = shrink(&stego, 40, 30);
+
+    let decoder = Decoder::with_config(DecoderConfig {
+        expected_dimensions: Some((64, 48)),
+        ..Default::default()
+    });
+    let err = decoder.decode(&resized).unwrap_err();
+    assert!(
+        matches!(
+            &err,
+            HideError::DimensionsChanged {
+                encoded: (64, 48),
+                actual: (40, 30),
+                ..
+            }
+        ),
+        "{:?}",
+        err
+    );
+    let message = err.to_string();
+    assert!(message.contains("40x30"), "{}", message);
+    assert!(message.contains("64x48"), "{}", message);
+
+    // Partial decodes are diagnosed the same way
+    assert!(matches!(
+        decoder.decode_lossy(&resized),
+        Err(HideError::DimensionsChanged { .. })
+    ));
+
+    // Without an expectation the resized copy carries nothing to compare with
+    assert!(!matches!(
+        Decoder::new().decode(&resized),
+        Err(HideError::DimensionsChanged { .. }) | Ok(_)
+    ));
+}
+
+#[test]
+fn test_recorded_dimensions_diagnose_a_cropped_image() {
+    // 40x40 holds 592 payload bytes; keeping 10 rows leaves 142
+    let message: Vec<u8> = (0..=255u8).cycle().take(400).collect();
+    let stego = encode_recording(40, 40, &message);
+    let cropped = StegoImage::from_dynamic_image(stego.inner().crop_imm(0, 0, 40, 10));
+
+    // The header and recorded size survive at the top of the image
+    assert_eq!(Decoder::new().recorded_dimensions(&cropped), Some((40, 40)));
+
+    let err = Decoder::new().decode(&cropped).unwrap_err();
+    let HideError::DimensionsChanged {
+        encoded,
+        actual,
+        cause,
+    } = err
+    else {
+        panic!("expected DimensionsChanged, got {:?}", err);
+    };
+    assert_eq!(encoded, (40, 40));
+    assert_eq!(actual, (40, 10));
+    assert!(matches!(*cause, HideError::TruncatedPayload { .. }));
+
+    // What is left can still be recovered, without the recorded size
+    let partial = Decoder::new().decode_lossy(&cropped).unwrap();
+    assert_eq!(partial.declared_length, message.len());
+    assert_eq!(partial.data, &message[..partial.recovered_length]);
+}
+
+#[test]
+fn test_matching_dimensions_decode_as_usual() {
+    let stego = encode_recording(30, 20, b"same size");
+    assert!(Decoder::new()
+        .read_header(&stego)
+        .unwrap()
+        .records_dimensions());
+
+    let decoder = Decoder::with_config(DecoderConfig {
+        expected_dimensions: Some((30, 20)),
+        ..Default::default()
+    });
+    assert_eq!(decoder.decode(&stego).unwrap(), b"same size");
+
+    // Failures on images of the expected size are left alone
+    let plain = gradient_image(30, 20);
+    assert!(!matches!(
+        decoder.decode(&plain),
+        Err(HideError::DimensionsChanged { .. }) | Ok(_)
+    ));
+}
+
+#[test]
+fn test_containers_skip_the_recorded_dimensions() {
+    let entries = vec![
+        ("a".to_string(), b"first".to_vec()),
+        ("b".to_string(), b"second".to_vec()),
+    ];
+    let stego = Encoder::with_config(EncoderConfig {
+        record_dimensions: true,
+        ..Default::default()
+    })
+    .encode_container(gradient_image(40, 40), &entries)
+    .unwrap();
+
+    let decoder = Decoder::new();
+    assert_eq!(decoder.decode_container(&stego).unwrap().len(), 2);
+    assert_eq!(
+        decoder.decode_entry(&stego, "b").unwrap(),
+        Some(b"second".to_vec())
+    );
+}
+
+#[test]
+fn test_cli_reports_the_size_change() {
+    let temp_dir = tempdir().unwrap();
+    let cover_path = temp_dir.path().join("cover.png");
+    let stego_path = temp_dir.path().join("stego.png");
+    gradient_image(40, 40).save(&cover_path).unwrap();
+
+    let message = "x".repeat(400);
+    let output = Command::new(env!("CARGO_BIN_EXE_hide"))
+        .args(["encode", "--record-dimensions", "--message", &message])
+        .arg("--image")
+        .arg(&cover_path)
+        .arg("--output")
+        .arg(&stego_path)
+        .output()
+        .expect("Failed to run hide");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    // Cropped: the recorded size is read from the surviving header
+    let stego = StegoImage::from_file(&stego_path).unwrap();
+    let cropped_path = temp_dir.path().join("cropped.png");
+    StegoImage::from_dynamic_image(stego.inner().crop_imm(0, 0, 40, 10))
+        .save(&cropped_path)
+        .unwrap();
+
+    // Resized: only the size the caller expects can be compared
+    let resized_path = temp_dir.path().join("resized.png");
+    shrink(&stego, 30, 30).save(&resized_path).unwrap();
+
+    for (path, extra_args, actual) in [
+        (&cropped_path, &[][..], "40x10"),
+        (
+            &resized_path,
+            &["--expected-dimensions", "40x40"][..],
+            "30x30",
+        ),
+    ] {
+        let output = Command::new(env!("CARGO_BIN_EXE_hide"))
+            .args(["decode", "--image"])
+            .arg(path)
+            .args(extra_args)
+            .output()
+            .expect("Failed to run hide");
+        assert!(!output.status.success());
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains(&format!("the image is {} but was encoded at 40x40", actual)),
+            "{}",
+            stderr
+        );
+        assert!(stderr.contains("resized copy"), "{}", stderr);
+    }
+}
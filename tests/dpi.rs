@@ -0,0 +1,90 @@
+use hide_rs::decoder::Decoder;
+use hide_rs::encoder::{Encoder, EncoderConfig};
+use hide_rs::img::{PhysicalDimensions, StegoImage};
+use hide_rs::testing::gradient_image;
+use image::ImageFormat;
+use std::fs;
+use std::process::Command;
+use tempfile::tempdir;
+
+/// A 48x32 RGB PNG with a `pHYs` chunk of 11811 pixels per meter (300 DPI)
+/// after a `tEXt` chunk
+const COVER: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/fixtures/png/cover_300dpi.png"
+);
+
+// Helper counting the `pHYs` chunks of a file
+fn phys_chunks(bytes: &[u8]) -> usize {
+    bytes.windows(4).filter(|window| window == b"pHYs").count()
+}
+
+#[test]
+fn test_cover_density_is_kept_on_save() {
+    let cover = StegoImage::from_file(COVER).unwrap();
+    assert_eq!(cover.dpi(), Some((300, 300)));
+    let expected = PhysicalDimensions {
+        x_pixels_per_unit: 11811,
+        y_pixels_per_unit: 11811,
+        in_meters: true,
+    };
+    assert_eq!(cover.physical_dimensions(), Some(expected));
+
+    let temp_dir = tempdir().unwrap();
+    let output = temp_dir.path().join("stego.png");
+    Encoder::new()
+        .encode_file(COVER, b"print me", output.to_str().unwrap())
+        .unwrap();
+
+    let bytes = fs::read(&output).unwrap();
+    assert_eq!(phys_chunks(&bytes), 1);
+    assert_eq!(PhysicalDimensions::from_png_bytes(&bytes), Some(expected));
+
+    // Still a valid PNG with the payload intact
+    let stego = StegoImage::from_bytes(&bytes).unwrap();
+    assert_eq!(Decoder::new().decode(&stego).unwrap(), b"print me");
+}
+
+#[test]
+fn test_density_can_be_set() {
+    let mut image = gradient_image(16, 16);
+    assert_eq!(image.dpi(), None);
+    assert_eq!(phys_chunks(&image.to_bytes(ImageFormat::Png).unwrap()), 0);
+
+    image.set_dpi(72, 144);
+    let bytes = image.to_bytes(ImageFormat::Png).unwrap();
+    let reloaded = StegoImage::from_bytes(&bytes).unwrap();
+    assert_eq!(reloaded.dpi(), Some((72, 144)));
+    assert_eq!(reloaded.inner().as_bytes(), image.inner().as_bytes());
+
+    // The encoder's setting replaces the cover's
+    let stego = Encoder::with_config(EncoderConfig::default().dpi(600, 600))
+        .encode(StegoImage::from_file(COVER).unwrap(), b"reprinted")
+        .unwrap();
+    assert_eq!(stego.dpi(), Some((600, 600)));
+}
+
+#[test]
+fn test_cli_dpi() {
+    let temp_dir = tempdir().unwrap();
+    let cover = temp_dir.path().join("cover.png");
+    gradient_image(32, 32).save(&cover).unwrap();
+    let output = temp_dir.path().join("stego.png");
+
+    let encode = Command::new(env!("CARGO_BIN_EXE_hide"))
+        .args(["encode", "--dpi", "300", "-m", "for the printer", "-i"])
+        .arg(&cover)
+        .arg("-o")
+        .arg(&output)
+        .output()
+        .unwrap();
+    assert!(
+        encode.status.success(),
+        "{}",
+        String::from_utf8_lossy(&encode.stderr)
+    );
+    assert_eq!(
+        StegoImage::from_file(&output).unwrap().dpi(),
+        Some((300, 300))
+    );
+}
@@ -0,0 +1,126 @@
+use hide_rs::decoder::Decoder;
+use hide_rs::encoder::Encoder;
+use hide_rs::error::HideError;
+use hide_rs::img::{self, create_rgb_image};
+
+#[test]
+fn test_single_pixel_image() {
+    let encoder = Encoder::new();
+    let decoder = Decoder::new();
+    let image = create_rgb_image(1, 1).unwrap();
+
+    // A single pixel cannot even hold the header
+    assert_eq!(encoder.max_message_size(&image), 0);
+    assert!(matches!(
+        encoder.encode(image.clone(), b""),
+        Err(HideError::MessageTooLarge)
+    ));
+    assert!(matches!(
+        decoder.decode(&image),
+        Err(HideError::NoMessageFound)
+    ));
+}
+
+#[test]
+fn test_one_pixel_wide_and_tall_images() {
+    let encoder = Encoder::new();
+    let decoder = Decoder::new();
+    let message = b"abc";
+
+    // 30 pixels hold 90 bits: 64 header bits plus 3 message bytes
+    for (width, height) in [(1, 30), (30, 1)] {
+        let image = create_rgb_image(width, height).unwrap();
+        assert_eq!(encoder.max_message_size(&image), message.len());
+
+        let stego_image = encoder.encode(image, message).unwrap();
+        let decoded = decoder.decode(&stego_image).unwrap();
+        assert_eq!(decoded, message, "Failed for {}x{} image", width, height);
+    }
+}
+
+#[test]
+fn test_header_only_strip_images() {
+    let encoder = Encoder::new();
+    let decoder = Decoder::new();
+
+    // 22 pixels hold 66 bits: just enough for the header and an empty message
+    for (width, height) in [(1, 22), (22, 1), (2, 11), (11, 2)] {
+        let image = create_rgb_image(width, height).unwrap();
+        let stego_image = encoder.encode(image.clone(), b"").unwrap();
+        assert!(decoder.decode(&stego_image).unwrap().is_empty());
+
+        // One byte does not fit
+        assert!(encoder.encode(image, b"x").is_err());
+    }
+}
+
+#[test]
+fn test_message_ending_on_chunk_boundary() {
+    let encoder = Encoder::new();
+    let decoder = Decoder::new();
+
+    // Messages whose bit length (plus header) is and isn't a multiple of 3
+    for len in 1..=6 {
+        let message: Vec<u8> = (0..len as u8).map(|b| b.wrapping_mul(37)).collect();
+        let image = create_rgb_image(1, 64).unwrap();
+
+        let stego_image = encoder.encode(image, &message).unwrap();
+        assert_eq!(decoder.decode(&stego_image).unwrap(), message);
+    }
+}
+
+#[test]
+fn test_capacity_near_u32_limits() {
+    // Products of these dimensions overflow u32 and, at the extreme, u64 bits
+    let cases = [
+        (u32::MAX, 1),
+        (1, u32::MAX),
+        (65536, 65536),
+        (u32::MAX, 2),
+        (u32::MAX, u32::MAX),
+    ];
+
+    for (width, height) in cases {
+        let bits = img::capacity_bits(width, height);
+        assert!(bits >= img::pixel_count(width, height).min(u64::MAX / 3));
+        assert!(img::capacity(width, height) > 0);
+        assert!(Encoder::max_message_size_for_dimensions(width, height) > 0);
+    }
+
+    // Swapping dimensions never changes capacity
+    assert_eq!(img::capacity(u32::MAX, 7), img::capacity(7, u32::MAX));
+}
+
+#[test]
+fn test_empty_images_are_rejected() {
+    for (width, height) in [(0, 5), (5, 0), (0, 0)] {
+        assert!(
+            matches!(
+                create_rgb_image(width, height),
+                Err(HideError::InvalidParameters(_))
+            ),
+            "Accepted a {}x{} image",
+            width,
+            height
+        );
+    }
+}
+
+#[test]
+fn test_oversized_images_are_rejected() {
+    // 100x100 RGB needs 30,000 bytes
+    assert!(matches!(
+        img::StegoImage::new_rgb_with_limit(100, 100, 29_999),
+        Err(HideError::AllocationTooLarge {
+            requested: 30_000,
+            limit: 29_999
+        })
+    ));
+    assert!(img::StegoImage::new_rgb_with_limit(100, 100, 30_000).is_ok());
+
+    // The default cap is checked before anything is allocated
+    assert!(matches!(
+        create_rgb_image(u32::MAX, u32::MAX),
+        Err(HideError::AllocationTooLarge { .. })
+    ));
+}
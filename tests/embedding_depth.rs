@@ -0,0 +1,440 @@
+use hide_rs::decoder::{Decoder, UntrustedLimits};
+use hide_rs::depth::{self, EmbeddingDepth};
+use hide_rs::encoder::{self, Encoder, EncoderConfig, Secret};
+use hide_rs::error::HideError;
+use hide_rs::header::{ExtraFlags, HeaderVersion};
+use hide_rs::img::{FillStyle, Rect, StegoImage};
+use hide_rs::padding::PaddingPolicy;
+use hide_rs::schedule::ChannelSchedule;
+use hide_rs::testing::{assert_lsb_only_changes, gradient_image, noise_image};
+use std::process::Command;
+use tempfile::tempdir;
+
+fn two_bit() -> EncoderConfig {
+    EncoderConfig::default().embedding_depth(EmbeddingDepth::Two)
+}
+
+// Helper making a message whose bits vary throughout
+fn message(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 37 + 11) as u8).collect()
+}
+
+// Helper making a mask image that excludes a block in the middle of a 32x32 cover
+fn block_mask_image() -> StegoImage {
+    let gray = image::GrayImage::from_fn(32, 32, |x, y| {
+        let inside = (12..20).contains(&x) && (12..20).contains(&y);
+        image::Luma([if inside { 255 } else { 0 }])
+    });
+    StegoImage::from_dynamic_image(image::DynamicImage::ImageLuma8(gray))
+}
+
+// Helper cropping the top rows of an image
+fn top_rows(image: &StegoImage, rows: u32) -> StegoImage {
+    StegoImage::from_dynamic_image(image.inner().crop_imm(0, 0, image.width(), rows))
+}
+
+// Helper asserting that only the two lowest bits of any channel changed,
+// returning how many channels had the second bit change
+fn assert_two_lowest_bits_only_change(cover: &StegoImage, stego: &StegoImage) -> usize {
+    let mut second_bits = 0;
+    for y in 0..cover.height() {
+        for x in 0..cover.width() {
+            let before = cover.get_pixel_rgb(x, y).unwrap();
+            let after = stego.get_pixel_rgb(x, y).unwrap();
+            for (old, new) in before.0.iter().zip(after.0) {
+                let diff = old ^ new;
+                assert_eq!(diff & !0b11, 0, "({}, {}): {} -> {}", x, y, old, new);
+                second_bits += usize::from(diff & 0b10 != 0);
+            }
+        }
+    }
+    second_bits
+}
+
+#[test]
+fn test_two_bit_payloads_round_trip() {
+    let cover = noise_image(32, 32, 7);
+    let message = message(600);
+    let (stego, stats) = Encoder::with_config(two_bit())
+        .encode_with_stats(cover.clone(), &message)
+        .unwrap();
+
+    let header = Decoder::new().read_header(&stego).unwrap();
+    assert_eq!(header.format_version, HeaderVersion::V2.number());
+    assert!(header.extra_flags.contains(ExtraFlags::TWO_BIT_DEPTH));
+    assert_eq!(header.embedding_depth(), EmbeddingDepth::Two);
+    assert_eq!(stats.pixels_used, depth::pixels_needed(message.len()));
+    assert_eq!(Decoder::new().decode(&stego).unwrap(), message);
+
+    // The header pixels keep one bit per channel
+    assert!(assert_two_lowest_bits_only_change(&cover, &stego) > 0);
+    for x in 0..depth::HEADER_PIXELS as u32 {
+        let before = cover.get_pixel_rgb(x, 0).unwrap();
+        let after = stego.get_pixel_rgb(x, 0).unwrap();
+        for (old, new) in before.0.iter().zip(after.0) {
+            assert_eq!((old ^ new) & !1, 0, "header pixel {}", x);
+        }
+    }
+}
+
+#[test]
+fn test_only_the_two_lowest_bits_change() {
+    for seed in 0..4 {
+        let cover = noise_image(24, 20, seed);
+        let capacity = Encoder::with_config(two_bit()).max_message_size(&cover);
+        let stego = Encoder::with_config(two_bit())
+            .encode(cover.clone(), &message(capacity))
+            .unwrap();
+        assert!(assert_two_lowest_bits_only_change(&cover, &stego) > 0);
+        assert_eq!(Decoder::new().decode(&stego).unwrap(), message(capacity));
+    }
+}
+
+#[test]
+fn test_capacity_reflects_the_depth() {
+    let cover = gradient_image(32, 32);
+    let one_bit = Encoder::new().max_message_size(&cover);
+    let two_bits = Encoder::with_config(two_bit()).max_message_size(&cover);
+    assert_eq!(one_bit, 32 * 32 * 3 / 8 - 8);
+    assert_eq!(two_bits, (32 * 32 - 32) * 6 / 8);
+    assert_eq!(two_bits, depth::max_message_size_for_dimensions(32, 32));
+
+    let breakdown = encoder::capacity_breakdown(&cover, &two_bit());
+    assert_eq!(breakdown.max_message_bytes, two_bits);
+    assert_eq!(
+        breakdown.max_message_bytes + breakdown.overhead_bytes(),
+        breakdown.raw_bytes
+    );
+
+    let encoder = Encoder::with_config(two_bit());
+    assert!(encoder.encode(cover.clone(), &message(two_bits)).is_ok());
+    assert!(matches!(
+        encoder.encode(cover, &message(two_bits + 1)),
+        Err(HideError::MessageTooLarge)
+    ));
+
+    // The header pixels count against a change budget too
+    let budgeted = EncoderConfig {
+        max_changed_pixels: Some(0.25),
+        ..two_bit()
+    };
+    let capacity = encoder::capacity_breakdown(&gradient_image(32, 32), &budgeted);
+    assert_eq!(capacity.max_message_bytes, (256 - 32) * 6 / 8);
+    let encoder = Encoder::with_config(budgeted);
+    assert!(encoder
+        .encode(gradient_image(32, 32), &message(capacity.max_message_bytes))
+        .is_ok());
+    assert!(matches!(
+        encoder.encode(
+            gradient_image(32, 32),
+            &message(capacity.max_message_bytes + 1)
+        ),
+        Err(HideError::ChangeBudgetExceeded { .. })
+    ));
+}
+
+#[test]
+fn test_one_bit_images_still_decode_by_default() {
+    assert_eq!(
+        EncoderConfig::default().embedding_depth,
+        EmbeddingDepth::One
+    );
+
+    let cover = gradient_image(32, 32);
+    let stego = Encoder::new().encode(cover.clone(), b"one bit").unwrap();
+    let header = Decoder::new().read_header(&stego).unwrap();
+    assert_eq!(header.format_version, HeaderVersion::CURRENT.number());
+    assert_eq!(header.embedding_depth(), EmbeddingDepth::One);
+    assert_lsb_only_changes(&cover, &stego);
+    assert_eq!(Decoder::new().decode(&stego).unwrap(), b"one bit");
+}
+
+#[test]
+fn test_two_bit_payloads_combine_with_payload_options() {
+    let message = message(300);
+    let configs = [
+        two_bit().compress(true).checksum(true),
+        two_bit().error_correction(hide_rs::ecc::EccParams::default()),
+        two_bit().pixel_key(b"deep"),
+        EncoderConfig {
+            verify_roundtrip: true,
+            ..two_bit()
+        },
+    ];
+    for config in configs {
+        let decoder = if config.pixel_key.is_some() {
+            Decoder::with_key(b"deep")
+        } else {
+            Decoder::new()
+        };
+        let stego = Encoder::with_config(config)
+            .encode(gradient_image(32, 32), &message)
+            .unwrap();
+        assert_eq!(
+            decoder.read_header(&stego).unwrap().embedding_depth(),
+            EmbeddingDepth::Two
+        );
+        assert_eq!(decoder.decode(&stego).unwrap(), message);
+    }
+
+    let passphrase = Secret::new("two planes");
+    let stego = Encoder::with_config(two_bit())
+        .encode_encrypted(gradient_image(32, 32), &message, &passphrase)
+        .unwrap();
+    assert_eq!(
+        Decoder::new()
+            .decode_encrypted(&stego, &passphrase)
+            .unwrap(),
+        message
+    );
+
+    let entries = [("deep.txt".to_string(), b"entry".to_vec())];
+    let stego = Encoder::with_config(two_bit())
+        .encode_container(gradient_image(32, 32), &entries)
+        .unwrap();
+    assert_eq!(
+        Decoder::new()
+            .decode_entry(&stego, "deep.txt")
+            .unwrap()
+            .unwrap(),
+        b"entry"
+    );
+}
+
+#[test]
+fn test_two_bit_payloads_combine_with_layout_options() {
+    let message = message(300);
+    let mask_image = block_mask_image();
+    let configs = [
+        EncoderConfig {
+            channel_schedule: ChannelSchedule::Rotating,
+            ..two_bit()
+        },
+        two_bit().pad_to(PaddingPolicy::NextPowerOfTwo),
+        EncoderConfig {
+            record_dimensions: true,
+            ..two_bit()
+        },
+        EncoderConfig {
+            embed_mask: true,
+            ..two_bit().mask(&mask_image)
+        },
+    ];
+    for config in configs {
+        let cover = noise_image(32, 32, 3);
+        let stego = Encoder::with_config(config.clone())
+            .encode(cover.clone(), &message)
+            .unwrap();
+        let header = Decoder::new().read_header(&stego).unwrap();
+        assert_eq!(header.format_version, HeaderVersion::V2.number());
+        assert_eq!(header.embedding_depth(), EmbeddingDepth::Two);
+        assert!(assert_two_lowest_bits_only_change(&cover, &stego) > 0);
+        assert_eq!(Decoder::new().decode(&stego).unwrap(), message);
+        assert_eq!(
+            Decoder::new()
+                .decode_untrusted(&stego, UntrustedLimits::default())
+                .unwrap(),
+            message
+        );
+
+        if let Some(mask) = &config.pixel_mask {
+            for index in (0..32 * 32).filter(|&index| mask.is_excluded(index)) {
+                let (x, y) = ((index % 32) as u32, (index / 32) as u32);
+                assert_eq!(
+                    cover.get_pixel_rgb(x, y).unwrap(),
+                    stego.get_pixel_rgb(x, y).unwrap()
+                );
+            }
+        }
+    }
+
+    // Covers grow to the two-bit size the message needs, smaller than one bit would take
+    let stego = Encoder::with_config(two_bit().auto_extend(FillStyle::Mirror))
+        .encode(gradient_image(16, 16), &message)
+        .unwrap();
+    assert!(stego.width() * stego.height() > 16 * 16);
+    assert!(stego.width() * stego.height() < Encoder::pixels_needed(message.len()) as u32);
+    assert_eq!(Decoder::new().decode(&stego).unwrap(), message);
+}
+
+#[test]
+fn test_two_bit_rgba_payloads_take_two_bits_of_alpha() {
+    let cover = StegoImage::from_dynamic_image(image::DynamicImage::ImageRgba8(
+        image::RgbaImage::from_fn(32, 32, |x, y| {
+            image::Rgba([(x * 8) as u8, (y * 8) as u8, (x + y) as u8, 200])
+        }),
+    ));
+    let config = EncoderConfig {
+        embed_alpha: true,
+        ..two_bit()
+    };
+
+    // Each pixel after the header carries eight bits, four in each plane
+    let capacity = Encoder::with_config(config.clone()).max_message_size(&cover);
+    assert_eq!(capacity, 32 * 32 - 32);
+    let stego = Encoder::with_config(config)
+        .encode(cover.clone(), &message(capacity))
+        .unwrap();
+    let header = Decoder::new().read_header(&stego).unwrap();
+    assert!(header.is_rgba());
+    assert_eq!(header.embedding_depth(), EmbeddingDepth::Two);
+    assert_eq!(Decoder::new().decode(&stego).unwrap(), message(capacity));
+
+    let (before, after) = (cover.inner().to_rgba8(), stego.inner().to_rgba8());
+    let alpha_planes = before
+        .pixels()
+        .zip(after.pixels())
+        .map(|(old, new)| old.0[3] ^ new.0[3])
+        .fold(0, |planes, diff| planes | diff);
+    assert_eq!(alpha_planes, 0b11);
+}
+
+#[test]
+fn test_two_bit_payloads_fit_views_and_appends() {
+    let original = gradient_image(64, 64);
+    let mut image = original.clone();
+    let rect = Rect::new(16, 16, 32, 32);
+    let message = message(500);
+    let mut view = image
+        .view_mut(rect.x, rect.y, rect.width, rect.height)
+        .unwrap();
+    Encoder::with_config(two_bit())
+        .encode_in_place(&mut view, &message)
+        .unwrap();
+    assert_eq!(Decoder::new().decode_view(&image, rect).unwrap(), message);
+    assert_eq!(
+        image.get_pixel_rgb(0, 0).unwrap(),
+        original.get_pixel_rgb(0, 0).unwrap()
+    );
+
+    let mut stego = Encoder::with_config(two_bit())
+        .encode(gradient_image(32, 32), b"fixed")
+        .unwrap();
+    Encoder::new().append(&mut stego, b" and appended").unwrap();
+    assert_eq!(
+        Decoder::new().decode(&stego).unwrap(),
+        b"fixed and appended"
+    );
+    assert_eq!(
+        Decoder::new()
+            .read_header(&stego)
+            .unwrap()
+            .embedding_depth(),
+        EmbeddingDepth::Two
+    );
+
+    // Appends get the two-bit capacity
+    let capacity = depth::max_message_size_for_dimensions(32, 32);
+    assert!(Encoder::new()
+        .append(&mut stego, &vec![0; capacity - 18])
+        .is_ok());
+    assert!(matches!(
+        Encoder::new().append(&mut stego, b"!"),
+        Err(HideError::MessageTooLarge)
+    ));
+}
+
+#[test]
+fn test_partial_and_limited_decodes_read_both_planes() {
+    let message = message(500);
+    let stego = Encoder::with_config(two_bit())
+        .encode(gradient_image(32, 32), &message)
+        .unwrap();
+
+    let limits = UntrustedLimits::default();
+    assert_eq!(
+        Decoder::new().decode_untrusted(&stego, limits).unwrap(),
+        message
+    );
+    let (decoded, trace) = Decoder::new().decode_with_trace(&stego).unwrap();
+    assert_eq!(decoded, message);
+    assert!(trace
+        .runs()
+        .iter()
+        .any(|run| run.bits_per_pixel == EmbeddingDepth::Two.bits_per_pixel()));
+
+    // The top half of the image holds 6 bits in each of 480 payload pixels
+    let top = top_rows(&stego, 16);
+    let partial = Decoder::new().decode_lossy(&top).unwrap();
+    assert!(!partial.complete);
+    assert_eq!(partial.recovered_length, (512 - 32) * 6 / 8);
+    assert_eq!(partial.data, message[..partial.recovered_length]);
+}
+
+#[test]
+fn test_overwriting_clears_both_planes() {
+    let cover = StegoImage::from_dynamic_image(image::DynamicImage::ImageRgb8(
+        image::RgbImage::from_pixel(32, 32, image::Rgb([0xfc, 0xfc, 0xfc])),
+    ));
+    let stego = Encoder::with_config(two_bit())
+        .encode(cover, &[0xff; 700])
+        .unwrap();
+
+    let overwritten = Encoder::with_config(EncoderConfig {
+        overwrite_existing: true,
+        ..Default::default()
+    })
+    .encode(stego, b"short")
+    .unwrap();
+    assert_eq!(Decoder::new().decode(&overwritten).unwrap(), b"short");
+    for (_, _, pixel) in overwritten.inner().to_rgb8().enumerate_pixels() {
+        assert!(pixel.0.iter().all(|channel| channel & 0b10 == 0));
+    }
+}
+
+#[test]
+fn test_cli_depth() {
+    let temp_dir = tempdir().unwrap();
+    let cover = temp_dir.path().join("cover.png");
+    gradient_image(32, 32).save(&cover).unwrap();
+    let output = temp_dir.path().join("stego.png");
+    let message_path = temp_dir.path().join("message.bin");
+
+    // Too long for one bit per channel
+    let message = message(600);
+    std::fs::write(&message_path, &message).unwrap();
+    let encode = |depth: &str| {
+        Command::new(env!("CARGO_BIN_EXE_hide"))
+            .args(["encode", "--depth", depth, "-i"])
+            .arg(&cover)
+            .arg("-f")
+            .arg(&message_path)
+            .arg("-o")
+            .arg(&output)
+            .arg("--overwrite")
+            .output()
+            .unwrap()
+    };
+    assert!(!encode("1").status.success());
+    let two = encode("2");
+    assert!(
+        two.status.success(),
+        "{}",
+        String::from_utf8_lossy(&two.stderr)
+    );
+    let invalid = encode("3");
+    assert!(!invalid.status.success());
+    assert!(String::from_utf8_lossy(&invalid.stderr).contains("1 or 2 bits per channel"));
+
+    let decoded = temp_dir.path().join("decoded.bin");
+    let decode = Command::new(env!("CARGO_BIN_EXE_hide"))
+        .args(["decode", "-i"])
+        .arg(&output)
+        .arg("-o")
+        .arg(&decoded)
+        .output()
+        .unwrap();
+    assert!(decode.status.success());
+    assert_eq!(std::fs::read(&decoded).unwrap(), message);
+
+    let capacity = Command::new(env!("CARGO_BIN_EXE_hide"))
+        .args(["capacity", "--depth", "2", "-i"])
+        .arg(&cover)
+        .output()
+        .unwrap();
+    assert!(capacity.status.success());
+    assert!(String::from_utf8_lossy(&capacity.stdout).contains(&format!(
+        "{:>12}",
+        depth::max_message_size_for_dimensions(32, 32)
+    )));
+}
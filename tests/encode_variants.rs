@@ -0,0 +1,112 @@
+use actix_web::{test, App};
+use hide_rs::api::models::{EncodeVariantsResponse, ValidationDetails};
+use hide_rs::api::routes::configure_routes;
+use hide_rs::decoder::Decoder;
+use hide_rs::img::StegoImage;
+use hide_rs::padding::PaddingPolicy;
+use hide_rs::preset::Preset;
+use hide_rs::schedule::ChannelSchedule;
+use hide_rs::testing::gradient_image;
+use std::path::Path;
+use tempfile::tempdir;
+
+mod common;
+
+use common::{create_state, file_multipart};
+
+// Helper to build an encode form with a cover, a message and a variants field
+fn variants_form(cover: &Path, message: &str, variants: &str) -> (Vec<u8>, String) {
+    file_multipart(
+        "cover_image",
+        cover,
+        &[("message", message), ("variants", variants)],
+    )
+}
+
+#[actix_web::test]
+async fn test_two_variants_decode_from_their_downloads() {
+    let temp_dir = tempdir().unwrap();
+    let cover = temp_dir.path().join("cover.png");
+    gradient_image(60, 60).save(&cover).unwrap();
+
+    let state = create_state(temp_dir.path());
+    let app = test::init_service(App::new().app_data(state).configure(configure_routes)).await;
+
+    let (payload, content_type) = variants_form(
+        &cover,
+        "Meet at the usual place",
+        r#"["fast", {"preset": "robust", "channel_schedule": "rotating"}]"#,
+    );
+    let req = test::TestRequest::post()
+        .uri("/api/v1/encode")
+        .insert_header(("content-type", content_type))
+        .set_payload(payload)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success(), "{}", resp.status());
+    let response: EncodeVariantsResponse = test::read_body_json(resp).await;
+
+    assert_eq!(response.status, "success");
+    assert_eq!(response.variants.len(), 2);
+    let (fast, robust) = (&response.variants[0], &response.variants[1]);
+    assert_ne!(fast.image_id, robust.image_id);
+
+    assert_eq!(fast.options.preset, Some(Preset::Fast));
+    assert_eq!(fast.options.padding, None);
+    assert_eq!(fast.options.verify_roundtrip, Some(false));
+    assert_eq!(robust.options.preset, Some(Preset::Robust));
+    assert_eq!(robust.options.padding, Some(PaddingPolicy::FillCapacity));
+    assert_eq!(
+        robust.options.channel_schedule,
+        Some(ChannelSchedule::Rotating)
+    );
+    assert_eq!(robust.options.record_dimensions, Some(true));
+    assert!(robust.capacity.max_message_bytes < fast.capacity.max_message_bytes);
+    assert_eq!(fast.metadata.embedded_message_bytes, Some(23));
+
+    let mut padded = Vec::new();
+    for variant in &response.variants {
+        let req = test::TestRequest::get()
+            .uri(&variant.download_url)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success(), "{}", resp.status());
+        let stego = StegoImage::from_bytes(&test::read_body(resp).await).unwrap();
+
+        let decoder = Decoder::new();
+        assert_eq!(decoder.decode(&stego).unwrap(), b"Meet at the usual place");
+        padded.push(decoder.read_header(&stego).unwrap().is_padded());
+    }
+    assert_eq!(padded, [false, true]);
+}
+
+#[actix_web::test]
+async fn test_invalid_variants_are_rejected() {
+    let temp_dir = tempdir().unwrap();
+    let cover = temp_dir.path().join("cover.png");
+    gradient_image(40, 40).save(&cover).unwrap();
+
+    let state = create_state(temp_dir.path());
+    let app = test::init_service(App::new().app_data(state).configure(configure_routes)).await;
+
+    for variants in [
+        "[]",
+        r#"["fast", "fast", "fast", "fast", "fast"]"#,
+        r#"["hardened"]"#,
+        r#"[{"preset": "fast", "fec": true}]"#,
+    ] {
+        let (payload, content_type) = variants_form(&cover, "hi", variants);
+        let req = test::TestRequest::post()
+            .uri("/api/v1/encode")
+            .insert_header(("content-type", content_type))
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400, "{}", variants);
+
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["error_code"], "validation_error");
+        let details: ValidationDetails = serde_json::from_value(body["details"].clone()).unwrap();
+        assert_eq!(details.field_errors[0].field, "variants");
+    }
+}
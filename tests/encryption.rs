@@ -0,0 +1,272 @@
+use hide_rs::crypto::{CipherSuite, ENCRYPTION_OVERHEAD};
+use hide_rs::decoder::{Decoder, DecoderConfig};
+use hide_rs::encoder::{Encoder, EncoderConfig, Secret};
+use hide_rs::error::HideError;
+use hide_rs::extract_raw_data;
+use hide_rs::header::EXTENDED_HEADER_SIZE;
+use hide_rs::img::StegoImage;
+use hide_rs::padding::PaddingPolicy;
+use hide_rs::rng::{Kdf, KdfParams, Key, SharedKdf};
+use hide_rs::testing::gradient_image;
+use sha2::{Digest, Sha256};
+
+/// A fast stand-in for PBKDF2, so most tests don't pay for its rounds
+struct HashKdf;
+
+impl Kdf for HashKdf {
+    fn derive(
+        &self,
+        passphrase: &Secret,
+        salt: &[u8],
+        _params: &KdfParams,
+    ) -> hide_rs::Result<Key> {
+        let mut hasher = Sha256::new();
+        hasher.update(passphrase.expose().as_bytes());
+        hasher.update(salt);
+        Ok(Key::new(hasher.finalize().into()))
+    }
+}
+
+fn encoder() -> Encoder {
+    Encoder::with_config(EncoderConfig {
+        kdf: Some(SharedKdf::new(HashKdf)),
+        ..Default::default()
+    })
+}
+
+fn decoder() -> Decoder {
+    Decoder::with_config(DecoderConfig {
+        kdf: Some(SharedKdf::new(HashKdf)),
+        ..Default::default()
+    })
+}
+
+fn passphrase() -> Secret {
+    Secret::new("correct horse battery staple")
+}
+
+// Helper to flip one decoded payload bit by changing the LSBs of the pixel carrying it
+fn flip_payload_bit(image: &mut StegoImage, bit: usize) {
+    let decoder = Decoder::new();
+    let bit = EXTENDED_HEADER_SIZE * 8 + bit;
+    let pixel = (bit / 3) as u32;
+    let (x, y) = (pixel % image.width(), pixel / image.width());
+    let original = image.get_pixel_rgb(x, y).unwrap();
+    let bits = decoder.decode_pixel(original[0], original[1], original[2]);
+
+    // Pick the LSB changes that flip exactly this bit of the pixel's three
+    for lsbs in 1..8u8 {
+        let mut changed = original;
+        for channel in 0..3 {
+            changed[channel] ^= (lsbs >> channel) & 1;
+        }
+        let changed_bits = decoder.decode_pixel(changed[0], changed[1], changed[2]);
+        let differs: Vec<usize> = (0..3).filter(|&i| bits[i] != changed_bits[i]).collect();
+        if differs == [bit % 3] {
+            image.set_pixel_rgb(x, y, changed).unwrap();
+            return;
+        }
+    }
+    panic!("no LSB change flips bit {} alone", bit);
+}
+
+#[test]
+fn test_round_trip_with_the_default_kdf() {
+    let stego = Encoder::new()
+        .encode_encrypted(gradient_image(64, 64), b"for your eyes only", &passphrase())
+        .unwrap();
+    assert_eq!(
+        Decoder::new()
+            .decode_encrypted(&stego, &passphrase())
+            .unwrap(),
+        b"for your eyes only"
+    );
+
+    // The header only reveals the sealed length
+    let header = Decoder::new().read_header(&stego).unwrap();
+    assert!(header.is_encrypted());
+    assert_eq!(header.message_length as usize, 18 + ENCRYPTION_OVERHEAD);
+}
+
+#[test]
+fn test_empty_and_binary_messages() {
+    let binary: Vec<u8> = (0..=255).collect();
+    for message in [&[][..], &binary, &[0; 40]] {
+        let stego = encoder()
+            .encode_encrypted(gradient_image(64, 64), message, &passphrase())
+            .unwrap();
+        assert_eq!(
+            decoder().decode_encrypted(&stego, &passphrase()).unwrap(),
+            message
+        );
+    }
+}
+
+#[test]
+fn test_wrong_passphrase_is_a_dedicated_error() {
+    let stego = encoder()
+        .encode_encrypted(gradient_image(64, 64), b"secret", &passphrase())
+        .unwrap();
+    for wrong in [
+        "",
+        "correct horse battery stapler",
+        "Correct horse battery staple",
+    ] {
+        assert!(matches!(
+            decoder().decode_encrypted(&stego, &Secret::new(wrong)),
+            Err(HideError::DecryptionFailed)
+        ));
+    }
+
+    // A different key derivation is as good as a wrong passphrase
+    assert!(matches!(
+        Decoder::new().decode_encrypted(&stego, &passphrase()),
+        Err(HideError::DecryptionFailed)
+    ));
+}
+
+#[test]
+fn test_tampered_ciphertext_is_detected() {
+    let message = b"tamper with me and I'll know";
+    let stego = encoder()
+        .encode_encrypted(gradient_image(64, 64), message, &passphrase())
+        .unwrap();
+
+    // Flip one embedded bit in the salt, the nonce, the ciphertext and the tag
+    let payload_bits = (ENCRYPTION_OVERHEAD + message.len()) * 8;
+    for bit in [8, 17 * 8 + 3, 29 * 8 + 40, payload_bits - 8] {
+        let mut tampered = stego.clone();
+        flip_payload_bit(&mut tampered, bit);
+
+        assert!(
+            matches!(
+                decoder().decode_encrypted(&tampered, &passphrase()),
+                Err(HideError::DecryptionFailed)
+            ),
+            "bit {}",
+            bit
+        );
+    }
+}
+
+#[test]
+fn test_plain_and_encrypted_decodes_are_kept_apart() {
+    let stego = encoder()
+        .encode_encrypted(gradient_image(64, 64), b"sealed", &passphrase())
+        .unwrap();
+    let error = decoder().decode(&stego).unwrap_err();
+    assert!(matches!(error, HideError::EncryptedPayload));
+    assert!(error.to_string().contains("encrypted"));
+
+    let plain: StegoImage = Encoder::new()
+        .encode(gradient_image(64, 64), b"not sealed")
+        .unwrap();
+    assert!(matches!(
+        decoder().decode_encrypted(&plain, &passphrase()),
+        Err(HideError::InvalidParameters(_))
+    ));
+}
+
+#[test]
+fn test_both_suites_round_trip_and_are_recorded() {
+    for suite in [CipherSuite::Aes256Gcm, CipherSuite::ChaCha20Poly1305] {
+        let stego = Encoder::with_config(
+            EncoderConfig {
+                kdf: Some(SharedKdf::new(HashKdf)),
+                ..Default::default()
+            }
+            .cipher(suite),
+        )
+        .encode_encrypted(
+            gradient_image(64, 64),
+            b"pick the right cipher",
+            &passphrase(),
+        )
+        .unwrap();
+
+        // The decoder isn't told which suite was used
+        assert_eq!(
+            decoder().decode_encrypted(&stego, &passphrase()).unwrap(),
+            b"pick the right cipher"
+        );
+        let raw = extract_raw_data(&stego).unwrap();
+        assert_eq!(raw[EXTENDED_HEADER_SIZE], suite.id());
+    }
+}
+#[test]
+fn test_integrity_failures_look_like_a_wrong_passphrase() {
+    let config = EncoderConfig {
+        kdf: Some(SharedKdf::new(HashKdf)),
+        ..Default::default()
+    };
+    let stego = Encoder::with_config(config.checksum(true).pad_to(PaddingPolicy::Exact(128)))
+        .encode_encrypted(gradient_image(64, 64), &[b's'; 32], &passphrase())
+        .unwrap();
+    let skipping = || {
+        Decoder::with_config(DecoderConfig {
+            kdf: Some(SharedKdf::new(HashKdf)),
+            skip_checksum: true,
+            ..Default::default()
+        })
+    };
+
+    let wrong = decoder()
+        .decode_encrypted(&stego, &Secret::new("wrong horse"))
+        .unwrap_err();
+    assert!(matches!(wrong, HideError::DecryptionFailed));
+
+    // A ciphertext bit, caught by the checksum or else by the tag, and a bit
+    // of the padding trailer, caught by the checksum or else by the trailer
+    let mut ciphertext = stego.clone();
+    flip_payload_bit(&mut ciphertext, 40 * 8);
+    let mut trailer = stego.clone();
+    flip_payload_bit(&mut trailer, 120 * 8);
+    for (tampered, decoder) in [
+        (&ciphertext, decoder()),
+        (&ciphertext, skipping()),
+        (&trailer, decoder()),
+        (&trailer, skipping()),
+    ] {
+        let error = decoder
+            .decode_encrypted(tampered, &passphrase())
+            .unwrap_err();
+        assert!(matches!(error, HideError::DecryptionFailed), "{:?}", error);
+        assert_eq!(error.to_string(), wrong.to_string());
+    }
+    assert_eq!(
+        decoder().decode_encrypted(&stego, &passphrase()).unwrap(),
+        [b's'; 32]
+    );
+}
+
+#[test]
+fn test_cross_cipher_and_unknown_cipher_failures() {
+    let chacha = || {
+        Encoder::with_config(
+            EncoderConfig {
+                kdf: Some(SharedKdf::new(HashKdf)),
+                ..Default::default()
+            }
+            .cipher(CipherSuite::ChaCha20Poly1305),
+        )
+        .encode_encrypted(gradient_image(64, 64), b"sealed with chacha", &passphrase())
+        .unwrap()
+    };
+
+    // ChaCha20 (ID 2) read as AES-256-GCM (ID 1) fails authentication
+    let mut relabelled = chacha();
+    flip_payload_bit(&mut relabelled, 6);
+    flip_payload_bit(&mut relabelled, 7);
+    assert!(matches!(
+        decoder().decode_encrypted(&relabelled, &passphrase()),
+        Err(HideError::DecryptionFailed)
+    ));
+
+    // An ID no suite has fails the same way
+    let mut unknown = chacha();
+    flip_payload_bit(&mut unknown, 0);
+    assert!(matches!(
+        decoder().decode_encrypted(&unknown, &passphrase()),
+        Err(HideError::DecryptionFailed)
+    ));
+}
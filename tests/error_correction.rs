@@ -0,0 +1,306 @@
+use hide_rs::decoder::Decoder;
+use hide_rs::ecc::{self, EccParams, ErrorCorrection};
+use hide_rs::encoder::{self, Encoder, EncoderConfig};
+use hide_rs::error::HideError;
+use hide_rs::extract_raw_data;
+use hide_rs::header::{EXTENDED_HEADER_SIZE, EXTENSION_SIZE};
+use hide_rs::img::{FillStyle, StegoImage};
+use hide_rs::padding::PaddingPolicy;
+use hide_rs::testing::gradient_image;
+use image::DynamicImage;
+use std::process::Command;
+use tempfile::tempdir;
+
+// 96 bytes make three full blocks of 32 data and 8 parity bytes, after the
+// 6 bytes holding the block layout
+const PARAMS: EccParams = EccParams {
+    data_shards: 32,
+    parity_shards: 8,
+};
+
+fn message() -> Vec<u8> {
+    (0..96u8).map(|i| b'a' + i % 26).collect()
+}
+
+fn corrected() -> EncoderConfig {
+    EncoderConfig::default().error_correction(PARAMS)
+}
+
+// Helper flipping a pixel whose three bits all lie in one payload byte, and
+// checking that exactly the given payload bytes changed
+fn corrupt_payload(image: &mut StegoImage, offsets: &[usize]) {
+    let before = extract_raw_data(image).unwrap();
+    for &offset in offsets {
+        let pixel = (((EXTENDED_HEADER_SIZE + offset) * 8 + 3) / 3) as u32;
+        let (x, y) = (pixel % image.width(), pixel / image.width());
+        let bit = image.get_lsb(x, y, 0).unwrap();
+        image.set_lsb(x, y, 0, !bit).unwrap();
+    }
+
+    let after = extract_raw_data(image).unwrap();
+    let changed: Vec<usize> = (EXTENDED_HEADER_SIZE..before.len())
+        .filter(|&i| before[i] != after[i])
+        .map(|i| i - EXTENDED_HEADER_SIZE)
+        .collect();
+    assert_eq!(changed, offsets);
+}
+
+#[test]
+fn test_error_correction_round_trips() {
+    let message = message();
+    let (stego, stats) = Encoder::with_config(corrected())
+        .encode_with_stats(gradient_image(32, 32), &message)
+        .unwrap();
+
+    let header = Decoder::new().read_header(&stego).unwrap();
+    assert!(header.is_error_corrected());
+    assert_eq!(
+        header.message_length as usize,
+        ecc::encoded_length(message.len(), PARAMS)
+    );
+    assert_eq!(stats.error_correction_bytes, ecc::PARAMS_SIZE + 3 * 8);
+    assert_eq!(Decoder::new().decode(&stego).unwrap(), message);
+
+    let (plain, stats) = Encoder::new()
+        .encode_with_stats(gradient_image(32, 32), &message)
+        .unwrap();
+    assert!(!Decoder::new()
+        .read_header(&plain)
+        .unwrap()
+        .is_error_corrected());
+    assert_eq!(stats.error_correction_bytes, 0);
+}
+
+#[test]
+fn test_corrupted_bytes_are_repaired() {
+    let message = message();
+    let mut stego = Encoder::with_config(corrected())
+        .encode(gradient_image(32, 32), &message)
+        .unwrap();
+
+    // Four bytes in every block, parity included, and one in the block layout
+    corrupt_payload(
+        &mut stego,
+        &[2, 8, 20, 30, 45, 50, 60, 70, 85, 90, 100, 110, 125],
+    );
+    assert_eq!(Decoder::new().decode(&stego).unwrap(), message);
+
+    // The same damage without parity changes the message
+    let mut plain = Encoder::new()
+        .encode(gradient_image(32, 32), &message)
+        .unwrap();
+    corrupt_payload(&mut plain, &[8, 20]);
+    assert_ne!(Decoder::new().decode(&plain).unwrap(), message);
+}
+
+#[test]
+fn test_over_corrupted_payloads_fail_clearly() {
+    let mut stego = Encoder::with_config(corrected())
+        .encode(gradient_image(32, 32), &message())
+        .unwrap();
+
+    // Five bytes in the second block, one more than its parity repairs
+    corrupt_payload(&mut stego, &[50, 55, 60, 65, 70]);
+    let err = Decoder::new().decode(&stego).unwrap_err();
+    assert!(matches!(err, HideError::UncorrectableErrors { block: 1 }));
+    assert!(err
+        .to_string()
+        .contains("more corrupted bytes than error correction can repair"));
+
+    // Repairs come before the checksum is checked
+    let mut stego = Encoder::with_config(corrected().checksum(true))
+        .encode(gradient_image(32, 32), &message())
+        .unwrap();
+    corrupt_payload(&mut stego, &[10, 15, 20]);
+    assert_eq!(Decoder::new().decode(&stego).unwrap(), message());
+}
+
+#[test]
+fn test_error_correction_combines_with_other_options() {
+    let rgba_cover = || {
+        StegoImage::from_dynamic_image(DynamicImage::ImageRgba8(
+            gradient_image(64, 64).inner().to_rgba8(),
+        ))
+    };
+    let message = "Meet at the old mill at noon. ".repeat(8).into_bytes();
+    let configs = [
+        EncoderConfig::default().error_correction(EccParams::default()),
+        corrected().checksum(true).compress(true),
+        EncoderConfig {
+            record_dimensions: true,
+            embed_alpha: true,
+            ..corrected()
+        },
+        corrected().pixel_key(b"parity"),
+    ];
+    for config in configs {
+        let keyed = config.pixel_key.is_some();
+        let stego = Encoder::with_config(config.verify_roundtrip(true))
+            .encode(rgba_cover(), &message)
+            .unwrap();
+        let decoder = if keyed {
+            Decoder::with_key(b"parity")
+        } else {
+            Decoder::new()
+        };
+        assert!(decoder.read_header(&stego).unwrap().is_error_corrected());
+        assert_eq!(decoder.decode(&stego).unwrap(), message);
+    }
+
+    let stego = Encoder::with_config(EncoderConfig {
+        record_dimensions: true,
+        ..corrected()
+    })
+    .encode(gradient_image(40, 30), &message)
+    .unwrap();
+    assert_eq!(Decoder::new().recorded_dimensions(&stego), Some((40, 30)));
+
+    let padded = corrected().pad_to(PaddingPolicy::NextPowerOfTwo);
+    assert!(matches!(
+        Encoder::with_config(padded).encode(gradient_image(32, 32), &message),
+        Err(HideError::InvalidParameters(_))
+    ));
+}
+
+#[test]
+fn test_parity_counts_against_capacity() {
+    let cover = gradient_image(32, 32);
+    let encoder = Encoder::with_config(corrected());
+    let capacity = encoder.max_message_size(&cover);
+    assert_eq!(
+        capacity,
+        ecc::max_data_length(
+            Encoder::new().max_message_size(&cover) - EXTENSION_SIZE,
+            PARAMS
+        )
+    );
+
+    let breakdown = encoder::capacity_breakdown(&cover, &corrected());
+    assert_eq!(breakdown.max_message_bytes, capacity);
+    assert_eq!(
+        breakdown.max_message_bytes + breakdown.overhead_bytes(),
+        breakdown.raw_bytes
+    );
+    assert!(breakdown
+        .overheads
+        .iter()
+        .any(|overhead| overhead.name == "error_correction"));
+
+    let message = vec![b'e'; capacity];
+    let stego = encoder.encode(cover.clone(), &message).unwrap();
+    assert_eq!(Decoder::new().decode(&stego).unwrap(), message);
+    assert!(matches!(
+        encoder.encode(cover.clone(), &[message, vec![b'e']].concat()),
+        Err(HideError::MessageTooLarge)
+    ));
+
+    // Extended covers grow to fit the parity too
+    let extending = Encoder::with_config(EncoderConfig {
+        auto_extend: Some(FillStyle::Mirror),
+        ..corrected()
+    });
+    let long = vec![b'x'; capacity * 2];
+    let stego = extending.encode(cover, &long).unwrap();
+    assert_eq!(Decoder::new().decode(&stego).unwrap(), long);
+}
+
+#[test]
+fn test_hamming_repairs_a_flipped_bit_per_codeword() {
+    let message = message();
+    let config = EncoderConfig::default().error_correction(ErrorCorrection::Hamming74);
+    let (stego, stats) = Encoder::with_config(config.clone())
+        .encode_with_stats(gradient_image(32, 32), &message)
+        .unwrap();
+    let header = Decoder::new().read_header(&stego).unwrap();
+    assert!(header.is_error_corrected());
+    assert_eq!(header.message_length as usize, ecc::PARAMS_SIZE + 168);
+    assert_eq!(stats.error_correction_bytes, ecc::PARAMS_SIZE + 72);
+
+    // Every nibble takes 7 bits instead of 4
+    let cover = gradient_image(32, 32);
+    let capacity = Encoder::with_config(config).max_message_size(&cover);
+    let plain_capacity = Encoder::new().max_message_size(&cover);
+    assert_eq!(
+        capacity,
+        (plain_capacity - EXTENSION_SIZE - ecc::PARAMS_SIZE) * 8 / 14
+    );
+
+    // One flipped bit in every codeword of the extracted stream, each at a
+    // different position in its codeword
+    let mut raw = extract_raw_data(&stego).unwrap();
+    raw.truncate(EXTENDED_HEADER_SIZE + header.message_length as usize);
+    let start = (EXTENDED_HEADER_SIZE + ecc::PARAMS_SIZE) * 8;
+    for codeword in 0..message.len() * 2 {
+        let bit = start + codeword * 7 + codeword % 7;
+        raw[bit / 8] ^= 0x80 >> (bit % 8);
+    }
+    let damaged = Encoder::new()
+        .encode_message(gradient_image(32, 32), &raw)
+        .unwrap();
+    assert_eq!(Decoder::new().decode(&damaged).unwrap(), message);
+
+    // Without parity the same kind of damage changes the message
+    let mut plain = Encoder::new()
+        .encode(gradient_image(32, 32), &message)
+        .unwrap();
+    corrupt_payload(&mut plain, &[8]);
+    assert_ne!(Decoder::new().decode(&plain).unwrap(), message);
+}
+
+#[test]
+fn test_cli_ecc() {
+    let temp_dir = tempdir().unwrap();
+    let cover = temp_dir.path().join("cover.png");
+    gradient_image(32, 32).save(&cover).unwrap();
+    let output = temp_dir.path().join("stego.png");
+    let message = String::from_utf8(message()).unwrap();
+
+    let encode = Command::new(env!("CARGO_BIN_EXE_hide"))
+        .args(["encode", "--ecc", "32:8", "-m", &message, "-i"])
+        .arg(&cover)
+        .arg("-o")
+        .arg(&output)
+        .output()
+        .unwrap();
+    assert!(
+        encode.status.success(),
+        "{}",
+        String::from_utf8_lossy(&encode.stderr)
+    );
+
+    let mut stego = StegoImage::from_file(&output).unwrap();
+    corrupt_payload(&mut stego, &[8, 20, 30]);
+    stego.save(&output).unwrap();
+    let decode = Command::new(env!("CARGO_BIN_EXE_hide"))
+        .args(["decode", "-i"])
+        .arg(&output)
+        .output()
+        .unwrap();
+    assert!(decode.status.success());
+    assert!(String::from_utf8_lossy(&decode.stdout).contains(&message));
+
+    let invalid = Command::new(env!("CARGO_BIN_EXE_hide"))
+        .args(["encode", "--ecc", "250:10", "-m", "x", "-i"])
+        .arg(&cover)
+        .arg("-o")
+        .arg(&output)
+        .output()
+        .unwrap();
+    assert!(!invalid.status.success());
+    assert!(String::from_utf8_lossy(&invalid.stderr).contains("at most 255 bytes"));
+
+    let encode = Command::new(env!("CARGO_BIN_EXE_hide"))
+        .args(["encode", "--ecc", "hamming74", "-m", &message, "-i"])
+        .arg(&cover)
+        .arg("-o")
+        .arg(&output)
+        .output()
+        .unwrap();
+    assert!(encode.status.success());
+    let decode = Command::new(env!("CARGO_BIN_EXE_hide"))
+        .args(["decode", "-i"])
+        .arg(&output)
+        .output()
+        .unwrap();
+    assert!(String::from_utf8_lossy(&decode.stdout).contains(&message));
+}
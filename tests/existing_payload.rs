@@ -0,0 +1,50 @@
+use hide_rs::decoder::Decoder;
+use hide_rs::encoder::{Encoder, EncoderConfig};
+use hide_rs::error::HideError;
+use hide_rs::img::create_rgb_image;
+use hide_rs::raw_decoder::extract_raw_data;
+
+#[test]
+fn test_detect_existing_payload() {
+    let decoder = Decoder::new();
+    let cover = create_rgb_image(40, 40).unwrap();
+    assert!(decoder.detect(&cover).is_none());
+
+    let stego = Encoder::new().encode(cover, b"first message").unwrap();
+    let header = decoder.detect(&stego).unwrap();
+    assert_eq!(header.message_length, 13);
+}
+
+#[test]
+fn test_double_encode_requires_overwrite() {
+    let stego = Encoder::new()
+        .encode(create_rgb_image(40, 40).unwrap(), b"first message")
+        .unwrap();
+
+    assert!(matches!(
+        Encoder::new().encode(stego, b"second"),
+        Err(HideError::ExistingPayload {
+            declared_length: 13
+        })
+    ));
+}
+
+#[test]
+fn test_overwrite_scrubs_old_payload() {
+    let first = vec![b'A'; 200];
+    let stego = Encoder::new()
+        .encode(create_rgb_image(40, 40).unwrap(), &first)
+        .unwrap();
+
+    let encoder = Encoder::with_config(EncoderConfig {
+        overwrite_existing: true,
+        ..Default::default()
+    });
+    let restego = encoder.encode(stego, b"hi").unwrap();
+    assert_eq!(Decoder::new().decode(&restego).unwrap(), b"hi");
+
+    // Nothing of the first message survives past the end of the new payload
+    let raw = extract_raw_data(&restego).unwrap();
+    assert_eq!(&raw[8..10], b"hi");
+    assert!(raw[10..8 + first.len()].iter().all(|&byte| byte == 0));
+}
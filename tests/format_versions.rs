@@ -0,0 +1,161 @@
+use hide_rs::decoder::Decoder;
+use hide_rs::encoder::{Encoder, EncoderConfig};
+use hide_rs::error::HideError;
+use hide_rs::header::{HeaderVersion, VersionedParser};
+use hide_rs::img::{create_rgb_image, StegoImage};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Stego images written by each released format version, named `v<N>_<case>.png`
+/// next to the message bytes they decode to in `v<N>_<case>.bin`
+const FIXTURE_DIR: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/fixtures/format_versions"
+);
+
+/// The fixture images and the version in their names
+fn fixtures() -> Vec<(u8, PathBuf)> {
+    let mut fixtures: Vec<_> = fs::read_dir(FIXTURE_DIR)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "png"))
+        .map(|path| (fixture_version(&path), path))
+        .collect();
+    fixtures.sort();
+    fixtures
+}
+
+/// The version a fixture was written with, from its file name
+fn fixture_version(path: &Path) -> u8 {
+    let name = path.file_stem().unwrap().to_str().unwrap();
+    let (version, _) = name
+        .strip_prefix('v')
+        .and_then(|rest| rest.split_once('_'))
+        .unwrap_or_else(|| panic!("fixture {} isn't named v<N>_<case>", name));
+    version.parse().unwrap()
+}
+
+#[test]
+fn test_every_fixture_decodes() {
+    let fixtures = fixtures();
+    assert!(!fixtures.is_empty());
+
+    for (version, path) in fixtures {
+        let stego = StegoImage::from_file(&path).unwrap();
+        let expected = fs::read(path.with_extension("bin")).unwrap();
+
+        let header = Decoder::new().read_header(&stego).unwrap();
+        assert_eq!(header.format_version, version, "{}", path.display());
+        assert_eq!(
+            Decoder::new().decode(&stego).unwrap(),
+            expected,
+            "{}",
+            path.display()
+        );
+    }
+}
+
+#[test]
+fn test_every_version_has_a_fixture_and_changelog_entry() {
+    let fixtures = fixtures();
+    for version in HeaderVersion::ALL {
+        assert!(
+            fixtures.iter().any(|(v, _)| *v == version.number()),
+            "no fixture for format version {}",
+            version.number()
+        );
+    }
+
+    let changelog: Vec<_> = VersionedParser::changelog().collect();
+    assert_eq!(changelog.len(), HeaderVersion::ALL.len());
+    assert!(changelog.iter().all(|(_, changes)| !changes.is_empty()));
+
+    // Fixtures only exist for versions the parser knows
+    for (version, path) in fixtures {
+        assert!(
+            HeaderVersion::from_byte(version).is_some(),
+            "{} names an unknown version",
+            path.display()
+        );
+    }
+}
+
+#[test]
+fn test_current_version_is_written_by_the_encoder() {
+    let stego = Encoder::new()
+        .encode(create_rgb_image(20, 20).unwrap(), b"now")
+        .unwrap();
+    let header = Decoder::new().read_header(&stego).unwrap();
+    assert_eq!(header.format_version, HeaderVersion::CURRENT.number());
+}
+
+#[test]
+fn test_flagged_payloads_are_written_as_version_2() {
+    let encoder = Encoder::with_config(EncoderConfig {
+        checksum: true,
+        ..Default::default()
+    });
+    let stego = encoder
+        .encode(create_rgb_image(20, 20).unwrap(), b"now")
+        .unwrap();
+    let header = Decoder::new().read_header(&stego).unwrap();
+    assert_eq!(header.format_version, HeaderVersion::V2.number());
+    assert!(header.is_checksummed());
+    assert_eq!(Decoder::new().decode(&stego).unwrap(), b"now");
+}
+
+#[test]
+fn test_longer_v2_extensions_are_skipped() {
+    // Version 2 with a 6-byte extension: no extra flags, no mask length and
+    // two bytes of a field this build doesn't know
+    let header_bytes = [2, 0, 0, 0, 5, 0, 0, 6, 0, 0, 0, 0, 0xAB, 0xCD];
+    let header = VersionedParser::parse(&header_bytes).unwrap();
+    assert_eq!(header.extension_length, 6);
+
+    let raw = [&header_bytes[..], b"hello"].concat();
+    let stego = Encoder::new()
+        .encode_message(create_rgb_image(20, 20).unwrap(), &raw)
+        .unwrap();
+    assert_eq!(Decoder::new().read_header(&stego).unwrap(), header);
+    assert_eq!(Decoder::new().decode(&stego).unwrap(), b"hello");
+}
+
+#[test]
+fn test_synthetic_v1_and_v2_headers_decode() {
+    // Version 1: version, length 5, no flags, no mask length
+    let v1 = &[1, 0, 0, 0, 5, 0, 0, 0][..];
+    // Version 2: a 4-byte extension with the flag for recorded dimensions,
+    // so the length counts a 20x20 cover's dimensions before the message
+    let v2 = &[2, 0, 0, 0, 9, 0, 0, 4, 0x80, 0, 0, 0][..];
+
+    for (header_bytes, prefix) in [(v1, &[][..]), (v2, &[0, 20, 0, 20][..])] {
+        let header = VersionedParser::parse(header_bytes).unwrap();
+        assert_eq!(header.to_bytes(), header_bytes);
+        assert_eq!(header.records_dimensions(), !prefix.is_empty());
+
+        let raw = [header_bytes, prefix, b"hello"].concat();
+        let stego = Encoder::new()
+            .encode_message(create_rgb_image(20, 20).unwrap(), &raw)
+            .unwrap();
+        assert_eq!(Decoder::new().read_header(&stego).unwrap(), header);
+        assert_eq!(Decoder::new().decode(&stego).unwrap(), b"hello");
+    }
+}
+
+#[test]
+fn test_unknown_versions_are_unsupported() {
+    let next = HeaderVersion::ALL.iter().map(|v| v.number()).max().unwrap() + 1;
+    let mut raw = vec![next, 0, 0, 0, 5, 0, 0, 0];
+    raw.extend_from_slice(b"later");
+    let stego = Encoder::new()
+        .encode_message(create_rgb_image(20, 20).unwrap(), &raw)
+        .unwrap();
+
+    let err = Decoder::new().decode(&stego).unwrap_err();
+    assert!(matches!(
+        err,
+        HideError::UnsupportedFeature { version, bits: 0 } if version == next
+    ));
+    assert!(err.to_string().contains(&format!("version {}", next)));
+    assert!(Decoder::new().detect(&stego).is_none());
+}
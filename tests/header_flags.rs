@@ -0,0 +1,121 @@
+use hide_rs::decoder::{Decoder, DecoderConfig, UntrustedLimits};
+use hide_rs::encoder::Encoder;
+use hide_rs::error::HideError;
+use hide_rs::header::{put_u16_be, HeaderFlags};
+use hide_rs::img::{create_rgb_image, StegoImage};
+use hide_rs::warning::StegoWarning;
+
+/// An extra flag bit no feature has claimed yet
+const RESERVED_BIT: u16 = 0x0001;
+
+// Embed a payload whose header sets the given flags byte
+fn stego_with_flags(flags: u8, message: &[u8]) -> StegoImage {
+    let mut raw = vec![1, 0, 0, 0, message.len() as u8, flags, 0, 0];
+    raw.extend_from_slice(message);
+    Encoder::new()
+        .encode_message(create_rgb_image(40, 40).unwrap(), &raw)
+        .unwrap()
+}
+
+// Embed a version 2 payload whose extension sets the given extra flag bits
+fn stego_with_extra_flags(flags: u8, extra_flags: u16, message: &[u8]) -> StegoImage {
+    let mut raw = vec![2, 0, 0, 0, message.len() as u8, flags, 0, 4];
+    put_u16_be(&mut raw, extra_flags);
+    put_u16_be(&mut raw, 0);
+    raw.extend_from_slice(message);
+    Encoder::new()
+        .encode_message(create_rgb_image(40, 40).unwrap(), &raw)
+        .unwrap()
+}
+
+#[test]
+fn test_every_flag_bit_is_registered() {
+    // The last free bit went to encryption; new features need a new format version
+    assert_eq!(HeaderFlags::all().bits(), 0xff);
+    let header = Decoder::new()
+        .read_header(&stego_with_flags(0xff, b""))
+        .unwrap();
+    assert_eq!(header.flags.unknown_bits(), 0);
+}
+
+#[test]
+fn test_encrypted_flag_is_refused_by_plain_decodes() {
+    let stego = stego_with_flags(HeaderFlags::ENCRYPTED.bits(), b"sealed bytes");
+    let decoder = Decoder::new();
+
+    assert!(matches!(
+        decoder.decode(&stego),
+        Err(HideError::EncryptedPayload)
+    ));
+    assert!(matches!(
+        decoder.decode_lossy(&stego),
+        Err(HideError::EncryptedPayload)
+    ));
+    assert!(matches!(
+        decoder.decode_untrusted(&stego, UntrustedLimits::default()),
+        Err(HideError::EncryptedPayload)
+    ));
+}
+
+#[test]
+fn test_known_flags_decode_normally() {
+    let stego = stego_with_flags(0, b"plain");
+    assert_eq!(Decoder::new().decode(&stego).unwrap(), b"plain");
+}
+
+#[test]
+fn test_unknown_flags_are_rejected_by_default() {
+    let decoder = Decoder::new();
+    // Version 1 keeps unknown bits where its extra flags live too
+    let mut legacy = vec![1, 0, 0, 0, 14, 0, 0, RESERVED_BIT as u8];
+    legacy.extend_from_slice(b"future payload");
+    let legacy = Encoder::new()
+        .encode_message(create_rgb_image(40, 40).unwrap(), &legacy)
+        .unwrap();
+
+    for (stego, version) in [
+        (
+            stego_with_extra_flags(0, RESERVED_BIT, b"future payload"),
+            2,
+        ),
+        (legacy, 1),
+    ] {
+        let header = decoder.read_header(&stego).unwrap();
+        assert_eq!(header.unknown_flag_bits(), RESERVED_BIT);
+
+        let unsupported = |result| {
+            matches!(
+                result,
+                Err(HideError::UnsupportedFeature { version: v, bits: RESERVED_BIT }) if v == version
+            )
+        };
+        assert!(unsupported(decoder.decode(&stego)));
+        assert!(unsupported(
+            decoder.decode_lossy(&stego).map(|decoded| decoded.data)
+        ));
+        assert!(unsupported(
+            decoder.decode_untrusted(&stego, UntrustedLimits::default())
+        ));
+    }
+}
+
+#[test]
+fn test_unknown_flags_can_be_ignored() {
+    let stego = stego_with_extra_flags(HeaderFlags::CONTAINER.bits(), RESERVED_BIT, b"raw bytes");
+    let decoder = Decoder::with_config(DecoderConfig {
+        ignore_unknown_flags: true,
+        ..Default::default()
+    });
+
+    assert_eq!(decoder.decode(&stego).unwrap(), b"raw bytes");
+
+    let detailed = decoder.decode_detailed(&stego, false).unwrap();
+    assert_eq!(detailed.decoded.data, b"raw bytes");
+    assert_eq!(
+        detailed.warnings,
+        [
+            StegoWarning::UnknownFlagsIgnored { bits: RESERVED_BIT },
+            StegoWarning::RawContainerBytes
+        ]
+    );
+}
@@ -0,0 +1,142 @@
+use hide_rs::decoder::{Decoder, DecoderConfig};
+use hide_rs::encoder::{Encoder, EncoderConfig};
+use hide_rs::header::EXTENDED_HEADER_SIZE;
+use hide_rs::img::StegoImage;
+use hide_rs::padding::PaddingPolicy;
+use hide_rs::testing::{gradient_image, noise_image};
+use std::process::Command;
+use tempfile::tempdir;
+
+const MESSAGE: &[u8] = b"the payload survived, only the header didn't";
+
+// Helper to encode a padded message, whose trailer CRC lets repairs be checked
+fn padded_stego() -> StegoImage {
+    Encoder::with_config(EncoderConfig::default().pad_to(PaddingPolicy::Exact(96)))
+        .encode(gradient_image(40, 40), MESSAGE)
+        .unwrap()
+}
+
+// Helper to flip one decoded header bit by changing the LSBs of the pixel carrying it
+fn flip_header_bit(image: &mut StegoImage, bit: usize) {
+    let decoder = Decoder::new();
+    let pixel = (bit / 3) as u32;
+    let (x, y) = (pixel % image.width(), pixel / image.width());
+    let original = image.get_pixel_rgb(x, y).unwrap();
+    let bits = decoder.decode_pixel(original[0], original[1], original[2]);
+
+    // Pick the LSB changes that flip exactly this bit of the pixel's three
+    for lsbs in 1..8u8 {
+        let mut changed = original;
+        for channel in 0..3 {
+            changed[channel] ^= (lsbs >> channel) & 1;
+        }
+        let changed_bits = decoder.decode_pixel(changed[0], changed[1], changed[2]);
+        let differs: Vec<usize> = (0..3).filter(|&i| bits[i] != changed_bits[i]).collect();
+        if differs == [bit % 3] {
+            image.set_pixel_rgb(x, y, changed).unwrap();
+            return;
+        }
+    }
+    panic!("no LSB change flips bit {} alone", bit);
+}
+
+#[test]
+fn test_every_single_bit_flip_is_repaired() {
+    let stego = padded_stego();
+
+    for bit in 0..EXTENDED_HEADER_SIZE * 8 {
+        let mut damaged = stego.clone();
+        flip_header_bit(&mut damaged, bit);
+
+        let decoded = Decoder::new().decode_with_repair(&damaged).unwrap();
+        assert_eq!(decoded.data, MESSAGE, "bit {}", bit);
+
+        // Flags that don't change how the payload is read can't be told apart
+        assert!(
+            decoded.repaired_bits.is_empty() || decoded.repaired_bits == [bit],
+            "bit {}: {:?}",
+            bit,
+            decoded.repaired_bits
+        );
+
+        // The version and length always matter
+        if bit < 40 {
+            assert_eq!(decoded.repaired_bits, [bit]);
+        }
+    }
+}
+
+#[test]
+fn test_intact_header_is_not_repaired() {
+    let decoded = Decoder::new().decode_with_repair(&padded_stego()).unwrap();
+    assert_eq!(decoded.data, MESSAGE);
+    assert!(decoded.repaired_bits.is_empty());
+}
+
+#[test]
+fn test_bit_pairs_need_the_flag() {
+    let mut damaged = padded_stego();
+    flip_header_bit(&mut damaged, 10);
+    flip_header_bit(&mut damaged, 30);
+
+    assert!(Decoder::new().decode_with_repair(&damaged).is_err());
+
+    let decoder = Decoder::with_config(DecoderConfig {
+        repair_bit_pairs: true,
+        ..Default::default()
+    });
+    let decoded = decoder.decode_with_repair(&damaged).unwrap();
+    assert_eq!(decoded.data, MESSAGE);
+    assert_eq!(decoded.repaired_bits, [10, 30]);
+}
+
+#[test]
+fn test_garbage_is_not_repaired_into_a_message() {
+    let decoder = Decoder::with_config(DecoderConfig {
+        repair_bit_pairs: true,
+        ..Default::default()
+    });
+    for seed in 0..4 {
+        assert!(decoder
+            .decode_with_repair(&noise_image(40, 40, seed))
+            .is_err());
+    }
+
+    // A repaired header still has to match the payload's CRC
+    let mut damaged = padded_stego();
+    flip_header_bit(&mut damaged, 20);
+    for bit in 200..203 {
+        flip_header_bit(&mut damaged, bit);
+    }
+    assert!(Decoder::new().decode_with_repair(&damaged).is_err());
+}
+
+#[test]
+fn test_cli_repair_header() {
+    let temp_dir = tempdir().unwrap();
+    let path = temp_dir.path().join("damaged.png");
+    let mut damaged = padded_stego();
+    flip_header_bit(&mut damaged, 12);
+    damaged.save(&path).unwrap();
+
+    let plain = Command::new(env!("CARGO_BIN_EXE_hide"))
+        .args(["decode", "-i"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(!plain.status.success());
+
+    let repaired = Command::new(env!("CARGO_BIN_EXE_hide"))
+        .args(["decode", "--repair-header", "-i"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(
+        repaired.status.success(),
+        "{}",
+        String::from_utf8_lossy(&repaired.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&repaired.stdout);
+    assert!(stdout.contains("Repaired header bit(s): 12"), "{}", stdout);
+    assert!(stdout.contains("the payload survived"), "{}", stdout);
+}
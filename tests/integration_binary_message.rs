@@ -0,0 +1,84 @@
+use actix_web::{test, App};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use hide_rs::api::handlers::ServerConfig;
+use hide_rs::api::routes::configure_routes;
+use hide_rs::img::create_rgb_image;
+use std::fs;
+use tempfile::tempdir;
+
+mod common;
+
+use common::{content_type, create_state_with, multipart, BOUNDARY};
+
+#[actix_web::test]
+async fn test_binary_messages_round_trip() {
+    // Create a temporary directory for the test
+    let temp_dir = tempdir().unwrap();
+    let upload_dir = temp_dir.path().to_path_buf();
+
+    // Create a cover image
+    let cover_path = upload_dir.join("cover.png");
+    create_rgb_image(100, 100)
+        .unwrap()
+        .save(&cover_path)
+        .unwrap();
+    let cover = fs::read(&cover_path).unwrap();
+
+    // Create test application
+    let state = create_state_with(
+        &upload_dir,
+        ServerConfig {
+            decode_cache_entries: 0,
+            ..Default::default()
+        },
+    );
+    let app = test::init_service(App::new().app_data(state).configure(configure_routes)).await;
+
+    let messages: Vec<Vec<u8>> = vec![
+        b"nul\0in\0the\0middle\0".to_vec(),
+        vec![0xFF; 64],
+        [b"text then ".as_slice(), &[0xFF, 0xFE, 0x00, 0x80]].concat(),
+        // Looks like the start of a boundary but isn't one
+        format!("\r\n--{}X\r\n", &BOUNDARY[..BOUNDARY.len() - 1]).into_bytes(),
+        b"ends with a line break\r\n".to_vec(),
+        b"\r\n\r\n--\r\n".to_vec(),
+    ];
+
+    for message in messages {
+        // Encode the message
+        let req = test::TestRequest::post()
+            .uri("/api/v1/encode")
+            .insert_header(("content-type", content_type()))
+            .set_payload(multipart(&[
+                ("cover_image", Some("cover.png"), &cover),
+                ("message", None, &message),
+            ]))
+            .to_request();
+        let encoded: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(encoded["status"], "success", "Encode failed: {}", encoded);
+        assert_eq!(
+            encoded["metadata"]["embedded_message_bytes"],
+            message.len(),
+            "Wrong length for {:?}",
+            message
+        );
+
+        // Fetch the stego image and decode it
+        let req = test::TestRequest::get()
+            .uri(encoded["download_url"].as_str().unwrap())
+            .to_request();
+        let stego = test::call_and_read_body(&app, req).await;
+
+        let req = test::TestRequest::post()
+            .uri("/api/v1/decode")
+            .insert_header(("content-type", content_type()))
+            .set_payload(multipart(&[("stego_image", Some("stego.png"), &stego)]))
+            .to_request();
+        let decoded: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+
+        let recovered = BASE64
+            .decode(decoded["binary_message"].as_str().unwrap())
+            .unwrap();
+        assert_eq!(recovered, message);
+    }
+}
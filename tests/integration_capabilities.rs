@@ -0,0 +1,40 @@
+use actix_web::{test, App};
+use hide_rs::api::handlers::ServerConfig;
+use hide_rs::api::routes::configure_routes;
+use hide_rs::Capabilities;
+use tempfile::tempdir;
+
+mod common;
+
+use common::create_state_with;
+
+#[actix_web::test]
+async fn test_capabilities_endpoint() {
+    let temp_dir = tempdir().unwrap();
+    let state = create_state_with(
+        temp_dir.path(),
+        ServerConfig {
+            decode_max_output_bytes: 4096,
+            ..Default::default()
+        },
+    );
+    let app = test::init_service(App::new().app_data(state).configure(configure_routes)).await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/capabilities")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+
+    let body = test::read_body(resp).await;
+    let capabilities: Capabilities = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(capabilities.version, hide_rs::VERSION);
+    assert_eq!(capabilities.features, hide_rs::capabilities().features);
+    assert!(capabilities.supports("streams"));
+    assert!(!capabilities.supports("encryption"));
+
+    let limits = capabilities.limits.expect("Missing server limits");
+    assert_eq!(limits.decode_max_output_bytes, 4096);
+    assert!(limits.max_message_bytes > 0);
+}
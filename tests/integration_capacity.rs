@@ -0,0 +1,65 @@
+use actix_web::{test, App};
+use hide_rs::api::routes::configure_routes;
+use hide_rs::testing::gradient_image;
+use image::ImageFormat;
+use tempfile::tempdir;
+
+mod common;
+
+use common::{create_state, post_multipart, upload_multipart};
+
+#[actix_web::test]
+async fn test_capacity_endpoint_applies_options() {
+    let temp_dir = tempdir().unwrap();
+    let upload_dir = temp_dir.path().to_path_buf();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(create_state(&upload_dir))
+            .configure(configure_routes),
+    )
+    .await;
+
+    // Without options only the header is taken
+    let resp = test::call_service(&app, capacity_request(&[]).to_request()).await;
+    assert!(resp.status().is_success());
+    let json: serde_json::Value = serde_json::from_slice(&test::read_body(resp).await).unwrap();
+    assert_eq!(json["width"], 40);
+    assert_eq!(json["capacity"]["raw_bytes"], 600);
+    assert_eq!(json["capacity"]["max_message_bytes"], 592);
+
+    // A 10% change budget leaves room for 52 bytes
+    let resp = test::call_service(
+        &app,
+        capacity_request(&[("max_change", "10%")]).to_request(),
+    )
+    .await;
+    assert!(resp.status().is_success());
+    let json: serde_json::Value = serde_json::from_slice(&test::read_body(resp).await).unwrap();
+    assert_eq!(json["capacity"]["max_message_bytes"], 52);
+    assert_eq!(json["capacity"]["overheads"][1]["name"], "change_budget");
+    assert_eq!(json["capacity"]["overheads"][1]["bytes"], 540);
+
+    // Invalid options are rejected like they are when encoding
+    let resp = test::call_service(
+        &app,
+        capacity_request(&[("max_change", "150%")]).to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 400);
+    let json: serde_json::Value = serde_json::from_slice(&test::read_body(resp).await).unwrap();
+    assert_eq!(json["details"]["field_errors"][0]["field"], "max_change");
+}
+
+// Helper to build a capacity request for a 40x40 image with extra text fields
+fn capacity_request(fields: &[(&str, &str)]) -> test::TestRequest {
+    let mut cover = std::io::Cursor::new(Vec::new());
+    gradient_image(40, 40)
+        .inner()
+        .write_to(&mut cover, ImageFormat::Png)
+        .unwrap();
+    post_multipart(
+        "/api/v1/capacity",
+        upload_multipart("cover_image", "cover.png", cover.get_ref(), fields),
+    )
+}
@@ -0,0 +1,49 @@
+use actix_web::{test, App};
+use hide_rs::api::routes::configure_routes;
+use hide_rs::decoder::Decoder;
+use hide_rs::img::StegoImage;
+use hide_rs::testing::gradient_image;
+use tempfile::tempdir;
+
+mod common;
+
+use common::{create_state, encode_request};
+
+#[actix_web::test]
+async fn test_encode_endpoint_reports_both_sizes() {
+    let temp_dir = tempdir().unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(create_state(temp_dir.path()))
+            .configure(configure_routes),
+    )
+    .await;
+    let cover_path = temp_dir.path().join("cover.png");
+    gradient_image(40, 40).save(&cover_path).unwrap();
+    // Too long for the 40x40 cover unless it is compressed
+    let message = "Meet at the old mill at noon; bring the maps. ".repeat(20);
+
+    let resp = test::call_service(
+        &app,
+        encode_request(Some(&cover_path), &message, &[("compress", "false")]).to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 400);
+    let json: serde_json::Value = serde_json::from_slice(&test::read_body(resp).await).unwrap();
+    assert_eq!(json["error_code"], "message_too_large");
+
+    let resp = test::call_service(
+        &app,
+        encode_request(Some(&cover_path), &message, &[("compress", "true")]).to_request(),
+    )
+    .await;
+    assert!(resp.status().is_success());
+    let json: serde_json::Value = serde_json::from_slice(&test::read_body(resp).await).unwrap();
+    let metadata = &json["metadata"];
+    assert_eq!(metadata["embedded_message_bytes"], message.len());
+    assert!(metadata["stored_message_bytes"].as_u64().unwrap() < message.len() as u64 / 4);
+
+    let image_id = json["image_id"].as_str().unwrap();
+    let stego = StegoImage::from_file(temp_dir.path().join(format!("{}.png", image_id))).unwrap();
+    assert_eq!(Decoder::new().decode(&stego).unwrap(), message.as_bytes());
+}
@@ -0,0 +1,133 @@
+use actix_web::dev::ServiceResponse;
+use actix_web::{test, App};
+use hide_rs::api::handlers::ServerConfig;
+use hide_rs::api::routes::configure_routes;
+use hide_rs::img::create_rgb_image;
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+mod common;
+
+use common::{create_state, create_state_with, encode_request};
+
+#[actix_web::test]
+async fn test_cover_pool() {
+    // Create a temporary directory for uploads and a pool with two covers
+    let temp_dir = tempdir().unwrap();
+    let upload_dir = temp_dir.path().join("uploads");
+    let pool_dir = temp_dir.path().join("covers");
+    fs::create_dir_all(&upload_dir).unwrap();
+    fs::create_dir_all(&pool_dir).unwrap();
+    create_rgb_image(20, 20)
+        .unwrap()
+        .save(pool_dir.join("small.png"))
+        .unwrap();
+    create_rgb_image(100, 100)
+        .unwrap()
+        .save(pool_dir.join("large.png"))
+        .unwrap();
+    let pool_files_before = read_pool(&pool_dir);
+
+    // Create test application
+    let state = create_state_with(
+        &upload_dir,
+        ServerConfig {
+            cover_pool_dir: Some(pool_dir.to_string_lossy().to_string()),
+            ..Default::default()
+        },
+    );
+    let app = test::init_service(App::new().app_data(state).configure(configure_routes)).await;
+
+    // The listing reports each cover's capacity, smallest first
+    let req = test::TestRequest::get().uri("/api/v1/covers").to_request();
+    let listing: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    let covers = listing["covers"].as_array().unwrap();
+    assert_eq!(covers.len(), 2);
+    assert_eq!(covers[0]["id"], "small.png");
+    assert_eq!(covers[0]["metadata"]["max_message_bytes"], 142);
+    assert_eq!(covers[1]["id"], "large.png");
+    assert_eq!(covers[1]["metadata"]["width"], 100);
+
+    // Explicit and automatic selection
+    for (cover_id, message_len, expected_width) in [
+        ("large.png", 10, 100),
+        ("auto", 10, 20),
+        ("auto", 1000, 100),
+    ] {
+        let resp = test::call_service(
+            &app,
+            encode_request(None, &"m".repeat(message_len), &[("cover_id", cover_id)]).to_request(),
+        )
+        .await;
+        let (status, json) = status_and_json(resp).await;
+        assert_eq!(status, 200, "Encode failed for {}: {}", cover_id, json);
+        assert_eq!(json["metadata"]["width"], expected_width);
+    }
+
+    // No cover is large enough
+    let resp = test::call_service(
+        &app,
+        encode_request(None, &"m".repeat(5000), &[("cover_id", "auto")]).to_request(),
+    )
+    .await;
+    let (status, json) = status_and_json(resp).await;
+    assert_eq!(status, 400);
+    assert_eq!(json["error_code"], "message_too_large");
+
+    // Unknown covers are not found
+    let resp = test::call_service(
+        &app,
+        encode_request(None, "hello", &[("cover_id", "../small.png")]).to_request(),
+    )
+    .await;
+    let (status, json) = status_and_json(resp).await;
+    assert_eq!(status, 404);
+    assert_eq!(json["error_code"], "not_found");
+
+    // The pool files are never modified
+    assert_eq!(read_pool(&pool_dir), pool_files_before);
+}
+
+#[actix_web::test]
+async fn test_cover_pool_disabled() {
+    let temp_dir = tempdir().unwrap();
+    let state = create_state(temp_dir.path());
+    let app = test::init_service(App::new().app_data(state).configure(configure_routes)).await;
+
+    let req = test::TestRequest::get().uri("/api/v1/covers").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+
+    let resp = test::call_service(
+        &app,
+        encode_request(None, "hello", &[("cover_id", "auto")]).to_request(),
+    )
+    .await;
+    let (status, json) = status_and_json(resp).await;
+    assert_eq!(status, 400);
+    assert_eq!(json["error_code"], "validation_error");
+}
+
+// Helper to read the status and JSON body of a response
+async fn status_and_json(resp: ServiceResponse) -> (u16, serde_json::Value) {
+    let status = resp.status().as_u16();
+    let body = test::read_body(resp).await;
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+// Read every file in the pool directory
+fn read_pool(dir: &Path) -> Vec<(String, Vec<u8>)> {
+    let mut files: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| {
+            let path = entry.unwrap().path();
+            (
+                path.file_name().unwrap().to_string_lossy().to_string(),
+                fs::read(&path).unwrap(),
+            )
+        })
+        .collect();
+    files.sort();
+    files
+}
@@ -0,0 +1,74 @@
+use actix_web::{test, App};
+use hide_rs::api::handlers::ServerConfig;
+use hide_rs::api::models::DebugInfo;
+use hide_rs::api::routes::configure_routes;
+use std::fs;
+use tempfile::tempdir;
+
+mod common;
+
+use common::{create_state_with, file_multipart, post_multipart};
+
+const MESSAGE: &str = "nobody should see this in a debug trace";
+
+#[actix_web::test]
+async fn test_debug_block_needs_the_config_flag_and_the_option() {
+    let temp_dir = tempdir().unwrap();
+    let cover_path = temp_dir.path().join("cover.png");
+    image::RgbImage::new(64, 64).save(&cover_path).unwrap();
+
+    for (allowed, requested) in [(false, false), (false, true), (true, false), (true, true)] {
+        let state = create_state_with(
+            &temp_dir.path().join("uploads"),
+            ServerConfig {
+                allow_debug_responses: allowed,
+                ..Default::default()
+            },
+        );
+        let app = test::init_service(App::new().app_data(state).configure(configure_routes)).await;
+
+        let mut fields = vec![("message", MESSAGE)];
+        if requested {
+            fields.push(("debug", "true"));
+        }
+        let req = post_multipart(
+            "/api/v1/encode",
+            file_multipart("cover_image", &cover_path, &fields),
+        )
+        .to_request();
+        let body = test::read_body(test::call_service(&app, req).await).await;
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["status"], "success", "{}", json);
+
+        if !(allowed && requested) {
+            assert!(
+                json.get("debug").is_none(),
+                "{:?}: {}",
+                (allowed, requested),
+                json
+            );
+            continue;
+        }
+
+        let debug: DebugInfo = serde_json::from_value(json["debug"].clone()).unwrap();
+        let names: Vec<_> = debug.fields.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["cover_image", "message", "debug"]);
+
+        let cover = &debug.fields[0];
+        assert_eq!(cover.bytes, fs::metadata(&cover_path).unwrap().len());
+        assert_eq!(cover.format.as_deref(), Some("png"));
+        assert_eq!(cover.temp_file.as_deref(), Some("cover.png"));
+
+        let message = &debug.fields[1];
+        assert_eq!(message.bytes, MESSAGE.len() as u64);
+        assert_eq!(message.format, None);
+        assert_eq!(message.temp_file, None);
+
+        let stages: Vec<_> = debug.stages.iter().map(|s| s.stage.as_str()).collect();
+        assert_eq!(stages, ["upload", "load", "encode", "save"]);
+        assert!(debug.stages.iter().all(|s| s.ms >= 0.0));
+
+        // Sizes only, never contents
+        assert!(!String::from_utf8_lossy(&body).contains(MESSAGE));
+    }
+}
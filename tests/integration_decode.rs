@@ -0,0 +1,512 @@
+use actix_web::{test, App};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use hide_rs::api::handlers::ServerConfig;
+use hide_rs::api::routes::configure_routes;
+use hide_rs::encoder::Encoder;
+use hide_rs::img::{create_rgb_image, StegoImage};
+use std::fs;
+use tempfile::tempdir;
+
+mod common;
+
+use common::{create_decode_multipart, create_state, create_state_with, post_multipart};
+
+#[actix_web::test]
+async fn test_repeated_decode_is_served_from_cache() {
+    // Create a temporary directory for the test
+    let temp_dir = tempdir().unwrap();
+    let upload_dir = temp_dir.path().to_path_buf();
+
+    // Create a stego image
+    let stego_path = upload_dir.join("stego.png");
+    Encoder::new()
+        .encode(create_rgb_image(40, 40).unwrap(), b"Cached message")
+        .unwrap()
+        .save(&stego_path)
+        .unwrap();
+
+    // Create test application
+    let app = test::init_service(
+        App::new()
+            .app_data(create_state(&upload_dir))
+            .configure(configure_routes),
+    )
+    .await;
+
+    // Upload the same image twice
+    let mut bodies = Vec::new();
+    for expected in ["MISS", "HIT"] {
+        let req = post_multipart("/api/v1/decode", create_decode_multipart(&stego_path, &[]))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        assert_eq!(resp.headers().get("X-Cache").unwrap(), expected);
+
+        let body = test::read_body(resp).await;
+        let json_response: serde_json::Value =
+            serde_json::from_slice(&body).expect("Failed to parse JSON response");
+        assert_eq!(json_response["message"], "Cached message");
+        bodies.push(json_response);
+    }
+
+    // Every response still gets its own request ID
+    assert_ne!(bodies[0]["request_id"], bodies[1]["request_id"]);
+
+    // The metrics endpoint reports the hit and the miss
+    let req = test::TestRequest::get().uri("/api/v1/metrics").to_request();
+    let resp = test::call_service(&app, req).await;
+    let body = test::read_body(resp).await;
+    let metrics: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(metrics["decode_cache"]["hits"], 1);
+    assert_eq!(metrics["decode_cache"]["misses"], 1);
+    assert_eq!(metrics["decode_cache"]["entries"], 1);
+
+    // Other decoder options are decoded, and cached, separately
+    let req = post_multipart(
+        "/api/v1/decode",
+        create_decode_multipart(&stego_path, &[("expected_dimensions", "40x40")]),
+    )
+    .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    assert_eq!(resp.headers().get("X-Cache").unwrap(), "MISS");
+}
+
+#[actix_web::test]
+async fn test_failed_decodes_are_not_cached() {
+    // Create a temporary directory for the test
+    let temp_dir = tempdir().unwrap();
+    let upload_dir = temp_dir.path().to_path_buf();
+
+    // An image without a hidden message
+    let image_path = upload_dir.join("blank.png");
+    create_rgb_image(40, 40).unwrap().save(&image_path).unwrap();
+
+    // Create test application
+    let app = test::init_service(
+        App::new()
+            .app_data(create_state(&upload_dir))
+            .configure(configure_routes),
+    )
+    .await;
+
+    for _ in 0..2 {
+        let req = post_multipart("/api/v1/decode", create_decode_multipart(&image_path, &[]))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+    }
+
+    let req = test::TestRequest::get().uri("/api/v1/metrics").to_request();
+    let resp = test::call_service(&app, req).await;
+    let body = test::read_body(resp).await;
+    let metrics: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(metrics["decode_cache"]["hits"], 0);
+    assert_eq!(metrics["decode_cache"]["entries"], 0);
+}
+
+#[actix_web::test]
+async fn test_truncated_payload_reports_lengths() {
+    // Create a temporary directory for the test
+    let temp_dir = tempdir().unwrap();
+    let upload_dir = temp_dir.path().to_path_buf();
+
+    // 20x20 holds 142 message bytes; cropping to 12 rows leaves 82
+    let message: Vec<u8> = (0..120u8).collect();
+    let stego = Encoder::new()
+        .encode(create_rgb_image(20, 20).unwrap(), &message)
+        .unwrap();
+    let image_path = upload_dir.join("cropped.png");
+    StegoImage::from_dynamic_image(stego.inner().crop_imm(0, 0, 20, 12))
+        .save(&image_path)
+        .unwrap();
+
+    // Create test application
+    let app = test::init_service(
+        App::new()
+            .app_data(create_state(&upload_dir))
+            .configure(configure_routes),
+    )
+    .await;
+
+    let req =
+        post_multipart("/api/v1/decode", create_decode_multipart(&image_path, &[])).to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+
+    let body = test::read_body(resp).await;
+    let json_response: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json_response["error_code"], "truncated_payload");
+    assert_eq!(json_response["details"]["declared_length"], 120);
+    assert_eq!(json_response["details"]["available_length"], 82);
+}
+
+#[actix_web::test]
+async fn test_resized_image_reports_dimension_change() {
+    // Create a temporary directory for the test
+    let temp_dir = tempdir().unwrap();
+    let upload_dir = temp_dir.path().to_path_buf();
+
+    // A proxy shrank the stego image after encoding
+    let stego = Encoder::new()
+        .encode(create_rgb_image(40, 30).unwrap(), b"Resized on the way")
+        .unwrap();
+    let image_path = upload_dir.join("resized.png");
+    StegoImage::from_dynamic_image(stego.inner().resize_exact(
+        20,
+        15,
+        image::imageops::FilterType::Triangle,
+    ))
+    .save(&image_path)
+    .unwrap();
+
+    // Create test application
+    let app = test::init_service(
+        App::new()
+            .app_data(create_state(&upload_dir))
+            .configure(configure_routes),
+    )
+    .await;
+
+    let req = post_multipart(
+        "/api/v1/decode",
+        create_decode_multipart(&image_path, &[("expected_dimensions", "40x30")]),
+    )
+    .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+
+    let body = test::read_body(resp).await;
+    let json_response: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json_response["error_code"], "dimensions_changed");
+    assert_eq!(
+        json_response["details"],
+        serde_json::json!({
+            "encoded": {"width": 40, "height": 30},
+            "actual": {"width": 20, "height": 15}
+        })
+    );
+    let message = json_response["message"].as_str().unwrap();
+    assert!(
+        message.contains("20x15") && message.contains("40x30"),
+        "{}",
+        message
+    );
+
+    // Malformed expectations are rejected up front
+    let req = post_multipart(
+        "/api/v1/decode",
+        create_decode_multipart(&image_path, &[("expected_dimensions", "40 by 30")]),
+    )
+    .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+    let body = test::read_body(resp).await;
+    let json_response: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json_response["error_code"], "validation_error");
+    assert_eq!(
+        json_response["details"]["field_errors"][0]["field"],
+        "expected_dimensions"
+    );
+}
+
+#[actix_web::test]
+async fn test_uploading_the_cover_is_named_as_the_cause() {
+    // Create a temporary directory for the test
+    let temp_dir = tempdir().unwrap();
+    let upload_dir = temp_dir.path().to_path_buf();
+
+    let cover_path = upload_dir.join("cover.png");
+    create_rgb_image(30, 30).unwrap().save(&cover_path).unwrap();
+    let cover_sha256 = hide_rs::manifest::sha256_hex(&fs::read(&cover_path).unwrap());
+
+    // Create test application
+    let app = test::init_service(
+        App::new()
+            .app_data(create_state(&upload_dir))
+            .configure(configure_routes),
+    )
+    .await;
+
+    let req = post_multipart(
+        "/api/v1/decode",
+        create_decode_multipart(&cover_path, &[("expected_cover_sha256", &cover_sha256)]),
+    )
+    .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+
+    let body = test::read_body(resp).await;
+    let json_response: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json_response["error_code"], "no_message_found");
+    assert!(json_response["message"]
+        .as_str()
+        .unwrap()
+        .contains("original cover"));
+}
+
+#[actix_web::test]
+async fn test_decode_limits_map_to_http_errors() {
+    // Create a temporary directory for the test
+    let temp_dir = tempdir().unwrap();
+    let upload_dir = temp_dir.path().to_path_buf();
+
+    // A stego image holding a 1000-byte message (2688 pixels with the header)
+    let stego_path = upload_dir.join("stego.png");
+    Encoder::new()
+        .encode(create_rgb_image(100, 100).unwrap(), &[b'x'; 1000])
+        .unwrap()
+        .save(&stego_path)
+        .unwrap();
+
+    let cases = [
+        (
+            ServerConfig {
+                decode_max_output_bytes: 500,
+                ..Default::default()
+            },
+            413,
+            "output_limit_exceeded",
+        ),
+        (
+            ServerConfig {
+                decode_max_pixels: 1000,
+                ..Default::default()
+            },
+            413,
+            "pixel_limit_exceeded",
+        ),
+        (
+            ServerConfig {
+                decode_deadline_ms: 0,
+                ..Default::default()
+            },
+            503,
+            "deadline_exceeded",
+        ),
+    ];
+
+    for (config, status, error_code) in cases {
+        let state = create_state_with(&upload_dir, config);
+        let app = test::init_service(App::new().app_data(state).configure(configure_routes)).await;
+
+        let req = post_multipart("/api/v1/decode", create_decode_multipart(&stego_path, &[]))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.status(),
+            status,
+            "Unexpected status for {}",
+            error_code
+        );
+
+        let body = test::read_body(resp).await;
+        let json_response: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json_response["error_code"], error_code);
+    }
+}
+
+#[actix_web::test]
+async fn test_large_decodes_are_stored_for_download() {
+    // Create a temporary directory for the test
+    let temp_dir = tempdir().unwrap();
+    let upload_dir = temp_dir.path().to_path_buf();
+
+    let state = create_state_with(
+        &upload_dir,
+        ServerConfig {
+            max_inline_decode_bytes: 1000,
+            ..Default::default()
+        },
+    );
+    let app = test::init_service(
+        App::new()
+            .app_data(state.clone())
+            .configure(configure_routes),
+    )
+    .await;
+
+    for length in [1000, 1001] {
+        // Binary messages just under and just over the threshold
+        let message: Vec<u8> = (0..length).map(|i| (i % 256) as u8).collect();
+        let stego_path = upload_dir.join(format!("stego_{}.png", length));
+        Encoder::new()
+            .encode(create_rgb_image(100, 100).unwrap(), &message)
+            .unwrap()
+            .save(&stego_path)
+            .unwrap();
+
+        let req = post_multipart("/api/v1/decode", create_decode_multipart(&stego_path, &[]))
+            .to_request();
+        let json_response: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(json_response["message_length"], length);
+
+        if length == 1000 {
+            // Inline responses keep the base64 message
+            assert_eq!(json_response["inline"], true);
+            assert!(json_response.get("download_url").is_none());
+            let inline = BASE64
+                .decode(json_response["binary_message"].as_str().unwrap())
+                .unwrap();
+            assert_eq!(inline, message);
+        } else {
+            // Larger messages are linked instead
+            assert_eq!(json_response["inline"], false);
+            assert_eq!(json_response["message_truncated"], false);
+            assert!(json_response.get("binary_message").is_none());
+
+            let download_url = json_response["download_url"].as_str().unwrap();
+            let req = test::TestRequest::get().uri(download_url).to_request();
+            let resp = test::call_service(&app, req).await;
+            assert!(resp.status().is_success());
+            assert_eq!(
+                resp.headers().get("content-type").unwrap(),
+                "application/octet-stream"
+            );
+            assert_eq!(test::read_body(resp).await, message);
+
+            // The stored message is held in the spool, against its budget
+            assert_eq!(state.spool.used_bytes(), length as u64);
+        }
+
+        // Asking for the raw bytes skips the JSON entirely
+        let req = post_multipart(
+            "/api/v1/decode",
+            create_decode_multipart(&stego_path, &[("response", "raw")]),
+        )
+        .to_request();
+        assert_eq!(test::call_and_read_body(&app, req).await, message);
+    }
+
+    // Unknown message IDs are rejected
+    let req = test::TestRequest::get()
+        .uri("/api/v1/messages/not-an-id")
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 400);
+}
+
+#[actix_web::test]
+async fn test_expected_message_is_compared() {
+    // Create a temporary directory for the test
+    let temp_dir = tempdir().unwrap();
+    let upload_dir = temp_dir.path().to_path_buf();
+
+    let stego_path = upload_dir.join("stego.png");
+    Encoder::new()
+        .encode(create_rgb_image(40, 40).unwrap(), b"Reference text")
+        .unwrap()
+        .save(&stego_path)
+        .unwrap();
+
+    // Create test application
+    let app = test::init_service(
+        App::new()
+            .app_data(create_state(&upload_dir))
+            .configure(configure_routes),
+    )
+    .await;
+
+    // A match, a mismatch sent as a file, and the match again from the cache
+    for (field, expected, matches) in [
+        ("expected_message", "Reference text", true),
+        ("expected_message_file", "Reference texT", false),
+        ("expected_message", "Reference text", true),
+    ] {
+        let req = post_multipart(
+            "/api/v1/decode",
+            create_decode_multipart(&stego_path, &[(field, expected)]),
+        )
+        .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let body = test::read_body(resp).await;
+        let json_response: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json_response["matches"], matches, "{}", expected);
+        assert_eq!(json_response["message"], "Reference text");
+        assert_eq!(json_response["message_length"], 14);
+    }
+
+    // Without an expected message there is nothing to report
+    let req =
+        post_multipart("/api/v1/decode", create_decode_multipart(&stego_path, &[])).to_request();
+    let body = test::read_body(test::call_service(&app, req).await).await;
+    let json_response: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert!(json_response.get("matches").is_none());
+}
+
+#[actix_web::test]
+async fn test_verify_only_leaves_the_message_out() {
+    // Create a temporary directory for the test
+    let temp_dir = tempdir().unwrap();
+    let upload_dir = temp_dir.path().to_path_buf();
+
+    let stego_path = upload_dir.join("stego.png");
+    Encoder::new()
+        .encode(create_rgb_image(40, 40).unwrap(), b"Keep me secret")
+        .unwrap()
+        .save(&stego_path)
+        .unwrap();
+
+    // Create test application without a cache, so every request decodes
+    let app = test::init_service(
+        App::new()
+            .app_data(create_state_with(
+                &upload_dir,
+                ServerConfig {
+                    decode_cache_entries: 0,
+                    ..Default::default()
+                },
+            ))
+            .configure(configure_routes),
+    )
+    .await;
+
+    for (expected, matches) in [("Keep me secret", true), ("Keep me", false)] {
+        let req = post_multipart(
+            "/api/v1/decode",
+            create_decode_multipart(
+                &stego_path,
+                &[("expected_message", expected), ("verify_only", "true")],
+            ),
+        )
+        .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let body = test::read_body(resp).await;
+        assert!(!String::from_utf8_lossy(&body).contains("secret"));
+        let json_response: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json_response["matches"], matches);
+        assert_eq!(json_response["inline"], false);
+        assert!(json_response.get("message").is_none());
+        assert!(json_response.get("binary_message").is_none());
+        assert!(json_response.get("download_url").is_none());
+    }
+
+    // verify_only needs something to compare with, and raw responses can't carry the result
+    for fields in [
+        &[("verify_only", "true")][..],
+        &[("expected_message", "Keep me secret"), ("response", "raw")][..],
+    ] {
+        let req = post_multipart(
+            "/api/v1/decode",
+            create_decode_multipart(&stego_path, fields),
+        )
+        .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+        let body = test::read_body(resp).await;
+        let json_response: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json_response["error_code"], "validation_error");
+    }
+}
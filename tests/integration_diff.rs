@@ -0,0 +1,235 @@
+use actix_web::{test, App};
+use hide_rs::api::routes::configure_routes;
+use hide_rs::encoder::Encoder;
+use hide_rs::testing::gradient_image;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::tempdir;
+
+mod common;
+
+use common::{content_type, create_state, multipart, post_multipart};
+
+#[actix_web::test]
+async fn test_diff_endpoint_with_heatmap() {
+    // Create a temporary directory for the test
+    let temp_dir = tempdir().unwrap();
+    let upload_dir = temp_dir.path().to_path_buf();
+
+    // Create a cover image and its encoded sibling
+    let cover_path = upload_dir.join("cover.png");
+    let stego_path = upload_dir.join("stego.png");
+    create_test_image(&cover_path, 50, 50);
+    Encoder::new()
+        .encode_file(&cover_path, b"This is a test message", &stego_path)
+        .unwrap();
+
+    // Create test application
+    let app = test::init_service(
+        App::new()
+            .app_data(create_state(&upload_dir))
+            .configure(configure_routes),
+    )
+    .await;
+
+    // Upload both images and ask for a heatmap
+    let req = post_multipart(
+        "/api/v1/diff",
+        create_diff_multipart(&cover_path, &stego_path, true, None),
+    )
+    .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert!(
+        resp.status().is_success(),
+        "Response status is not success: {}",
+        resp.status()
+    );
+
+    // Parse response body
+    let body = test::read_body(resp).await;
+    let json_response: serde_json::Value =
+        serde_json::from_slice(&body).expect("Failed to parse JSON response");
+
+    assert_eq!(json_response["status"], "success");
+    assert_eq!(json_response["stats"]["total_pixels"], 2500);
+    assert!(json_response["stats"]["changed_pixels"].as_u64().unwrap() > 0);
+    assert!(json_response["stats"]["changed_channels"].as_u64().unwrap() > 0);
+
+    // The heatmap must be downloadable
+    let download_url = json_response["download_url"]
+        .as_str()
+        .expect("Missing download_url field");
+    let req = test::TestRequest::get().uri(download_url).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+
+    let body = test::read_body(resp).await;
+    let heatmap = image::load_from_memory(&body).expect("Heatmap is not a valid image");
+    assert_eq!((heatmap.width(), heatmap.height()), (50, 50));
+}
+
+#[actix_web::test]
+async fn test_diff_endpoint_without_render() {
+    // Create a temporary directory for the test
+    let temp_dir = tempdir().unwrap();
+    let upload_dir = temp_dir.path().to_path_buf();
+
+    // Compare an image against itself
+    let cover_path = upload_dir.join("cover.png");
+    create_test_image(&cover_path, 20, 20);
+
+    // Create test application
+    let app = test::init_service(
+        App::new()
+            .app_data(create_state(&upload_dir))
+            .configure(configure_routes),
+    )
+    .await;
+
+    let req = post_multipart(
+        "/api/v1/diff",
+        create_diff_multipart(&cover_path, &cover_path, false, None),
+    )
+    .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+
+    let body = test::read_body(resp).await;
+    let json_response: serde_json::Value =
+        serde_json::from_slice(&body).expect("Failed to parse JSON response");
+
+    assert_eq!(json_response["stats"]["changed_pixels"], 0);
+    assert!(json_response.get("download_url").is_none());
+}
+
+#[actix_web::test]
+async fn test_diff_endpoint_dimension_mismatch() {
+    // Create a temporary directory for the test
+    let temp_dir = tempdir().unwrap();
+    let upload_dir = temp_dir.path().to_path_buf();
+
+    // Create two images of different sizes
+    let small_path = upload_dir.join("small.png");
+    let large_path = upload_dir.join("large.png");
+    create_test_image(&small_path, 10, 10);
+    create_test_image(&large_path, 20, 15);
+
+    // Create test application
+    let app = test::init_service(
+        App::new()
+            .app_data(create_state(&upload_dir))
+            .configure(configure_routes),
+    )
+    .await;
+
+    let req = post_multipart(
+        "/api/v1/diff",
+        create_diff_multipart(&small_path, &large_path, true, None),
+    )
+    .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+
+    let body = test::read_body(resp).await;
+    let json_response: serde_json::Value =
+        serde_json::from_slice(&body).expect("Failed to parse JSON response");
+
+    // Both sizes are reported in the details
+    assert_eq!(json_response["error_code"], "validation_error");
+    assert_eq!(json_response["details"]["image_a"]["width"], 10);
+    assert_eq!(json_response["details"]["image_a"]["height"], 10);
+    assert_eq!(json_response["details"]["image_b"]["width"], 20);
+    assert_eq!(json_response["details"]["image_b"]["height"], 15);
+}
+
+#[actix_web::test]
+async fn test_diff_endpoint_block_counts() {
+    let temp_dir = tempdir().unwrap();
+    let upload_dir = temp_dir.path().to_path_buf();
+
+    let cover_path = upload_dir.join("cover.png");
+    let stego_path = upload_dir.join("stego.png");
+    create_test_image(&cover_path, 50, 30);
+    Encoder::new()
+        .encode_file(&cover_path, b"Counted per block", &stego_path)
+        .unwrap();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(create_state(&upload_dir))
+            .configure(configure_routes),
+    )
+    .await;
+
+    let req = post_multipart(
+        "/api/v1/diff",
+        create_diff_multipart(&cover_path, &stego_path, false, Some("16x8")),
+    )
+    .to_request();
+    let json_response: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+
+    // 50x30 in 16x8 blocks is 4 blocks across and 4 down
+    let stats = &json_response["stats"];
+    let blocks = &stats["blocks"];
+    assert_eq!(blocks["block_width"], 16);
+    assert_eq!(blocks["block_height"], 8);
+    assert_eq!(blocks["columns"], 4);
+    assert_eq!(blocks["rows"], 4);
+    let counts: Vec<u64> = blocks["counts"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|count| count.as_u64().unwrap())
+        .collect();
+    assert_eq!(counts.len(), 16);
+    assert_eq!(
+        counts.iter().sum::<u64>(),
+        stats["changed_bits"].as_u64().unwrap()
+    );
+    // The payload sits in the first rows, so the bottom blocks are untouched
+    assert!(counts[0] > 0);
+    assert_eq!(counts[12..], [0, 0, 0, 0]);
+
+    // A zero block size is a validation error naming the field
+    let req = post_multipart(
+        "/api/v1/diff",
+        create_diff_multipart(&cover_path, &stego_path, false, Some("0")),
+    )
+    .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+    let json_response: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(
+        json_response["details"]["field_errors"][0]["field"],
+        "block_size"
+    );
+}
+
+// Helper to create a test image with some texture
+fn create_test_image(path: &PathBuf, width: u32, height: u32) {
+    gradient_image(width, height).save(path).unwrap();
+}
+
+// Helper to create a multipart form with two images, the render flag and a block size
+fn create_diff_multipart(
+    image_a: &Path,
+    image_b: &Path,
+    render: bool,
+    block_size: Option<&str>,
+) -> (Vec<u8>, String) {
+    let (image_a, image_b) = (fs::read(image_a).unwrap(), fs::read(image_b).unwrap());
+    let render = render.to_string();
+    let mut fields = vec![
+        ("image_a", Some("image.png"), image_a.as_slice()),
+        ("image_b", Some("image.png"), image_b.as_slice()),
+    ];
+    if let Some(block_size) = block_size {
+        fields.push(("block_size", None, block_size.as_bytes()));
+    }
+    fields.push(("render", None, render.as_bytes()));
+
+    (multipart(&fields), content_type())
+}
@@ -0,0 +1,76 @@
+use actix_web::{test, App};
+use hide_rs::api::handlers::DECODED_MESSAGE_FILE;
+use hide_rs::api::routes::configure_routes;
+use hide_rs::testing::gradient_image;
+use tempfile::tempdir;
+use uuid::Uuid;
+
+mod common;
+
+use common::create_state;
+
+#[actix_web::test]
+async fn test_downloads_default_by_kind_and_follow_the_query() {
+    let temp_dir = tempdir().unwrap();
+    let image_id = Uuid::new_v4();
+    gradient_image(8, 8)
+        .save(temp_dir.path().join(format!("{}.png", image_id)))
+        .unwrap();
+
+    let state = create_state(temp_dir.path());
+    let message_id = Uuid::new_v4();
+    state
+        .spool
+        .keep(message_id, DECODED_MESSAGE_FILE, b"payload")
+        .unwrap();
+    let app = test::init_service(App::new().app_data(state).configure(configure_routes)).await;
+
+    let disposition_of = |uri: String| {
+        let req = test::TestRequest::get().uri(&uri).to_request();
+        let app = &app;
+        async move {
+            let resp = test::call_service(app, req).await;
+            assert!(resp.status().is_success(), "{}: {}", uri, resp.status());
+            resp.headers()
+                .get("Content-Disposition")
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .to_string()
+        }
+    };
+
+    // Images are shown, messages are saved
+    assert_eq!(
+        disposition_of(format!("/api/v1/images/{}", image_id)).await,
+        format!("inline; filename=\"{}.png\"", image_id)
+    );
+    assert_eq!(
+        disposition_of(format!("/api/v1/messages/{}", message_id)).await,
+        format!("attachment; filename=\"{}.bin\"", message_id)
+    );
+
+    // Either can be asked for the other way
+    assert_eq!(
+        disposition_of(format!(
+            "/api/v1/images/{}?disposition=attachment",
+            image_id
+        ))
+        .await,
+        format!("attachment; filename=\"{}.png\"", image_id)
+    );
+    assert_eq!(
+        disposition_of(format!(
+            "/api/v1/messages/{}?disposition=inline",
+            message_id
+        ))
+        .await,
+        format!("inline; filename=\"{}.bin\"", message_id)
+    );
+
+    // Unknown dispositions are rejected
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/v1/images/{}?disposition=download", image_id))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 400);
+}
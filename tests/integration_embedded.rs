@@ -0,0 +1,94 @@
+use actix_web::{test, web, App, HttpResponse};
+use hide_rs::api::{self, handlers::ServerConfig, ApiConfig};
+use hide_rs::img::create_rgb_image;
+use tempfile::tempdir;
+
+mod common;
+
+use common::{create_encode_multipart, post_multipart};
+
+#[actix_web::test]
+async fn test_api_mounted_under_a_custom_prefix() {
+    let temp_dir = tempdir().unwrap();
+    let upload_dir = temp_dir.path().to_path_buf();
+    let cover_path = upload_dir.join("cover.png");
+    create_rgb_image(50, 50).unwrap().save(&cover_path).unwrap();
+
+    let config = ApiConfig::new(
+        "/embedded/hide/",
+        ServerConfig {
+            upload_dir: upload_dir.to_string_lossy().to_string(),
+            enable_legacy_routes: false,
+            ..Default::default()
+        },
+    );
+    assert_eq!(config.mount_path(), "/embedded/hide");
+
+    // The host registers no state of its own
+    let app = test::init_service(
+        App::new()
+            .route(
+                "/",
+                web::get().to(|| async { HttpResponse::Ok().body("host") }),
+            )
+            .service(api::scope(config)),
+    )
+    .await;
+
+    // The host's own routes keep working
+    let req = test::TestRequest::get().uri("/").to_request();
+    assert_eq!(test::call_and_read_body(&app, req).await, "host");
+
+    let req = test::TestRequest::get()
+        .uri("/embedded/hide/api/v1/health")
+        .to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+    let req = test::TestRequest::get()
+        .uri("/embedded/hide/api/capabilities")
+        .to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+
+    // Nothing is served outside the prefix, nor the disabled legacy routes
+    for uri in ["/api/v1/health", "/embedded/hide/api/health"] {
+        let req = test::TestRequest::get().uri(uri).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 404, "{}", uri);
+    }
+
+    // Download URLs point back under the prefix
+    let req = post_multipart(
+        "/embedded/hide/api/v1/encode",
+        create_encode_multipart(&cover_path, "mounted elsewhere"),
+    )
+    .to_request();
+    let json_response: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    let download_url = json_response["download_url"].as_str().unwrap();
+    assert!(
+        download_url.starts_with("/embedded/hide/api/v1/images/"),
+        "{}",
+        download_url
+    );
+
+    let req = test::TestRequest::get().uri(download_url).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+}
+
+#[actix_web::test]
+async fn test_api_mounted_with_legacy_routes() {
+    let temp_dir = tempdir().unwrap();
+    let config = ApiConfig::new(
+        "/hide",
+        ServerConfig {
+            upload_dir: temp_dir.path().to_string_lossy().to_string(),
+            ..Default::default()
+        },
+    );
+    let app = test::init_service(App::new().service(api::scope(config))).await;
+
+    let req = test::TestRequest::get()
+        .uri("/hide/api/health")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    assert!(resp.headers().contains_key("deprecation"));
+}
@@ -0,0 +1,88 @@
+use actix_web::{test, App};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use hide_rs::api::routes::configure_routes;
+use hide_rs::depth::{self, EmbeddingDepth};
+use hide_rs::encoder::{Encoder, EncoderConfig};
+use hide_rs::testing::gradient_image;
+use image::ImageFormat;
+use tempfile::tempdir;
+
+mod common;
+
+use common::{content_type, create_state, multipart};
+
+#[actix_web::test]
+async fn test_two_bit_depth_through_the_api() {
+    let temp_dir = tempdir().unwrap();
+    let state = create_state(temp_dir.path());
+    let app = test::init_service(App::new().app_data(state).configure(configure_routes)).await;
+    let cover = gradient_image(40, 40).to_bytes(ImageFormat::Png).unwrap();
+
+    // Longer than the 592 bytes one bit per channel holds
+    let message: Vec<u8> = (0..900).map(|i| (i * 31 + 7) as u8).collect();
+    let req = test::TestRequest::post()
+        .uri("/api/v1/encode")
+        .insert_header(("content-type", content_type()))
+        .set_payload(multipart(&[
+            ("cover_image", Some("cover.png"), &cover),
+            ("message", None, &message),
+            ("embedding_depth", None, b"2"),
+        ]))
+        .to_request();
+    let encoded: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(encoded["status"], "success", "{}", encoded);
+
+    let two_bits =
+        Encoder::with_config(EncoderConfig::default().embedding_depth(EmbeddingDepth::Two))
+            .max_message_size(&gradient_image(40, 40));
+    assert_eq!(two_bits, depth::max_message_size_for_dimensions(40, 40));
+    assert_eq!(encoded["metadata"]["max_message_bytes"], two_bits);
+    assert!(two_bits > Encoder::new().max_message_size(&gradient_image(40, 40)));
+
+    let req = test::TestRequest::get()
+        .uri(encoded["download_url"].as_str().unwrap())
+        .to_request();
+    let stego = test::call_and_read_body(&app, req).await;
+    let req = test::TestRequest::post()
+        .uri("/api/v1/decode")
+        .insert_header(("content-type", content_type()))
+        .set_payload(multipart(&[("stego_image", Some("stego.png"), &stego)]))
+        .to_request();
+    let decoded: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    let recovered = BASE64
+        .decode(decoded["binary_message"].as_str().unwrap())
+        .unwrap();
+    assert_eq!(recovered, message);
+
+    // The capacity endpoint takes the same field
+    let req = test::TestRequest::post()
+        .uri("/api/v1/capacity")
+        .insert_header(("content-type", content_type()))
+        .set_payload(multipart(&[
+            ("cover_image", Some("cover.png"), &cover),
+            ("embedding_depth", None, b"2"),
+        ]))
+        .to_request();
+    let capacity: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(capacity["capacity"]["max_message_bytes"], two_bits);
+
+    // Only one and two bits per channel are supported
+    for uri in ["/api/v1/encode", "/api/v1/capacity"] {
+        let req = test::TestRequest::post()
+            .uri(uri)
+            .insert_header(("content-type", content_type()))
+            .set_payload(multipart(&[
+                ("cover_image", Some("cover.png"), &cover),
+                ("message", None, b"deep"),
+                ("embedding_depth", None, b"3"),
+            ]))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400, "{}", uri);
+        let json: serde_json::Value = serde_json::from_slice(&test::read_body(resp).await).unwrap();
+        assert_eq!(
+            json["details"]["field_errors"][0]["field"],
+            "embedding_depth"
+        );
+    }
+}
@@ -1,10 +1,16 @@
-use actix_web::{test, web, App};
-use hide_rs::api::{handlers::AppState, routes::configure_routes};
-use std::fs::File;
-use std::io::Read;
+use actix_web::{test, App};
+use hide_rs::api::models::{MessageTooLargeDetails, ValidationDetails, MAX_TEXT_FIELD_LENGTH};
+use hide_rs::api::{handlers::ServerConfig, routes::configure_routes};
 use std::path::PathBuf;
 use tempfile::tempdir;
 
+mod common;
+
+use common::{
+    content_type, create_encode_multipart, create_state, create_state_with, encode_request,
+    file_multipart, multipart, post_multipart, BOUNDARY,
+};
+
 #[actix_web::test]
 async fn test_encode_endpoint() {
     // Create a temporary directory for the test
@@ -16,26 +22,20 @@ async fn test_encode_endpoint() {
     create_test_image(&test_image_path, 100, 100);
 
     // Create application state
-    let state = web::Data::new(AppState {
-        config: hide_rs::api::handlers::ServerConfig {
+    let state = create_state_with(
+        &upload_dir,
+        ServerConfig {
             host: "127.0.0.1".to_string(),
             port: 8080,
-            upload_dir: upload_dir.to_string_lossy().to_string(),
+            ..Default::default()
         },
-    });
+    );
 
     // Create test application
     let app = test::init_service(App::new().app_data(state).configure(configure_routes)).await;
 
-    // Create a multipart form with an image and message
-    let (payload, content_type) = create_test_multipart(&test_image_path, "This is a test message");
-
     // Send request to the endpoint
-    let req = test::TestRequest::post()
-        .uri("/api/encode")
-        .insert_header(("content-type", content_type))
-        .set_payload(payload)
-        .to_request();
+    let req = encode_request(Some(&test_image_path), "This is a test message", &[]).to_request();
 
     let resp = test::call_service(&app, req).await;
 
@@ -65,6 +65,17 @@ async fn test_encode_endpoint() {
         json_response.get("download_url").is_some(),
         "Missing download_url field"
     );
+
+    // The cover is fingerprinted as the server received it
+    let cover_bytes = std::fs::read(&test_image_path).unwrap();
+    assert_eq!(
+        json_response["cover_sha256"],
+        hide_rs::manifest::sha256_hex(&cover_bytes)
+    );
+    assert_eq!(
+        json_response["cover_dimensions"],
+        serde_json::json!({"width": 100, "height": 100})
+    );
 }
 
 #[actix_web::test]
@@ -78,26 +89,20 @@ async fn test_encode_empty_message() {
     create_test_image(&test_image_path, 100, 100);
 
     // Create application state
-    let state = web::Data::new(AppState {
-        config: hide_rs::api::handlers::ServerConfig {
+    let state = create_state_with(
+        &upload_dir,
+        ServerConfig {
             host: "127.0.0.1".to_string(),
             port: 8080,
-            upload_dir: upload_dir.to_string_lossy().to_string(),
+            ..Default::default()
         },
-    });
+    );
 
     // Create test application
     let app = test::init_service(App::new().app_data(state).configure(configure_routes)).await;
 
-    // Create a multipart form with an image and an empty message
-    let (payload, content_type) = create_test_multipart(&test_image_path, "");
-
     // Send request to the endpoint
-    let req = test::TestRequest::post()
-        .uri("/api/encode")
-        .insert_header(("content-type", content_type))
-        .set_payload(payload)
-        .to_request();
+    let req = encode_request(Some(&test_image_path), "", &[]).to_request();
 
     let resp = test::call_service(&app, req).await;
 
@@ -116,35 +121,23 @@ async fn test_encode_no_image() {
     let upload_dir = temp_dir.path().to_path_buf();
 
     // Create application state
-    let state = web::Data::new(AppState {
-        config: hide_rs::api::handlers::ServerConfig {
+    let state = create_state_with(
+        &upload_dir,
+        ServerConfig {
             host: "127.0.0.1".to_string(),
             port: 8080,
-            upload_dir: upload_dir.to_string_lossy().to_string(),
+            ..Default::default()
         },
-    });
+    );
 
     // Create test application
     let app = test::init_service(App::new().app_data(state).configure(configure_routes)).await;
 
     // Create a multipart form with just a message, no image
-    let boundary = "------------------------abcdef1234567890";
-    let content_type = format!("multipart/form-data; boundary={}", boundary);
-
-    let payload = format!(
-        "--{boundary}\r\n\
-         Content-Disposition: form-data; name=\"message\"\r\n\r\n\
-         This is a test message\r\n\
-         --{boundary}--\r\n",
-        boundary = boundary
-    );
+    let payload = multipart(&[("message", None, b"This is a test message")]);
 
     // Send request to the endpoint
-    let req = test::TestRequest::post()
-        .uri("/api/encode")
-        .insert_header(("content-type", content_type))
-        .set_payload(payload)
-        .to_request();
+    let req = post_multipart("/api/v1/encode", (payload, content_type())).to_request();
 
     let resp = test::call_service(&app, req).await;
 
@@ -163,6 +156,11 @@ async fn test_encode_no_image() {
         .as_str()
         .unwrap()
         .contains("Missing cover image"));
+
+    // The missing field is named in the details
+    let details: ValidationDetails =
+        serde_json::from_value(json_response["details"].clone()).unwrap();
+    assert_eq!(details.field_errors[0].field, "cover_image");
 }
 
 #[actix_web::test]
@@ -176,45 +174,23 @@ async fn test_encode_no_message() {
     create_test_image(&test_image_path, 100, 100);
 
     // Create application state
-    let state = web::Data::new(AppState {
-        config: hide_rs::api::handlers::ServerConfig {
+    let state = create_state_with(
+        &upload_dir,
+        ServerConfig {
             host: "127.0.0.1".to_string(),
             port: 8080,
-            upload_dir: upload_dir.to_string_lossy().to_string(),
+            ..Default::default()
         },
-    });
+    );
 
     // Create test application
     let app = test::init_service(App::new().app_data(state).configure(configure_routes)).await;
 
     // Create a multipart form with just an image, no message
-    let boundary = "------------------------abcdef1234567890";
-    let content_type = format!("multipart/form-data; boundary={}", boundary);
-
-    // Read the test image file
-    let mut file_data = Vec::new();
-    let mut file = File::open(&test_image_path).unwrap();
-    file.read_to_end(&mut file_data).unwrap();
-
-    let payload = format!(
-        "--{boundary}\r\n\
-         Content-Disposition: form-data; name=\"cover_image\"; filename=\"test_image.png\"\r\n\
-         Content-Type: image/png\r\n\r\n",
-        boundary = boundary
-    );
-
-    // Combine the text part with the binary file data
-    let mut body = Vec::new();
-    body.extend_from_slice(payload.as_bytes());
-    body.extend_from_slice(&file_data);
-    body.extend_from_slice(format!("\r\n--{boundary}--\r\n", boundary = boundary).as_bytes());
+    let form = file_multipart("cover_image", &test_image_path, &[]);
 
     // Send request to the endpoint
-    let req = test::TestRequest::post()
-        .uri("/api/encode")
-        .insert_header(("content-type", content_type))
-        .set_payload(body)
-        .to_request();
+    let req = post_multipart("/api/v1/encode", form).to_request();
 
     let resp = test::call_service(&app, req).await;
 
@@ -235,43 +211,324 @@ async fn test_encode_no_message() {
         .contains("Missing message"));
 }
 
-// Helper to create a test image
-fn create_test_image(path: &PathBuf, width: u32, height: u32) {
-    let img = image::RgbImage::new(width, height);
-    img.save(path).unwrap();
+#[actix_web::test]
+async fn test_encode_existing_payload() {
+    // Create a temporary directory for the test
+    let temp_dir = tempdir().unwrap();
+    let upload_dir = temp_dir.path().to_path_buf();
+
+    // A cover that already holds a message
+    let test_image_path = upload_dir.join("test_image.png");
+    hide_rs::encoder::Encoder::new()
+        .encode(
+            hide_rs::img::create_rgb_image(100, 100).unwrap(),
+            b"Already hidden",
+        )
+        .unwrap()
+        .save(&test_image_path)
+        .unwrap();
+
+    // Create application state
+    let state = create_state(&upload_dir);
+
+    // Create test application
+    let app = test::init_service(App::new().app_data(state).configure(configure_routes)).await;
+
+    // Without the overwrite field the encode is refused
+    let req = encode_request(Some(&test_image_path), "New message", &[]).to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+
+    let body = test::read_body(resp).await;
+    let json_response: serde_json::Value =
+        serde_json::from_slice(&body).expect("Failed to parse JSON response");
+    assert_eq!(json_response["error_code"], "existing_payload");
+
+    // Opting in replaces the payload
+    let (payload, content_type) = create_encode_multipart(&test_image_path, "New message");
+    let payload = append_field(payload, "overwrite", "true");
+    let req = test::TestRequest::post()
+        .uri("/api/v1/encode")
+        .insert_header(("content-type", content_type))
+        .set_payload(payload)
+        .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert!(
+        resp.status().is_success(),
+        "Response status is not success: {}",
+        resp.status()
+    );
+}
+
+#[actix_web::test]
+async fn test_encode_change_budget() {
+    // Create a temporary directory for the test
+    let temp_dir = tempdir().unwrap();
+    let upload_dir = temp_dir.path().to_path_buf();
+
+    // Create a test image
+    let test_image_path = upload_dir.join("test_image.png");
+    create_test_image(&test_image_path, 100, 100);
+
+    // Create application state
+    let state = create_state(&upload_dir);
+
+    // Create test application
+    let app = test::init_service(App::new().app_data(state).configure(configure_routes)).await;
+
+    // "Secret" plus the header touches 38 of the 10000 pixels
+    let cases = [
+        ("0.3%", 400, Some("change_budget_exceeded")),
+        ("0.5%", 200, None),
+        ("0.005", 200, None),
+        ("lots", 400, Some("validation_error")),
+    ];
+
+    for (budget, status, error_code) in cases {
+        let (payload, content_type) = create_encode_multipart(&test_image_path, "Secret");
+        let payload = append_field(payload, "max_change", budget);
+        let req = test::TestRequest::post()
+            .uri("/api/v1/encode")
+            .insert_header(("content-type", content_type))
+            .set_payload(payload)
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), status, "Wrong status for budget {}", budget);
+
+        let body = test::read_body(resp).await;
+        let json_response: serde_json::Value =
+            serde_json::from_slice(&body).expect("Failed to parse JSON response");
+        if let Some(error_code) = error_code {
+            assert_eq!(json_response["error_code"], error_code);
+        }
+    }
+}
+
+#[actix_web::test]
+async fn test_encode_intended_channel_warnings() {
+    // Create a temporary directory for the test
+    let temp_dir = tempdir().unwrap();
+    let upload_dir = temp_dir.path().to_path_buf();
+
+    // Create a test image
+    let test_image_path = upload_dir.join("test_image.png");
+    create_test_image(&test_image_path, 100, 100);
+
+    // Create application state
+    let state = create_state(&upload_dir);
+
+    // Create test application
+    let app = test::init_service(App::new().app_data(state).configure(configure_routes)).await;
+
+    let cases = [
+        ("jpeg", false, 200, Some("lossy_channel")),
+        ("png", false, 200, None),
+        ("jpeg", true, 400, None),
+        ("png", true, 200, None),
+        ("fax", false, 400, None),
+    ];
+
+    for (channel, strict, status, warning) in cases {
+        let (payload, content_type) = create_encode_multipart(&test_image_path, "Secret");
+        let mut payload = append_field(payload, "intended_channel", channel);
+        if strict {
+            payload = append_field(payload, "strict", "true");
+        }
+        let req = test::TestRequest::post()
+            .uri("/api/v1/encode")
+            .insert_header(("content-type", content_type))
+            .set_payload(payload)
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.status(),
+            status,
+            "Wrong status for {} {}",
+            channel,
+            strict
+        );
+
+        let json_response: serde_json::Value = test::read_body_json(resp).await;
+        match (status, warning) {
+            (200, Some(code)) => {
+                assert_eq!(json_response["warnings"][0]["code"], code);
+                assert!(json_response["warnings"][0]["message"]
+                    .as_str()
+                    .unwrap()
+                    .contains("lossy"));
+            }
+            (200, None) => assert!(json_response.get("warnings").is_none()),
+            _ if strict => {
+                assert_eq!(json_response["error_code"], "warnings_in_strict_mode");
+                assert_eq!(
+                    json_response["details"]["warnings"][0]["code"],
+                    "lossy_channel"
+                );
+            }
+            _ => assert_eq!(json_response["error_code"], "validation_error"),
+        }
+    }
 }
 
-// Helper to create a multipart form with an image and message
-fn create_test_multipart(image_path: &PathBuf, message: &str) -> (Vec<u8>, String) {
-    let boundary = "------------------------abcdef1234567890";
-    let content_type = format!("multipart/form-data; boundary={}", boundary);
-
-    // Read the test image file
-    let mut file_data = Vec::new();
-    let mut file = File::open(image_path).unwrap();
-    file.read_to_end(&mut file_data).unwrap();
-
-    let payload = format!(
-        "--{boundary}\r\n\
-         Content-Disposition: form-data; name=\"cover_image\"; filename=\"test_image.png\"\r\n\
-         Content-Type: image/png\r\n\r\n",
-        boundary = boundary
+#[actix_web::test]
+async fn test_error_details_are_typed() {
+    // Create a temporary directory for the test
+    let temp_dir = tempdir().unwrap();
+    let upload_dir = temp_dir.path().to_path_buf();
+
+    // A 10x10 image holds at most 29 message bytes
+    let test_image_path = upload_dir.join("test_image.png");
+    create_test_image(&test_image_path, 10, 10);
+
+    // Create application state
+    let state = create_state(&upload_dir);
+
+    // Create test application
+    let app = test::init_service(App::new().app_data(state).configure(configure_routes)).await;
+
+    // A message that doesn't fit reports its size and the capacity
+    let req = encode_request(Some(&test_image_path), &"x".repeat(40), &[]).to_request();
+    let json_response: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(json_response["error_code"], "message_too_large");
+    let details: MessageTooLargeDetails =
+        serde_json::from_value(json_response["details"].clone()).unwrap();
+    assert_eq!(
+        details,
+        MessageTooLargeDetails {
+            message_bytes: 40,
+            capacity_bytes: 29
+        }
     );
 
-    let message_part = format!(
-        "\r\n--{boundary}\r\n\
-         Content-Disposition: form-data; name=\"message\"\r\n\r\n\
-         {message}\r\n\
-         --{boundary}--\r\n",
-        boundary = boundary,
-        message = message
+    // An invalid field names the field and echoes the value
+    let (payload, content_type) = create_encode_multipart(&test_image_path, "Secret");
+    let payload = append_field(payload, "max_change", "lots");
+    let req = test::TestRequest::post()
+        .uri("/api/v1/encode")
+        .insert_header(("content-type", content_type))
+        .set_payload(payload)
+        .to_request();
+    let json_response: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(json_response["error_code"], "validation_error");
+    let details: ValidationDetails =
+        serde_json::from_value(json_response["details"].clone()).unwrap();
+    assert_eq!(details.field_errors.len(), 1);
+    assert_eq!(details.field_errors[0].field, "max_change");
+    assert_eq!(details.field_errors[0].received.as_deref(), Some("lots"));
+
+    // An option field longer than the limit is refused without echoing it
+    let flag = "true ".repeat(MAX_TEXT_FIELD_LENGTH);
+    let req = encode_request(Some(&test_image_path), "Secret", &[("strict", &flag)]).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+    let json_response: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(json_response["error_code"], "validation_error");
+    let details: ValidationDetails =
+        serde_json::from_value(json_response["details"].clone()).unwrap();
+    assert_eq!(details.field_errors[0].field, "strict");
+    assert_eq!(details.field_errors[0].received, None);
+}
+
+#[actix_web::test]
+async fn test_upload_filename_cannot_escape_the_request_directory() {
+    let temp_dir = tempdir().unwrap();
+    let outer_dir = temp_dir.path().join("outer");
+    let upload_dir = outer_dir.join("uploads");
+    std::fs::create_dir_all(&upload_dir).unwrap();
+
+    let test_image_path = temp_dir.path().join("test_image.png");
+    create_test_image(&test_image_path, 100, 100);
+
+    let state = create_state(&upload_dir);
+    let app = test::init_service(App::new().app_data(state).configure(configure_routes)).await;
+
+    // A request directory is uploads/<id>, so this would land in outer/
+    let (payload, content_type) = create_encode_multipart(&test_image_path, "hostile name");
+    let original: &[u8] = b"filename=\"test_image.png\"";
+    let start = payload
+        .windows(original.len())
+        .position(|window| window == original)
+        .unwrap();
+    let mut hostile = payload[..start].to_vec();
+    hostile.extend_from_slice(b"filename=\"../../x.png\"");
+    hostile.extend_from_slice(&payload[start + original.len()..]);
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/encode")
+        .insert_header(("content-type", content_type))
+        .set_payload(hostile)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success(), "{}", resp.status());
+
+    assert!(!outer_dir.join("x.png").exists());
+    assert!(!temp_dir.path().join("x.png").exists());
+}
+
+#[actix_web::test]
+async fn test_message_hash_and_conflicting_message_fields() {
+    let temp_dir = tempdir().unwrap();
+    let test_image_path = temp_dir.path().join("test_image.png");
+    create_test_image(&test_image_path, 100, 100);
+
+    let state = create_state(temp_dir.path());
+    let app = test::init_service(App::new().app_data(state).configure(configure_routes)).await;
+
+    // The response carries the hash of the message as the server received it
+    let message = "hash me on the way in";
+    let req = encode_request(Some(&test_image_path), message, &[]).to_request();
+    let json_response: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(
+        json_response["metadata"]["message_sha256"],
+        hide_rs::manifest::sha256_hex(message.as_bytes())
     );
 
-    // Combine the parts with the binary file data
-    let mut body = Vec::new();
-    body.extend_from_slice(payload.as_bytes());
-    body.extend_from_slice(&file_data);
-    body.extend_from_slice(message_part.as_bytes());
+    // Sending both a message and a message file is refused, naming both
+    let (payload, content_type) = create_encode_multipart(&test_image_path, "one");
+    let payload = append_field(payload, "message_file", "two");
+    let req = test::TestRequest::post()
+        .uri("/api/v1/encode")
+        .insert_header(("content-type", content_type))
+        .set_payload(payload)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+    let json_response: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(json_response["error_code"], "validation_error");
+    let details: ValidationDetails =
+        serde_json::from_value(json_response["details"].clone()).unwrap();
+    let fields: Vec<&str> = details
+        .field_errors
+        .iter()
+        .map(|error| error.field.as_str())
+        .collect();
+    assert_eq!(fields, ["message", "message_file"]);
+}
+
+// Helper to add a text field to a multipart form built by create_encode_multipart
+fn append_field(mut payload: Vec<u8>, name: &str, value: &str) -> Vec<u8> {
+    payload.truncate(payload.len() - format!("--{}--\r\n", BOUNDARY).len());
+    payload.extend_from_slice(
+        format!(
+            "--{boundary}\r\n\
+             Content-Disposition: form-data; name=\"{name}\"\r\n\r\n\
+             {value}\r\n\
+             --{boundary}--\r\n",
+            boundary = BOUNDARY,
+            name = name,
+            value = value
+        )
+        .as_bytes(),
+    );
+    payload
+}
 
-    (body, content_type)
+// Helper to create a test image
+fn create_test_image(path: &PathBuf, width: u32, height: u32) {
+    let img = image::RgbImage::new(width, height);
+    img.save(path).unwrap();
 }
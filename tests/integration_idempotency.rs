@@ -0,0 +1,250 @@
+use actix_web::http::StatusCode;
+use actix_web::{test, web, App};
+use hide_rs::api::handlers::{AppState, ServerConfig};
+use hide_rs::api::routes::configure_routes;
+use hide_rs::testing::gradient_image;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use tempfile::tempdir;
+
+mod common;
+
+use common::{create_state_with, encode_request};
+
+// Save a cover outside the upload directory, whose images are counted
+fn save_cover(dir: &Path) -> PathBuf {
+    let path = dir.join("cover.png");
+    gradient_image(40, 40).save(&path).unwrap();
+    path
+}
+
+// Helper to build an encode request sent with an Idempotency-Key header
+fn keyed_request(cover: &Path, message: &str, key: &str) -> test::TestRequest {
+    encode_request(Some(cover), message, &[]).insert_header(("Idempotency-Key", key))
+}
+
+fn stored_images(dir: &Path) -> usize {
+    fs::read_dir(dir)
+        .unwrap()
+        .filter(|entry| {
+            entry
+                .as_ref()
+                .unwrap()
+                .path()
+                .extension()
+                .is_some_and(|ext| ext == "png")
+        })
+        .count()
+}
+
+fn state(upload_dir: &Path, window_secs: u64) -> web::Data<AppState> {
+    create_state_with(
+        upload_dir,
+        ServerConfig {
+            idempotency_window_secs: window_secs,
+            ..Default::default()
+        },
+    )
+}
+
+#[actix_web::test]
+async fn test_concurrent_duplicates_store_one_image() {
+    let upload_dir = tempdir().unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(state(upload_dir.path(), 60))
+            .configure(configure_routes),
+    )
+    .await;
+    let cover_dir = tempdir().unwrap();
+    let cover = save_cover(cover_dir.path());
+
+    let (first, second) = futures::join!(
+        test::call_service(
+            &app,
+            encode_request(Some(&cover), "retried message", &[]).to_request()
+        ),
+        test::call_service(
+            &app,
+            encode_request(Some(&cover), "retried message", &[]).to_request()
+        ),
+    );
+    assert!(first.status().is_success());
+    assert!(second.status().is_success());
+
+    let replays: Vec<bool> = [&first, &second]
+        .iter()
+        .map(|resp| {
+            resp.headers()
+                .get("X-Idempotent-Replay")
+                .is_some_and(|v| v == "true")
+        })
+        .collect();
+    assert_eq!(replays.iter().filter(|&&replay| replay).count(), 1);
+
+    let first: serde_json::Value = test::read_body_json(first).await;
+    let second: serde_json::Value = test::read_body_json(second).await;
+    assert_eq!(first["image_id"], second["image_id"]);
+    assert_eq!(first["download_url"], second["download_url"]);
+    assert_eq!(stored_images(upload_dir.path()), 1);
+}
+
+#[actix_web::test]
+async fn test_idempotency_key_header_and_distinct_requests() {
+    let upload_dir = tempdir().unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(state(upload_dir.path(), 60))
+            .configure(configure_routes),
+    )
+    .await;
+    let cover_dir = tempdir().unwrap();
+    let cover = save_cover(cover_dir.path());
+
+    // Different messages are different requests
+    let a: serde_json::Value = test::call_and_read_body_json(
+        &app,
+        encode_request(Some(&cover), "first", &[]).to_request(),
+    )
+    .await;
+    let b: serde_json::Value = test::call_and_read_body_json(
+        &app,
+        encode_request(Some(&cover), "second", &[]).to_request(),
+    )
+    .await;
+    assert_ne!(a["image_id"], b["image_id"]);
+
+    // A client key identifies a retry of the same request
+    let keyed: serde_json::Value = test::call_and_read_body_json(
+        &app,
+        keyed_request(&cover, "third", "order-42").to_request(),
+    )
+    .await;
+    let retry = test::call_service(
+        &app,
+        keyed_request(&cover, "third", "order-42").to_request(),
+    )
+    .await;
+    assert_eq!(retry.headers().get("X-Idempotent-Replay").unwrap(), "true");
+    let retry: serde_json::Value = test::read_body_json(retry).await;
+    assert_eq!(keyed["image_id"], retry["image_id"]);
+    assert_eq!(stored_images(upload_dir.path()), 3);
+}
+
+#[actix_web::test]
+async fn test_reused_key_with_other_contents_is_refused() {
+    let upload_dir = tempdir().unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(state(upload_dir.path(), 60))
+            .configure(configure_routes),
+    )
+    .await;
+    let cover_dir = tempdir().unwrap();
+    let cover = save_cover(cover_dir.path());
+
+    let first =
+        test::call_service(&app, keyed_request(&cover, "first", "order-7").to_request()).await;
+    assert!(first.status().is_success());
+
+    let reused = test::call_service(
+        &app,
+        keyed_request(&cover, "something else", "order-7").to_request(),
+    )
+    .await;
+    assert_eq!(reused.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(reused.headers().get("X-Idempotent-Replay").is_none());
+    let body: serde_json::Value = test::read_body_json(reused).await;
+    assert_eq!(body["error_code"], "idempotency_key_reused");
+    assert_eq!(stored_images(upload_dir.path()), 1);
+}
+
+#[actix_web::test]
+async fn test_keys_are_scoped_per_client() {
+    let upload_dir = tempdir().unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(state(upload_dir.path(), 60))
+            .configure(configure_routes),
+    )
+    .await;
+    let cover_dir = tempdir().unwrap();
+    let cover = save_cover(cover_dir.path());
+    let from = |addr: &str| {
+        keyed_request(&cover, "same request", "order-1")
+            .peer_addr(addr.parse::<SocketAddr>().unwrap())
+            .to_request()
+    };
+
+    let a: serde_json::Value = test::call_and_read_body_json(&app, from("10.0.0.1:1000")).await;
+    // Another client with the same key and contents gets its own image
+    let b = test::call_service(&app, from("10.0.0.2:1000")).await;
+    assert!(b.headers().get("X-Idempotent-Replay").is_none());
+    let b: serde_json::Value = test::read_body_json(b).await;
+    assert_ne!(a["image_id"], b["image_id"]);
+
+    // The first client retrying from a new connection still gets its own response
+    let retry = test::call_service(&app, from("10.0.0.1:2000")).await;
+    assert_eq!(retry.headers().get("X-Idempotent-Replay").unwrap(), "true");
+    let retry: serde_json::Value = test::read_body_json(retry).await;
+    assert_eq!(a["image_id"], retry["image_id"]);
+    assert_eq!(stored_images(upload_dir.path()), 2);
+}
+
+#[actix_web::test]
+async fn test_keys_are_scoped_per_forwarded_client_behind_a_proxy() {
+    let upload_dir = tempdir().unwrap();
+    let state = create_state_with(
+        upload_dir.path(),
+        ServerConfig {
+            idempotency_window_secs: 60,
+            trust_proxy: true,
+            ..Default::default()
+        },
+    );
+    let app = test::init_service(App::new().app_data(state).configure(configure_routes)).await;
+    let cover_dir = tempdir().unwrap();
+    let cover = save_cover(cover_dir.path());
+    // Every request reaches the server from the proxy's address
+    let via_proxy = |client: &str| {
+        keyed_request(&cover, "same request", "order-1")
+            .peer_addr("10.0.0.1:1000".parse::<SocketAddr>().unwrap())
+            .insert_header(("X-Forwarded-For", client))
+            .to_request()
+    };
+
+    let a: serde_json::Value = test::call_and_read_body_json(&app, via_proxy("203.0.113.1")).await;
+    let b = test::call_service(&app, via_proxy("203.0.113.2")).await;
+    assert!(b.headers().get("X-Idempotent-Replay").is_none());
+    let b: serde_json::Value = test::read_body_json(b).await;
+    assert_ne!(a["image_id"], b["image_id"]);
+
+    let retry = test::call_service(&app, via_proxy("203.0.113.1")).await;
+    assert_eq!(retry.headers().get("X-Idempotent-Replay").unwrap(), "true");
+    assert_eq!(stored_images(upload_dir.path()), 2);
+}
+
+#[actix_web::test]
+async fn test_disabled_window_encodes_every_request() {
+    let upload_dir = tempdir().unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(state(upload_dir.path(), 0))
+            .configure(configure_routes),
+    )
+    .await;
+    let cover_dir = tempdir().unwrap();
+    let cover = save_cover(cover_dir.path());
+
+    for _ in 0..2 {
+        let resp = test::call_service(
+            &app,
+            encode_request(Some(&cover), "no dedup", &[]).to_request(),
+        )
+        .await;
+        assert!(resp.status().is_success());
+        assert!(resp.headers().get("X-Idempotent-Replay").is_none());
+    }
+    assert_eq!(stored_images(upload_dir.path()), 2);
+}
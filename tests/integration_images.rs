@@ -0,0 +1,112 @@
+use actix_web::{test, web, App};
+use chrono::{TimeZone, Utc};
+use hide_rs::api::handlers::AppState;
+use hide_rs::api::models::ImagesResponse;
+use hide_rs::api::routes::configure_routes;
+use hide_rs::api::store::{ImageRecord, MemoryMetadataStore};
+use std::collections::HashSet;
+use std::fs;
+use std::sync::Arc;
+use tempfile::tempdir;
+use uuid::Uuid;
+
+mod common;
+
+use common::create_state;
+
+#[actix_web::test]
+async fn test_walk_all_pages() {
+    let temp_dir = tempdir().unwrap();
+    let store = MemoryMetadataStore::new();
+    for i in 0..50 {
+        store.insert(ImageRecord {
+            image_id: Uuid::new_v4(),
+            // Pairs of records share a timestamp to exercise the tiebreaker
+            created_at: Utc.timestamp_opt(1_700_000_000 + i / 2, 0).unwrap(),
+        });
+    }
+    let state = web::Data::new(AppState {
+        image_store: Arc::new(store),
+        ..AppState::clone(&create_state(temp_dir.path()))
+    });
+    let app = test::init_service(App::new().app_data(state).configure(configure_routes)).await;
+
+    let mut seen = Vec::new();
+    let mut uri = "/api/v1/images?limit=7".to_string();
+    loop {
+        let req = test::TestRequest::get().uri(&uri).to_request();
+        let page: ImagesResponse = test::call_and_read_body_json(&app, req).await;
+        assert!(page.images.len() <= 7);
+        assert_eq!(page.total_count, Some(50));
+        seen.extend(page.images);
+
+        match page.next_cursor {
+            Some(cursor) => {
+                assert!(page.has_more);
+                uri = format!("/api/v1/images?limit=7&cursor={}", cursor);
+            }
+            None => {
+                assert!(!page.has_more);
+                break;
+            }
+        }
+    }
+
+    assert_eq!(seen.len(), 50);
+    let ids: HashSet<_> = seen.iter().map(|image| image.image_id).collect();
+    assert_eq!(ids.len(), 50);
+    assert!(seen.windows(2).all(
+        |pair| (pair[0].created_at, pair[0].image_id) > (pair[1].created_at, pair[1].image_id)
+    ));
+    assert_eq!(
+        seen[0].download_url,
+        format!("/api/v1/images/{}", seen[0].image_id)
+    );
+}
+
+#[actix_web::test]
+async fn test_lists_images_in_upload_dir() {
+    let temp_dir = tempdir().unwrap();
+    let id = Uuid::new_v4();
+    fs::write(temp_dir.path().join(format!("{}.png", id)), b"png").unwrap();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(create_state(temp_dir.path()))
+            .configure(configure_routes),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/api/v1/images").to_request();
+    let page: ImagesResponse = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(page.images.len(), 1);
+    assert_eq!(page.images[0].image_id, id);
+    assert!(!page.has_more);
+}
+
+#[actix_web::test]
+async fn test_invalid_query_parameters() {
+    let temp_dir = tempdir().unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(create_state(temp_dir.path()))
+            .configure(configure_routes),
+    )
+    .await;
+
+    for (query, field) in [
+        ("cursor=bogus", "cursor"),
+        ("limit=0", "limit"),
+        ("limit=ten", "limit"),
+    ] {
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/v1/images?{}", query))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400, "{}", query);
+
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["error_code"], "validation_error");
+        assert_eq!(body["details"]["field_errors"][0]["field"], field);
+    }
+}
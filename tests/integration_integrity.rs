@@ -0,0 +1,123 @@
+use actix_web::{test, App};
+use hide_rs::api::routes::configure_routes;
+use hide_rs::api::store::CONTENT_SHA256_HEADER;
+use hide_rs::img::create_rgb_image;
+use hide_rs::manifest::sha256_hex;
+use std::fs;
+use tempfile::tempdir;
+
+mod common;
+
+use common::{create_state, encode_request};
+
+#[actix_web::test]
+async fn test_download_carries_content_hash() {
+    let temp_dir = tempdir().unwrap();
+
+    let upload_dir = temp_dir.path().to_path_buf();
+    let cover_path = upload_dir.join("cover.png");
+    create_rgb_image(50, 50).unwrap().save(&cover_path).unwrap();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(create_state(&upload_dir))
+            .configure(configure_routes),
+    )
+    .await;
+
+    let json_response: serde_json::Value = test::call_and_read_body_json(
+        &app,
+        encode_request(Some(&cover_path), "integrity test", &[]).to_request(),
+    )
+    .await;
+    let (image_id, content_sha256) = stored_image(&json_response);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/v1/images/{}", image_id))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    assert_eq!(
+        resp.headers().get(CONTENT_SHA256_HEADER).unwrap(),
+        content_sha256.as_str()
+    );
+    assert_eq!(
+        resp.headers().get("ETag").unwrap(),
+        format!("\"{}\"", content_sha256).as_str()
+    );
+
+    let body = test::read_body(resp).await;
+    assert_eq!(sha256_hex(&body), content_sha256);
+}
+
+#[actix_web::test]
+async fn test_tampered_image_is_not_served() {
+    let temp_dir = tempdir().unwrap();
+    let upload_dir = temp_dir.path().to_path_buf();
+    let cover_path = upload_dir.join("cover.png");
+    create_rgb_image(50, 50).unwrap().save(&cover_path).unwrap();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(create_state(&upload_dir))
+            .configure(configure_routes),
+    )
+    .await;
+
+    let json_response: serde_json::Value = test::call_and_read_body_json(
+        &app,
+        encode_request(Some(&cover_path), "integrity test", &[]).to_request(),
+    )
+    .await;
+    let (image_id, _) = stored_image(&json_response);
+
+    // Flip a bit of the stored file
+    let stored_path = upload_dir.join(format!("{}.png", image_id));
+    let mut stored = fs::read(&stored_path).unwrap();
+    let last = stored.len() - 1;
+    stored[last] ^= 1;
+    fs::write(&stored_path, &stored).unwrap();
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/v1/images/{}", image_id))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 500);
+    assert!(resp.headers().get(CONTENT_SHA256_HEADER).is_none());
+}
+
+#[actix_web::test]
+async fn test_images_without_a_recorded_hash_are_served() {
+    // Images stored before hashes were recorded are served unchecked
+    let temp_dir = tempdir().unwrap();
+    let image_id = uuid::Uuid::new_v4();
+    create_rgb_image(10, 10)
+        .unwrap()
+        .save(temp_dir.path().join(format!("{}.png", image_id)))
+        .unwrap();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(create_state(temp_dir.path()))
+            .configure(configure_routes),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/v1/images/{}", image_id))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    assert!(resp.headers().get(CONTENT_SHA256_HEADER).is_none());
+    assert!(resp.headers().get("ETag").is_none());
+}
+
+// Helper to read the image ID and content hash from an encode response
+fn stored_image(json_response: &serde_json::Value) -> (String, String) {
+    let image_id = json_response["image_id"].as_str().unwrap().to_string();
+    let content_sha256 = json_response["metadata"]["content_sha256"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    (image_id, content_sha256)
+}
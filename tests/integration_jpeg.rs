@@ -0,0 +1,104 @@
+#![cfg(feature = "jpeg")]
+
+use actix_web::{test, App};
+use hide_rs::api::routes::configure_routes;
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+mod common;
+
+use common::{create_state, file_multipart, post_multipart};
+
+/// A 64x48 4:4:4 baseline JPEG written by the `image` crate at quality 85
+const COVER: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/jpeg/cover.jpg");
+
+#[actix_web::test]
+async fn test_encode_download_and_decode_a_jpeg() {
+    let temp_dir = tempdir().unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(create_state(temp_dir.path()))
+            .configure(configure_routes),
+    )
+    .await;
+
+    let req = post_multipart(
+        "/api/v1/encode",
+        file_multipart(
+            "cover_image",
+            Path::new(COVER),
+            &[
+                ("message", "kept in the coefficients"),
+                ("algorithm", "jpeg-dct"),
+            ],
+        ),
+    )
+    .to_request();
+    let encoded: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(encoded["status"], "success", "{}", encoded);
+    assert_eq!(encoded["metadata"]["format"], "jpeg");
+    assert_eq!(encoded["metadata"]["width"], 64);
+
+    let req = test::TestRequest::get()
+        .uri(encoded["download_url"].as_str().unwrap())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    assert_eq!(resp.headers().get("content-type").unwrap(), "image/jpeg");
+    let disposition = resp.headers().get("content-disposition").unwrap();
+    assert!(disposition.to_str().unwrap().contains(".jpg"));
+    let stego = test::read_body(resp).await;
+    assert!(image::load_from_memory(&stego).is_ok());
+
+    let stego_path = temp_dir.path().join("download.jpg");
+    fs::write(&stego_path, &stego).unwrap();
+    let req = post_multipart(
+        "/api/v1/decode",
+        file_multipart("stego_image", &stego_path, &[("algorithm", "jpeg-dct")]),
+    )
+    .to_request();
+    let decoded: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(
+        decoded["message"], "kept in the coefficients",
+        "{}",
+        decoded
+    );
+}
+
+#[actix_web::test]
+async fn test_png_covers_and_variants_are_rejected() {
+    let temp_dir = tempdir().unwrap();
+    let png_path = temp_dir.path().join("cover.png");
+    image::load_from_memory(&fs::read(COVER).unwrap())
+        .unwrap()
+        .save(&png_path)
+        .unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(create_state(temp_dir.path()))
+            .configure(configure_routes),
+    )
+    .await;
+
+    for (cover, extra, error_code) in [
+        (png_path.as_path(), None, "invalid_image"),
+        (
+            Path::new(COVER),
+            Some(("variants", "[\"fast\"]")),
+            "validation_error",
+        ),
+    ] {
+        let mut fields = vec![("message", "hi"), ("algorithm", "jpeg-dct")];
+        fields.extend(extra);
+        let req = post_multipart(
+            "/api/v1/encode",
+            file_multipart("cover_image", cover, &fields),
+        )
+        .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["error_code"], error_code, "{}", body);
+    }
+}
@@ -0,0 +1,63 @@
+use actix_web::{test, App};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use hide_rs::api::handlers::ServerConfig;
+use hide_rs::api::routes::configure_routes;
+use hide_rs::encoder::Encoder;
+use hide_rs::testing::gradient_image;
+use image::ImageFormat;
+use tempfile::tempdir;
+
+mod common;
+
+use common::{content_type, create_state_with, multipart};
+
+#[actix_web::test]
+async fn test_boundary_lengths_round_trip_through_the_api() {
+    let temp_dir = tempdir().unwrap();
+    let state = create_state_with(
+        temp_dir.path(),
+        ServerConfig {
+            decode_cache_entries: 0,
+            ..Default::default()
+        },
+    );
+    let app = test::init_service(App::new().app_data(state).configure(configure_routes)).await;
+
+    for len in [1, 255, 256, 65535, 65536] {
+        let mut message: Vec<u8> = (0..len).map(|i| (i * 31 + 7) as u8).collect();
+        message[len - 1] = 0xff;
+        let pixels = Encoder::pixels_needed(len) as u32;
+        let side = (pixels as f64).sqrt().ceil() as u32;
+        let cover = gradient_image(side, pixels.div_ceil(side))
+            .to_bytes(ImageFormat::Png)
+            .unwrap();
+
+        let req = test::TestRequest::post()
+            .uri("/api/v1/encode")
+            .insert_header(("content-type", content_type()))
+            .set_payload(multipart(&[
+                ("cover_image", Some("cover.png"), &cover),
+                ("message", None, &message),
+            ]))
+            .to_request();
+        let encoded: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(encoded["status"], "success", "{} bytes: {}", len, encoded);
+        assert_eq!(encoded["metadata"]["embedded_message_bytes"], len);
+
+        let req = test::TestRequest::get()
+            .uri(encoded["download_url"].as_str().unwrap())
+            .to_request();
+        let stego = test::call_and_read_body(&app, req).await;
+
+        let req = test::TestRequest::post()
+            .uri("/api/v1/decode")
+            .insert_header(("content-type", content_type()))
+            .set_payload(multipart(&[("stego_image", Some("stego.png"), &stego)]))
+            .to_request();
+        let decoded: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let recovered = BASE64
+            .decode(decoded["binary_message"].as_str().unwrap())
+            .unwrap();
+        assert_eq!(recovered, message, "{} bytes", len);
+    }
+}
@@ -0,0 +1,81 @@
+use hide_rs::pack::{pack_dir, unpack_dir, PACK_MANIFEST_FILENAME};
+use hide_rs::testing::gradient_image;
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+#[test]
+fn test_pack_and_unpack_round_trip() {
+    let temp_dir = tempdir().unwrap();
+    let input_dir = temp_dir.path().join("docs");
+    let covers_dir = temp_dir.path().join("covers");
+    let output_dir = temp_dir.path().join("out");
+    let restored_dir = temp_dir.path().join("restored");
+
+    // Three files of different sizes, one in a subdirectory
+    let files: [(&str, Vec<u8>); 3] = [
+        ("notes.txt", b"Remember the milk".to_vec()),
+        ("nested/data.bin", (0..=255u8).cycle().take(900).collect()),
+        ("todo.md", b"- pack\n- unpack\n".to_vec()),
+    ];
+    for (name, data) in &files {
+        let path = input_dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, data).unwrap();
+    }
+
+    // Three covers; only the largest can hold the binary file
+    fs::create_dir_all(&covers_dir).unwrap();
+    create_cover(&covers_dir.join("small.png"), 16, 16);
+    create_cover(&covers_dir.join("medium.png"), 24, 24);
+    create_cover(&covers_dir.join("large.png"), 60, 60);
+
+    let manifest = pack_dir(&input_dir, &covers_dir, &output_dir).unwrap();
+    assert_eq!(manifest.entries.len(), 3);
+    assert!(output_dir.join(PACK_MANIFEST_FILENAME).exists());
+
+    let binary = manifest
+        .entries
+        .iter()
+        .find(|e| e.file == "nested/data.bin")
+        .unwrap();
+    assert_eq!(binary.cover, "large.png");
+
+    // Every file is restored byte for byte
+    let restored = unpack_dir(&output_dir, &restored_dir).unwrap();
+    assert_eq!(restored.len(), 3);
+    for (name, data) in &files {
+        assert_eq!(&fs::read(restored_dir.join(name)).unwrap(), data);
+    }
+}
+
+#[test]
+fn test_pack_fails_before_writing_when_capacity_is_insufficient() {
+    let temp_dir = tempdir().unwrap();
+    let input_dir = temp_dir.path().join("docs");
+    let covers_dir = temp_dir.path().join("covers");
+    let output_dir = temp_dir.path().join("out");
+
+    fs::create_dir_all(&input_dir).unwrap();
+    fs::write(input_dir.join("small.txt"), b"fits").unwrap();
+    fs::write(input_dir.join("big.bin"), vec![7u8; 4096]).unwrap();
+
+    fs::create_dir_all(&covers_dir).unwrap();
+    create_cover(&covers_dir.join("a.png"), 20, 20);
+    create_cover(&covers_dir.join("b.png"), 20, 20);
+
+    let err = pack_dir(&input_dir, &covers_dir, &output_dir).unwrap_err();
+    assert!(
+        err.to_string().contains("big.bin"),
+        "Unexpected error: {}",
+        err
+    );
+
+    // Nothing was written
+    assert!(!output_dir.exists());
+}
+
+// Helper to create a cover image with some texture
+fn create_cover(path: &Path, width: u32, height: u32) {
+    gradient_image(width, height).save(path).unwrap();
+}
@@ -0,0 +1,60 @@
+use actix_web::{test, App};
+use hide_rs::api::routes::configure_routes;
+use hide_rs::testing::gradient_image;
+use image::ImageFormat;
+use tempfile::tempdir;
+
+mod common;
+
+use common::{create_state, post_multipart, upload_multipart};
+
+#[actix_web::test]
+async fn test_plan_endpoint_recommends_an_option() {
+    let temp_dir = tempdir().unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(create_state(temp_dir.path()))
+            .configure(configure_routes),
+    )
+    .await;
+
+    let resp = test::call_service(
+        &app,
+        plan_request(&[("message_size", "100"), ("robustness", "recoverable")]).to_request(),
+    )
+    .await;
+    assert!(resp.status().is_success());
+    let json: serde_json::Value = serde_json::from_slice(&test::read_body(resp).await).unwrap();
+    assert_eq!(json["width"], 40);
+    assert_eq!(json["plan"]["recommended"], "recorded");
+    assert_eq!(json["plan"]["options"].as_array().unwrap().len(), 5);
+
+    // Nothing fits, but the plan still lists what was considered
+    let resp =
+        test::call_service(&app, plan_request(&[("message_size", "5000")]).to_request()).await;
+    assert!(resp.status().is_success());
+    let json: serde_json::Value = serde_json::from_slice(&test::read_body(resp).await).unwrap();
+    assert!(json["plan"].get("recommended").is_none());
+
+    let resp = test::call_service(
+        &app,
+        plan_request(&[("message_size", "100"), ("robustness", "bulletproof")]).to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 400);
+    let json: serde_json::Value = serde_json::from_slice(&test::read_body(resp).await).unwrap();
+    assert_eq!(json["details"]["field_errors"][0]["field"], "robustness");
+}
+
+// Helper to build a plan request for a 40x40 image with extra text fields
+fn plan_request(fields: &[(&str, &str)]) -> test::TestRequest {
+    let mut cover = std::io::Cursor::new(Vec::new());
+    gradient_image(40, 40)
+        .inner()
+        .write_to(&mut cover, ImageFormat::Png)
+        .unwrap();
+    post_multipart(
+        "/api/v1/plan",
+        upload_multipart("cover_image", "cover.png", cover.get_ref(), fields),
+    )
+}
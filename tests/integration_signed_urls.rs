@@ -0,0 +1,191 @@
+use actix_web::dev::ServiceResponse;
+use actix_web::{test, App};
+use hide_rs::api::handlers::{ServerConfig, DECODED_MESSAGE_FILE};
+use hide_rs::api::models::error_codes;
+use hide_rs::api::routes::configure_routes;
+use hide_rs::api::signing::UrlSigner;
+use hide_rs::img::create_rgb_image;
+use std::time::{Duration, SystemTime};
+use tempfile::tempdir;
+
+mod common;
+
+use common::{create_state, create_state_with, encode_request};
+
+const SECRET: &str = "integration test secret";
+
+#[actix_web::test]
+async fn test_signed_download_urls() {
+    let temp_dir = tempdir().unwrap();
+    let upload_dir = temp_dir.path().to_path_buf();
+    let cover_path = upload_dir.join("cover.png");
+    create_rgb_image(50, 50).unwrap().save(&cover_path).unwrap();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(create_state_with(
+                &upload_dir,
+                ServerConfig {
+                    url_signing_secret: Some(SECRET.to_string()),
+                    ..Default::default()
+                },
+            ))
+            .configure(configure_routes),
+    )
+    .await;
+
+    let json_response: serde_json::Value = test::call_and_read_body_json(
+        &app,
+        encode_request(Some(&cover_path), "signed url test", &[]).to_request(),
+    )
+    .await;
+    let image_id = json_response["image_id"].as_str().unwrap();
+    let download_url = json_response["download_url"].as_str().unwrap();
+    assert!(download_url.contains("expires="));
+    assert!(download_url.contains("&sig="));
+
+    // The signed URL downloads the image
+    let req = test::TestRequest::get().uri(download_url).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+
+    // So do the signed URLs of the image listing
+    let listing: serde_json::Value = test::call_and_read_body_json(
+        &app,
+        test::TestRequest::get().uri("/api/v1/images").to_request(),
+    )
+    .await;
+    let listed_url = listing["images"][0]["download_url"].as_str().unwrap();
+    let req = test::TestRequest::get().uri(listed_url).to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+
+    // Unsigned access is refused, even for images that don't exist
+    let plain = format!("/api/v1/images/{}", image_id);
+    assert_forbidden(
+        test::call_service(&app, test::TestRequest::get().uri(&plain).to_request()).await,
+        error_codes::INVALID_SIGNATURE,
+    )
+    .await;
+    let missing = format!("/api/v1/images/{}", uuid::Uuid::new_v4());
+    assert_forbidden(
+        test::call_service(&app, test::TestRequest::get().uri(&missing).to_request()).await,
+        error_codes::INVALID_SIGNATURE,
+    )
+    .await;
+
+    // A signature for one image doesn't download another
+    let query = download_url.split_once('?').unwrap().1;
+    let other = format!("/api/v1/images/{}?{}", uuid::Uuid::new_v4(), query);
+    assert_forbidden(
+        test::call_service(&app, test::TestRequest::get().uri(&other).to_request()).await,
+        error_codes::INVALID_SIGNATURE,
+    )
+    .await;
+
+    // Expired links are refused with their own code
+    let an_hour_ago = unix_now() - 3600;
+    let expired = format!(
+        "{}?{}",
+        plain,
+        UrlSigner::new(SECRET, Duration::from_secs(60)).sign_at(image_id, an_hour_ago)
+    );
+    assert_forbidden(
+        test::call_service(&app, test::TestRequest::get().uri(&expired).to_request()).await,
+        error_codes::LINK_EXPIRED,
+    )
+    .await;
+}
+
+#[actix_web::test]
+async fn test_signed_message_urls() {
+    let temp_dir = tempdir().unwrap();
+    let state = create_state_with(
+        temp_dir.path(),
+        ServerConfig {
+            url_signing_secret: Some(SECRET.to_string()),
+            ..Default::default()
+        },
+    );
+    let message_id = uuid::Uuid::new_v4();
+    state
+        .spool
+        .keep(message_id, DECODED_MESSAGE_FILE, b"stored message")
+        .unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(state.clone())
+            .configure(configure_routes),
+    )
+    .await;
+
+    // Stored messages are linked with a signature, like images
+    let download_url = state
+        .url_builder
+        .message_url(&test::TestRequest::default().to_http_request(), message_id);
+    assert!(download_url.contains("expires="));
+    let req = test::TestRequest::get().uri(&download_url).to_request();
+    assert_eq!(test::call_and_read_body(&app, req).await, "stored message");
+
+    // Unsigned access and another message's signature are refused
+    let plain = format!("/api/v1/messages/{}", message_id);
+    assert_forbidden(
+        test::call_service(&app, test::TestRequest::get().uri(&plain).to_request()).await,
+        error_codes::INVALID_SIGNATURE,
+    )
+    .await;
+    let query = download_url.split_once('?').unwrap().1;
+    let other = format!("/api/v1/messages/{}?{}", uuid::Uuid::new_v4(), query);
+    assert_forbidden(
+        test::call_service(&app, test::TestRequest::get().uri(&other).to_request()).await,
+        error_codes::INVALID_SIGNATURE,
+    )
+    .await;
+}
+
+#[actix_web::test]
+async fn test_unsigned_download_urls_without_a_secret() {
+    let temp_dir = tempdir().unwrap();
+    let upload_dir = temp_dir.path().to_path_buf();
+    let cover_path = upload_dir.join("cover.png");
+    create_rgb_image(50, 50).unwrap().save(&cover_path).unwrap();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(create_state(&upload_dir))
+            .configure(configure_routes),
+    )
+    .await;
+
+    let json_response: serde_json::Value = test::call_and_read_body_json(
+        &app,
+        encode_request(Some(&cover_path), "signed url test", &[]).to_request(),
+    )
+    .await;
+    let image_id = json_response["image_id"].as_str().unwrap();
+    let download_url = json_response["download_url"].as_str().unwrap();
+    assert_eq!(download_url, format!("/api/v1/images/{}", image_id));
+
+    let req = test::TestRequest::get().uri(download_url).to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+
+    // Signature parameters are ignored
+    let req = test::TestRequest::get()
+        .uri(&format!("{}?expires=1&sig=00", download_url))
+        .to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+}
+
+// Helper to check that a download is refused with an error code
+async fn assert_forbidden(resp: ServiceResponse, error_code: &str) {
+    assert_eq!(resp.status(), 403);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["error_code"], error_code);
+}
+
+// Helper to get the current time in seconds since the Unix epoch
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
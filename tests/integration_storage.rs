@@ -0,0 +1,124 @@
+#![cfg(unix)]
+
+use actix_web::{test, App};
+use hide_rs::api::handlers::{ServerConfig, STORAGE_RETRY_AFTER_SECS};
+use hide_rs::api::routes::configure_routes;
+use hide_rs::img::create_rgb_image;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use tempfile::tempdir;
+use uuid::Uuid;
+
+mod common;
+
+use common::{create_state, create_state_with, encode_request};
+
+#[actix_web::test]
+async fn test_read_only_upload_dir_degrades_health() {
+    // Create a read-only upload directory
+    let temp_dir = tempdir().unwrap();
+    let upload_dir = temp_dir.path().join("uploads");
+    fs::create_dir(&upload_dir).unwrap();
+    set_mode(&upload_dir, 0o555);
+
+    // Permissions aren't enforced for privileged users, so there's nothing to test
+    if fs::write(upload_dir.join("probe"), b"probe").is_ok() {
+        set_mode(&upload_dir, 0o755);
+        return;
+    }
+
+    // The cover image lives outside the upload directory
+    let cover_path = temp_dir.path().join("cover.png");
+    create_rgb_image(40, 40).unwrap().save(&cover_path).unwrap();
+
+    // Create test application
+    let state = create_state(&upload_dir);
+    let app = test::init_service(App::new().app_data(state).configure(configure_routes)).await;
+
+    // Uploads fail with a retryable error
+    let req = encode_request(Some(&cover_path), "Read-only test", &[]).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 503);
+    assert_eq!(
+        resp.headers().get("Retry-After").unwrap(),
+        &STORAGE_RETRY_AFTER_SECS.to_string()
+    );
+    let body = test::read_body(resp).await;
+    let json_response: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json_response["error_code"], "storage_unavailable");
+
+    let req = test::TestRequest::get().uri("/api/v1/health").to_request();
+    let health: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(health["status"], "degraded");
+
+    // Health recovers after the next successful write
+    set_mode(&upload_dir, 0o755);
+    let req = encode_request(Some(&cover_path), "Read-only test", &[]).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+
+    let req = test::TestRequest::get().uri("/api/v1/health").to_request();
+    let health: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(health["status"], "ok");
+}
+
+#[actix_web::test]
+async fn test_spool_budget_sweeps_leftovers_then_refuses_uploads() {
+    let temp_dir = tempdir().unwrap();
+    let upload_dir = temp_dir.path().join("uploads");
+    fs::create_dir(&upload_dir).unwrap();
+    let cover_path = temp_dir.path().join("cover.png");
+    create_rgb_image(40, 40).unwrap().save(&cover_path).unwrap();
+    let cover_bytes = fs::metadata(&cover_path).unwrap().len();
+
+    // A request directory left behind by an earlier run fills the budget
+    let leftover = upload_dir.join(Uuid::new_v4().to_string());
+    fs::create_dir(&leftover).unwrap();
+    fs::write(
+        leftover.join("cover.png"),
+        vec![0u8; 4 * cover_bytes as usize],
+    )
+    .unwrap();
+
+    let state = create_state_with(
+        &upload_dir,
+        ServerConfig {
+            max_spool_bytes: 2 * cover_bytes,
+            ..Default::default()
+        },
+    );
+    let app = test::init_service(
+        App::new()
+            .app_data(state.clone())
+            .configure(configure_routes),
+    )
+    .await;
+
+    // The sweep makes room for the upload
+    let req = encode_request(Some(&cover_path), "Read-only test", &[]).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    assert!(!leftover.exists());
+    assert_eq!(state.spool.used_bytes(), 0);
+
+    // An upload bigger than the whole budget is refused as it is written
+    let small = create_state_with(
+        &upload_dir,
+        ServerConfig {
+            max_spool_bytes: cover_bytes / 2,
+            ..Default::default()
+        },
+    );
+    let app = test::init_service(App::new().app_data(small).configure(configure_routes)).await;
+    let req = encode_request(Some(&cover_path), "Read-only test", &[]).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 503);
+    let json_response: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(json_response["error_code"], "storage_unavailable");
+}
+
+// Helper to change the permissions of a directory
+fn set_mode(path: &Path, mode: u32) {
+    fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+}
@@ -0,0 +1,96 @@
+use actix_web::{test, App};
+use hide_rs::api::handlers::ServerConfig;
+use hide_rs::api::routes::configure_routes;
+use hide_rs::encoder::Encoder;
+use hide_rs::img::create_rgb_image;
+use tempfile::tempdir;
+
+mod common;
+
+use common::{
+    create_decode_multipart, create_encode_multipart, create_state, create_state_with,
+    post_multipart,
+};
+
+#[actix_web::test]
+async fn test_encode_reports_timing() {
+    // Create a temporary directory for the test
+    let temp_dir = tempdir().unwrap();
+    let upload_dir = temp_dir.path().to_path_buf();
+
+    let cover_path = upload_dir.join("cover.png");
+    create_rgb_image(100, 100)
+        .unwrap()
+        .save(&cover_path)
+        .unwrap();
+
+    // Create test application
+    let app = test::init_service(
+        App::new()
+            .app_data(create_state(&upload_dir))
+            .configure(configure_routes),
+    )
+    .await;
+
+    let req = post_multipart(
+        "/api/v1/encode",
+        create_encode_multipart(&cover_path, "Timing test"),
+    )
+    .to_request();
+
+    let json_response: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert!(json_response["processing_ms"].as_f64().unwrap() > 0.0);
+    assert!(json_response["pixels_per_second"].as_f64().unwrap() > 0.0);
+}
+
+#[actix_web::test]
+async fn test_decode_timing_scales_with_image_size() {
+    // Create a temporary directory for the test
+    let temp_dir = tempdir().unwrap();
+    let upload_dir = temp_dir.path().to_path_buf();
+
+    // Create test application, without a decode cache so every decode is timed
+    let app = test::init_service(
+        App::new()
+            .app_data(create_state_with(
+                &upload_dir,
+                ServerConfig {
+                    decode_cache_entries: 0,
+                    ..Default::default()
+                },
+            ))
+            .configure(configure_routes),
+    )
+    .await;
+
+    let mut durations = Vec::new();
+    for size in [20, 400] {
+        // Fill a small and a large image with a message
+        let cover = create_rgb_image(size, size).unwrap();
+        let message = "t".repeat(Encoder::new().max_message_size(&cover));
+        let stego_path = upload_dir.join(format!("stego_{}.png", size));
+        Encoder::new()
+            .encode(cover, message.as_bytes())
+            .unwrap()
+            .save(&stego_path)
+            .unwrap();
+
+        let req = post_multipart("/api/v1/decode", create_decode_multipart(&stego_path, &[]))
+            .to_request();
+
+        let json_response: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(json_response["message"], message.as_str());
+
+        let processing_ms = json_response["processing_ms"].as_f64().unwrap();
+        assert!(processing_ms > 0.0);
+        assert!(json_response["pixels_per_second"].as_f64().unwrap() > 0.0);
+        durations.push(processing_ms);
+    }
+
+    // Decoding reads every pixel holding the message, so 400x more takes longer
+    assert!(
+        durations[1] > durations[0],
+        "Large image decoded faster: {:?}",
+        durations
+    );
+}
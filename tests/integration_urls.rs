@@ -0,0 +1,87 @@
+use actix_web::{test, App};
+use hide_rs::api::handlers::ServerConfig;
+use hide_rs::api::routes::configure_routes;
+use hide_rs::img::create_rgb_image;
+use tempfile::tempdir;
+
+mod common;
+
+use common::{create_state_with, encode_request};
+
+#[actix_web::test]
+async fn test_download_url_behind_trusted_proxy() {
+    // Create a temporary directory for the test
+    let temp_dir = tempdir().unwrap();
+    let upload_dir = temp_dir.path().to_path_buf();
+
+    let cover_path = upload_dir.join("cover.png");
+    create_rgb_image(50, 50).unwrap().save(&cover_path).unwrap();
+
+    // Create test application
+    let state = create_state_with(
+        &upload_dir,
+        ServerConfig {
+            trust_proxy: true,
+            ..Default::default()
+        },
+    );
+    let app = test::init_service(App::new().app_data(state).configure(configure_routes)).await;
+
+    // Encode through a proxy that serves the API under /hide
+    let req = encode_request(Some(&cover_path), "URL test", &[])
+        .insert_header(("Host", "example.com"))
+        .insert_header(("X-Forwarded-Proto", "https"))
+        .insert_header(("X-Forwarded-Prefix", "/hide"))
+        .to_request();
+
+    let json_response: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    let image_id = json_response["image_id"].as_str().unwrap();
+    let download_url = json_response["download_url"].as_str().unwrap();
+    assert_eq!(
+        download_url,
+        format!("https://example.com/hide/api/v1/images/{}", image_id)
+    );
+
+    // The proxy strips the prefix before forwarding the download
+    let path = download_url
+        .strip_prefix("https://example.com/hide")
+        .unwrap();
+    let req = test::TestRequest::get().uri(path).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+
+    let body = test::read_body(resp).await;
+    let image = image::load_from_memory(&body).expect("Download is not a valid image");
+    assert_eq!((image.width(), image.height()), (50, 50));
+}
+
+#[actix_web::test]
+async fn test_download_url_with_public_base_url() {
+    // Create a temporary directory for the test
+    let temp_dir = tempdir().unwrap();
+    let upload_dir = temp_dir.path().to_path_buf();
+
+    let cover_path = upload_dir.join("cover.png");
+    create_rgb_image(50, 50).unwrap().save(&cover_path).unwrap();
+
+    // Create test application; forwarded headers are ignored without trust_proxy
+    let state = create_state_with(
+        &upload_dir,
+        ServerConfig {
+            public_base_url: Some("https://stego.example.org/".to_string()),
+            ..Default::default()
+        },
+    );
+    let app = test::init_service(App::new().app_data(state).configure(configure_routes)).await;
+
+    let req = encode_request(Some(&cover_path), "URL test", &[])
+        .insert_header(("X-Forwarded-Prefix", "/ignored"))
+        .to_request();
+
+    let json_response: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    let image_id = json_response["image_id"].as_str().unwrap();
+    assert_eq!(
+        json_response["download_url"],
+        format!("https://stego.example.org/api/v1/images/{}", image_id)
+    );
+}
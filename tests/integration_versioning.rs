@@ -0,0 +1,105 @@
+use actix_web::{test, App};
+use hide_rs::api::handlers::ServerConfig;
+use hide_rs::api::models::VersionsResponse;
+use hide_rs::api::routes::{configure_routes, LEGACY_SUNSET};
+use tempfile::tempdir;
+
+mod common;
+
+use common::{create_state, create_state_with};
+
+#[actix_web::test]
+async fn test_both_prefixes_serve_identical_bodies() {
+    let temp_dir = tempdir().unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(create_state(temp_dir.path()))
+            .configure(configure_routes),
+    )
+    .await;
+
+    for endpoint in ["health", "ping"] {
+        // Current version
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/v1/{}", endpoint))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        assert!(resp.headers().get("Deprecation").is_none());
+        assert!(resp.headers().get("Sunset").is_none());
+        let v1_body = test::read_body(resp).await;
+
+        // Legacy alias
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/{}", endpoint))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        assert_eq!(resp.headers().get("Deprecation").unwrap(), "true");
+        assert_eq!(resp.headers().get("Sunset").unwrap(), LEGACY_SUNSET);
+        let legacy_body = test::read_body(resp).await;
+
+        assert_eq!(v1_body, legacy_body, "Bodies differ for {}", endpoint);
+    }
+}
+
+#[actix_web::test]
+async fn test_legacy_routes_can_be_disabled() {
+    let temp_dir = tempdir().unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(create_state_with(
+                temp_dir.path(),
+                ServerConfig {
+                    enable_legacy_routes: false,
+                    ..Default::default()
+                },
+            ))
+            .configure(configure_routes),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/api/health").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+
+    let req = test::TestRequest::get().uri("/api/v1/health").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+}
+
+#[actix_web::test]
+async fn test_versions_endpoint() {
+    let temp_dir = tempdir().unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(create_state(temp_dir.path()))
+            .configure(configure_routes),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/api/versions").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    assert!(resp.headers().get("Deprecation").is_none());
+
+    let body = test::read_body(resp).await;
+    let response: VersionsResponse = serde_json::from_slice(&body).unwrap();
+
+    let v1 = response
+        .versions
+        .iter()
+        .find(|v| v.version == "v1")
+        .expect("Missing v1");
+    assert_eq!(v1.prefix, "/api/v1");
+    assert_eq!(v1.status, "current");
+
+    let legacy = response
+        .versions
+        .iter()
+        .find(|v| v.version == "legacy")
+        .expect("Missing legacy version");
+    assert_eq!(legacy.prefix, "/api");
+    assert_eq!(legacy.status, "deprecated");
+    assert_eq!(legacy.sunset.as_deref(), Some(LEGACY_SUNSET));
+}
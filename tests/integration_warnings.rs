@@ -0,0 +1,74 @@
+use actix_web::{test, App};
+use hide_rs::api::routes::configure_routes;
+use hide_rs::encoder::Encoder;
+use hide_rs::img::StegoImage;
+use hide_rs::testing::gradient_image;
+use image::{DynamicImage, ImageFormat};
+use tempfile::tempdir;
+
+mod common;
+
+use common::{create_state, post_multipart, upload_multipart};
+
+#[actix_web::test]
+async fn test_responses_carry_library_warnings() {
+    let temp_dir = tempdir().unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(create_state(temp_dir.path()))
+            .configure(configure_routes),
+    )
+    .await;
+
+    let rgba = StegoImage::from_dynamic_image(DynamicImage::ImageRgba8(
+        gradient_image(40, 40).inner().to_rgba8(),
+    ));
+    let req = multipart_request(
+        "/api/v1/encode",
+        "cover_image",
+        &rgba,
+        &[("message", "see through")],
+    );
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(resp.status().is_success());
+    let json: serde_json::Value = serde_json::from_slice(&test::read_body(resp).await).unwrap();
+    assert_eq!(json["warnings"][0]["code"], "alpha_dropped");
+
+    // The cached response keeps the decode's warnings
+    let entries = [("notes.txt".to_string(), b"inside".to_vec())];
+    let container = Encoder::new()
+        .encode_container(gradient_image(40, 40), &entries)
+        .unwrap();
+    for cache in ["MISS", "HIT"] {
+        let req = multipart_request("/api/v1/decode", "stego_image", &container, &[]);
+        let resp = test::call_service(&app, req.to_request()).await;
+        assert!(resp.status().is_success());
+        assert_eq!(resp.headers().get("X-Cache").unwrap(), cache);
+        let json: serde_json::Value = serde_json::from_slice(&test::read_body(resp).await).unwrap();
+        assert_eq!(json["warnings"][0]["code"], "raw_container_bytes");
+    }
+
+    // Clean decodes leave the array out
+    let plain = Encoder::new()
+        .encode(gradient_image(40, 40), b"plain")
+        .unwrap();
+    let req = multipart_request("/api/v1/decode", "stego_image", &plain, &[]);
+    let resp = test::call_service(&app, req.to_request()).await;
+    let json: serde_json::Value = serde_json::from_slice(&test::read_body(resp).await).unwrap();
+    assert_eq!(json["message"], "plain");
+    assert!(json.get("warnings").is_none());
+}
+
+// Helper to build a multipart request with an image and text fields
+fn multipart_request(
+    uri: &str,
+    image_field: &str,
+    image: &StegoImage,
+    fields: &[(&str, &str)],
+) -> test::TestRequest {
+    let data = image.to_bytes(ImageFormat::Png).unwrap();
+    post_multipart(
+        uri,
+        upload_multipart(image_field, "image.png", &data, fields),
+    )
+}
@@ -0,0 +1,129 @@
+#![cfg(feature = "jpeg")]
+
+use hide_rs::error::HideError;
+use hide_rs::jpeg::{is_jpeg, JpegStego};
+use std::fs;
+use std::process::Command;
+use tempfile::tempdir;
+
+/// A 64x48 4:4:4 baseline JPEG written by the `image` crate at quality 85
+const COVER: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/jpeg/cover.jpg");
+
+fn cover() -> Vec<u8> {
+    fs::read(COVER).unwrap()
+}
+
+#[test]
+fn test_round_trip_on_fixture() {
+    let stego = JpegStego::new();
+    let capacity = stego.capacity(&cover()).unwrap();
+    assert_eq!((capacity.width, capacity.height), (64, 48));
+    assert!(capacity.max_message_bytes >= 32, "{:?}", capacity);
+
+    let message = vec![0xA5; capacity.max_message_bytes];
+    let encoded = stego.encode(&cover(), &message).unwrap();
+    assert!(is_jpeg(&encoded));
+    assert_eq!(stego.decode(&encoded).unwrap(), message);
+
+    let too_long = vec![0; capacity.max_message_bytes + 1];
+    assert!(matches!(
+        stego.encode(&cover(), &too_long),
+        Err(HideError::MessageTooLarge)
+    ));
+}
+
+#[test]
+fn test_output_still_decodes_as_an_image() {
+    let encoded = JpegStego::new().encode(&cover(), b"still a JPEG").unwrap();
+
+    let original = image::load_from_memory(&cover()).unwrap().to_rgb8();
+    let stego = image::load_from_memory(&encoded).unwrap().to_rgb8();
+    assert_eq!(stego.dimensions(), original.dimensions());
+
+    // Nudging a few quantized coefficients by one step moves pixels a little
+    let max_diff = original
+        .as_raw()
+        .iter()
+        .zip(stego.as_raw())
+        .map(|(a, b)| a.abs_diff(*b))
+        .max()
+        .unwrap();
+    assert!(max_diff < 64, "max channel difference {}", max_diff);
+}
+
+#[test]
+fn test_cli_round_trip() {
+    let temp_dir = tempdir().unwrap();
+    let output = temp_dir.path().join("nested").join("stego.jpg");
+
+    let encode = Command::new(env!("CARGO_BIN_EXE_hide"))
+        .args([
+            "encode",
+            "--algorithm",
+            "jpeg-dct",
+            "--create-dirs",
+            "-i",
+            COVER,
+        ])
+        .args(["-m", "hello from the coefficients", "-o"])
+        .arg(&output)
+        .output()
+        .unwrap();
+    assert!(
+        encode.status.success(),
+        "{}",
+        String::from_utf8_lossy(&encode.stderr)
+    );
+
+    let decode = Command::new(env!("CARGO_BIN_EXE_hide"))
+        .args(["decode", "--algorithm", "jpeg-dct", "-i"])
+        .arg(&output)
+        .output()
+        .unwrap();
+    assert!(decode.status.success());
+    assert!(String::from_utf8_lossy(&decode.stdout).contains("hello from the coefficients"));
+}
+
+#[test]
+fn test_cli_only_writes_jpeg_with_the_jpeg_algorithm() {
+    let temp_dir = tempdir().unwrap();
+
+    // BLTM refuses a JPEG output and points at the algorithm that can write one
+    let bltm = Command::new(env!("CARGO_BIN_EXE_hide"))
+        .args(["encode", "-i", COVER, "-m", "hi", "-o"])
+        .arg(temp_dir.path().join("stego.jpg"))
+        .output()
+        .unwrap();
+    assert!(!bltm.status.success());
+    assert!(String::from_utf8_lossy(&bltm.stderr).contains("--algorithm jpeg-dct"));
+
+    // The JPEG algorithm refuses anything else
+    let png_output = temp_dir.path().join("stego.png");
+    let jpeg = Command::new(env!("CARGO_BIN_EXE_hide"))
+        .args([
+            "encode",
+            "--algorithm",
+            "jpeg-dct",
+            "-i",
+            COVER,
+            "-m",
+            "hi",
+            "-o",
+        ])
+        .arg(&png_output)
+        .output()
+        .unwrap();
+    assert!(!jpeg.status.success());
+    assert!(String::from_utf8_lossy(&jpeg.stderr).contains(".jpg"));
+    assert!(!png_output.exists());
+
+    // Options that only mean something for pixels are rejected
+    let masked = Command::new(env!("CARGO_BIN_EXE_hide"))
+        .args(["encode", "--algorithm", "jpeg-dct", "--preset", "robust"])
+        .args(["-i", COVER, "-m", "hi", "-o"])
+        .arg(temp_dir.path().join("stego.jpg"))
+        .output()
+        .unwrap();
+    assert!(!masked.status.success());
+    assert!(String::from_utf8_lossy(&masked.stderr).contains("--preset"));
+}
@@ -0,0 +1,130 @@
+use hide_rs::decoder::Decoder;
+use hide_rs::encoder::Encoder;
+use hide_rs::error::HideError;
+use hide_rs::img::StegoImage;
+use hide_rs::testing::gradient_image;
+use std::fs;
+use std::process::Command;
+use tempfile::tempdir;
+
+// Lengths at the edges of the 3-bit pixel chunks and of the bytes of the
+// big-endian length field
+const LENGTHS: [usize; 13] = [0, 1, 2, 3, 7, 8, 9, 255, 256, 257, 65535, 65536, 65537];
+
+// Helper making a message whose bytes all differ from their neighbours and
+// whose last byte has every bit set, so a misread final chunk shows
+fn message(len: usize) -> Vec<u8> {
+    let mut message: Vec<u8> = (0..len).map(|i| (i * 31 + 7) as u8).collect();
+    if let Some(last) = message.last_mut() {
+        *last = 0xff;
+    }
+    message
+}
+
+// Helper returning the dimensions of the smallest one-row cover a message
+// fits, and of a roughly square cover with a partly used last row
+fn covers(len: usize) -> [(u32, u32); 2] {
+    let pixels = Encoder::pixels_needed(len) as u32;
+    let side = (pixels as f64).sqrt().ceil() as u32;
+    [(pixels, 1), (side, pixels.div_ceil(side))]
+}
+
+#[test]
+fn test_boundary_lengths_round_trip_through_the_library() {
+    for len in LENGTHS {
+        let message = message(len);
+        for (width, height) in covers(len) {
+            let cover = gradient_image(width, height);
+            assert!(
+                Encoder::new().max_message_size(&cover) >= len,
+                "{} bytes in {}x{}",
+                len,
+                width,
+                height
+            );
+
+            let stego = Encoder::new().encode(cover, &message).unwrap();
+            let header = Decoder::new().read_header(&stego).unwrap();
+            assert_eq!(header.message_length as usize, len);
+            assert_eq!(
+                Decoder::new().decode(&stego).unwrap(),
+                message,
+                "{} bytes in {}x{}",
+                len,
+                width,
+                height
+            );
+            let detailed = Decoder::new().decode_detailed(&stego, true).unwrap();
+            assert!(detailed.decoded.complete);
+            assert_eq!(detailed.decoded.data, message);
+        }
+    }
+}
+
+#[test]
+fn test_one_pixel_less_than_needed_is_too_small() {
+    for len in LENGTHS {
+        let [(pixels, _), _] = covers(len);
+        let cover = gradient_image(pixels - 1, 1);
+        assert!(
+            matches!(
+                Encoder::new().encode(cover, &message(len)),
+                Err(HideError::MessageTooLarge)
+            ),
+            "{} bytes in {} pixels",
+            len,
+            pixels - 1
+        );
+    }
+}
+
+#[test]
+fn test_boundary_lengths_round_trip_through_the_cli() {
+    let temp_dir = tempdir().unwrap();
+    let message_path = temp_dir.path().join("message.bin");
+    let decoded_path = temp_dir.path().join("decoded.bin");
+
+    for len in [0, 1, 8, 255, 256, 65535, 65536] {
+        let message = message(len);
+        fs::write(&message_path, &message).unwrap();
+        let [_, (width, height)] = covers(len);
+        let cover = temp_dir.path().join(format!("cover-{}.png", len));
+        gradient_image(width, height).save(&cover).unwrap();
+        let output = temp_dir.path().join(format!("stego-{}.png", len));
+
+        let encode = Command::new(env!("CARGO_BIN_EXE_hide"))
+            .args(["encode", "-i"])
+            .arg(&cover)
+            .arg("-f")
+            .arg(&message_path)
+            .arg("-o")
+            .arg(&output)
+            .output()
+            .unwrap();
+        assert!(
+            encode.status.success(),
+            "{} bytes: {}",
+            len,
+            String::from_utf8_lossy(&encode.stderr)
+        );
+        let header = Decoder::new()
+            .read_header(&StegoImage::from_file(&output).unwrap())
+            .unwrap();
+        assert_eq!(header.message_length as usize, len);
+
+        let decode = Command::new(env!("CARGO_BIN_EXE_hide"))
+            .args(["decode", "-i"])
+            .arg(&output)
+            .arg("-o")
+            .arg(&decoded_path)
+            .output()
+            .unwrap();
+        assert!(
+            decode.status.success(),
+            "{} bytes: {}",
+            len,
+            String::from_utf8_lossy(&decode.stderr)
+        );
+        assert_eq!(fs::read(&decoded_path).unwrap(), message, "{} bytes", len);
+    }
+}
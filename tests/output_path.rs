@@ -0,0 +1,135 @@
+use hide_rs::decoder::Decoder;
+use hide_rs::encoder::{Encoder, EncoderConfig};
+use hide_rs::error::HideError;
+use hide_rs::testing::noise_image;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use tempfile::tempdir;
+
+/// An encoder that panics if it starts embedding
+fn encoder_that_must_not_run(create_dirs: bool) -> Encoder {
+    Encoder::with_config(EncoderConfig {
+        create_dirs,
+        ..EncoderConfig::default().on_progress(|_, _| panic!("Encoding started"))
+    })
+}
+
+/// Save a large cover, which would take a while to encode into
+fn large_cover(dir: &Path) -> std::path::PathBuf {
+    let cover_path = dir.join("cover.png");
+    noise_image(1024, 1024, 1).save(&cover_path).unwrap();
+    cover_path
+}
+
+#[test]
+fn test_missing_parent_fails_before_encoding() {
+    let temp_dir = tempdir().unwrap();
+    let cover_path = large_cover(temp_dir.path());
+    let output_path = temp_dir.path().join("missing").join("out.png");
+
+    let err = encoder_that_must_not_run(false)
+        .encode_file(&cover_path, &[1; 100_000], &output_path)
+        .unwrap_err();
+    match err {
+        HideError::OutputPathInvalid { path, reason } => {
+            assert_eq!(path, output_path);
+            assert!(reason.contains("does not exist"), "{}", reason);
+        }
+        e => panic!("Unexpected error: {}", e),
+    }
+    assert!(!output_path.parent().unwrap().exists());
+}
+
+#[test]
+fn test_parent_that_is_a_file_is_rejected() {
+    let temp_dir = tempdir().unwrap();
+    let cover_path = large_cover(temp_dir.path());
+    let not_a_dir = temp_dir.path().join("file");
+    fs::write(&not_a_dir, b"").unwrap();
+
+    let err = encoder_that_must_not_run(true)
+        .encode_file(&cover_path, b"message", &not_a_dir.join("out.png"))
+        .unwrap_err();
+    assert!(
+        matches!(err, HideError::OutputPathInvalid { .. }),
+        "{}",
+        err
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_unwritable_parent_fails_before_encoding() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp_dir = tempdir().unwrap();
+    let cover_path = large_cover(temp_dir.path());
+    let read_only = temp_dir.path().join("read-only");
+    fs::create_dir(&read_only).unwrap();
+    fs::set_permissions(&read_only, fs::Permissions::from_mode(0o555)).unwrap();
+
+    // Permissions aren't enforced for privileged users, so there's nothing to test
+    if fs::write(read_only.join("probe"), b"probe").is_err() {
+        let err = encoder_that_must_not_run(false)
+            .encode_file(&cover_path, &[1; 100_000], &read_only.join("out.png"))
+            .unwrap_err();
+        assert!(
+            matches!(&err, HideError::OutputPathInvalid { reason, .. } if reason.contains("not writable")),
+            "{}",
+            err
+        );
+    }
+    fs::set_permissions(&read_only, fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+#[test]
+fn test_create_dirs_creates_missing_parents() {
+    let temp_dir = tempdir().unwrap();
+    let cover_path = temp_dir.path().join("cover.png");
+    noise_image(64, 64, 2).save(&cover_path).unwrap();
+    let output_path = temp_dir.path().join("a").join("b").join("out.png");
+
+    Encoder::with_config(EncoderConfig {
+        create_dirs: true,
+        ..Default::default()
+    })
+    .encode_file(&cover_path, b"nested", &output_path)
+    .unwrap();
+
+    let stego = hide_rs::img::load_image(&output_path).unwrap();
+    assert_eq!(Decoder::new().decode(&stego).unwrap(), b"nested");
+}
+
+#[test]
+fn test_cli_reports_missing_parent_and_creates_it_on_request() {
+    let temp_dir = tempdir().unwrap();
+    let cover_path = temp_dir.path().join("cover.png");
+    noise_image(64, 64, 3).save(&cover_path).unwrap();
+    let output_path = temp_dir.path().join("missing").join("out.png");
+
+    let run = |extra: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_hide"))
+            .args(["encode", "--message", "cli", "--image"])
+            .arg(&cover_path)
+            .arg("--output")
+            .arg(&output_path)
+            .args(extra)
+            .output()
+            .expect("Failed to run hide")
+    };
+
+    let output = run(&[]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("does not exist"), "{}", stderr);
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+
+    let output = run(&["--create-dirs"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(output_path.exists());
+}
@@ -0,0 +1,153 @@
+use hide_rs::decoder::Decoder;
+use hide_rs::encoder::{capacity_breakdown, Encoder, EncoderConfig};
+use hide_rs::error::HideError;
+use hide_rs::header::{HeaderVersion, DIMENSIONS_SIZE, EXTENSION_SIZE, PADDING_TRAILER_SIZE};
+use hide_rs::img::StegoImage;
+use hide_rs::padding::PaddingPolicy;
+use hide_rs::rng::SharedRng;
+use hide_rs::testing::{deny_default_rng, gradient_image, CountingRng};
+
+// Helper to make a padding encoder drawing from a counting generator
+fn padding_encoder(policy: PaddingPolicy, rng: &CountingRng) -> Encoder {
+    Encoder::with_config(
+        EncoderConfig {
+            rng: Some(SharedRng::new(rng.clone())),
+            ..Default::default()
+        }
+        .pad_to(policy),
+    )
+}
+
+// Helper to encode a message into a 40x40 cover
+fn encode_padded(policy: PaddingPolicy, message: &[u8]) -> StegoImage {
+    padding_encoder(policy, &CountingRng::new())
+        .encode(gradient_image(40, 40), message)
+        .unwrap()
+}
+
+#[test]
+fn test_header_only_reveals_the_padded_size() {
+    let _guard = deny_default_rng();
+    // Padding is a flag, so the header carries the extension
+    let capacity = Encoder::max_message_size_for_dimensions(40, 40) - EXTENSION_SIZE;
+
+    for (policy, padded) in [
+        (PaddingPolicy::Exact(300), [300, 300]),
+        (PaddingPolicy::NextPowerOfTwo, [32, 128]),
+        (PaddingPolicy::FillCapacity, [capacity, capacity]),
+    ] {
+        for (message, padded) in [(&b"short"[..], padded[0]), (&[7u8; 100][..], padded[1])] {
+            let stego = encode_padded(policy, message);
+
+            let header = Decoder::new().read_header(&stego).unwrap();
+            assert_eq!(header.format_version, HeaderVersion::V2.number());
+            assert_eq!(header.message_length as usize, padded, "{:?}", policy);
+            assert!(header.is_padded() && header.records_dimensions());
+
+            assert_eq!(Decoder::new().decode(&stego).unwrap(), message);
+        }
+    }
+}
+
+#[test]
+fn test_messages_of_different_lengths_share_a_header() {
+    for policy in [PaddingPolicy::Exact(200), PaddingPolicy::FillCapacity] {
+        let headers: Vec<_> = [&b"a"[..], &[1u8; 150][..]]
+            .into_iter()
+            .map(|message| {
+                Decoder::new()
+                    .read_header(&encode_padded(policy, message))
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(headers[0], headers[1]);
+    }
+}
+
+#[test]
+fn test_padding_is_drawn_from_the_injected_rng() {
+    let _guard = deny_default_rng();
+
+    let rng = CountingRng::new();
+    let stats = padding_encoder(PaddingPolicy::Exact(64), &rng)
+        .encode_with_stats(gradient_image(40, 40), b"hello")
+        .unwrap()
+        .1;
+
+    let padding = 64 - DIMENSIONS_SIZE - 5 - PADDING_TRAILER_SIZE;
+    assert_eq!(rng.bytes_drawn(), padding as u64);
+    assert_eq!(stats.message_bytes, 5);
+    assert_eq!(stats.padding_bytes, padding + PADDING_TRAILER_SIZE);
+}
+
+#[test]
+fn test_capacity_accounts_for_padding() {
+    let cover = gradient_image(40, 40);
+    let rng = CountingRng::new();
+
+    for policy in [
+        PaddingPolicy::Exact(300),
+        PaddingPolicy::NextPowerOfTwo,
+        PaddingPolicy::FillCapacity,
+    ] {
+        let encoder = padding_encoder(policy, &rng);
+        let max = encoder.max_message_size(&cover);
+        let config = EncoderConfig::default().pad_to(policy);
+        assert_eq!(capacity_breakdown(&cover, &config).max_message_bytes, max);
+
+        assert!(encoder.encode(cover.clone(), &vec![1u8; max]).is_ok());
+        assert!(encoder.encode(cover.clone(), &vec![1u8; max + 1]).is_err());
+    }
+
+    let encoder = padding_encoder(PaddingPolicy::Exact(300), &rng);
+    assert_eq!(
+        encoder.max_message_size(&cover),
+        300 - DIMENSIONS_SIZE - PADDING_TRAILER_SIZE
+    );
+    assert!(matches!(
+        padding_encoder(PaddingPolicy::Exact(4096), &rng).encode(cover.clone(), b"x"),
+        Err(HideError::MessageTooLarge)
+    ));
+}
+
+#[test]
+fn test_padded_containers_and_partial_decodes() {
+    let entries = vec![
+        ("a".to_string(), b"first".to_vec()),
+        ("b".to_string(), b"second".to_vec()),
+    ];
+    let stego = padding_encoder(PaddingPolicy::FillCapacity, &CountingRng::new())
+        .encode_container(gradient_image(40, 40), &entries)
+        .unwrap();
+
+    let decoder = Decoder::new();
+    assert_eq!(decoder.decode_container(&stego).unwrap().len(), 2);
+    assert_eq!(
+        decoder.decode_entry(&stego, "b").unwrap(),
+        Some(b"second".to_vec())
+    );
+
+    // Complete lossy decodes drop the padding too
+    let stego = encode_padded(PaddingPolicy::NextPowerOfTwo, b"lossy");
+    let partial = decoder.decode_lossy(&stego).unwrap();
+    assert!(partial.complete);
+    assert_eq!(partial.data, b"lossy");
+    assert_eq!(partial.declared_length, 5);
+}
+
+#[test]
+fn test_padded_payloads_refuse_append_and_views() {
+    let mut stego = encode_padded(PaddingPolicy::Exact(64), b"padded");
+    assert!(matches!(
+        Encoder::new().append(&mut stego, b" more"),
+        Err(HideError::InvalidParameters(_))
+    ));
+    assert_eq!(Decoder::new().decode(&stego).unwrap(), b"padded");
+
+    let mut cover = gradient_image(40, 40);
+    let encoder = padding_encoder(PaddingPolicy::FillCapacity, &CountingRng::new());
+    assert!(matches!(
+        encoder.encode_in_place(&mut cover, b"view"),
+        Err(HideError::InvalidParameters(_))
+    ));
+}
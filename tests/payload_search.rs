@@ -0,0 +1,138 @@
+use hide_rs::decoder::{Decoder, SearchWindow};
+use hide_rs::encoder::Encoder;
+use hide_rs::error::HideError;
+use hide_rs::img::StegoImage;
+use hide_rs::testing::gradient_image;
+use image::{DynamicImage, Rgb, RgbImage};
+use std::process::Command;
+use tempfile::tempdir;
+
+/// Place an image on a taller canvas, below `rows` rows of padding
+fn pad_top(stego: &StegoImage, rows: u32) -> StegoImage {
+    let pixels = stego.inner().to_rgb8();
+    let (width, height) = pixels.dimensions();
+    let mut canvas = RgbImage::from_pixel(width, height + rows, Rgb([200, 200, 200]));
+    image::imageops::overlay(&mut canvas, &pixels, 0, rows as i64);
+    StegoImage::from_dynamic_image(DynamicImage::ImageRgb8(canvas))
+}
+
+/// Move every pixel `offset` places along in row-major order, adding a row at the bottom
+fn shift_pixels(stego: &StegoImage, offset: u32) -> StegoImage {
+    let pixels = stego.inner().to_rgb8();
+    let (width, height) = pixels.dimensions();
+    let shifted = RgbImage::from_fn(width, height + 1, |x, y| {
+        (y * width + x)
+            .checked_sub(offset)
+            .filter(|index| *index < width * height)
+            .map_or(Rgb([10, 20, 30]), |index| {
+                *pixels.get_pixel(index % width, index / width)
+            })
+    });
+    StegoImage::from_dynamic_image(DynamicImage::ImageRgb8(shifted))
+}
+
+#[test]
+fn test_search_finds_payload_below_padding() {
+    let message = b"pushed down by a letterbox".repeat(4);
+    let stego = Encoder::new()
+        .encode(gradient_image(40, 40), &message)
+        .unwrap();
+    let padded = pad_top(&stego, 3);
+
+    // A plain decode reads the padding and finds nothing
+    assert!(Decoder::new().decode(&padded).is_err());
+
+    let found = Decoder::new()
+        .search_and_decode(&padded, SearchWindow::default())
+        .unwrap();
+    assert_eq!(found.data, message);
+    assert_eq!(found.pixel_offset, 3 * 40);
+    assert_eq!(found.position, (0, 3));
+    assert_eq!(found.header.message_length as usize, message.len());
+}
+
+#[test]
+fn test_search_finds_payload_inside_a_row() {
+    let message = b"seven pixels along";
+    let stego = Encoder::new()
+        .encode(gradient_image(30, 30), message)
+        .unwrap();
+
+    let found = Decoder::new()
+        .search_and_decode(&shift_pixels(&stego, 7), SearchWindow::default())
+        .unwrap();
+    assert_eq!(found.data, message);
+    assert_eq!(found.pixel_offset, 7);
+    assert_eq!(found.position, (7, 0));
+
+    // An unshifted image is found at the first pixel
+    let found = Decoder::new()
+        .search_and_decode(&stego, SearchWindow::default())
+        .unwrap();
+    assert_eq!(
+        (found.pixel_offset, found.data.as_slice()),
+        (0, &message[..])
+    );
+}
+
+#[test]
+fn test_search_stays_within_its_window() {
+    let stego = Encoder::new()
+        .encode(gradient_image(40, 40), b"too far down")
+        .unwrap();
+    let padded = pad_top(&stego, 5);
+
+    let window = SearchWindow {
+        max_rows: 4,
+        max_pixel_offset: 100,
+    };
+    assert!(matches!(
+        Decoder::new().search_and_decode(&padded, window),
+        Err(HideError::NoMessageFound)
+    ));
+    assert!(matches!(
+        Decoder::new().search_and_decode(&gradient_image(40, 40), SearchWindow::default()),
+        Err(HideError::NoMessageFound)
+    ));
+}
+
+#[test]
+fn test_cli_decode_search() {
+    let temp_dir = tempdir().unwrap();
+    let padded_path = temp_dir.path().join("padded.png");
+    let stego = Encoder::new()
+        .encode(gradient_image(30, 30), b"found by the command line")
+        .unwrap();
+    pad_top(&stego, 3).save(&padded_path).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_hide"))
+        .args(["decode", "--search", "--image"])
+        .arg(&padded_path)
+        .output()
+        .expect("Failed to run hide");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("found by the command line"));
+    assert!(stdout.contains("Payload found at pixel (0, 3), offset 90"));
+}
+
+#[test]
+fn test_search_reports_column_and_row_on_non_square_images() {
+    let message = b"not a square";
+    for (width, height) in [(60, 20), (12, 50)] {
+        let stego = Encoder::new()
+            .encode(gradient_image(width, height), message)
+            .unwrap();
+
+        let found = Decoder::new()
+            .search_and_decode(&pad_top(&stego, 2), SearchWindow::default())
+            .unwrap();
+        assert_eq!(found.data, message);
+        assert_eq!(found.pixel_offset, 2 * width as u64);
+        assert_eq!(found.position, (0, 2), "{}x{}", width, height);
+    }
+}
@@ -0,0 +1,192 @@
+use bitvec::prelude::*;
+use hide_rs::decoder::{Decoder, DecoderConfig};
+use hide_rs::encoder::{Encoder, EncoderConfig, Secret};
+use hide_rs::img::StegoImage;
+use hide_rs::mask::PixelMask;
+use hide_rs::raw_decoder;
+use hide_rs::rgba;
+use hide_rs::rng::{Kdf, KdfParams, Key, SharedKdf};
+use hide_rs::schedule::ChannelSchedule;
+use hide_rs::testing::{gradient_image, noise_image};
+use hide_rs::trace::PayloadTrace;
+use image::{DynamicImage, Rgba, RgbaImage};
+use sha2::{Digest, Sha256};
+use std::process::Command;
+use tempfile::tempdir;
+
+/// Fast stand-in for a real key derivation function
+struct HashKdf;
+
+impl Kdf for HashKdf {
+    fn derive(
+        &self,
+        passphrase: &Secret,
+        salt: &[u8],
+        _params: &KdfParams,
+    ) -> hide_rs::Result<Key> {
+        let mut hasher = Sha256::new();
+        hasher.update(passphrase.expose());
+        hasher.update(salt);
+        Ok(Key::new(hasher.finalize().into()))
+    }
+}
+
+/// Read the payload back from the pixels and channel orders of a trace
+fn payload_from_trace(stego: &StegoImage, trace: &PayloadTrace) -> Vec<u8> {
+    let decoder = Decoder::new();
+    let mut bits = BitVec::<u8, Msb0>::new();
+    for pixel in trace.pixels() {
+        let rgb = stego.get_pixel_rgb(pixel.x, pixel.y).unwrap().0;
+        let [r, g, b] = pixel.channel_order.map(|channel| rgb[channel]);
+        let carried = (pixel.bits.end - pixel.bits.start) as usize;
+        bits.extend_from_bitslice(&decoder.decode_pixel(r, g, b)[..carried]);
+    }
+    bits.into_vec()
+}
+
+#[test]
+fn test_trace_covers_the_payload_pixels() {
+    let message = b"where did these bits go?".repeat(5);
+    let stego = Encoder::new()
+        .encode(gradient_image(40, 40), &message)
+        .unwrap();
+
+    let (decoded, trace) = Decoder::new().decode_with_trace(&stego).unwrap();
+    assert_eq!(decoded, message);
+
+    // One run from the first pixel over exactly the pixels the payload needs
+    let expected_pixels = ((8 + message.len()) * 8).div_ceil(3) as u64;
+    assert_eq!(trace.pixel_count(), expected_pixels);
+    assert_eq!(trace.runs().len(), 1);
+    assert_eq!(trace.runs()[0].first_pixel, 0);
+    assert_eq!(trace.total_bytes(), 8 + message.len() as u64);
+
+    let raw = raw_decoder::extract_raw_data(&stego).unwrap();
+    assert_eq!(payload_from_trace(&stego, &trace), raw[..8 + message.len()]);
+
+    // The first message byte (bits 64..72) lives in pixels 21 to 23 of row 0
+    assert_eq!(trace.pixels_for_bytes(8..9), [(21, 0), (22, 0), (23, 0)]);
+}
+
+#[test]
+fn test_trace_follows_keyed_channel_orders() {
+    let config = EncoderConfig {
+        channel_schedule: ChannelSchedule::Keyed,
+        passphrase: Some(Secret::new("trace me")),
+        kdf: Some(SharedKdf::new(HashKdf)),
+        ..Default::default()
+    };
+    let message = b"keyed orders".repeat(10);
+    let stego = Encoder::with_config(config)
+        .encode(noise_image(40, 40, 1970), &message)
+        .unwrap();
+
+    let decoder = Decoder::with_config(DecoderConfig {
+        passphrase: Some(Secret::new("trace me")),
+        kdf: Some(SharedKdf::new(HashKdf)),
+        ..Default::default()
+    });
+    let (_, trace) = decoder.decode_with_trace(&stego).unwrap();
+    assert_eq!(
+        trace.pixel_count(),
+        ((12 + message.len()) * 8).div_ceil(3) as u64
+    );
+
+    // Header pixels use the fixed order; the rest follow the key
+    let orders: Vec<_> = trace.pixels().map(|pixel| pixel.channel_order).collect();
+    assert!(orders[..32].iter().all(|order| *order == [0, 1, 2]));
+    assert!(orders[32..].iter().any(|order| *order != [0, 1, 2]));
+
+    // Reading the traced pixels in the traced orders gives back the payload
+    let payload = payload_from_trace(&stego, &trace);
+    assert_eq!(&payload[12..], message.as_slice());
+}
+
+#[test]
+fn test_trace_skips_masked_pixels() {
+    let mask = PixelMask::from_fn(40, 40, |x, y| y >= 10 && (10..20).contains(&x));
+    let config = EncoderConfig {
+        pixel_mask: Some(mask.clone()),
+        embed_mask: true,
+        ..Default::default()
+    };
+    let message = b"around the holes".repeat(20);
+    let stego = Encoder::with_config(config)
+        .encode(gradient_image(40, 40), &message)
+        .unwrap();
+
+    let (decoded, trace) = Decoder::new().decode_with_trace(&stego).unwrap();
+    assert_eq!(decoded, message);
+
+    let embedded = mask.to_rle().len();
+    assert_eq!(trace.header_bytes(), (12 + embedded) as u64);
+    assert_eq!(
+        trace.pixel_count(),
+        ((12 + embedded + message.len()) * 8).div_ceil(3) as u64
+    );
+    assert!(trace.runs().len() > 1);
+    for pixel in trace.pixels() {
+        assert!(!mask.is_excluded(pixel.y as u64 * 40 + pixel.x as u64));
+    }
+    assert_eq!(
+        &payload_from_trace(&stego, &trace)[12 + embedded..],
+        message.as_slice()
+    );
+}
+
+#[test]
+fn test_trace_of_rgba_payloads() {
+    let cover = StegoImage::from_dynamic_image(DynamicImage::ImageRgba8(RgbaImage::from_fn(
+        30,
+        30,
+        |x, y| Rgba([x as u8 * 8, y as u8 * 8, 90, if x == 25 { 0 } else { 255 }]),
+    )));
+    let config = EncoderConfig {
+        embed_alpha: true,
+        skip_transparent: true,
+        ..Default::default()
+    };
+    let message = b"four bits a pixel".repeat(6);
+    let stego = Encoder::with_config(config)
+        .encode(cover, &message)
+        .unwrap();
+
+    let (_, trace) = Decoder::new().decode_with_trace(&stego).unwrap();
+    assert_eq!(
+        trace.pixel_count(),
+        rgba::pixels_needed(message.len()) as u64
+    );
+    assert_eq!(trace.runs()[0].pixels(), 0..32);
+    assert_eq!(trace.runs()[1].bits_per_pixel, 4);
+    // Only the header is embedded in transparent pixels
+    assert!(trace.pixels().skip(32).all(|pixel| pixel.x != 25));
+}
+
+#[test]
+fn test_cli_trace_overlay() {
+    let temp_dir = tempdir().unwrap();
+    let stego_path = temp_dir.path().join("stego.png");
+    let trace_path = temp_dir.path().join("trace.png");
+    Encoder::new()
+        .encode(gradient_image(30, 30), b"traced from the command line")
+        .unwrap()
+        .save(&stego_path)
+        .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_hide"))
+        .args(["decode", "--image"])
+        .arg(&stego_path)
+        .arg("--trace")
+        .arg(&trace_path)
+        .output()
+        .expect("Failed to run hide");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(String::from_utf8_lossy(&output.stdout).contains("traced from the command line"));
+
+    let overlay = StegoImage::from_file(&trace_path).unwrap();
+    assert_eq!(overlay.dimensions(), (30, 30));
+}
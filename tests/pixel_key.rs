@@ -0,0 +1,128 @@
+use hide_rs::decoder::{Decoder, DecoderConfig};
+use hide_rs::encoder::{Encoder, EncoderConfig};
+use hide_rs::error::HideError;
+use hide_rs::img::StegoImage;
+use hide_rs::mask::PixelMask;
+use hide_rs::scatter;
+use hide_rs::testing::gradient_image;
+
+const KEY: &[u8] = b"correct horse battery staple";
+const MESSAGE: &[u8] = b"nowhere near the top-left corner";
+
+#[test]
+fn test_round_trip_with_the_right_key() {
+    let cover = gradient_image(64, 64);
+    let stego = Encoder::with_key(KEY)
+        .encode(cover.clone(), MESSAGE)
+        .unwrap();
+    assert_eq!(Decoder::with_key(KEY).decode(&stego).unwrap(), MESSAGE);
+
+    // The changed pixels are spread over the image, not packed into the first rows
+    let last_changed_row = (0..64)
+        .flat_map(|y| (0..64).map(move |x| (x, y)))
+        .filter(|&(x, y)| cover.get_pixel_rgb(x, y).unwrap() != stego.get_pixel_rgb(x, y).unwrap())
+        .map(|(_, y)| y)
+        .max()
+        .unwrap();
+    assert!(last_changed_row > 32, "{}", last_changed_row);
+}
+
+#[test]
+fn test_wrong_or_missing_key_finds_no_message() {
+    let stego = Encoder::with_key(KEY)
+        .encode(gradient_image(64, 64), MESSAGE)
+        .unwrap();
+
+    for key in [&b"wrong horse battery staple"[..], b"", b"correct horse"] {
+        assert!(
+            matches!(
+                Decoder::with_key(key).decode(&stego),
+                Err(HideError::NoMessageFound)
+            ),
+            "{:?}",
+            key
+        );
+    }
+    assert!(Decoder::new().detect(&stego).is_none());
+    assert_ne!(Decoder::new().decode(&stego).ok().as_deref(), Some(MESSAGE));
+
+    // A key doesn't find a payload embedded without one either
+    let plain = Encoder::new()
+        .encode(gradient_image(64, 64), MESSAGE)
+        .unwrap();
+    assert!(matches!(
+        Decoder::with_key(KEY).decode(&plain),
+        Err(HideError::NoMessageFound)
+    ));
+}
+
+#[test]
+fn test_keyed_options_round_trip() {
+    let config = EncoderConfig {
+        embed_alpha: true,
+        ..EncoderConfig::default()
+            .pixel_key(KEY)
+            .verify_roundtrip(true)
+    };
+    let mut cover = gradient_image(48, 48);
+    cover.to_rgba();
+    let stego = Encoder::with_config(config).encode(cover, MESSAGE).unwrap();
+
+    let decoder = Decoder::with_key(KEY);
+    assert_eq!(decoder.decode(&stego).unwrap(), MESSAGE);
+    assert_eq!(decoder.decode_lossy(&stego).unwrap().data, MESSAGE);
+    assert_eq!(decoder.read_header(&stego).unwrap().message_length, 32);
+
+    let encoder = Encoder::with_key(KEY);
+    let container = encoder
+        .encode_container(
+            gradient_image(48, 48),
+            &[("note".to_string(), b"keyed".to_vec())],
+        )
+        .unwrap();
+    assert_eq!(
+        decoder.decode_entry(&container, "note").unwrap().unwrap(),
+        b"keyed"
+    );
+}
+
+#[test]
+fn test_unsupported_combinations_are_rejected() {
+    let masked = Encoder::with_config(EncoderConfig {
+        pixel_mask: Some(PixelMask::from_image(
+            &StegoImage::new_rgb(32, 32).unwrap(),
+            128,
+        )),
+        ..EncoderConfig::default().pixel_key(KEY)
+    });
+    assert!(matches!(
+        masked.encode(gradient_image(32, 32), MESSAGE),
+        Err(HideError::InvalidParameters(_))
+    ));
+
+    let mut image = gradient_image(32, 32);
+    assert!(matches!(
+        Encoder::with_key(KEY).encode_in_place(&mut image, MESSAGE),
+        Err(HideError::InvalidParameters(_))
+    ));
+
+    let stego = Encoder::with_key(KEY)
+        .encode(gradient_image(32, 32), MESSAGE)
+        .unwrap();
+    assert!(matches!(
+        Decoder::with_key(KEY).decode_with_trace(&stego),
+        Err(HideError::InvalidParameters(_))
+    ));
+}
+
+#[test]
+fn test_config_key_matches_with_key() {
+    let stego = Encoder::with_config(EncoderConfig::default().pixel_key(KEY))
+        .encode(gradient_image(32, 32), MESSAGE)
+        .unwrap();
+    let decoder = Decoder::with_config(DecoderConfig {
+        pixel_key: Some(scatter::derive_key(KEY)),
+        ..Default::default()
+    });
+    assert_eq!(decoder.decode(&stego).unwrap(), MESSAGE);
+}
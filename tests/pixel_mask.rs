@@ -0,0 +1,243 @@
+use hide_rs::decoder::{Decoder, DecoderConfig, UntrustedLimits};
+use hide_rs::encoder::{self, Encoder, EncoderConfig};
+use hide_rs::error::HideError;
+use hide_rs::img::StegoImage;
+use hide_rs::mask::PixelMask;
+use hide_rs::testing::{assert_lsb_only_changes, gradient_image, noise_image};
+use image::{DynamicImage, GrayImage, Luma};
+use std::process::Command;
+use tempfile::tempdir;
+
+/// Mask image whitening a block in the middle of a 40x40 cover
+fn block_mask_image() -> StegoImage {
+    let gray = GrayImage::from_fn(40, 40, |x, y| {
+        let inside = (10..30).contains(&x) && (10..30).contains(&y);
+        Luma([if inside { 255 } else { 0 }])
+    });
+    StegoImage::from_dynamic_image(DynamicImage::ImageLuma8(gray))
+}
+
+/// Assert that no pixel the mask excludes changed
+fn assert_masked_pixels_untouched(cover: &StegoImage, stego: &StegoImage, mask: &PixelMask) {
+    let (width, height) = cover.dimensions();
+    for y in 0..height {
+        for x in 0..width {
+            if mask.is_excluded(y as u64 * width as u64 + x as u64) {
+                assert_eq!(
+                    cover.get_pixel_rgb(x, y).unwrap(),
+                    stego.get_pixel_rgb(x, y).unwrap(),
+                    "masked pixel ({}, {}) changed",
+                    x,
+                    y
+                );
+            }
+        }
+    }
+}
+
+fn masked_decoder(mask: PixelMask) -> Decoder {
+    Decoder::with_config(DecoderConfig {
+        mask: Some(mask),
+        ..Default::default()
+    })
+}
+
+#[test]
+fn test_masked_pixels_are_untouched() {
+    let cover = noise_image(40, 40, 1968);
+    let mask_image = block_mask_image();
+    let mask = PixelMask::from_image(&mask_image, 127);
+    assert_eq!(mask.excluded_pixels(), 400);
+
+    // Long enough to need pixels after the masked block
+    let message = vec![0x5a; 400];
+    let stego = Encoder::with_config(EncoderConfig::default().mask(&mask_image))
+        .encode(cover.clone(), &message)
+        .unwrap();
+
+    assert_lsb_only_changes(&cover, &stego);
+    assert_masked_pixels_untouched(&cover, &stego, &mask);
+
+    let header = Decoder::new().read_header(&stego).unwrap();
+    assert!(header.is_masked());
+    assert_eq!(header.embedded_mask_length, 0);
+}
+
+#[test]
+fn test_decode_with_external_mask() {
+    let mask_image = block_mask_image();
+    let mask = PixelMask::from_image(&mask_image, 127);
+    let message = b"designers keep the centre".repeat(15);
+    let stego = Encoder::with_config(EncoderConfig::default().mask(&mask_image))
+        .encode(gradient_image(40, 40), &message)
+        .unwrap();
+
+    let decoder = masked_decoder(mask);
+    assert_eq!(decoder.decode(&stego).unwrap(), message);
+    assert_eq!(decoder.decode_lossy(&stego).unwrap().data, message);
+    assert_eq!(
+        decoder
+            .decode_untrusted(&stego, UntrustedLimits::default())
+            .unwrap(),
+        message
+    );
+
+    // Without the mask there is no way to find the payload's pixels
+    assert!(matches!(
+        Decoder::new().decode(&stego),
+        Err(HideError::InvalidParameters(_))
+    ));
+
+    // A mask for another image size is refused
+    let wrong = masked_decoder(PixelMask::from_fn(20, 20, |_, _| false));
+    assert!(matches!(
+        wrong.decode(&stego),
+        Err(HideError::InvalidParameters(_))
+    ));
+}
+
+#[test]
+fn test_decode_with_embedded_mask() {
+    let cover = noise_image(40, 40, 7);
+    let mask_image = block_mask_image();
+    let mask = PixelMask::from_image(&mask_image, 127);
+    let message = b"no mask needed to read this".repeat(12);
+
+    let config = EncoderConfig {
+        embed_mask: true,
+        ..EncoderConfig::default().mask(&mask_image)
+    };
+    let stego = Encoder::with_config(config)
+        .encode(cover.clone(), &message)
+        .unwrap();
+    assert_masked_pixels_untouched(&cover, &stego, &mask);
+
+    let header = Decoder::new().read_header(&stego).unwrap();
+    assert_eq!(header.embedded_mask_length as usize, mask.to_rle().len());
+
+    let decoder = Decoder::new();
+    assert_eq!(decoder.decode(&stego).unwrap(), message);
+    assert_eq!(decoder.decode_lossy(&stego).unwrap().data, message);
+    assert_eq!(
+        decoder
+            .decode_untrusted(&stego, UntrustedLimits::default())
+            .unwrap(),
+        message
+    );
+    assert!(decoder.detect(&stego).is_some());
+}
+
+#[test]
+fn test_mask_reduces_capacity() {
+    let cover = gradient_image(40, 40);
+    let mask_image = block_mask_image();
+    let plain = Encoder::new().max_message_size(&cover);
+    let masked_config = EncoderConfig::default().mask(&mask_image);
+    let masked = Encoder::with_config(masked_config.clone()).max_message_size(&cover);
+    assert_eq!(masked, 1200 * 3 / 8 - 12);
+    assert!(masked < plain);
+
+    // The largest message fits and one more byte doesn't
+    let encoder = Encoder::with_config(masked_config.clone());
+    assert!(encoder.encode(cover.clone(), &vec![1; masked]).is_ok());
+    assert!(matches!(
+        encoder.encode(cover.clone(), &vec![1; masked + 1]),
+        Err(HideError::MessageTooLarge)
+    ));
+
+    // The breakdown accounts for the excluded pixels and an embedded mask
+    let breakdown = encoder::capacity_breakdown(
+        &cover,
+        &EncoderConfig {
+            embed_mask: true,
+            ..masked_config
+        },
+    );
+    assert_eq!(
+        breakdown.overhead_bytes() + breakdown.max_message_bytes,
+        breakdown.raw_bytes
+    );
+    let names: Vec<_> = breakdown
+        .overheads
+        .iter()
+        .map(|o| o.name.as_str())
+        .collect();
+    assert_eq!(names, ["header", "mask", "embedded_mask"]);
+    assert_eq!(
+        breakdown.max_message_bytes,
+        masked - breakdown.overheads[2].bytes
+    );
+}
+
+#[test]
+fn test_mask_must_leave_header_pixels_free() {
+    let mask = PixelMask::from_fn(40, 40, |x, y| x == 5 && y == 0);
+    let config = EncoderConfig {
+        pixel_mask: Some(mask.clone()),
+        embed_mask: true,
+        ..Default::default()
+    };
+    assert!(matches!(
+        Encoder::with_config(config).encode(gradient_image(40, 40), b"x"),
+        Err(HideError::InvalidParameters(_))
+    ));
+
+    // A mask sized for another image is refused too
+    let config = EncoderConfig::default().mask(&block_mask_image());
+    assert!(matches!(
+        Encoder::with_config(config).encode(gradient_image(30, 30), b"x"),
+        Err(HideError::InvalidParameters(_))
+    ));
+}
+
+#[test]
+fn test_cli_mask_options() {
+    let temp_dir = tempdir().unwrap();
+    let cover_path = temp_dir.path().join("cover.png");
+    let mask_path = temp_dir.path().join("mask.png");
+    gradient_image(40, 40).save(&cover_path).unwrap();
+    block_mask_image().save(&mask_path).unwrap();
+
+    let hide = |args: &[&std::ffi::OsStr]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_hide"))
+            .args(args)
+            .output()
+            .expect("Failed to run hide");
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+
+    for embed in [false, true] {
+        let output_path = temp_dir.path().join(format!("stego-{}.png", embed));
+        let mut args = vec![
+            "encode".as_ref(),
+            "--image".as_ref(),
+            cover_path.as_os_str(),
+            "--message".as_ref(),
+            "masked secret".as_ref(),
+            "--output".as_ref(),
+            output_path.as_os_str(),
+            "--mask".as_ref(),
+            mask_path.as_os_str(),
+        ];
+        if embed {
+            args.push("--embed-mask".as_ref());
+        }
+        hide(&args);
+
+        // Only the payload without an embedded mask needs it to decode
+        let mut args = vec![
+            "decode".as_ref(),
+            "--image".as_ref(),
+            output_path.as_os_str(),
+        ];
+        if !embed {
+            args.extend(["--mask".as_ref(), mask_path.as_os_str()]);
+        }
+        assert!(hide(&args).contains("masked secret"));
+    }
+}
@@ -0,0 +1,113 @@
+use hide_rs::decoder::Decoder;
+use hide_rs::encoder::Encoder;
+use hide_rs::img::{self, StegoImage};
+use hide_rs::planner::{self, PlanConstraints, Robustness};
+use hide_rs::testing::{checkerboard, gradient_image, noise_image};
+use std::process::Command;
+use tempfile::tempdir;
+
+fn covers() -> Vec<(&'static str, StegoImage)> {
+    vec![
+        ("gradient", gradient_image(64, 48)),
+        ("noise", noise_image(64, 48, 3)),
+        ("checkerboard", checkerboard(64, 48)),
+    ]
+}
+
+fn constraint_sets() -> Vec<PlanConstraints> {
+    vec![
+        PlanConstraints::default(),
+        PlanConstraints {
+            max_change: Some(0.5),
+            ..Default::default()
+        },
+        PlanConstraints {
+            robustness: Robustness::Checked,
+            ..Default::default()
+        },
+        PlanConstraints {
+            robustness: Robustness::Recoverable,
+            ..Default::default()
+        },
+        PlanConstraints {
+            hide_length: true,
+            ..Default::default()
+        },
+    ]
+}
+
+#[test]
+fn test_recommended_options_encode_within_their_constraints() {
+    let message = b"planned message ".repeat(8);
+
+    for (cover_name, cover) in covers() {
+        let total_pixels = img::pixel_count(cover.width(), cover.height());
+        for constraints in constraint_sets() {
+            let plan = planner::plan(&cover, message.len(), constraints.clone());
+            let option = plan.recommendation().unwrap_or_else(|| {
+                panic!("{}: nothing recommended for {:?}", cover_name, constraints)
+            });
+            let predicted = option.predicted.as_ref().unwrap();
+            assert!(option.robustness >= constraints.robustness);
+            if constraints.hide_length {
+                assert!(option.config.padding.is_some());
+            }
+
+            let (stego, stats) = Encoder::with_config(option.config.clone())
+                .encode_with_stats(cover.clone(), &message)
+                .unwrap_or_else(|e| panic!("{}/{}: {}", cover_name, option.name, e));
+            assert_eq!(Decoder::new().decode(&stego).unwrap(), message);
+            assert!(stats.pixels_used <= predicted.pixels_used);
+            if let Some(max_change) = constraints.max_change {
+                assert!(stats.pixels_used as f64 <= max_change * total_pixels as f64);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_options_predicted_to_fit_do_fit() {
+    for (cover_name, cover) in covers() {
+        let plan = planner::plan(&cover, 500, PlanConstraints::default());
+        for option in &plan.options {
+            let result =
+                Encoder::with_config(option.config.clone()).encode(cover.clone(), &[7; 500]);
+            assert_eq!(
+                result.is_ok(),
+                option.fits,
+                "{}/{}: {:?}",
+                cover_name,
+                option.name,
+                result.err()
+            );
+        }
+    }
+}
+
+#[test]
+fn test_cli_prints_the_recommendation() {
+    let temp_dir = tempdir().unwrap();
+    let cover_path = temp_dir.path().join("cover.png");
+    noise_image(64, 64, 1).save(&cover_path).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_hide"))
+        .args(["plan", "--size", "100", "--hide-length", "--image"])
+        .arg(&cover_path)
+        .output()
+        .expect("Failed to run hide");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Recommended: stealth"));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_hide"))
+        .args(["plan", "--size", "100000", "--json", "--image"])
+        .arg(&cover_path)
+        .output()
+        .expect("Failed to run hide");
+    assert_eq!(output.status.code(), Some(1));
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(json.get("recommended").is_none());
+}
@@ -0,0 +1,91 @@
+use hide_rs::decoder::Decoder;
+use hide_rs::encoder::{Encoder, EncoderConfig};
+use hide_rs::error::HideError;
+use hide_rs::header::HeaderVersion;
+use hide_rs::img::{self, StegoImage};
+use hide_rs::mask::PixelMask;
+use hide_rs::rng::SharedRng;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+const MESSAGE: &[u8] = b"hidden in a screenshot";
+
+// Helper to draw a screenshot-like cover: flat panels with hard edges
+fn screenshot(width: u32, height: u32) -> StegoImage {
+    let mut image = StegoImage::new_rgb(width, height).unwrap();
+    for y in 0..height {
+        for x in 0..width {
+            let value = if x < width / 3 { 240 } else { 32 };
+            image
+                .set_pixel_rgb(x, y, image::Rgb([value, value, 200]))
+                .unwrap();
+        }
+    }
+    image
+}
+
+fn dithering_encoder(seed: u64) -> Encoder {
+    Encoder::with_config(EncoderConfig {
+        rng: Some(SharedRng::new(StdRng::seed_from_u64(seed))),
+        ..EncoderConfig::default().pre_dither(0.5)
+    })
+}
+
+#[test]
+fn test_dithered_encode_round_trips_with_a_plain_header() {
+    let cover = screenshot(64, 64);
+    let stego = dithering_encoder(1).encode(cover.clone(), MESSAGE).unwrap();
+
+    assert_eq!(Decoder::new().decode(&stego).unwrap(), MESSAGE);
+    let header = Decoder::new().detect(&stego).unwrap();
+    assert_eq!(header.format_version, HeaderVersion::CURRENT.number());
+    assert!(header.flags.is_empty());
+
+    // The dither reaches past the payload and never moves a value by more than one
+    let plain = Encoder::new().encode(cover.clone(), MESSAGE).unwrap();
+    assert!(img::cover_score(&stego) > img::cover_score(&plain));
+    for (old, new) in cover
+        .inner()
+        .as_bytes()
+        .iter()
+        .zip(stego.inner().as_bytes())
+    {
+        assert!(old.abs_diff(*new) <= 1);
+    }
+}
+
+#[test]
+fn test_dither_follows_the_injected_generator() {
+    let first = dithering_encoder(7)
+        .encode(screenshot(48, 48), MESSAGE)
+        .unwrap();
+    let again = dithering_encoder(7)
+        .encode(screenshot(48, 48), MESSAGE)
+        .unwrap();
+    let other = dithering_encoder(8)
+        .encode(screenshot(48, 48), MESSAGE)
+        .unwrap();
+    assert_eq!(first.inner().as_bytes(), again.inner().as_bytes());
+    assert_ne!(first.inner().as_bytes(), other.inner().as_bytes());
+}
+
+#[test]
+fn test_existing_payloads_are_found_before_dithering() {
+    let stego = Encoder::new().encode(screenshot(48, 48), b"first").unwrap();
+    assert!(matches!(
+        dithering_encoder(1).encode(stego, MESSAGE),
+        Err(HideError::ExistingPayload { .. })
+    ));
+
+    let masked = Encoder::with_config(EncoderConfig {
+        pixel_mask: Some(PixelMask::from_image(
+            &StegoImage::new_rgb(48, 48).unwrap(),
+            128,
+        )),
+        ..EncoderConfig::default().pre_dither(0.5)
+    });
+    assert!(matches!(
+        masked.encode(screenshot(48, 48), MESSAGE),
+        Err(HideError::InvalidParameters(_))
+    ));
+}
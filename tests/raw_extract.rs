@@ -0,0 +1,229 @@
+use bitvec::prelude::*;
+use hide_rs::decoder::Decoder;
+use hide_rs::error::HideError;
+use hide_rs::img::{PixelSurface, StegoImage};
+use hide_rs::raw_decoder::{
+    extract_raw_data, extract_raw_to_writer, format_data_preview, format_data_preview_with,
+    PreviewOptions, RawExtractOptions,
+};
+use hide_rs::testing::{image_with_payload, noise_image};
+use hide_rs::utils;
+use image::Rgb;
+use std::cell::Cell;
+
+/// Whole-image extraction as it was done before streaming
+fn extract_all_bits(image: &StegoImage) -> Vec<u8> {
+    let decoder = Decoder::new();
+    let mut bits = BitVec::<u8, Msb0>::new();
+    for y in 0..image.height() {
+        for x in 0..image.width() {
+            let pixel = image.get_pixel_rgb(x, y).unwrap();
+            bits.extend_from_bitslice(&decoder.decode_pixel(pixel.0[0], pixel.0[1], pixel.0[2]));
+        }
+    }
+    utils::bits_to_bytes(&bits)
+}
+
+/// Read-only surface that records which pixels are read
+struct CountingSurface {
+    image: StegoImage,
+    reads: Cell<usize>,
+    last_row: Cell<Option<u32>>,
+}
+
+impl CountingSurface {
+    fn new(image: StegoImage) -> Self {
+        Self {
+            image,
+            reads: Cell::new(0),
+            last_row: Cell::new(None),
+        }
+    }
+}
+
+impl PixelSurface for CountingSurface {
+    fn width(&self) -> u32 {
+        self.image.width()
+    }
+
+    fn height(&self) -> u32 {
+        self.image.height()
+    }
+
+    fn get_pixel_rgb(&self, x: u32, y: u32) -> hide_rs::Result<Rgb<u8>> {
+        self.reads.set(self.reads.get() + 1);
+        self.last_row.set(Some(y));
+        self.image.get_pixel_rgb(x, y)
+    }
+
+    fn set_pixel_rgb(&mut self, _x: u32, _y: u32, _pixel: Rgb<u8>) -> hide_rs::Result<()> {
+        Err(HideError::InvalidParameters("read only".to_string()))
+    }
+}
+
+#[test]
+fn test_streamed_output_matches_whole_image_extraction() {
+    // Odd sizes leave a partial final byte that must be padded the same way
+    for (width, height) in [(1, 1), (7, 5), (40, 40), (33, 17)] {
+        let image = noise_image(width, height, 1958);
+        let expected = extract_all_bits(&image);
+
+        let mut streamed = Vec::new();
+        let written =
+            extract_raw_to_writer(&image, &mut streamed, RawExtractOptions::default()).unwrap();
+
+        assert_eq!(streamed, expected, "{}x{}", width, height);
+        assert_eq!(written, expected.len() as u64);
+        assert_eq!(extract_raw_data(&image).unwrap(), expected);
+    }
+
+    let stego = image_with_payload(30, 30, b"raw payload");
+    assert_eq!(extract_raw_data(&stego).unwrap()[8..19], *b"raw payload");
+}
+
+#[test]
+fn test_max_bytes_stops_after_needed_rows() {
+    // 40 pixels per row carry 15 bytes, so 32 bytes need exactly 3 rows
+    let surface = CountingSurface::new(noise_image(40, 40, 7));
+    let expected = extract_all_bits(&surface.image);
+
+    let mut head = Vec::new();
+    let written = extract_raw_to_writer(
+        &surface,
+        &mut head,
+        RawExtractOptions {
+            max_bytes: Some(32),
+        },
+    )
+    .unwrap();
+
+    assert_eq!(written, 32);
+    assert_eq!(head, expected[..32]);
+    assert_eq!(surface.reads.get(), 3 * 40);
+    assert_eq!(surface.last_row.get(), Some(2));
+}
+
+#[test]
+fn test_max_bytes_edge_cases() {
+    let surface = CountingSurface::new(noise_image(10, 10, 3));
+    let expected = extract_all_bits(&surface.image);
+
+    let mut nothing = Vec::new();
+    let options = RawExtractOptions { max_bytes: Some(0) };
+    assert_eq!(
+        extract_raw_to_writer(&surface, &mut nothing, options).unwrap(),
+        0
+    );
+    assert!(nothing.is_empty());
+    assert_eq!(surface.reads.get(), 0);
+
+    // A limit past the end returns everything, including the padded byte
+    let mut all = Vec::new();
+    let options = RawExtractOptions {
+        max_bytes: Some(10_000),
+    };
+    extract_raw_to_writer(&surface, &mut all, options).unwrap();
+    assert_eq!(all, expected);
+
+    // A limit that ends on the padded byte still includes it
+    let mut capped = Vec::new();
+    let options = RawExtractOptions {
+        max_bytes: Some(expected.len() as u64),
+    };
+    extract_raw_to_writer(&surface, &mut capped, options).unwrap();
+    assert_eq!(capped, expected);
+}
+
+#[test]
+fn test_preview_of_short_data() {
+    for len in [0, 3, 7] {
+        let data: Vec<u8> = (1..=len).collect();
+        let preview = format_data_preview(&data, 32);
+        assert!(
+            preview.contains(&format!("too short ({} of 8 bytes)", len)),
+            "{}",
+            preview
+        );
+        assert!(!preview.contains("Format version"));
+        let (_, binary) = preview.split_once("Binary view:").unwrap();
+        let bytes_shown = binary
+            .split_whitespace()
+            .filter(|word| word.len() == 8 && word.chars().all(|c| c == '0' || c == '1'))
+            .count();
+        assert_eq!(bytes_shown, len as usize);
+    }
+
+    // A preview shorter than the data doesn't read the header past it
+    let preview = format_data_preview(&[1, 0, 0, 0, 5, 0, 0, 0], 4);
+    assert!(preview.contains("too short (4 of 8 bytes)"));
+
+    let preview = format_data_preview(&[1, 0, 0, 0, 5, 0, 0, 0], 8);
+    assert!(preview.contains("Message length: 5 bytes"));
+}
+
+// Helper returning the data the golden previews were made from: a header, some
+// text and a few bytes that aren't printable
+fn preview_data() -> Vec<u8> {
+    let mut data = vec![1, 0, 0, 0, 5, 0, 0, 0];
+    data.extend_from_slice(b"Hello, preview!");
+    data.extend_from_slice(&[0x7f, 0x80, 0xff, 0x09]);
+    data.extend(0x20..0x2d);
+    data
+}
+
+#[test]
+fn test_default_preview_matches_golden() {
+    let expected = include_str!("fixtures/preview/default.txt");
+    let preview = format_data_preview(&preview_data(), 40);
+    assert_eq!(format!("{}\n", preview), expected);
+    assert_eq!(
+        format_data_preview_with(&preview_data(), 40, &PreviewOptions::default()),
+        preview
+    );
+}
+
+#[test]
+fn test_custom_previews_match_golden() {
+    let wide = PreviewOptions {
+        bytes_per_hex_row: 8,
+        bytes_per_bin_row: 8,
+        show_ascii_column: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        format!("{}\n", format_data_preview_with(&preview_data(), 40, &wide)),
+        include_str!("fixtures/preview/ascii_8_per_row.txt")
+    );
+
+    let narrow = PreviewOptions {
+        bytes_per_hex_row: 3,
+        bytes_per_bin_row: 1,
+        show_header_analysis: false,
+        show_ascii_column: true,
+    };
+    assert_eq!(
+        format!(
+            "{}\n",
+            format_data_preview_with(&preview_data(), 5, &narrow)
+        ),
+        include_str!("fixtures/preview/no_header_3_of_5.txt")
+    );
+}
+
+#[test]
+fn test_empty_preview() {
+    let options = PreviewOptions {
+        bytes_per_hex_row: 0,
+        bytes_per_bin_row: 0,
+        show_header_analysis: true,
+        show_ascii_column: true,
+    };
+    for options in [PreviewOptions::default(), options] {
+        for n in [0, 8, 64] {
+            assert_eq!(
+                format_data_preview_with(&[], n, &options),
+                "Raw data preview:\nPotential header: too short (0 of 8 bytes)\nHex view:\n\n\nBinary view:\n"
+            );
+        }
+    }
+}
@@ -0,0 +1,119 @@
+#![cfg(feature = "client")]
+
+use actix_files::Files;
+use actix_web::{App, HttpServer};
+use hide_rs::api::models::EncodeOptions;
+use hide_rs::api::routes::configure_routes;
+use hide_rs::client::{ClientError, HideClient, RemoteDetectOptions};
+use hide_rs::encoder::Encoder;
+use hide_rs::testing::{gradient_image, noise_image};
+use image::DynamicImage;
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+mod common;
+
+use common::create_state;
+
+/// Serve a directory of files, which answers range requests, and return a client for it
+fn serve_dir(dir: &Path) -> HideClient {
+    let dir = dir.to_path_buf();
+    let server = HttpServer::new(move || App::new().service(Files::new("/files", &dir)))
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+    let addr = server.addrs()[0];
+    actix_web::rt::spawn(server.run());
+
+    HideClient::new(format!("http://{}", addr))
+}
+
+#[actix_web::test]
+async fn test_only_the_start_of_a_large_png_is_fetched() {
+    let dir = tempdir().unwrap();
+    // Noise barely compresses, so the file is about as large as its pixels
+    let cover = noise_image(512, 512, 5);
+    cover.save(dir.path().join("cover.png")).unwrap();
+    Encoder::new()
+        .encode(cover, b"remote message")
+        .unwrap()
+        .save(dir.path().join("stego.png"))
+        .unwrap();
+    let file_size = fs::metadata(dir.path().join("stego.png")).unwrap().len();
+    let client = serve_dir(dir.path());
+
+    let detection = client.remote_detect("/files/stego.png").await.unwrap();
+    let header = detection.header.unwrap();
+    assert_eq!(header.message_length, 14);
+    assert!(!detection.full_download);
+    assert!(
+        detection.bytes_transferred * 20 < file_size,
+        "fetched {} of {} bytes",
+        detection.bytes_transferred,
+        file_size
+    );
+
+    let detection = client.remote_detect("/files/cover.png").await.unwrap();
+    assert_eq!(detection.header, None);
+    assert!(detection.bytes_transferred * 20 < file_size);
+}
+
+#[actix_web::test]
+async fn test_unsupported_pngs_fall_back_to_a_full_download() {
+    let dir = tempdir().unwrap();
+    let cover = DynamicImage::ImageRgb16(gradient_image(64, 64).inner().to_rgb16());
+    cover.save(dir.path().join("deep.png")).unwrap();
+    let client = serve_dir(dir.path());
+
+    let err = client.remote_detect("/files/deep.png").await.unwrap_err();
+    assert!(matches!(err, ClientError::Image(_)), "{}", err);
+
+    let options = RemoteDetectOptions {
+        fallback_to_download: true,
+        ..Default::default()
+    };
+    let detection = client
+        .remote_detect_with_options("/files/deep.png", &options)
+        .await
+        .unwrap();
+    assert!(detection.full_download);
+    assert_eq!(detection.header, None);
+}
+
+#[actix_web::test]
+async fn test_servers_without_range_support() {
+    let uploads = tempdir().unwrap();
+    let state = create_state(uploads.path());
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(state.clone())
+            .configure(configure_routes)
+    })
+    .workers(1)
+    .bind(("127.0.0.1", 0))
+    .unwrap();
+    let addr = server.addrs()[0];
+    actix_web::rt::spawn(server.run());
+    let client = HideClient::new(format!("http://{}", addr));
+
+    let covers = tempdir().unwrap();
+    let cover = covers.path().join("cover.png");
+    gradient_image(40, 40).save(&cover).unwrap();
+    let encoded = client
+        .encode(
+            cover.as_path(),
+            b"no ranges here",
+            &EncodeOptions::default(),
+        )
+        .await
+        .unwrap();
+
+    // The API's image downloads answer with the whole file
+    let detection = client
+        .remote_detect(&encoded.response.download_url)
+        .await
+        .unwrap();
+    assert_eq!(detection.header.unwrap().message_length, 14);
+    assert!(!detection.full_download);
+}
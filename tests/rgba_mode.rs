@@ -0,0 +1,161 @@
+use hide_rs::decoder::{Decoder, UntrustedLimits};
+use hide_rs::encoder::{capacity_breakdown, Encoder, EncoderConfig};
+use hide_rs::error::HideError;
+use hide_rs::header::HeaderFlags;
+use hide_rs::img::StegoImage;
+use hide_rs::rgba;
+use hide_rs::testing::{assert_roundtrip, gradient_image};
+use image::{DynamicImage, ImageBuffer, Rgba};
+
+/// Create a cover where every third pixel is fully transparent and some are barely visible
+fn translucent_image(width: u32, height: u32) -> StegoImage {
+    let img = ImageBuffer::from_fn(width, height, |x, y| {
+        let alpha = match (x + y * width) % 3 {
+            0 => 0,
+            1 => 1,
+            _ => 255,
+        };
+        Rgba([(x * 15) as u8, (y * 15) as u8, (x + y) as u8, alpha])
+    });
+    StegoImage::from_dynamic_image(DynamicImage::ImageRgba8(img))
+}
+
+fn rgba_config(skip_transparent: bool) -> EncoderConfig {
+    EncoderConfig {
+        embed_alpha: true,
+        skip_transparent,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_roundtrip_skipping_transparent_pixels() {
+    let cover = translucent_image(30, 30);
+    let message = b"four bits a pixel, none of them invisible";
+    let stego = assert_roundtrip(cover.clone(), message, rgba_config(true));
+
+    let header = Decoder::new().read_header(&stego).unwrap();
+    assert!(header
+        .flags
+        .contains(HeaderFlags::RGBA | HeaderFlags::SKIP_TRANSPARENT));
+
+    let mut visible_changed = false;
+    for (index, ((_, _, before), (_, _, after))) in cover
+        .inner()
+        .as_rgba8()
+        .unwrap()
+        .enumerate_pixels()
+        .zip(stego.inner().as_rgba8().unwrap().enumerate_pixels())
+        .enumerate()
+    {
+        if index >= rgba::HEADER_PIXELS && before.0[3] == 0 {
+            assert_eq!(before, after, "Transparent pixel {} changed", index);
+        } else {
+            // Header pixels keep their alpha and nothing turns fully transparent
+            if index < rgba::HEADER_PIXELS {
+                assert_eq!(
+                    before.0[3], after.0[3],
+                    "Header pixel {} alpha changed",
+                    index
+                );
+            }
+            assert!(
+                before.0[3] == 0 || after.0[3] != 0,
+                "Pixel {} became transparent",
+                index
+            );
+            visible_changed |= before.0[3] != after.0[3];
+        }
+    }
+    assert!(visible_changed, "No alpha LSB carried message bits");
+}
+
+#[test]
+fn test_roundtrip_without_skipping() {
+    let message = b"transparent pixels carry bits too";
+    let stego = assert_roundtrip(translucent_image(30, 30), message, rgba_config(false));
+    let header = Decoder::new().read_header(&stego).unwrap();
+    assert!(header.is_rgba());
+    assert!(!header.skips_transparent());
+
+    // Opaque RGB covers gain an alpha channel and work the same way
+    let stego = assert_roundtrip(gradient_image(20, 20), message, rgba_config(false));
+    assert!(stego.inner().as_rgba8().is_some());
+}
+
+#[test]
+fn test_every_decode_path_reads_rgba_payloads() {
+    let message = b"decoded by every path";
+    let stego = Encoder::with_config(rgba_config(true))
+        .encode(translucent_image(30, 30), message)
+        .unwrap();
+    let decoder = Decoder::new();
+
+    let lossy = decoder.decode_lossy(&stego).unwrap();
+    assert!(lossy.complete);
+    assert_eq!(lossy.data, message);
+    assert_eq!(
+        decoder
+            .decode_untrusted(&stego, UntrustedLimits::default())
+            .unwrap(),
+        message
+    );
+    assert!(decoder.detect(&stego).is_some());
+
+    let limits = UntrustedLimits {
+        max_pixels: 30,
+        ..Default::default()
+    };
+    assert!(matches!(
+        decoder.decode_untrusted(&stego, limits),
+        Err(HideError::PixelLimitExceeded { .. })
+    ));
+}
+
+#[test]
+fn test_rgba_containers() {
+    let entries = vec![
+        ("a.txt".to_string(), b"first".to_vec()),
+        ("b.txt".to_string(), b"second".to_vec()),
+    ];
+    let stego = Encoder::with_config(rgba_config(true))
+        .encode_container(translucent_image(30, 30), &entries)
+        .unwrap();
+
+    let decoder = Decoder::new();
+    assert_eq!(decoder.decode_container(&stego).unwrap().len(), 2);
+    assert_eq!(
+        decoder.decode_entry(&stego, "b.txt").unwrap(),
+        Some(b"second".to_vec())
+    );
+}
+
+#[test]
+fn test_rgba_capacity() {
+    // 400 pixels: 32 for the header, 368 carrying 4 bits each
+    let opaque = gradient_image(20, 20);
+    assert_eq!(rgba::max_message_size(&opaque, true), 184);
+    assert_eq!(rgba::max_message_size_for_dimensions(20, 20), 184);
+    assert_eq!(
+        Encoder::with_config(rgba_config(false)).max_message_size(&opaque),
+        184
+    );
+    assert_eq!(Encoder::new().max_message_size(&opaque), 142);
+
+    // Every third pixel is transparent, 123 of the 368 payload pixels
+    let translucent = translucent_image(20, 20);
+    let usable = 368 - 123;
+    assert_eq!(rgba::max_message_size(&translucent, true), usable / 2);
+    assert_eq!(rgba::max_message_size(&translucent, false), 184);
+    assert_eq!(
+        capacity_breakdown(&translucent, &rgba_config(true)).max_message_bytes,
+        usable / 2
+    );
+
+    let fits = vec![7u8; usable / 2];
+    assert_roundtrip(translucent.clone(), &fits, rgba_config(true));
+    assert!(matches!(
+        Encoder::with_config(rgba_config(true)).encode(translucent, &[fits, vec![7]].concat()),
+        Err(HideError::MessageTooLarge)
+    ));
+}
@@ -0,0 +1,60 @@
+use hide_rs::encoder::EncoderConfig;
+use hide_rs::img::StegoImage;
+use hide_rs::rng::SharedRng;
+use hide_rs::stream::StreamEncoder;
+use hide_rs::testing::{deny_default_rng, gradient_image, CountingRng};
+
+// Encode a short stream into two covers with the given RNG
+fn encode_with(rng: Option<SharedRng>) -> Vec<StegoImage> {
+    let data: Vec<u8> = (0..400u16).map(|i| i as u8).collect();
+    let mut encoder = StreamEncoder::new(
+        data.as_slice(),
+        EncoderConfig {
+            rng,
+            ..Default::default()
+        },
+    );
+
+    let mut images = Vec::new();
+    while !encoder.is_finished() {
+        images.push(encoder.next_cover(gradient_image(40, 40)).unwrap());
+    }
+    images
+}
+
+#[test]
+fn test_injected_rng_gives_reproducible_output() {
+    let _guard = deny_default_rng();
+
+    let counter = CountingRng::new();
+    let first = encode_with(Some(SharedRng::new(CountingRng::new())));
+    let second = encode_with(Some(SharedRng::new(counter.clone())));
+
+    assert_eq!(first.len(), second.len());
+    for (a, b) in first.iter().zip(&second) {
+        assert_eq!(a.inner().as_bytes(), b.inner().as_bytes());
+    }
+
+    // Only the stream ID was drawn
+    assert_eq!(counter.bytes_drawn(), 16);
+}
+
+#[test]
+fn test_system_rng_is_used_by_default() {
+    let a = encode_with(None);
+    let b = encode_with(None);
+    assert_ne!(a[0].inner().as_bytes(), b[0].inner().as_bytes());
+}
+
+#[test]
+#[should_panic(expected = "system RNG")]
+fn test_denied_fallback_panics() {
+    let _guard = deny_default_rng();
+    encode_with(None);
+}
+
+#[test]
+fn test_guard_restores_fallback() {
+    drop(deny_default_rng());
+    encode_with(None);
+}
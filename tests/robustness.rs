@@ -0,0 +1,78 @@
+use hide_rs::encoder::Encoder;
+use hide_rs::robustness::{simulate, simulate_with_payload, Transform};
+use hide_rs::testing::{image_with_payload, noise_image};
+
+#[test]
+fn test_png_resave_preserves_payload() {
+    let stego = image_with_payload(40, 40, b"survives png");
+    let results = simulate(&stego, &[Transform::PngResave]).unwrap();
+
+    assert_eq!(results.len(), 1);
+    let result = &results[0];
+    assert!(result.detected);
+    assert!(result.decoded);
+    assert_eq!(result.recovered_bytes, b"survives png".len());
+    assert_eq!(result.byte_error_rate, 0.0);
+    assert!(result.error.is_none());
+}
+
+#[test]
+fn test_jpeg_destroys_payload() {
+    let message: Vec<u8> = (0..200u8).collect();
+    let stego = Encoder::new()
+        .encode(noise_image(64, 64, 9), &message)
+        .unwrap();
+
+    for result in simulate(
+        &stego,
+        &[
+            Transform::Jpeg { quality: 95 },
+            Transform::Jpeg { quality: 50 },
+        ],
+    )
+    .unwrap()
+    {
+        assert!(!result.decoded, "{}", result.transform);
+        assert!(result.byte_error_rate > 0.5, "{}", result.transform);
+    }
+}
+
+#[test]
+fn test_crop_keeps_a_partial_prefix() {
+    // 20x20 holds 142 bytes; keeping 12 of 20 rows leaves room for 82
+    let message: Vec<u8> = (0..120u8).collect();
+    let stego = image_with_payload(20, 20, &message);
+
+    let results = simulate_with_payload(&stego, &message, &[Transform::Crop { keep: 0.6 }]);
+    let result = &results[0];
+    assert!(!result.decoded);
+    assert_eq!(result.recovered_bytes, 82);
+    assert!((result.byte_error_rate - 38.0 / 120.0).abs() < 1e-9);
+
+    // A crop that leaves the whole payload in place changes nothing
+    let short = image_with_payload(20, 20, b"short");
+    let results = simulate(&short, &[Transform::Crop { keep: 0.5 }]).unwrap();
+    assert!(results[0].decoded);
+    assert_eq!(results[0].byte_error_rate, 0.0);
+}
+
+#[test]
+fn test_results_follow_requested_order() {
+    let stego = image_with_payload(40, 40, b"ordered");
+    let ops: Vec<Transform> = ["png", "resize:0.5", "bitdepth:7"]
+        .iter()
+        .map(|op| op.parse().unwrap())
+        .collect();
+
+    let results = simulate(&stego, &ops).unwrap();
+    let applied: Vec<_> = results.iter().map(|result| result.transform).collect();
+    assert_eq!(applied, ops);
+
+    // Dropping the LSBs removes every embedded bit
+    assert!(!results[2].decoded);
+}
+
+#[test]
+fn test_simulate_requires_a_payload() {
+    assert!(simulate(&noise_image(20, 20, 1), &[Transform::PngResave]).is_err());
+}
@@ -0,0 +1,59 @@
+use hide_rs::report::Report;
+use hide_rs::self_test::{self, COMMAND};
+use std::process::Command;
+
+fn self_test(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_hide"))
+        .arg("self-test")
+        .args(args)
+        .output()
+        .expect("Failed to run hide")
+}
+
+#[test]
+fn test_quick_self_test_reports_json() {
+    let output = self_test(&["--quick", "--json"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+
+    let report: Report = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(report.hide_rs_version, hide_rs::VERSION);
+    let [run] = report.runs.as_slice() else {
+        panic!("expected one run: {:?}", report.runs);
+    };
+    assert_eq!(run.command, COMMAND);
+    assert_eq!(run.arguments, ["--quick"]);
+    assert_eq!(self_test::failures(run).count(), 0, "{:?}", run.files);
+
+    let paths: Vec<_> = run.files.iter().map(|file| file.path.as_str()).collect();
+    for expected in [
+        "roundtrip/noise/padded",
+        "roundtrip/checkerboard/container",
+        "conformance",
+        "throughput/256x256",
+        "save-load/png",
+    ] {
+        assert!(paths.contains(&expected), "{:?}", paths);
+    }
+    assert!(!paths.iter().any(|path| path.contains("large")));
+
+    let throughput = run
+        .files
+        .iter()
+        .find(|file| file.path == "throughput/256x256")
+        .unwrap();
+    for score in ["encode_mib_per_s", "decode_mib_per_s"] {
+        assert!(throughput.scores[score] > 0.0, "{:?}", throughput.scores);
+    }
+}
+
+#[test]
+fn test_self_test_summary_and_flags() {
+    let output = self_test(&[]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("ok   save-load/tiff"), "{}", stdout);
+    assert!(stdout.contains(", 0 failed"), "{}", stdout);
+
+    assert!(!self_test(&["--quick", "--full"]).status.success());
+}
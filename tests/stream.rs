@@ -0,0 +1,109 @@
+use hide_rs::encoder::EncoderConfig;
+use hide_rs::img::{create_rgb_image, StegoImage};
+use hide_rs::stream::{StreamDecoder, StreamEncoder, FRAME_HEADER_SIZE};
+use std::io::{ErrorKind, Read};
+use tempfile::tempdir;
+
+/// Bytes of stream data a 200x200 cover carries
+const CHUNK_SIZE: usize = 200 * 200 * 3 / 8 - 12 - FRAME_HEADER_SIZE;
+
+// Stream data filling five covers, the last one partially
+fn telemetry() -> Vec<u8> {
+    (0..5 * CHUNK_SIZE - 100)
+        .map(|i| (i * 31 % 251) as u8)
+        .collect()
+}
+
+// Encode the data into as many 200x200 covers as it needs
+fn encode_stream(data: &[u8]) -> Vec<StegoImage> {
+    let mut encoder = StreamEncoder::new(data, EncoderConfig::default());
+    let mut images = Vec::new();
+    while !encoder.is_finished() {
+        images.push(
+            encoder
+                .next_cover(create_rgb_image(200, 200).unwrap())
+                .unwrap(),
+        );
+    }
+    images
+}
+
+#[test]
+fn test_stream_reassembled_from_shuffled_images() {
+    let data = telemetry();
+    let images = encode_stream(&data);
+    assert_eq!(images.len(), 5);
+
+    let mut decoder = StreamDecoder::new();
+    for index in [3, 0, 4, 2, 1] {
+        assert_eq!(decoder.add_image(&images[index]).unwrap(), index as u32);
+    }
+    assert!(decoder.is_complete());
+
+    let mut reconstructed = Vec::new();
+    decoder.read_to_end(&mut reconstructed).unwrap();
+    assert_eq!(reconstructed, data);
+}
+
+#[test]
+fn test_missing_image_reports_gap() {
+    let data = telemetry();
+    let images = encode_stream(&data);
+
+    let mut decoder = StreamDecoder::new();
+    for index in [4, 0, 1, 3] {
+        decoder.add_image(&images[index]).unwrap();
+    }
+    assert!(!decoder.is_complete());
+    assert_eq!(decoder.missing(), vec![2..=2]);
+
+    // Everything before the gap can be read
+    let mut reconstructed = vec![0; 3 * CHUNK_SIZE];
+    let err = decoder.read_exact(&mut reconstructed).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+
+    let mut prefix = Vec::new();
+    let mut decoder = StreamDecoder::new();
+    decoder.add_image(&images[0]).unwrap();
+    decoder.add_image(&images[1]).unwrap();
+    assert_eq!(
+        decoder.read_to_end(&mut prefix).unwrap_err().kind(),
+        ErrorKind::WouldBlock
+    );
+    assert_eq!(prefix, data[..2 * CHUNK_SIZE]);
+}
+
+#[test]
+fn test_decoder_resumes_from_saved_state() {
+    let data = telemetry();
+    let images = encode_stream(&data);
+    let temp_dir = tempdir().unwrap();
+    let state_path = temp_dir.path().join("stream.json");
+
+    // Read part of the stream, then save
+    let mut decoder = StreamDecoder::new();
+    decoder.add_image(&images[1]).unwrap();
+    decoder.add_image(&images[0]).unwrap();
+    let mut reconstructed = vec![0; CHUNK_SIZE + 10];
+    decoder.read_exact(&mut reconstructed).unwrap();
+    decoder.save(&state_path).unwrap();
+
+    // A fresh decoder picks up where the old one stopped
+    let mut decoder = StreamDecoder::load(&state_path).unwrap();
+    for image in &images[2..] {
+        decoder.add_image(image).unwrap();
+    }
+    decoder.read_to_end(&mut reconstructed).unwrap();
+    assert_eq!(reconstructed, data);
+}
+
+#[test]
+fn test_frames_of_other_streams_are_rejected() {
+    let data = telemetry();
+    let first = encode_stream(&data);
+    let second = encode_stream(&data);
+
+    let mut decoder = StreamDecoder::new();
+    decoder.add_image(&first[0]).unwrap();
+    assert!(decoder.add_image(&second[1]).is_err());
+}
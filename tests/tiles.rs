@@ -0,0 +1,141 @@
+use hide_rs::decoder::Decoder;
+use hide_rs::encoder::Encoder;
+use hide_rs::error::HideError;
+use hide_rs::header::HEADER_SIZE;
+use hide_rs::img::{self, StegoImage, TileLayout};
+use hide_rs::testing::gradient_image;
+use std::fs;
+use std::process::Command;
+use tempfile::tempdir;
+
+const WIDTH: u32 = 100;
+const HEIGHT: u32 = 70;
+const TILE: u32 = 32;
+const LAYOUT: TileLayout = TileLayout {
+    width: WIDTH,
+    height: HEIGHT,
+};
+
+// Helper to cut an image into tiles of at most TILE pixels a side, in shuffled order
+fn cut_tiles(image: &StegoImage) -> Vec<(u32, u32, StegoImage)> {
+    let mut tiles = Vec::new();
+    for y in (0..image.height()).step_by(TILE as usize) {
+        for x in (0..image.width()).step_by(TILE as usize) {
+            let width = TILE.min(image.width() - x);
+            let height = TILE.min(image.height() - y);
+            let tile = image.inner().crop_imm(x, y, width, height);
+            tiles.push((x, y, StegoImage::from_dynamic_image(tile)));
+        }
+    }
+    tiles.rotate_left(5);
+    tiles.reverse();
+    tiles
+}
+
+fn encode(message: &[u8]) -> StegoImage {
+    Encoder::new()
+        .encode(gradient_image(WIDTH, HEIGHT), message)
+        .unwrap()
+}
+
+#[test]
+fn test_shuffled_tiles_reassemble_and_decode() {
+    let message = vec![0x5a; 1500];
+    let stego = encode(&message);
+    let tiles = cut_tiles(&stego);
+
+    let assembled = img::assemble_tiles(&tiles, WIDTH, HEIGHT).unwrap();
+    assert_eq!(assembled.inner().as_bytes(), stego.inner().as_bytes());
+    assert_eq!(Decoder::new().decode(&assembled).unwrap(), message);
+
+    assert_eq!(
+        Decoder::new().decode_tiles(&tiles, LAYOUT).unwrap(),
+        message
+    );
+}
+
+#[test]
+fn test_missing_tiles_past_the_payload_are_tolerated() {
+    let message = b"only the top rows carry this";
+    let mut tiles = cut_tiles(&encode(message));
+    tiles.retain(|&(x, y, _)| (x, y) != (96, 64));
+
+    assert!(img::assemble_tiles(&tiles, WIDTH, HEIGHT).is_err());
+    assert_eq!(
+        Decoder::new().decode_tiles(&tiles, LAYOUT).unwrap(),
+        message
+    );
+}
+
+#[test]
+fn test_missing_tiles_inside_the_payload_truncate_it() {
+    let message = vec![0xa5; 2500];
+    let mut tiles = cut_tiles(&encode(&message));
+
+    // The payload reaches the third row of tiles
+    tiles.retain(|&(x, y, _)| (x, y) != (32, 64));
+    match Decoder::new().decode_tiles(&tiles, LAYOUT) {
+        Err(HideError::TruncatedPayload {
+            declared,
+            available,
+        }) => {
+            assert_eq!(declared, message.len());
+            // Everything before the first missing pixel, at (32, 64)
+            assert_eq!(
+                available,
+                ((64 * WIDTH + 32) as usize * 3 - HEADER_SIZE * 8) / 8
+            );
+        }
+        other => panic!("expected a truncated payload, got {:?}", other),
+    }
+
+    // Without the header there is nothing to decode
+    tiles.retain(|&(x, y, _)| (x, y) != (0, 0));
+    assert!(matches!(
+        Decoder::new().decode_tiles(&tiles, LAYOUT),
+        Err(HideError::NoMessageFound)
+    ));
+}
+
+#[test]
+fn test_keyed_tiles_are_assembled_first() {
+    let message = b"shuffled across every tile";
+    let stego = Encoder::with_key(b"tiles")
+        .encode(gradient_image(WIDTH, HEIGHT), message)
+        .unwrap();
+    let mut tiles = cut_tiles(&stego);
+    let decoder = Decoder::with_key(b"tiles");
+    assert_eq!(decoder.decode_tiles(&tiles, LAYOUT).unwrap(), message);
+
+    tiles.pop();
+    assert!(decoder.decode_tiles(&tiles, LAYOUT).is_err());
+}
+
+#[test]
+fn test_cli_decode_tiles() {
+    let temp_dir = tempdir().unwrap();
+    let mut entries = Vec::new();
+    for (x, y, tile) in cut_tiles(&encode(b"hello from the tiles")) {
+        let name = format!("tile_{}_{}.png", x, y);
+        tile.save(temp_dir.path().join(&name)).unwrap();
+        entries.push(serde_json::json!({ "x": x, "y": y, "path": name }));
+    }
+    let manifest = temp_dir.path().join("tiles.json");
+    fs::write(
+        &manifest,
+        serde_json::json!({ "width": WIDTH, "height": HEIGHT, "tiles": entries }).to_string(),
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_hide"))
+        .args(["decode-tiles", "--manifest"])
+        .arg(&manifest)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(String::from_utf8_lossy(&output.stdout).contains("hello from the tiles"));
+}
@@ -0,0 +1,71 @@
+use hide_rs::decoder::Decoder;
+use hide_rs::encoder::Encoder;
+use hide_rs::error::HideError;
+use hide_rs::img::{create_rgb_image, StegoImage};
+use hide_rs::testing::image_with_payload;
+
+// Helper to keep only the top rows of an image
+fn crop_rows(image: &StegoImage, rows: u32) -> StegoImage {
+    StegoImage::from_dynamic_image(image.inner().crop_imm(0, 0, image.width(), rows))
+}
+
+#[test]
+fn test_cropped_image_reports_truncation() {
+    // 30x30 holds 329 message bytes; cropping to 10 rows leaves 104
+    let message: Vec<u8> = (0..=255u8).cycle().take(300).collect();
+    let stego = image_with_payload(30, 30, &message);
+    let cropped = crop_rows(&stego, 10);
+
+    let err = Decoder::new().decode(&cropped).unwrap_err();
+    assert!(matches!(
+        err,
+        HideError::TruncatedPayload {
+            declared: 300,
+            available: 104
+        }
+    ));
+    assert_eq!(
+        err.to_string(),
+        "Header declares 300 message bytes but the image only holds 104"
+    );
+
+    // The header is still detected and the prefix can be recovered
+    let partial = Decoder::new().decode_lossy(&cropped).unwrap();
+    assert_eq!(partial.data, &message[..104]);
+}
+
+#[test]
+fn test_interrupted_encode_reports_truncation() {
+    // A header declaring more than the whole image can hold
+    let mut stego = Encoder::new()
+        .encode(create_rgb_image(10, 10).unwrap(), b"hi")
+        .unwrap();
+    let view = Encoder::new()
+        .encode(create_rgb_image(100, 10).unwrap(), &[7; 300])
+        .unwrap();
+    for x in 0..10 {
+        for y in 0..10 {
+            stego
+                .set_pixel_rgb(x, y, view.get_pixel_rgb(y * 10 + x, 0).unwrap())
+                .unwrap();
+        }
+    }
+
+    // 100 pixels carry 300 bits: 64 for the header and 29 whole bytes
+    assert!(matches!(
+        Decoder::new().decode(&stego),
+        Err(HideError::TruncatedPayload {
+            declared: 300,
+            available: 29
+        })
+    ));
+}
+
+#[test]
+fn test_no_header_is_still_no_message() {
+    // Images too small for a header have no plausible payload
+    assert!(matches!(
+        Decoder::new().decode(&create_rgb_image(4, 4).unwrap()),
+        Err(HideError::NoMessageFound)
+    ));
+}
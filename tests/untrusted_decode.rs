@@ -0,0 +1,98 @@
+use hide_rs::decoder::{Decoder, UntrustedLimits};
+use hide_rs::encoder::Encoder;
+use hide_rs::error::HideError;
+use hide_rs::img::{create_rgb_image, StegoImage};
+use std::time::Duration;
+
+fn stego_with_message(len: usize) -> StegoImage {
+    Encoder::new()
+        .encode(create_rgb_image(100, 100).unwrap(), &vec![0x5A; len])
+        .unwrap()
+}
+
+#[test]
+fn test_decode_untrusted_within_limits() {
+    let stego = stego_with_message(1000);
+    let decoded = Decoder::new()
+        .decode_untrusted(&stego, UntrustedLimits::default())
+        .unwrap();
+    assert_eq!(decoded, vec![0x5A; 1000]);
+}
+
+#[test]
+fn test_output_limit_applies_to_well_formed_headers() {
+    let stego = stego_with_message(1000);
+    let limits = UntrustedLimits {
+        max_output_bytes: 999,
+        ..Default::default()
+    };
+
+    assert!(matches!(
+        Decoder::new().decode_untrusted(&stego, limits),
+        Err(HideError::OutputLimitExceeded {
+            declared_length: 1000,
+            limit: 999
+        })
+    ));
+}
+
+#[test]
+fn test_pixel_limit() {
+    // 1008 bytes of header and message need 2688 pixels
+    let stego = stego_with_message(1000);
+    let limits = UntrustedLimits {
+        max_pixels: 2687,
+        ..Default::default()
+    };
+
+    assert!(matches!(
+        Decoder::new().decode_untrusted(&stego, limits),
+        Err(HideError::PixelLimitExceeded {
+            required: 2688,
+            limit: 2687
+        })
+    ));
+
+    let limits = UntrustedLimits {
+        max_pixels: 2688,
+        ..Default::default()
+    };
+    assert!(Decoder::new().decode_untrusted(&stego, limits).is_ok());
+}
+
+#[test]
+fn test_deadline() {
+    let stego = stego_with_message(1000);
+    let limits = UntrustedLimits {
+        deadline: Duration::ZERO,
+        ..Default::default()
+    };
+
+    assert!(matches!(
+        Decoder::new().decode_untrusted(&stego, limits),
+        Err(HideError::DeadlineExceeded)
+    ));
+}
+
+#[test]
+fn test_partial_untrusted_decode() {
+    // Crop the image so only part of the message remains
+    let stego = stego_with_message(1000);
+    let cropped = StegoImage::from_dynamic_image(stego.inner().crop_imm(0, 0, 100, 10));
+
+    let decoder = Decoder::new();
+    assert!(matches!(
+        decoder.decode_untrusted(&cropped, UntrustedLimits::default()),
+        Err(HideError::TruncatedPayload {
+            declared: 1000,
+            available: 367
+        })
+    ));
+
+    let detailed = decoder
+        .decode_untrusted_detailed(&cropped, UntrustedLimits::default(), true)
+        .unwrap();
+    assert_eq!(detailed.decoded.declared_length, 1000);
+    assert_eq!(detailed.decoded.recovered_length, 367);
+    assert!(!detailed.decoded.complete);
+}
@@ -0,0 +1,69 @@
+use hide_rs::decoder::Decoder;
+use hide_rs::encoder::Encoder;
+use hide_rs::error::HideError;
+use hide_rs::img::{PixelSurface, Rect};
+use hide_rs::testing::gradient_image;
+
+#[test]
+fn test_encode_into_centered_view() {
+    let original = gradient_image(256, 256);
+    let mut image = original.clone();
+    let rect = Rect::new(96, 96, 64, 64);
+    let message = b"Only the middle of the picture carries this";
+
+    let mut view = image
+        .view_mut(rect.x, rect.y, rect.width, rect.height)
+        .unwrap();
+    assert_eq!((view.width(), view.height()), (64, 64));
+    let stats = Encoder::new().encode_in_place(&mut view, message).unwrap();
+    assert_eq!(stats.message_bytes, message.len());
+
+    // Pixels outside the view are untouched
+    for y in 0..256 {
+        for x in 0..256 {
+            let inside = (96..160).contains(&x) && (96..160).contains(&y);
+            if !inside {
+                assert_eq!(
+                    image.get_pixel_rgb(x, y).unwrap(),
+                    original.get_pixel_rgb(x, y).unwrap(),
+                    "Pixel ({}, {}) outside the view changed",
+                    x,
+                    y
+                );
+            }
+        }
+    }
+
+    assert_eq!(Decoder::new().decode_view(&image, rect).unwrap(), message);
+}
+
+#[test]
+fn test_view_capacity_and_bounds() {
+    let mut image = gradient_image(256, 256);
+
+    // Views must lie within the image
+    assert!(image.view_mut(200, 200, 64, 64).is_err());
+    assert!(image.view_mut(0, 0, 0, 10).is_err());
+    assert!(image.view_mut(u32::MAX, 0, 2, 2).is_err());
+    assert!(Decoder::new()
+        .decode_view(&image, Rect::new(250, 0, 10, 10))
+        .is_err());
+
+    // Capacity comes from the view, not the whole image
+    let mut view = image.view_mut(96, 96, 64, 64).unwrap();
+    let capacity = Encoder::max_message_size_for_dimensions(64, 64);
+    assert!(matches!(
+        Encoder::new().encode_in_place(&mut view, &vec![0; capacity + 1]),
+        Err(HideError::MessageTooLarge)
+    ));
+    assert!(view.get_pixel_rgb(64, 0).is_err());
+}
+
+#[test]
+fn test_encode_in_place_whole_image() {
+    let mut image = gradient_image(256, 256);
+    Encoder::new()
+        .encode_in_place(&mut image, b"whole")
+        .unwrap();
+    assert_eq!(Decoder::new().decode(&image).unwrap(), b"whole");
+}
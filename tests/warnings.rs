@@ -0,0 +1,148 @@
+use hide_rs::decoder::Decoder;
+use hide_rs::encoder::{Encoder, EncoderConfig};
+use hide_rs::img::StegoImage;
+use hide_rs::testing::gradient_image;
+use hide_rs::warning::{warning_codes, StegoWarning, Warning};
+use image::DynamicImage;
+use std::process::Command;
+use tempfile::tempdir;
+
+fn rgba_cover() -> StegoImage {
+    StegoImage::from_dynamic_image(DynamicImage::ImageRgba8(
+        gradient_image(32, 32).inner().to_rgba8(),
+    ))
+}
+
+fn container_stego() -> StegoImage {
+    let entries = [("notes.txt".to_string(), b"inside".to_vec())];
+    Encoder::new()
+        .encode_container(gradient_image(40, 40), &entries)
+        .unwrap()
+}
+
+#[test]
+fn test_rgba_covers_warn_unless_alpha_is_embedded() {
+    let (_, stats) = Encoder::new()
+        .encode_with_stats(rgba_cover(), b"flattened")
+        .unwrap();
+    assert_eq!(stats.warnings, [StegoWarning::AlphaDropped]);
+    assert_eq!(
+        Warning::from(&stats.warnings[0]).code,
+        warning_codes::ALPHA_DROPPED
+    );
+
+    let config = EncoderConfig {
+        embed_alpha: true,
+        ..Default::default()
+    };
+    let (_, stats) = Encoder::with_config(config)
+        .encode_with_stats(rgba_cover(), b"kept")
+        .unwrap();
+    assert!(stats.warnings.is_empty());
+
+    let (_, stats) = Encoder::new()
+        .encode_with_stats(gradient_image(32, 32), b"opaque")
+        .unwrap();
+    assert!(stats.warnings.is_empty());
+}
+
+#[test]
+fn test_messages_near_capacity_warn() {
+    let cover = gradient_image(32, 32);
+    let capacity = Encoder::new().max_message_size(&cover);
+
+    let (stego, stats) = Encoder::new()
+        .encode_with_stats(cover.clone(), &vec![b'n'; capacity])
+        .unwrap();
+    assert_eq!(
+        stats.warnings,
+        [StegoWarning::NearCapacity {
+            message_bytes: capacity,
+            max_message_bytes: capacity,
+        }]
+    );
+    assert_eq!(Decoder::new().decode(&stego).unwrap().len(), capacity);
+
+    let (_, stats) = Encoder::new()
+        .encode_with_stats(cover, &vec![b'n'; capacity / 2])
+        .unwrap();
+    assert!(stats.warnings.is_empty());
+}
+
+#[test]
+fn test_decodes_report_raw_payload_bytes() {
+    let stego = container_stego();
+    let detailed = Decoder::new().decode_detailed(&stego, false).unwrap();
+    assert_eq!(detailed.warnings, [StegoWarning::RawContainerBytes]);
+    assert_eq!(
+        detailed.decoded.data,
+        Decoder::new().decode(&stego).unwrap()
+    );
+
+    let found = Decoder::new().decode_with_repair(&stego).unwrap();
+    assert_eq!(found.warnings, [StegoWarning::RawContainerBytes]);
+
+    let plain = Encoder::new()
+        .encode(gradient_image(40, 40), b"plain")
+        .unwrap();
+    let detailed = Decoder::new().decode_detailed(&plain, false).unwrap();
+    assert!(detailed.warnings.is_empty());
+}
+
+#[test]
+fn test_partial_decodes_warn_about_truncation() {
+    let stego = Encoder::new()
+        .encode(gradient_image(40, 40), &[b't'; 200])
+        .unwrap();
+    let cropped = StegoImage::from_dynamic_image(stego.inner().crop_imm(0, 0, 40, 10));
+
+    let detailed = Decoder::new().decode_detailed(&cropped, true).unwrap();
+    assert!(!detailed.decoded.complete);
+    assert_eq!(
+        detailed.warnings,
+        [StegoWarning::TruncatedPayload {
+            declared: 200,
+            recovered: detailed.decoded.recovered_length,
+        }]
+    );
+}
+
+#[test]
+fn test_cli_prints_warnings_to_stderr() {
+    let temp_dir = tempdir().unwrap();
+    let cover = temp_dir.path().join("cover.png");
+    rgba_cover().save(&cover).unwrap();
+    let output = temp_dir.path().join("stego.png");
+
+    let encode = Command::new(env!("CARGO_BIN_EXE_hide"))
+        .args(["encode", "-m", "see through", "-i"])
+        .arg(&cover)
+        .arg("-o")
+        .arg(&output)
+        .output()
+        .unwrap();
+    assert!(encode.status.success());
+    let stderr = String::from_utf8_lossy(&encode.stderr);
+    assert!(stderr.contains("warning: the cover's transparency was dropped"));
+    assert!(!String::from_utf8_lossy(&encode.stdout).contains("warning:"));
+
+    let container = temp_dir.path().join("container.png");
+    container_stego().save(&container).unwrap();
+    let decode = Command::new(env!("CARGO_BIN_EXE_hide"))
+        .args(["decode", "-i"])
+        .arg(&container)
+        .output()
+        .unwrap();
+    assert!(decode.status.success());
+    assert!(String::from_utf8_lossy(&decode.stderr)
+        .contains("warning: the image holds a container payload"));
+
+    // Nothing to warn about
+    let decode = Command::new(env!("CARGO_BIN_EXE_hide"))
+        .args(["decode", "-i"])
+        .arg(&output)
+        .output()
+        .unwrap();
+    assert!(decode.status.success());
+    assert!(!String::from_utf8_lossy(&decode.stderr).contains("warning:"));
+}
@@ -0,0 +1,82 @@
+use hide_rs::decoder::Decoder;
+use hide_rs::encoder::Encoder;
+use hide_rs::error::HideError;
+use hide_rs::img::StegoImage;
+use hide_rs::testing::{assert_lsb_only_changes, gradient_image, noise_image};
+use hide_rs::watermark::MAX_WATERMARK_ID_LEN;
+
+// Helper to crop a region out of an image
+fn crop(image: &StegoImage, x: u32, y: u32, width: u32, height: u32) -> StegoImage {
+    StegoImage::from_dynamic_image(image.inner().crop_imm(x, y, width, height))
+}
+
+#[test]
+fn test_watermark_round_trip() {
+    let cover = gradient_image(64, 64);
+    let stego = Encoder::new()
+        .encode_watermark(cover.clone(), b"asset-42")
+        .unwrap();
+    assert_lsb_only_changes(&cover, &stego);
+
+    // 15-byte frames take 40 pixels, so the 4096 pixels hold 102 copies
+    let result = Decoder::new().decode_watermark(&stego).unwrap();
+    assert_eq!(result.id, b"asset-42");
+    assert_eq!(result.copies, 102);
+}
+
+#[test]
+fn test_watermark_survives_cropping() {
+    let stego = Encoder::new()
+        .encode_watermark(noise_image(256, 256, 3), b"asset-42")
+        .unwrap();
+
+    // Any quarter of the image, aligned or not
+    for (x, y) in [(0, 0), (128, 128), (37, 91), (101, 5)] {
+        let cropped = crop(&stego, x, y, 128, 128);
+        let result = Decoder::new().decode_watermark(&cropped).unwrap();
+        assert_eq!(result.id, b"asset-42", "Crop at ({}, {})", x, y);
+        assert!(result.copies > 100, "Crop at ({}, {})", x, y);
+    }
+}
+
+#[test]
+fn test_longest_watermark_survives_cropping() {
+    // A 64-byte ID needs 192-pixel frames, so the crop must be wider than that
+    let id = [0xAB; MAX_WATERMARK_ID_LEN];
+    let stego = Encoder::new()
+        .encode_watermark(gradient_image(512, 512), &id)
+        .unwrap();
+
+    let cropped = crop(&stego, 200, 300, 256, 256);
+    let result = Decoder::new().decode_watermark(&cropped).unwrap();
+    assert_eq!(result.id, id);
+}
+
+#[test]
+fn test_watermark_is_not_a_standard_payload() {
+    let stego = Encoder::new()
+        .encode_watermark(gradient_image(64, 64), b"asset-42")
+        .unwrap();
+
+    let decoder = Decoder::new();
+    assert!(decoder.detect(&stego).is_none());
+    assert!(decoder.decode(&stego).is_err());
+
+    // And a plain image has no watermark
+    assert!(matches!(
+        decoder.decode_watermark(&gradient_image(64, 64)),
+        Err(HideError::NoMessageFound)
+    ));
+}
+
+#[test]
+fn test_watermark_needs_room_for_one_copy() {
+    // 15-byte frames need 40 pixels
+    assert!(matches!(
+        Encoder::new().encode_watermark(gradient_image(6, 6), b"asset-42"),
+        Err(HideError::MessageTooLarge)
+    ));
+    assert!(Encoder::new()
+        .encode_watermark(gradient_image(8, 5), b"asset-42")
+        .is_ok());
+}